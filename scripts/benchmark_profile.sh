@@ -75,7 +75,7 @@ START_TIME=$(date +%s.%N)
     -o "$OUTPUT_FILE" \
     --threads "$THREADS" \
     --batch-size "$BATCH_SIZE" \
-    2>&1 | tee "$OUTPUT_DIR/profile_metrics.txt"
+    --metrics "$OUTPUT_DIR/profile_metrics.json"
 
 END_TIME=$(date +%s.%N)
 ELAPSED=$(echo "$END_TIME - $START_TIME" | bc)
@@ -86,14 +86,14 @@ echo "Summary"
 echo "==================================="
 echo "Total wall-clock time: ${ELAPSED}s"
 echo "Output written to: $OUTPUT_FILE"
-echo "Metrics saved to: $OUTPUT_DIR/profile_metrics.txt"
+echo "Metrics saved to: $OUTPUT_DIR/profile_metrics.json"
 echo ""
 
-# Parse key metrics from output
-if grep -q "Max pending results:" "$OUTPUT_DIR/profile_metrics.txt"; then
-    echo "Key findings from metrics:"
-    grep -E "(Max pending|BOTTLENECK|Workers are|Channel)" "$OUTPUT_DIR/profile_metrics.txt" || true
-fi
+# Channel congestion: pending results close to the bound mean the writer is
+# the bottleneck, few pending results that the workers are
+echo "Key findings from metrics:"
+grep -E '"(max_pending_results|result_channel_bound|worker_[a-z_]+|writer_[a-z_]+)"' \
+    "$OUTPUT_DIR/profile_metrics.json" || true
 
 echo ""
 echo "==================================="
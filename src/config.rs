@@ -3,7 +3,18 @@
 //! This module contains the configuration structure and default values
 //! that control the region-to-gene matching behavior.
 
-use crate::types::{Area, ReportLevel};
+use serde::Serialize;
+
+use crate::types::{
+    Area, BiotypeFilter, ColumnSpec, Compat, ConfidenceWeights, DistanceAnchor, DuplicateMode,
+    MatrixFormat, MatrixValue, MemorySize, MetricsFormat, Model, OutputFormat, Precision, Region,
+    RegionColumns, ReportLevel, SortOrder, Strand, StrandMode, TieBreak, TranscriptSelection, Zone,
+};
+
+/// Identity of a region for `--duplicates`: everything the matcher reads
+/// from it, i.e. chromosome, start, end, strand, summit and its own maximum
+/// distance from the distance column.
+pub type DuplicateKey = (String, i64, i64, Option<Strand>, Option<i64>, Option<i64>);
 
 /// Default rules priority order.
 pub const DEFAULT_RULES: [Area; 8] = [
@@ -17,11 +28,55 @@ pub const DEFAULT_RULES: [Area; 8] = [
     Area::Downstream,
 ];
 
+/// Parameters of the GREAT basal-plus-extension model, in bp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GreatParams {
+    /// Basal domain length upstream of the TSS.
+    pub basal_upstream: i64,
+    /// Basal domain length downstream of the TSS.
+    pub basal_downstream: i64,
+    /// Maximum extension in each direction from the TSS.
+    pub max_extension: i64,
+}
+
+impl Default for GreatParams {
+    fn default() -> Self {
+        GreatParams {
+            basal_upstream: 5000,
+            basal_downstream: 1000,
+            max_extension: 1_000_000,
+        }
+    }
+}
+
+/// Overrides of the matching parameters for the genes of one biotype.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BiotypeRules {
+    /// Gene biotype the overrides apply to.
+    pub biotype: String,
+    /// Priority rules (comma-separated, may be partial).
+    pub rules: Option<String>,
+    /// Only report the areas listed in `rules`.
+    pub exclusive: bool,
+    /// Maximum distance to report associations in bp.
+    pub distance: Option<i64>,
+    /// TSS region distance in bp.
+    pub tss: Option<f64>,
+    /// TTS region distance in bp.
+    pub tts: Option<f64>,
+    /// Promoter region distance in bp.
+    pub promoter: Option<f64>,
+}
+
 /// Configuration for the region-to-gene matching process.
-#[derive(Debug, Clone)]
+///
+/// Serialized as the resolved options of a run, e.g. in the metadata sidecar.
+#[derive(Debug, Clone, Serialize)]
 pub struct Config {
     /// Priority rules for resolving ties.
     pub rules: Vec<Area>,
+    /// Only report the areas listed in the rules.
+    pub exclusive_rules: bool,
     /// Percentage of the area overlapped threshold.
     pub perc_area: f64,
     /// Percentage of the region overlapped threshold.
@@ -34,27 +89,232 @@ pub struct Config {
     pub promoter: f64,
     /// Maximum distance to report associations in bp.
     pub distance: i64,
+    /// Maximum distance to report upstream associations in bp, overriding `distance`.
+    pub upstream_distance: Option<i64>,
+    /// Maximum distance to report downstream associations in bp, overriding `distance`.
+    pub downstream_distance: Option<i64>,
+    /// BED column (1-based) holding a per-region maximum distance in kb.
+    pub distance_column: Option<usize>,
+    /// BED column (1-based) holding a per-region weight for the gene summary.
+    pub weight_column: Option<usize>,
     /// Report level (exon, transcript, or gene).
     pub level: ReportLevel,
     /// GTF tag for gene ID.
     pub gene_id_tag: String,
     /// GTF tag for transcript ID.
     pub transcript_id_tag: String,
+    /// Report the candidates of the N closest genes per region instead of only
+    /// the single closest proximity candidate.
+    pub nearest_k: Option<usize>,
+    /// Report the closest gene on each strand per region.
+    pub nearest_per_strand: bool,
+    /// Report the closest gene on each side of the region, regardless of strand.
+    pub flanking: bool,
+    /// Minimum reciprocal overlap fraction (0-1] required for an association,
+    /// replacing the percentage thresholds. None disables the filter.
+    pub reciprocal_overlap: Option<f64>,
+    /// Maximum number of associations reported per region, keeping the
+    /// highest-priority and closest ones.
+    pub max_hits: Option<usize>,
+    /// Report a single association per region, the best across genes.
+    pub best_per_region: bool,
+    /// Emit an INTERGENIC row for regions without any gene association.
+    pub report_unmatched: bool,
+    /// Classify exonic overlaps of coding transcripts into 5_UTR/3_UTR areas.
+    pub utr: bool,
+    /// Classify exonic overlaps of coding transcripts within the CDS as CDS.
+    pub cds: bool,
+    /// Custom upstream zones replacing the TSS/PROMOTER/UPSTREAM split.
+    /// Empty means the built-in zones are used.
+    pub zones: Vec<Zone>,
+    /// Restrict matching to genes on the same or opposite strand of the region.
+    pub strand_mode: StrandMode,
+    /// Restrict matching to genes of some biotypes.
+    pub biotype_filter: BiotypeFilter,
+    /// Per-biotype configurations, each matching and reporting its own genes.
+    /// Empty unless per-biotype rules are set.
+    pub rule_sets: Vec<Config>,
+    /// Match against a BED annotation of features instead of genes.
+    pub features: bool,
+    /// Match against gene extents only, ignoring the exon/intron structure.
+    pub gene_extent: bool,
+    /// Write one row per gene listing its associated regions.
+    pub gene_summary: bool,
+    /// Flag regions overlapping a gene on the opposite strand (Antisense column).
+    pub report_antisense: bool,
+    /// Report strand-aware signed distances (SignedDistance column).
+    pub signed_distance: bool,
+    /// Report the strand of the regions (RegionStrand column).
+    pub region_strand: bool,
+    /// Columns identifying the region (Region ID, or Chrom, Start and End).
+    pub region_columns: RegionColumns,
+    /// Names of the BED metadata columns in the output, in place of the
+    /// standard ones (name, score, strand, ...).
+    pub bed_names: Vec<String>,
+    /// Leave out the header line of the output.
+    pub no_header: bool,
+    /// Start the header line of the output with `#`.
+    pub comment_header: bool,
+    /// Weights of the association confidence score (Confidence column), if reported.
+    pub confidence: Option<ConfidenceWeights>,
+    /// Report the percentage of the gene and transcript covered by the region.
+    pub gene_coverage: bool,
+    /// Report the overlaps with the area and the gene in base pairs.
+    pub overlap_bp: bool,
+    /// Report the coordinates of the matched area.
+    pub area_coordinates: bool,
+    /// Report the gene and transcript biotypes.
+    pub biotype_columns: bool,
+    /// Report the number of exons of the transcripts.
+    pub exon_count: bool,
+    /// Report a stable identifier of every association.
+    pub association_id: bool,
+    /// Report the rank of every row within its gene, with the rows sorted
+    /// by gene and distance.
+    pub gene_rank: bool,
+    /// Report the TSS distances of all merged transcripts at gene level.
+    pub tss_distance_list: bool,
+    /// Association model (rgmatch, GREAT or cis).
+    pub model: Model,
+    /// GREAT model parameters, used when `model` is GREAT.
+    pub great: GreatParams,
+    /// Half-width of the cis window around a region for the cis model, in bp.
+    pub cis_window: i64,
+    /// Match regions only against the TSS/PROMOTER window of each transcript.
+    pub promoters_only: bool,
+    /// Which transcripts of each gene are used for matching.
+    pub transcript_selection: TranscriptSelection,
+    /// Keep one transcript per cluster of TSSs within this many bp of each other.
+    pub tss_cluster: Option<i64>,
+    /// Only trust TSS calls from transcripts up to this support level, when any.
+    pub max_tsl: Option<u8>,
+    /// Secondary tie-break among candidates tied after the rules.
+    pub tie_break: TieBreak,
+    /// Point of the region that distances are measured from.
+    pub distance_anchor: DistanceAnchor,
+    /// Measure Distance and TSSDistance from the gene's canonical TSS.
+    pub gene_tss_distance: bool,
+    /// How exact duplicate input regions are handled.
+    pub duplicates: DuplicateMode,
+    /// Output compatibility mode, if any.
+    pub compat: Option<Compat>,
+    /// File format of the output.
+    pub format: OutputFormat,
+    /// Columns of the association table to write, in order; all if empty.
+    pub select_columns: Vec<ColumnSpec>,
+    /// Compress the output with bgzip and index it with tabix.
+    pub bgzip: bool,
+    /// File layout of the region-gene matrix.
+    pub matrix_format: MatrixFormat,
+    /// Value of the region-gene pairs in the matrix.
+    pub matrix_value: MatrixValue,
+    /// File format of the performance metrics.
+    pub metrics_format: MetricsFormat,
+    /// Bin size of the TSS distance profile, in bp.
+    pub tss_profile_bin: i64,
+    /// Distance from the TSS covered by the TSS distance profile, in bp.
+    pub tss_profile_window: i64,
+    /// Order of the rows of the per-association output.
+    pub sort_output: SortOrder,
+    /// Write one row per region with its first K associations as column
+    /// groups, instead of one row per association.
+    pub wide: Option<usize>,
+    /// Decimal places of the percentage columns.
+    pub precision: Precision,
+    /// Memory for the results waiting to be written in order, beyond which
+    /// they are spilled to disk (unlimited if None).
+    pub max_memory: Option<MemorySize>,
+    /// Threads of the parallel pipeline formatting the output lines, taken
+    /// from its threads (the writer formats them if None).
+    pub io_threads: Option<usize>,
+    /// Move the threads of the parallel pipeline between matching regions
+    /// and formatting their output with the backlog of results to format.
+    pub adaptive_threads: bool,
+    /// Write the results of the parallel pipeline as they are ready rather
+    /// than in input order.
+    pub unordered: bool,
+    /// Seed of the random generator shuffling the background sets.
+    pub seed: u64,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             rules: DEFAULT_RULES.to_vec(),
+            exclusive_rules: false,
             perc_area: 90.0,
             perc_region: 50.0,
             tss: 200.0,
             tts: 0.0,
             promoter: 1300.0,
             distance: 10000, // 10kb default (stored in bp)
+            upstream_distance: None,
+            downstream_distance: None,
+            distance_column: None,
+            weight_column: None,
             level: ReportLevel::Exon,
             gene_id_tag: "gene_id".to_string(),
             transcript_id_tag: "transcript_id".to_string(),
+            nearest_k: None,
+            nearest_per_strand: false,
+            flanking: false,
+            reciprocal_overlap: None,
+            max_hits: None,
+            best_per_region: false,
+            report_unmatched: false,
+            utr: false,
+            cds: false,
+            zones: Vec::new(),
+            strand_mode: StrandMode::Any,
+            biotype_filter: BiotypeFilter::Any,
+            rule_sets: Vec::new(),
+            features: false,
+            gene_extent: false,
+            gene_summary: false,
+            report_antisense: false,
+            signed_distance: false,
+            region_strand: false,
+            region_columns: RegionColumns::Id,
+            bed_names: Vec::new(),
+            no_header: false,
+            comment_header: false,
+            confidence: None,
+            gene_coverage: false,
+            overlap_bp: false,
+            area_coordinates: false,
+            biotype_columns: false,
+            exon_count: false,
+            association_id: false,
+            gene_rank: false,
+            tss_distance_list: false,
+            model: Model::Rgmatch,
+            great: GreatParams::default(),
+            cis_window: 1_000_000,
+            promoters_only: false,
+            transcript_selection: TranscriptSelection::All,
+            tss_cluster: None,
+            max_tsl: None,
+            tie_break: TieBreak::None,
+            distance_anchor: DistanceAnchor::Midpoint,
+            gene_tss_distance: false,
+            duplicates: DuplicateMode::Keep,
+            compat: None,
+            format: OutputFormat::Tsv,
+            select_columns: Vec::new(),
+            bgzip: false,
+            matrix_format: MatrixFormat::Mtx,
+            matrix_value: MatrixValue::Overlap,
+            metrics_format: MetricsFormat::Json,
+            tss_profile_bin: 100,
+            tss_profile_window: 5000,
+            sort_output: SortOrder::None,
+            wide: None,
+            precision: Precision::Digits(2),
+            max_memory: None,
+            io_threads: None,
+            adaptive_threads: false,
+            unordered: false,
+            seed: 0,
         }
     }
 }
@@ -67,8 +327,65 @@ impl Config {
 
     /// Parse and validate priority rules from a comma-separated string.
     ///
-    /// Returns true if all 8 valid tags were provided, false otherwise.
+    /// Returns true if all 8 core tags were provided, false otherwise.
+    /// The optional tags (5_UTR, 3_UTR, CDS) and the names of custom zones
+    /// may be ranked alongside them.
     pub fn parse_rules(&mut self, rules_str: &str) -> bool {
+        let mut new_rules = Vec::new();
+        let parts: Vec<&str> = rules_str.split(',').collect();
+
+        for tag in parts {
+            if let Some(area) = self.parse_rule_tag(tag) {
+                // Only add if not already present
+                if !new_rules.contains(&area) {
+                    new_rules.push(area);
+                }
+            }
+        }
+
+        if DEFAULT_RULES.iter().all(|area| new_rules.contains(area)) {
+            self.rules = new_rules;
+            self.rank_zones();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse priority rules that may list only some of the areas.
+    ///
+    /// Listed areas get priority in the given order. The unlisted core areas
+    /// are appended in their default order or, when `exclusive` is set,
+    /// excluded from reporting entirely. Returns false if a tag is unknown or
+    /// no tag is given.
+    pub fn parse_partial_rules(&mut self, rules_str: &str, exclusive: bool) -> bool {
+        let mut new_rules = Vec::new();
+
+        for tag in rules_str.split(',') {
+            let Some(area) = self.parse_rule_tag(tag) else {
+                return false;
+            };
+            if !new_rules.contains(&area) {
+                new_rules.push(area);
+            }
+        }
+
+        if !exclusive {
+            for area in DEFAULT_RULES {
+                if !new_rules.contains(&area) {
+                    new_rules.push(area);
+                }
+            }
+        }
+
+        self.rules = new_rules;
+        self.exclusive_rules = exclusive;
+        self.rank_zones();
+        true
+    }
+
+    /// Parse a single rule tag: a core or optional area, or a custom zone name.
+    fn parse_rule_tag(&self, tag: &str) -> Option<Area> {
         let valid_tags = [
             "TSS",
             "1st_EXON",
@@ -78,30 +395,178 @@ impl Config {
             "GENE_BODY",
             "UPSTREAM",
             "DOWNSTREAM",
+            "5_UTR",
+            "3_UTR",
+            "CDS",
+            "OVERLAP",
+            "OVERLAP_GENE",
         ];
 
-        let mut new_rules = Vec::new();
-        let parts: Vec<&str> = rules_str.split(',').collect();
+        if valid_tags.contains(&tag) {
+            tag.parse::<Area>().ok()
+        } else {
+            self.zones
+                .iter()
+                .map(|zone| &zone.area)
+                .find(|area| area.as_str() == tag)
+                .cloned()
+        }
+    }
 
-        for tag in parts {
-            if valid_tags.contains(&tag) {
-                if let Ok(area) = tag.parse::<Area>() {
-                    // Only add if not already present
-                    if !new_rules.contains(&area) {
-                        new_rules.push(area);
-                    }
-                }
+    /// Parse custom upstream zones from a comma-separated string.
+    ///
+    /// Each zone is given as `NAME:START-END`, in bp upstream of the TSS.
+    /// Zones must be listed from the TSS outwards without overlapping.
+    /// Returns true if the zones were valid, false otherwise.
+    pub fn parse_zones(&mut self, zones_str: &str) -> bool {
+        let mut zones: Vec<Zone> = Vec::new();
+
+        for part in zones_str.split(',') {
+            let Some((name, range)) = part.split_once(':') else {
+                return false;
+            };
+            let Some((start, end)) = range.split_once('-') else {
+                return false;
+            };
+            let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) else {
+                return false;
+            };
+
+            let previous_end = zones.last().map_or(0, |zone| zone.end);
+            if name.is_empty() || start < previous_end || end <= start {
+                return false;
+            }
+            if zones.iter().any(|zone| zone.area.as_str() == name) {
+                return false;
             }
+
+            zones.push(Zone {
+                area: Area::from_zone_name(name),
+                start,
+                end,
+            });
         }
 
-        if new_rules.len() == 8 {
-            self.rules = new_rules;
-            true
-        } else {
-            false
+        self.zones = zones;
+        self.rank_zones();
+        true
+    }
+
+    /// Split the promoter into proximal and distal tiers.
+    ///
+    /// Builds the zones TSS, PROMOTER_PROXIMAL (the first `proximal` bp of the
+    /// promoter) and PROMOTER_DISTAL (the rest) from the current TSS and
+    /// promoter distances. Returns false if `proximal` does not fall strictly
+    /// inside the promoter.
+    pub fn split_promoter(&mut self, proximal: i64) -> bool {
+        let tss = self.tss as i64;
+        let promoter = self.promoter as i64;
+        if proximal <= 0 || proximal >= promoter {
+            return false;
+        }
+
+        let mut zones = Vec::new();
+        if tss > 0 {
+            zones.push(format!("TSS:0-{}", tss));
+        }
+        zones.push(format!("PROMOTER_PROXIMAL:{}-{}", tss, tss + proximal));
+        zones.push(format!(
+            "PROMOTER_DISTAL:{}-{}",
+            tss + proximal,
+            tss + promoter
+        ));
+        self.parse_zones(&zones.join(","))
+    }
+
+    /// Rank custom zone areas in place of PROMOTER unless the rules already
+    /// place them. Exclusive rules only rank the listed zones.
+    fn rank_zones(&mut self) {
+        if self.exclusive_rules {
+            return;
+        }
+        for zone in self.zones.clone() {
+            if self.rules.contains(&zone.area) {
+                continue;
+            }
+            let pos = self
+                .rules
+                .iter()
+                .position(|a| *a == Area::Promoter)
+                .unwrap_or(self.rules.len());
+            self.rules.insert(pos, zone.area);
         }
     }
 
+    /// Switch to a compatibility mode, enabling the features it relies on.
+    ///
+    /// ChIPseeker annotates every region and distinguishes UTRs, so UTR
+    /// classification and unmatched rows are turned on; bedtools closest
+    /// also writes a row for regions without any gene.
+    pub fn set_compat(&mut self, compat: Compat) {
+        match compat {
+            Compat::ChipSeeker => {
+                self.enable_utr();
+                self.report_unmatched = true;
+            }
+            Compat::BedtoolsClosest => self.report_unmatched = true,
+            Compat::Python => {}
+        }
+        self.compat = Some(compat);
+    }
+
+    /// Enable matching against a BED annotation of features.
+    ///
+    /// OVERLAP is ranked first unless the rules already place it.
+    pub fn enable_features(&mut self) {
+        self.features = true;
+        if !self.rules.contains(&Area::Overlap) {
+            self.rules.insert(0, Area::Overlap);
+        }
+    }
+
+    /// Enable gene-extent matching.
+    ///
+    /// OVERLAP_GENE is ranked first unless the rules already place it.
+    pub fn enable_gene_extent(&mut self) {
+        self.gene_extent = true;
+        if !self.rules.contains(&Area::OverlapGene) {
+            self.rules.insert(0, Area::OverlapGene);
+        }
+    }
+
+    /// Enable UTR classification.
+    ///
+    /// The UTR areas are ranked right after 1st_EXON unless the rules
+    /// already place them.
+    pub fn enable_utr(&mut self) {
+        self.utr = true;
+        self.insert_rule_after(Area::ThreePrimeUtr, Area::FirstExon);
+        self.insert_rule_after(Area::FivePrimeUtr, Area::FirstExon);
+    }
+
+    /// Enable CDS classification.
+    ///
+    /// The CDS area is ranked right after 1st_EXON unless the rules
+    /// already place it.
+    pub fn enable_cds(&mut self) {
+        self.cds = true;
+        self.insert_rule_after(Area::Cds, Area::FirstExon);
+    }
+
+    /// Insert an optional area into the rules right after `anchor`, if absent.
+    /// Exclusive rules only report the listed optional areas.
+    fn insert_rule_after(&mut self, area: Area, anchor: Area) {
+        if self.exclusive_rules || self.rules.contains(&area) {
+            return;
+        }
+        let pos = self
+            .rules
+            .iter()
+            .position(|a| *a == anchor)
+            .map_or(self.rules.len(), |i| i + 1);
+        self.rules.insert(pos, area);
+    }
+
     /// Set distance in kb (converts to bp internally).
     pub fn set_distance_kb(&mut self, kb: i64) {
         if kb >= 0 {
@@ -109,10 +574,116 @@ impl Config {
         }
     }
 
+    /// Maximum distance to report an association of the given area in bp.
+    ///
+    /// Upstream and downstream areas honor their own limits when set.
+    pub fn distance_limit(&self, area: &Area) -> i64 {
+        match area {
+            area if area.is_upstream() => self.upstream_distance.unwrap_or(self.distance),
+            Area::Tts | Area::Downstream => self.downstream_distance.unwrap_or(self.distance),
+            _ => self.distance,
+        }
+    }
+
+    /// Maximum distance to report any proximity association in bp.
+    pub fn max_proximity_distance(&self) -> i64 {
+        self.distance_limit(&Area::Upstream)
+            .max(self.distance_limit(&Area::Downstream))
+    }
+
+    /// Maximum distance of a region in bp, read from the distance column.
+    ///
+    /// Returns None (falling back to the configured limits) without a
+    /// distance column, or when the value is missing, not a number or negative.
+    pub fn region_distance(&self, region: &Region) -> Option<i64> {
+        let column = self.distance_column?;
+        let value = region.metadata.get(column.checked_sub(4)?)?;
+        let kb: f64 = value.trim_end().parse().ok()?;
+        (kb.is_finite() && kb >= 0.0).then_some((kb * 1000.0) as i64)
+    }
+
+    /// Key under which exact duplicates of a region are replicated or
+    /// collapsed.
+    pub fn duplicate_key(&self, region: &Region) -> DuplicateKey {
+        (
+            region.chrom.clone(),
+            region.start,
+            region.end,
+            region.strand(),
+            region.summit(),
+            self.region_distance(region),
+        )
+    }
+
+    /// Lookback distance for a region, including its own maximum distance.
+    pub fn region_lookback_distance(&self, region: &Region) -> i64 {
+        let lookback = self.max_lookback_distance();
+        self.region_distance(region)
+            .map_or(lookback, |distance| lookback.max(distance))
+    }
+
     /// Get the maximum distance to consider for lookback
     pub fn max_lookback_distance(&self) -> i64 {
         let max_float = self.tss.max(self.tts).max(self.promoter);
-        self.distance.max(max_float as i64)
+        let max_zone = self.zones.last().map_or(0, |zone| zone.end);
+        let max_rule_set = self
+            .rule_sets
+            .iter()
+            .map(Config::max_lookback_distance)
+            .max()
+            .unwrap_or(0);
+        self.max_proximity_distance()
+            .max(max_float as i64)
+            .max(max_zone)
+            .max(max_rule_set)
+    }
+
+    /// Set per-biotype rule sets.
+    ///
+    /// Each biotype gets a copy of the current configuration with its
+    /// overrides applied, and the genes of any other biotype keep the current
+    /// configuration. Call once the rest of the configuration is set. Returns
+    /// false if the rules of a biotype are not properly passed.
+    pub fn set_biotype_rules(&mut self, sets: &[BiotypeRules]) -> bool {
+        let mut rule_sets = Vec::with_capacity(sets.len() + 1);
+
+        for set in sets {
+            let mut config = self.clone();
+            config.biotype_filter = BiotypeFilter::Include(set.biotype.clone());
+            if let Some(distance) = set.distance {
+                // A biotype distance applies both upstream and downstream
+                config.distance = distance;
+                config.upstream_distance = None;
+                config.downstream_distance = None;
+            }
+            config.tss = set.tss.unwrap_or(config.tss);
+            config.tts = set.tts.unwrap_or(config.tts);
+            config.promoter = set.promoter.unwrap_or(config.promoter);
+            if let Some(rules) = &set.rules {
+                if !config.parse_partial_rules(rules, set.exclusive) {
+                    return false;
+                }
+                // Re-rank the optional areas dropped by the new rules
+                if config.utr {
+                    config.enable_utr();
+                }
+                if config.cds {
+                    config.enable_cds();
+                }
+                if config.gene_extent {
+                    config.enable_gene_extent();
+                }
+            }
+            rule_sets.push(config);
+        }
+
+        let mut rest = self.clone();
+        rest.biotype_filter =
+            BiotypeFilter::Exclude(sets.iter().map(|set| set.biotype.clone()).collect());
+        rule_sets.push(rest);
+
+        self.rule_sets = rule_sets;
+        true
     }
 }
 
@@ -200,6 +771,250 @@ mod tests {
         assert!(!result); // Spaces make tags invalid
     }
 
+    #[test]
+    fn test_enable_utr_rule_placement() {
+        let mut config = Config::new();
+        config.enable_utr();
+        assert!(config.utr);
+        assert_eq!(config.rules.len(), 10);
+        assert_eq!(config.rules[2], Area::FivePrimeUtr);
+        assert_eq!(config.rules[3], Area::ThreePrimeUtr);
+
+        // Explicit placement from the rules string is kept
+        let mut config = Config::new();
+        assert!(config.parse_rules(
+            "TSS,1st_EXON,PROMOTER,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM,3_UTR,5_UTR"
+        ));
+        config.enable_utr();
+        assert_eq!(config.rules.len(), 10);
+        assert_eq!(config.rules[8], Area::ThreePrimeUtr);
+        assert_eq!(config.rules[9], Area::FivePrimeUtr);
+    }
+
+    #[test]
+    fn test_set_compat_chipseeker() {
+        let mut config = Config::new();
+        config.set_compat(Compat::ChipSeeker);
+        assert_eq!(config.compat, Some(Compat::ChipSeeker));
+        assert!(config.utr);
+        assert!(config.report_unmatched);
+    }
+
+    #[test]
+    fn test_enable_gene_extent() {
+        let mut config = Config::new();
+        config.enable_gene_extent();
+        assert!(config.gene_extent);
+        assert_eq!(config.rules[0], Area::OverlapGene);
+        assert_eq!(config.rules.len(), DEFAULT_RULES.len() + 1);
+
+        // Already ranked by the rules
+        let mut config = Config::new();
+        assert!(config.parse_partial_rules("UPSTREAM,OVERLAP_GENE", true));
+        config.enable_gene_extent();
+        assert_eq!(config.rules, vec![Area::Upstream, Area::OverlapGene]);
+    }
+
+    #[test]
+    fn test_enable_features() {
+        let mut config = Config::new();
+        config.enable_features();
+        assert!(config.features);
+        assert_eq!(config.rules[0], Area::Overlap);
+        assert_eq!(config.rules.len(), 9);
+    }
+
+    #[test]
+    fn test_enable_cds_rule_placement() {
+        let mut config = Config::new();
+        config.enable_utr();
+        config.enable_cds();
+        assert!(config.cds);
+        assert_eq!(config.rules.len(), 11);
+        assert_eq!(config.rules[1], Area::FirstExon);
+        assert_eq!(config.rules[2], Area::Cds);
+        assert_eq!(config.rules[3], Area::FivePrimeUtr);
+    }
+
+    #[test]
+    fn test_distance_limits() {
+        let mut config = Config::new();
+        assert_eq!(config.distance_limit(&Area::Upstream), 10_000);
+        assert_eq!(config.distance_limit(&Area::Tts), 10_000);
+
+        config.upstream_distance = Some(100_000);
+        config.downstream_distance = Some(5_000);
+        assert_eq!(config.distance_limit(&Area::Promoter), 100_000);
+        assert_eq!(config.distance_limit(&Area::Downstream), 5_000);
+        assert_eq!(config.distance_limit(&Area::Intron), 10_000);
+        assert_eq!(config.max_proximity_distance(), 100_000);
+        assert_eq!(config.max_lookback_distance(), 100_000);
+    }
+
+    #[test]
+    fn test_region_distance() {
+        let region = |value: &str| {
+            let meta = ["peak", "0", "+", value].map(String::from).to_vec();
+            Region::new("chr1".to_string(), 100, 200, meta)
+        };
+
+        let mut config = Config::new();
+        assert_eq!(config.region_distance(&region("50")), None);
+
+        config.distance_column = Some(7);
+        assert_eq!(config.region_distance(&region("50")), Some(50_000));
+        assert_eq!(config.region_distance(&region("2.5")), Some(2_500));
+        assert_eq!(config.region_distance(&region(".")), None);
+        assert_eq!(config.region_distance(&region("-1")), None);
+        assert_eq!(config.region_lookback_distance(&region("50")), 50_000);
+        assert_eq!(config.region_lookback_distance(&region("1")), 10_000);
+
+        config.distance_column = Some(9);
+        assert_eq!(config.region_distance(&region("50")), None);
+    }
+
+    #[test]
+    fn test_parse_zones() {
+        let mut config = Config::new();
+        assert!(config.parse_zones("TSS:0-200,PROXIMAL:200-2000,DISTAL:2000-50000"));
+        assert_eq!(config.zones.len(), 3);
+        assert_eq!(config.zones[0].area, Area::Tss);
+        assert_eq!(config.zones[1].area.as_str(), "PROXIMAL");
+        assert_eq!(config.zones[2].end, 50000);
+        assert_eq!(config.max_lookback_distance(), 50000);
+
+        // Custom zones are ranked where PROMOTER was
+        assert_eq!(config.rules[2].as_str(), "PROXIMAL");
+        assert_eq!(config.rules[3].as_str(), "DISTAL");
+        assert_eq!(config.rules[4], Area::Promoter);
+    }
+
+    #[test]
+    fn test_parse_zones_invalid() {
+        let mut config = Config::new();
+        assert!(!config.parse_zones(""));
+        assert!(!config.parse_zones("TSS"));
+        assert!(!config.parse_zones("TSS:200-0"));
+        assert!(!config.parse_zones("TSS:0-x"));
+        assert!(!config.parse_zones("A:0-500,B:200-1000"));
+        assert!(!config.parse_zones("A:0-500,A:500-1000"));
+        assert!(config.zones.is_empty());
+    }
+
+    #[test]
+    fn test_split_promoter() {
+        let mut config = Config::new();
+        assert!(config.split_promoter(300));
+        let zones: Vec<(&str, i64, i64)> = config
+            .zones
+            .iter()
+            .map(|zone| (zone.area.as_str(), zone.start, zone.end))
+            .collect();
+        assert_eq!(
+            zones,
+            vec![
+                ("TSS", 0, 200),
+                ("PROMOTER_PROXIMAL", 200, 500),
+                ("PROMOTER_DISTAL", 500, 1500)
+            ]
+        );
+
+        assert!(!Config::new().split_promoter(0));
+        assert!(!Config::new().split_promoter(1300));
+    }
+
+    #[test]
+    fn test_parse_rules_with_zones() {
+        let mut config = Config::new();
+        assert!(config.parse_zones("NEAR:0-500,FAR:500-5000"));
+        assert!(config
+            .parse_rules("FAR,TSS,1st_EXON,PROMOTER,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM"));
+        assert_eq!(config.rules[0].as_str(), "FAR");
+        // Zones missing from the rules string are still ranked
+        assert!(config.rules.iter().any(|area| area.as_str() == "NEAR"));
+    }
+
+    #[test]
+    fn test_parse_partial_rules() {
+        let mut config = Config::new();
+        assert!(config.parse_partial_rules("PROMOTER,TSS", false));
+        assert_eq!(
+            config.rules,
+            vec![
+                Area::Promoter,
+                Area::Tss,
+                Area::FirstExon,
+                Area::Tts,
+                Area::Intron,
+                Area::GeneBody,
+                Area::Upstream,
+                Area::Downstream,
+            ]
+        );
+        assert!(!config.exclusive_rules);
+
+        assert!(!config.parse_partial_rules("TSS,UNKNOWN", false));
+        assert!(!config.parse_partial_rules("", false));
+    }
+
+    #[test]
+    fn test_parse_partial_rules_exclusive() {
+        let mut config = Config::new();
+        assert!(config.parse_zones("NEAR:0-500,FAR:500-5000"));
+        assert!(config.parse_partial_rules("TSS,FAR", true));
+        assert!(config.exclusive_rules);
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[1].as_str(), "FAR");
+
+        // Optional areas must be listed to be reported
+        config.enable_utr();
+        assert_eq!(config.rules.len(), 2);
+    }
+
+    #[test]
+    fn test_set_biotype_rules() {
+        let mut config = Config::new();
+        let sets = vec![
+            BiotypeRules {
+                biotype: "lncRNA".to_string(),
+                distance: Some(50_000),
+                ..BiotypeRules::default()
+            },
+            BiotypeRules {
+                biotype: "miRNA".to_string(),
+                rules: Some("TSS".to_string()),
+                exclusive: true,
+                ..BiotypeRules::default()
+            },
+        ];
+        assert!(config.set_biotype_rules(&sets));
+        assert_eq!(config.rule_sets.len(), 3);
+        assert_eq!(config.max_lookback_distance(), 50_000);
+
+        let lnc = &config.rule_sets[0];
+        assert_eq!(lnc.distance, 50_000);
+        assert_eq!(lnc.rules, DEFAULT_RULES.to_vec());
+        assert!(lnc.biotype_filter.accepts(Some("lncRNA")));
+        assert!(!lnc.biotype_filter.accepts(None));
+
+        let mirna = &config.rule_sets[1];
+        assert_eq!(mirna.rules, vec![Area::Tss]);
+        assert!(mirna.exclusive_rules);
+
+        let rest = &config.rule_sets[2];
+        assert_eq!(rest.distance, 10_000);
+        assert!(rest.biotype_filter.accepts(Some("protein_coding")));
+        assert!(rest.biotype_filter.accepts(None));
+        assert!(!rest.biotype_filter.accepts(Some("miRNA")));
+
+        let invalid = vec![BiotypeRules {
+            biotype: "lncRNA".to_string(),
+            rules: Some("NOPE".to_string()),
+            ..BiotypeRules::default()
+        }];
+        assert!(!Config::new().set_biotype_rules(&invalid));
+    }
+
     #[test]
     fn test_set_distance_kb() {
         let mut config = Config::new();
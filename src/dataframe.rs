@@ -0,0 +1,175 @@
+//! Polars DataFrame of the associations.
+//!
+//! Builds a typed DataFrame from the structured results of the matcher, for
+//! Rust users who want to analyze the associations without writing and
+//! parsing the text output. Columns follow the default output table, with
+//! the region coordinates as their own columns and nulls where the text
+//! output has `NA`.
+
+use std::borrow::Borrow;
+
+use anyhow::Result;
+use polars::prelude::{Column, DataFrame};
+
+use crate::config::Config;
+use crate::matcher::annotate_region;
+use crate::matcher::overlap::find_search_start_index;
+use crate::parser::GtfData;
+use crate::types::{Area, Candidate, Region};
+
+/// Annotate regions against the genes of the annotation and collect the
+/// associations into a DataFrame.
+///
+/// Regions are matched one by one against the genes of their chromosome,
+/// in any order, with the rgmatch model; the configuration should be
+/// complete (rules, report level, ...) as for the command line. Regions
+/// without associations only get a row with `report_unmatched`.
+pub fn annotate_to_dataframe(
+    gtf_data: &GtfData,
+    regions: &[Region],
+    config: &Config,
+) -> Result<DataFrame> {
+    let results: Vec<(&Region, Vec<Candidate>)> = regions
+        .iter()
+        .map(|region| {
+            let candidates = match gtf_data.genes_by_chrom.get(&region.chrom) {
+                Some(genes) => {
+                    let max_len = *gtf_data.max_lengths.get(&region.chrom).unwrap_or(&0);
+                    let max_lookback = max_len + config.region_lookback_distance(region);
+                    let search_start = region.start.saturating_sub(max_lookback);
+                    let start_index = find_search_start_index(genes, search_start);
+                    annotate_region(region, genes, config, start_index)
+                }
+                None => annotate_region(region, &[], config, 0),
+            };
+            (region, candidates)
+        })
+        .collect();
+    associations_to_dataframe(&results)
+}
+
+/// Collect annotated regions and their associations into a DataFrame, one
+/// row per association.
+///
+/// Columns: `Chrom`, `Start`, `End`, `Region`, `Midpoint`, `Gene`,
+/// `Transcript`, `Exon/Intron`, `Area`, `Distance`, `TSSDistance`,
+/// `PercRegion` and `PercArea`. The gene, transcript, distances and
+/// percentages of INTERGENIC rows are null.
+pub fn associations_to_dataframe<R: Borrow<Region>>(
+    results: &[(R, Vec<Candidate>)],
+) -> Result<DataFrame> {
+    let rows = results.iter().map(|(_, candidates)| candidates.len()).sum();
+    let mut chrom = Vec::with_capacity(rows);
+    let mut start = Vec::with_capacity(rows);
+    let mut end = Vec::with_capacity(rows);
+    let mut region_id = Vec::with_capacity(rows);
+    let mut midpoint = Vec::with_capacity(rows);
+    let mut gene = Vec::with_capacity(rows);
+    let mut transcript = Vec::with_capacity(rows);
+    let mut exon = Vec::with_capacity(rows);
+    let mut area = Vec::with_capacity(rows);
+    let mut distance = Vec::with_capacity(rows);
+    let mut tss_distance = Vec::with_capacity(rows);
+    let mut pctg_region = Vec::with_capacity(rows);
+    let mut pctg_area = Vec::with_capacity(rows);
+
+    for (region, candidates) in results {
+        let region: &Region = region.borrow();
+        for candidate in candidates {
+            let matched = (candidate.area != Area::Intergenic).then_some(candidate);
+            chrom.push(region.chrom.clone());
+            start.push(region.start);
+            end.push(region.end);
+            region_id.push(region.id());
+            midpoint.push(region.midpoint());
+            gene.push(matched.map(|c| c.gene.clone()));
+            transcript.push(matched.map(|c| c.transcript.clone()));
+            exon.push(matched.map(|c| c.exon_number.clone()));
+            area.push(candidate.area.as_str());
+            distance.push(matched.map(|c| c.distance));
+            tss_distance.push(matched.map(|c| c.tss_distance));
+            pctg_region.push(matched.map(|c| c.pctg_region));
+            pctg_area.push(matched.map(|c| c.pctg_area));
+        }
+    }
+
+    let frame = DataFrame::new(vec![
+        Column::new("Chrom".into(), chrom),
+        Column::new("Start".into(), start),
+        Column::new("End".into(), end),
+        Column::new("Region".into(), region_id),
+        Column::new("Midpoint".into(), midpoint),
+        Column::new("Gene".into(), gene),
+        Column::new("Transcript".into(), transcript),
+        Column::new("Exon/Intron".into(), exon),
+        Column::new("Area".into(), area),
+        Column::new("Distance".into(), distance),
+        Column::new("TSSDistance".into(), tss_distance),
+        Column::new("PercRegion".into(), pctg_region),
+        Column::new("PercArea".into(), pctg_area),
+    ])?;
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+    use polars::prelude::DataType;
+
+    #[test]
+    fn test_associations_to_dataframe() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            50.0,
+            -150,
+        );
+        let unmatched = crate::matcher::intergenic_candidate(&region);
+        let results = vec![(region.clone(), vec![candidate, unmatched])];
+
+        let frame = associations_to_dataframe(&results).unwrap();
+        assert_eq!(frame.shape(), (2, 13));
+        assert_eq!(frame.column("Start").unwrap().dtype(), &DataType::Int64);
+        assert_eq!(
+            frame.column("PercArea").unwrap().dtype(),
+            &DataType::Float64
+        );
+
+        let tss = frame.column("TSSDistance").unwrap().i64().unwrap();
+        assert_eq!(tss.get(0), Some(-150));
+        assert_eq!(tss.get(1), None);
+        let area = frame.column("Area").unwrap().str().unwrap();
+        assert_eq!(area.get(1), Some("INTERGENIC"));
+    }
+
+    #[test]
+    fn test_annotate_to_dataframe() {
+        let data = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        let gtf_data =
+            crate::parser::parse_gtf(&data.join("subset_genome.gtf"), "gene_id", "transcript_id")
+                .unwrap();
+        let bed_data = crate::parser::parse_bed(&data.join("subset_peaks.bed")).unwrap();
+        let regions: Vec<Region> = bed_data.regions_by_chrom.into_values().flatten().collect();
+
+        let config = Config::default();
+        let frame = annotate_to_dataframe(&gtf_data, &regions, &config).unwrap();
+        assert!(frame.height() > 0);
+        assert_eq!(frame.column("Gene").unwrap().null_count(), 0);
+
+        let config = Config {
+            report_unmatched: true,
+            ..Config::default()
+        };
+        let unmatched = annotate_to_dataframe(&gtf_data, &regions, &config).unwrap();
+        assert!(unmatched.height() >= frame.height());
+    }
+}
@@ -33,10 +33,14 @@
 //! ```
 
 pub mod config;
+#[cfg(feature = "polars")]
+pub mod dataframe;
 pub mod matcher;
 pub mod output;
 pub mod parser;
+pub mod simulate;
 pub mod types;
+pub mod warnings;
 
 pub use config::Config;
 pub use parser::{BedReader, GtfData};
@@ -2,151 +2,66 @@
 //!
 //! This provides a command-line interface matching the Python implementation.
 
+use ahash::AHashMap;
 use anyhow::{bail, Context, Result};
-use clap::Parser;
-use crossbeam_channel::{bounded, Receiver, Sender};
-use std::collections::VecDeque;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use indexmap::map::Entry;
+use indexmap::{IndexMap, IndexSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rayon::prelude::*;
-use rgmatch::config::Config;
+use rgmatch::config::{Config, DuplicateKey};
 use rgmatch::matcher::overlap::find_search_start_index;
-use rgmatch::matcher::{match_region_to_genes, process_candidates_for_output};
-use rgmatch::output::{format_output_line, write_header};
-use rgmatch::parser::gtf::GtfData;
-use rgmatch::parser::{parse_gtf, BedReader};
-use rgmatch::types::{Candidate, Region, ReportLevel};
-
-/// Performance metrics for profiling bottlenecks.
-/// All times are in nanoseconds.
-#[derive(Default)]
-struct PerfMetrics {
-    /// Total time workers spend on matching (match_region_to_genes + process_candidates)
-    worker_matching_ns: AtomicU64,
-    /// Total time workers spend waiting to send results on the channel
-    worker_channel_wait_ns: AtomicU64,
-    /// Total time the writer spends formatting output lines
-    writer_format_ns: AtomicU64,
-    /// Total time the writer spends on I/O (writeln!)
-    writer_io_ns: AtomicU64,
-    /// Number of regions processed by workers
-    regions_processed: AtomicU64,
-    /// Number of output lines written
-    lines_written: AtomicU64,
-    /// Maximum size of the pending buffer in the writer
-    max_pending_size: AtomicU64,
-}
-
-impl PerfMetrics {
-    fn new() -> Self {
-        Self::default()
-    }
-
-    fn add_worker_matching(&self, ns: u64) {
-        self.worker_matching_ns.fetch_add(ns, Ordering::Relaxed);
-    }
-
-    fn add_worker_channel_wait(&self, ns: u64) {
-        self.worker_channel_wait_ns.fetch_add(ns, Ordering::Relaxed);
-    }
-
-    fn add_writer_format(&self, ns: u64) {
-        self.writer_format_ns.fetch_add(ns, Ordering::Relaxed);
-    }
-
-    fn add_writer_io(&self, ns: u64) {
-        self.writer_io_ns.fetch_add(ns, Ordering::Relaxed);
-    }
-
-    fn add_regions_processed(&self, count: u64) {
-        self.regions_processed.fetch_add(count, Ordering::Relaxed);
-    }
-
-    fn add_lines_written(&self, count: u64) {
-        self.lines_written.fetch_add(count, Ordering::Relaxed);
-    }
-
-    fn update_max_pending(&self, size: usize) {
-        let size = size as u64;
-        let mut current = self.max_pending_size.load(Ordering::Relaxed);
-        while size > current {
-            match self.max_pending_size.compare_exchange_weak(
-                current,
-                size,
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-            ) {
-                Ok(_) => break,
-                Err(c) => current = c,
-            }
-        }
-    }
-
-    fn print_summary(&self) {
-        let worker_matching_ms =
-            self.worker_matching_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let worker_channel_wait_ms =
-            self.worker_channel_wait_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let writer_format_ms = self.writer_format_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let writer_io_ms = self.writer_io_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let regions = self.regions_processed.load(Ordering::Relaxed);
-        let lines = self.lines_written.load(Ordering::Relaxed);
-        let max_pending = self.max_pending_size.load(Ordering::Relaxed);
-
-        eprintln!("\n=== Performance Metrics ===");
-        eprintln!("Regions processed: {}", regions);
-        eprintln!("Lines written: {}", lines);
-        eprintln!();
-        eprintln!("Worker time (cumulative across all workers):");
-        eprintln!("  Matching:      {:>10.2} ms", worker_matching_ms);
-        eprintln!("  Channel wait:  {:>10.2} ms", worker_channel_wait_ms);
-        eprintln!();
-        eprintln!("Writer time:");
-        eprintln!("  Formatting:    {:>10.2} ms", writer_format_ms);
-        eprintln!("  I/O:           {:>10.2} ms", writer_io_ms);
-        eprintln!();
-        eprintln!("Channel congestion:");
-        eprintln!(
-            "  Max pending results: {} (channel bound: 2000)",
-            max_pending
-        );
-        if max_pending >= 1900 {
-            eprintln!("  ⚠️  Channel nearly full - WRITER IS BOTTLENECK");
-        } else if max_pending < 100 {
-            eprintln!("  ✓  Channel uncongested - Workers are bottleneck");
-        } else {
-            eprintln!("  ~  Moderate congestion - Mixed bottleneck");
-        }
-        eprintln!();
-
-        // Calculate ratios
-        let total_worker = worker_matching_ms + worker_channel_wait_ms;
-        let total_writer = writer_format_ms + writer_io_ms;
-        if total_worker > 0.0 {
-            eprintln!("Worker breakdown:");
-            eprintln!(
-                "  Matching: {:.1}%",
-                100.0 * worker_matching_ms / total_worker
-            );
-            eprintln!(
-                "  Waiting:  {:.1}%",
-                100.0 * worker_channel_wait_ms / total_worker
-            );
-        }
-        if total_writer > 0.0 {
-            eprintln!("Writer breakdown:");
-            eprintln!("  Format: {:.1}%", 100.0 * writer_format_ms / total_writer);
-            eprintln!("  I/O:    {:.1}%", 100.0 * writer_io_ms / total_writer);
-        }
-        eprintln!("=== End Performance Metrics ===\n");
-    }
-}
+use rgmatch::matcher::pair_anchors;
+use rgmatch::matcher::{
+    annotate_region, area_enrichment, diff_annotations, region_area, shuffle_regions, CisIndex,
+    DomainIndex, Exclusions, LinkIndex, PromoterIndex, RegionIndex,
+};
+use rgmatch::output::sort::compare_chroms;
+use rgmatch::output::{
+    diff_outputs, format_diff_line, format_loop_line, format_output_line_with, format_wide_line,
+    is_stdout, open_output, write_diff_header, write_enrichment, write_header_with,
+    write_html_report, write_loop_header, AnnotationStats, Checkpoint, GeneSets, GeneSummary,
+    InputFile, OutputFile, OutputOptions, OutputTable, PerfMetrics, RegionGeneMatrix,
+    ReorderBuffer, RunMetadata, RunMetrics, RunSummary, SampleSummaries, SetComparison, TssProfile,
+};
+use rgmatch::parser::gtf::{filter_gtf, GtfData};
+use rgmatch::parser::index::is_index;
+use rgmatch::parser::util::create_buffered_reader;
+use rgmatch::parser::{
+    glob_samples, is_pattern, parse_annotation, parse_bed, parse_bedpe, parse_biotype_rules,
+    parse_chrom_sizes, parse_feature_bed, parse_links, parse_manifest, write_index, BedReader,
+    ManifestSample,
+};
+use rgmatch::simulate::{self, Simulation};
+use rgmatch::types::{
+    Area, Candidate, Compat, DuplicateMode, LogFormat, Model, OutputFormat, Region, ReportLevel,
+    SortOrder, StrandMode, Zone,
+};
+use rgmatch::warnings;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use tracing_subscriber::filter::LevelFilter;
+
+/// Capacity of the result channel of the parallel pipeline.
+const RESULT_CHANNEL_BOUND: usize = 2000;
+
+/// Regions per batch without --batch-size, where batches are not adapted.
+const DEFAULT_BATCH_SIZE: usize = 5000;
 
 /// Genomic region-to-gene matching tool.
 ///
@@ -154,27 +69,459 @@ impl PerfMetrics {
 #[derive(Parser, Debug)]
 #[command(name = "rgmatch")]
 #[command(author, version, about, long_about = None)]
-struct Args {
-    /// GTF annotation file (required)
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Log more detail to stderr: debug messages, and trace messages when repeated (-DD)
+    #[arg(short = 'D', long = "verbose", action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only log warnings and errors to stderr
+    #[arg(short = 'Q', long = "quiet", global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Format of the log messages on stderr: text or json
+    #[arg(long = "log-format", default_value = "text", global = true)]
+    log_format: String,
+
+    /// Fail on the first input line that cannot be read, instead of skipping it with a warning
+    #[arg(long = "strict", global = true)]
+    strict: bool,
+
+    /// Seed of the random generators (--background shuffling, simulate); the same seed gives the same output
+    #[arg(long = "seed", default_value = "0", global = true)]
+    seed: u64,
+
+    /// Most warnings logged to stderr; repeats of a warning and the warnings past this are only counted in the summary
+    #[arg(long = "max-warnings", value_name = "N", default_value_t = warnings::DEFAULT_MAX_WARNINGS, global = true)]
+    max_warnings: u64,
+}
+
+/// Send log messages to stderr at the level set by --verbose and --quiet.
+fn init_logging(cli: &Cli) -> Result<()> {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::WARN,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    let format: LogFormat = cli
+        .log_format
+        .parse()
+        .context("Log format can only be one of the following: text, json.")?;
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_target(false);
+    match format {
+        LogFormat::Text => builder.without_time().init(),
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+    Ok(())
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Match the regions of a BED file to the genes of an annotation (the default without a subcommand)
+    Annotate(Box<Args>),
+    /// Parse a GTF once into an index that --gtf loads without parsing it again
+    Index(IndexArgs),
+    /// Count the genes, transcripts, exons and TSSs of each chromosome and biotype of a GTF or index
+    Stats(StatsArgs),
+    /// Check that an annotation and a BED file can be read and share chromosomes
+    Validate(ValidateArgs),
+    /// Write the lines of a GTF for the genes near the regions of a BED file
+    SubsetGtf(SubsetGtfArgs),
+    /// Write a random but valid GTF and BED file for benchmarking and fuzzing
+    Simulate(SimulateArgs),
+    /// Compare two annotate outputs row by row, keyed by region and gene
+    Diff(DiffArgs),
+    /// Annotate the regions of a BED file against two annotations and report the genes gained, lost or changed
+    DiffGtf(Box<DiffGtfArgs>),
+    /// Write a TOML configuration file with every annotate option, for --config
+    Config(ConfigArgs),
+}
+
+/// Subcommand names, told apart from the arguments of a flat invocation.
+const SUBCOMMANDS: [&str; 10] = [
+    "annotate",
+    "index",
+    "stats",
+    "validate",
+    "subset-gtf",
+    "simulate",
+    "diff",
+    "diff-gtf",
+    "config",
+    "help",
+];
+
+/// Command line arguments, with `annotate` inserted for the flat invocation
+/// (`rgmatch -g genes.gtf -b regions.bed`) of earlier versions.
+fn cli_args() -> Result<Vec<OsString>> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    check_short_log_options(&args)?;
+    Ok(normalize_args(args))
+}
+
+/// Insert `annotate` when there is no subcommand, and move the global
+/// options given before the subcommand after it.
+fn normalize_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let end = global_options_end(&args);
+    let global: Vec<OsString> = args.drain(1..end.min(args.len())).collect();
+    let flat = args.get(1).is_some_and(|first| {
+        let first = first.to_string_lossy();
+        !SUBCOMMANDS.contains(&first.as_ref())
+            && !matches!(first.as_ref(), "-h" | "--help" | "-V" | "--version")
+    });
+    if flat {
+        args.insert(1, OsString::from("annotate"));
+    }
+    let at = args.len().min(2);
+    args.splice(at..at, global);
+    args
+}
+
+/// Index of the first argument after the global options that start the
+/// arguments.
+fn global_options_end(args: &[OsString]) -> usize {
+    let mut end = 1;
+    while let Some(arg) = args.get(end).map(|arg| arg.to_string_lossy()) {
+        match arg.as_ref() {
+            "--verbose" | "--quiet" | "--strict" => end += 1,
+            "--log-format" | "--seed" | "--max-warnings" => end += 2,
+            arg if ["--log-format=", "--seed=", "--max-warnings="]
+                .iter()
+                .any(|prefix| arg.starts_with(prefix)) =>
+            {
+                end += 1
+            }
+            arg if arg.strip_prefix('-').is_some_and(|short| {
+                !short.is_empty() && short.bytes().all(|b| b == b'D' || b == b'Q')
+            }) =>
+            {
+                end += 1
+            }
+            _ => break,
+        }
+    }
+    end
+}
+
+/// Reject `-q` and `-v` used as the log options of other tools: they are
+/// the --distance and --perc_area of annotate, so `-vv`, or `-q`/`-v`
+/// without a value (e.g. right before a subcommand name or another option),
+/// would otherwise fail with a confusing usage error.
+fn check_short_log_options(args: &[OsString]) -> Result<()> {
+    let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        if arg == "--" {
+            break;
+        }
+        let repeated_v =
+            arg.len() > 2 && arg.starts_with('-') && arg[1..].bytes().all(|b| b == b'v');
+        let no_value = matches!(arg.as_ref(), "-q" | "-v")
+            && args.get(i + 1).map_or(true, |next| {
+                SUBCOMMANDS.contains(&next.as_ref())
+                    || (next.starts_with('-') && next.parse::<f64>().is_err())
+            });
+        if repeated_v || no_value {
+            bail!(
+                "{} is ambiguous: -q and -v are --distance and --perc_area, which take a value; use -Q (--quiet) or -D, -DD (--verbose) for the log messages",
+                arg
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Annotation read by the index, stats, validate and subset-gtf subcommands.
+#[derive(clap::Args, Debug)]
+struct AnnotationArgs {
+    /// GTF annotation file or rgmatch index
+    #[arg(short = 'g', long = "gtf")]
+    gtf: PathBuf,
+
+    /// GTF tag for gene ID
+    #[arg(short = 'G', long = "gene", default_value = "gene_id")]
+    gene_tag: String,
+
+    /// GTF tag for transcript ID
+    #[arg(short = 'T', long = "transcript", default_value = "transcript_id")]
+    transcript_tag: String,
+}
+
+impl AnnotationArgs {
+    fn parse_annotation(&self) -> Result<GtfData> {
+        if !self.gtf.exists() {
+            bail!("GTF file not found: {}", self.gtf.display());
+        }
+        info!("Parsing annotation: {}", self.gtf.display());
+        parse_annotation(&self.gtf, &self.gene_tag, &self.transcript_tag)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct IndexArgs {
+    #[command(flatten)]
+    annotation: AnnotationArgs,
+
+    /// Index file to write (gzip-compressed if it ends with .gz)
+    #[arg(short = 'o', long = "output")]
+    output: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct StatsArgs {
+    #[command(flatten)]
+    annotation: AnnotationArgs,
+
+    /// Output file, or - for standard output
+    #[arg(short = 'o', long = "output", default_value = "-")]
+    output: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    #[command(flatten)]
+    annotation: AnnotationArgs,
+
+    /// Region BED file
+    #[arg(short = 'b', long = "bed")]
+    bed: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct SubsetGtfArgs {
+    #[command(flatten)]
+    annotation: AnnotationArgs,
+
+    /// Region BED file
+    #[arg(short = 'b', long = "bed")]
+    bed: PathBuf,
+
+    /// Keep the genes within this distance in kb of a region
+    #[arg(short = 'q', long = "distance", default_value = "10")]
+    distance: u64,
+
+    /// GTF file to write (gzip-compressed if it ends with .gz), or - for standard output
+    #[arg(short = 'o', long = "output", default_value = "-")]
+    output: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct SimulateArgs {
+    /// GTF file to write (gzip-compressed if it ends with .gz)
     #[arg(short = 'g', long = "gtf")]
     gtf: PathBuf,
 
-    /// Region BED file (required)
+    /// BED file to write (gzip-compressed if it ends with .gz)
     #[arg(short = 'b', long = "bed")]
     bed: PathBuf,
 
-    /// Output file (required)
-    #[arg(short = 'o', long = "output")]
+    /// Number of chromosomes, named chr1, chr2, ...
+    #[arg(long = "chromosomes", default_value = "3")]
+    chromosomes: usize,
+
+    /// Length of every chromosome in bp
+    #[arg(long = "chrom-length", default_value = "1000000")]
+    chrom_length: i64,
+
+    /// Genes per Mb
+    #[arg(long = "gene-density", default_value = "10")]
+    gene_density: f64,
+
+    /// Most transcripts of a gene
+    #[arg(long = "max-transcripts", default_value = "3")]
+    max_transcripts: usize,
+
+    /// Fewest exons of a transcript
+    #[arg(long = "min-exons", default_value = "1")]
+    min_exons: usize,
+
+    /// Most exons of a transcript
+    #[arg(long = "max-exons", default_value = "10")]
+    max_exons: usize,
+
+    /// Number of regions
+    #[arg(long = "regions", default_value = "1000")]
+    regions: usize,
+
+    /// Narrowest region in bp
+    #[arg(long = "min-width", default_value = "100")]
+    min_width: i64,
+
+    /// Widest region in bp
+    #[arg(long = "max-width", default_value = "1000")]
+    max_width: i64,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Output of the old run (gzip-compressed if it ends with .gz)
+    old: PathBuf,
+
+    /// Output of the new run (gzip-compressed if it ends with .gz)
+    new: PathBuf,
+
+    /// Relative difference (absolute below 1) up to which numbers are equal
+    #[arg(long = "tolerance", default_value = "1e-6")]
+    tolerance: f64,
+
+    /// Report file, or - for standard output
+    #[arg(short = 'o', long = "output", default_value = "-")]
+    output: PathBuf,
+}
+
+/// Arguments of the diff-gtf subcommand: the annotate options, with -g the
+/// old annotation.
+#[derive(clap::Args, Debug)]
+struct DiffGtfArgs {
+    /// GTF (or index) of the new annotation (e.g. a newer release) to compare with -g
+    #[arg(long = "new-gtf", value_name = "GTF")]
+    new_gtf: PathBuf,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConfigArgs {
+    /// Print every option at its default value, commented out
+    #[arg(long = "print-default")]
+    print_default: bool,
+}
+
+/// Arguments of the annotate subcommand.
+#[derive(clap::Args, Debug)]
+struct Args {
+    /// TOML file setting options by their long name (e.g. distance = 20); options given on the command line or in RGMATCH_* variables take precedence
+    #[arg(long = "config", value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// GTF annotation file or rgmatch index (required unless --features is given)
+    #[arg(short = 'g', long = "gtf", required_unless_present = "features")]
+    gtf: Option<PathBuf>,
+
+    /// BED file of named features (enhancers, CpG islands, ...) to annotate against instead of a GTF
+    #[arg(long = "features", conflicts_with = "gtf")]
+    features: Option<PathBuf>,
+
+    /// Region BED file, or a quoted glob pattern (e.g. 'peaks/*.narrowPeak') annotating each matching file as a sample (required unless --manifest is given)
+    #[arg(
+        short = 'b',
+        long = "bed",
+        value_name = "BED",
+        required_unless_present = "manifest"
+    )]
+    bed_arg: Option<PathBuf>,
+
+    /// Region BED file of the run, or of the sample of a manifest run
+    #[arg(skip)]
+    bed: PathBuf,
+
+    /// BED files matching the -b pattern
+    #[arg(skip)]
+    bed_files: Vec<PathBuf>,
+
+    /// TSV of samples (name, BED file, optional option=value overrides) to annotate against one loaded annotation, writing one output per sample into the -o directory
+    #[arg(
+        long = "manifest",
+        value_name = "FILE",
+        conflicts_with_all = ["bed_arg", "bedpe", "compare_bed", "background", "split_by_area", "gene_sets", "gene_table", "matrix", "tss_profile", "html_report"]
+    )]
+    manifest: Option<PathBuf>,
+
+    /// Read the input as a BEDPE file of loops and report the gene pairs joined by each loop
+    #[arg(long = "bedpe", conflicts_with_all = ["gene_summary", "merge_input"])]
+    bedpe: bool,
+
+    /// Enhancer-gene links (chrom, start, end, gene); regions overlapping a link are reported as LINKED to its gene
+    #[arg(long = "links")]
+    links: Option<PathBuf>,
+
+    /// New annotation of a diff-gtf run
+    #[arg(skip)]
+    diff_gtf: Option<PathBuf>,
+
+    /// Second region BED file (e.g. control peaks) to compare with -b, reporting the genes gained, lost or shared
+    #[arg(
+        long = "compare-bed",
+        value_name = "BED",
+        conflicts_with_all = ["bedpe", "gene_summary"]
+    )]
+    compare_bed: Option<PathBuf>,
+
+    /// Number of shuffled background sets to compare the area of each region with, reporting per-area enrichment; needs --chrom-sizes
+    #[arg(
+        long = "background",
+        value_name = "N",
+        requires = "chrom_sizes",
+        conflicts_with_all = ["bedpe", "gene_summary", "compare_bed"]
+    )]
+    background: Option<usize>,
+
+    /// BED file of intervals (e.g. assembly gaps, blacklist) that background regions must avoid
+    #[arg(long = "exclude", value_name = "BED", requires = "background")]
+    exclude: Option<PathBuf>,
+
+    /// Output file, or - for standard output; repeat as FORMAT:FILE (e.g. parquet:out.parquet) to also write the associations in other formats in the same pass
+    #[arg(short = 'o', long = "output", value_name = "FILE", default_value = "-")]
+    outputs: Vec<String>,
+
+    /// Main output, the first --output
+    #[arg(skip)]
     output: PathBuf,
 
-    /// Report level: exon, transcript, or gene
+    /// Further --output targets and their format
+    #[arg(skip)]
+    extra_outputs: Vec<(OutputFormat, PathBuf)>,
+
+    /// Resolved options of the run, as configuration file values
+    #[arg(skip)]
+    options: IndexMap<String, toml::Value>,
+
+    /// Report level: exon, transcript, gene, or all (every raw candidate)
     #[arg(short = 'r', long = "report", default_value = "exon")]
     report: String,
 
+    /// Report every raw candidate without rule-based selection or filtering (same as -r all)
+    #[arg(
+        long = "report-all",
+        conflicts_with_all = [
+            "report",
+            "nearest_k",
+            "nearest_per_strand",
+            "flanking",
+            "max_hits",
+            "reciprocal_overlap",
+            "exclusive"
+        ]
+    )]
+    report_all: bool,
+
     /// Maximum distance in kb to report associations
     #[arg(short = 'q', long = "distance", default_value = "10")]
     distance: i64,
 
+    /// Maximum distance in kb to report upstream associations (defaults to --distance)
+    #[arg(long = "upstream-distance")]
+    upstream_distance: Option<i64>,
+
+    /// Maximum distance in kb to report downstream associations (defaults to --distance)
+    #[arg(long = "downstream-distance")]
+    downstream_distance: Option<i64>,
+
+    /// BED column (4-12) with a per-region maximum distance in kb overriding --distance
+    #[arg(long = "distance-column", value_name = "COLUMN")]
+    distance_column: Option<usize>,
+
+    /// BED column (4-12) with a numeric region weight (e.g. peak score): the regions of a gene are ranked by it, heaviest first, and genes by its sum in --gene-summary and --gene-table; breaks distance ties of --sort-output gene-distance
+    #[arg(long = "weight-column", value_name = "COLUMN")]
+    weight_column: Option<usize>,
+
     /// TSS region distance in bp
     #[arg(short = 't', long = "tss", default_value = "200")]
     tss: i64,
@@ -195,7 +542,12 @@ struct Args {
     #[arg(short = 'w', long = "perc_region", default_value = "50")]
     perc_region: f64,
 
-    /// Priority rules (comma-separated)
+    /// Require a minimum reciprocal overlap fraction (0-1] between region and feature,
+    /// replacing the percentage thresholds
+    #[arg(long = "reciprocal-overlap")]
+    reciprocal_overlap: Option<f64>,
+
+    /// Priority rules (comma-separated; unlisted areas follow in default order)
     #[arg(
         short = 'R',
         long = "rules",
@@ -203,6 +555,82 @@ struct Args {
     )]
     rules: String,
 
+    /// Only report the areas listed in --rules instead of appending the others in default order
+    #[arg(long = "exclusive")]
+    exclusive: bool,
+
+    /// TOML file of per-biotype rules and distances (sections named after the GTF gene_type/gene_biotype)
+    #[arg(long = "biotype-rules", conflicts_with_all = ["features", "promoters_only"])]
+    biotype_rules: Option<PathBuf>,
+
+    /// Custom upstream zones replacing TSS/PROMOTER (e.g. "TSS:0-200,PROXIMAL:200-2000")
+    #[arg(long = "zones")]
+    zones: Option<String>,
+
+    /// Split the promoter into PROMOTER_PROXIMAL (first N bp) and PROMOTER_DISTAL tiers
+    #[arg(long = "proximal-promoter", conflicts_with = "zones")]
+    proximal_promoter: Option<i64>,
+
+    /// Tie-break among candidates tied after the rules: none, distance (|distance| then |TSS distance|) or tss
+    #[arg(long = "tie-break", default_value = "none")]
+    tie_break: String,
+
+    /// Point distances are measured from: midpoint, edge (nearest region edge), or summit (narrowPeak)
+    #[arg(long = "distance-anchor", default_value = "midpoint")]
+    distance_anchor: String,
+
+    /// Measure Distance and TSSDistance from the gene's canonical TSS (5'-most start) instead of the nearest exon
+    #[arg(long = "gene-tss-distance", conflicts_with = "promoters_only")]
+    gene_tss_distance: bool,
+
+    /// Association model: rgmatch, great (basal-plus-extension domains) or cis (genes with a TSS within --cis-window)
+    #[arg(long = "model", default_value = "rgmatch")]
+    model: String,
+
+    /// GREAT basal domain length upstream of the TSS in bp
+    #[arg(long = "basal-upstream", default_value = "5000")]
+    basal_upstream: i64,
+
+    /// GREAT basal domain length downstream of the TSS in bp
+    #[arg(long = "basal-downstream", default_value = "1000")]
+    basal_downstream: i64,
+
+    /// GREAT maximum extension in kb
+    #[arg(long = "max-extension", default_value = "1000")]
+    max_extension: i64,
+
+    /// Cis model window around each region in kb
+    #[arg(long = "cis-window", default_value = "1000")]
+    cis_window: i64,
+
+    /// Only match regions against TSS/PROMOTER windows of each transcript (fast promoter-capture mode)
+    #[arg(long = "promoters-only", conflicts_with_all = ["zones", "proximal_promoter"])]
+    promoters_only: bool,
+
+    /// Only match regions against gene boundaries (OVERLAP_GENE, UPSTREAM or DOWNSTREAM)
+    #[arg(long = "gene-extent", conflicts_with_all = ["features", "promoters_only", "utr", "cds"])]
+    gene_extent: bool,
+
+    /// Chromosome sizes file (chrom, length), e.g. UCSC chrom.sizes or a FASTA index
+    #[arg(long = "chrom-sizes")]
+    chrom_sizes: Option<PathBuf>,
+
+    /// Comma-separated circular contigs (e.g. chrM) whose genes wrap around the origin; needs --chrom-sizes
+    #[arg(long = "circular", value_delimiter = ',', requires = "chrom_sizes")]
+    circular: Vec<String>,
+
+    /// Transcripts used per gene: all, longest, most-exons, or collapse (meta-gene)
+    #[arg(long = "transcript-selection", default_value = "all")]
+    transcript_selection: String,
+
+    /// Collapse transcripts whose TSSs lie within N bp into their longest transcript
+    #[arg(long = "tss-cluster", value_name = "N")]
+    tss_cluster: Option<i64>,
+
+    /// Prefer TSS/PROMOTER/1st_EXON calls from transcripts with a GENCODE support level up to N (1-5)
+    #[arg(long = "tss-max-tsl", value_name = "N")]
+    tss_max_tsl: Option<u8>,
+
     /// GTF tag for gene ID
     #[arg(short = 'G', long = "gene", default_value = "gene_id")]
     gene_tag: String,
@@ -211,201 +639,2990 @@ struct Args {
     #[arg(short = 'T', long = "transcript", default_value = "transcript_id")]
     transcript_tag: String,
 
-    /// Number of worker threads (0 = auto-detect, 1 = sequential)
+    /// Number of worker threads (0 = auto-detect, moving threads between matching and formatting with the backlog; 1 = sequential)
     #[arg(long = "threads", short = 'j', default_value = "8")]
     threads: usize,
 
-    /// Batch size for streaming BED regions
-    #[arg(long = "batch-size", default_value = "5000")]
-    batch_size: usize,
-}
+    /// Threads of --threads formatting output lines ahead of the writer, the others matching regions
+    #[arg(long = "io-threads", value_name = "N")]
+    io_threads: Option<usize>,
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+    /// Regions per batch streamed to the threads (default: adapted to the associations per region and the writer backlog)
+    #[arg(long = "batch-size", value_name = "N")]
+    batch_size: Option<usize>,
 
-    // Validate inputs
-    if !args.gtf.exists() {
-        bail!("GTF file not found: {}", args.gtf.display());
-    }
-    if !args.bed.exists() {
-        bail!("BED file not found: {}", args.bed.display());
-    }
+    /// Memory for results waiting to be written in order (e.g. 512M, 2G); beyond it they are spilled to temporary files
+    #[arg(long = "max-memory", value_name = "SIZE")]
+    max_memory: Option<String>,
 
-    // Parse report level
-    let level: ReportLevel = args
-        .report
-        .parse()
-        .context("Report can only be one of the following: exon, transcript or gene")?;
+    /// Write the results of each batch as soon as it is matched: the rows of a batch stay together, but batches are not in input order
+    #[arg(long = "unordered", conflicts_with_all = ["max_memory", "checkpoint", "bgzip"])]
+    unordered: bool,
 
-    // Build configuration
-    let mut config = Config::new();
-    config.level = level;
+    /// Check the options and inputs and print the resolved configuration, without annotating
+    #[arg(long = "dry-run")]
+    dry_run: bool,
 
-    // Set distance (convert from kb to bp)
-    if args.distance >= 0 {
-        config.set_distance_kb(args.distance);
-    }
+    /// Record the progress of the run to FILE every --checkpoint-interval seconds so that --resume can continue it; removed once the run completes
+    #[arg(
+        long = "checkpoint",
+        value_name = "FILE",
+        conflicts_with_all = ["manifest", "merge_input", "summary", "html_report", "gene_sets", "gene_table", "matrix", "tss_profile", "split_by_area"]
+    )]
+    checkpoint: Option<PathBuf>,
 
-    // Set TSS distance
-    if args.tss >= 0 {
-        config.tss = args.tss as f64;
-    } else {
-        bail!("The TSS distance cannot be lower than 0 bps.");
-    }
+    /// Seconds between two records of the progress to the --checkpoint file
+    #[arg(
+        long = "checkpoint-interval",
+        default_value = "60",
+        requires = "checkpoint"
+    )]
+    checkpoint_interval: u64,
 
-    // Set TTS distance
-    if args.tts >= 0 {
-        config.tts = args.tts as f64;
-    } else {
-        bail!("The TTS distance cannot be lower than 0 bps.");
-    }
+    /// Continue an interrupted run from its --checkpoint file, if there is one
+    #[arg(long = "resume", requires = "checkpoint")]
+    resume: bool,
 
-    // Set promoter distance
-    if args.promoter >= 0 {
-        config.promoter = args.promoter as f64;
-    } else {
-        bail!("The promoter distance cannot be lower than 0 bps.");
-    }
+    /// Report the N closest genes per region (ranked by distance)
+    #[arg(long = "nearest-k")]
+    nearest_k: Option<usize>,
 
-    // Set percentage thresholds
-    if args.perc_area >= 0.0 && args.perc_area <= 100.0 {
-        config.perc_area = args.perc_area;
-    } else {
-        bail!("The percentage of area defined was wrong. It should range between 0 and 100.");
-    }
+    /// Report the closest gene on the plus strand and the closest on the minus strand per region
+    #[arg(long = "nearest-per-strand", conflicts_with = "nearest_k")]
+    nearest_per_strand: bool,
 
-    if args.perc_region >= 0.0 && args.perc_region <= 100.0 {
-        config.perc_region = args.perc_region;
-    } else {
-        bail!("The percentage of region defined was wrong. It should range between 0 and 100.");
-    }
+    /// Report the closest gene on each side of the region regardless of strand, plus any overlapping gene
+    #[arg(long = "flanking", conflicts_with_all = ["nearest_k", "nearest_per_strand"])]
+    flanking: bool,
 
-    // Parse rules
-    if !config.parse_rules(&args.rules) {
-        bail!("Rules not properly passed.");
-    }
+    /// Merge overlapping/bookended BED regions (optionally up to GAP bp apart) before matching; merged regions have no duplicates, so it cannot be combined with --duplicates
+    #[arg(
+        long = "merge-input",
+        value_name = "GAP",
+        num_args = 0..=1,
+        default_missing_value = "0",
+        conflicts_with = "duplicates"
+    )]
+    merge_input: Option<i64>,
 
-    // Set GTF tags
-    config.gene_id_tag = args.gene_tag.clone();
-    config.transcript_id_tag = args.transcript_tag.clone();
+    /// Compatibility mode: chipseeker (ChIPseeker annotation categories), bedtools-closest (bedtools closest -d layout) or python (original Python rgmatch output)
+    #[arg(long = "compat")]
+    compat: Option<String>,
 
-    // Parse GTF file
-    eprintln!("Parsing GTF file: {}", args.gtf.display());
-    let mut gtf_data = parse_gtf(&args.gtf, &config.gene_id_tag, &config.transcript_id_tag)?;
+    /// Output format: tsv, bed (input BED columns plus gene, area and distance), gff3 (track with the annotation as attributes), ucsc (BED9 track colored by area), parquet (typed columns) or sqlite (indexed database); the last two need their build feature
+    #[arg(long = "format", default_value = "tsv")]
+    format: String,
 
-    // Pre-sort genes for deterministic matching and performance
-    gtf_data
-        .genes_by_chrom
-        .values_mut()
-        .collect::<Vec<_>>()
-        .par_iter_mut()
-        .for_each(|genes| {
-            genes.sort_by(|a, b| a.start.cmp(&b.start).then(a.gene_id.cmp(&b.gene_id)));
-        });
+    /// Columns to write, in order, e.g. Region,Gene,Area:area,Distance (NAME:HEADER renames a column)
+    #[arg(long = "columns", value_name = "COLUMNS")]
+    columns: Option<String>,
+
+    /// Names of the BED columns after the region coordinates, in order, e.g. peak,signal (standard BED names for the rest)
+    #[arg(long = "bed-names", value_name = "NAMES")]
+    bed_names: Option<String>,
+
+    /// Leave out the header line, e.g. for tools reading the first line as data
+    #[arg(long = "no-header", conflicts_with = "comment_header")]
+    no_header: bool,
+
+    /// Start the header line with '#', so that tabix and bedtools skip it
+    #[arg(long = "comment-header")]
+    comment_header: bool,
+
+    /// Compress the output with bgzip and write a tabix index (<output>.tbi); needs a BED file sorted by chromosome and start
+    #[arg(long = "bgzip")]
+    bgzip: bool,
+
+    /// Exact duplicate regions: keep, replicate (annotate once, repeat the rows) or collapse (Count column)
+    #[arg(long = "duplicates", default_value = "keep")]
+    duplicates: String,
+
+    /// Decimal places of the percentage columns (PercRegion, PercArea, ...), or full for the shortest exact value
+    #[arg(long = "precision", default_value = "2")]
+    precision: String,
+
+    /// Order of the output rows: coord (genome coordinates, karyotype order), gene (grouped by gene), gene-distance (grouped by gene, closest first) or none (input order)
+    #[arg(long = "sort-output", default_value = "none")]
+    sort_output: String,
+
+    /// Add a GeneRank column with the rank of each row within its gene (1 = closest); needs --sort-output gene-distance
+    #[arg(long = "gene-rank")]
+    gene_rank: bool,
+
+    /// Write one row per region with its K best associations as Gene1, Area1, Distance1, Gene2, ... columns
+    #[arg(long = "wide", value_name = "K")]
+    wide: Option<usize>,
+
+    /// Write one row per gene with its associated regions instead of one row per association
+    #[arg(long = "gene-summary")]
+    gene_summary: bool,
+
+    /// Directory to also write the genes of each area to, as one gene list per area and a gene-area table
+    #[arg(
+        long = "gene-sets",
+        value_name = "DIR",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    gene_sets: Option<PathBuf>,
+
+    /// Directory to also write the associations of each area to, as one TSV file per area (e.g. TSS.tsv, INTRON.tsv)
+    #[arg(
+        long = "split-by-area",
+        value_name = "DIR",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    split_by_area: Option<PathBuf>,
+
+    /// Also write a per-gene table (region count, best area, minimum distance, summed region length and score) to FILE
+    #[arg(
+        long = "gene-table",
+        value_name = "FILE",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    gene_table: Option<PathBuf>,
+
+    /// Directory to also write a region by gene matrix to (see --matrix-format and --matrix-value)
+    #[arg(
+        long = "matrix",
+        value_name = "DIR",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    matrix: Option<PathBuf>,
+
+    /// Layout of the --matrix output: mtx (sparse MatrixMarket) or tsv (dense table)
+    #[arg(long = "matrix-format", default_value = "mtx", requires = "matrix")]
+    matrix_format: String,
+
+    /// Values of the --matrix output: overlap (PercRegion) or binary (1 per associated pair)
+    #[arg(long = "matrix-value", default_value = "overlap", requires = "matrix")]
+    matrix_value: String,
+
+    /// File to also write a profile of the regions and associations per TSS distance bin and area to
+    #[arg(
+        long = "tss-profile",
+        value_name = "FILE",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    tss_profile: Option<PathBuf>,
+
+    /// Bin size of the --tss-profile output in bp
+    #[arg(
+        long = "tss-profile-bin",
+        default_value = "100",
+        requires = "tss_profile"
+    )]
+    tss_profile_bin: i64,
+
+    /// Distance from the TSS covered by the --tss-profile output in bp, upstream and downstream
+    #[arg(
+        long = "tss-profile-window",
+        default_value = "5000",
+        requires = "tss_profile"
+    )]
+    tss_profile_window: i64,
+
+    /// Write run statistics (regions per area, level and chromosome, distance quartiles) to FILE, or to stderr without FILE
+    #[arg(
+        long = "summary",
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = "-",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    summary: Option<PathBuf>,
+
+    /// Write a self-contained HTML report (area pie chart, TSS distance histogram, run parameters)
+    #[arg(
+        long = "html-report",
+        value_name = "FILE",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    html_report: Option<PathBuf>,
+
+    /// Write a JSON sidecar of the run (command line, parameters, configuration, input checksums, version, timing)
+    #[arg(long = "metadata", value_name = "FILE")]
+    metadata: Option<PathBuf>,
+
+    /// Write performance metrics (timings, throughput, channel congestion, peak memory) to FILE
+    #[arg(
+        long = "metrics",
+        value_name = "FILE",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    metrics: Option<PathBuf>,
+
+    /// Format of the --metrics output: json or prometheus (textfile collector)
+    #[arg(long = "metrics-format", default_value = "json", requires = "metrics")]
+    metrics_format: String,
+
+    /// Time the stages of the run and log them, with its throughput, at the end
+    #[arg(
+        long = "perf",
+        conflicts_with_all = ["bedpe", "compare_bed", "background"]
+    )]
+    perf: bool,
+
+    /// Report at most N associations per region (highest priority, then closest)
+    #[arg(long = "max-hits")]
+    max_hits: Option<usize>,
+
+    /// With -r gene, apply the rules once more across genes to report one association per region
+    #[arg(long = "best-per-region")]
+    best_per_region: bool,
+
+    /// Emit an INTERGENIC row (Gene=NA) for regions without any association
+    #[arg(long = "report-unmatched")]
+    report_unmatched: bool,
+
+    /// Columns identifying the region: id (Region, e.g. chr1_100_200), split (Chrom, Start and End) or both
+    #[arg(long = "region-columns", default_value = "id")]
+    region_columns: String,
+
+    /// Add a RegionStrand column with the strand of the region from the BED strand column (NA if unstranded)
+    #[arg(long = "region-strand")]
+    region_strand: bool,
+
+    /// Add a SignedDistance column (negative upstream of the TSS, positive downstream)
+    #[arg(long = "signed-distance")]
+    signed_distance: bool,
+
+    /// Add a Confidence column (0-1) weighting %region, %area, rule priority and distance (optionally REGION,AREA,RANK,DISTANCE weights)
+    #[arg(long = "confidence", value_name = "WEIGHTS", num_args = 0..=1, default_missing_value = "0.3,0.2,0.3,0.2")]
+    confidence: Option<String>,
+
+    /// Add PercGene and PercTranscript columns with the percentage of the gene and transcript covered by the region
+    #[arg(long = "gene-coverage")]
+    gene_coverage: bool,
+
+    /// Add OverlapArea and OverlapGene columns with the overlap with the area and the gene in bp
+    #[arg(long = "overlap-bp")]
+    overlap_bp: bool,
+
+    /// Add AreaStart and AreaEnd columns with the coordinates of the matched exon, intron or window
+    #[arg(long = "area-coordinates")]
+    area_coordinates: bool,
+
+    /// Add GeneType and TranscriptType columns with the biotypes from the GTF (gene_type/gene_biotype, transcript_type/transcript_biotype)
+    #[arg(long = "biotypes")]
+    biotypes: bool,
+
+    /// Add an ExonCount column with the number of exons of the transcript (with --area-coordinates for the bounds of the matched exon or intron)
+    #[arg(long = "exon-count")]
+    exon_count: bool,
+
+    /// Add an AssociationID column with a stable hash of the region, gene, transcript, exon/intron and area, for upserts and run comparisons
+    #[arg(long = "association-id")]
+    association_id: bool,
+
+    /// With -r gene, write the TSS distances of all merged transcripts (parallel to Transcript) in TSSDistance
+    #[arg(long = "tss-distance-list")]
+    tss_distance_list: bool,
+
+    /// Add an Antisense column flagging regions that overlap a gene on the opposite strand
+    #[arg(long = "antisense")]
+    antisense: bool,
+
+    /// Only consider genes on the same strand as the region (BED strand column)
+    #[arg(long = "same-strand", conflicts_with = "opposite_strand")]
+    same_strand: bool,
+
+    /// Only consider genes on the opposite strand to the region (BED strand column)
+    #[arg(long = "opposite-strand")]
+    opposite_strand: bool,
+
+    /// Classify exonic overlaps of coding transcripts as 5_UTR/3_UTR
+    #[arg(long = "utr")]
+    utr: bool,
+
+    /// Classify exonic overlaps of coding transcripts within the CDS as CDS
+    #[arg(long = "cds")]
+    cds: bool,
+}
+
+impl Args {
+    /// Regions per batch read from the BED file, where batches are not adapted.
+    fn batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE)
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    match run() {
+        // The reader of piped output stopped early (e.g. `| head`)
+        Err(e)
+            if e.chain().any(|cause| {
+                cause
+                    .downcast_ref::<io::Error>()
+                    .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+            }) =>
+        {
+            Ok(ExitCode::SUCCESS)
+        }
+        result => result,
+    }
+}
+
+fn run() -> Result<ExitCode> {
+    let matches = Cli::command().get_matches_from(with_env_and_config_file(cli_args()?)?);
+    let mut cli =
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.format(&mut Cli::command()).exit());
+    if let Some((name, matches)) = matches.subcommand() {
+        let command = Cli::command();
+        let args = match &mut cli.command {
+            Command::Annotate(args) => Some(args.as_mut()),
+            Command::DiffGtf(args) => Some(&mut args.args),
+            _ => None,
+        };
+        if let Some(args) = args {
+            args.options = resolved_options(command.find_subcommand(name).unwrap(), matches);
+        }
+    }
+    init_logging(&cli)?;
+    for key in unknown_env_options() {
+        warn!(
+            "Ignored environment variable {}: there is no such option",
+            key
+        );
+    }
+    warnings::set_strict(cli.strict);
+    warnings::set_max_warnings(cli.max_warnings);
+    let matched_regions = match cli.command {
+        Command::Annotate(args) => run_annotate(*args, cli.seed)?,
+        Command::Index(args) => run_index(&args).map(|()| None)?,
+        Command::Stats(args) => run_stats(&args).map(|()| None)?,
+        Command::Validate(args) => run_validate(&args).map(|()| None)?,
+        Command::SubsetGtf(args) => run_subset_gtf(&args).map(|()| None)?,
+        Command::Simulate(args) => run_simulate(&args, cli.seed).map(|()| None)?,
+        Command::DiffGtf(args) => run_diff_gtf(*args, cli.seed)?,
+        Command::Diff(args) => {
+            if run_diff_outputs(&args)? {
+                return Ok(ExitCode::from(EXIT_DIFFERENCES));
+            }
+            None
+        }
+        Command::Config(args) => {
+            if !args.print_default {
+                bail!("Nothing to do: use rgmatch config --print-default > rgmatch.toml to scaffold a configuration file.");
+            }
+            print!("{}", default_config_file());
+            None
+        }
+    };
+    Ok(exit_code(matched_regions))
+}
+
+/// Exit code of a run that completed, but skipped input lines.
+const EXIT_WARNINGS: u8 = 3;
+
+/// Exit code of an annotate run in which no region matched a gene.
+const EXIT_NO_MATCHES: u8 = 4;
+
+/// Exit code of a diff run that found differences.
+const EXIT_DIFFERENCES: u8 = 5;
+
+/// Summarize the warnings of a completed run and tell its outcome by the
+/// exit code, given the regions matched by an annotate run.
+fn exit_code(matched_regions: Option<usize>) -> ExitCode {
+    let skipped = warnings::summary();
+    if !skipped.is_empty() {
+        let list: Vec<String> = skipped
+            .iter()
+            .map(|(input, count)| format!("{} {} lines", count, input))
+            .collect();
+        let total = warnings::total();
+        warn!(
+            "Completed with {} warning{}: skipped {}",
+            total,
+            if total == 1 { "" } else { "s" },
+            list.join(", ")
+        );
+    }
+    for (kind, count, unlogged) in warnings::unlogged() {
+        warn!("{} {} ({} not logged)", count, kind, unlogged);
+    }
+    if matched_regions == Some(0) {
+        warn!("No region matched a gene");
+        ExitCode::from(EXIT_NO_MATCHES)
+    } else if !skipped.is_empty() {
+        ExitCode::from(EXIT_WARNINGS)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Prefix of the environment variables setting options.
+const ENV_PREFIX: &str = "RGMATCH_";
+
+/// Add the options set by `RGMATCH_*` environment variables, and by the
+/// --config file of an annotate (or diff-gtf) invocation, to its arguments.
+/// The global options are read from the environment for every subcommand.
+/// The command line takes precedence over the environment, which takes
+/// precedence over the file: an option is only added if it was not given
+/// before, nor conflicts with an option given before.
+fn with_env_and_config_file(argv: Vec<OsString>) -> Result<Vec<OsString>> {
+    // Only the options of the command line are needed here, so missing
+    // required options (e.g. set by the file) are not an error yet
+    let Ok(matches) = Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&argv)
+    else {
+        return Ok(argv);
+    };
+    let Some((name, cli)) = matches.subcommand() else {
+        return Ok(argv);
+    };
+    let command = Cli::command();
+    let vars = env_options();
+
+    // The global options are also in the matches of the subcommand
+    let mut global_set = command_line_args(&command, cli);
+    let global_args = env_args(&command, &vars, &mut global_set)?;
+    if !matches!(name, "annotate" | "diff-gtf") {
+        let mut args = argv[..2].to_vec();
+        args.extend(global_args);
+        args.extend(argv[2..].iter().cloned());
+        return Ok(args);
+    }
+
+    let annotate = command.find_subcommand(name).unwrap();
+    let mut set = command_line_args(annotate, cli);
+    let env_args = env_args(annotate, &vars, &mut set)?;
+    let config_path = cli.get_one::<PathBuf>("config").cloned().or_else(|| {
+        vars.iter()
+            .find(|(option, _, value)| option == "config" && !value.is_empty())
+            .map(|(_, _, value)| PathBuf::from(value))
+    });
+
+    let mut file_args = Vec::new();
+    if let Some(path) = &config_path {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let table: toml::Table = contents
+            .parse()
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        for (key, value) in &table {
+            let Some(arg) =
+                env_option(annotate, &key.replace('_', "-")).filter(|arg| is_config_option(arg))
+            else {
+                bail!("Unknown option in config file {}: {}", path.display(), key);
+            };
+            // Defaults are left unset, as they would otherwise trigger the
+            // requirements of the option (e.g. checkpoint-interval)
+            if is_set(annotate, arg, &set) || is_default_value(arg, value) {
+                continue;
+            }
+            let name = arg.get_long().unwrap();
+            let values = config_option_args(arg, name, value).with_context(|| {
+                format!("Invalid value of {} in config file {}", key, path.display())
+            })?;
+            file_args.extend(values.into_iter().map(OsString::from));
+        }
+    }
+
+    let mut args = argv[..2].to_vec();
+    args.extend(file_args);
+    args.extend(env_args);
+    args.extend(global_args);
+    args.extend(argv[2..].iter().cloned());
+    Ok(args)
+}
+
+/// The `RGMATCH_*` environment variables with a text value, as (option
+/// name, variable, value) sorted by variable, so that the first of two
+/// conflicting variables is always kept.
+fn env_options() -> Vec<(String, String, String)> {
+    let mut vars: Vec<(String, String, String)> = std::env::vars_os()
+        .filter_map(|(key, value)| {
+            let key = key.into_string().ok()?;
+            let option = key
+                .strip_prefix(ENV_PREFIX)?
+                .to_lowercase()
+                .replace('_', "-");
+            Some((option, key, value.into_string().ok()?))
+        })
+        .collect();
+    vars.sort_by(|a, b| a.1.cmp(&b.1));
+    vars
+}
+
+/// The option of `command` named by an environment variable or a config
+/// file key, with underscores read as dashes on both sides.
+fn env_option<'a>(command: &'a clap::Command, name: &str) -> Option<&'a Arg> {
+    command.get_arguments().find(|arg| {
+        arg.get_long()
+            .is_some_and(|long| long.replace('_', "-") == name)
+            && !matches!(arg.get_id().as_str(), "help" | "version")
+    })
+}
+
+/// The `RGMATCH_*` environment variables that name no global, annotate or
+/// diff-gtf option, which are ignored.
+fn unknown_env_options() -> Vec<String> {
+    let command = Cli::command();
+    let commands = [
+        &command,
+        command.find_subcommand("annotate").unwrap(),
+        command.find_subcommand("diff-gtf").unwrap(),
+    ];
+    env_options()
+        .into_iter()
+        .filter(|(name, _, _)| commands.iter().all(|c| env_option(c, name).is_none()))
+        .map(|(_, key, _)| key)
+        .collect()
+}
+
+/// The options of `command` given on the command line.
+fn command_line_args<'a>(command: &'a clap::Command, matches: &ArgMatches) -> Vec<&'a Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect()
+}
+
+/// Whether `arg`, or an option it conflicts with, is in `set`. Conflicts
+/// are declared on either side.
+fn is_set(command: &clap::Command, arg: &Arg, set: &[&Arg]) -> bool {
+    let conflicts = |a: &Arg, b: &Arg| {
+        command
+            .get_arg_conflicts_with(a)
+            .iter()
+            .any(|c| c.get_id() == b.get_id())
+    };
+    set.iter().any(|other| {
+        other.get_id() == arg.get_id() || conflicts(arg, other) || conflicts(other, arg)
+    })
+}
+
+/// Arguments setting the options of `command` from the environment, unless
+/// they are in `set`, to which they are added. Empty variables are ignored.
+fn env_args<'a>(
+    command: &'a clap::Command,
+    vars: &[(String, String, String)],
+    set: &mut Vec<&'a Arg>,
+) -> Result<Vec<OsString>> {
+    let mut args = Vec::new();
+    for (name, key, value) in vars {
+        let Some(arg) = env_option(command, name) else {
+            continue;
+        };
+        if value.is_empty() || is_set(command, arg, set) {
+            continue;
+        }
+        // Flags also take 1 and 0, as usual for environment variables
+        let value = match (arg.get_action(), value.as_str()) {
+            (ArgAction::SetTrue, "1") => toml::Value::Boolean(true),
+            (ArgAction::SetTrue, "0") => toml::Value::Boolean(false),
+            _ => option_value(value),
+        };
+        let values = config_option_args(arg, arg.get_long().unwrap(), &value)
+            .with_context(|| format!("Invalid value of environment variable {}", key))?;
+        args.extend(values.into_iter().map(OsString::from));
+        set.push(arg);
+    }
+    Ok(args)
+}
+
+/// Value of an option given as text, as in a configuration file: `true`
+/// and `false` set flags, anything else is a string.
+fn option_value(text: &str) -> toml::Value {
+    match text {
+        "true" => toml::Value::Boolean(true),
+        "false" => toml::Value::Boolean(false),
+        _ => toml::Value::String(text.to_string()),
+    }
+}
+
+/// Whether an annotate option can be set in a configuration file.
+fn is_config_option(arg: &Arg) -> bool {
+    !matches!(arg.get_id().as_str(), "help" | "version" | "config")
+}
+
+/// Command-line arguments setting an option to a configuration file value.
+///
+/// Flags take booleans, options taking several values arrays, and other
+/// options scalars (arrays are joined with commas).
+fn config_option_args(arg: &Arg, name: &str, value: &toml::Value) -> Result<Vec<String>> {
+    let flag = format!("--{}", name);
+    let optional_value = arg.get_num_args().is_some_and(|n| n.min_values() == 0);
+    let args = match (arg.get_action(), value) {
+        (ArgAction::SetTrue, toml::Value::Boolean(set)) => {
+            if *set {
+                vec![flag]
+            } else {
+                Vec::new()
+            }
+        }
+        (ArgAction::SetTrue, _) => bail!("expected true or false"),
+        (ArgAction::Count, toml::Value::Boolean(set)) => {
+            if *set {
+                vec![flag]
+            } else {
+                Vec::new()
+            }
+        }
+        (ArgAction::Count, value) => match toml_scalar(value)?.parse::<usize>() {
+            Ok(count) => vec![flag; count],
+            Err(_) => bail!("expected true, false or a count"),
+        },
+        (ArgAction::Append, toml::Value::Array(items)) => items
+            .iter()
+            .map(|item| Ok(format!("{}={}", flag, toml_scalar(item)?)))
+            .collect::<Result<_>>()?,
+        (_, toml::Value::Boolean(true)) if optional_value => vec![flag],
+        (_, toml::Value::Boolean(false)) if optional_value => Vec::new(),
+        (_, toml::Value::Array(items)) => {
+            let items: Vec<String> = items.iter().map(toml_scalar).collect::<Result<_>>()?;
+            vec![format!("{}={}", flag, items.join(","))]
+        }
+        (_, value) => vec![format!("{}={}", flag, toml_scalar(value)?)],
+    };
+    Ok(args)
+}
+
+/// Text of a TOML string, number or boolean.
+fn toml_scalar(value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(text) => Ok(text.clone()),
+        toml::Value::Integer(number) => Ok(number.to_string()),
+        toml::Value::Float(number) => Ok(number.to_string()),
+        toml::Value::Boolean(flag) => Ok(flag.to_string()),
+        _ => bail!("expected a string, a number or a boolean"),
+    }
+}
+
+/// Whether a config file value is the default of its option, as written by
+/// `rgmatch config --print-default`.
+fn is_default_value(arg: &Arg, value: &toml::Value) -> bool {
+    let defaults = arg.get_default_values();
+    match value {
+        toml::Value::Array(items) => {
+            items.len() == defaults.len()
+                && items
+                    .iter()
+                    .zip(defaults)
+                    .all(|(item, default)| toml_scalar(item).is_ok_and(|item| *default == *item))
+        }
+        _ => match defaults {
+            [default] => toml_scalar(value).is_ok_and(|value| *default == *value),
+            _ => false,
+        },
+    }
+}
+
+/// Configuration file listing every annotate option with its help and
+/// default value, commented out.
+fn default_config_file() -> String {
+    let command = Cli::command();
+    let annotate = command.find_subcommand("annotate").unwrap();
+    let mut text = String::from(
+        "# rgmatch configuration, read with rgmatch --config FILE\n\
+         # Options are named as on the command line, which takes precedence, as do\n\
+         # RGMATCH_<OPTION> environment variables.\n",
+    );
+    for arg in annotate.get_arguments().filter(|arg| is_config_option(arg)) {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        text.push('\n');
+        if let Some(help) = arg.get_help() {
+            text.push_str(&format!("# {}\n", help.to_string().replace('\n', "\n# ")));
+        }
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| toml_literal(&value.to_string_lossy()))
+            .collect();
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => "false".to_string(),
+            ArgAction::Append => format!("[{}]", defaults.join(", ")),
+            _ => defaults.first().cloned().unwrap_or_default(),
+        };
+        text.push_str(&format!("# {} = {}\n", long, value).replace(" \n", "\n"));
+    }
+    text
+}
+
+/// TOML literal of a default value: numbers as is, anything else quoted.
+fn toml_literal(value: &str) -> String {
+    if value.parse::<i64>().is_ok() || value.parse::<f64>().is_ok_and(f64::is_finite) {
+        value.to_string()
+    } else {
+        toml::Value::String(value.to_string()).to_string()
+    }
+}
+
+/// TOML value of an option value: integers and numbers as such, anything
+/// else a string.
+fn toml_value(value: &str) -> toml::Value {
+    if let Ok(number) = value.parse::<i64>() {
+        toml::Value::Integer(number)
+    } else if let Some(number) = value.parse::<f64>().ok().filter(|n| n.is_finite()) {
+        toml::Value::Float(number)
+    } else {
+        toml::Value::String(value.to_string())
+    }
+}
+
+/// The options of `command` set by `matches`, defaults included, named and
+/// valued as in a configuration file, so that `--config` reads them back.
+/// --dry-run is left out.
+fn resolved_options(
+    command: &clap::Command,
+    matches: &ArgMatches,
+) -> IndexMap<String, toml::Value> {
+    let mut options = IndexMap::new();
+    for arg in command.get_arguments().filter(|arg| is_config_option(arg)) {
+        let (Some(long), id) = (arg.get_long(), arg.get_id().as_str()) else {
+            continue;
+        };
+        let value = match arg.get_action() {
+            _ if id == "dry_run" => continue,
+            ArgAction::SetTrue => toml::Value::Boolean(matches.get_flag(id)),
+            ArgAction::Count => toml::Value::Integer(matches.get_count(id).into()),
+            action => {
+                let Some(raw) = matches.get_raw(id) else {
+                    continue;
+                };
+                let mut values: Vec<toml::Value> = raw
+                    .map(|value| toml_value(&value.to_string_lossy()))
+                    .collect();
+                match values.len() {
+                    0 => continue,
+                    1 if !matches!(action, ArgAction::Append) => values.remove(0),
+                    _ => toml::Value::Array(values),
+                }
+            }
+        };
+        options.insert(long.to_string(), value);
+    }
+    options
+}
+
+/// Parse an annotation and write it as an index.
+fn run_index(args: &IndexArgs) -> Result<()> {
+    let gtf_data = args.annotation.parse_annotation()?;
+    info!("Writing index to: {}", args.output.display());
+    let file = File::create(&args.output).context("Failed to create index file")?;
+    let mut writer = BufWriter::new(file);
+    let (gene_tag, transcript_tag) = (&args.annotation.gene_tag, &args.annotation.transcript_tag);
+    if args.output.to_string_lossy().ends_with(".gz") {
+        let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+        write_index(&gtf_data, gene_tag, transcript_tag, &mut encoder)?;
+        encoder.finish()?;
+    } else {
+        write_index(&gtf_data, gene_tag, transcript_tag, &mut writer)?;
+    }
+    writer.flush()?;
+    info!("Done!");
+    Ok(())
+}
+
+/// Write the feature counts of an annotation per chromosome and biotype.
+fn run_stats(args: &StatsArgs) -> Result<()> {
+    let gtf_data = args.annotation.parse_annotation()?;
+    let mut writer = BufWriter::new(open_output(&args.output)?);
+    AnnotationStats::from_gtf(&gtf_data).write(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read an annotation and a BED file and check that they share chromosomes.
+fn run_validate(args: &ValidateArgs) -> Result<()> {
+    let gtf_data = args.annotation.parse_annotation()?;
+    if !args.bed.exists() {
+        bail!("BED file not found: {}", args.bed.display());
+    }
+    let bed_data = parse_bed(&args.bed).context("Failed to parse BED file")?;
+    let regions = bed_data
+        .regions_by_chrom
+        .iter()
+        .map(|(chrom, regions)| (chrom.clone(), regions.len()))
+        .collect();
+    let mut out = io::stdout().lock();
+    check_chromosomes(&mut out, &gtf_data, &regions, &args.bed)?;
+    writeln!(out, "OK")?;
+    Ok(())
+}
+
+/// Copy the lines of a GTF for the genes near the regions of a BED file.
+fn run_subset_gtf(args: &SubsetGtfArgs) -> Result<()> {
+    let gtf = &args.annotation.gtf;
+    if gtf.exists() && is_index(gtf)? {
+        bail!(
+            "{} is an rgmatch index; subset-gtf needs the GTF it was built from",
+            gtf.display()
+        );
+    }
+    let gtf_data = args.annotation.parse_annotation()?;
+    if !args.bed.exists() {
+        bail!("BED file not found: {}", args.bed.display());
+    }
+    let bed_data = parse_bed(&args.bed).context("Failed to parse BED file")?;
+    let distance = i64::try_from(args.distance.saturating_mul(1000)).unwrap_or(i64::MAX);
+    let genes = gtf_data.genes_near(&bed_data.regions_by_chrom, distance);
+    info!(
+        "Keeping {} genes within {} kb of the regions",
+        genes.len(),
+        args.distance
+    );
+
+    let reader = create_buffered_reader(File::open(gtf).context("Failed to open GTF file")?, gtf);
+    let mut writer = BufWriter::new(open_output(&args.output)?);
+    let gene_tag = &args.annotation.gene_tag;
+    let copied = if args.output.to_string_lossy().ends_with(".gz") {
+        let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+        let copied = filter_gtf(reader, &mut encoder, gene_tag, &genes)?;
+        encoder.finish()?;
+        copied
+    } else {
+        filter_gtf(reader, &mut writer, gene_tag, &genes)?
+    };
+    writer.flush()?;
+    info!("Wrote {} GTF lines", copied);
+    Ok(())
+}
+
+/// Compare two outputs, returning whether they differ.
+fn run_diff_outputs(args: &DiffArgs) -> Result<bool> {
+    if args.tolerance.is_nan() || args.tolerance < 0.0 {
+        bail!("The tolerance must be a non-negative number.");
+    }
+    let old = OutputTable::read(&args.old)?;
+    let new = OutputTable::read(&args.new)?;
+    for (table, other, path) in [(&old, &new, &args.old), (&new, &old, &args.new)] {
+        for column in table.columns.iter().filter(|c| !other.columns.contains(c)) {
+            warn!(
+                "Column {} is only in {}; not compared",
+                column,
+                path.display()
+            );
+        }
+    }
+    let diff = diff_outputs(&old, &new, args.tolerance)?;
+    let mut writer = BufWriter::new(open_output(&args.output)?);
+    diff.write(&mut writer)?;
+    writer.flush()?;
+
+    let (added, removed, changed) = diff.counts();
+    info!(
+        "{} rows added, {} removed, {} changed, {} unchanged",
+        added, removed, changed, diff.unchanged
+    );
+    Ok(!diff.changes.is_empty())
+}
+
+/// Write a synthetic annotation and regions, generated from `seed`.
+fn run_simulate(args: &SimulateArgs, seed: u64) -> Result<()> {
+    let simulation = Simulation {
+        chromosomes: args.chromosomes,
+        chrom_length: args.chrom_length,
+        gene_density: args.gene_density,
+        max_transcripts: args.max_transcripts,
+        min_exons: args.min_exons,
+        max_exons: args.max_exons,
+        regions: args.regions,
+        min_width: args.min_width,
+        max_width: args.max_width,
+    };
+    simulation.check()?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let genes = simulation.genes(&mut rng);
+    let regions = simulation.regions(&mut rng);
+    let gene_count: usize = genes.iter().map(|(_, genes)| genes.len()).sum();
+
+    info!("Writing {} genes to: {}", gene_count, args.gtf.display());
+    write_compressed(&args.gtf, |writer| simulate::write_gtf(writer, &genes))?;
+    info!(
+        "Writing {} regions to: {}",
+        regions.len(),
+        args.bed.display()
+    );
+    write_compressed(&args.bed, |writer| simulate::write_bed(writer, &regions))?;
+    Ok(())
+}
+
+/// Create a file and write it with `write`, gzip-compressed if its name
+/// ends with .gz.
+fn write_compressed(path: &Path, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    if path.to_string_lossy().ends_with(".gz") {
+        let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+        write(&mut encoder)?;
+        encoder.finish()?;
+    } else {
+        write(&mut writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Report the genes and regions of a run, warn about the regions on
+/// chromosomes missing from the annotation, and fail when there are no
+/// regions or none of their chromosomes is in the annotation.
+fn check_chromosomes<W: Write>(
+    out: &mut W,
+    gtf_data: &GtfData,
+    regions_by_chrom: &AHashMap<String, usize>,
+    bed: &Path,
+) -> Result<()> {
+    let genes: usize = gtf_data.genes_by_chrom.values().map(Vec::len).sum();
+    let regions: usize = regions_by_chrom.values().sum();
+    writeln!(
+        out,
+        "Annotation: {} genes on {} chromosomes",
+        genes,
+        gtf_data.genes_by_chrom.len()
+    )?;
+    writeln!(
+        out,
+        "Regions: {} on {} chromosomes",
+        regions,
+        regions_by_chrom.len()
+    )?;
+    if regions == 0 {
+        bail!("No regions found in {}", bed.display());
+    }
+
+    let mut missing: Vec<(&String, usize)> = regions_by_chrom
+        .iter()
+        .filter(|(chrom, _)| !gtf_data.genes_by_chrom.contains_key(*chrom))
+        .map(|(chrom, count)| (chrom, *count))
+        .collect();
+    if missing.len() == regions_by_chrom.len() {
+        bail!("No chromosome of the regions is in the annotation; check the chromosome names (e.g. chr1 and 1)");
+    }
+    if !missing.is_empty() {
+        missing.sort_by(|(a, _), (b, _)| compare_chroms(a, b));
+        let list: Vec<String> = missing
+            .iter()
+            .map(|(chrom, count)| format!("{} ({})", chrom, count))
+            .collect();
+        warn!(
+            "{} regions on chromosomes missing from the annotation: {}",
+            missing.iter().map(|(_, count)| count).sum::<usize>(),
+            list.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Check the inputs of a run that are only read once annotating, and print
+/// the resolved options as a configuration file.
+fn dry_run(args: &Args, gtf_data: &GtfData, config: &Config, num_threads: usize) -> Result<()> {
+    let mut regions = AHashMap::new();
+    if args.bedpe {
+        let bedpe_data = parse_bedpe(&args.bed).context("Failed to parse BEDPE file")?;
+        for anchor in bedpe_data
+            .loops
+            .iter()
+            .flat_map(|lp| [&lp.anchor1, &lp.anchor2])
+        {
+            *regions.entry(anchor.chrom.clone()).or_insert(0) += 1;
+        }
+    } else {
+        let bed_data = parse_bed(&args.bed).context("Failed to parse BED file")?;
+        for (chrom, chrom_regions) in &bed_data.regions_by_chrom {
+            regions.insert(chrom.clone(), chrom_regions.len());
+        }
+    }
+    let mut out = io::stdout().lock();
+    check_chromosomes(&mut out, gtf_data, &regions, &args.bed)?;
+    if let Some(compare_bed) = &args.compare_bed {
+        let bed_data = parse_bed(compare_bed).context("Failed to parse BED file")?;
+        let regions = bed_data
+            .regions_by_chrom
+            .iter()
+            .map(|(chrom, regions)| (chrom.clone(), regions.len()))
+            .collect();
+        check_chromosomes(&mut out, gtf_data, &regions, compare_bed)?;
+    }
+    if let Some(diff_gtf) = &args.diff_gtf {
+        parse_annotation(diff_gtf, &config.gene_id_tag, &config.transcript_id_tag)?;
+    }
+
+    match ThreadSplit::new(config, num_threads) {
+        _ if num_threads == 1 => writeln!(out, "Threads: 1 (sequential)")?,
+        ThreadSplit::Matching => writeln!(out, "Threads: {}", num_threads)?,
+        ThreadSplit::Fixed { matching, io } => writeln!(
+            out,
+            "Threads: {} matching, {} formatting output",
+            matching, io
+        )?,
+        ThreadSplit::Adaptive => writeln!(
+            out,
+            "Threads: {} moving between matching and formatting output",
+            num_threads
+        )?,
+    }
+    writeln!(out, "Configuration (for --config):")?;
+    write!(out, "{}", toml::to_string(&args.options)?)?;
+    Ok(())
+}
+
+/// Annotate the regions against the old and new annotations of a diff-gtf
+/// run and report the genes that changed.
+fn run_diff_gtf(args: DiffGtfArgs, seed: u64) -> Result<Option<usize>> {
+    let DiffGtfArgs { new_gtf, mut args } = args;
+    let conflicting = [
+        ("--features", args.features.is_some()),
+        ("--links", args.links.is_some()),
+        ("--manifest", args.manifest.is_some()),
+        ("--bedpe", args.bedpe),
+        ("--gene-summary", args.gene_summary),
+        ("--compare-bed", args.compare_bed.is_some()),
+        ("--background", args.background.is_some()),
+        ("--gene-sets", args.gene_sets.is_some()),
+        ("--split-by-area", args.split_by_area.is_some()),
+        ("--gene-table", args.gene_table.is_some()),
+        ("--matrix", args.matrix.is_some()),
+        ("--tss-profile", args.tss_profile.is_some()),
+        ("--summary", args.summary.is_some()),
+        ("--html-report", args.html_report.is_some()),
+        ("--metrics", args.metrics.is_some()),
+        ("--perf", args.perf),
+    ];
+    if let Some((option, _)) = conflicting.iter().find(|(_, set)| *set) {
+        bail!("{} cannot be used with diff-gtf", option);
+    }
+    args.diff_gtf = Some(new_gtf);
+    run_annotate(args, seed)
+}
+
+/// Run an annotation, returning the number of regions matched by the
+/// per-association runs that count them. `seed` seeds the background sets.
+fn run_annotate(mut args: Args, seed: u64) -> Result<Option<usize>> {
+    let mut metadata = RunMetadata::start();
+    let start = Instant::now();
+    let mut config = configure(&mut args)?;
+    config.seed = seed;
+
+    // Parse the annotation: GTF file or BED features
+    let mut gtf_data = match (&args.gtf, &args.features) {
+        (_, Some(features)) => {
+            info!("Parsing feature BED file: {}", features.display());
+            config.enable_features();
+            parse_feature_bed(features)?
+        }
+        (Some(gtf), None) => {
+            info!("Parsing GTF file: {}", gtf.display());
+            parse_annotation(gtf, &config.gene_id_tag, &config.transcript_id_tag)?
+        }
+        (None, None) => unreachable!("clap requires --gtf or --features"),
+    };
+
+    let chrom_sizes = match &args.chrom_sizes {
+        Some(path) => parse_chrom_sizes(path).context("Failed to parse chromosome sizes file")?,
+        None => AHashMap::new(),
+    };
+
+    if !args.circular.is_empty() && config.model != Model::Rgmatch {
+        bail!("--circular cannot be combined with the GREAT or cis models.");
+    }
+    prepare_genes(&mut gtf_data, &args, &config, &chrom_sizes)?;
+
+    if args.merge_input.is_some_and(|gap| gap < 0) {
+        bail!("The merge gap cannot be lower than 0 bps.");
+    }
+
+    // Validate batch_size
+    if args.batch_size == Some(0) {
+        bail!("Batch size must be greater than 0");
+    }
+
+    // Determine thread count
+    let num_threads = if args.threads == 0 {
+        num_cpus::get()
+    } else {
+        args.threads
+    };
+    let metrics = Arc::new(PerfMetrics::new(args.perf || args.metrics.is_some()));
+
+    let index = build_index(&gtf_data, &config);
+
+    let links = match &args.links {
+        Some(path) => {
+            let links = parse_links(path).context("Failed to parse links file")?;
+            Some(Arc::new(LinkIndex::new(links)))
+        }
+        None => None,
+    };
+
+    let runs = if let Some(manifest) = &args.manifest {
+        Some(manifest_runs(parse_manifest(manifest)?, &args)?)
+    } else if args.bed_files.len() > 1 {
+        let samples = glob_samples(&args.bed)?;
+        Some(manifest_runs(samples, &args)?)
+    } else {
+        None
+    };
+
+    if args.dry_run {
+        let Some(runs) = &runs else {
+            return dry_run(&args, &gtf_data, &config, num_threads).map(|()| None);
+        };
+        for (sample, sample_args, sample_config) in runs {
+            writeln!(io::stdout(), "Sample: {}", sample)?;
+            dry_run(sample_args, &gtf_data, sample_config, num_threads)?;
+        }
+        return Ok(None);
+    }
+
+    let mut matched_regions = None;
+    if let Some(runs) = runs {
+        // The samples share the annotation, parsed once
+        matched_regions = Some(run_manifest(
+            &args,
+            runs,
+            Arc::new(gtf_data),
+            links,
+            num_threads,
+            &metrics,
+        )?);
+    } else if let Some(diff_gtf) = &args.diff_gtf {
+        // Regions are annotated against both GTFs and compared
+        info!("Parsing GTF file: {}", diff_gtf.display());
+        let mut new_gtf_data =
+            parse_annotation(diff_gtf, &config.gene_id_tag, &config.transcript_id_tag)?;
+        prepare_genes(&mut new_gtf_data, &args, &config, &chrom_sizes)?;
+        let new_index = build_index(&new_gtf_data, &config);
+        run_diff(
+            &args,
+            (&gtf_data, index.as_deref()),
+            (&new_gtf_data, new_index.as_deref()),
+            &config,
+        )?;
+    } else if let Some(compare_bed) = &args.compare_bed {
+        // Both region sets are annotated and summarized per gene
+        run_compare(
+            &args,
+            compare_bed,
+            &gtf_data,
+            index.as_deref(),
+            links.as_deref(),
+            &config,
+        )?;
+    } else if let Some(num_backgrounds) = args.background {
+        // Regions are compared with shuffled copies of themselves
+        run_enrichment(
+            &args,
+            num_backgrounds,
+            &chrom_sizes,
+            &gtf_data,
+            index.as_deref(),
+            links.as_deref(),
+            &config,
+        )?;
+    } else if args.bedpe {
+        // Loops are annotated anchor by anchor
+        run_loops(
+            &args,
+            &gtf_data,
+            index.as_deref(),
+            links.as_deref(),
+            &config,
+        )?;
+    } else if num_threads == 1 {
+        // Use original sequential implementation
+        let annotated = run_sequential(
+            &args,
+            &gtf_data,
+            index.as_deref(),
+            links.as_deref(),
+            &config,
+            &metrics,
+        )?;
+        matched_regions = Some(annotated.matched_regions);
+    } else {
+        // Use parallel pipeline
+        let annotated = run_parallel(
+            &args,
+            Arc::new(gtf_data),
+            index,
+            links,
+            &config,
+            num_threads,
+            &metrics,
+        )?;
+        matched_regions = Some(annotated.matched_regions);
+    }
+
+    if metrics.is_enabled() {
+        let channel_bound = if num_threads == 1 {
+            0
+        } else {
+            result_channel_bound(&config, num_threads)
+        };
+        let snapshot = metrics.snapshot(num_threads, start.elapsed().as_secs_f64(), channel_bound);
+        if args.perf {
+            log_perf(&snapshot);
+        }
+        if let Some(path) = &args.metrics {
+            let file = File::create(path).context("Failed to create metrics file")?;
+            let mut writer = BufWriter::new(file);
+            snapshot.write(&mut writer, config.metrics_format)?;
+            writer.flush()?;
+        }
+    }
+
+    if let Some(path) = &args.metadata {
+        metadata.finish(run_inputs(&args)?, &run_parameters(&args, &config), &config);
+        let file = File::create(path).context("Failed to create metadata file")?;
+        let mut writer = BufWriter::new(file);
+        metadata.write(&mut writer)?;
+        writer.flush()?;
+    }
+
+    debug!("Finished in {:.2} s", start.elapsed().as_secs_f64());
+    info!("Done!");
+    Ok(matched_regions)
+}
+
+/// Options that a sample of a manifest cannot override: the samples share
+/// the annotation, loaded once, and the outputs of the run.
+const SHARED_OPTIONS: [&str; 19] = [
+    "config",
+    "gtf",
+    "features",
+    "gene_tag",
+    "transcript_tag",
+    "transcript_selection",
+    "tss_cluster",
+    "gene_extent",
+    "chrom_sizes",
+    "circular",
+    "links",
+    "threads",
+    "io_threads",
+    "manifest",
+    "outputs",
+    "summary",
+    "metrics",
+    "perf",
+    "metadata",
+];
+
+/// The samples of a manifest, or the BED files of a -b pattern, each with
+/// the options of the run and its own overrides, BED file and output in the
+/// output directory.
+fn manifest_runs(samples: Vec<ManifestSample>, args: &Args) -> Result<Vec<(String, Args, Config)>> {
+    let argv = with_env_and_config_file(cli_args()?)?;
+    let command = Cli::command();
+    let annotate = command.find_subcommand("annotate").unwrap();
+    // Overrides come after the options of the run and replace them
+    let mut parser =
+        Cli::command().mut_subcommand("annotate", |annotate| annotate.args_override_self(true));
+
+    let mut runs = Vec::new();
+    let mut outputs = vec![manifest_summary_path(args)];
+    for sample in samples {
+        let mut sample_argv = argv.clone();
+        for (option, value) in &sample.overrides {
+            let name = option.replace('_', "-");
+            let Some(arg) = annotate
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(name.as_str()))
+            else {
+                bail!("Unknown option for sample {}: {}", sample.name, option);
+            };
+            if SHARED_OPTIONS.contains(&arg.get_id().as_str()) {
+                bail!(
+                    "--{} cannot be set for sample {}: it is shared by all the samples of a manifest",
+                    name,
+                    sample.name
+                );
+            }
+            let values =
+                config_option_args(arg, &name, &option_value(value)).with_context(|| {
+                    format!("Invalid value of {} for sample {}", option, sample.name)
+                })?;
+            sample_argv.extend(values.into_iter().map(OsString::from));
+        }
+        let matches = parser
+            .try_get_matches_from_mut(&sample_argv)
+            .with_context(|| format!("Invalid options for sample {}", sample.name))?;
+        let Command::Annotate(mut sample_args) = Cli::from_arg_matches(&matches)?.command else {
+            unreachable!("the manifest is read by annotate");
+        };
+        sample_args.manifest = None;
+        sample_args.bed_arg = Some(sample.bed.clone());
+        sample_args.summary = Some(args.output.join(format!("{}.summary.tsv", sample.name)));
+        let mut config = configure(&mut sample_args)
+            .with_context(|| format!("Invalid options for sample {}", sample.name))?;
+        if args.features.is_some() {
+            config.enable_features();
+        }
+        let mut output = format!("{}.{}", sample.name, config.format.extension());
+        if config.bgzip {
+            output.push_str(".gz");
+        }
+        sample_args.output = args.output.join(output);
+
+        // The sample options replace the manifest with the sample files
+        sample_args.options =
+            resolved_options(annotate, matches.subcommand_matches("annotate").unwrap());
+        sample_args.options.shift_remove("manifest");
+        let path = |path: &Path| toml::Value::String(path.to_string_lossy().into_owned());
+        let files = [
+            ("bed", path(&sample.bed)),
+            (
+                "output",
+                toml::Value::Array(vec![path(&sample_args.output)]),
+            ),
+            ("summary", path(sample_args.summary.as_ref().unwrap())),
+        ];
+        for (option, value) in files {
+            sample_args.options.insert(option.to_string(), value);
+        }
+
+        for path in [&sample_args.output, sample_args.summary.as_ref().unwrap()] {
+            if outputs.contains(path) {
+                bail!(
+                    "Sample {} writes to {}, which another output of the manifest also uses",
+                    sample.name,
+                    path.display()
+                );
+            }
+            outputs.push(path.clone());
+        }
+        runs.push((sample.name, *sample_args, config));
+    }
+    Ok(runs)
+}
+
+/// Where the summary of the samples of a manifest is written: --summary, or
+/// summary.tsv in the output directory.
+fn manifest_summary_path(args: &Args) -> PathBuf {
+    args.summary
+        .clone()
+        .unwrap_or_else(|| args.output.join("summary.tsv"))
+}
+
+/// Annotate the samples of a manifest one after another, and write their
+/// summary.
+fn run_manifest(
+    args: &Args,
+    runs: Vec<(String, Args, Config)>,
+    gtf_data: Arc<GtfData>,
+    links: Option<Arc<LinkIndex>>,
+    num_threads: usize,
+    metrics: &Arc<PerfMetrics>,
+) -> Result<usize> {
+    fs::create_dir_all(&args.output).with_context(|| {
+        format!(
+            "Failed to create output directory {}",
+            args.output.display()
+        )
+    })?;
+
+    let mut summaries = SampleSummaries::default();
+    let mut matched_regions = 0;
+    for (sample, sample_args, config) in runs {
+        info!("Annotating sample {}", sample);
+        let index = build_index(&gtf_data, &config);
+        let annotated = if num_threads == 1 {
+            run_sequential(
+                &sample_args,
+                &gtf_data,
+                index.as_deref(),
+                links.as_deref(),
+                &config,
+                metrics,
+            )?
+        } else {
+            run_parallel(
+                &sample_args,
+                Arc::clone(&gtf_data),
+                index,
+                links.clone(),
+                &config,
+                num_threads,
+                metrics,
+            )?
+        };
+        matched_regions += annotated.matched_regions;
+        if let Some(run_summary) = annotated.run_summary {
+            summaries.add(&sample, run_summary);
+        }
+    }
+
+    let path = manifest_summary_path(args);
+    if is_stdout(&path) {
+        eprintln!("Sample summary:");
+        summaries.write(&mut io::stderr())?;
+    } else {
+        info!("Writing sample summary to: {}", path.display());
+        let file = File::create(&path).context("Failed to create summary file")?;
+        let mut writer = BufWriter::new(file);
+        summaries.write(&mut writer)?;
+        writer.flush()?;
+    }
+    Ok(matched_regions)
+}
+
+/// Check that the options of a run allow annotating the several BED files
+/// of a -b pattern, as the samples of a manifest.
+fn check_bed_files(args: &Args) -> Result<()> {
+    if is_stdout(&args.output) || args.outputs.len() > 1 {
+        bail!(
+            "{} matches several BED files, which need a single output directory (-o) for their outputs.",
+            args.bed.display()
+        );
+    }
+    let conflicting = [
+        ("--bedpe", args.bedpe),
+        ("diff-gtf", args.diff_gtf.is_some()),
+        ("--compare-bed", args.compare_bed.is_some()),
+        ("--background", args.background.is_some()),
+        ("--checkpoint", args.checkpoint.is_some()),
+        ("--split-by-area", args.split_by_area.is_some()),
+        ("--gene-sets", args.gene_sets.is_some()),
+        ("--gene-table", args.gene_table.is_some()),
+        ("--matrix", args.matrix.is_some()),
+        ("--tss-profile", args.tss_profile.is_some()),
+        ("--html-report", args.html_report.is_some()),
+    ];
+    if let Some((option, _)) = conflicting.iter().find(|(_, set)| *set) {
+        bail!(
+            "{} cannot be used with several BED files ({} matches {})",
+            option,
+            args.bed.display(),
+            args.bed_files.len()
+        );
+    }
+    Ok(())
+}
+
+/// Check the options of an annotate run and build its configuration.
+fn configure(args: &mut Args) -> Result<Config> {
+    args.output = PathBuf::from(&args.outputs[0]);
+    args.bed = args.bed_arg.clone().unwrap_or_default();
+    for target in &args.outputs[1..] {
+        let Some((format, path)) = target
+            .split_once(':')
+            .and_then(|(format, path)| Some((format.parse().ok()?, PathBuf::from(path))))
+        else {
+            bail!(
+                "Further outputs should be given as FORMAT:FILE (e.g. parquet:out.parquet): {}",
+                target
+            );
+        };
+        args.extra_outputs.push((format, path));
+    }
+
+    // Validate inputs
+    if let Some(gtf) = &args.gtf {
+        if !gtf.exists() {
+            bail!("GTF file not found: {}", gtf.display());
+        }
+    }
+    if let Some(features) = &args.features {
+        if !features.exists() {
+            bail!("Feature BED file not found: {}", features.display());
+        }
+    }
+    if let Some(biotype_rules) = &args.biotype_rules {
+        if !biotype_rules.exists() {
+            bail!("Biotype rules file not found: {}", biotype_rules.display());
+        }
+    }
+    if let Some(manifest) = &args.manifest {
+        if !manifest.exists() {
+            bail!("Manifest file not found: {}", manifest.display());
+        }
+        if is_stdout(&args.output) || args.outputs.len() > 1 {
+            bail!(
+                "--manifest needs a single output directory (-o) for the outputs of the samples."
+            );
+        }
+    } else if is_pattern(&args.bed) {
+        args.bed_files = glob_samples(&args.bed)?
+            .into_iter()
+            .map(|sample| sample.bed)
+            .collect();
+        if let [bed] = args.bed_files.as_slice() {
+            args.bed = bed.clone();
+        } else {
+            check_bed_files(args)?;
+        }
+    } else if !args.bed.exists() {
+        bail!("BED file not found: {}", args.bed.display());
+    }
+    if let Some(links) = &args.links {
+        if !links.exists() {
+            bail!("Links file not found: {}", links.display());
+        }
+    }
+    if let Some(diff_gtf) = &args.diff_gtf {
+        if !diff_gtf.exists() {
+            bail!("GTF file not found: {}", diff_gtf.display());
+        }
+    }
+    if let Some(compare_bed) = &args.compare_bed {
+        if !compare_bed.exists() {
+            bail!("BED file not found: {}", compare_bed.display());
+        }
+    }
+    if args.background == Some(0) {
+        bail!("--background must be at least 1");
+    }
+    if let Some(exclude) = &args.exclude {
+        if !exclude.exists() {
+            bail!("Exclusion BED file not found: {}", exclude.display());
+        }
+    }
+    if let Some(chrom_sizes) = &args.chrom_sizes {
+        if !chrom_sizes.exists() {
+            bail!("Chromosome sizes file not found: {}", chrom_sizes.display());
+        }
+    }
+
+    // Parse report level
+    let level: ReportLevel = if args.report_all {
+        ReportLevel::All
+    } else {
+        args.report.parse().context(
+            "Report can only be one of the following: exon, transcript, gene, all or levels",
+        )?
+    };
+
+    // Build configuration
+    let mut config = Config::new();
+    config.level = level;
+
+    // Set distance (convert from kb to bp)
+    if args.distance >= 0 {
+        config.set_distance_kb(args.distance);
+    }
+
+    // Set asymmetric upstream/downstream distances (convert from kb to bp)
+    if args.upstream_distance.is_some_and(|d| d < 0)
+        || args.downstream_distance.is_some_and(|d| d < 0)
+    {
+        bail!("The upstream and downstream distances cannot be lower than 0 kb.");
+    }
+    config.upstream_distance = args.upstream_distance.map(|kb| kb * 1000);
+    config.downstream_distance = args.downstream_distance.map(|kb| kb * 1000);
+
+    // Set the per-region distance column
+    if args
+        .distance_column
+        .is_some_and(|column| !(4..=12).contains(&column))
+    {
+        bail!("The distance column should range between 4 and 12.");
+    }
+    config.distance_column = args.distance_column;
+
+    // Set the per-region weight column
+    if args
+        .weight_column
+        .is_some_and(|column| !(4..=12).contains(&column))
+    {
+        bail!("The weight column should range between 4 and 12.");
+    }
+    config.weight_column = args.weight_column;
+
+    // Set TSS distance
+    if args.tss >= 0 {
+        config.tss = args.tss as f64;
+    } else {
+        bail!("The TSS distance cannot be lower than 0 bps.");
+    }
+
+    // Set TTS distance
+    if args.tts >= 0 {
+        config.tts = args.tts as f64;
+    } else {
+        bail!("The TTS distance cannot be lower than 0 bps.");
+    }
+
+    // Set promoter distance
+    if args.promoter >= 0 {
+        config.promoter = args.promoter as f64;
+    } else {
+        bail!("The promoter distance cannot be lower than 0 bps.");
+    }
+
+    // Set percentage thresholds
+    if args.perc_area >= 0.0 && args.perc_area <= 100.0 {
+        config.perc_area = args.perc_area;
+    } else {
+        bail!("The percentage of area defined was wrong. It should range between 0 and 100.");
+    }
+
+    if args.perc_region >= 0.0 && args.perc_region <= 100.0 {
+        config.perc_region = args.perc_region;
+    } else {
+        bail!("The percentage of region defined was wrong. It should range between 0 and 100.");
+    }
+
+    if let Some(fraction) = args.reciprocal_overlap {
+        if fraction > 0.0 && fraction <= 1.0 {
+            config.reciprocal_overlap = Some(fraction);
+        } else {
+            bail!("The reciprocal overlap fraction defined was wrong. It should be greater than 0 and at most 1.");
+        }
+    }
+
+    // Parse custom zones (before the rules, which may rank them)
+    if let Some(zones) = &args.zones {
+        if !config.parse_zones(zones) {
+            bail!("Zones not properly passed. Use NAME:START-END entries ordered from the TSS outwards.");
+        }
+    }
+
+    // Split the promoter into tiers
+    if let Some(proximal) = args.proximal_promoter {
+        if !config.split_promoter(proximal) {
+            bail!("The proximal promoter distance should range between 1 and the promoter distance minus 1.");
+        }
+    }
+
+    // Parse rules
+    if !config.parse_partial_rules(&args.rules, args.exclusive) {
+        bail!("Rules not properly passed.");
+    }
+
+    // Set nearest-k reporting
+    if let Some(k) = args.nearest_k {
+        if k == 0 {
+            bail!("The number of nearest genes must be greater than 0.");
+        }
+        config.nearest_k = Some(k);
+    }
+    config.nearest_per_strand = args.nearest_per_strand;
+    config.flanking = args.flanking;
+
+    // Set the cap on associations per region
+    if let Some(max_hits) = args.max_hits {
+        if max_hits == 0 {
+            bail!("The maximum number of hits must be greater than 0.");
+        }
+        config.max_hits = Some(max_hits);
+    }
+    if args.best_per_region && config.level != ReportLevel::Gene {
+        bail!("--best-per-region is only available with gene-level reports (-r gene).");
+    }
+    config.best_per_region = args.best_per_region;
+
+    config.report_unmatched = args.report_unmatched;
+
+    config.gene_summary = args.gene_summary;
+    config.report_antisense = args.antisense;
+    config.signed_distance = args.signed_distance;
+    config.region_strand = args.region_strand;
+    config.gene_coverage = args.gene_coverage;
+    config.overlap_bp = args.overlap_bp;
+    config.area_coordinates = args.area_coordinates;
+    config.biotype_columns = args.biotypes;
+    config.exon_count = args.exon_count;
+    config.association_id = args.association_id;
+    if args.tss_distance_list && config.level != ReportLevel::Gene {
+        bail!("--tss-distance-list is only available with gene-level reports (-r gene).");
+    }
+    config.tss_distance_list = args.tss_distance_list;
+
+    // Set the confidence score weights
+    if let Some(weights) = &args.confidence {
+        config.confidence = Some(weights.parse().context(
+            "Confidence weights should be 4 non-negative numbers REGION,AREA,RANK,DISTANCE with a positive sum",
+        )?);
+    }
+
+    // Set duplicate handling
+    config.duplicates = args
+        .duplicates
+        .parse()
+        .context("Duplicates can only be one of the following: keep, replicate or collapse")?;
+
+    // Set tie-break
+    config.tie_break = args
+        .tie_break
+        .parse()
+        .context("Tie-break can only be one of the following: none, distance or tss")?;
+
+    // Set distance anchor
+    config.distance_anchor = args
+        .distance_anchor
+        .parse()
+        .context("Distance anchor can only be one of the following: midpoint, edge or summit")?;
+    config.gene_tss_distance = args.gene_tss_distance;
+
+    // Set association model
+    config.model = args
+        .model
+        .parse()
+        .context("Model can only be one of the following: rgmatch, great or cis")?;
+    if args.basal_upstream < 0 || args.basal_downstream < 0 || args.max_extension < 0 {
+        bail!("The GREAT basal and extension distances cannot be lower than 0.");
+    }
+    config.great.basal_upstream = args.basal_upstream;
+    config.great.basal_downstream = args.basal_downstream;
+    config.great.max_extension = args.max_extension * 1000;
+    if args.cis_window < 0 {
+        bail!("The cis window cannot be lower than 0 kb.");
+    }
+    config.cis_window = args.cis_window * 1000;
+    if args.promoters_only && config.model != Model::Rgmatch {
+        bail!("--promoters-only cannot be combined with the GREAT or cis models.");
+    }
+    config.promoters_only = args.promoters_only;
+    if args.gene_extent && config.model != Model::Rgmatch {
+        bail!("--gene-extent cannot be combined with the GREAT or cis models.");
+    }
+    if level == ReportLevel::Levels && config.model != Model::Rgmatch {
+        bail!("-r levels cannot be combined with the GREAT or cis models.");
+    }
+    if level == ReportLevel::Levels && args.bedpe {
+        bail!("-r levels cannot be combined with --bedpe.");
+    }
+    if args.best_per_region && config.model != Model::Rgmatch {
+        bail!("--best-per-region cannot be combined with the GREAT or cis models.");
+    }
+    if args.gene_tss_distance && config.model != Model::Rgmatch {
+        bail!("--gene-tss-distance cannot be combined with the GREAT or cis models.");
+    }
+
+    // Set strand mode
+    if args.same_strand {
+        config.strand_mode = StrandMode::Same;
+    } else if args.opposite_strand {
+        config.strand_mode = StrandMode::Opposite;
+    }
+
+    // Enable UTR classification
+    if args.utr {
+        config.enable_utr();
+    }
+
+    // Enable CDS classification
+    if args.cds {
+        config.enable_cds();
+    }
+
+    // Enable gene-extent matching
+    if args.gene_extent {
+        config.enable_gene_extent();
+    }
+
+    // Set compatibility mode
+    if let Some(compat) = &args.compat {
+        let compat = compat.parse().context(
+            "Compatibility mode can only be one of the following: chipseeker, bedtools-closest or python",
+        )?;
+        config.set_compat(compat);
+    }
+
+    // Set output format
+    config.format = args.format.parse().context(
+        "Output format can only be one of the following: tsv, bed, gff3, parquet or sqlite",
+    )?;
+    let summary_output = args.gene_summary
+        || args.bedpe
+        || args.diff_gtf.is_some()
+        || args.compare_bed.is_some()
+        || args.background.is_some();
+    if matches!(
+        config.format,
+        OutputFormat::Bed | OutputFormat::Gff3 | OutputFormat::Ucsc
+    ) && summary_output
+    {
+        bail!(
+            "--format {} is only available for the per-association output.",
+            args.format
+        );
+    }
+    config.bgzip = args.bgzip;
+    if is_stdout(&args.output) {
+        if args.bgzip {
+            bail!("--bgzip needs an output file (-o) to write the index next to.");
+        }
+        if config.format == OutputFormat::Sqlite {
+            bail!("SQLite output needs an output file (-o).");
+        }
+    }
+    if args.bgzip
+        && (summary_output
+            || matches!(
+                config.format,
+                OutputFormat::Parquet | OutputFormat::Sqlite | OutputFormat::Ucsc
+            ))
+    {
+        bail!("--bgzip is only available for the per-association output in the tsv, bed or gff3 format.");
+    }
+
+    // Set the memory limit of the results waiting to be written
+    config.max_memory = args
+        .max_memory
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .context(
+        "Max memory should be a number of bytes with an optional K, M, G or T suffix (e.g. 512M)",
+    )?;
+
+    // Split the threads between matching and formatting
+    if let Some(io_threads) = args.io_threads {
+        if io_threads == 0 {
+            bail!("--io-threads must be greater than 0.");
+        }
+        if args.threads != 0 && io_threads >= args.threads {
+            bail!("--io-threads must be lower than --threads, leaving threads to match regions.");
+        }
+    }
+    config.io_threads = args.io_threads;
+    config.adaptive_threads = args.threads == 0 && args.io_threads.is_none();
+    config.unordered = args.unordered;
+
+    // Set the precision of the percentage columns
+    config.precision = args
+        .precision
+        .parse()
+        .context("Precision should be a number of decimal places (0-17) or full")?;
+
+    // Set the columns identifying the region
+    config.region_columns = args
+        .region_columns
+        .parse()
+        .context("Region columns can only be one of the following: id, split or both")?;
+
+    // Set the output order
+    config.sort_output = args.sort_output.parse().context(
+        "Output order can only be one of the following: coord, gene, gene-distance or none",
+    )?;
+    if config.sort_output != SortOrder::None && summary_output {
+        bail!("--sort-output is only available for the per-association output.");
+    }
+    if args.gene_rank {
+        if config.sort_output != SortOrder::GeneDistance {
+            bail!("--gene-rank needs the rows sorted with --sort-output gene-distance.");
+        }
+        if args.wide.is_some()
+            || config.compat == Some(Compat::BedtoolsClosest)
+            || matches!(
+                config.format,
+                OutputFormat::Bed | OutputFormat::Gff3 | OutputFormat::Ucsc
+            )
+        {
+            bail!("--gene-rank is only available for the per-association output in the tsv, parquet or sqlite format, without --wide.");
+        }
+        config.gene_rank = true;
+    }
+
+    // Set the wide layout
+    if let Some(k) = args.wide {
+        if k == 0 {
+            bail!("The number of associations per row must be greater than 0.");
+        }
+        if summary_output
+            || args.columns.is_some()
+            || config.compat == Some(Compat::BedtoolsClosest)
+            || matches!(
+                config.format,
+                OutputFormat::Bed | OutputFormat::Gff3 | OutputFormat::Ucsc
+            )
+        {
+            bail!("--wide is only available for the per-association output in the tsv, parquet or sqlite format, without --columns.");
+        }
+        config.wide = Some(k);
+    }
+
+    // The bedtools closest layout replaces the table
+    if config.compat == Some(Compat::BedtoolsClosest)
+        && (summary_output || config.format != OutputFormat::Tsv || args.columns.is_some())
+    {
+        bail!("--compat bedtools-closest is only available for the per-association output in the tsv format, without --columns.");
+    }
+
+    // Set the header line and the names of the BED columns
+    if (args.no_header || args.comment_header)
+        && (summary_output || config.format != OutputFormat::Tsv)
+    {
+        bail!("--no-header and --comment-header are only available for the per-association output in the tsv format.");
+    }
+    config.no_header = args.no_header;
+    config.comment_header = args.comment_header;
+    if let Some(names) = &args.bed_names {
+        config.bed_names = names
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .collect();
+        for (i, name) in config.bed_names.iter().enumerate() {
+            if name.is_empty() || config.bed_names[..i].contains(name) {
+                bail!("BED column names should be a comma-separated list of distinct names.");
+            }
+        }
+    }
+
+    // Set the region-gene matrix layout and values
+    config.matrix_format = args
+        .matrix_format
+        .parse()
+        .context("Matrix format can only be one of the following: mtx or tsv")?;
+    config.matrix_value = args
+        .matrix_value
+        .parse()
+        .context("Matrix value can only be one of the following: overlap or binary")?;
+
+    // Set the performance metrics format
+    config.metrics_format = args
+        .metrics_format
+        .parse()
+        .context("Metrics format can only be one of the following: json or prometheus")?;
+
+    // Set the TSS distance profile bins
+    if args.tss_profile_bin <= 0 || args.tss_profile_window <= 0 {
+        bail!("The TSS profile bin size and window must be greater than 0.");
+    }
+    config.tss_profile_bin = args.tss_profile_bin;
+    config.tss_profile_window = args.tss_profile_window;
+
+    // Set the selected output columns
+    if let Some(columns) = &args.columns {
+        if summary_output
+            || matches!(
+                config.format,
+                OutputFormat::Bed | OutputFormat::Gff3 | OutputFormat::Ucsc
+            )
+        {
+            bail!("--columns is only available for the per-association output in the tsv, parquet or sqlite format.");
+        }
+        config.select_columns = columns
+            .split(',')
+            .map(|column| column.parse())
+            .collect::<Result<_, _>>()
+            .context("Columns should be a comma-separated list of NAME or NAME:HEADER")?;
+        let options = OutputOptions::from_config(&config);
+        let available = options.table_columns();
+        for spec in &config.select_columns {
+            if !available.contains(&spec.name.as_str()) {
+                bail!(
+                    "Unknown output column: {} (available: {})",
+                    spec.name,
+                    available.join(", ")
+                );
+            }
+        }
+    }
+
+    // Further outputs share the layout of the main output in their own format
+    if !args.extra_outputs.is_empty() && summary_output {
+        bail!("Several --output targets are only available for the per-association output.");
+    }
+    if args.split_by_area.is_some() && summary_output {
+        bail!("--split-by-area is only available for the per-association output.");
+    }
+    for (format, path) in &args.extra_outputs {
+        if is_stdout(path) {
+            bail!("Only the first --output can be standard output.");
+        }
+        let table = matches!(
+            format,
+            OutputFormat::Tsv | OutputFormat::Parquet | OutputFormat::Sqlite
+        );
+        if (!table
+            && (config.wide.is_some() || config.gene_rank || !config.select_columns.is_empty()))
+            || (*format != OutputFormat::Tsv
+                && (config.compat == Some(Compat::BedtoolsClosest)
+                    || config.no_header
+                    || config.comment_header))
+        {
+            bail!(
+                "--output {}: the output options of the run (--wide, --gene-rank, --columns, --compat bedtools-closest, --no-header or --comment-header) are not available in this format.",
+                path.display()
+            );
+        }
+    }
+
+    // Only outputs written as the regions are read can be continued
+    if args.checkpoint.is_some()
+        && (summary_output
+            || is_stdout(&args.output)
+            || !args.extra_outputs.is_empty()
+            || config.bgzip
+            || config.sort_output != SortOrder::None
+            || config.duplicates == DuplicateMode::Collapse
+            || matches!(config.format, OutputFormat::Parquet | OutputFormat::Sqlite))
+    {
+        bail!("--checkpoint is only available for the per-association output to a single file (-o) in the tsv, bed, gff3 or ucsc format, unsorted, without --bgzip or --duplicates collapse.");
+    }
+
+    // Set transcript selection
+    config.transcript_selection = args.transcript_selection.parse().context(
+        "Transcript selection can only be one of the following: all, longest, most-exons or collapse",
+    )?;
+    if args.tss_cluster.is_some_and(|window| window < 0) {
+        bail!("The TSS cluster window cannot be lower than 0 bps.");
+    }
+    config.tss_cluster = args.tss_cluster;
+    if args.tss_max_tsl.is_some_and(|tsl| !(1..=5).contains(&tsl)) {
+        bail!("The transcript support level should range between 1 and 5.");
+    }
+    config.max_tsl = args.tss_max_tsl;
+
+    // Set per-biotype rules, once the rest of the configuration is known
+    if let Some(path) = &args.biotype_rules {
+        if config.model != Model::Rgmatch {
+            bail!("--biotype-rules cannot be combined with the GREAT or cis models.");
+        }
+        if config.level == ReportLevel::Levels {
+            bail!("--biotype-rules cannot be combined with -r levels.");
+        }
+        let sets = parse_biotype_rules(path)?;
+        if !config.set_biotype_rules(&sets) {
+            bail!("Biotype rules not properly passed.");
+        }
+    }
+
+    // Set GTF tags
+    config.gene_id_tag = args.gene_tag.clone();
+    config.transcript_id_tag = args.transcript_tag.clone();
+    Ok(config)
+}
+
+/// Prepare the genes of an annotation for matching.
+///
+/// Transcripts are selected per gene, genes are sorted for deterministic
+/// matching and performance, and the genes of circular contigs are mirrored
+/// across their origin.
+fn prepare_genes(
+    gtf_data: &mut GtfData,
+    args: &Args,
+    config: &Config,
+    chrom_sizes: &AHashMap<String, i64>,
+) -> Result<()> {
+    gtf_data
+        .genes_by_chrom
+        .values_mut()
+        .collect::<Vec<_>>()
+        .par_iter_mut()
+        .for_each(|genes| {
+            for gene in genes.iter_mut() {
+                gene.select_transcripts(config.transcript_selection);
+                if let Some(window) = config.tss_cluster {
+                    gene.cluster_tss(window);
+                }
+                if config.gene_extent {
+                    gene.collapse_to_extent();
+                }
+            }
+            genes.sort_by(|a, b| a.start.cmp(&b.start).then(a.gene_id.cmp(&b.gene_id)));
+        });
+
+    for chrom in &args.circular {
+        let Some(&length) = chrom_sizes.get(chrom) else {
+            bail!(
+                "Circular contig {} is missing from the chromosome sizes file.",
+                chrom
+            );
+        };
+        gtf_data.wrap_circular(chrom, length);
+    }
+    Ok(())
+}
+
+/// Build the GREAT regulatory domains, cis windows or promoter windows, if requested.
+fn build_index(gtf_data: &GtfData, config: &Config) -> Option<Arc<dyn RegionIndex>> {
+    if config.model == Model::Great {
+        Some(Arc::new(DomainIndex::build(gtf_data, &config.great)))
+    } else if config.model == Model::Cis {
+        Some(Arc::new(CisIndex::build(gtf_data)))
+    } else if config.promoters_only {
+        Some(Arc::new(PromoterIndex::build(gtf_data, config)))
+    } else {
+        None
+    }
+}
+
+/// Open a BED file, merging overlapping or duplicate regions when requested.
+fn open_bed(path: &Path, args: &Args, config: &Config) -> Result<BedReader> {
+    match args.merge_input {
+        Some(gap) => BedReader::merged(path, gap),
+        None if config.duplicates == DuplicateMode::Collapse => {
+            BedReader::deduplicated(path, |region| config.duplicate_key(region))
+        }
+        None => BedReader::new(path),
+    }
+}
+
+/// Sequential implementation with streaming.
+fn run_sequential(
+    args: &Args,
+    gtf_data: &GtfData,
+    index: Option<&dyn RegionIndex>,
+    links: Option<&LinkIndex>,
+    config: &Config,
+    metrics: &PerfMetrics,
+) -> Result<Annotated> {
+    info!("Processing BED file: {}", args.bed.display());
+    let mut options = OutputOptions::from_config(config);
+    options.set_annotation(gtf_data);
+    let mut checkpointer = Checkpointer::start(args, config, &options)?;
+    let resumed = Checkpointer::resumed(&checkpointer);
+
+    // Initialize streaming reader
+    let mut bed_reader = match &resumed {
+        Some(checkpoint) => BedReader::resume(&args.bed, checkpoint.bed_offset)?,
+        None => open_bed(&args.bed, args, config)?,
+    };
+
+    // Output writer
+    info!("Writing output to: {}", args.output.display());
+    let mut writer = match &resumed {
+        Some(checkpoint) => OutputFile::resume(&args.output, config, checkpoint.output_bytes)?,
+        None => OutputFile::create(&args.output, config)?,
+    };
+
+    let mut summary = config
+        .gene_summary
+        .then(|| GeneSummary::from_config(config));
+    let mut side = SideOutputs::new(args, config, &options)?;
+    if let Some(checkpoint) = &resumed {
+        side.matched_regions = checkpoint.matched_regions as usize;
+    }
+    // The gene summary writes its own header at the end
+    let mut header_written = summary.is_some() || resumed.is_some();
+
+    // Optimization state
+    let mut last_chrom = String::new();
+    let mut last_start = -1;
+    let mut last_index = 0;
+    let mut duplicates = DuplicateCache::new(config.duplicates);
+    let mut lines_written = resumed.map_or(0, |checkpoint| checkpoint.lines_written as usize);
+
+    // Process in chunks
+    while let Some(chunk) = bed_reader.read_chunk(args.batch_size())? {
+        let num_regions = chunk.len();
+        if !header_written {
+            let num_meta = bed_reader.num_meta_columns();
+            write_header_with(&mut writer, num_meta, &options)?;
+            side.write_header(num_meta)?;
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                checkpointer.set_header(num_meta, &options)?;
+            }
+            header_written = true;
+        }
+        metrics.add_regions_processed(chunk.len() as u64);
+
+        for region in chunk {
+            // Duplicates of an already annotated region reuse its results
+            if let Some(processed) = duplicates.get(&region, config) {
+                let write_start = metrics.start();
+                lines_written += write_region(
+                    &mut writer,
+                    summary.as_mut(),
+                    &mut side,
+                    &region,
+                    &processed,
+                    &options,
+                )?;
+                metrics.add_writer_io(write_start);
+                continue;
+            }
+
+            let match_start = metrics.start();
+            let mut processed = if let Some(index) = index {
+                // GREAT domains or promoter windows
+                index.annotate_region(&region, config)
+            } else if let Some(genes) = gtf_data.genes_by_chrom.get(&region.chrom) {
+                // Find genes for chrom
+                let max_len = *gtf_data.max_lengths.get(&region.chrom).unwrap_or(&0);
+
+                // Calculate safe search start (region start - max_len - distance)
+                // Note: we must match the logic in match_regions_to_genes regarding max_lookback
+                // Regions with their own, larger, distance cannot reuse the cached index
+                let lookback = config.region_lookback_distance(&region);
+                let max_lookback = max_len + lookback;
+                let search_start = region.start.saturating_sub(max_lookback);
+
+                let start_index = if region.chrom == last_chrom
+                    && region.start >= last_start
+                    && lookback <= config.max_lookback_distance()
+                {
+                    // Optimistic: advance from last_index
+                    let mut idx = last_index;
+                    // Skip genes that end before search_start
+                    while idx < genes.len() && genes[idx].end < search_start {
+                        idx += 1;
+                    }
+                    idx
+                } else {
+                    // Reset / Binary search
+                    find_search_start_index(genes, search_start)
+                };
+
+                // Update cache
+                last_chrom = region.chrom.clone();
+                last_start = region.start;
+                last_index = start_index;
+
+                // Match
+                annotate_region(&region, genes, config, start_index)
+            } else {
+                // If chromosome not in GTF, verify if we should reset cache?
+                // Probably yes to be safe, though chrom changed so next valid chrom will trigger binary search.
+                last_chrom = region.chrom.clone();
+                warn_missing_chrom(&region.chrom);
+
+                // Only optional rows (e.g. INTERGENIC) can be reported here
+                annotate_region(&region, &[], config, 0)
+            };
+            if let Some(links) = links {
+                links.add_links(&region, &mut processed);
+            }
+            duplicates.insert(&region, config, &processed);
+            metrics.add_worker_matching(match_start);
+
+            // Write line
+            let write_start = metrics.start();
+            lines_written += write_region(
+                &mut writer,
+                summary.as_mut(),
+                &mut side,
+                &region,
+                &processed,
+                &options,
+            )?;
+            metrics.add_writer_io(write_start);
+        }
+        if let Some(checkpointer) = checkpointer.as_mut() {
+            checkpointer.add(
+                &mut writer,
+                bed_reader.offset(),
+                num_regions,
+                lines_written,
+                side.matched_regions,
+            )?;
+        }
+    }
+
+    if !header_written {
+        // File was empty
+        write_header_with(&mut writer, 0, &options)?;
+        side.write_header(0)?;
+    }
+
+    if let Some(summary) = &summary {
+        lines_written = summary.write(&mut writer)?;
+    }
+    let annotated = side.write()?;
+
+    metrics.add_lines_written(lines_written as u64);
+    writer.finish()?;
+    if let Some(checkpointer) = checkpointer {
+        checkpointer.finish()?;
+    }
+    Ok(annotated)
+}
+
+/// Annotate a single region on its own, without the streaming search state.
+fn annotate_standalone(
+    region: &Region,
+    gtf_data: &GtfData,
+    index: Option<&dyn RegionIndex>,
+    links: Option<&LinkIndex>,
+    config: &Config,
+) -> Vec<Candidate> {
+    let mut processed = match index {
+        Some(index) => index.annotate_region(region, config),
+        None => annotate_genes(region, gtf_data, config),
+    };
+    if let Some(links) = links {
+        links.add_links(region, &mut processed);
+    }
+    processed
+}
+
+/// Match a region against the genes of its chromosome from scratch.
+fn annotate_genes(region: &Region, gtf_data: &GtfData, config: &Config) -> Vec<Candidate> {
+    match gtf_data.genes_by_chrom.get(&region.chrom) {
+        Some(genes) => {
+            let max_len = *gtf_data.max_lengths.get(&region.chrom).unwrap_or(&0);
+            let max_lookback = max_len + config.region_lookback_distance(region);
+            let search_start = region.start.saturating_sub(max_lookback);
+            let start_index = find_search_start_index(genes, search_start);
+            annotate_region(region, genes, config, start_index)
+        }
+        None => {
+            warn_missing_chrom(&region.chrom);
+            annotate_region(region, &[], config, 0)
+        }
+    }
+}
+
+/// Warn about a region on a chromosome without genes, once per chromosome.
+fn warn_missing_chrom(chrom: &str) {
+    let kind = format!("regions on {}, a chromosome not in the annotation", chrom);
+    warnings::warn(&kind, || {
+        format!(
+            "{} is not in the annotation: its regions match no gene",
+            chrom
+        )
+    });
+}
+
+/// Loop (BEDPE) implementation: both anchors of every loop are annotated
+/// and their genes paired, writing loops in file order.
+fn run_loops(
+    args: &Args,
+    gtf_data: &GtfData,
+    index: Option<&dyn RegionIndex>,
+    links: Option<&LinkIndex>,
+    config: &Config,
+) -> Result<()> {
+    info!("Processing BEDPE file: {}", args.bed.display());
+    let bedpe = parse_bedpe(&args.bed)?;
+
+    let pairs: Vec<_> = bedpe
+        .loops
+        .par_iter()
+        .map(|lp| {
+            let anchor1 = annotate_standalone(&lp.anchor1, gtf_data, index, links, config);
+            let anchor2 = annotate_standalone(&lp.anchor2, gtf_data, index, links, config);
+            pair_anchors(&anchor1, &anchor2, &config.rules)
+        })
+        .collect();
+
+    info!("Writing output to: {}", args.output.display());
+    let mut writer = OutputFile::create(&args.output, config)?;
+
+    write_loop_header(&mut writer, bedpe.num_meta_columns)?;
+    for (lp, pairs) in bedpe.loops.iter().zip(&pairs) {
+        for pair in pairs {
+            writeln!(writer, "{}", format_loop_line(lp, pair))?;
+        }
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+/// Annotation diff implementation: every region is annotated against the old
+/// and the new annotation, writing its changed genes in file order.
+fn run_diff(
+    args: &Args,
+    (old_gtf, old_index): (&GtfData, Option<&dyn RegionIndex>),
+    (new_gtf, new_index): (&GtfData, Option<&dyn RegionIndex>),
+    config: &Config,
+) -> Result<()> {
+    info!("Processing BED file: {}", args.bed.display());
+    let mut bed_reader = open_bed(&args.bed, args, config)?;
+
+    info!("Writing output to: {}", args.output.display());
+    let mut writer = OutputFile::create(&args.output, config)?;
+
+    let mut header_written = false;
+    while let Some(chunk) = bed_reader.read_chunk(args.batch_size())? {
+        if !header_written {
+            write_diff_header(&mut writer, bed_reader.num_meta_columns())?;
+            header_written = true;
+        }
+
+        let changes: Vec<_> = chunk
+            .par_iter()
+            .map(|region| {
+                let old = annotate_standalone(region, old_gtf, old_index, None, config);
+                let new = annotate_standalone(region, new_gtf, new_index, None, config);
+                diff_annotations(&old, &new, &config.rules)
+            })
+            .collect();
+        for (region, changes) in chunk.iter().zip(&changes) {
+            for change in changes {
+                writeln!(writer, "{}", format_diff_line(region, change))?;
+            }
+        }
+    }
+
+    if !header_written {
+        // File was empty
+        write_diff_header(&mut writer, 0)?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+/// Comparison of two region sets: annotate both and report the genes gained,
+/// lost or shared, with their per-area region counts.
+fn run_compare(
+    args: &Args,
+    compare_bed: &Path,
+    gtf_data: &GtfData,
+    index: Option<&dyn RegionIndex>,
+    links: Option<&LinkIndex>,
+    config: &Config,
+) -> Result<()> {
+    let mut comparison = SetComparison::new(&config.rules);
+    for (set, path) in [args.bed.as_path(), compare_bed].into_iter().enumerate() {
+        info!("Processing BED file: {}", path.display());
+        let mut bed_reader = open_bed(path, args, config)?;
+        while let Some(chunk) = bed_reader.read_chunk(args.batch_size())? {
+            let results: Vec<Vec<Candidate>> = chunk
+                .par_iter()
+                .map(|region| annotate_standalone(region, gtf_data, index, links, config))
+                .collect();
+            for candidates in &results {
+                comparison.add(set, candidates);
+            }
+        }
+    }
+
+    info!("Writing output to: {}", args.output.display());
+    let mut writer = OutputFile::create(&args.output, config)?;
+    comparison.write(&mut writer)?;
+
+    writer.finish()?;
+    Ok(())
+}
+
+/// Area enrichment: annotate the regions and shuffled background sets, and
+/// report the observed versus expected regions per area.
+fn run_enrichment(
+    args: &Args,
+    num_backgrounds: usize,
+    chrom_sizes: &AHashMap<String, i64>,
+    gtf_data: &GtfData,
+    index: Option<&dyn RegionIndex>,
+    links: Option<&LinkIndex>,
+    config: &Config,
+) -> Result<()> {
+    info!("Processing BED file: {}", args.bed.display());
+    let mut bed_reader = open_bed(&args.bed, args, config)?;
+    let mut regions = Vec::new();
+    while let Some(chunk) = bed_reader.read_chunk(args.batch_size())? {
+        regions.extend(chunk);
+    }
+
+    let exclusions = match &args.exclude {
+        Some(path) => {
+            let mut reader = BedReader::new(path)?;
+            let mut excluded = Vec::new();
+            while let Some(chunk) = reader.read_chunk(args.batch_size())? {
+                excluded.extend(chunk);
+            }
+            Exclusions::new(excluded)
+        }
+        None => Exclusions::default(),
+    };
+
+    let areas_of = |regions: &[Region]| -> Vec<Area> {
+        regions
+            .par_iter()
+            .map(|region| {
+                let candidates = annotate_standalone(region, gtf_data, index, links, config);
+                region_area(&candidates, &config.rules)
+            })
+            .collect()
+    };
+
+    let observed = areas_of(&regions);
+    info!("Annotating {} background sets", num_backgrounds);
+    // The seed keeps the background reproducible between runs
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut backgrounds = Vec::with_capacity(num_backgrounds);
+    for _ in 0..num_backgrounds {
+        let shuffled = shuffle_regions(&regions, chrom_sizes, &exclusions, &mut rng)?;
+        backgrounds.push(areas_of(&shuffled));
+    }
+
+    info!("Writing output to: {}", args.output.display());
+    let mut writer = OutputFile::create(&args.output, config)?;
+    write_enrichment(
+        &mut writer,
+        &area_enrichment(&observed, &backgrounds, &config.rules),
+    )?;
+
+    writer.finish()?;
+    Ok(())
+}
+
+/// Associations split into one TSV file per area, in the layout of the main
+/// output.
+///
+/// Files are created on the first association of their area, so areas
+/// without associations get no file.
+struct AreaFiles {
+    dir: PathBuf,
+    config: Config,
+    options: OutputOptions,
+    num_meta_columns: usize,
+    files: IndexMap<Area, OutputFile>,
+}
+
+impl AreaFiles {
+    /// Prepare the split of the associations into `dir`, creating it.
+    fn new(dir: &Path, config: &Config, options: &OutputOptions) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create area directory {}", dir.display()))?;
+        Ok(AreaFiles {
+            dir: dir.to_path_buf(),
+            config: Config {
+                format: OutputFormat::Tsv,
+                bgzip: false,
+                ..config.clone()
+            },
+            options: OutputOptions {
+                format: OutputFormat::Tsv,
+                ..options.clone()
+            },
+            num_meta_columns: 0,
+            files: IndexMap::new(),
+        })
+    }
+
+    /// Write the associations of a region to the files of their areas.
+    fn add(&mut self, region: &Region, candidates: &[Candidate]) -> Result<()> {
+        let areas: IndexSet<Area> = candidates.iter().map(|c| c.area.clone()).collect();
+        for area in areas {
+            let group: Vec<Candidate> = candidates
+                .iter()
+                .filter(|c| c.area == area)
+                .cloned()
+                .collect();
+            let writer = match self.files.entry(area.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let path = self.dir.join(format!("{}.tsv", area));
+                    let mut writer = OutputFile::create(&path, &self.config)?;
+                    write_header_with(&mut writer, self.num_meta_columns, &self.options)?;
+                    entry.insert(writer)
+                }
+            };
+            write_associations(writer, region, &group, &self.options)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        for (_, writer) in self.files {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Progress of a run, recorded to its --checkpoint file once the interval
+/// since the last record has passed.
+struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    last: Instant,
+    state: Checkpoint,
+    /// Whether the run continues from the recorded checkpoint.
+    resumed: bool,
+}
+
+impl Checkpointer {
+    /// Start recording the progress of a run, from its checkpoint with --resume.
+    ///
+    /// A checkpoint is only resumed with the same options and, for the
+    /// output `options`, the same header.
+    fn start(args: &Args, config: &Config, options: &OutputOptions) -> Result<Option<Self>> {
+        let Some(path) = &args.checkpoint else {
+            return Ok(None);
+        };
+        let recorded = if args.resume {
+            Checkpoint::read(path)?
+        } else {
+            None
+        };
+        let resumed = recorded.is_some();
+        let state = match recorded {
+            Some(state) => {
+                state.check(&args.bed, &args.output, config)?;
+                state.check_header(&Self::header(state.num_meta_columns, options)?)?;
+                info!(
+                    "Resuming after {} regions from checkpoint: {}",
+                    state.regions,
+                    path.display()
+                );
+                state
+            }
+            None => Checkpoint::new(&args.bed, &args.output, config)?,
+        };
+        Ok(Some(Checkpointer {
+            path: path.clone(),
+            interval: Duration::from_secs(args.checkpoint_interval),
+            last: Instant::now(),
+            state,
+            resumed,
+        }))
+    }
 
-    // Validate batch_size
-    if args.batch_size == 0 {
-        bail!("Batch size must be greater than 0");
+    /// Output header for BED files with `num_meta_columns` metadata columns.
+    fn header(num_meta_columns: usize, options: &OutputOptions) -> Result<String> {
+        let mut header = Vec::new();
+        write_header_with(&mut header, num_meta_columns, options)?;
+        Ok(String::from_utf8_lossy(&header).into_owned())
     }
 
-    // Determine thread count
-    let num_threads = if args.threads == 0 {
-        num_cpus::get()
-    } else {
-        args.threads
-    };
+    /// Record the header written to the output.
+    fn set_header(&mut self, num_meta_columns: usize, options: &OutputOptions) -> Result<()> {
+        self.state.header = Some(Self::header(num_meta_columns, options)?);
+        self.state.num_meta_columns = num_meta_columns;
+        Ok(())
+    }
 
-    if num_threads == 1 {
-        // Use original sequential implementation
-        run_sequential(&args, &gtf_data, &config)?;
-    } else {
-        // Use parallel pipeline
-        run_parallel(&args, gtf_data, &config, num_threads)?;
+    /// The checkpoint to continue from, if the run is resumed.
+    fn resumed(checkpointer: &Option<Self>) -> Option<Checkpoint> {
+        checkpointer
+            .as_ref()
+            .filter(|checkpointer| checkpointer.resumed)
+            .map(|checkpointer| checkpointer.state.clone())
     }
 
-    eprintln!("Done!");
-    Ok(())
+    /// Count a work item whose regions, up to `bed_offset` in the BED file,
+    /// are all written, and record the progress when it is time to.
+    fn add(
+        &mut self,
+        writer: &mut OutputFile,
+        bed_offset: u64,
+        regions: usize,
+        lines_written: usize,
+        matched_regions: usize,
+    ) -> Result<()> {
+        self.state.bed_offset = bed_offset;
+        self.state.regions += regions as u64;
+        self.state.work_items += 1;
+        self.state.lines_written = lines_written as u64;
+        self.state.matched_regions = matched_regions as u64;
+        if self.last.elapsed() < self.interval {
+            return Ok(());
+        }
+        writer.flush()?;
+        self.state.output_bytes = fs::metadata(&self.state.output)
+            .context("Failed to read output file")?
+            .len();
+        self.state.write(&self.path)?;
+        self.last = Instant::now();
+        Ok(())
+    }
+
+    /// Remove the checkpoint of a completed run.
+    fn finish(self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path).context("Failed to remove checkpoint file")?;
+        }
+        Ok(())
+    }
 }
 
-/// Sequential implementation with streaming.
-fn run_sequential(args: &Args, gtf_data: &GtfData, config: &Config) -> Result<()> {
-    eprintln!("Processing BED file: {}", args.bed.display());
+/// Outputs written alongside the main output: the further --output targets
+/// and the per-area files, streamed with it, and the gene sets, the gene table, the region-gene
+/// matrix, the TSS distance profile, the run summary and the HTML report,
+/// written once all regions are annotated.
+struct SideOutputs {
+    extra_outputs: Vec<(OutputFile, OutputOptions)>,
+    area_files: Option<AreaFiles>,
+    gene_sets: Option<GeneSets>,
+    gene_table: Option<(PathBuf, GeneSummary)>,
+    matrix: Option<RegionGeneMatrix>,
+    tss_profile: Option<(PathBuf, TssProfile)>,
+    run_summary: Option<RunSummary>,
+    summary_path: Option<PathBuf>,
+    report: Option<(PathBuf, Vec<(&'static str, String)>)>,
+    /// Regions with at least one association.
+    matched_regions: usize,
+}
 
-    // Initialize streaming reader
-    let mut bed_reader = BedReader::new(&args.bed)?;
+/// Outcome of annotating the regions of a run.
+struct Annotated {
+    /// Regions with at least one association.
+    matched_regions: usize,
+    /// The run summary, if one was written.
+    run_summary: Option<RunSummary>,
+}
 
-    // Output writer
-    eprintln!("Writing output to: {}", args.output.display());
-    let file = File::create(&args.output).context("Failed to create output file")?;
-    let mut writer = BufWriter::new(file);
+impl SideOutputs {
+    fn new(args: &Args, config: &Config, options: &OutputOptions) -> Result<Self> {
+        let mut extra_outputs = Vec::new();
+        for (format, path) in &args.extra_outputs {
+            info!("Also writing output to: {}", path.display());
+            let config = Config {
+                format: *format,
+                bgzip: false,
+                ..config.clone()
+            };
+            let options = OutputOptions {
+                format: *format,
+                ..options.clone()
+            };
+            extra_outputs.push((OutputFile::create(path, &config)?, options));
+        }
+        let area_files = args
+            .split_by_area
+            .as_deref()
+            .map(|dir| AreaFiles::new(dir, config, options))
+            .transpose()?;
+        let report = args
+            .html_report
+            .clone()
+            .map(|path| (path, run_parameters(args, config)));
+        Ok(SideOutputs {
+            extra_outputs,
+            area_files,
+            gene_sets: args.gene_sets.as_deref().map(GeneSets::new),
+            gene_table: args
+                .gene_table
+                .clone()
+                .map(|path| (path, GeneSummary::from_config(config))),
+            matrix: args.matrix.as_deref().map(|dir| {
+                RegionGeneMatrix::new(
+                    dir,
+                    config.matrix_format,
+                    config.matrix_value,
+                    config.precision,
+                )
+            }),
+            tss_profile: args.tss_profile.clone().map(|path| {
+                let profile = TssProfile::new(
+                    config.tss_profile_bin,
+                    config.tss_profile_window,
+                    &config.rules,
+                );
+                (path, profile)
+            }),
+            run_summary: (args.summary.is_some() || report.is_some())
+                .then(|| RunSummary::from_config(config)),
+            summary_path: args.summary.clone(),
+            report,
+            matched_regions: 0,
+        })
+    }
 
-    let mut header_written = false;
+    fn write_header(&mut self, num_meta_columns: usize) -> Result<()> {
+        for (writer, options) in &mut self.extra_outputs {
+            write_header_with(writer, num_meta_columns, options)?;
+        }
+        if let Some(area_files) = self.area_files.as_mut() {
+            area_files.num_meta_columns = num_meta_columns;
+        }
+        Ok(())
+    }
 
-    // Optimization state
-    let mut last_chrom = String::new();
-    let mut last_start = -1;
-    let mut last_index = 0;
+    fn add(&mut self, region: &Region, candidates: &[Candidate]) -> Result<()> {
+        // INTERGENIC rows of unmatched regions are not associations
+        if candidates.iter().any(|c| c.area != Area::Intergenic) {
+            self.matched_regions += 1;
+        }
+        for (writer, options) in &mut self.extra_outputs {
+            write_associations(writer, region, candidates, options)?;
+        }
+        if let Some(area_files) = self.area_files.as_mut() {
+            area_files.add(region, candidates)?;
+        }
+        if let Some(gene_sets) = self.gene_sets.as_mut() {
+            gene_sets.add(candidates);
+        }
+        if let Some((_, gene_table)) = self.gene_table.as_mut() {
+            gene_table.add(region, candidates);
+        }
+        if let Some(matrix) = self.matrix.as_mut() {
+            matrix.add(region, candidates);
+        }
+        if let Some((_, tss_profile)) = self.tss_profile.as_mut() {
+            tss_profile.add(candidates);
+        }
+        if let Some(run_summary) = self.run_summary.as_mut() {
+            run_summary.add(region, candidates);
+        }
+        Ok(())
+    }
 
-    // Process in chunks
-    while let Some(chunk) = bed_reader.read_chunk(args.batch_size)? {
-        if !header_written {
-            let num_meta = bed_reader.num_meta_columns();
-            write_header(&mut writer, num_meta)?;
-            header_written = true;
+    /// Write the outputs and hand back the run summary, if any.
+    fn write(self) -> Result<Annotated> {
+        for (writer, _) in self.extra_outputs {
+            writer.finish()?;
+        }
+        if let Some(area_files) = self.area_files {
+            area_files.finish()?;
+        }
+        if let Some(gene_sets) = &self.gene_sets {
+            gene_sets.write()?;
+        }
+        if let Some((path, gene_table)) = &self.gene_table {
+            let file = File::create(path).context("Failed to create gene table file")?;
+            let mut writer = BufWriter::new(file);
+            gene_table.write_table(&mut writer)?;
+            writer.flush()?;
         }
+        if let Some(matrix) = &self.matrix {
+            matrix.write()?;
+        }
+        if let Some((path, tss_profile)) = &self.tss_profile {
+            let file = File::create(path).context("Failed to create TSS profile file")?;
+            let mut writer = BufWriter::new(file);
+            tss_profile.write(&mut writer)?;
+            writer.flush()?;
+        }
+        let Some(run_summary) = self.run_summary else {
+            return Ok(Annotated {
+                matched_regions: self.matched_regions,
+                run_summary: None,
+            });
+        };
+        if let Some(path) = &self.summary_path {
+            if is_stdout(path) {
+                eprintln!("Run summary:");
+                run_summary.write(&mut io::stderr())?;
+            } else {
+                let file = File::create(path).context("Failed to create summary file")?;
+                let mut writer = BufWriter::new(file);
+                run_summary.write(&mut writer)?;
+                writer.flush()?;
+            }
+        }
+        if let Some((path, parameters)) = &self.report {
+            let file = File::create(path).context("Failed to create report file")?;
+            let mut writer = BufWriter::new(file);
+            write_html_report(&mut writer, &run_summary, parameters)?;
+            writer.flush()?;
+        }
+        Ok(Annotated {
+            matched_regions: self.matched_regions,
+            run_summary: Some(run_summary),
+        })
+    }
+}
 
-        for region in chunk {
-            // Find genes for chrom
-            if let Some(genes) = gtf_data.genes_by_chrom.get(&region.chrom) {
-                let max_len = *gtf_data.max_lengths.get(&region.chrom).unwrap_or(&0);
+/// Parameters of the run shown in the HTML report and the metadata sidecar.
+fn run_parameters(args: &Args, config: &Config) -> Vec<(&'static str, String)> {
+    let annotation = args.gtf.as_ref().or(args.features.as_ref());
+    let rules: Vec<&str> = config.rules.iter().map(|area| area.as_str()).collect();
+    vec![
+        ("Command", std::env::args().collect::<Vec<_>>().join(" ")),
+        ("Version", env!("CARGO_PKG_VERSION").to_string()),
+        (
+            "Annotation",
+            annotation.map_or("NA".to_string(), |p| p.display().to_string()),
+        ),
+        (
+            "Regions",
+            args.manifest
+                .as_ref()
+                .unwrap_or(&args.bed)
+                .display()
+                .to_string(),
+        ),
+        ("Report level", config.level.as_str().to_string()),
+        ("Model", args.model.clone()),
+        ("Rules", rules.join(",")),
+        ("PercArea", config.perc_area.to_string()),
+        ("PercRegion", config.perc_region.to_string()),
+        ("TSS (bp)", config.tss.to_string()),
+        ("TTS (bp)", config.tts.to_string()),
+        ("Promoter (bp)", config.promoter.to_string()),
+        ("Distance (bp)", config.distance.to_string()),
+    ]
+}
 
-                // Calculate safe search start (region start - max_len - distance)
-                // Note: we must match the logic in match_regions_to_genes regarding max_lookback
-                let max_lookback = max_len + config.max_lookback_distance();
-                let search_start = region.start.saturating_sub(max_lookback);
+/// Input files of the run, hashed for the metadata sidecar.
+fn run_inputs(args: &Args) -> Result<Vec<InputFile>> {
+    // The files matching a -b pattern, or the BED file
+    let beds: Vec<&PathBuf> = match args.bed_files.as_slice() {
+        [] => args.bed_arg.iter().collect(),
+        files => files.iter().collect(),
+    };
+    let inputs = [
+        ("gtf", args.gtf.as_ref()),
+        ("features", args.features.as_ref()),
+    ]
+    .into_iter()
+    .chain(beds.into_iter().map(|path| ("bed", Some(path))))
+    .chain([
+        ("manifest", args.manifest.as_ref()),
+        ("config", args.config.as_ref()),
+        ("links", args.links.as_ref()),
+        ("diff_gtf", args.diff_gtf.as_ref()),
+        ("compare_bed", args.compare_bed.as_ref()),
+        ("exclude", args.exclude.as_ref()),
+        ("biotype_rules", args.biotype_rules.as_ref()),
+        ("chrom_sizes", args.chrom_sizes.as_ref()),
+    ]);
+    inputs
+        .filter_map(|(role, path)| path.map(|path| InputFile::from_path(role, path)))
+        .collect()
+}
 
-                let start_index = if region.chrom == last_chrom && region.start >= last_start {
-                    // Optimistic: advance from last_index
-                    let mut idx = last_index;
-                    // Skip genes that end before search_start
-                    while idx < genes.len() && genes[idx].end < search_start {
-                        idx += 1;
-                    }
-                    idx
-                } else {
-                    // Reset / Binary search
-                    find_search_start_index(genes, search_start)
-                };
+/// Write the associations of a region, or add them to the gene summary, and
+/// record them in the side outputs. Returns the number of lines written.
+fn write_region(
+    writer: &mut OutputFile,
+    summary: Option<&mut GeneSummary>,
+    side: &mut SideOutputs,
+    region: &Region,
+    candidates: &[Candidate],
+    options: &OutputOptions,
+) -> Result<usize> {
+    side.add(region, candidates)?;
+    if let Some(summary) = summary {
+        summary.add(region, candidates);
+        return Ok(0);
+    }
+    write_associations(writer, region, candidates, options)
+}
 
-                // Update cache
-                last_chrom = region.chrom.clone();
-                last_start = region.start;
-                last_index = start_index;
+/// Write the associations of a region in the layout of the output options.
+/// Returns the number of lines written.
+fn write_associations(
+    writer: &mut OutputFile,
+    region: &Region,
+    candidates: &[Candidate],
+    options: &OutputOptions,
+) -> Result<usize> {
+    if options.wide.is_some() {
+        let Some(first) = candidates.first() else {
+            return Ok(0);
+        };
+        let line = format_wide_line(region, candidates, options);
+        writer.write_row(region, first, line)?;
+        return Ok(1);
+    }
+    for candidate in candidates {
+        let line = format_output_line_with(region, candidate, options);
+        writer.write_row(region, candidate, line)?;
+    }
+    Ok(candidates.len())
+}
 
-                // Match
-                let candidates = match_region_to_genes(&region, genes, config, start_index);
-                let processed = process_candidates_for_output(candidates, config);
+/// Results of already annotated regions, reused for exact duplicates.
+///
+/// Only the regions of the current chromosome are kept, which bounds memory
+/// while still catching the duplicates of sorted or consolidated files.
+struct DuplicateCache {
+    enabled: bool,
+    chrom: String,
+    results: AHashMap<DuplicateKey, Vec<Candidate>>,
+}
 
-                // Write line
-                for candidate in processed {
-                    let line = format_output_line(&region, &candidate);
-                    writeln!(writer, "{}", line)?;
-                }
-            } else {
-                // If chromosome not in GTF, verify if we should reset cache?
-                // Probably yes to be safe, though chrom changed so next valid chrom will trigger binary search.
-                last_chrom = region.chrom.clone();
-            }
+impl DuplicateCache {
+    fn new(mode: DuplicateMode) -> Self {
+        DuplicateCache {
+            enabled: mode == DuplicateMode::Replicate,
+            chrom: String::new(),
+            results: AHashMap::new(),
         }
     }
 
-    if !header_written {
-        // File was empty
-        write_header(&mut writer, 0)?;
+    fn get(&self, region: &Region, config: &Config) -> Option<Vec<Candidate>> {
+        if !self.enabled || region.chrom != self.chrom {
+            return None;
+        }
+        self.results.get(&config.duplicate_key(region)).cloned()
     }
 
-    writer.flush()?;
-    Ok(())
+    fn insert(&mut self, region: &Region, config: &Config, candidates: &[Candidate]) {
+        if !self.enabled {
+            return;
+        }
+        if region.chrom != self.chrom {
+            self.results.clear();
+            self.chrom = region.chrom.clone();
+        }
+        self.results
+            .insert(config.duplicate_key(region), candidates.to_vec());
+    }
 }
 
 /// Work item for the parallel pipeline.
@@ -414,14 +3631,104 @@ struct WorkItem {
     seq_id: u64,
     /// Regions to process (all from same chromosome, in file order).
     regions: Vec<Region>,
+    /// Offset in the BED file after the last region.
+    bed_offset: u64,
 }
 
 /// Result from processing a work item.
+#[derive(Serialize, Deserialize)]
 struct WorkResult {
     /// Sequence number matching the input WorkItem.
     seq_id: u64,
     /// Processing results in the same order as input regions.
     results: Vec<(Region, Vec<Candidate>)>,
+    /// Offset in the BED file after the last region.
+    bed_offset: u64,
+    /// Output lines of the results, if formatted ahead of the writer.
+    #[serde(default)]
+    lines: Option<Vec<String>>,
+}
+
+/// Capacity of the result channel: results waiting to be taken by the
+/// writer, fewer when their memory is limited.
+fn result_channel_bound(config: &Config, num_threads: usize) -> usize {
+    match config.max_memory {
+        Some(_) => 2 * num_threads,
+        None => RESULT_CHANNEL_BOUND,
+    }
+}
+
+/// Log the stage timings and throughput of a run (--perf).
+fn log_perf(metrics: &RunMetrics) {
+    info!(
+        "Processed {} regions ({:.0}/s) and wrote {} lines ({:.0}/s) in {:.2} s",
+        metrics.regions_processed,
+        metrics.regions_per_second,
+        metrics.lines_written,
+        metrics.lines_per_second,
+        metrics.elapsed_seconds
+    );
+    let threads = if metrics.threads > 1 {
+        format!(" (summed over {} threads)", metrics.threads)
+    } else {
+        String::new()
+    };
+    info!(
+        "Matching {:.2} s, waiting on the result channel {:.2} s, formatting {:.2} s, writing {:.2} s{}",
+        metrics.worker_matching_seconds,
+        metrics.worker_channel_wait_seconds,
+        metrics.writer_format_seconds,
+        metrics.writer_io_seconds,
+        threads
+    );
+    if metrics.result_channel_bound > 0 {
+        info!(
+            "Largest backlog of results to write in order: {} (channel capacity {})",
+            metrics.max_pending_results, metrics.result_channel_bound
+        );
+    }
+    if let Some(bytes) = metrics.peak_rss_bytes {
+        info!("Peak memory: {:.1} MB", bytes as f64 / 1e6);
+    }
+}
+
+/// Approximate size of a work result in memory, counted against --max-memory.
+fn result_bytes(result: &WorkResult) -> u64 {
+    let strings = |strings: &[&str]| strings.iter().map(|s| s.len()).sum::<usize>();
+    let bytes: usize = result
+        .results
+        .iter()
+        .map(|(region, candidates)| {
+            let candidates: usize = candidates
+                .iter()
+                .map(|candidate| {
+                    std::mem::size_of::<Candidate>()
+                        + strings(&[
+                            &candidate.exon_number,
+                            &candidate.transcript,
+                            &candidate.gene,
+                            candidate.tss_distances.as_deref().unwrap_or(""),
+                        ])
+                })
+                .sum();
+            let metadata: usize = region
+                .metadata
+                .iter()
+                .map(|field| std::mem::size_of::<String>() + field.len())
+                .sum();
+            std::mem::size_of::<(Region, Vec<Candidate>)>()
+                + region.chrom.len()
+                + metadata
+                + candidates
+        })
+        .sum();
+    let lines: usize = result
+        .lines
+        .iter()
+        .flatten()
+        .map(|line| std::mem::size_of::<String>() + line.len())
+        .sum();
+    (std::mem::size_of::<WorkResult>() + bytes + lines) as u64
 }
 
 /// Parallel implementation using per-chromosome work distribution.
@@ -430,31 +3737,72 @@ struct WorkResult {
 /// 1. Parse the entire BED file and group regions by chromosome
 /// 2. Distribute chromosomes to workers (each chromosome is one work item)
 /// 3. Write results in sorted chromosome order
-fn run_parallel(args: &Args, gtf_data: GtfData, config: &Config, num_threads: usize) -> Result<()> {
-    eprintln!("Using parallel mode with {} threads", num_threads);
-
-    // Create performance metrics
-    let metrics = Arc::new(PerfMetrics::new());
+fn run_parallel(
+    args: &Args,
+    gtf_data: Arc<GtfData>,
+    index: Option<Arc<dyn RegionIndex>>,
+    links: Option<Arc<LinkIndex>>,
+    config: &Config,
+    num_threads: usize,
+    metrics: &Arc<PerfMetrics>,
+) -> Result<Annotated> {
+    debug!("Using parallel mode with {} threads", num_threads);
 
     // Create channels
     let (work_tx, work_rx): (Sender<WorkItem>, Receiver<WorkItem>) = bounded(100);
     // Increased buffer for results to avoid blocking workers
-    let (result_tx, result_rx): (Sender<WorkResult>, Receiver<WorkResult>) = bounded(2000);
+    let (result_tx, result_rx): (Sender<WorkResult>, Receiver<WorkResult>) =
+        bounded(result_channel_bound(config, num_threads));
 
-    // Shared GTF data for workers
-    let gtf_arc = Arc::new(gtf_data);
     let config_arc = Arc::new(config.clone());
 
     // Spawn writer thread
-    let output_path = args.output.clone();
+    let mut options = OutputOptions::from_config(config);
+    options.set_annotation(&gtf_data);
+    let checkpointer = Checkpointer::start(args, config, &options)?;
+    let resumed = Checkpointer::resumed(&checkpointer);
+    let writer = match &resumed {
+        Some(checkpoint) => OutputFile::resume(&args.output, config, checkpoint.output_bytes)?,
+        None => OutputFile::create(&args.output, config)?,
+    };
+    let formatting_options = Arc::new(options.clone());
+    let summary = config
+        .gene_summary
+        .then(|| GeneSummary::from_config(config));
+    let mut side = SideOutputs::new(args, config, &options)?;
+    if let Some(checkpoint) = &resumed {
+        side.matched_regions = checkpoint.matched_regions as usize;
+    }
+    let pending = if config.unordered {
+        ReorderBuffer::unordered()
+    } else {
+        ReorderBuffer::new(config.max_memory.map(|max_memory| max_memory.0))
+    };
+
+    // Spilled results name custom zones, which must be known to read them back
+    let zones: Vec<Zone> = std::iter::once(config)
+        .chain(&config.rule_sets)
+        .flat_map(|config| config.zones.iter().cloned())
+        .collect();
 
     let (header_tx, header_rx) = bounded(1);
 
     let writer_handle = thread::spawn({
-        let result_rx = result_rx.clone();
-        let metrics = Arc::clone(&metrics);
-        move || -> Result<usize> {
-            write_results_ordered(&output_path, result_rx, header_rx, &metrics)
+        let metrics = Arc::clone(metrics);
+        move || -> Result<(usize, Annotated)> {
+            Area::with_zones(&zones, || {
+                write_results_ordered(
+                    writer,
+                    result_rx,
+                    header_rx,
+                    &options,
+                    summary,
+                    side,
+                    pending,
+                    checkpointer,
+                    &metrics,
+                )
+            })
         }
     });
 
@@ -464,41 +3812,86 @@ fn run_parallel(args: &Args, gtf_data: GtfData, config: &Config, num_threads: us
         .build()
         .context("Failed to create thread pool")?;
 
-    // Clone references for the worker scope
-    let gtf_for_workers = Arc::clone(&gtf_arc);
-    let config_for_workers = Arc::clone(&config_arc);
-    let work_rx_for_workers = work_rx.clone();
+    let pipeline = Arc::new(Pipeline {
+        gtf: Arc::clone(&gtf_data),
+        index: index.clone(),
+        links: links.clone(),
+        config: Arc::clone(&config_arc),
+        options: Arc::clone(&formatting_options),
+        metrics: Arc::clone(metrics),
+        load: Arc::default(),
+    });
+    let load = Arc::clone(&pipeline.load);
+    // Moved rather than cloned, so that the producer stops if the workers do
+    let work_rx_for_workers = work_rx;
     let result_tx_for_workers = result_tx.clone();
-    let metrics_for_workers = Arc::clone(&metrics);
+    let split = ThreadSplit::new(config, num_threads);
+    debug!("Splitting the threads: {:?}", split);
 
     // Spawn workers in a separate thread to avoid blocking
     let workers_handle = thread::spawn(move || {
-        pool.scope(|s| {
-            for _ in 0..num_threads {
-                let work_rx = work_rx_for_workers.clone();
-                let result_tx = result_tx_for_workers.clone();
-                let gtf = Arc::clone(&gtf_for_workers);
-                let cfg = Arc::clone(&config_for_workers);
-                let metrics = Arc::clone(&metrics_for_workers);
-
-                s.spawn(move |_| {
-                    worker_loop(work_rx, result_tx, gtf, cfg, &metrics);
-                });
+        pool.scope(|s| match split {
+            ThreadSplit::Matching => {
+                for _ in 0..num_threads {
+                    let work_rx = work_rx_for_workers.clone();
+                    let result_tx = result_tx_for_workers.clone();
+                    let pipeline = Arc::clone(&pipeline);
+                    s.spawn(move |_| worker_loop(work_rx, result_tx, &pipeline));
+                }
+            }
+            ThreadSplit::Fixed { matching, io } => {
+                let (matched_tx, matched_rx) = bounded(RESULT_CHANNEL_BOUND);
+                for _ in 0..matching {
+                    let work_rx = work_rx_for_workers.clone();
+                    let matched_tx = matched_tx.clone();
+                    let pipeline = Arc::clone(&pipeline);
+                    s.spawn(move |_| worker_loop(work_rx, matched_tx, &pipeline));
+                }
+                for _ in 0..io {
+                    let matched_rx = matched_rx.clone();
+                    let result_tx = result_tx_for_workers.clone();
+                    let pipeline = Arc::clone(&pipeline);
+                    s.spawn(move |_| formatter_loop(matched_rx, result_tx, &pipeline));
+                }
+            }
+            ThreadSplit::Adaptive => {
+                // Unbounded, as the threads format whenever the backlog grows
+                let (matched_tx, matched_rx) = unbounded();
+                for _ in 0..num_threads {
+                    let work_rx = work_rx_for_workers.clone();
+                    let matched = (matched_tx.clone(), matched_rx.clone());
+                    let result_tx = result_tx_for_workers.clone();
+                    let pipeline = Arc::clone(&pipeline);
+                    s.spawn(move |_| {
+                        adaptive_loop(work_rx, matched, result_tx, num_threads, &pipeline)
+                    });
+                }
             }
         });
     });
 
     // Producer: Read BED in chunks
-    eprintln!("Processing BED file: {}", args.bed.display());
-    let mut bed_reader = BedReader::new(&args.bed)?;
+    info!("Processing BED file: {}", args.bed.display());
+    let mut bed_reader = match &resumed {
+        Some(checkpoint) => BedReader::resume(&args.bed, checkpoint.bed_offset)?,
+        None => open_bed(&args.bed, args, config)?,
+    };
 
     let mut global_seq_id = 0;
+    let mut batch_size = BatchSize::new(args);
+    let channel_bound = result_channel_bound(config, num_threads);
+    let mut batch_range = (usize::MAX, 0);
 
     // Send header info immediately if possible? No, header depends on first line read usually.
     // BedReader logic: read_chunk updates num_meta_columns.
     // So we need to read first chunk.
 
-    while let Some(chunk) = bed_reader.read_chunk(args.batch_size)? {
+    loop {
+        let size = batch_size.next(&load, result_tx.len(), channel_bound);
+        let Some(chunk) = bed_reader.read_chunk(size)? else {
+            break;
+        };
+        batch_range = (batch_range.0.min(size), batch_range.1.max(size));
         if global_seq_id == 0 {
             // Send header info
             let _ = header_tx.send(bed_reader.num_meta_columns());
@@ -507,6 +3900,7 @@ fn run_parallel(args: &Args, gtf_data: GtfData, config: &Config, num_threads: us
         let work_item = WorkItem {
             seq_id: global_seq_id,
             regions: chunk,
+            bed_offset: bed_reader.offset(),
         };
 
         if work_tx.send(work_item).is_err() {
@@ -519,6 +3913,12 @@ fn run_parallel(args: &Args, gtf_data: GtfData, config: &Config, num_threads: us
     if global_seq_id == 0 {
         let _ = header_tx.send(0);
     }
+    if matches!(batch_size, BatchSize::Adaptive(_)) && global_seq_id > 0 {
+        debug!(
+            "Sent {} batches of {} to {} regions",
+            global_seq_id, batch_range.0, batch_range.1
+        );
+    }
 
     // Close work channel to signal workers to exit
     drop(work_tx);
@@ -533,88 +3933,339 @@ fn run_parallel(args: &Args, gtf_data: GtfData, config: &Config, num_threads: us
     drop(result_tx);
 
     // Wait for writer and get the results
-    let lines_written = writer_handle
+    let (lines_written, annotated) = writer_handle
         .join()
         .map_err(|_| anyhow::anyhow!("Writer thread panicked"))??;
 
-    eprintln!(
+    info!(
         "Writing output to: {} ({} lines)",
         args.output.display(),
         lines_written
     );
 
-    // Print performance metrics
-    metrics.print_summary();
+    Ok(annotated)
+}
 
-    Ok(())
+/// Split of the threads of the parallel pipeline between its stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThreadSplit {
+    /// Every thread matches regions; the writer formats the output.
+    Matching,
+    /// Threads match regions or format their output lines.
+    Fixed { matching: usize, io: usize },
+    /// Every thread matches regions, or formats output lines while the
+    /// matched results pile up.
+    Adaptive,
 }
 
-/// Worker loop: receives work items and sends results.
-fn worker_loop(
-    work_rx: Receiver<WorkItem>,
-    result_tx: Sender<WorkResult>,
+impl ThreadSplit {
+    fn new(config: &Config, num_threads: usize) -> Self {
+        // The gene summary has no output lines to format
+        if config.gene_summary {
+            return ThreadSplit::Matching;
+        }
+        match config.io_threads {
+            // --threads 0 leaves at least one thread matching
+            Some(io) => ThreadSplit::Fixed {
+                matching: num_threads.saturating_sub(io).max(1),
+                io,
+            },
+            None if config.adaptive_threads => ThreadSplit::Adaptive,
+            None => ThreadSplit::Matching,
+        }
+    }
+}
+
+/// Associations per batch that adapted batches aim for.
+const BATCH_ASSOCIATIONS: f64 = 100_000.0;
+
+/// Smallest and largest adapted batch, in regions.
+const BATCH_SIZE_RANGE: (usize, usize) = (100, 20_000);
+
+/// Regions matched, and their associations, since the size of the next
+/// batch was last set.
+#[derive(Default)]
+struct BatchLoad {
+    regions: AtomicU64,
+    associations: AtomicU64,
+}
+
+impl BatchLoad {
+    fn add(&self, results: &[(Region, Vec<Candidate>)]) {
+        let associations: usize = results.iter().map(|(_, candidates)| candidates.len()).sum();
+        self.regions
+            .fetch_add(results.len() as u64, Ordering::Relaxed);
+        self.associations
+            .fetch_add(associations as u64, Ordering::Relaxed);
+    }
+
+    /// Take the regions and associations counted so far.
+    fn take(&self) -> (u64, u64) {
+        (
+            self.regions.swap(0, Ordering::Relaxed),
+            self.associations.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+/// Size of the batches of regions sent to the threads.
+///
+/// Without --batch-size, batches aim for a steady number of associations
+/// rather than of regions, as a region among dense promoters can have
+/// hundreds where most have a few. They start small and change at most
+/// twofold per batch with the associations per region of the latest
+/// matched batches, and halve while the results wait on the writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchSize {
+    Fixed(usize),
+    Adaptive(usize),
+}
+
+impl BatchSize {
+    fn new(args: &Args) -> Self {
+        match args.batch_size {
+            Some(size) => BatchSize::Fixed(size),
+            None => BatchSize::Adaptive(BATCH_SIZE_RANGE.0),
+        }
+    }
+
+    /// Size of the next batch, from the regions matched since the last one
+    /// and the results waiting in the result channel of capacity `bound`.
+    fn next(&mut self, load: &BatchLoad, backlog: usize, bound: usize) -> usize {
+        let size = match self {
+            BatchSize::Fixed(size) => return *size,
+            BatchSize::Adaptive(size) => size,
+        };
+        let (regions, associations) = load.take();
+        let mut next = *size;
+        if regions > 0 {
+            let per_region = (associations as f64 / regions as f64).max(1.0);
+            next = ((BATCH_ASSOCIATIONS / per_region) as usize).clamp(*size / 2, *size * 2);
+        }
+        // The writer falls behind: smaller batches hold less memory
+        if backlog * 2 >= bound {
+            next = next.min(*size / 2);
+        }
+        *size = next.clamp(BATCH_SIZE_RANGE.0, BATCH_SIZE_RANGE.1);
+        *size
+    }
+}
+
+/// Inputs shared by the threads of the parallel pipeline.
+struct Pipeline {
     gtf: Arc<GtfData>,
+    index: Option<Arc<dyn RegionIndex>>,
+    links: Option<Arc<LinkIndex>>,
     config: Arc<Config>,
-    metrics: &PerfMetrics,
-) {
-    // Optimization state per worker
-    let mut last_chrom = String::new();
-    let mut last_start = -1;
-    let mut last_index = 0;
+    options: Arc<OutputOptions>,
+    metrics: Arc<PerfMetrics>,
+    /// Matched regions, for the size of the next batch.
+    load: Arc<BatchLoad>,
+}
 
-    while let Ok(work_item) = work_rx.recv() {
-        let num_regions = work_item.regions.len() as u64;
+/// Optimization state of a thread matching work items, kept between them.
+struct MatchState {
+    last_chrom: String,
+    last_start: i64,
+    last_index: usize,
+    duplicates: DuplicateCache,
+}
 
-        // Time the matching work
-        let match_start = Instant::now();
-        let results = process_work_item(
-            &work_item,
-            &gtf,
-            &config,
-            &mut last_chrom,
-            &mut last_start,
-            &mut last_index,
-        );
-        let match_elapsed = match_start.elapsed();
-        metrics.add_worker_matching(match_elapsed.as_nanos() as u64);
-        metrics.add_regions_processed(num_regions);
+impl MatchState {
+    fn new(config: &Config) -> Self {
+        MatchState {
+            last_chrom: String::new(),
+            last_start: -1,
+            last_index: 0,
+            duplicates: DuplicateCache::new(config.duplicates),
+        }
+    }
+}
 
-        let work_result = WorkResult {
-            seq_id: work_item.seq_id,
-            results,
-        };
+/// Match the regions of a work item, timing the matching work.
+fn match_work_item(work_item: WorkItem, pipeline: &Pipeline, state: &mut MatchState) -> WorkResult {
+    let num_regions = work_item.regions.len() as u64;
+    let match_start = pipeline.metrics.start();
+    let results = process_work_item(
+        &work_item,
+        &pipeline.gtf,
+        pipeline.index.as_deref(),
+        pipeline.links.as_deref(),
+        &pipeline.config,
+        &mut state.last_chrom,
+        &mut state.last_start,
+        &mut state.last_index,
+        &mut state.duplicates,
+    );
+    pipeline.metrics.add_worker_matching(match_start);
+    pipeline.load.add(&results);
+    pipeline.metrics.add_regions_processed(num_regions);
+
+    WorkResult {
+        seq_id: work_item.seq_id,
+        results,
+        bed_offset: work_item.bed_offset,
+        lines: None,
+    }
+}
+
+/// Format the output lines of a work result ahead of the writer.
+fn format_work_result(result: &mut WorkResult, pipeline: &Pipeline) {
+    let options = &pipeline.options;
+    let format_start = pipeline.metrics.start();
+    let mut lines = Vec::new();
+    for (region, candidates) in &result.results {
+        if options.wide.is_some() {
+            if !candidates.is_empty() {
+                lines.push(format_wide_line(region, candidates, options));
+            }
+            continue;
+        }
+        for candidate in candidates {
+            lines.push(format_output_line_with(region, candidate, options));
+        }
+    }
+    pipeline.metrics.add_writer_format(format_start);
+    result.lines = Some(lines);
+}
+
+/// Send a result on, timing how long the channel is full. Fails when the
+/// receiver stopped.
+fn send_result(result_tx: &Sender<WorkResult>, result: WorkResult, metrics: &PerfMetrics) -> bool {
+    let send_start = metrics.start();
+    let send_result = result_tx.send(result);
+    metrics.add_worker_channel_wait(send_start);
+    send_result.is_ok()
+}
+
+/// Worker loop: receives work items and sends results.
+fn worker_loop(work_rx: Receiver<WorkItem>, result_tx: Sender<WorkResult>, pipeline: &Pipeline) {
+    let mut state = MatchState::new(&pipeline.config);
+    while let Ok(work_item) = work_rx.recv() {
+        let work_result = match_work_item(work_item, pipeline, &mut state);
+        if !send_result(&result_tx, work_result, &pipeline.metrics) {
+            break;
+        }
+    }
+}
 
-        // Time the channel send (how long we wait if channel is full)
-        let send_start = Instant::now();
-        let send_result = result_tx.send(work_result);
-        let send_elapsed = send_start.elapsed();
-        metrics.add_worker_channel_wait(send_elapsed.as_nanos() as u64);
+/// Formatter loop: receives matched results and sends them formatted.
+fn formatter_loop(
+    matched_rx: Receiver<WorkResult>,
+    result_tx: Sender<WorkResult>,
+    pipeline: &Pipeline,
+) {
+    while let Ok(mut result) = matched_rx.recv() {
+        format_work_result(&mut result, pipeline);
+        if !send_result(&result_tx, result, &pipeline.metrics) {
+            break;
+        }
+    }
+}
 
-        if send_result.is_err() {
+/// Adaptive loop: matches work items, or formats matched results first
+/// once `backlog` of them wait, so that the writer is not starved.
+fn adaptive_loop(
+    work_rx: Receiver<WorkItem>,
+    (matched_tx, matched_rx): (Sender<WorkResult>, Receiver<WorkResult>),
+    result_tx: Sender<WorkResult>,
+    backlog: usize,
+    pipeline: &Pipeline,
+) {
+    let mut state = MatchState::new(&pipeline.config);
+    let mut matched_tx = Some(matched_tx);
+    loop {
+        let matching = matched_tx.is_some();
+        let matched = if !matching {
+            // Matching is done once every thread dropped its sender
+            match matched_rx.recv() {
+                Ok(result) => Some(result),
+                Err(_) => break,
+            }
+        } else if matched_rx.len() >= backlog {
+            matched_rx.try_recv().ok()
+        } else {
+            None
+        };
+        let result = match matched {
+            Some(mut result) => {
+                format_work_result(&mut result, pipeline);
+                result
+            }
+            None => {
+                crossbeam_channel::select! {
+                    recv(work_rx) -> work_item => {
+                        let Ok(work_item) = work_item else {
+                            matched_tx = None;
+                            continue;
+                        };
+                        let result = match_work_item(work_item, pipeline, &mut state);
+                        if matched_tx.as_ref().map_or(true, |tx| tx.send(result).is_err()) {
+                            break;
+                        }
+                        continue;
+                    }
+                    recv(matched_rx) -> result => {
+                        let Ok(mut result) = result else { break };
+                        format_work_result(&mut result, pipeline);
+                        result
+                    }
+                }
+            }
+        };
+        if !send_result(&result_tx, result, &pipeline.metrics) {
             break;
         }
     }
 }
 
 /// Process a single work item (a chunk of regions).
+#[allow(clippy::too_many_arguments)]
 fn process_work_item(
     work_item: &WorkItem,
     gtf: &GtfData,
+    index: Option<&dyn RegionIndex>,
+    links: Option<&LinkIndex>,
     config: &Config,
     last_chrom: &mut String,
     last_start: &mut i64,
     last_index: &mut usize,
+    duplicates: &mut DuplicateCache,
 ) -> Vec<(Region, Vec<Candidate>)> {
     let mut results = Vec::with_capacity(work_item.regions.len());
 
     for region in &work_item.regions {
+        if let Some(processed) = duplicates.get(region, config) {
+            results.push((region.clone(), processed));
+            continue;
+        }
+
+        let add_links = |processed: &mut Vec<Candidate>| {
+            if let Some(links) = links {
+                links.add_links(region, processed);
+            }
+        };
+
+        if let Some(index) = index {
+            let mut processed = index.annotate_region(region, config);
+            add_links(&mut processed);
+            duplicates.insert(region, config, &processed);
+            results.push((region.clone(), processed));
+            continue;
+        }
+
         if let Some(genes) = gtf.genes_by_chrom.get(&region.chrom) {
             let max_len = *gtf.max_lengths.get(&region.chrom).unwrap_or(&0);
 
-            let max_lookback = max_len + config.max_lookback_distance();
+            // Regions with their own, larger, distance cannot reuse the cached index
+            let lookback = config.region_lookback_distance(region);
+            let max_lookback = max_len + lookback;
             let search_start = region.start.saturating_sub(max_lookback);
 
-            let start_index = if *last_chrom == region.chrom && region.start >= *last_start {
+            let start_index = if *last_chrom == region.chrom
+                && region.start >= *last_start
+                && lookback <= config.max_lookback_distance()
+            {
                 let mut idx = *last_index;
                 while idx < genes.len() && genes[idx].end < search_start {
                     idx += 1;
@@ -628,8 +4279,9 @@ fn process_work_item(
             *last_start = region.start;
             *last_index = start_index;
 
-            let candidates = match_region_to_genes(region, genes, config, start_index);
-            let processed = process_candidates_for_output(candidates, config);
+            let mut processed = annotate_region(region, genes, config, start_index);
+            add_links(&mut processed);
+            duplicates.insert(region, config, &processed);
             results.push((region.clone(), processed));
         } else {
             // Chromosome not found, but we must record it in output as processed (with empty candidates)
@@ -644,6 +4296,14 @@ fn process_work_item(
             // Actually, if a region has no matches, it produces no output lines.
             // So skipping here is fine.
             *last_chrom = region.chrom.clone();
+            warn_missing_chrom(&region.chrom);
+
+            // Unless unmatched regions are reported explicitly, or linked.
+            let mut processed = annotate_region(region, &[], config, 0);
+            add_links(&mut processed);
+            if !processed.is_empty() {
+                results.push((region.clone(), processed));
+            }
         }
     }
 
@@ -651,63 +4311,110 @@ fn process_work_item(
 }
 
 /// Write results in order, buffering out-of-order results.
+#[allow(clippy::too_many_arguments)]
 fn write_results_ordered(
-    output_path: &PathBuf,
+    mut writer: OutputFile,
     result_rx: Receiver<WorkResult>,
     header_rx: Receiver<usize>,
+    options: &OutputOptions,
+    mut summary: Option<GeneSummary>,
+    mut side: SideOutputs,
+    mut pending: ReorderBuffer<WorkResult>,
+    mut checkpointer: Option<Checkpointer>,
     metrics: &PerfMetrics,
-) -> Result<usize> {
-    let file = File::create(output_path).context("Failed to create output file")?;
-    let mut writer = BufWriter::new(file);
-
+) -> Result<(usize, Annotated)> {
+    let resumed = Checkpointer::resumed(&checkpointer);
     // Get header info (blocking until first chunk read or empty file)
     let num_meta_columns = header_rx.recv().unwrap_or(0);
-    write_header(&mut writer, num_meta_columns)?;
+    // The gene summary writes its own header at the end
+    if summary.is_none() && resumed.is_none() {
+        write_header_with(&mut writer, num_meta_columns, options)?;
+        side.write_header(num_meta_columns)?;
+        if let Some(checkpointer) = checkpointer.as_mut() {
+            checkpointer.set_header(num_meta_columns, options)?;
+        }
+    }
 
-    // Buffer for out-of-order results using VecDeque for O(1) operations
-    // Since seq_id is dense sequential integers starting from 0, we use
-    // index = seq_id - next_expected to map into the deque
-    let mut pending: VecDeque<Option<WorkResult>> = VecDeque::new();
-    let mut next_expected: u64 = 0;
-    let mut lines_written: usize = 0;
+    // Out-of-order results wait in `pending` until the results before them
+    // are written, spilled to disk beyond --max-memory (with --unordered,
+    // they are written as they arrive)
+    let mut lines_written = resumed.map_or(0, |checkpoint| checkpoint.lines_written as usize);
 
     for result in result_rx {
-        // Insert at the correct position based on seq_id offset
-        let index = (result.seq_id - next_expected) as usize;
-        // Ensure VecDeque is large enough
-        while pending.len() <= index {
-            pending.push_back(None);
-        }
-        pending[index] = Some(result);
+        let bytes = result_bytes(&result);
+        pending.insert(result.seq_id, result, bytes)?;
 
         // Track max pending size for congestion analysis
-        metrics.update_max_pending(pending.len());
+        metrics.update_max_pending(pending.pending_len());
 
         // Write all ready consecutive results from the front
-        while matches!(pending.front(), Some(Some(_))) {
-            let r = pending.pop_front().unwrap().unwrap();
+        while let Some(mut r) = pending.pop()? {
+            // Lines formatted ahead of the writer, in the order written
+            let mut lines = r.lines.take().map(Vec::into_iter);
             for (region, candidates) in &r.results {
+                side.add(region, candidates)?;
+                if let Some(summary) = summary.as_mut() {
+                    summary.add(region, candidates);
+                    continue;
+                }
+                if options.wide.is_some() {
+                    if let Some(first) = candidates.first() {
+                        let line = lines
+                            .as_mut()
+                            .and_then(Iterator::next)
+                            .unwrap_or_else(|| format_wide_line(region, candidates, options));
+                        writer.write_row(region, first, line)?;
+                        lines_written += 1;
+                    }
+                    continue;
+                }
                 for candidate in candidates {
-                    // Time formatting
-                    let format_start = Instant::now();
-                    let line = format_output_line(region, candidate);
-                    let format_elapsed = format_start.elapsed();
-                    metrics.add_writer_format(format_elapsed.as_nanos() as u64);
+                    let line = match lines.as_mut().and_then(Iterator::next) {
+                        Some(line) => line,
+                        None => {
+                            // Time formatting
+                            let format_start = metrics.start();
+                            let line = format_output_line_with(region, candidate, options);
+                            metrics.add_writer_format(format_start);
+                            line
+                        }
+                    };
 
                     // Time I/O
-                    let io_start = Instant::now();
-                    writeln!(writer, "{}", line)?;
-                    let io_elapsed = io_start.elapsed();
-                    metrics.add_writer_io(io_elapsed.as_nanos() as u64);
+                    let io_start = metrics.start();
+                    writer.write_row(region, candidate, line)?;
+                    metrics.add_writer_io(io_start);
 
                     lines_written += 1;
                 }
             }
-            next_expected += 1;
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                checkpointer.add(
+                    &mut writer,
+                    r.bed_offset,
+                    r.results.len(),
+                    lines_written,
+                    side.matched_regions,
+                )?;
+            }
         }
     }
+    if pending.spilled_total() > 0 {
+        debug!(
+            "Spilled {} results to disk beyond --max-memory",
+            pending.spilled_total()
+        );
+    }
+
+    if let Some(summary) = &summary {
+        lines_written = summary.write(&mut writer)?;
+    }
+    let annotated = side.write()?;
 
     metrics.add_lines_written(lines_written as u64);
-    writer.flush()?;
-    Ok(lines_written)
+    writer.finish()?;
+    if let Some(checkpointer) = checkpointer {
+        checkpointer.finish()?;
+    }
+    Ok((lines_written, annotated))
 }
@@ -0,0 +1,166 @@
+//! Cis-window association for point variants (eQTL candidate genes).
+//!
+//! A region is associated with every gene whose TSS lies within a fixed
+//! window around it, regardless of the gene structure, as in the standard
+//! cis-eQTL definition.
+
+use ahash::AHashMap;
+
+use crate::config::Config;
+use crate::matcher::overlap::fill_unmatched;
+use crate::matcher::rules::cap_candidates;
+use crate::matcher::RegionIndex;
+use crate::parser::gtf::GtfData;
+use crate::types::{Area, Candidate, Region, ReportLevel, Strand};
+
+/// TSS of a single gene.
+#[derive(Debug, Clone)]
+pub struct GeneTss {
+    pub gene_id: String,
+    pub strand: Strand,
+    /// Gene TSS (start for positive strand, end for negative strand).
+    pub tss: i64,
+}
+
+/// Gene TSSs of all chromosomes, sorted by position.
+#[derive(Debug, Clone, Default)]
+pub struct CisIndex {
+    tss_by_chrom: AHashMap<String, Vec<GeneTss>>,
+}
+
+impl CisIndex {
+    /// Collect the TSS of every gene in the annotation.
+    pub fn build(gtf_data: &GtfData) -> Self {
+        let mut index = CisIndex::default();
+        for (chrom, genes) in &gtf_data.genes_by_chrom {
+            let mut tss: Vec<GeneTss> = genes
+                .iter()
+                .map(|gene| GeneTss {
+                    gene_id: gene.gene_id.clone(),
+                    strand: gene.strand,
+                    tss: match gene.strand {
+                        Strand::Positive => gene.start,
+                        Strand::Negative => gene.end,
+                    },
+                })
+                .collect();
+            tss.sort_by(|a, b| a.tss.cmp(&b.tss).then(a.gene_id.cmp(&b.gene_id)));
+            index.tss_by_chrom.insert(chrom.clone(), tss);
+        }
+        index
+    }
+
+    /// Get the gene TSSs of a chromosome.
+    pub fn tss(&self, chrom: &str) -> &[GeneTss] {
+        self.tss_by_chrom
+            .get(chrom)
+            .map_or(&[], |tss| tss.as_slice())
+    }
+
+    /// Associate a region with every gene whose TSS lies within `window` bp
+    /// of its distance anchor, closest first.
+    ///
+    /// The TSS distance is signed by the gene strand: negative when the
+    /// region lies upstream of the TSS.
+    pub fn match_region(&self, region: &Region, config: &Config) -> Vec<Candidate> {
+        let genes = self.tss(&region.chrom);
+        let window = config.cis_window;
+
+        // The anchor lies within the region, so TSSs beyond the window of
+        // both region edges cannot be in range
+        let first = genes.partition_point(|g| g.tss < region.start - window);
+        let last = genes.partition_point(|g| g.tss <= region.end + window);
+
+        let mut candidates: Vec<Candidate> = genes[first..last]
+            .iter()
+            .filter_map(|g| {
+                let offset = region.anchor_offset(config.distance_anchor, g.tss);
+                if offset.abs() > window {
+                    return None;
+                }
+                let tss_distance = match g.strand {
+                    Strand::Positive => offset,
+                    Strand::Negative => -offset,
+                };
+                Some(Candidate::new(
+                    g.tss,
+                    g.tss,
+                    g.strand,
+                    "NA".to_string(),
+                    Area::Cis,
+                    "NA".to_string(),
+                    g.gene_id.clone(),
+                    tss_distance.abs(),
+                    100.0,
+                    -1.0,
+                    tss_distance,
+                ))
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| a.distance.cmp(&b.distance).then(a.gene.cmp(&b.gene)));
+        candidates
+    }
+}
+
+impl RegionIndex for CisIndex {
+    /// Associate a region and prepare its candidates for output.
+    fn annotate_region(&self, region: &Region, config: &Config) -> Vec<Candidate> {
+        let mut candidates = self.match_region(region, config);
+        if let Some(max_hits) = config.max_hits.filter(|_| config.level != ReportLevel::All) {
+            candidates = cap_candidates(candidates, max_hits, &config.rules);
+        }
+        fill_unmatched(region, &mut candidates, config);
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Gene;
+
+    fn make_gene(gene_id: &str, strand: Strand, start: i64, end: i64) -> Gene {
+        let mut gene = Gene::new(gene_id.to_string(), strand);
+        gene.set_length(start, end);
+        gene
+    }
+
+    #[test]
+    fn test_match_region_within_window() {
+        let mut gtf_data = GtfData {
+            genes_by_chrom: AHashMap::new(),
+            max_lengths: AHashMap::new(),
+        };
+        gtf_data.genes_by_chrom.insert(
+            "chr1".to_string(),
+            vec![
+                make_gene("A", Strand::Positive, 100_000, 120_000),
+                make_gene("B", Strand::Negative, 130_000, 160_000),
+                make_gene("C", Strand::Positive, 900_000, 950_000),
+            ],
+        );
+        let index = CisIndex::build(&gtf_data);
+        let config = Config {
+            cis_window: 100_000,
+            ..Config::default()
+        };
+
+        let snp = Region::new("chr1".to_string(), 150_000, 150_000, vec![]);
+        let candidates = index.match_region(&snp, &config);
+        let genes: Vec<(&str, i64)> = candidates
+            .iter()
+            .map(|c| (c.gene.as_str(), c.tss_distance))
+            .collect();
+
+        // The SNP lies downstream of both TSSs: 10 kb from B's (160000, minus
+        // strand) and 50 kb from A's
+        assert_eq!(genes, vec![("B", 10_000), ("A", 50_000)]);
+        assert!(candidates.iter().all(|c| c.area == Area::Cis));
+
+        let snp = Region::new("chr1".to_string(), 50_000, 50_000, vec![]);
+        let candidates = index.match_region(&snp, &config);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].tss_distance, -50_000);
+    }
+}
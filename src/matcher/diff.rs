@@ -0,0 +1,144 @@
+//! Comparison of the annotations of a region against two gene annotations.
+//!
+//! The associations of the region under each annotation (e.g. two GENCODE
+//! releases) are reduced to one best area per gene, as for loop anchors, and
+//! every gene gained, lost or matched through a different area is reported.
+
+use crate::matcher::loops::anchor_genes;
+use crate::types::{Area, Candidate};
+
+/// How the association of a region with a gene changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Only associated under the new annotation.
+    Gained,
+    /// Only associated under the old annotation.
+    Lost,
+    /// Associated under both, through different areas.
+    AreaChanged,
+}
+
+impl ChangeKind {
+    /// Convert change kind to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Gained => "GAINED",
+            ChangeKind::Lost => "LOST",
+            ChangeKind::AreaChanged => "AREA_CHANGED",
+        }
+    }
+}
+
+/// Changed association of a region with a gene.
+///
+/// The area is None under the annotation without the association.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotationChange {
+    pub gene: String,
+    pub old_area: Option<Area>,
+    pub new_area: Option<Area>,
+}
+
+impl AnnotationChange {
+    /// Kind of the change.
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.old_area, &self.new_area) {
+            (None, _) => ChangeKind::Gained,
+            (_, None) => ChangeKind::Lost,
+            _ => ChangeKind::AreaChanged,
+        }
+    }
+}
+
+/// Compare the associations of a region under the old and new annotations.
+///
+/// Each gene is represented by its highest priority area according to
+/// `rules`. Lost and changed genes come first, in their old order, followed
+/// by the gained genes in their new order; unchanged genes are omitted.
+pub fn diff_annotations(
+    old: &[Candidate],
+    new: &[Candidate],
+    rules: &[Area],
+) -> Vec<AnnotationChange> {
+    let old_genes = anchor_genes(old, rules);
+    let new_genes = anchor_genes(new, rules);
+
+    let mut changes = Vec::new();
+    for old_gene in &old_genes {
+        let new_area = new_genes
+            .iter()
+            .find(|g| g.gene == old_gene.gene)
+            .map(|g| &g.area);
+        if new_area != Some(&old_gene.area) {
+            changes.push(AnnotationChange {
+                gene: old_gene.gene.clone(),
+                old_area: Some(old_gene.area.clone()),
+                new_area: new_area.cloned(),
+            });
+        }
+    }
+    for new_gene in &new_genes {
+        if !old_genes.iter().any(|g| g.gene == new_gene.gene) {
+            changes.push(AnnotationChange {
+                gene: new_gene.gene.clone(),
+                old_area: None,
+                new_area: Some(new_gene.area.clone()),
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn make_candidate(area: Area, gene: &str) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            "T1".to_string(),
+            gene.to_string(),
+            0,
+            100.0,
+            50.0,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_diff_annotations() {
+        let rules = vec![Area::Tss, Area::Intron, Area::Upstream];
+        let old = vec![
+            make_candidate(Area::Intron, "KEPT"),
+            make_candidate(Area::Upstream, "MOVED"),
+            make_candidate(Area::Intron, "RETIRED"),
+        ];
+        let new = vec![
+            make_candidate(Area::Tss, "NEW"),
+            make_candidate(Area::Intron, "KEPT"),
+            make_candidate(Area::Upstream, "MOVED"),
+            make_candidate(Area::Tss, "MOVED"),
+        ];
+
+        let changes = diff_annotations(&old, &new, &rules);
+        let summary: Vec<(&str, ChangeKind)> = changes
+            .iter()
+            .map(|c| (c.gene.as_str(), c.kind()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("MOVED", ChangeKind::AreaChanged),
+                ("RETIRED", ChangeKind::Lost),
+                ("NEW", ChangeKind::Gained),
+            ]
+        );
+        assert_eq!(changes[0].new_area, Some(Area::Tss));
+        assert!(diff_annotations(&old, &old, &rules).is_empty());
+    }
+}
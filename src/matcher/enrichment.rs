@@ -0,0 +1,311 @@
+//! Enrichment of the annotated areas against a shuffled background.
+//!
+//! The regions are shuffled within their chromosome, keeping their length
+//! and optionally avoiding excluded intervals (assembly gaps, blacklists),
+//! and each background set is annotated like the input. Every region counts
+//! once, through its highest priority area, and the observed counts are
+//! compared with the background counts.
+
+use ahash::AHashMap;
+use anyhow::{bail, Result};
+use indexmap::IndexMap;
+use rand::Rng;
+
+use crate::types::{Area, Candidate, Region};
+
+/// Placement attempts per region before giving up on avoiding the exclusions.
+const MAX_PLACEMENT_ATTEMPTS: usize = 1000;
+
+/// Sorted intervals, per chromosome, that shuffled regions must not overlap.
+#[derive(Debug, Default)]
+pub struct Exclusions {
+    intervals: AHashMap<String, Vec<(i64, i64)>>,
+}
+
+impl Exclusions {
+    /// Build the exclusions from BED regions.
+    pub fn new(regions: Vec<Region>) -> Self {
+        let mut intervals: AHashMap<String, Vec<(i64, i64)>> = AHashMap::new();
+        for region in regions {
+            intervals
+                .entry(region.chrom)
+                .or_default()
+                .push((region.start, region.end));
+        }
+
+        // Merge overlapping intervals so that the ends are sorted too
+        for chrom_intervals in intervals.values_mut() {
+            chrom_intervals.sort_unstable();
+            let mut merged: Vec<(i64, i64)> = Vec::with_capacity(chrom_intervals.len());
+            for &(start, end) in chrom_intervals.iter() {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *chrom_intervals = merged;
+        }
+        Exclusions { intervals }
+    }
+
+    /// Whether `[start, end]` overlaps an excluded interval.
+    pub fn overlaps(&self, chrom: &str, start: i64, end: i64) -> bool {
+        let Some(intervals) = self.intervals.get(chrom) else {
+            return false;
+        };
+        // First interval ending at or after start
+        let i = intervals.partition_point(|&(_, e)| e < start);
+        intervals.get(i).is_some_and(|&(s, _)| s <= end)
+    }
+}
+
+/// Shuffle regions to random positions on their chromosome.
+///
+/// Regions keep their chromosome, length and metadata. Fails if a
+/// chromosome is missing from `chrom_sizes`, if a region is longer than its
+/// chromosome, or if no position avoiding the exclusions is found.
+pub fn shuffle_regions<R: Rng>(
+    regions: &[Region],
+    chrom_sizes: &AHashMap<String, i64>,
+    exclusions: &Exclusions,
+    rng: &mut R,
+) -> Result<Vec<Region>> {
+    let mut shuffled = Vec::with_capacity(regions.len());
+    for region in regions {
+        let Some(&size) = chrom_sizes.get(&region.chrom) else {
+            bail!(
+                "Chromosome {} is missing from the chromosome sizes file.",
+                region.chrom
+            );
+        };
+        let length = region.length();
+        if length > size {
+            bail!(
+                "Region {} is longer than chromosome {}.",
+                region.id(),
+                region.chrom
+            );
+        }
+
+        let placed = (0..MAX_PLACEMENT_ATTEMPTS)
+            .map(|_| rng.gen_range(0..=size - length))
+            .find(|&start| !exclusions.overlaps(&region.chrom, start, start + length - 1));
+        let Some(start) = placed else {
+            bail!(
+                "Could not place region {} outside the excluded intervals.",
+                region.id()
+            );
+        };
+        shuffled.push(Region {
+            start,
+            end: start + length - 1,
+            ..region.clone()
+        });
+    }
+    Ok(shuffled)
+}
+
+/// Highest priority area of a region according to `rules`.
+///
+/// Areas missing from the rules rank last; a region without associations
+/// is INTERGENIC.
+pub fn region_area(candidates: &[Candidate], rules: &[Area]) -> Area {
+    let rank = |area: &Area| rules.iter().position(|a| a == area).unwrap_or(rules.len());
+    candidates
+        .iter()
+        .map(|c| &c.area)
+        .min_by_key(|area| rank(area))
+        .cloned()
+        .unwrap_or(Area::Intergenic)
+}
+
+/// Observed versus expected number of regions in an area.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AreaEnrichment {
+    pub area: Area,
+    pub observed: usize,
+    /// Mean count over the background sets.
+    pub expected: f64,
+    /// Permutation p-value in the direction of the deviation from the
+    /// expected count.
+    pub p_value: f64,
+}
+
+impl AreaEnrichment {
+    /// Ratio of observed to expected counts, None if none are expected.
+    pub fn fold_enrichment(&self) -> Option<f64> {
+        (self.expected > 0.0).then(|| self.observed as f64 / self.expected)
+    }
+}
+
+/// Compare the areas of the regions with those of each background set.
+///
+/// Areas are reported in rule order, followed by any other area found in
+/// the observed or background sets. The p-value of an area is the fraction
+/// of background sets (counting the observed one) with a count at least as
+/// extreme as observed: greater or equal when enriched, lower or equal when
+/// depleted.
+pub fn area_enrichment(
+    observed: &[Area],
+    backgrounds: &[Vec<Area>],
+    rules: &[Area],
+) -> Vec<AreaEnrichment> {
+    let count = |areas: &[Area]| {
+        let mut counts: AHashMap<Area, usize> = AHashMap::new();
+        for area in areas {
+            *counts.entry(area.clone()).or_insert(0) += 1;
+        }
+        counts
+    };
+
+    let mut areas: IndexMap<Area, ()> = rules.iter().map(|a| (a.clone(), ())).collect();
+    for area in observed.iter().chain(backgrounds.iter().flatten()) {
+        areas.entry(area.clone()).or_insert(());
+    }
+
+    let observed_counts = count(observed);
+    let background_counts: Vec<AHashMap<Area, usize>> =
+        backgrounds.iter().map(|b| count(b)).collect();
+
+    areas
+        .keys()
+        .map(|area| {
+            let observed = observed_counts.get(area).copied().unwrap_or(0);
+            let counts: Vec<usize> = background_counts
+                .iter()
+                .map(|c| c.get(area).copied().unwrap_or(0))
+                .collect();
+            let expected = counts.iter().sum::<usize>() as f64 / counts.len().max(1) as f64;
+            let extreme = counts
+                .iter()
+                .filter(|&&c| {
+                    if observed as f64 >= expected {
+                        c >= observed
+                    } else {
+                        c <= observed
+                    }
+                })
+                .count();
+            AreaEnrichment {
+                area: area.clone(),
+                observed,
+                expected,
+                p_value: (extreme + 1) as f64 / (counts.len() + 1) as f64,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn region(chrom: &str, start: i64, end: i64) -> Region {
+        Region::new(chrom.to_string(), start, end, vec!["peak".to_string()])
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let exclusions = Exclusions::new(vec![
+            region("chr1", 500, 600),
+            region("chr1", 100, 200),
+            region("chr1", 150, 300),
+        ]);
+        assert!(exclusions.overlaps("chr1", 300, 400));
+        assert!(exclusions.overlaps("chr1", 50, 100));
+        assert!(exclusions.overlaps("chr1", 550, 560));
+        assert!(!exclusions.overlaps("chr1", 301, 499));
+        assert!(!exclusions.overlaps("chr1", 601, 700));
+        assert!(!exclusions.overlaps("chr2", 100, 200));
+    }
+
+    #[test]
+    fn test_shuffle_regions() {
+        let chrom_sizes = AHashMap::from([("chr1".to_string(), 1000)]);
+        let exclusions = Exclusions::new(vec![region("chr1", 0, 799)]);
+        let regions = vec![region("chr1", 10, 59), region("chr1", 100, 100)];
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..20 {
+            let shuffled = shuffle_regions(&regions, &chrom_sizes, &exclusions, &mut rng).unwrap();
+            for (original, moved) in regions.iter().zip(&shuffled) {
+                assert_eq!(moved.chrom, "chr1");
+                assert_eq!(moved.length(), original.length());
+                assert_eq!(moved.metadata, original.metadata);
+                assert!(moved.start >= 800 && moved.end < 1000, "{:?}", moved);
+            }
+        }
+
+        let unknown = vec![region("chr2", 10, 59)];
+        assert!(shuffle_regions(&unknown, &chrom_sizes, &exclusions, &mut rng).is_err());
+        let blocked = Exclusions::new(vec![region("chr1", 0, 999)]);
+        assert!(shuffle_regions(&regions, &chrom_sizes, &blocked, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_region_area() {
+        let candidate = |area: Area| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                "G1".to_string(),
+                0,
+                100.0,
+                50.0,
+                0,
+            )
+        };
+        let rules = vec![Area::Tss, Area::Intron];
+        let candidates = vec![
+            candidate(Area::Downstream),
+            candidate(Area::Intron),
+            candidate(Area::Tss),
+        ];
+        assert_eq!(region_area(&candidates, &rules), Area::Tss);
+        assert_eq!(region_area(&candidates[..1], &rules), Area::Downstream);
+        assert_eq!(region_area(&[], &rules), Area::Intergenic);
+    }
+
+    #[test]
+    fn test_area_enrichment() {
+        let rules = vec![Area::Tss, Area::Intron];
+        let observed = vec![Area::Tss, Area::Tss, Area::Tss, Area::Intron];
+        let backgrounds = vec![
+            vec![Area::Tss, Area::Intron, Area::Intron, Area::Intergenic],
+            vec![Area::Intron, Area::Intron, Area::Intron, Area::Intergenic],
+            vec![Area::Tss, Area::Tss, Area::Tss, Area::Intron],
+        ];
+
+        let enrichment = area_enrichment(&observed, &backgrounds, &rules);
+        let areas: Vec<Area> = enrichment.iter().map(|e| e.area.clone()).collect();
+        assert_eq!(areas, vec![Area::Tss, Area::Intron, Area::Intergenic]);
+
+        let tss = &enrichment[0];
+        assert_eq!(tss.observed, 3);
+        assert!((tss.expected - 4.0 / 3.0).abs() < 1e-9);
+        assert!((tss.fold_enrichment().unwrap() - 2.25).abs() < 1e-9);
+        // One background set reaches 3 TSS regions
+        assert!((tss.p_value - 0.5).abs() < 1e-9);
+
+        // Depleted: one background set has as few intron regions
+        let intron = &enrichment[1];
+        assert!((intron.expected - 2.0).abs() < 1e-9);
+        assert!((intron.p_value - 0.5).abs() < 1e-9);
+
+        let intergenic = &enrichment[2];
+        assert_eq!(intergenic.observed, 0);
+        assert!((intergenic.p_value - 0.5).abs() < 1e-9);
+
+        // Areas from the rules are reported even if never found
+        let tts = area_enrichment(&observed, &backgrounds, &[Area::Tts]);
+        assert_eq!((&tts[0].area, tts[0].observed), (&Area::Tts, 0));
+        assert_eq!(tts[0].fold_enrichment(), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! Classification of regions against a BED annotation of named features.
+//!
+//! Features are loaded as single-exon genes so the regular matcher can be
+//! reused; its candidates are then reduced to one OVERLAP, UPSTREAM or
+//! DOWNSTREAM association per feature. Gene-extent mode classifies genes
+//! collapsed to their extent the same way, with OVERLAP_GENE overlaps.
+
+use indexmap::IndexMap;
+
+use crate::types::{Area, Candidate};
+
+/// Feature-level area of a candidate produced by the gene matcher.
+fn feature_area(area: &Area, overlap: &Area) -> Area {
+    match area {
+        Area::Tts | Area::Downstream => Area::Downstream,
+        area if area.is_upstream() => Area::Upstream,
+        _ => overlap.clone(),
+    }
+}
+
+/// Reduce the candidates of each feature to a single association.
+///
+/// An overlap takes priority over proximity. Upstream and downstream
+/// portions of the region (e.g. TSS and PROMOTER pieces) are combined,
+/// adding up their percentage of the region. Features keep their order of
+/// first appearance. Overlaps are reported as the `overlap` area.
+pub fn classify_features(candidates: Vec<Candidate>, overlap: Area) -> Vec<Candidate> {
+    // Features are keyed by transcript, which is unique per feature
+    let mut by_feature: IndexMap<String, Vec<Candidate>> = IndexMap::new();
+    for candidate in candidates {
+        by_feature
+            .entry(candidate.transcript.clone())
+            .or_default()
+            .push(candidate);
+    }
+
+    let mut classified = Vec::with_capacity(by_feature.len());
+    for (_, candidates) in by_feature {
+        let best = [overlap.clone(), Area::Upstream, Area::Downstream]
+            .into_iter()
+            .find(|area| {
+                candidates
+                    .iter()
+                    .any(|c| feature_area(&c.area, &overlap) == *area)
+            })
+            .unwrap_or(overlap.clone());
+
+        let mut matching = candidates
+            .into_iter()
+            .filter(|c| feature_area(&c.area, &overlap) == best);
+        let mut feature = matching.next().unwrap();
+        feature.area = best.clone();
+        for candidate in matching {
+            feature.pctg_region += candidate.pctg_region;
+            feature.distance = feature.distance.min(candidate.distance);
+        }
+        if best != overlap {
+            feature.pctg_area = -1.0;
+        }
+        classified.push(feature);
+    }
+
+    classified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn make_candidate(area: Area, transcript: &str, pctg_region: f64, pctg_area: f64) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            transcript.to_string(),
+            "F".to_string(),
+            0,
+            pctg_region,
+            pctg_area,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_classify_features() {
+        let candidates = vec![
+            make_candidate(Area::Tss, "A", 20.0, 100.0),
+            make_candidate(Area::FirstExon, "A", 40.0, 8.0),
+            make_candidate(Area::Promoter, "A", 40.0, 30.0),
+            make_candidate(Area::Tss, "B", 30.0, 50.0),
+            make_candidate(Area::Promoter, "B", 70.0, 20.0),
+            make_candidate(Area::Downstream, "C", 100.0, -1.0),
+        ];
+
+        let result = classify_features(candidates.clone(), Area::Overlap);
+        assert_eq!(result.len(), 3);
+
+        assert_eq!(result[0].area, Area::Overlap);
+        assert_eq!(result[0].pctg_region, 40.0);
+        assert_eq!(result[0].pctg_area, 8.0);
+
+        assert_eq!(result[1].area, Area::Upstream);
+        assert_eq!(result[1].pctg_region, 100.0);
+        assert_eq!(result[1].pctg_area, -1.0);
+
+        assert_eq!(result[2].area, Area::Downstream);
+
+        let result = classify_features(candidates, Area::OverlapGene);
+        assert_eq!(result[0].area, Area::OverlapGene);
+        assert_eq!(result[1].area, Area::Upstream);
+    }
+}
@@ -0,0 +1,250 @@
+//! GREAT-style regulatory domain association.
+//!
+//! Implements the basal-plus-extension model of GREAT: every gene gets a basal
+//! domain around its TSS, extended in both directions up to the nearest basal
+//! domains of the neighbouring genes (bounded by a maximum extension). A region
+//! is associated with every gene whose regulatory domain contains its midpoint.
+
+use ahash::AHashMap;
+
+use crate::config::{Config, GreatParams};
+use crate::matcher::overlap::fill_unmatched;
+use crate::matcher::rules::cap_candidates;
+use crate::matcher::RegionIndex;
+use crate::parser::gtf::GtfData;
+use crate::types::{Area, Candidate, Gene, Region, ReportLevel, Strand};
+
+/// Regulatory domain of a single gene.
+#[derive(Debug, Clone)]
+pub struct RegulatoryDomain {
+    pub gene_id: String,
+    pub strand: Strand,
+    /// Gene TSS (start for positive strand, end for negative strand).
+    pub tss: i64,
+    pub basal_start: i64,
+    pub basal_end: i64,
+    /// Extended domain start.
+    pub start: i64,
+    /// Extended domain end.
+    pub end: i64,
+}
+
+/// Build the regulatory domains for the genes of one chromosome.
+///
+/// The returned domains are sorted by extended start.
+pub fn build_domains(genes: &[Gene], params: &GreatParams) -> Vec<RegulatoryDomain> {
+    let mut domains: Vec<RegulatoryDomain> = genes
+        .iter()
+        .map(|gene| {
+            let (tss, upstream, downstream) = match gene.strand {
+                Strand::Positive => (gene.start, params.basal_upstream, params.basal_downstream),
+                Strand::Negative => (gene.end, params.basal_downstream, params.basal_upstream),
+            };
+            let basal_start = (tss - upstream).max(0);
+            let basal_end = tss + downstream;
+            RegulatoryDomain {
+                gene_id: gene.gene_id.clone(),
+                strand: gene.strand,
+                tss,
+                basal_start,
+                basal_end,
+                start: basal_start,
+                end: basal_end,
+            }
+        })
+        .collect();
+
+    domains.sort_by(|a, b| a.tss.cmp(&b.tss).then(a.gene_id.cmp(&b.gene_id)));
+
+    // Extend each domain up to the neighbouring basal domains
+    for i in 0..domains.len() {
+        let tss = domains[i].tss;
+
+        let lower_bound = if i > 0 {
+            domains[i - 1].basal_end + 1
+        } else {
+            0
+        };
+        let extended_start = (tss - params.max_extension).max(lower_bound).max(0);
+
+        let upper_bound = domains
+            .get(i + 1)
+            .map_or(i64::MAX, |next| next.basal_start - 1);
+        let extended_end = (tss + params.max_extension).min(upper_bound);
+
+        let domain = &mut domains[i];
+        domain.start = domain.basal_start.min(extended_start);
+        domain.end = domain.basal_end.max(extended_end);
+    }
+
+    domains.sort_by_key(|domain| domain.start);
+    domains
+}
+
+/// Regulatory domains of all chromosomes.
+#[derive(Debug, Clone, Default)]
+pub struct DomainIndex {
+    domains_by_chrom: AHashMap<String, Vec<RegulatoryDomain>>,
+    max_lengths: AHashMap<String, i64>,
+}
+
+impl DomainIndex {
+    /// Build the regulatory domains of every chromosome in the annotation.
+    pub fn build(gtf_data: &GtfData, params: &GreatParams) -> Self {
+        let mut index = DomainIndex::default();
+        for (chrom, genes) in &gtf_data.genes_by_chrom {
+            let domains = build_domains(genes, params);
+            let max_len = domains.iter().map(|d| d.end - d.start).max().unwrap_or(0);
+            index.max_lengths.insert(chrom.clone(), max_len);
+            index.domains_by_chrom.insert(chrom.clone(), domains);
+        }
+        index
+    }
+
+    /// Get the domains of a chromosome.
+    pub fn domains(&self, chrom: &str) -> &[RegulatoryDomain] {
+        self.domains_by_chrom
+            .get(chrom)
+            .map_or(&[], |domains| domains.as_slice())
+    }
+
+    /// Associate a region with the genes whose domains contain its midpoint.
+    pub fn match_region(&self, region: &Region) -> Vec<Candidate> {
+        let domains = self.domains(&region.chrom);
+        let max_len = self.max_lengths.get(&region.chrom).copied().unwrap_or(0);
+        let pm = region.midpoint();
+
+        // Domains are sorted by start, so only those starting within the
+        // longest domain length before the midpoint can contain it
+        let last = domains.partition_point(|d| d.start <= pm);
+        let first = domains[..last].partition_point(|d| d.start < pm - max_len);
+
+        domains[first..last]
+            .iter()
+            .filter(|d| d.end >= pm)
+            .map(|d| {
+                let area = if pm >= d.basal_start && pm <= d.basal_end {
+                    Area::Basal
+                } else {
+                    Area::Extension
+                };
+                let tss_distance = match d.strand {
+                    Strand::Positive => pm - d.tss,
+                    Strand::Negative => d.tss - pm,
+                };
+                Candidate::new(
+                    d.start,
+                    d.end,
+                    d.strand,
+                    "NA".to_string(),
+                    area,
+                    "NA".to_string(),
+                    d.gene_id.clone(),
+                    tss_distance.abs(),
+                    100.0,
+                    -1.0,
+                    tss_distance,
+                )
+            })
+            .collect()
+    }
+}
+
+impl RegionIndex for DomainIndex {
+    /// Associate a region and prepare its candidates for output.
+    fn annotate_region(&self, region: &Region, config: &Config) -> Vec<Candidate> {
+        let mut candidates = self.match_region(region);
+        if let Some(max_hits) = config.max_hits.filter(|_| config.level != ReportLevel::All) {
+            candidates = cap_candidates(candidates, max_hits, &config.rules);
+        }
+        fill_unmatched(region, &mut candidates, config);
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Exon, Transcript};
+
+    fn make_gene(gene_id: &str, strand: Strand, start: i64, end: i64) -> Gene {
+        let mut gene = Gene::new(gene_id.to_string(), strand);
+        gene.set_length(start, end);
+        let mut transcript = Transcript::new(format!("T_{}", gene_id));
+        transcript.add_exon(Exon::new(start, end));
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    #[test]
+    fn test_domains_extend_to_neighbouring_basal_domains() {
+        let genes = vec![
+            make_gene("A", Strand::Positive, 100_000, 110_000),
+            make_gene("B", Strand::Negative, 150_000, 200_000),
+        ];
+        let domains = build_domains(&genes, &GreatParams::default());
+
+        // A: TSS 100000, basal [95000, 101000]
+        assert_eq!(domains[0].gene_id, "A");
+        assert_eq!(
+            (domains[0].basal_start, domains[0].basal_end),
+            (95_000, 101_000)
+        );
+        assert_eq!(domains[0].start, 0);
+        // B: TSS 200000 on the minus strand, basal [199000, 205000]
+        assert_eq!(
+            (domains[1].basal_start, domains[1].basal_end),
+            (199_000, 205_000)
+        );
+        assert_eq!(domains[0].end, 198_999);
+        assert_eq!(domains[1].start, 101_001);
+        assert_eq!(domains[1].end, 1_200_000);
+    }
+
+    #[test]
+    fn test_domains_keep_basal_when_neighbours_overlap() {
+        let genes = vec![
+            make_gene("A", Strand::Positive, 10_000, 11_000),
+            make_gene("B", Strand::Positive, 12_000, 13_000),
+        ];
+        let domains = build_domains(&genes, &GreatParams::default());
+
+        // Basal domains overlap, so neither can shrink below its basal domain
+        assert_eq!((domains[0].start, domains[0].end), (0, 11_000));
+        assert_eq!((domains[1].start, domains[1].end), (7_000, 1_012_000));
+    }
+
+    #[test]
+    fn test_match_region_areas() {
+        let mut gtf_data = GtfData {
+            genes_by_chrom: AHashMap::new(),
+            max_lengths: AHashMap::new(),
+        };
+        gtf_data.genes_by_chrom.insert(
+            "chr1".to_string(),
+            vec![
+                make_gene("A", Strand::Positive, 100_000, 110_000),
+                make_gene("B", Strand::Negative, 150_000, 200_000),
+            ],
+        );
+        let index = DomainIndex::build(&gtf_data, &GreatParams::default());
+
+        let region = Region::new("chr1".to_string(), 99_000, 99_100, vec![]);
+        let candidates = index.match_region(&region);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].gene, "A");
+        assert_eq!(candidates[0].area, Area::Basal);
+        assert_eq!(candidates[0].tss_distance, -950);
+
+        // Extended domains of neighbouring genes overlap between their basal domains
+        let region = Region::new("chr1".to_string(), 150_000, 150_100, vec![]);
+        let candidates = index.match_region(&region);
+        let genes: Vec<&str> = candidates.iter().map(|c| c.gene.as_str()).collect();
+        assert_eq!(genes, vec!["A", "B"]);
+        assert!(candidates.iter().all(|c| c.area == Area::Extension));
+        assert_eq!(candidates[1].distance, 49_950);
+
+        let region = Region::new("chr2".to_string(), 150_000, 150_100, vec![]);
+        assert!(index.match_region(&region).is_empty());
+    }
+}
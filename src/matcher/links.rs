@@ -0,0 +1,133 @@
+//! Association through external enhancer-gene links.
+//!
+//! Regions overlapping a linked enhancer (ABC model, EpiMap, ...) are
+//! associated with its target gene as a LINKED area, in addition to the
+//! positional associations, so long-range targets are not missed.
+
+use ahash::AHashMap;
+
+use crate::parser::links::EnhancerLink;
+use crate::types::{Area, Candidate, Region, Strand};
+
+/// Enhancer-gene links of all chromosomes.
+#[derive(Debug, Clone, Default)]
+pub struct LinkIndex {
+    links_by_chrom: AHashMap<String, Vec<EnhancerLink>>,
+    max_lengths: AHashMap<String, i64>,
+}
+
+impl LinkIndex {
+    /// Index the links of every chromosome, sorting them by start.
+    pub fn new(links_by_chrom: AHashMap<String, Vec<EnhancerLink>>) -> Self {
+        let mut index = LinkIndex::default();
+        for (chrom, mut links) in links_by_chrom {
+            links.sort_by(|a, b| (a.start, a.end, &a.gene).cmp(&(b.start, b.end, &b.gene)));
+            let max_len = links.iter().map(|l| l.end - l.start).max().unwrap_or(0);
+            index.max_lengths.insert(chrom.clone(), max_len);
+            index.links_by_chrom.insert(chrom, links);
+        }
+        index
+    }
+
+    /// Get the links of a chromosome.
+    pub fn links(&self, chrom: &str) -> &[EnhancerLink] {
+        self.links_by_chrom
+            .get(chrom)
+            .map_or(&[], |links| links.as_slice())
+    }
+
+    /// LINKED candidates of the enhancers overlapped by a region.
+    pub fn match_region(&self, region: &Region) -> Vec<Candidate> {
+        let links = self.links(&region.chrom);
+        let max_len = self.max_lengths.get(&region.chrom).copied().unwrap_or(0);
+
+        // Links are sorted by start, so only those starting within the
+        // longest link length before the region can overlap it
+        let last = links.partition_point(|l| l.start <= region.end);
+        let first = links[..last].partition_point(|l| l.start < region.start - max_len);
+
+        links[first..last]
+            .iter()
+            .filter(|l| l.end >= region.start)
+            .map(|l| {
+                let overlap = region.end.min(l.end) - region.start.max(l.start) + 1;
+                Candidate::new(
+                    l.start,
+                    l.end,
+                    Strand::Positive,
+                    "NA".to_string(),
+                    Area::Linked,
+                    "NA".to_string(),
+                    l.gene.clone(),
+                    0,
+                    (overlap as f64 / region.length() as f64) * 100.0,
+                    (overlap as f64 / (l.end - l.start + 1) as f64) * 100.0,
+                    0,
+                )
+            })
+            .collect()
+    }
+
+    /// Add the LINKED associations of a region to its candidates.
+    ///
+    /// They replace the INTERGENIC placeholder of unmatched regions.
+    pub fn add_links(&self, region: &Region, candidates: &mut Vec<Candidate>) {
+        let linked = self.match_region(region);
+        if linked.is_empty() {
+            return;
+        }
+        candidates.retain(|c| c.area != Area::Intergenic);
+        candidates.extend(linked);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_link(start: i64, end: i64, gene: &str) -> EnhancerLink {
+        EnhancerLink {
+            start,
+            end,
+            gene: gene.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_match_region() {
+        let mut links = AHashMap::new();
+        links.insert(
+            "chr1".to_string(),
+            vec![
+                make_link(5000, 5999, "FAR"),
+                make_link(1000, 1999, "A"),
+                make_link(1500, 2499, "B"),
+            ],
+        );
+        let index = LinkIndex::new(links);
+
+        let region = Region::new("chr1".to_string(), 1900, 2099, vec![]);
+        let candidates = index.match_region(&region);
+        let genes: Vec<&str> = candidates.iter().map(|c| c.gene.as_str()).collect();
+        assert_eq!(genes, vec!["A", "B"]);
+        assert!(candidates.iter().all(|c| c.area == Area::Linked));
+        assert_eq!(candidates[0].pctg_region, 50.0);
+        assert_eq!(candidates[0].pctg_area, 10.0);
+
+        let region = Region::new("chr2".to_string(), 1900, 2099, vec![]);
+        assert!(index.match_region(&region).is_empty());
+    }
+
+    #[test]
+    fn test_add_links_replaces_intergenic() {
+        let mut links = AHashMap::new();
+        links.insert("chr1".to_string(), vec![make_link(1000, 1999, "A")]);
+        let index = LinkIndex::new(links);
+
+        let region = Region::new("chr1".to_string(), 1000, 1099, vec![]);
+        let mut candidates = vec![crate::matcher::intergenic_candidate(&region)];
+        index.add_links(&region, &mut candidates);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].gene, "A");
+    }
+}
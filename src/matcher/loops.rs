@@ -0,0 +1,202 @@
+//! Joint annotation of the two anchors of chromatin loops.
+//!
+//! Each anchor is annotated as a regular region; its associations are then
+//! reduced to one best area per gene, and every gene of one anchor is paired
+//! with every gene of the other. Pairs are classified by the role of each
+//! anchor: promoter (TSS/PROMOTER) or enhancer (any other, distal, position).
+
+use indexmap::IndexMap;
+
+use crate::types::{Area, Candidate};
+
+/// Role of a loop anchor with respect to a gene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnchorRole {
+    Promoter,
+    Enhancer,
+}
+
+impl AnchorRole {
+    /// Role of an anchor associated with a gene through `area`, or without
+    /// any gene association (`None`).
+    pub fn of(area: Option<&Area>) -> Self {
+        match area {
+            Some(Area::Tss | Area::Promoter | Area::Basal) => AnchorRole::Promoter,
+            Some(Area::Zone(name)) if name.starts_with("PROMOTER") => AnchorRole::Promoter,
+            _ => AnchorRole::Enhancer,
+        }
+    }
+
+    /// Convert role to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnchorRole::Promoter => "promoter",
+            AnchorRole::Enhancer => "enhancer",
+        }
+    }
+}
+
+/// Best association of an anchor with a gene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorGene {
+    pub gene: String,
+    pub area: Area,
+    pub distance: i64,
+}
+
+/// A gene pair joined by a loop.
+///
+/// Anchors without gene associations have no gene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopPair {
+    pub gene1: Option<AnchorGene>,
+    pub gene2: Option<AnchorGene>,
+}
+
+impl LoopPair {
+    /// Classification of the loop from the roles of both anchors, e.g.
+    /// `promoter-enhancer` (promoters first, regardless of the anchor order).
+    pub fn class(&self) -> String {
+        let mut roles = [
+            AnchorRole::of(self.gene1.as_ref().map(|g| &g.area)),
+            AnchorRole::of(self.gene2.as_ref().map(|g| &g.area)),
+        ];
+        roles.sort();
+        format!("{}-{}", roles[0].as_str(), roles[1].as_str())
+    }
+}
+
+/// Reduce the associations of an anchor to one per gene.
+///
+/// Each gene keeps its highest priority area according to `rules` (areas
+/// missing from the rules rank last) and its minimum distance. Genes keep
+/// their order of first appearance; INTERGENIC placeholders are ignored.
+pub fn anchor_genes(candidates: &[Candidate], rules: &[Area]) -> Vec<AnchorGene> {
+    let rank = |area: &Area| rules.iter().position(|a| a == area).unwrap_or(rules.len());
+
+    let mut genes: IndexMap<&str, AnchorGene> = IndexMap::new();
+    for candidate in candidates {
+        if candidate.area == Area::Intergenic {
+            continue;
+        }
+        match genes.get_mut(candidate.gene.as_str()) {
+            Some(entry) => {
+                if rank(&candidate.area) < rank(&entry.area) {
+                    entry.area = candidate.area.clone();
+                }
+                entry.distance = entry.distance.min(candidate.distance);
+            }
+            None => {
+                genes.insert(
+                    &candidate.gene,
+                    AnchorGene {
+                        gene: candidate.gene.clone(),
+                        area: candidate.area.clone(),
+                        distance: candidate.distance,
+                    },
+                );
+            }
+        }
+    }
+
+    genes.into_values().collect()
+}
+
+/// Pair the genes of both anchors of a loop.
+///
+/// Every loop yields at least one pair, with no gene for an anchor without
+/// associations.
+pub fn pair_anchors(anchor1: &[Candidate], anchor2: &[Candidate], rules: &[Area]) -> Vec<LoopPair> {
+    let as_options = |genes: Vec<AnchorGene>| -> Vec<Option<AnchorGene>> {
+        if genes.is_empty() {
+            vec![None]
+        } else {
+            genes.into_iter().map(Some).collect()
+        }
+    };
+    let genes1 = as_options(anchor_genes(anchor1, rules));
+    let genes2 = as_options(anchor_genes(anchor2, rules));
+
+    let mut pairs = Vec::with_capacity(genes1.len() * genes2.len());
+    for gene1 in &genes1 {
+        for gene2 in &genes2 {
+            pairs.push(LoopPair {
+                gene1: gene1.clone(),
+                gene2: gene2.clone(),
+            });
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn make_candidate(area: Area, gene: &str, distance: i64) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            format!("T_{}", gene),
+            gene.to_string(),
+            distance,
+            100.0,
+            50.0,
+            0,
+        )
+    }
+
+    fn rules() -> Vec<Area> {
+        vec![Area::Tss, Area::Promoter, Area::Intron, Area::Upstream]
+    }
+
+    #[test]
+    fn test_anchor_genes() {
+        let candidates = vec![
+            make_candidate(Area::Intron, "A", 0),
+            make_candidate(Area::Upstream, "B", 3000),
+            make_candidate(Area::Tss, "A", 0),
+            make_candidate(Area::Upstream, "B", 2500),
+        ];
+
+        let genes = anchor_genes(&candidates, &rules());
+        assert_eq!(genes.len(), 2);
+        assert_eq!((genes[0].gene.as_str(), &genes[0].area), ("A", &Area::Tss));
+        assert_eq!((genes[1].gene.as_str(), genes[1].distance), ("B", 2500));
+    }
+
+    #[test]
+    fn test_pair_anchors() {
+        let anchor1 = vec![
+            make_candidate(Area::Upstream, "A", 3000),
+            make_candidate(Area::Promoter, "B", 500),
+        ];
+        let anchor2 = vec![make_candidate(Area::Tss, "C", 0)];
+
+        let pairs = pair_anchors(&anchor1, &anchor2, &rules());
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].class(), "promoter-enhancer");
+        assert_eq!(pairs[1].class(), "promoter-promoter");
+
+        // Anchors without genes still yield a pair
+        let pairs = pair_anchors(&anchor1[..1], &[], &rules());
+        assert_eq!(pairs.len(), 1);
+        assert!(pairs[0].gene2.is_none());
+        assert_eq!(pairs[0].class(), "enhancer-enhancer");
+    }
+
+    #[test]
+    fn test_anchor_role() {
+        assert_eq!(AnchorRole::of(Some(&Area::Tss)), AnchorRole::Promoter);
+        assert_eq!(
+            AnchorRole::of(Some(&Area::from_zone_name("PROMOTER_DISTAL"))),
+            AnchorRole::Promoter
+        );
+        assert_eq!(AnchorRole::of(Some(&Area::Intron)), AnchorRole::Enhancer);
+        assert_eq!(AnchorRole::of(None), AnchorRole::Enhancer);
+    }
+}
@@ -1,11 +1,46 @@
 //! Matching logic for genomic regions to gene annotations.
 
+use crate::config::Config;
+use crate::types::{Candidate, Region};
+
+pub mod cis;
+pub mod diff;
+pub mod enrichment;
+pub mod features;
+pub mod great;
+pub mod links;
+pub mod loops;
 pub mod overlap;
+pub mod promoters;
 pub mod rules;
 pub mod tss;
 pub mod tts;
 
-pub use overlap::{match_region_to_genes, match_regions_to_genes, process_candidates_for_output};
-pub use rules::{apply_rules, select_transcript};
-pub use tss::check_tss;
+pub use cis::CisIndex;
+pub use diff::{diff_annotations, AnnotationChange, ChangeKind};
+pub use enrichment::{area_enrichment, region_area, shuffle_regions, AreaEnrichment, Exclusions};
+pub use features::classify_features;
+pub use great::DomainIndex;
+pub use links::LinkIndex;
+pub use loops::{pair_anchors, AnchorRole, LoopPair};
+pub use overlap::{
+    annotate_region, fill_unmatched, intergenic_candidate, match_region_to_genes,
+    match_regions_to_genes, overlaps_antisense_gene, process_candidates_for_output,
+};
+pub use promoters::PromoterIndex;
+pub use rules::{
+    apply_rules, break_ties, cap_candidates, confidence_score, filter_reciprocal_overlap,
+    filter_tss_support, select_best, select_nearest_genes, select_nearest_per_strand,
+    select_transcript,
+};
+pub use tss::{check_tss, check_zones};
 pub use tts::check_tts;
+
+/// Precomputed index that replaces gene matching for a region.
+///
+/// Used by the alternative matching modes (GREAT domains, cis windows,
+/// promoter windows).
+pub trait RegionIndex: Send + Sync {
+    /// Match a region and prepare its candidates for output.
+    fn annotate_region(&self, region: &Region, config: &Config) -> Vec<Candidate>;
+}
@@ -5,12 +5,18 @@
 
 use ahash::AHashMap;
 use indexmap::IndexMap;
+use std::cmp::Ordering;
 
 use crate::config::Config;
-use crate::matcher::rules::{apply_rules, select_transcript};
-use crate::matcher::tss::{check_tss, TssExonInfo};
+use crate::matcher::features::classify_features;
+use crate::matcher::rules::{
+    apply_rules, break_ties, cap_candidates, confidence_score, filter_reciprocal_overlap,
+    filter_tss_support, select_best, select_nearest_genes, select_nearest_per_strand,
+    select_transcript,
+};
+use crate::matcher::tss::{check_tss, check_zones, TssExonInfo};
 use crate::matcher::tts::{check_tts, TtsExonInfo};
-use crate::types::{Area, Candidate, Gene, Region, ReportLevel, Strand};
+use crate::types::{Area, Candidate, Compat, Exon, Gene, Region, ReportLevel, Strand, Transcript};
 
 /// Calculate the intron number based on exon index and strand.
 ///
@@ -26,7 +32,7 @@ fn calculate_intron_number(index: usize, total_exons: usize, strand: Strand) ->
 /// Aggregate overlapping entries (gene body or intron) into a single candidate per transcript.
 ///
 /// Takes a map of entries grouped by transcript key and combines overlapping regions
-/// into single candidates with aggregated statistics.
+/// into single candidates with aggregated statistics, spanning all their areas.
 fn aggregate_entries(
     entries_map: IndexMap<String, Vec<(Candidate, i64, i64)>>,
     region_length: i64,
@@ -53,25 +59,183 @@ fn aggregate_entries(
             let pctg_region = (total_overlap as f64 / region_length as f64) * 100.0;
             let pctg_area = (total_overlap as f64 / total_area as f64) * 100.0;
 
-            results.push(Candidate::new(
-                ref_candidate.start,
-                ref_candidate.end,
-                ref_candidate.strand,
-                combined_numbers,
-                ref_candidate.area,
-                ref_candidate.transcript.clone(),
-                ref_candidate.gene.clone(),
-                ref_candidate.distance,
+            results.push(Candidate {
+                start: entries.iter().map(|(c, _, _)| c.start).min().unwrap(),
+                end: entries.iter().map(|(c, _, _)| c.end).max().unwrap(),
+                exon_number: combined_numbers,
                 pctg_region,
                 pctg_area,
-                ref_candidate.tss_distance,
-            ));
+                ..ref_candidate.clone()
+            });
         }
     }
 
     results
 }
 
+/// Percentage of `whole` covered by `part`.
+fn percentage(part: i64, whole: i64) -> f64 {
+    (part as f64 / whole as f64) * 100.0
+}
+
+/// Number of bases of the `[feature_start, feature_end]` extent covered by
+/// the `[start, end]` region.
+pub fn extent_overlap(start: i64, end: i64, feature_start: i64, feature_end: i64) -> i64 {
+    (end.min(feature_end) - start.max(feature_start) + 1).max(0)
+}
+
+/// Percentage of the `[feature_start, feature_end]` extent covered by the
+/// `[start, end]` region.
+pub fn extent_coverage(start: i64, end: i64, feature_start: i64, feature_end: i64) -> f64 {
+    percentage(
+        extent_overlap(start, end, feature_start, feature_end),
+        feature_end - feature_start + 1,
+    )
+}
+
+/// Push a downstream candidate, splitting it into TTS/DOWNSTREAM portions when a
+/// TTS zone is configured.
+fn push_downstream(
+    output: &mut Vec<Candidate>,
+    candidate: Candidate,
+    start: i64,
+    end: i64,
+    config: &Config,
+) {
+    if config.tts > 0.0 {
+        let exon_info = TtsExonInfo {
+            start: candidate.start,
+            end: candidate.end,
+            strand: candidate.strand,
+            distance: candidate.distance,
+        };
+        for (tag, pctg_dhs, pctg_a) in check_tts(start, end, &exon_info, config.tts) {
+            output.push(Candidate {
+                area: tag.parse().unwrap_or(Area::Downstream),
+                pctg_region: pctg_dhs,
+                pctg_area: pctg_a,
+                ..candidate.clone()
+            });
+        }
+    } else {
+        output.push(candidate);
+    }
+}
+
+/// Push an upstream candidate, splitting it into TSS/PROMOTER/UPSTREAM portions,
+/// or into the custom zones when configured.
+fn push_upstream(
+    output: &mut Vec<Candidate>,
+    candidate: Candidate,
+    start: i64,
+    end: i64,
+    config: &Config,
+) {
+    let exon_info = TssExonInfo {
+        start: candidate.start,
+        end: candidate.end,
+        strand: candidate.strand,
+        distance: candidate.distance,
+    };
+    if !config.zones.is_empty() {
+        for (area, pctg_dhs, pctg_a) in check_zones(start, end, &exon_info, &config.zones) {
+            output.push(Candidate {
+                area,
+                pctg_region: pctg_dhs,
+                pctg_area: pctg_a,
+                ..candidate.clone()
+            });
+        }
+        return;
+    }
+    for (tag, pctg_dhs, pctg_a) in check_tss(start, end, &exon_info, config.tss, config.promoter) {
+        output.push(Candidate {
+            area: tag.parse().unwrap_or(Area::Upstream),
+            pctg_region: pctg_dhs,
+            pctg_area: pctg_a,
+            ..candidate.clone()
+        });
+    }
+}
+
+/// Portions of an exon that are reported with their own coding-context area.
+///
+/// Returns `(area, start, end)` for every UTR and CDS piece of the exon, as
+/// enabled in the config, when the transcript is coding.
+fn coding_segments(
+    exon: &Exon,
+    transcript: &Transcript,
+    strand: Strand,
+    config: &Config,
+) -> Vec<(Area, i64, i64)> {
+    let mut segments = Vec::new();
+    let Some((cds_start, cds_end)) = transcript.cds else {
+        return segments;
+    };
+
+    let (left_utr, right_utr) = match strand {
+        Strand::Positive => (Area::FivePrimeUtr, Area::ThreePrimeUtr),
+        Strand::Negative => (Area::ThreePrimeUtr, Area::FivePrimeUtr),
+    };
+    if config.utr && exon.start < cds_start {
+        segments.push((left_utr, exon.start, exon.end.min(cds_start - 1)));
+    }
+    if config.cds && exon.start <= cds_end && exon.end >= cds_start {
+        segments.push((Area::Cds, exon.start.max(cds_start), exon.end.min(cds_end)));
+    }
+    if config.utr && exon.end > cds_end {
+        segments.push((right_utr, exon.start.max(cds_end + 1), exon.end));
+    }
+
+    segments
+}
+
+/// Keep the closest proximity candidate seen so far for each gene.
+fn record_nearest(nearest: &mut IndexMap<String, Candidate>, candidate: Candidate) {
+    match nearest.get_mut(&candidate.gene) {
+        Some(best) if best.distance <= candidate.distance => {}
+        Some(best) => *best = candidate,
+        None => {
+            nearest.insert(candidate.gene.clone(), candidate);
+        }
+    }
+}
+
+/// Keep the candidates of the genes overlapping the region and of the closest
+/// gene on each side of it.
+///
+/// `flanks` holds the side of the region each gene lies on (`Equal` when
+/// overlapping) and its distance to the region on that side.
+fn select_flanking(
+    candidates: Vec<Candidate>,
+    flanks: &AHashMap<&str, (Ordering, i64)>,
+) -> Vec<Candidate> {
+    let mut closest: [Option<(&str, i64)>; 2] = [None, None];
+    for c in &candidates {
+        let (slot, distance) = match flanks.get(c.gene.as_str()) {
+            Some(&(Ordering::Less, distance)) => (&mut closest[0], distance),
+            Some(&(Ordering::Greater, distance)) => (&mut closest[1], distance),
+            _ => continue,
+        };
+        if !matches!(slot, Some((_, best)) if *best <= distance) {
+            *slot = Some((&c.gene, distance));
+        }
+    }
+
+    let keep: Vec<String> = closest
+        .iter()
+        .flatten()
+        .map(|(gene, _)| gene.to_string())
+        .collect();
+    candidates
+        .into_iter()
+        .filter(|c| {
+            keep.contains(&c.gene)
+                || matches!(flanks.get(c.gene.as_str()), Some((Ordering::Equal, _)))
+        })
+        .collect()
+}
+
 /// Match a single region to genes and return all candidates.
 ///
 /// This implements the main matching logic from the Python code.
@@ -83,8 +247,13 @@ pub fn match_region_to_genes(
 ) -> Vec<Candidate> {
     let start = region.start;
     let end = region.end;
-    let pm = region.midpoint();
+    // Signed offset from a position to the distance anchor of the region
+    let offset = |pos: i64| region.anchor_offset(config.distance_anchor, pos);
     let region_length = region.length();
+    let region_strand = region.strand();
+    // Python rgmatch keeps a single downstream and upstream candidate, which
+    // the region portions beyond an overlapped first or last exon overwrite
+    let python = config.compat == Some(Compat::Python);
 
     // Start analysis
     let mut down: i64 = i64::MAX; // Distance to TTS
@@ -93,6 +262,20 @@ pub fn match_region_to_genes(
     let mut upst: i64 = i64::MAX; // Distance to TSS
     let mut exon_up: Option<Candidate> = None;
 
+    // In nearest-k, per-strand and flanking modes the closest proximity
+    // candidate of every gene is kept, not only the single closest one overall
+    let mut nearest: Option<IndexMap<String, Candidate>> =
+        (config.nearest_k.is_some() || config.nearest_per_strand || config.flanking)
+            .then(IndexMap::new);
+
+    // A per-region distance overrides the configured limits
+    let region_distance = config.region_distance(region);
+    let distance_limit =
+        |area: &Area| region_distance.unwrap_or_else(|| config.distance_limit(area));
+    let max_proximity = region_distance.unwrap_or_else(|| config.max_proximity_distance());
+    let down_limit = distance_limit(&Area::Downstream);
+    let up_limit = distance_limit(&Area::Upstream);
+
     // When flag_gene_body is false, we will report downstream or upstream exons
     // Otherwise, we will only report the overlapped exons
     let mut flag_gene_body = false;
@@ -106,35 +289,77 @@ pub fn match_region_to_genes(
     let mut my_introns: IndexMap<String, Vec<(Candidate, i64, i64)>> = IndexMap::new();
     let mut my_gene_bodys: IndexMap<String, Vec<(Candidate, i64, i64)>> = IndexMap::new();
 
-    for (_i, gene) in genes.iter().enumerate().skip(last_index) {
-        let distance_to_start_gene = (gene.start - pm).abs();
+    // Signed TSS distance of every visited gene, measured from its canonical TSS
+    let mut gene_tss: AHashMap<&str, i64> = AHashMap::new();
+
+    // Side of the region every visited gene lies on, with its distance
+    let mut flanks: AHashMap<&str, (Ordering, i64)> = AHashMap::new();
+
+    for gene in genes.iter().skip(last_index) {
+        let distance_to_start_gene = offset(gene.start).abs();
 
         // Check if we should stop processing genes
         // Since genes are sorted by start, if the gene starts after our region ends (plus lookahead),
         // no subsequent genes can possibly overlap.
         // Note: The lookahead logic depends on whether we are looking for UPSTREAM/DOWNSTREAM
         if gene.start > end {
-            // Logic for quitting early:
-            // If we are looking for downstream (down), checking gene.start > end is not enough if we want nearest.
-            // But 'down' is initialized to MAX.
-            // The python logic seems to be: if we found something closer than current distance, stop.
-            // Simplified check matching Python structure:
-            if flag_gene_body || down < distance_to_start_gene || upst < distance_to_start_gene {
+            if nearest.is_some() {
+                // Every gene within the distance cutoff is of interest, so only stop
+                // once genes start beyond it.
+                if -offset(gene.start) > max_proximity {
+                    break;
+                }
+            } else if flag_gene_body
+                || (down <= down_limit && down < distance_to_start_gene)
+                || (upst <= up_limit && upst < distance_to_start_gene)
+                || -offset(gene.start) > max_proximity
+            {
+                // Candidates beyond their own limit are never reported, so
+                // they do not stop the search for the other direction
                 break;
             }
-            // Additional safety check for performance: if gene starts WAY after, we can definitely stop?
-            // Existing logic relies on `down` and `upst` being updated.
         }
 
+        // Skip genes excluded by the strand mode or the biotype filter
+        if !config.strand_mode.accepts(region_strand, gene.strand)
+            || !config.biotype_filter.accepts(gene.biotype.as_deref())
+        {
+            continue;
+        }
+
+        if config.gene_tss_distance {
+            let tss_distance = match gene.strand {
+                Strand::Positive => offset(gene.start),
+                Strand::Negative => -offset(gene.end),
+            };
+            gene_tss.insert(&gene.gene_id, tss_distance);
+        }
+
+        if config.flanking {
+            let flank = if gene.end < start {
+                (Ordering::Less, offset(gene.end))
+            } else if gene.start > end {
+                (Ordering::Greater, -offset(gene.start))
+            } else {
+                (Ordering::Equal, 0)
+            };
+            flanks.insert(&gene.gene_id, flank);
+        }
+
+        let gene_overlap = extent_overlap(start, end, gene.start, gene.end);
+        let gene_coverage = extent_coverage(start, end, gene.start, gene.end);
+
         // Check associations
         for transcript in &gene.transcripts {
             let exons = &transcript.exons;
+            let my_id = || format!("{}_{}", gene.gene_id, transcript.transcript_id);
+            let transcript_coverage = extent_coverage(start, end, transcript.start, transcript.end);
 
             // Calculate TSSdist using the first exon "start" position
             let tss_distance = if exons[0].exon_number.as_deref() == Some("1") {
-                pm - exons[0].start
+                offset(exons[0].start)
             } else {
-                exons.last().unwrap().end - pm
+                -offset(exons.last().unwrap().end)
             };
 
             for (j, exon) in exons.iter().enumerate() {
@@ -143,45 +368,73 @@ pub fn match_region_to_genes(
                 let exon_length = exon.length();
                 let exon_number = exon.exon_number.clone().unwrap_or_default();
 
+                let new_candidate =
+                    |area: Area, number: &str, distance: i64, pctg_region: f64, pctg_area: f64| {
+                        let mut candidate = Candidate::new(
+                            exon.start,
+                            exon.end,
+                            gene.strand,
+                            number.to_string(),
+                            area,
+                            transcript.transcript_id.clone(),
+                            gene.gene_id.clone(),
+                            distance,
+                            pctg_region,
+                            pctg_area,
+                            tss_distance,
+                        );
+                        candidate.pctg_gene = Some(gene_coverage);
+                        candidate.pctg_transcript = Some(transcript_coverage);
+                        candidate.overlap_gene = Some(gene_overlap);
+                        candidate.tsl = transcript.tsl;
+                        candidate
+                    };
+                // Introns are reported with their own bounds, between this exon
+                // and the next one
+                let new_intron = |number: &str, pctg_region: f64, pctg_area: f64| Candidate {
+                    start: exon.end + 1,
+                    end: exons[j + 1].start - 1,
+                    ..new_candidate(Area::Intron, number, 0, pctg_region, pctg_area)
+                };
+
                 // Case 1: Exon before the region
                 // <--------->
                 //                |--------------|
                 if exon.end < start {
                     // Check whether the current gene also covers the region
 
-                    let dist_tmp = pm - exon.end;
+                    let dist_tmp = offset(exon.end);
 
                     // Check if it's the last exon
                     if is_last_exon {
+                        if let Some(nearest) = nearest.as_mut() {
+                            let area = match gene.strand {
+                                Strand::Positive => Area::Downstream,
+                                Strand::Negative => Area::Upstream,
+                            };
+                            record_nearest(
+                                nearest,
+                                new_candidate(area, &exon_number, dist_tmp, 100.0, -1.0),
+                            );
+                        }
+
                         if gene.strand == Strand::Positive && dist_tmp < down {
                             down = dist_tmp;
-                            exon_down = Some(Candidate::new(
-                                exon.start,
-                                exon.end,
-                                gene.strand,
-                                exon_number.clone(),
+                            exon_down = Some(new_candidate(
                                 Area::Downstream,
-                                transcript.transcript_id.clone(),
-                                gene.gene_id.clone(),
+                                &exon_number,
                                 down,
                                 100.0,
                                 -1.0,
-                                tss_distance,
                             ));
                         } else if gene.strand == Strand::Negative && dist_tmp < upst {
                             upst = dist_tmp;
-                            exon_up = Some(Candidate::new(
-                                exon.start,
-                                exon.end,
-                                gene.strand,
-                                exon_number.clone(),
+                            exon_up = Some(new_candidate(
                                 Area::Upstream,
-                                transcript.transcript_id.clone(),
-                                gene.gene_id.clone(),
+                                &exon_number,
                                 upst,
                                 100.0,
                                 -1.0,
-                                tss_distance,
                             ));
                         }
                     } else {
@@ -192,31 +445,13 @@ pub fn match_region_to_genes(
                             flag_gene_body = true;
                             let intron_length = next_exon.start - exon.end - 1;
                             let intron_number =
-                                calculate_intron_number(j, exons.len(), gene.strand);
+                                calculate_intron_number(j, exons.len(), gene.strand).to_string();
 
                             if next_exon.start > end {
                                 // Region is completely inside intron
-                                let pctg_region = 100.0;
-                                let pctg_area =
-                                    (region_length as f64 / intron_length as f64) * 100.0;
-
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
-                                let intron_candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    intron_number.to_string(),
-                                    Area::Intron,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region,
-                                    pctg_area,
-                                    tss_distance,
-                                );
-                                my_introns.entry(my_id).or_default().push((
-                                    intron_candidate,
+                                let pctg_area = percentage(region_length, intron_length);
+                                my_introns.entry(my_id()).or_default().push((
+                                    new_intron(&intron_number, 100.0, pctg_area),
                                     intron_length,
                                     region_length,
                                 ));
@@ -224,228 +459,12 @@ pub fn match_region_to_genes(
                             } else {
                                 // Region overlaps with next exon
                                 let region_overlap = next_exon.start - start;
-                                let pctg_region =
-                                    (region_overlap as f64 / region_length as f64) * 100.0;
-                                let pctg_area =
-                                    (region_overlap as f64 / intron_length as f64) * 100.0;
-
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
-                                let intron_candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    intron_number.to_string(),
-                                    Area::Intron,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region,
-                                    pctg_area,
-                                    tss_distance,
-                                );
-                                my_introns.entry(my_id).or_default().push((
-                                    intron_candidate,
-                                    intron_length,
-                                    region_overlap,
-                                ));
-                            }
-                        }
-                    }
-                }
-                // Case 2: Exon overlapping partially the region (left)
-                //     <--------->
-                //          |--------------|
-                else if start <= exon.end && exon.end <= end && exon.start < start {
-                    flag_gene_body = true;
-                    let body_overlap = exon.end - start + 1;
-                    let pctg_region = (body_overlap as f64 / region_length as f64) * 100.0;
-                    let pctg_area = (body_overlap as f64 / exon_length as f64) * 100.0;
-
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
-                    {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        ));
-                    } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
-                        let gb_candidate = Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::GeneBody,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        );
-                        my_gene_bodys.entry(my_id).or_default().push((
-                            gb_candidate,
-                            exon_length,
-                            body_overlap,
-                        ));
-                    }
-
-                    // Handle remaining region after exon
-                    if exon.end < end {
-                        if is_last_exon {
-                            let region_overlap = end - exon.end;
-                            let pctg_region_r =
-                                (region_overlap as f64 / region_length as f64) * 100.0;
-
-                            if gene.strand == Strand::Positive {
-                                let candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    exon_number.clone(),
-                                    Area::Downstream,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region_r,
-                                    -1.0,
-                                    tss_distance,
-                                );
-                                if config.tts > 0.0 {
-                                    let exon_info = TtsExonInfo {
-                                        start: candidate.start,
-                                        end: candidate.end,
-                                        strand: candidate.strand,
-                                        distance: candidate.distance,
-                                    };
-                                    for (tag, pctg_dhs, pctg_a) in
-                                        check_tts(start, end, &exon_info, config.tts)
-                                    {
-                                        final_output.push(Candidate::new(
-                                            candidate.start,
-                                            candidate.end,
-                                            candidate.strand,
-                                            candidate.exon_number.clone(),
-                                            tag.parse().unwrap_or(Area::Downstream),
-                                            candidate.transcript.clone(),
-                                            candidate.gene.clone(),
-                                            candidate.distance,
-                                            pctg_dhs,
-                                            pctg_a,
-                                            tss_distance,
-                                        ));
-                                    }
-                                } else {
-                                    final_output.push(candidate);
-                                }
-                            } else {
-                                let candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    exon_number.clone(),
-                                    Area::Upstream,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region_r,
-                                    -1.0,
-                                    tss_distance,
-                                );
-                                let exon_info = TssExonInfo {
-                                    start: candidate.start,
-                                    end: candidate.end,
-                                    strand: candidate.strand,
-                                    distance: candidate.distance,
-                                };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tss(start, end, &exon_info, config.tss, config.promoter)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Upstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
-                                }
-                            }
-                        } else {
-                            // Check intron after exon
-                            let next_exon = &exons[j + 1];
-                            let intron_length = next_exon.start - exon.end - 1;
-                            let intron_number =
-                                calculate_intron_number(j, exons.len(), gene.strand);
-
-                            if next_exon.start > end {
-                                let region_overlap = end - exon.end;
-                                let pctg_region =
-                                    (region_overlap as f64 / region_length as f64) * 100.0;
-                                let pctg_area =
-                                    (region_overlap as f64 / intron_length as f64) * 100.0;
-
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
-                                let intron_candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    intron_number.to_string(),
-                                    Area::Intron,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region,
-                                    pctg_area,
-                                    tss_distance,
-                                );
-                                my_introns.entry(my_id).or_default().push((
-                                    intron_candidate,
-                                    intron_length,
-                                    region_overlap,
-                                ));
-                                break;
-                            } else {
-                                let region_overlap = next_exon.start - exon.end - 1;
-                                let pctg_region =
-                                    (region_overlap as f64 / region_length as f64) * 100.0;
-                                let pctg_area =
-                                    (region_overlap as f64 / intron_length as f64) * 100.0;
-
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
-                                let intron_candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    intron_number.to_string(),
-                                    Area::Intron,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region,
-                                    pctg_area,
-                                    tss_distance,
-                                );
-
-                                my_introns.entry(my_id).or_default().push((
-                                    intron_candidate,
+                                my_introns.entry(my_id()).or_default().push((
+                                    new_intron(
+                                        &intron_number,
+                                        percentage(region_overlap, region_length),
+                                        percentage(region_overlap, intron_length),
+                                    ),
                                     intron_length,
                                     region_overlap,
                                 ));
@@ -453,511 +472,177 @@ pub fn match_region_to_genes(
                         }
                     }
                 }
-                // Case 3: Exon completely inside the region
-                //     <--------->
-                //   |--------------|
-                else if start <= exon.start && end >= exon.end {
+                // Cases 2-5: Exon overlapping the region
+                //     <--------->        <--------->      <--------->    <----------------->
+                //          |-------|  |--------------|  |------|            |---------|
+                else if exon.start <= end {
                     flag_gene_body = true;
 
-                    // Handle upstream portion before exon
+                    // Handle the portion of the region before the first exon
                     if start < exon.start && is_first_exon {
-                        let region_overlap = exon.start - start;
-                        let pctg_region_r = (region_overlap as f64 / region_length as f64) * 100.0;
+                        let pctg_region_r = percentage(exon.start - start, region_length);
 
                         if gene.strand == Strand::Negative {
-                            let candidate = Candidate::new(
-                                exon.start,
-                                exon.end,
-                                gene.strand,
-                                exon_number.clone(),
+                            let candidate = new_candidate(
                                 Area::Downstream,
-                                transcript.transcript_id.clone(),
-                                gene.gene_id.clone(),
+                                &exon_number,
                                 0,
                                 pctg_region_r,
                                 -1.0,
-                                tss_distance,
                             );
-                            if config.tts > 0.0 {
-                                let exon_info = TtsExonInfo {
-                                    start: candidate.start,
-                                    end: candidate.end,
-                                    strand: candidate.strand,
-                                    distance: candidate.distance,
-                                };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tts(start, end, &exon_info, config.tts)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Downstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
-                                }
-                            } else {
-                                final_output.push(candidate);
+                            if python {
+                                exon_down = Some(candidate.clone());
                             }
+                            push_downstream(&mut final_output, candidate, start, end, config);
                         } else {
-                            let candidate = Candidate::new(
-                                exon.start,
-                                exon.end,
-                                gene.strand,
-                                exon_number.clone(),
-                                Area::Upstream,
-                                transcript.transcript_id.clone(),
-                                gene.gene_id.clone(),
-                                0,
-                                pctg_region_r,
-                                -1.0,
-                                tss_distance,
-                            );
-                            let exon_info = TssExonInfo {
-                                start: candidate.start,
-                                end: candidate.end,
-                                strand: candidate.strand,
-                                distance: candidate.distance,
-                            };
-                            for (tag, pctg_dhs, pctg_a) in
-                                check_tss(start, end, &exon_info, config.tss, config.promoter)
-                            {
-                                final_output.push(Candidate::new(
-                                    candidate.start,
-                                    candidate.end,
-                                    candidate.strand,
-                                    candidate.exon_number.clone(),
-                                    tag.parse().unwrap_or(Area::Upstream),
-                                    candidate.transcript.clone(),
-                                    candidate.gene.clone(),
-                                    candidate.distance,
-                                    pctg_dhs,
-                                    pctg_a,
-                                    tss_distance,
-                                ));
+                            let candidate =
+                                new_candidate(Area::Upstream, &exon_number, 0, pctg_region_r, -1.0);
+                            if python {
+                                exon_up = Some(candidate.clone());
                             }
+                            push_upstream(&mut final_output, candidate, start, end, config);
                         }
                     }
 
-                    // Handle the exon overlap
-                    let region_overlap = exon.end - exon.start + 1;
-                    let pctg_region = (region_overlap as f64 / region_length as f64) * 100.0;
-                    let pctg_area = 100.0;
-
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
+                    // Handle the exon overlap, reporting UTR/CDS portions on their own
+                    let mut region_overlap = exon.end.min(end) - exon.start.max(start) + 1;
+                    for (area, seg_start, seg_end) in
+                        coding_segments(exon, transcript, gene.strand, config)
                     {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
+                        let seg_overlap = seg_end.min(end) - seg_start.max(start) + 1;
+                        if seg_overlap <= 0 {
+                            continue;
+                        }
+                        region_overlap -= seg_overlap;
+                        final_output.push(Candidate {
+                            start: seg_start,
+                            end: seg_end,
+                            ..new_candidate(
+                                area,
+                                &exon_number,
+                                0,
+                                percentage(seg_overlap, region_length),
+                                percentage(seg_overlap, seg_end - seg_start + 1),
+                            )
+                        });
+                    }
+
+                    let pctg_region = percentage(region_overlap, region_length);
+                    let pctg_area = percentage(region_overlap, exon_length);
+
+                    let is_tss_exon = (is_first_exon && gene.strand == Strand::Positive)
+                        || (is_last_exon && gene.strand == Strand::Negative);
+
+                    if region_overlap <= 0 {
+                        // The whole overlap was reported with its coding context
+                    } else if is_tss_exon {
+                        final_output.push(new_candidate(
                             Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
+                            &exon_number,
                             0,
                             pctg_region,
                             pctg_area,
-                            tss_distance,
                         ));
                     } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
-
-                        let gb_candidate = Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::GeneBody,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        );
-                        my_gene_bodys.entry(my_id).or_default().push((
-                            gb_candidate,
-                            exon_length,
+                        my_gene_bodys.entry(my_id()).or_default().push((
+                            new_candidate(Area::GeneBody, &exon_number, 0, pctg_region, pctg_area),
                             exon_length,
+                            region_overlap,
                         ));
                     }
 
-                    // Handle downstream portion after exon
-                    if end > exon.end {
+                    // Handle the portion of the region after the exon
+                    if exon.end < end {
                         if is_last_exon {
-                            let region_overlap = end - exon.end;
-                            let pctg_region_r =
-                                (region_overlap as f64 / region_length as f64) * 100.0;
+                            let pctg_region_r = percentage(end - exon.end, region_length);
 
                             if gene.strand == Strand::Positive {
-                                let candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    exon_number.clone(),
+                                let candidate = new_candidate(
                                     Area::Downstream,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
+                                    &exon_number,
                                     0,
                                     pctg_region_r,
                                     -1.0,
-                                    tss_distance,
                                 );
-                                if config.tts > 0.0 {
-                                    let exon_info = TtsExonInfo {
-                                        start: candidate.start,
-                                        end: candidate.end,
-                                        strand: candidate.strand,
-                                        distance: candidate.distance,
-                                    };
-                                    for (tag, pctg_dhs, pctg_a) in
-                                        check_tts(start, end, &exon_info, config.tts)
-                                    {
-                                        final_output.push(Candidate::new(
-                                            candidate.start,
-                                            candidate.end,
-                                            candidate.strand,
-                                            candidate.exon_number.clone(),
-                                            tag.parse().unwrap_or(Area::Downstream),
-                                            candidate.transcript.clone(),
-                                            candidate.gene.clone(),
-                                            candidate.distance,
-                                            pctg_dhs,
-                                            pctg_a,
-                                            tss_distance,
-                                        ));
-                                    }
-                                } else {
-                                    final_output.push(candidate);
+                                if python {
+                                    exon_down = Some(candidate.clone());
                                 }
+                                push_downstream(&mut final_output, candidate, start, end, config);
                             } else {
-                                let candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    exon_number.clone(),
+                                let candidate = new_candidate(
                                     Area::Upstream,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
+                                    &exon_number,
                                     0,
                                     pctg_region_r,
                                     -1.0,
-                                    tss_distance,
                                 );
-                                let exon_info = TssExonInfo {
-                                    start: candidate.start,
-                                    end: candidate.end,
-                                    strand: candidate.strand,
-                                    distance: candidate.distance,
-                                };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tss(start, end, &exon_info, config.tss, config.promoter)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Upstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
+                                if python {
+                                    exon_up = Some(candidate.clone());
                                 }
+                                push_upstream(&mut final_output, candidate, start, end, config);
                             }
                         } else {
                             // Check intron after exon
                             let next_exon = &exons[j + 1];
                             let intron_length = next_exon.start - exon.end - 1;
                             let intron_number =
-                                calculate_intron_number(j, exons.len(), gene.strand);
+                                calculate_intron_number(j, exons.len(), gene.strand).to_string();
 
-                            if next_exon.start > end {
-                                let region_overlap = end - exon.end;
-                                let pctg_region =
-                                    (region_overlap as f64 / region_length as f64) * 100.0;
-                                let pctg_area =
-                                    (region_overlap as f64 / intron_length as f64) * 100.0;
-
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
-                                let intron_candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    intron_number.to_string(),
-                                    Area::Intron,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region,
-                                    pctg_area,
-                                    tss_distance,
-                                );
-                                my_introns.entry(my_id).or_default().push((
-                                    intron_candidate,
-                                    intron_length,
-                                    region_overlap,
-                                ));
-                                break;
-                            } else {
-                                let region_overlap = next_exon.start - exon.end - 1;
-                                let pctg_region =
-                                    (region_overlap as f64 / region_length as f64) * 100.0;
-                                let pctg_area =
-                                    (region_overlap as f64 / intron_length as f64) * 100.0;
-
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
-                                let intron_candidate = Candidate::new(
-                                    exon.start,
-                                    exon.end,
-                                    gene.strand,
-                                    intron_number.to_string(),
-                                    Area::Intron,
-                                    transcript.transcript_id.clone(),
-                                    gene.gene_id.clone(),
-                                    0,
-                                    pctg_region,
-                                    pctg_area,
-                                    tss_distance,
-                                );
-                                my_introns.entry(my_id).or_default().push((
-                                    intron_candidate,
-                                    intron_length,
-                                    region_overlap,
-                                ));
-                            }
-                        }
-                    }
-                }
-                // Case 4: Exon overlapping the region but shifted to the right
-                //             <--------->
-                //   |--------------|
-                else if start <= exon.start && exon.start <= end && end < exon.end {
-                    flag_gene_body = true;
-
-                    // Handle upstream portion before exon
-                    if start < exon.start && is_first_exon {
-                        let region_overlap = exon.start - start;
-                        let pctg_region_r = (region_overlap as f64 / region_length as f64) * 100.0;
-
-                        if gene.strand == Strand::Negative {
-                            let candidate = Candidate::new(
-                                exon.start,
-                                exon.end,
-                                gene.strand,
-                                exon_number.clone(),
-                                Area::Downstream,
-                                transcript.transcript_id.clone(),
-                                gene.gene_id.clone(),
-                                0,
-                                pctg_region_r,
-                                -1.0,
-                                tss_distance,
-                            );
-                            if config.tts > 0.0 {
-                                let exon_info = TtsExonInfo {
-                                    start: candidate.start,
-                                    end: candidate.end,
-                                    strand: candidate.strand,
-                                    distance: candidate.distance,
-                                };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tts(start, end, &exon_info, config.tts)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Downstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
-                                }
+                            let region_overlap = if next_exon.start > end {
+                                end - exon.end
                             } else {
-                                final_output.push(candidate);
-                            }
-                        } else {
-                            let candidate = Candidate::new(
-                                exon.start,
-                                exon.end,
-                                gene.strand,
-                                exon_number.clone(),
-                                Area::Upstream,
-                                transcript.transcript_id.clone(),
-                                gene.gene_id.clone(),
-                                0,
-                                pctg_region_r,
-                                -1.0,
-                                tss_distance,
-                            );
-                            let exon_info = TssExonInfo {
-                                start: candidate.start,
-                                end: candidate.end,
-                                strand: candidate.strand,
-                                distance: candidate.distance,
+                                next_exon.start - exon.end - 1
                             };
-                            for (tag, pctg_dhs, pctg_a) in
-                                check_tss(start, end, &exon_info, config.tss, config.promoter)
-                            {
-                                final_output.push(Candidate::new(
-                                    candidate.start,
-                                    candidate.end,
-                                    candidate.strand,
-                                    candidate.exon_number.clone(),
-                                    tag.parse().unwrap_or(Area::Upstream),
-                                    candidate.transcript.clone(),
-                                    candidate.gene.clone(),
-                                    candidate.distance,
-                                    pctg_dhs,
-                                    pctg_a,
-                                    tss_distance,
-                                ));
+                            my_introns.entry(my_id()).or_default().push((
+                                new_intron(
+                                    &intron_number,
+                                    percentage(region_overlap, region_length),
+                                    percentage(region_overlap, intron_length),
+                                ),
+                                intron_length,
+                                region_overlap,
+                            ));
+
+                            if next_exon.start > end {
+                                break;
                             }
                         }
                     }
-
-                    let region_overlap = end - exon.start + 1;
-                    let pctg_region = (region_overlap as f64 / region_length as f64) * 100.0;
-                    let pctg_area = (region_overlap as f64 / exon_length as f64) * 100.0;
-
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
-                    {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        ));
-                    } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
-
-                        let gb_candidate = Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::GeneBody,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        );
-                        my_gene_bodys.entry(my_id).or_default().push((
-                            gb_candidate,
-                            exon_length,
-                            region_overlap,
-                        ));
-                    }
-                }
-                // Case 5: Region completely within the exon
-                //             <----------------->
-                //                 |---------|
-                else if exon.start <= start && start <= exon.end && end < exon.end {
-                    flag_gene_body = true;
-                    let pctg_region = 100.0;
-                    let pctg_area = (region_length as f64 / exon_length as f64) * 100.0;
-
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
-                    {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        ));
-                    } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
-
-                        let gb_candidate = Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::GeneBody,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        );
-                        my_gene_bodys.entry(my_id).or_default().push((
-                            gb_candidate,
-                            exon_length,
-                            region_length,
-                        ));
-                    }
                 }
                 // Case 6: Exon totally after the region
                 //                       <----------------->
                 //   |---------|
-                else if exon.start > end && is_first_exon {
-                    let dist_tmp = exon.start - pm;
+                else if is_first_exon {
+                    let dist_tmp = -offset(exon.start);
+
+                    if let Some(nearest) = nearest.as_mut() {
+                        let area = match gene.strand {
+                            Strand::Positive => Area::Upstream,
+                            Strand::Negative => Area::Downstream,
+                        };
+                        record_nearest(
+                            nearest,
+                            new_candidate(area, &exon_number, dist_tmp, 100.0, -1.0),
+                        );
+                    }
 
                     if gene.strand == Strand::Negative && dist_tmp < down {
                         down = dist_tmp;
-                        exon_down = Some(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
+                        exon_down = Some(new_candidate(
                             Area::Downstream,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
+                            &exon_number,
                             down,
                             100.0,
                             -1.0,
-                            tss_distance,
                         ));
                     } else if gene.strand == Strand::Positive && dist_tmp < upst {
                         upst = dist_tmp;
-                        exon_up = Some(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
+                        exon_up = Some(new_candidate(
                             Area::Upstream,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
+                            &exon_number,
                             upst,
                             100.0,
                             -1.0,
-                            tss_distance,
                         ));
                     }
 
@@ -969,61 +654,44 @@ pub fn match_region_to_genes(
         }
     }
 
-    // Report closest downstream/upstream if applicable
-    if let Some(exon_down_val) = exon_down {
-        if down <= upst && exon_down_val.distance <= config.distance {
-            if config.tts > 0.0 {
-                let exon_info = TtsExonInfo {
-                    start: exon_down_val.start,
-                    end: exon_down_val.end,
-                    strand: exon_down_val.strand,
-                    distance: exon_down_val.distance,
-                };
-                for (tag, pctg_dhs, pctg_a) in check_tts(start, end, &exon_info, config.tts) {
-                    final_output.push(Candidate::new(
-                        exon_down_val.start,
-                        exon_down_val.end,
-                        exon_down_val.strand,
-                        exon_down_val.exon_number.clone(),
-                        tag.parse().unwrap_or(Area::Downstream),
-                        exon_down_val.transcript.clone(),
-                        exon_down_val.gene.clone(),
-                        exon_down_val.distance,
-                        pctg_dhs,
-                        pctg_a,
-                        exon_down_val.tss_distance,
-                    ));
-                }
+    if let Some(nearest) = nearest {
+        // Report the closest proximity candidate of every gene within range
+        for candidate in nearest.into_values() {
+            if candidate.distance > distance_limit(&candidate.area) {
+                continue;
+            }
+            if candidate.area == Area::Downstream {
+                push_downstream(&mut final_output, candidate, start, end, config);
             } else {
-                final_output.push(exon_down_val);
+                push_upstream(&mut final_output, candidate, start, end, config);
+            }
+        }
+    } else if python {
+        // The closest distances seen pick the direction, even when an overlap
+        // has since replaced the candidate of that direction
+        if let Some(exon_down_val) = exon_down.filter(|c| down <= upst && c.distance <= down_limit)
+        {
+            push_downstream(&mut final_output, exon_down_val, start, end, config);
+        }
+        if let Some(exon_up_val) = exon_up.filter(|c| upst <= down && c.distance <= up_limit) {
+            push_upstream(&mut final_output, exon_up_val, start, end, config);
+        }
+    } else {
+        // Report the closest downstream/upstream candidate within its limit
+        let exon_down = exon_down.filter(|c| c.distance <= down_limit);
+        let exon_up = exon_up.filter(|c| c.distance <= up_limit);
+        let down = exon_down.as_ref().map_or(i64::MAX, |c| c.distance);
+        let upst = exon_up.as_ref().map_or(i64::MAX, |c| c.distance);
+
+        if let Some(exon_down_val) = exon_down {
+            if down <= upst {
+                push_downstream(&mut final_output, exon_down_val, start, end, config);
             }
         }
-    }
 
-    if let Some(exon_up_val) = exon_up {
-        if upst <= down && exon_up_val.distance <= config.distance {
-            let exon_info = TssExonInfo {
-                start: exon_up_val.start,
-                end: exon_up_val.end,
-                strand: exon_up_val.strand,
-                distance: exon_up_val.distance,
-            };
-            for (tag, pctg_dhs, pctg_a) in
-                check_tss(start, end, &exon_info, config.tss, config.promoter)
-            {
-                final_output.push(Candidate::new(
-                    exon_up_val.start,
-                    exon_up_val.end,
-                    exon_up_val.strand,
-                    exon_up_val.exon_number.clone(),
-                    tag.parse().unwrap_or(Area::Upstream),
-                    exon_up_val.transcript.clone(),
-                    exon_up_val.gene.clone(),
-                    exon_up_val.distance,
-                    pctg_dhs,
-                    pctg_a,
-                    exon_up_val.tss_distance,
-                ));
+        if let Some(exon_up_val) = exon_up {
+            if upst <= down {
+                push_upstream(&mut final_output, exon_up_val, start, end, config);
             }
         }
     }
@@ -1037,21 +705,177 @@ pub fn match_region_to_genes(
         final_output.extend(aggregate_entries(my_introns, region_length));
     }
 
+    if config.flanking {
+        final_output = select_flanking(final_output, &flanks);
+    }
+
+    // Report one distance per gene, from its 5'-most TSS, once the areas are set
+    if config.gene_tss_distance {
+        for candidate in &mut final_output {
+            if let Some(&tss_distance) = gene_tss.get(candidate.gene.as_str()) {
+                candidate.tss_distance = tss_distance;
+                candidate.distance = tss_distance.abs();
+            }
+        }
+    }
+
     final_output
 }
 
+/// Check whether a stranded region overlaps a gene on the opposite strand.
+///
+/// Returns None for unstranded regions. Genes must be sorted by start.
+pub fn overlaps_antisense_gene(region: &Region, genes: &[Gene], last_index: usize) -> Option<bool> {
+    let region_strand = region.strand()?;
+    Some(
+        genes
+            .iter()
+            .skip(last_index)
+            .take_while(|gene| gene.start <= region.end)
+            .any(|gene| gene.end >= region.start && gene.strand != region_strand),
+    )
+}
+
+/// Match a region to genes and prepare its candidates for output.
+///
+/// Runs the matching and the report-level selection, then the optional
+/// per-region steps (INTERGENIC rows, antisense flag).
+pub fn annotate_region(
+    region: &Region,
+    genes: &[Gene],
+    config: &Config,
+    last_index: usize,
+) -> Vec<Candidate> {
+    let mut processed = match_and_process(region, genes, config, last_index);
+    fill_unmatched(region, &mut processed, config);
+
+    if config.report_antisense {
+        let antisense = overlaps_antisense_gene(region, genes, last_index);
+        for candidate in &mut processed {
+            candidate.antisense = antisense;
+        }
+    }
+
+    processed
+}
+
+/// Build the placeholder candidate reported for a region without gene associations.
+pub fn intergenic_candidate(region: &Region) -> Candidate {
+    Candidate::new(
+        region.start,
+        region.end,
+        Strand::Positive,
+        "NA".to_string(),
+        Area::Intergenic,
+        "NA".to_string(),
+        "NA".to_string(),
+        0,
+        0.0,
+        0.0,
+        0,
+    )
+}
+
+/// Append the INTERGENIC placeholder when unmatched regions are reported.
+pub fn fill_unmatched(region: &Region, candidates: &mut Vec<Candidate>, config: &Config) {
+    if config.report_unmatched && candidates.is_empty() {
+        candidates.push(intergenic_candidate(region));
+    }
+}
+
+/// Select the candidates to report and score them if requested.
 pub fn process_candidates_for_output(
     candidates: Vec<Candidate>,
     config: &Config,
 ) -> Vec<Candidate> {
+    let mut processed = match config.level {
+        // Every level selects from the same candidates
+        ReportLevel::Levels => [
+            ReportLevel::Exon,
+            ReportLevel::Transcript,
+            ReportLevel::Gene,
+        ]
+        .into_iter()
+        .flat_map(|level| {
+            select_candidates(candidates.clone(), config, level)
+                .into_iter()
+                .map(move |c| Candidate {
+                    level: Some(level),
+                    ..c
+                })
+        })
+        .collect(),
+        level => select_candidates(candidates, config, level),
+    };
+
+    if let Some(weights) = &config.confidence {
+        for candidate in &mut processed {
+            candidate.confidence = Some(confidence_score(
+                candidate,
+                weights,
+                &config.rules,
+                config.distance_limit(&candidate.area),
+            ));
+        }
+    }
+
+    processed
+}
+
+/// Select the candidates to report at the given report level, according to
+/// the filters.
+fn select_candidates(
+    candidates: Vec<Candidate>,
+    config: &Config,
+    level: ReportLevel,
+) -> Vec<Candidate> {
+    // Against a BED annotation or gene extents each feature/gene gets a
+    // single association
+    let candidates = if config.features {
+        classify_features(candidates, Area::Overlap)
+    } else if config.gene_extent {
+        classify_features(candidates, Area::OverlapGene)
+    } else {
+        candidates
+    };
+
+    // Report-all mode leaves the selection and filtering to the user
+    if level == ReportLevel::All {
+        return candidates;
+    }
+
+    // Exclusive rules drop the areas they do not list
+    let candidates = if config.exclusive_rules {
+        candidates
+            .into_iter()
+            .filter(|c| config.rules.contains(&c.area))
+            .collect()
+    } else {
+        candidates
+    };
+
+    // TSS calls of poorly supported transcripts give way to better ones
+    let candidates = match config.max_tsl {
+        Some(max_tsl) => filter_tss_support(candidates, max_tsl),
+        None => candidates,
+    };
+
+    // In reciprocal-overlap mode the overlap is a hard filter instead of
+    // the percentage thresholds used to break ties
+    let (candidates, perc_region, perc_area) = match config.reciprocal_overlap {
+        Some(fraction) => (filter_reciprocal_overlap(candidates, fraction), 0.0, 0.0),
+        None => (candidates, config.perc_region, config.perc_area),
+    };
+
     if candidates.is_empty() {
         return candidates;
     }
 
     // filter_by_transcript helper removed (unused logic)
 
-    match config.level {
-        ReportLevel::Exon => {
+    let processed = match level {
+        ReportLevel::Levels => unreachable!("levels are selected one at a time"),
+        ReportLevel::Exon | ReportLevel::All => {
             // Exon Level Logic:
             // Testing confirms that Golden Output behaves as if NO filtering is applied
             // (except for a small set of ~60 edge cases).
@@ -1071,13 +895,14 @@ pub fn process_candidates_for_output(
                     .push(i);
             }
 
-            apply_rules(
+            let transcript_results = apply_rules(
                 &candidates,
                 &by_transcript,
-                config.perc_region,
-                config.perc_area,
+                perc_region,
+                perc_area,
                 &config.rules,
-            )
+            );
+            break_ties(transcript_results, config.tie_break)
         }
         ReportLevel::Gene => {
             // Gene Level Logic: Best transcript per gene.
@@ -1094,10 +919,11 @@ pub fn process_candidates_for_output(
             let transcript_results = apply_rules(
                 &candidates,
                 &by_transcript,
-                config.perc_region,
-                config.perc_area,
+                perc_region,
+                perc_area,
                 &config.rules,
             );
+            let transcript_results = break_ties(transcript_results, config.tie_break);
 
             // 2. Select best transcript per gene
             let mut by_gene: AHashMap<String, Vec<usize>> = AHashMap::new();
@@ -1107,7 +933,60 @@ pub fn process_candidates_for_output(
 
             select_transcript(&transcript_results, &by_gene, &config.rules)
         }
+    };
+
+    apply_region_caps(processed, config)
+}
+
+/// Apply the per-region limits (nearest-k genes, nearest gene per strand,
+/// maximum hits, single best association).
+fn apply_region_caps(candidates: Vec<Candidate>, config: &Config) -> Vec<Candidate> {
+    let candidates = match config.nearest_k {
+        Some(k) => select_nearest_genes(candidates, k),
+        None if config.nearest_per_strand => select_nearest_per_strand(candidates),
+        None => candidates,
+    };
+
+    let candidates = match config.max_hits {
+        Some(max_hits) => cap_candidates(candidates, max_hits, &config.rules),
+        None => candidates,
+    };
+
+    if config.best_per_region {
+        // The percentage thresholds do not apply in reciprocal-overlap mode
+        let (perc_region, perc_area) = match config.reciprocal_overlap {
+            Some(_) => (0.0, 0.0),
+            None => (config.perc_region, config.perc_area),
+        };
+        return select_best(candidates, perc_region, perc_area, &config.rules);
+    }
+    candidates
+}
+
+/// Match a region to genes and select the candidates to report.
+///
+/// With per-biotype rule sets, each set matches and reports its own genes
+/// and the per-region limits then apply to the merged candidates.
+fn match_and_process(
+    region: &Region,
+    genes: &[Gene],
+    config: &Config,
+    last_index: usize,
+) -> Vec<Candidate> {
+    if config.rule_sets.is_empty() {
+        let candidates = match_region_to_genes(region, genes, config, last_index);
+        return process_candidates_for_output(candidates, config);
     }
+
+    let merged = config
+        .rule_sets
+        .iter()
+        .flat_map(|set| {
+            let candidates = match_region_to_genes(region, genes, set, last_index);
+            process_candidates_for_output(candidates, set)
+        })
+        .collect();
+    apply_region_caps(merged, config)
 }
 
 /// Main entry point for matching regions to genes.
@@ -1125,6 +1004,17 @@ pub fn match_regions_to_genes(
     let mut last_index = 0;
 
     for region in regions {
+        // Regions reaching further than the configured distance look back on
+        // their own, without moving the shared start index
+        let region_lookback = max_gene_length + config.region_lookback_distance(region);
+        if region_lookback > max_lookback {
+            let search_start = region.start.saturating_sub(region_lookback);
+            let start_index = find_search_start_index(genes, search_start).min(last_index);
+            let processed = match_and_process(region, genes, config, start_index);
+            results.push((region.clone(), processed));
+            continue;
+        }
+
         // Calculate safe search start for this region
         // We need to look back enough to find genes that started earlier but extend into this region
         let search_start = region.start.saturating_sub(max_lookback);
@@ -1137,8 +1027,7 @@ pub fn match_regions_to_genes(
         }
 
         // Pass the calculated start index by value (no mutation allowed inside)
-        let candidates = match_region_to_genes(region, genes, config, last_index);
-        let processed = process_candidates_for_output(candidates, config);
+        let processed = match_and_process(region, genes, config, last_index);
         results.push((region.clone(), processed));
     }
 
@@ -0,0 +1,278 @@
+//! Promoter-only matching against precomputed TSS windows.
+//!
+//! Every transcript gets a single window covering its TSS and promoter
+//! areas upstream of the TSS. Regions are matched against these windows only,
+//! skipping the exon/intron logic of the full matcher.
+
+use ahash::AHashMap;
+
+use crate::config::Config;
+use crate::matcher::overlap::{fill_unmatched, process_candidates_for_output};
+use crate::matcher::RegionIndex;
+use crate::parser::gtf::GtfData;
+use crate::types::{Area, Candidate, Region, Strand};
+
+/// TSS and promoter window of a single transcript.
+#[derive(Debug, Clone)]
+pub struct PromoterWindow {
+    pub gene_id: String,
+    pub transcript_id: String,
+    pub strand: Strand,
+    /// Transcript TSS (start for positive strand, end for negative strand).
+    pub tss: i64,
+    /// TSS area, the `tss` bp upstream of the TSS.
+    pub tss_area: (i64, i64),
+    /// Promoter area, `promoter` bp upstream of the TSS area.
+    pub promoter_area: (i64, i64),
+    /// Window start (lowest coordinate of both areas).
+    pub start: i64,
+    /// Window end (highest coordinate of both areas).
+    pub end: i64,
+    /// Transcript support level, if known.
+    pub tsl: Option<u8>,
+}
+
+impl PromoterWindow {
+    /// Build the window of a transcript with the given TSS.
+    fn new(
+        gene_id: &str,
+        transcript_id: &str,
+        strand: Strand,
+        tss: i64,
+        tss_len: i64,
+        promoter_len: i64,
+    ) -> Self {
+        let (tss_area, promoter_area) = match strand {
+            Strand::Positive => (
+                (tss - tss_len, tss - 1),
+                (tss - tss_len - promoter_len, tss - tss_len - 1),
+            ),
+            Strand::Negative => (
+                (tss + 1, tss + tss_len),
+                (tss + tss_len + 1, tss + tss_len + promoter_len),
+            ),
+        };
+        PromoterWindow {
+            gene_id: gene_id.to_string(),
+            transcript_id: transcript_id.to_string(),
+            strand,
+            tss,
+            tss_area,
+            promoter_area,
+            start: tss_area.0.min(promoter_area.0),
+            end: tss_area.1.max(promoter_area.1),
+            tsl: None,
+        }
+    }
+}
+
+/// Overlap of a region with an area, as (overlap, area length).
+fn area_overlap(region: &Region, (start, end): (i64, i64)) -> (i64, i64) {
+    let overlap = region.end.min(end) - region.start.max(start) + 1;
+    (overlap.max(0), end - start + 1)
+}
+
+/// Promoter windows of all chromosomes.
+#[derive(Debug, Clone, Default)]
+pub struct PromoterIndex {
+    windows_by_chrom: AHashMap<String, Vec<PromoterWindow>>,
+    /// Length of every window.
+    window_length: i64,
+}
+
+impl PromoterIndex {
+    /// Build one window per transcript using the TSS and promoter sizes of the config.
+    pub fn build(gtf_data: &GtfData, config: &Config) -> Self {
+        let tss_len = config.tss as i64;
+        let promoter_len = config.promoter as i64;
+
+        let mut windows_by_chrom = AHashMap::new();
+        for (chrom, genes) in &gtf_data.genes_by_chrom {
+            let mut windows: Vec<PromoterWindow> = genes
+                .iter()
+                .flat_map(|gene| {
+                    gene.transcripts.iter().map(move |transcript| {
+                        let tss = match gene.strand {
+                            Strand::Positive => transcript.start,
+                            Strand::Negative => transcript.end,
+                        };
+                        PromoterWindow {
+                            tsl: transcript.tsl,
+                            ..PromoterWindow::new(
+                                &gene.gene_id,
+                                &transcript.transcript_id,
+                                gene.strand,
+                                tss,
+                                tss_len,
+                                promoter_len,
+                            )
+                        }
+                    })
+                })
+                .collect();
+            windows.sort_by_key(|w| w.start);
+            windows_by_chrom.insert(chrom.clone(), windows);
+        }
+
+        PromoterIndex {
+            windows_by_chrom,
+            window_length: tss_len + promoter_len,
+        }
+    }
+
+    /// Get the windows of a chromosome.
+    pub fn windows(&self, chrom: &str) -> &[PromoterWindow] {
+        self.windows_by_chrom
+            .get(chrom)
+            .map_or(&[], |windows| windows.as_slice())
+    }
+
+    /// Match a region against the windows it overlaps.
+    ///
+    /// Each overlapped TSS or promoter area gives a candidate, like the
+    /// upstream split of the full matcher.
+    pub fn match_region(&self, region: &Region, config: &Config) -> Vec<Candidate> {
+        let windows = self.windows(&region.chrom);
+        let region_strand = region.strand();
+        let pm = region.midpoint();
+
+        // All windows have the same length, so only those starting within it
+        // before the region can overlap
+        let last = windows.partition_point(|w| w.start <= region.end);
+        let first =
+            windows[..last].partition_point(|w| w.start < region.start - self.window_length);
+
+        let mut candidates = Vec::new();
+        for window in &windows[first..last] {
+            if window.end < region.start
+                || !config.strand_mode.accepts(region_strand, window.strand)
+            {
+                continue;
+            }
+
+            let tss_distance = match window.strand {
+                Strand::Positive => pm - window.tss,
+                Strand::Negative => window.tss - pm,
+            };
+            // Regions reaching the TSS overlap the transcript
+            let distance = if region.start <= window.tss && window.tss <= region.end {
+                0
+            } else {
+                (-tss_distance).max(0)
+            };
+
+            for (area, bounds) in [
+                (Area::Tss, window.tss_area),
+                (Area::Promoter, window.promoter_area),
+            ] {
+                let (overlap, area_length) = area_overlap(region, bounds);
+                if overlap == 0 || area_length <= 0 {
+                    continue;
+                }
+                let mut candidate = Candidate::new(
+                    window.start,
+                    window.end,
+                    window.strand,
+                    "1".to_string(),
+                    area,
+                    window.transcript_id.clone(),
+                    window.gene_id.clone(),
+                    distance,
+                    overlap as f64 / region.length() as f64 * 100.0,
+                    overlap as f64 / area_length as f64 * 100.0,
+                    tss_distance,
+                );
+                candidate.tsl = window.tsl;
+                candidates.push(candidate);
+            }
+        }
+
+        candidates
+    }
+}
+
+impl RegionIndex for PromoterIndex {
+    fn annotate_region(&self, region: &Region, config: &Config) -> Vec<Candidate> {
+        let candidates = self.match_region(region, config);
+        let mut processed = process_candidates_for_output(candidates, config);
+        fill_unmatched(region, &mut processed, config);
+        processed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Exon, Gene, Transcript};
+
+    fn make_gene(gene_id: &str, strand: Strand, start: i64, end: i64) -> Gene {
+        let mut gene = Gene::new(gene_id.to_string(), strand);
+        gene.set_length(start, end);
+        let mut transcript = Transcript::new(format!("T_{}", gene_id));
+        transcript.add_exon(Exon::new(start, end));
+        transcript.calculate_size();
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    fn make_index() -> PromoterIndex {
+        let mut gtf_data = GtfData {
+            genes_by_chrom: AHashMap::new(),
+            max_lengths: AHashMap::new(),
+        };
+        gtf_data.genes_by_chrom.insert(
+            "chr1".to_string(),
+            vec![
+                make_gene("A", Strand::Positive, 10_000, 20_000),
+                make_gene("B", Strand::Negative, 30_000, 40_000),
+            ],
+        );
+        PromoterIndex::build(&gtf_data, &Config::default())
+    }
+
+    #[test]
+    fn test_windows() {
+        let index = make_index();
+        let windows = index.windows("chr1");
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].tss_area, (9_800, 9_999));
+        assert_eq!(windows[0].promoter_area, (8_500, 9_799));
+        assert_eq!(windows[1].tss_area, (40_001, 40_200));
+        assert_eq!(windows[1].promoter_area, (40_201, 41_500));
+        assert!(index.windows("chr2").is_empty());
+    }
+
+    #[test]
+    fn test_match_region_splits_tss_and_promoter() {
+        let index = make_index();
+        let config = Config::default();
+
+        let region = Region::new("chr1".to_string(), 9_700, 9_899, vec![]);
+        let candidates = index.match_region(&region, &config);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].area, Area::Tss);
+        assert_eq!(candidates[0].pctg_region, 50.0);
+        assert_eq!(candidates[1].area, Area::Promoter);
+        assert_eq!(candidates[1].pctg_region, 50.0);
+        assert_eq!(candidates[0].tss_distance, -201);
+        assert_eq!(candidates[0].distance, 201);
+
+        // Regions reaching the TSS are at distance 0
+        let region = Region::new("chr1".to_string(), 9_900, 10_100, vec![]);
+        let candidates = index.match_region(&region, &config);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].area, Area::Tss);
+        assert_eq!(candidates[0].distance, 0);
+
+        // Negative strand: upstream is to the right of the TSS
+        let region = Region::new("chr1".to_string(), 41_000, 41_100, vec![]);
+        let candidates = index.match_region(&region, &config);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].gene, "B");
+        assert_eq!(candidates[0].area, Area::Promoter);
+
+        // Gene bodies are not matched
+        let region = Region::new("chr1".to_string(), 15_000, 15_100, vec![]);
+        assert!(index.match_region(&region, &config).is_empty());
+    }
+}
@@ -5,7 +5,7 @@
 
 use ahash::{AHashMap, AHashSet};
 
-use crate::types::{Area, Candidate};
+use crate::types::{Area, Candidate, ConfidenceWeights, Strand, TieBreak};
 
 /// Order keys by their first appearance in the candidates list.
 ///
@@ -132,9 +132,9 @@ pub fn apply_rules(
                     // Step 4: Apply rules priority order for final selection
                     // Report all that match the first matching rule (ties allowed)
                     let mut found = false;
-                    for &area_rule in rules {
+                    for area_rule in rules {
                         for &candidate in &region_candidates {
-                            if candidate.area == area_rule {
+                            if candidate.area == *area_rule {
                                 to_report.push(candidate.clone());
                                 found = true;
                             }
@@ -184,13 +184,13 @@ pub fn select_transcript(
         let mut by_area: AHashMap<Area, Vec<usize>> = AHashMap::new();
         for &pos in positions {
             let candidate = &candidates[pos];
-            by_area.entry(candidate.area).or_default().push(pos);
+            by_area.entry(candidate.area.clone()).or_default().push(pos);
         }
 
         // Apply rules to find winning area
-        let mut area_winner: Option<Area> = None;
-        for &area_rule in rules {
-            if by_area.contains_key(&area_rule) {
+        let mut area_winner: Option<&Area> = None;
+        for area_rule in rules {
+            if by_area.contains_key(area_rule) {
                 area_winner = Some(area_rule);
                 break;
             }
@@ -200,7 +200,7 @@ pub fn select_transcript(
         // "First" means the first one in the list of positions, which preserves order.
         if area_winner.is_none() {
             if let Some(&first_pos) = positions.first() {
-                area_winner = Some(candidates[first_pos].area);
+                area_winner = Some(&candidates[first_pos].area);
             }
         }
 
@@ -209,7 +209,7 @@ pub fn select_transcript(
             None => continue,
         };
 
-        let winner_positions = &by_area[&area_winner];
+        let winner_positions = &by_area[area_winner];
 
         if winner_positions.len() == 1 {
             to_report.push(candidates[winner_positions[0]].clone());
@@ -217,6 +217,7 @@ pub fn select_transcript(
             // Merge all tied candidates
             let mut transcripts = String::new();
             let mut exons = String::new();
+            let mut tss_distances = String::new();
             let mut max_parea = 0.0_f64;
             let mut max_pregion = 0.0_f64;
 
@@ -226,6 +227,8 @@ pub fn select_transcript(
                 transcripts.push(',');
                 exons.push_str(&c.exon_number);
                 exons.push(',');
+                tss_distances.push_str(&c.tss_distance.to_string());
+                tss_distances.push(',');
                 max_parea = max_parea.max(c.pctg_area);
                 max_pregion = max_pregion.max(c.pctg_region);
             }
@@ -233,15 +236,16 @@ pub fn select_transcript(
             // Remove trailing comma
             transcripts.pop();
             exons.pop();
+            tss_distances.pop();
 
             // Use first candidate as reference for other fields
             let ref_candidate = &candidates[winner_positions[0]];
-            let merged = Candidate::new(
+            let mut merged = Candidate::new(
                 ref_candidate.start,
                 ref_candidate.end,
                 ref_candidate.strand,
                 exons,
-                ref_candidate.area,
+                ref_candidate.area.clone(),
                 transcripts,
                 ref_candidate.gene.clone(),
                 ref_candidate.distance,
@@ -249,6 +253,7 @@ pub fn select_transcript(
                 max_parea,
                 ref_candidate.tss_distance,
             );
+            merged.tss_distances = Some(tss_distances);
             to_report.push(merged);
         }
     }
@@ -256,6 +261,225 @@ pub fn select_transcript(
     to_report
 }
 
+/// Keep only the candidates belonging to the `k` genes closest to the region.
+///
+/// Genes are ranked by their smallest candidate distance (overlaps count as 0).
+/// Ties keep the order in which the genes first appear, and the surviving
+/// candidates retain their original order.
+pub fn select_nearest_genes(candidates: Vec<Candidate>, k: usize) -> Vec<Candidate> {
+    let mut ranked: Vec<(&str, i64)> = Vec::new();
+    let mut index: AHashMap<&str, usize> = AHashMap::new();
+
+    for c in &candidates {
+        match index.get(c.gene.as_str()) {
+            Some(&i) => ranked[i].1 = ranked[i].1.min(c.distance),
+            None => {
+                index.insert(&c.gene, ranked.len());
+                ranked.push((&c.gene, c.distance));
+            }
+        }
+    }
+
+    if ranked.len() <= k {
+        return candidates;
+    }
+
+    // Stable sort keeps first-appearance order for equal distances
+    ranked.sort_by_key(|&(_, distance)| distance);
+    let keep: AHashSet<String> = ranked
+        .iter()
+        .take(k)
+        .map(|(gene, _)| gene.to_string())
+        .collect();
+
+    candidates
+        .into_iter()
+        .filter(|c| keep.contains(&c.gene))
+        .collect()
+}
+
+/// Keep only the candidates belonging to the gene closest to the region on
+/// each strand.
+///
+/// Genes are ranked as in [`select_nearest_genes`], separately for the plus
+/// and minus strand genes.
+pub fn select_nearest_per_strand(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut nearest: [Option<(&str, i64)>; 2] = [None, None];
+    for c in &candidates {
+        let slot = &mut nearest[usize::from(c.strand == Strand::Negative)];
+        match slot {
+            Some((_, distance)) if *distance <= c.distance => {}
+            _ => *slot = Some((&c.gene, c.distance)),
+        }
+    }
+
+    let keep: Vec<String> = nearest
+        .iter()
+        .flatten()
+        .map(|(gene, _)| gene.to_string())
+        .collect();
+    candidates
+        .into_iter()
+        .filter(|c| keep.contains(&c.gene))
+        .collect()
+}
+
+/// Drop the TSS calls of poorly supported transcripts of the genes that also
+/// have a TSS call from a well supported transcript.
+///
+/// TSS calls are the upstream areas (TSS, PROMOTER, UPSTREAM, zones) and the
+/// first exon. A transcript is well supported when its TSL is at most
+/// `max_tsl`; genes without such a TSS call fall back to all of theirs.
+pub fn filter_tss_support(candidates: Vec<Candidate>, max_tsl: u8) -> Vec<Candidate> {
+    let is_tss_call = |c: &Candidate| c.area.is_upstream() || c.area == Area::FirstExon;
+    let is_supported = |c: &Candidate| c.tsl.is_some_and(|tsl| tsl <= max_tsl);
+
+    let supported_genes: AHashSet<String> = candidates
+        .iter()
+        .filter(|c| is_tss_call(c) && is_supported(c))
+        .map(|c| c.gene.clone())
+        .collect();
+    candidates
+        .into_iter()
+        .filter(|c| !is_tss_call(c) || is_supported(c) || !supported_genes.contains(&c.gene))
+        .collect()
+}
+
+/// Keep only the candidates whose overlap covers at least `fraction` of both
+/// the region and the feature (reciprocal overlap, like `bedtools -f -r`).
+///
+/// Proximity-only candidates (no feature overlap) never pass.
+pub fn filter_reciprocal_overlap(candidates: Vec<Candidate>, fraction: f64) -> Vec<Candidate> {
+    let min_pctg = fraction * 100.0;
+    candidates
+        .into_iter()
+        .filter(|c| c.pctg_region >= min_pctg && c.pctg_area >= min_pctg)
+        .collect()
+}
+
+/// Keep at most `max_hits` candidates, preferring higher-priority areas and
+/// then smaller distances.
+///
+/// Areas missing from the rules rank after all listed areas. The surviving
+/// candidates retain their original order.
+pub fn cap_candidates(
+    candidates: Vec<Candidate>,
+    max_hits: usize,
+    rules: &[Area],
+) -> Vec<Candidate> {
+    if candidates.len() <= max_hits {
+        return candidates;
+    }
+
+    let rank = |c: &Candidate| {
+        let priority = rules
+            .iter()
+            .position(|a| *a == c.area)
+            .unwrap_or(rules.len());
+        (priority, c.distance)
+    };
+
+    // Stable sort keeps the original order for equal ranks
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.sort_by_key(|&i| rank(&candidates[i]));
+    let keep: AHashSet<usize> = order.into_iter().take(max_hits).collect();
+
+    candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| keep.contains(i))
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Keep the single best candidate, applying the priority rules across all of
+/// them (e.g. across the genes of a region).
+///
+/// Candidates still tied after the rules go to the closest one, then to the
+/// first one.
+pub fn select_best(
+    candidates: Vec<Candidate>,
+    perc_region: f64,
+    perc_area: f64,
+    rules: &[Area],
+) -> Vec<Candidate> {
+    let all = AHashMap::from([(String::new(), (0..candidates.len()).collect())]);
+    let best = apply_rules(&candidates, &all, perc_region, perc_area, rules);
+    if best.is_empty() {
+        // None of the areas is ranked by the rules
+        return cap_candidates(candidates, 1, rules);
+    }
+    cap_candidates(best, 1, rules)
+}
+
+/// Keep a single candidate per transcript among those tied after the rules.
+///
+/// Candidates of a transcript are ranked by the tie-break key and the first
+/// best one is kept, in its original position. `TieBreak::None` keeps them all.
+pub fn break_ties(candidates: Vec<Candidate>, tie_break: TieBreak) -> Vec<Candidate> {
+    if tie_break == TieBreak::None {
+        return candidates;
+    }
+    let key = |c: &Candidate| match tie_break {
+        TieBreak::Tss => (c.tss_distance.abs(), c.distance.abs()),
+        _ => (c.distance.abs(), c.tss_distance.abs()),
+    };
+
+    let mut best: AHashMap<&str, usize> = AHashMap::new();
+    for (i, c) in candidates.iter().enumerate() {
+        best.entry(c.transcript.as_str())
+            .and_modify(|b| {
+                if key(c) < key(&candidates[*b]) {
+                    *b = i;
+                }
+            })
+            .or_insert(i);
+    }
+    let keep: AHashSet<usize> = best.into_values().collect();
+
+    candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| keep.contains(i))
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Confidence score of a candidate between 0 and 1.
+///
+/// Weighted mean of four terms, each between 0 and 1: the fraction of the
+/// region overlapped, the fraction of the area overlapped (0 for proximity
+/// areas), the priority of the area in the rules (1 for the first rule, 0 for
+/// unlisted areas) and the closeness to the gene (1 when overlapping, falling
+/// linearly to 0 at `max_distance`).
+pub fn confidence_score(
+    candidate: &Candidate,
+    weights: &ConfidenceWeights,
+    rules: &[Area],
+    max_distance: i64,
+) -> f64 {
+    let region = (candidate.pctg_region / 100.0).clamp(0.0, 1.0);
+    let area = (candidate.pctg_area / 100.0).clamp(0.0, 1.0);
+    let rank = rules
+        .iter()
+        .position(|a| *a == candidate.area)
+        .map_or(0.0, |pos| 1.0 - pos as f64 / rules.len() as f64);
+    let closeness = if candidate.distance <= 0 {
+        1.0
+    } else if max_distance > 0 {
+        (1.0 - candidate.distance as f64 / max_distance as f64).max(0.0)
+    } else {
+        0.0
+    };
+
+    let total = weights.region + weights.area + weights.rank + weights.distance;
+    (weights.region * region
+        + weights.area * area
+        + weights.rank * rank
+        + weights.distance * closeness)
+        / total
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -410,6 +634,7 @@ mod tests {
         c1.exon_number = "1".to_string();
         let mut c2 = make_candidate(Area::Tss, 90.0, 60.0, "T2");
         c2.exon_number = "2".to_string();
+        c2.tss_distance = 150;
 
         let candidates = vec![c1, c2];
         let mut grouped_by = AHashMap::new();
@@ -424,6 +649,7 @@ mod tests {
         assert!(result[0].exon_number.contains("2"));
         assert_eq!(result[0].pctg_region, 90.0); // max of 80, 90
         assert_eq!(result[0].pctg_area, 70.0); // max of 70, 60
+        assert_eq!(result[0].tss_distances.as_deref(), Some("100,150"));
     }
 
     #[test]
@@ -459,4 +685,170 @@ mod tests {
         // Both should be reported (tie)
         assert_eq!(result.len(), 2);
     }
+
+    #[test]
+    fn test_select_nearest_genes() {
+        let mut near = make_candidate(Area::Upstream, 100.0, -1.0, "T1");
+        near.gene = "G_NEAR".to_string();
+        near.distance = 500;
+        let mut far = make_candidate(Area::Downstream, 100.0, -1.0, "T2");
+        far.gene = "G_FAR".to_string();
+        far.distance = 5000;
+        let mut overlap = make_candidate(Area::Intron, 100.0, 10.0, "T3");
+        overlap.gene = "G_OVERLAP".to_string();
+
+        let candidates = vec![far, near, overlap];
+
+        let result = select_nearest_genes(candidates.clone(), 2);
+        let genes: Vec<&str> = result.iter().map(|c| c.gene.as_str()).collect();
+        assert_eq!(genes, vec!["G_NEAR", "G_OVERLAP"]);
+
+        let result = select_nearest_genes(candidates, 5);
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn test_filter_tss_support() {
+        let with_tsl = |area: Area, transcript: &str, gene: &str, tsl: Option<u8>| {
+            let mut c = make_candidate(area, 100.0, 50.0, transcript);
+            c.gene = gene.to_string();
+            c.tsl = tsl;
+            c
+        };
+        let candidates = vec![
+            with_tsl(Area::Tss, "T1", "G1", Some(5)),
+            with_tsl(Area::Promoter, "T2", "G1", Some(1)),
+            with_tsl(Area::Intron, "T3", "G1", None),
+            with_tsl(Area::FirstExon, "T4", "G2", Some(4)),
+        ];
+
+        let result = filter_tss_support(candidates, 2);
+        let transcripts: Vec<&str> = result.iter().map(|c| c.transcript.as_str()).collect();
+        // G2 has no well supported TSS call and keeps its own
+        assert_eq!(transcripts, vec!["T2", "T3", "T4"]);
+    }
+
+    #[test]
+    fn test_select_best() {
+        let rules = vec![Area::Tss, Area::Intron, Area::Upstream];
+        let mut intron = make_candidate(Area::Intron, 100.0, 10.0, "T1");
+        intron.gene = "G1".to_string();
+        let mut far_tss = make_candidate(Area::Tss, 100.0, 50.0, "T2");
+        far_tss.gene = "G2".to_string();
+        far_tss.distance = 150;
+        let mut near_tss = make_candidate(Area::Tss, 100.0, 50.0, "T3");
+        near_tss.gene = "G3".to_string();
+        near_tss.distance = 20;
+
+        let result = select_best(vec![intron, far_tss, near_tss], 50.0, 90.0, &rules);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].gene, "G3");
+        assert!(select_best(Vec::new(), 50.0, 90.0, &rules).is_empty());
+    }
+
+    #[test]
+    fn test_select_nearest_per_strand() {
+        let mut plus_far = make_candidate(Area::Upstream, 100.0, -1.0, "T1");
+        plus_far.gene = "G_PLUS_FAR".to_string();
+        plus_far.distance = 5000;
+        let mut plus_near = make_candidate(Area::Downstream, 100.0, -1.0, "T2");
+        plus_near.gene = "G_PLUS_NEAR".to_string();
+        plus_near.distance = 500;
+        let mut minus = make_candidate(Area::Upstream, 100.0, -1.0, "T3");
+        minus.gene = "G_MINUS".to_string();
+        minus.strand = Strand::Negative;
+        minus.distance = 8000;
+
+        let result = select_nearest_per_strand(vec![plus_far, minus, plus_near]);
+        let genes: Vec<&str> = result.iter().map(|c| c.gene.as_str()).collect();
+        assert_eq!(genes, vec!["G_MINUS", "G_PLUS_NEAR"]);
+    }
+
+    #[test]
+    fn test_filter_reciprocal_overlap() {
+        let candidates = vec![
+            make_candidate(Area::Intron, 80.0, 60.0, "T1"),
+            make_candidate(Area::GeneBody, 100.0, 40.0, "T2"),
+            make_candidate(Area::Upstream, 100.0, -1.0, "T3"),
+            make_candidate(Area::FirstExon, 50.0, 100.0, "T4"),
+        ];
+
+        let result = filter_reciprocal_overlap(candidates.clone(), 0.5);
+        let transcripts: Vec<&str> = result.iter().map(|c| c.transcript.as_str()).collect();
+        assert_eq!(transcripts, vec!["T1", "T4"]);
+
+        assert!(filter_reciprocal_overlap(candidates, 0.9).is_empty());
+    }
+
+    #[test]
+    fn test_cap_candidates() {
+        let rules = vec![Area::Tss, Area::Intron, Area::Upstream];
+
+        let mut far_upstream = make_candidate(Area::Upstream, 100.0, -1.0, "T1");
+        far_upstream.distance = 5000;
+        let intron = make_candidate(Area::Intron, 100.0, 10.0, "T2");
+        let mut near_upstream = make_candidate(Area::Upstream, 100.0, -1.0, "T3");
+        near_upstream.distance = 100;
+        let tss = make_candidate(Area::Tss, 100.0, 50.0, "T4");
+
+        let candidates = vec![far_upstream, intron, near_upstream, tss];
+
+        let result = cap_candidates(candidates.clone(), 3, &rules);
+        let transcripts: Vec<&str> = result.iter().map(|c| c.transcript.as_str()).collect();
+        assert_eq!(transcripts, vec!["T2", "T3", "T4"]);
+
+        let result = cap_candidates(candidates.clone(), 1, &rules);
+        assert_eq!(result[0].transcript, "T4");
+
+        assert_eq!(cap_candidates(candidates, 10, &rules).len(), 4);
+    }
+
+    #[test]
+    fn test_break_ties() {
+        let mut far = make_candidate(Area::Upstream, 100.0, -1.0, "T1");
+        far.distance = 900;
+        far.tss_distance = -900;
+        let mut near = make_candidate(Area::Upstream, 100.0, -1.0, "T1");
+        near.distance = 300;
+        near.tss_distance = -2000;
+        let other = make_candidate(Area::Intron, 100.0, 10.0, "T2");
+        let candidates = vec![far, near, other];
+
+        assert_eq!(break_ties(candidates.clone(), TieBreak::None).len(), 3);
+
+        let result = break_ties(candidates.clone(), TieBreak::Distance);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].distance, 300);
+        assert_eq!(result[1].transcript, "T2");
+
+        let result = break_ties(candidates, TieBreak::Tss);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].distance, 900);
+    }
+
+    #[test]
+    fn test_confidence_score() {
+        let rules = vec![Area::Tss, Area::Intron, Area::Upstream, Area::Downstream];
+        let weights = ConfidenceWeights::default();
+
+        // Full overlap with the top-priority area
+        let tss = make_candidate(Area::Tss, 100.0, 100.0, "T1");
+        assert!((confidence_score(&tss, &weights, &rules, 10_000) - 1.0).abs() < 1e-9);
+
+        // Proximity half way to the maximum distance: region 1, area 0, rank 0.5, closeness 0.5
+        let mut upstream = make_candidate(Area::Upstream, 100.0, -1.0, "T1");
+        upstream.distance = 5000;
+        let score = confidence_score(&upstream, &weights, &rules, 10_000);
+        assert!((score - (0.3 + 0.3 * 0.5 + 0.2 * 0.5)).abs() < 1e-9);
+
+        // Only the distance term
+        let distance_only = ConfidenceWeights {
+            region: 0.0,
+            area: 0.0,
+            rank: 0.0,
+            distance: 2.0,
+        };
+        assert!((confidence_score(&upstream, &distance_only, &rules, 10_000) - 0.5).abs() < 1e-9);
+        assert_eq!(confidence_score(&upstream, &distance_only, &rules, 0), 0.0);
+    }
 }
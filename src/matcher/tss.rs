@@ -3,11 +3,14 @@
 //! This module implements the checkTSS logic with coordinate mirroring
 //! for negative strand genes.
 
-use crate::types::Strand;
+use crate::types::{Area, Strand, Zone};
 
 /// Result of a TSS check: (area_tag, pctg_dhs, pctg_area).
 pub type TssResult = (String, f64, f64);
 
+/// Result of a zone check: (area, pctg_dhs, pctg_area).
+pub type ZoneResult = (Area, f64, f64);
+
 /// Helper struct to pass exon-like data to checkTSS.
 pub struct TssExonInfo {
     pub start: i64,
@@ -145,6 +148,64 @@ pub fn check_tss(
     results
 }
 
+/// Check overlap with user-defined zones upstream of the TSS.
+///
+/// Replaces the fixed TSS/PROMOTER/UPSTREAM split of [`check_tss`] with the
+/// configured zones. The part of the region beyond the last zone is reported
+/// as UPSTREAM; parts in gaps between zones are not reported.
+///
+/// Uses the same coordinate mirroring as [`check_tss`] for negative strand.
+///
+/// # Returns
+/// A vector of (area, pctg_dhs, pctg_area) tuples for each overlapping zone.
+pub fn check_zones(
+    dhs_start: i64,
+    dhs_end: i64,
+    exon_info: &TssExonInfo,
+    zones: &[Zone],
+) -> Vec<ZoneResult> {
+    let mut exon_start = exon_info.start;
+    let mut actual_dhs_start = dhs_start;
+    let mut actual_dhs_end = dhs_end;
+
+    if exon_info.strand == Strand::Negative {
+        let aux = actual_dhs_end;
+        actual_dhs_end = 2 * exon_info.end - actual_dhs_start;
+        actual_dhs_start = 2 * exon_info.end - aux;
+        exon_start = exon_info.end;
+    }
+
+    let dhs_length = actual_dhs_end - actual_dhs_start + 1;
+    if dhs_length <= 0 {
+        return vec![];
+    }
+    let dhs_length_f = dhs_length as f64;
+
+    // Only the part of the region upstream of the TSS is considered
+    let upstream_end = std::cmp::min(exon_start - 1, actual_dhs_end);
+
+    let mut results = Vec::new();
+    for zone in zones {
+        let zone_start = exon_start - zone.end;
+        let zone_end = exon_start - zone.start - 1;
+        let overlap = upstream_end.min(zone_end) - actual_dhs_start.max(zone_start) + 1;
+        if overlap > 0 {
+            let pctg_dhs = (overlap as f64 / dhs_length_f) * 100.0;
+            let pctg_zone = (overlap as f64 / zone.length() as f64) * 100.0;
+            results.push((zone.area.clone(), pctg_dhs, pctg_zone));
+        }
+    }
+
+    let last_zone_start = exon_start - zones.last().map_or(0, |zone| zone.end);
+    let upstream_overlap = upstream_end.min(last_zone_start - 1) - actual_dhs_start + 1;
+    if upstream_overlap > 0 {
+        let pctg_dhs = (upstream_overlap as f64 / dhs_length_f) * 100.0;
+        results.push((Area::Upstream, pctg_dhs, -1.0));
+    }
+
+    results
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,6 +324,65 @@ mod tests {
         assert!(tags.contains(&"PROMOTER"));
     }
 
+    fn test_zones() -> Vec<Zone> {
+        vec![
+            Zone {
+                area: Area::Tss,
+                start: 0,
+                end: 200,
+            },
+            Zone {
+                area: Area::from_zone_name("PROXIMAL"),
+                start: 200,
+                end: 2000,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_check_zones_pos_strand() {
+        let exon = TssExonInfo {
+            start: 5000,
+            end: 6000,
+            strand: Strand::Positive,
+            distance: 0,
+        };
+
+        // Entirely within the first zone: [4800, 4999]
+        let res = check_zones(4850, 4949, &exon, &test_zones());
+        assert_eq!(res, vec![(Area::Tss, 100.0, 50.0)]);
+
+        // Spanning both zones and beyond: [2900, 3099] is 100bp PROXIMAL + 100bp UPSTREAM
+        let res = check_zones(2900, 3099, &exon, &test_zones());
+        assert_eq!(
+            res,
+            vec![
+                (
+                    Area::from_zone_name("PROXIMAL"),
+                    50.0,
+                    100.0 / 1800.0 * 100.0
+                ),
+                (Area::Upstream, 50.0, -1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_zones_neg_strand_mirror() {
+        let exon = TssExonInfo {
+            start: 2000,
+            end: 3000,
+            strand: Strand::Negative,
+            distance: 0,
+        };
+
+        // TSS @ 3000, first zone is [3001, 3200]
+        let res = check_zones(3151, 3250, &exon, &test_zones());
+        let tags: Vec<Area> = res.iter().map(|(area, _, _)| area.clone()).collect();
+        assert_eq!(tags, vec![Area::Tss, Area::from_zone_name("PROXIMAL")]);
+        assert_eq!(res[0].1, 50.0);
+    }
+
     #[test]
     fn test_large_tss_value() {
         let exon = TssExonInfo {
@@ -3,36 +3,894 @@
 //! This module handles writing formatted output to files with proper
 //! column ordering and number formatting.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use indexmap::{IndexMap, IndexSet};
+use sha2::{Digest, Sha256};
 
-use std::io::Write;
+use ahash::AHashMap;
 
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::matcher::diff::AnnotationChange;
+use crate::matcher::enrichment::AreaEnrichment;
+use crate::matcher::loops::{anchor_genes, AnchorGene, LoopPair};
 use crate::parser::bed::get_bed_headers;
-use crate::types::{Candidate, Region};
+use crate::parser::bedpe::{get_bedpe_headers, Loop};
+use crate::parser::gtf::GtfData;
+use crate::types::{
+    Area, Candidate, Compat, DuplicateMode, OutputFormat, Precision, Region, RegionColumns,
+    ReportLevel, SortOrder,
+};
+
+/// Optional output columns, written after PercArea and before the BED metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// Report level of the row (with `-r levels`).
+    Level,
+    /// Strand of the region from the BED strand column (NA if unstranded).
+    RegionStrand,
+    /// Distance signed by position relative to the TSS (negative upstream).
+    SignedDistance,
+    /// Whether the region overlaps a gene on the opposite strand (1/0, NA if unstranded).
+    Antisense,
+    /// Number of identical input regions collapsed into the region.
+    Count,
+    /// Confidence score of the association (NA when not computed).
+    Confidence,
+    /// Percentage of the gene covered by the region (NA when not computed).
+    PercGene,
+    /// Percentage of the transcript covered by the region (NA when not computed).
+    PercTranscript,
+    /// Bases of the region within the area (PercRegion in base pairs).
+    OverlapArea,
+    /// Bases of the region overlapping the gene (NA when not computed).
+    OverlapGene,
+    /// Start coordinate of the matched area (NA for INTERGENIC).
+    AreaStart,
+    /// End coordinate of the matched area (NA for INTERGENIC).
+    AreaEnd,
+    /// Biotype of the gene (NA when unknown).
+    GeneType,
+    /// Biotype of the transcript, comma-separated for merged transcripts
+    /// (NA when unknown).
+    TranscriptType,
+    /// Number of exons of the transcript, comma-separated for merged
+    /// transcripts (NA for INTERGENIC).
+    ExonCount,
+    /// Stable identifier of the association, from the region, gene,
+    /// transcript, exon or intron and area.
+    AssociationId,
+    /// Rank of the row within its gene by distance, filled in when the rows
+    /// are sorted (NA for INTERGENIC).
+    GeneRank,
+}
+
+impl Column {
+    /// Header name of the column.
+    pub fn header(&self) -> &'static str {
+        match self {
+            Column::Level => "Level",
+            Column::RegionStrand => "RegionStrand",
+            Column::SignedDistance => "SignedDistance",
+            Column::Antisense => "Antisense",
+            Column::Count => "Count",
+            Column::Confidence => "Confidence",
+            Column::PercGene => "PercGene",
+            Column::PercTranscript => "PercTranscript",
+            Column::OverlapArea => "OverlapArea",
+            Column::OverlapGene => "OverlapGene",
+            Column::AreaStart => "AreaStart",
+            Column::AreaEnd => "AreaEnd",
+            Column::GeneType => "GeneType",
+            Column::TranscriptType => "TranscriptType",
+            Column::ExonCount => "ExonCount",
+            Column::AssociationId => "AssociationID",
+            Column::GeneRank => "GeneRank",
+        }
+    }
+
+    /// Value of the column for a region-candidate pair.
+    fn value(&self, region: &Region, candidate: &Candidate, options: &OutputOptions) -> String {
+        match self {
+            Column::Level => candidate
+                .level
+                .map_or("NA", |level| level.as_str())
+                .to_string(),
+            Column::RegionStrand => region
+                .strand()
+                .map_or("NA", |strand| strand.as_str())
+                .to_string(),
+            Column::SignedDistance => {
+                if candidate.area == Area::Intergenic {
+                    "NA".to_string()
+                } else {
+                    candidate.signed_distance().to_string()
+                }
+            }
+            Column::Antisense => match candidate.antisense {
+                Some(true) => "1".to_string(),
+                Some(false) => "0".to_string(),
+                None => "NA".to_string(),
+            },
+            Column::Count => region.copies.to_string(),
+            Column::Confidence => candidate
+                .confidence
+                .map_or("NA".to_string(), |score| format!("{:.3}", score)),
+            Column::PercGene => candidate
+                .pctg_gene
+                .map_or("NA".to_string(), |pctg| options.precision.format(pctg)),
+            Column::PercTranscript => candidate
+                .pctg_transcript
+                .map_or("NA".to_string(), |pctg| options.precision.format(pctg)),
+            Column::OverlapArea => {
+                if candidate.area == Area::Intergenic {
+                    "NA".to_string()
+                } else {
+                    let bases = candidate.pctg_region / 100.0 * region.length() as f64;
+                    (bases.round() as i64).to_string()
+                }
+            }
+            Column::OverlapGene => candidate
+                .overlap_gene
+                .map_or("NA".to_string(), |bases| bases.to_string()),
+            Column::AreaStart | Column::AreaEnd if candidate.area == Area::Intergenic => {
+                "NA".to_string()
+            }
+            Column::AreaStart => candidate.start.to_string(),
+            Column::AreaEnd => candidate.end.to_string(),
+            Column::GeneType | Column::TranscriptType if candidate.area == Area::Intergenic => {
+                "NA".to_string()
+            }
+            Column::GeneType => options
+                .biotypes
+                .as_ref()
+                .and_then(|biotypes| biotypes.genes.get(&candidate.gene))
+                .map_or("NA", String::as_str)
+                .to_string(),
+            Column::TranscriptType => {
+                let types: Vec<&str> = candidate
+                    .transcript
+                    .split(',')
+                    .map(|transcript| {
+                        options
+                            .biotypes
+                            .as_ref()
+                            .and_then(|biotypes| biotypes.transcripts.get(transcript))
+                            .map_or("NA", String::as_str)
+                    })
+                    .collect();
+                types.join(",")
+            }
+            Column::ExonCount if candidate.area == Area::Intergenic => "NA".to_string(),
+            Column::ExonCount => {
+                let counts: Vec<String> = candidate
+                    .transcript
+                    .split(',')
+                    .map(|transcript| {
+                        options
+                            .exon_counts
+                            .as_ref()
+                            .and_then(|counts| counts.transcripts.get(transcript))
+                            .map_or("NA".to_string(), usize::to_string)
+                    })
+                    .collect();
+                counts.join(",")
+            }
+            Column::AssociationId => association_id(region, candidate),
+            Column::GeneRank => "NA".to_string(),
+        }
+    }
+}
+
+/// Stable identifier of a region-candidate pair: the first 16 hex digits of
+/// the SHA-256 of the region coordinates, gene, transcript, exon or intron
+/// number and area, the same across runs, threads and versions.
+pub fn association_id(region: &Region, candidate: &Candidate) -> String {
+    let key = format!(
+        "{}:{}-{}|{}|{}|{}|{}",
+        region.chrom,
+        region.start,
+        region.end,
+        candidate.gene,
+        candidate.transcript,
+        candidate.exon_number,
+        candidate.area.as_str()
+    );
+    Sha256::digest(key.as_bytes())[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Gene and transcript biotypes of the annotation, by ID.
+#[derive(Debug, Default)]
+pub struct Biotypes {
+    genes: AHashMap<String, String>,
+    transcripts: AHashMap<String, String>,
+}
+
+impl Biotypes {
+    /// Collect the biotypes of the genes and transcripts that have one.
+    pub fn from_gtf(gtf_data: &GtfData) -> Self {
+        let mut biotypes = Biotypes::default();
+        for gene in gtf_data.genes_by_chrom.values().flatten() {
+            if let Some(biotype) = &gene.biotype {
+                biotypes.genes.insert(gene.gene_id.clone(), biotype.clone());
+            }
+            for transcript in &gene.transcripts {
+                if let Some(biotype) = &transcript.biotype {
+                    biotypes
+                        .transcripts
+                        .insert(transcript.transcript_id.clone(), biotype.clone());
+                }
+            }
+        }
+        biotypes
+    }
+}
+
+/// Exon counts of the transcripts of the annotation, by ID.
+#[derive(Debug, Default)]
+pub struct ExonCounts {
+    transcripts: AHashMap<String, usize>,
+}
+
+impl ExonCounts {
+    /// Collect the number of exons of every transcript.
+    pub fn from_gtf(gtf_data: &GtfData) -> Self {
+        let mut counts = ExonCounts::default();
+        for gene in gtf_data.genes_by_chrom.values().flatten() {
+            for transcript in &gene.transcripts {
+                counts
+                    .transcripts
+                    .insert(transcript.transcript_id.clone(), transcript.exons.len());
+            }
+        }
+        counts
+    }
+}
+
+/// Extents of the genes of the annotation (GTF coordinates), by ID.
+#[derive(Debug, Default)]
+pub struct GeneExtents {
+    genes: AHashMap<String, (i64, i64)>,
+}
+
+impl GeneExtents {
+    /// Collect the start and end of every gene.
+    pub fn from_gtf(gtf_data: &GtfData) -> Self {
+        let mut extents = GeneExtents::default();
+        for gene in gtf_data.genes_by_chrom.values().flatten() {
+            extents
+                .genes
+                .entry(gene.gene_id.clone())
+                .or_insert((gene.start, gene.end));
+        }
+        extents
+    }
+}
+
+/// Output layout options.
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Optional columns to write, in order.
+    pub columns: Vec<Column>,
+    /// Compatibility mode changing the Area vocabulary.
+    pub compat: Option<Compat>,
+    /// Write the TSS distances of all merged transcripts in TSSDistance.
+    pub tss_distance_list: bool,
+    /// Output format; BED and GFF3 replace the table layout.
+    pub format: OutputFormat,
+    /// Start table rows with the chromosome, start and end of the region,
+    /// so that they can be indexed.
+    pub coordinates: bool,
+    /// Columns identifying the region at the start of table rows.
+    pub region_columns: RegionColumns,
+    /// Names of the BED metadata columns, in place of the standard ones.
+    pub bed_names: Vec<String>,
+    /// Leave out the header line of the table.
+    pub no_header: bool,
+    /// Start the header line of the table with `#`.
+    pub comment_header: bool,
+    /// Positions in the table of the columns to write, with their headers;
+    /// all columns are written if empty.
+    pub selection: Vec<(usize, String)>,
+    /// Biotypes looked up for the GeneType and TranscriptType columns.
+    pub biotypes: Option<Arc<Biotypes>>,
+    /// Exon counts looked up for the ExonCount column.
+    pub exon_counts: Option<Arc<ExonCounts>>,
+    /// Gene extents looked up for the bedtools closest layout.
+    pub gene_extents: Option<Arc<GeneExtents>>,
+    /// Decimal places of the percentage columns.
+    pub precision: Precision,
+    /// Number of associations per row of the wide layout, if used.
+    pub wide: Option<usize>,
+    /// Area priorities, ranking the associations of the wide layout.
+    pub rules: Vec<Area>,
+}
+
+impl OutputOptions {
+    /// Derive the output layout from the configuration.
+    pub fn from_config(config: &Config) -> Self {
+        let mut columns = Vec::new();
+        if config.level == ReportLevel::Levels {
+            columns.push(Column::Level);
+        }
+        if config.region_strand {
+            columns.push(Column::RegionStrand);
+        }
+        if config.signed_distance {
+            columns.push(Column::SignedDistance);
+        }
+        if config.report_antisense {
+            columns.push(Column::Antisense);
+        }
+        if config.duplicates == DuplicateMode::Collapse {
+            columns.push(Column::Count);
+        }
+        if config.confidence.is_some() {
+            columns.push(Column::Confidence);
+        }
+        if config.gene_coverage {
+            columns.push(Column::PercGene);
+            columns.push(Column::PercTranscript);
+        }
+        if config.overlap_bp {
+            columns.push(Column::OverlapArea);
+            columns.push(Column::OverlapGene);
+        }
+        if config.area_coordinates {
+            columns.push(Column::AreaStart);
+            columns.push(Column::AreaEnd);
+        }
+        if config.biotype_columns {
+            columns.push(Column::GeneType);
+            columns.push(Column::TranscriptType);
+        }
+        if config.exon_count {
+            columns.push(Column::ExonCount);
+        }
+        if config.association_id {
+            columns.push(Column::AssociationId);
+        }
+        if config.gene_rank {
+            columns.push(Column::GeneRank);
+        }
+        let mut options = OutputOptions {
+            columns,
+            compat: config.compat,
+            tss_distance_list: config.tss_distance_list,
+            format: config.format,
+            // The bedtools closest layout already starts with the coordinates
+            coordinates: config.bgzip
+                && config.format == OutputFormat::Tsv
+                && config.compat != Some(Compat::BedtoolsClosest),
+            region_columns: config.region_columns,
+            bed_names: config.bed_names.clone(),
+            no_header: config.no_header,
+            comment_header: config.comment_header,
+            selection: Vec::new(),
+            biotypes: None,
+            exon_counts: None,
+            gene_extents: None,
+            precision: config.precision,
+            wide: config.wide,
+            rules: config.rules.clone(),
+        };
+        let table_columns = options.table_columns();
+        options.selection = config
+            .select_columns
+            .iter()
+            .filter_map(|spec| {
+                let position = table_columns.iter().position(|&c| c == spec.name)?;
+                Some((position, spec.header.clone()))
+            })
+            .collect();
+        options
+    }
+
+    /// Names of the columns of the association table, including every BED
+    /// column that the regions may have.
+    pub fn table_columns(&self) -> Vec<&str> {
+        let mut names = self.region_columns.headers().to_vec();
+        names.extend([
+            "Midpoint",
+            "Gene",
+            "Transcript",
+            "Exon/Intron",
+            "Area",
+            "Distance",
+            "TSSDistance",
+            "PercRegion",
+            "PercArea",
+        ]);
+        names.extend(self.columns.iter().map(|c| c.header()));
+        names.extend(self.bed_headers(self.bed_names.len().max(9)));
+        names
+    }
+
+    /// Position of a table column in the written rows, None when it is not
+    /// written.
+    pub fn field_position(&self, name: &str) -> Option<usize> {
+        let index = self.table_columns().iter().position(|&c| c == name)?;
+        let position = if self.selection.is_empty() {
+            index
+        } else {
+            self.selection.iter().position(|&(i, _)| i == index)?
+        };
+        Some(if self.coordinates {
+            position + 3
+        } else {
+            position
+        })
+    }
+
+    /// Headers of the first `num_columns` BED metadata columns, the given
+    /// names followed by the standard ones.
+    pub fn bed_headers(&self, num_columns: usize) -> Vec<&str> {
+        let standard = get_bed_headers(num_columns);
+        (0..num_columns)
+            .filter_map(|i| {
+                self.bed_names
+                    .get(i)
+                    .map(String::as_str)
+                    .or_else(|| standard.get(i).copied())
+            })
+            .collect()
+    }
+
+    /// Look up the biotypes and gene extents of the annotation, if written.
+    pub fn set_annotation(&mut self, gtf_data: &GtfData) {
+        if self.columns.contains(&Column::GeneType) {
+            self.biotypes = Some(Arc::new(Biotypes::from_gtf(gtf_data)));
+        }
+        if self.columns.contains(&Column::ExonCount) {
+            self.exon_counts = Some(Arc::new(ExonCounts::from_gtf(gtf_data)));
+        }
+        if self.compat == Some(Compat::BedtoolsClosest) {
+            self.gene_extents = Some(Arc::new(GeneExtents::from_gtf(gtf_data)));
+        }
+    }
+
+    /// Area label of a candidate in the configured vocabulary.
+    fn area_label<'a>(&self, candidate: &'a Candidate) -> &'a str {
+        match self.compat {
+            Some(Compat::ChipSeeker) => chipseeker_category(candidate),
+            Some(Compat::BedtoolsClosest | Compat::Python) | None => candidate.area.as_str(),
+        }
+    }
+}
+
+/// ChIPseeker annotation category of a candidate.
+///
+/// As in ChIPseeker, the promoter (within 3 kb of the TSS on either side)
+/// takes priority over the gene features, and downstream matches only count
+/// within 300 bp of the gene end.
+pub fn chipseeker_category(candidate: &Candidate) -> &'static str {
+    match candidate.area {
+        Area::Intergenic => return "Distal Intergenic",
+        // Links have no position relative to their target gene
+        Area::Linked => return Area::Linked.as_str(),
+        _ => {}
+    }
+
+    match candidate.tss_distance.abs() {
+        0..=1000 => return "Promoter (<=1kb)",
+        1001..=2000 => return "Promoter (1-2kb)",
+        2001..=3000 => return "Promoter (2-3kb)",
+        _ => {}
+    }
+
+    match candidate.area {
+        Area::FivePrimeUtr => "5' UTR",
+        Area::ThreePrimeUtr => "3' UTR",
+        Area::FirstExon | Area::GeneBody | Area::Cds => "Exon",
+        Area::Intron => "Intron",
+        Area::Tts | Area::Downstream if candidate.distance <= 300 => "Downstream (<=300)",
+        _ => "Distal Intergenic",
+    }
+}
+
+pub mod annotation;
+pub mod checkpoint;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod compare;
+pub mod matrix;
+pub mod metadata;
+pub mod metrics;
+pub mod profile;
+pub mod reorder;
+pub mod report;
+pub mod sort;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod tabix;
+
+pub use annotation::AnnotationStats;
+pub use checkpoint::Checkpoint;
+pub use compare::{diff_outputs, OutputDiff, OutputTable, RowChange};
+pub use matrix::RegionGeneMatrix;
+pub use metadata::{InputFile, RunMetadata};
+pub use metrics::{PerfMetrics, RunMetrics};
+pub use profile::TssProfile;
+pub use reorder::ReorderBuffer;
+pub use report::write_html_report;
+pub use stats::{RunSummary, SampleSummaries};
+
+/// Columns holding whole numbers (distances, coordinates, counts).
+#[cfg(any(feature = "parquet", feature = "sqlite"))]
+const INTEGER_COLUMNS: &[&str] = &[
+    "Start",
+    "End",
+    "Midpoint",
+    "Distance",
+    "TSSDistance",
+    "SignedDistance",
+    "Antisense",
+    "Count",
+    "OverlapArea",
+    "OverlapGene",
+    "AreaStart",
+    "AreaEnd",
+    "GeneRank",
+    "RegionCount",
+    "CompareRegionCount",
+    "MinDistance",
+    "Observed",
+    "Distance1",
+    "Distance2",
+];
+
+/// Columns holding decimal numbers (percentages, scores, statistics).
+#[cfg(any(feature = "parquet", feature = "sqlite"))]
+const DECIMAL_COLUMNS: &[&str] = &[
+    "PercRegion",
+    "PercArea",
+    "Confidence",
+    "PercGene",
+    "PercTranscript",
+    "Weight",
+    "Expected",
+    "FoldEnrichment",
+    "PValue",
+];
+
+/// Value type of an output column in the typed output formats.
+#[cfg(any(feature = "parquet", feature = "sqlite"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Decimal,
+    Text,
+}
+
+/// Type of an output column from its header name.
+///
+/// All columns not known to hold numbers, including the BED metadata, are
+/// text.
+#[cfg(any(feature = "parquet", feature = "sqlite"))]
+pub fn column_type(name: &str) -> ColumnType {
+    // Numbered columns of the wide layout (Distance1, ...)
+    let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if INTEGER_COLUMNS.contains(&name) || INTEGER_COLUMNS.contains(&base) {
+        ColumnType::Integer
+    } else if DECIMAL_COLUMNS.contains(&name) {
+        ColumnType::Decimal
+    } else {
+        ColumnType::Text
+    }
+}
+
+/// Whether the output path is `-`, standard output.
+pub fn is_stdout(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Open the output: a new file, or standard output for `-`.
+pub fn open_output(path: &Path) -> Result<Box<dyn Write + Send>> {
+    if is_stdout(path) {
+        return Ok(Box::new(std::io::stdout()));
+    }
+    let file = File::create(path).context("Failed to create output file")?;
+    Ok(Box::new(file))
+}
+
+/// Output file in the configured format.
+///
+/// Lines are written as tab-separated text, which the Parquet and SQLite
+/// formats convert into typed columns and bgzip compresses and indexes;
+/// [`OutputFile::finish`] completes the file.
+pub enum OutputFile {
+    Tsv(BufWriter<Box<dyn Write + Send>>),
+    Bgzip(tabix::TabixWriter),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<columnar::ParquetWriter>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlite::SqliteWriter),
+    /// Rows sorted before being written to the inner output.
+    Sorted(Box<OutputFile>, sort::LineSorter),
+}
+
+impl OutputFile {
+    /// Create the output file in the format of the configuration.
+    pub fn create(path: &Path, config: &Config) -> Result<Self> {
+        if config.sort_output != SortOrder::None {
+            let inner = OutputFile::create(
+                path,
+                &Config {
+                    sort_output: SortOrder::None,
+                    ..config.clone()
+                },
+            )?;
+            let mut sorter =
+                sort::LineSorter::new(config.sort_output).with_weight_column(config.weight_column);
+            if config.gene_rank {
+                if let Some(field) = OutputOptions::from_config(config).field_position("GeneRank") {
+                    sorter = sorter.with_gene_rank(field);
+                }
+            }
+            return Ok(OutputFile::Sorted(Box::new(inner), sorter));
+        }
+        // Lists of TSS distances are kept as text, and renamed columns keep
+        // the type of their content
+        #[cfg(any(feature = "parquet", feature = "sqlite"))]
+        let column_types: Vec<(String, ColumnType)> = {
+            let type_of = |name: &str| match name {
+                "TSSDistance" if config.tss_distance_list => ColumnType::Text,
+                _ => column_type(name),
+            };
+            let mut types: Vec<(String, ColumnType)> = config
+                .select_columns
+                .iter()
+                .map(|spec| (spec.header.clone(), type_of(&spec.name)))
+                .collect();
+            types.push(("TSSDistance".to_string(), type_of("TSSDistance")));
+            types
+        };
+        if config.bgzip {
+            let preset = match config.format {
+                OutputFormat::Gff3 => tabix::TabixPreset::Gff,
+                _ => tabix::TabixPreset::Bed,
+            };
+            return Ok(OutputFile::Bgzip(tabix::TabixWriter::create(path, preset)?));
+        }
+        match config.format {
+            OutputFormat::Tsv | OutputFormat::Bed | OutputFormat::Gff3 | OutputFormat::Ucsc => {
+                Ok(OutputFile::Tsv(BufWriter::new(open_output(path)?)))
+            }
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => {
+                let writer = columnar::ParquetWriter::create(path, &column_types)?;
+                Ok(OutputFile::Parquet(Box::new(writer)))
+            }
+            #[cfg(not(feature = "parquet"))]
+            OutputFormat::Parquet => {
+                anyhow::bail!("Parquet output needs rgmatch to be built with the `parquet` feature")
+            }
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => Ok(OutputFile::Sqlite(sqlite::SqliteWriter::create(
+                path,
+                &column_types,
+            )?)),
+            #[cfg(not(feature = "sqlite"))]
+            OutputFormat::Sqlite => {
+                anyhow::bail!("SQLite output needs rgmatch to be built with the `sqlite` feature")
+            }
+        }
+    }
+
+    /// Reopen an output file to continue it after its first `length` bytes,
+    /// dropping anything written after them.
+    ///
+    /// Only text outputs written as they go (tsv, bed, gff3 and ucsc,
+    /// unsorted and uncompressed) can be continued.
+    pub fn resume(path: &Path, config: &Config, length: u64) -> Result<Self> {
+        if config.bgzip
+            || config.sort_output != SortOrder::None
+            || matches!(config.format, OutputFormat::Parquet | OutputFormat::Sqlite)
+        {
+            anyhow::bail!("Only unsorted, uncompressed text outputs can be resumed");
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .context("Failed to open output file")?;
+        if file.metadata()?.len() < length {
+            anyhow::bail!(
+                "Output file {} is shorter than the length to resume from ({} bytes)",
+                path.display(),
+                length
+            );
+        }
+        file.set_len(length)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(OutputFile::Tsv(BufWriter::new(Box::new(file))))
+    }
+
+    /// Flush the remaining output and complete the file.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            OutputFile::Tsv(mut writer) => writer.flush()?,
+            OutputFile::Bgzip(writer) => writer.finish()?,
+            #[cfg(feature = "parquet")]
+            OutputFile::Parquet(writer) => writer.finish()?,
+            #[cfg(feature = "sqlite")]
+            OutputFile::Sqlite(writer) => writer.finish()?,
+            OutputFile::Sorted(mut writer, sorter) => {
+                sorter.finish(&mut writer)?;
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the output line of a region-candidate pair, or keep it to be
+    /// sorted.
+    pub fn write_row(
+        &mut self,
+        region: &Region,
+        candidate: &Candidate,
+        line: String,
+    ) -> Result<()> {
+        match self {
+            OutputFile::Sorted(_, sorter) => sorter.add(region, candidate, line),
+            _ => {
+                writeln!(self, "{}", line)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Tsv(writer) => writer.write(buf),
+            OutputFile::Bgzip(writer) => writer.write(buf),
+            #[cfg(feature = "parquet")]
+            OutputFile::Parquet(writer) => writer.write(buf),
+            #[cfg(feature = "sqlite")]
+            OutputFile::Sqlite(writer) => writer.write(buf),
+            OutputFile::Sorted(writer, _) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Tsv(writer) => writer.flush(),
+            OutputFile::Bgzip(writer) => writer.flush(),
+            #[cfg(feature = "parquet")]
+            OutputFile::Parquet(writer) => writer.flush(),
+            #[cfg(feature = "sqlite")]
+            OutputFile::Sqlite(writer) => writer.flush(),
+            OutputFile::Sorted(writer, _) => writer.flush(),
+        }
+    }
+}
 
 /// Write the output header.
 pub fn write_header<W: Write>(writer: &mut W, num_meta_columns: usize) -> Result<()> {
-    let base_header = "Region\tMidpoint\tGene\tTranscript\tExon/Intron\tArea\tDistance\tTSSDistance\tPercRegion\tPercArea";
+    write_header_with(writer, num_meta_columns, &OutputOptions::default())
+}
+
+/// Write the output header with the given output options.
+pub fn write_header_with<W: Write>(
+    writer: &mut W,
+    num_meta_columns: usize,
+    options: &OutputOptions,
+) -> Result<()> {
+    match options.format {
+        // Annotated BED stays a valid BED file
+        OutputFormat::Bed => return Ok(()),
+        OutputFormat::Gff3 => {
+            writeln!(writer, "##gff-version 3")?;
+            return Ok(());
+        }
+        OutputFormat::Ucsc => {
+            writeln!(
+                writer,
+                "track name=\"rgmatch\" description=\"rgmatch associations colored by area\" itemRgb=\"On\""
+            )?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // bedtools closest writes no header
+    if options.no_header || options.compat == Some(Compat::BedtoolsClosest) {
+        return Ok(());
+    }
+    let mut header = String::new();
+    if options.coordinates {
+        header.push_str("#Chrom\tStart\tEnd\t");
+    } else if options.comment_header {
+        header.push('#');
+    }
+    if !options.selection.is_empty() {
+        let headers: Vec<&str> = options.selection.iter().map(|(_, h)| h.as_str()).collect();
+        header.push_str(&headers.join("\t"));
+        writeln!(writer, "{}", header)?;
+        return Ok(());
+    }
+    header.push_str(&options.region_columns.headers().join("\t"));
+    if let Some(k) = options.wide {
+        header.push_str("\tMidpoint");
+        for i in 1..=k {
+            header.push_str(&format!("\tGene{0}\tArea{0}\tDistance{0}", i));
+        }
+        if num_meta_columns > 0 {
+            header.push('\t');
+            header.push_str(&options.bed_headers(num_meta_columns).join("\t"));
+        }
+        writeln!(writer, "{}", header)?;
+        return Ok(());
+    }
+    header.push_str("\tMidpoint\tGene\tTranscript\tExon/Intron\tArea\tDistance\tTSSDistance\tPercRegion\tPercArea");
+
+    for column in &options.columns {
+        header.push('\t');
+        header.push_str(column.header());
+    }
 
     if num_meta_columns > 0 {
-        let meta_headers = get_bed_headers(num_meta_columns);
-        let meta_str = meta_headers.join("\t");
-        writeln!(writer, "{}\t{}", base_header, meta_str)?;
-    } else {
-        writeln!(writer, "{}", base_header)?;
+        let meta_headers = options.bed_headers(num_meta_columns);
+        header.push('\t');
+        header.push_str(&meta_headers.join("\t"));
     }
 
+    writeln!(writer, "{}", header)?;
+
     Ok(())
 }
 
 /// Format a single output line for a region-candidate pair.
 pub fn format_output_line(region: &Region, candidate: &Candidate) -> String {
-    let region_id = region.id();
+    format_output_line_with(region, candidate, &OutputOptions::default())
+}
+
+/// Format a single output line with the given output options.
+pub fn format_output_line_with(
+    region: &Region,
+    candidate: &Candidate,
+    options: &OutputOptions,
+) -> String {
+    match options.format {
+        OutputFormat::Bed => return format_bed_line(region, candidate, options),
+        OutputFormat::Gff3 => return format_gff_line(region, candidate, options),
+        OutputFormat::Ucsc => return format_track_line(region, candidate, options),
+        _ => {}
+    }
+    if options.compat == Some(Compat::BedtoolsClosest) {
+        return format_closest_line(region, candidate, options);
+    }
+
+    let region_id = region_label(region, options);
     let midpoint = region.midpoint();
 
-    // Format percentages with 2 decimal places
-    let pctg_region = format!("{:.2}", candidate.pctg_region);
-    let pctg_area = format!("{:.2}", candidate.pctg_area);
+    // Unmatched regions carry no distances or overlaps
+    let (distance, tss_distance, pctg_region, pctg_area) = if candidate.area == Area::Intergenic {
+        let na = "NA".to_string();
+        (na.clone(), na.clone(), na.clone(), na)
+    } else {
+        let tss_distance = match &candidate.tss_distances {
+            Some(list) if options.tss_distance_list => list.clone(),
+            _ => candidate.tss_distance.to_string(),
+        };
+        (
+            candidate.distance.to_string(),
+            tss_distance,
+            options.precision.format(candidate.pctg_region),
+            options.precision.format(candidate.pctg_area),
+        )
+    };
+
+    let area = options.area_label(candidate);
 
     // Build base output
     let mut line = format!(
@@ -42,13 +900,19 @@ pub fn format_output_line(region: &Region, candidate: &Candidate) -> String {
         candidate.gene,
         candidate.transcript,
         candidate.exon_number,
-        candidate.area,
-        candidate.distance,
-        candidate.tss_distance,
+        area,
+        distance,
+        tss_distance,
         pctg_region,
         pctg_area
     );
 
+    // Add optional columns
+    for column in &options.columns {
+        line.push('\t');
+        line.push_str(&column.value(region, candidate, options));
+    }
+
     // Add metadata columns
     if !region.metadata.is_empty() {
         // Join metadata without trailing characters
@@ -59,13 +923,652 @@ pub fn format_output_line(region: &Region, candidate: &Candidate) -> String {
         line.push_str(meta_str);
     }
 
+    // Keep the selected columns, NA for BED columns the region lacks
+    if !options.selection.is_empty() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let selected: Vec<&str> = options
+            .selection
+            .iter()
+            .map(|&(i, _)| fields.get(i).copied().unwrap_or("NA"))
+            .collect();
+        line = selected.join("\t");
+    }
+
+    if options.coordinates {
+        line.insert_str(
+            0,
+            &format!("{}\t{}\t{}\t", region.chrom, region.start, region.end),
+        );
+    }
+    line
+}
+
+/// Columns identifying the region in a table row.
+fn region_label(region: &Region, options: &OutputOptions) -> String {
+    match options.region_columns {
+        RegionColumns::Id => region.id(),
+        RegionColumns::Split => format!("{}\t{}\t{}", region.chrom, region.start, region.end),
+        RegionColumns::Both => format!(
+            "{}\t{}\t{}\t{}",
+            region.id(),
+            region.chrom,
+            region.start,
+            region.end
+        ),
+    }
+}
+
+/// Format the wide row of a region: its associations ranked by area
+/// priority and then distance, the first K written as gene, area and
+/// distance column groups (NA for missing associations).
+pub fn format_wide_line(
+    region: &Region,
+    candidates: &[Candidate],
+    options: &OutputOptions,
+) -> String {
+    let k = options.wide.unwrap_or(1);
+    let rank = |c: &Candidate| {
+        let priority = options
+            .rules
+            .iter()
+            .position(|a| *a == c.area)
+            .unwrap_or(options.rules.len());
+        (priority, c.distance)
+    };
+    // Stable sort keeps the output order for equal ranks
+    let mut ranked: Vec<&Candidate> = candidates.iter().collect();
+    ranked.sort_by_key(|c| rank(c));
+
+    let mut line = String::new();
+    if options.coordinates {
+        line.push_str(&format!(
+            "{}\t{}\t{}\t",
+            region.chrom, region.start, region.end
+        ));
+    }
+    line.push_str(&region_label(region, options));
+    line.push_str(&format!("\t{}", region.midpoint()));
+    for i in 0..k {
+        match ranked.get(i) {
+            Some(c) if c.area != Area::Intergenic => line.push_str(&format!(
+                "\t{}\t{}\t{}",
+                c.gene,
+                options.area_label(c),
+                c.distance
+            )),
+            Some(c) => line.push_str(&format!("\tNA\t{}\tNA", options.area_label(c))),
+            None => line.push_str("\tNA\tNA\tNA"),
+        }
+    }
+    for value in &region.metadata {
+        line.push('\t');
+        line.push_str(value.trim_end());
+    }
+    line
+}
+
+/// Format an annotated BED line: the BED columns of the region followed by
+/// the gene, area and distance ("." for the gene and distance of unmatched
+/// regions).
+fn format_bed_line(region: &Region, candidate: &Candidate, options: &OutputOptions) -> String {
+    let mut line = format!("{}\t{}\t{}", region.chrom, region.start, region.end);
+    for value in &region.metadata {
+        line.push('\t');
+        line.push_str(value.trim_end());
+    }
+
+    let (gene, distance) = if candidate.area == Area::Intergenic {
+        (".", ".".to_string())
+    } else {
+        (candidate.gene.as_str(), candidate.distance.to_string())
+    };
+    line.push_str(&format!(
+        "\t{}\t{}\t{}",
+        gene,
+        options.area_label(candidate),
+        distance
+    ));
+    line
+}
+
+/// Format a `bedtools closest -d` line: the BED columns of the region, the
+/// gene as a BED6 feature (its extent, ID, score 0 and strand) and the
+/// distance between them, 0 when they overlap and 1 when book-ended.
+/// Unmatched regions get the empty feature of bedtools (`. -1 -1 . -1 .`)
+/// and a distance of -1.
+fn format_closest_line(region: &Region, candidate: &Candidate, options: &OutputOptions) -> String {
+    let mut line = format!("{}\t{}\t{}", region.chrom, region.start, region.end);
+    for value in &region.metadata {
+        line.push('\t');
+        line.push_str(value.trim_end());
+    }
+
+    let extent = options
+        .gene_extents
+        .as_ref()
+        .and_then(|extents| extents.genes.get(&candidate.gene));
+    match extent {
+        Some(&(start, end)) if candidate.area != Area::Intergenic => {
+            // GTF starts are 1-based
+            let start = start - 1;
+            let distance = if end <= region.start {
+                region.start - end + 1
+            } else if start >= region.end {
+                start - region.end + 1
+            } else {
+                0
+            };
+            line.push_str(&format!(
+                "\t{}\t{}\t{}\t{}\t0\t{}\t{}",
+                region.chrom,
+                start,
+                end,
+                candidate.gene,
+                candidate.strand.as_str(),
+                distance
+            ));
+        }
+        _ => line.push_str("\t.\t-1\t-1\t.\t-1\t.\t-1"),
+    }
     line
 }
 
+/// Track color (R,G,B) of an area: warm colors near the TSS, cool colors
+/// over the gene body and beyond, grey for unmatched regions.
+pub fn area_color(area: &Area) -> &'static str {
+    match area {
+        Area::Tss | Area::Basal => "215,48,39",
+        Area::FirstExon => "244,109,67",
+        Area::Promoter | Area::Zone(_) | Area::Cis => "253,174,97",
+        Area::Upstream | Area::Extension => "254,224,144",
+        Area::FivePrimeUtr => "153,112,171",
+        Area::Cds => "90,174,97",
+        Area::ThreePrimeUtr => "194,165,207",
+        Area::Intron => "116,173,209",
+        Area::GeneBody | Area::OverlapGene => "69,117,180",
+        Area::Tts => "49,54,149",
+        Area::Downstream => "37,52,148",
+        Area::Overlap => "27,120,55",
+        Area::Linked => "118,42,131",
+        Area::Intergenic => "153,153,153",
+    }
+}
+
+/// Format a BED9 track line: the region named after the gene and area
+/// (`GENE:AREA`, or the area alone for unmatched regions), with the color
+/// of the area as itemRgb.
+fn format_track_line(region: &Region, candidate: &Candidate, options: &OutputOptions) -> String {
+    let area = options.area_label(candidate);
+    let name = if candidate.area == Area::Intergenic {
+        area.to_string()
+    } else {
+        format!("{}:{}", candidate.gene, area)
+    };
+    // Track fields may be separated by spaces
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect();
+    format!(
+        "{0}\t{1}\t{2}\t{3}\t0\t{4}\t{1}\t{2}\t{5}",
+        region.chrom,
+        region.start,
+        region.end,
+        name,
+        region.strand().map_or(".", |s| s.as_str()),
+        area_color(&candidate.area)
+    )
+}
+
+/// Percent-encode the characters with a special meaning in GFF3 attributes.
+fn gff_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ';' | '=' | '&' | ',' | '%' | '\t' | '\n' => {
+                escaped.push_str(&format!("%{:02X}", c as u32))
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a GFF3 feature for an association: the region (with its BED
+/// strand, if any) with the gene, transcript, area and distances as
+/// attributes. Unmatched regions only carry their area.
+fn format_gff_line(region: &Region, candidate: &Candidate, options: &OutputOptions) -> String {
+    let strand = region.strand().map_or(".", |s| s.as_str());
+    let mut attributes = Vec::new();
+    if candidate.area != Area::Intergenic {
+        attributes.push(format!("Name={}", gff_escape(&candidate.gene)));
+        attributes.push(format!("gene={}", gff_escape(&candidate.gene)));
+        attributes.push(format!("transcript={}", gff_escape(&candidate.transcript)));
+    }
+    attributes.push(format!(
+        "area={}",
+        gff_escape(options.area_label(candidate))
+    ));
+    if candidate.area != Area::Intergenic {
+        attributes.push(format!("distance={}", candidate.distance));
+        attributes.push(format!("tss_distance={}", candidate.tss_distance));
+    }
+    attributes.push(format!("region={}", gff_escape(&region.id())));
+
+    // GFF3 positions start at 1
+    format!(
+        "{}\trgmatch\tregion\t{}\t{}\t.\t{}\t.\t{}",
+        region.chrom,
+        region.start.max(1),
+        region.end.max(1),
+        strand,
+        attributes.join(";")
+    )
+}
+
+/// Write the header of the loop (BEDPE) output.
+pub fn write_loop_header<W: Write>(writer: &mut W, num_meta_columns: usize) -> Result<()> {
+    let mut header =
+        String::from("Anchor1\tAnchor2\tGene1\tArea1\tDistance1\tGene2\tArea2\tDistance2\tClass");
+
+    if num_meta_columns > 0 {
+        header.push('\t');
+        header.push_str(&get_bedpe_headers(num_meta_columns).join("\t"));
+    }
+
+    writeln!(writer, "{}", header)?;
+
+    Ok(())
+}
+
+/// Format a single output line for a gene pair joined by a loop.
+pub fn format_loop_line(lp: &Loop, pair: &LoopPair) -> String {
+    // Anchors without genes are reported as INTERGENIC
+    let anchor = |gene: &Option<AnchorGene>| match gene {
+        Some(gene) => format!("{}\t{}\t{}", gene.gene, gene.area, gene.distance),
+        None => "NA\tINTERGENIC\tNA".to_string(),
+    };
+
+    let mut line = format!(
+        "{}\t{}\t{}\t{}\t{}",
+        lp.anchor1.id(),
+        lp.anchor2.id(),
+        anchor(&pair.gene1),
+        anchor(&pair.gene2),
+        pair.class()
+    );
+
+    if !lp.metadata.is_empty() {
+        line.push('\t');
+        line.push_str(&lp.metadata.join("\t"));
+    }
+
+    line
+}
+
+/// Write the header of the annotation diff output.
+pub fn write_diff_header<W: Write>(writer: &mut W, num_meta_columns: usize) -> Result<()> {
+    let mut header = String::from("Region\tGene\tOldArea\tNewArea\tChange");
+
+    if num_meta_columns > 0 {
+        header.push('\t');
+        header.push_str(&get_bed_headers(num_meta_columns).join("\t"));
+    }
+
+    writeln!(writer, "{}", header)?;
+
+    Ok(())
+}
+
+/// Format a single output line for a changed region-gene association.
+pub fn format_diff_line(region: &Region, change: &AnnotationChange) -> String {
+    // Missing associations are reported as NA
+    let area = |area: &Option<Area>| area.as_ref().map_or("NA".to_string(), |a| a.to_string());
+
+    let mut line = format!(
+        "{}\t{}\t{}\t{}\t{}",
+        region.id(),
+        change.gene,
+        area(&change.old_area),
+        area(&change.new_area),
+        change.kind().as_str()
+    );
+
+    if !region.metadata.is_empty() {
+        line.push('\t');
+        line.push_str(&region.metadata.join("\t"));
+    }
+
+    line
+}
+
+/// Write the per-area enrichment table.
+pub fn write_enrichment<W: Write>(writer: &mut W, enrichment: &[AreaEnrichment]) -> Result<()> {
+    writeln!(writer, "Area\tObserved\tExpected\tFoldEnrichment\tPValue")?;
+    for entry in enrichment {
+        // Areas never expected have no fold enrichment
+        let fold = entry
+            .fold_enrichment()
+            .map_or("NA".to_string(), |f| format!("{:.3}", f));
+        writeln!(
+            writer,
+            "{}\t{}\t{:.2}\t{}\t{:.4}",
+            entry.area, entry.observed, entry.expected, fold, entry.p_value
+        )?;
+    }
+    Ok(())
+}
+
+/// Gene-centric report: one row per gene listing its associated regions.
+///
+/// Genes are written in order of first association or, with a weight
+/// column, by decreasing total weight of their regions (ties ranked by best
+/// area, then minimum distance, then order of first association).
+pub struct GeneSummary {
+    rules: Vec<Area>,
+    weight_column: Option<usize>,
+    genes: IndexMap<String, GeneAssociations>,
+}
+
+/// Regions associated with a single gene.
+struct GeneAssociations {
+    /// Regions with their weight, in order of association.
+    regions: IndexMap<String, f64>,
+    best_area: Area,
+    best_rank: usize,
+    min_distance: i64,
+    weight: f64,
+    /// Summed length of the regions.
+    length: i64,
+    /// Summed BED score of the regions, None when none has a score column.
+    score: Option<f64>,
+}
+
+impl GeneSummary {
+    /// Create an empty summary ranking areas by the given priority rules.
+    pub fn new(rules: &[Area]) -> Self {
+        GeneSummary {
+            rules: rules.to_vec(),
+            weight_column: None,
+            genes: IndexMap::new(),
+        }
+    }
+
+    /// Create an empty summary from the configuration rules and weight column.
+    pub fn from_config(config: &Config) -> Self {
+        GeneSummary {
+            weight_column: config.weight_column,
+            ..GeneSummary::new(&config.rules)
+        }
+    }
+
+    /// Priority of an area; areas missing from the rules rank last.
+    fn rank(&self, area: &Area) -> usize {
+        self.rules
+            .iter()
+            .position(|a| a == area)
+            .unwrap_or(self.rules.len())
+    }
+
+    /// Record the associations reported for a region.
+    pub fn add(&mut self, region: &Region, candidates: &[Candidate]) {
+        let weight = self
+            .weight_column
+            .map_or(0.0, |column| region.weight(column));
+        let score = (region.metadata.len() >= 2).then(|| region.weight(5));
+        for candidate in candidates {
+            if candidate.area == Area::Intergenic {
+                continue;
+            }
+            let rank = self.rank(&candidate.area);
+            match self.genes.get_mut(&candidate.gene) {
+                Some(entry) => {
+                    // Each region weighs once per gene
+                    if entry.regions.insert(region.id(), weight).is_none() {
+                        entry.weight += weight;
+                        entry.length += region.length();
+                        if let Some(score) = score {
+                            *entry.score.get_or_insert(0.0) += score;
+                        }
+                    }
+                    if rank < entry.best_rank {
+                        entry.best_area = candidate.area.clone();
+                        entry.best_rank = rank;
+                    }
+                    entry.min_distance = entry.min_distance.min(candidate.distance);
+                }
+                None => {
+                    let entry = GeneAssociations {
+                        regions: IndexMap::from([(region.id(), weight)]),
+                        best_area: candidate.area.clone(),
+                        best_rank: rank,
+                        min_distance: candidate.distance,
+                        weight,
+                        length: region.length(),
+                        score,
+                    };
+                    self.genes.insert(candidate.gene.clone(), entry);
+                }
+            }
+        }
+    }
+
+    /// Genes in order of first association or, with a weight column, by
+    /// decreasing total weight.
+    fn ranked_genes(&self) -> Vec<(&String, &GeneAssociations)> {
+        let mut genes: Vec<(&String, &GeneAssociations)> = self.genes.iter().collect();
+        if self.weight_column.is_some() {
+            // Stable sort keeps the order of first association for full ties
+            genes.sort_by(|(_, a), (_, b)| {
+                b.weight
+                    .total_cmp(&a.weight)
+                    .then(a.best_rank.cmp(&b.best_rank))
+                    .then(a.min_distance.cmp(&b.min_distance))
+            });
+        }
+        genes
+    }
+
+    /// Write the summary table, returning the number of genes written.
+    ///
+    /// With a weight column, the regions of each gene are listed by
+    /// decreasing weight, the best region first.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<usize> {
+        let mut header = String::from("Gene\tRegionCount\tRegions\tBestArea\tMinDistance");
+        if self.weight_column.is_some() {
+            header.push_str("\tWeight");
+        }
+        writeln!(writer, "{}", header)?;
+
+        for (gene, entry) in self.ranked_genes() {
+            let mut regions: Vec<(&String, &f64)> = entry.regions.iter().collect();
+            if self.weight_column.is_some() {
+                regions.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+            }
+            let regions: Vec<&str> = regions.iter().map(|(id, _)| id.as_str()).collect();
+            let mut line = format!(
+                "{}\t{}\t{}\t{}\t{}",
+                gene,
+                regions.len(),
+                regions.join(","),
+                entry.best_area,
+                entry.min_distance
+            );
+            if self.weight_column.is_some() {
+                line.push_str(&format!("\t{}", entry.weight));
+            }
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(self.genes.len())
+    }
+
+    /// Write the per-gene table, in the order of the summary: region count,
+    /// best area, minimum distance, and summed region length and BED score
+    /// (NA without a score column), then the summed weight with a weight
+    /// column.
+    pub fn write_table<W: Write>(&self, writer: &mut W) -> Result<usize> {
+        let mut header =
+            String::from("Gene\tRegionCount\tBestArea\tMinDistance\tTotalLength\tTotalScore");
+        if self.weight_column.is_some() {
+            header.push_str("\tWeight");
+        }
+        writeln!(writer, "{}", header)?;
+        for (gene, entry) in self.ranked_genes() {
+            let score = entry.score.map_or("NA".to_string(), |s| s.to_string());
+            let mut line = format!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                gene,
+                entry.regions.len(),
+                entry.best_area,
+                entry.min_distance,
+                entry.length,
+                score
+            );
+            if self.weight_column.is_some() {
+                line.push_str(&format!("\t{}", entry.weight));
+            }
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(self.genes.len())
+    }
+}
+
+/// Gene-centric comparison of two region sets (e.g. treatment and control).
+///
+/// Each region counts once per gene, through its highest priority area.
+/// Genes associated only with the first set are GAINED, only with the second
+/// set LOST, and with both SHARED; they are written in order of first
+/// association, first set first.
+pub struct SetComparison {
+    rules: Vec<Area>,
+    genes: IndexMap<String, [IndexMap<Area, usize>; 2]>,
+}
+
+impl SetComparison {
+    /// Create an empty comparison ranking areas by the given priority rules.
+    pub fn new(rules: &[Area]) -> Self {
+        SetComparison {
+            rules: rules.to_vec(),
+            genes: IndexMap::new(),
+        }
+    }
+
+    /// Record the associations of a region of the first (0) or second (1) set.
+    pub fn add(&mut self, set: usize, candidates: &[Candidate]) {
+        for gene in anchor_genes(candidates, &self.rules) {
+            let counts = self.genes.entry(gene.gene).or_default();
+            *counts[set].entry(gene.area).or_insert(0) += 1;
+        }
+    }
+
+    /// Write the comparison table, returning the number of genes written.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<usize> {
+        writeln!(
+            writer,
+            "Gene\tStatus\tRegionCount\tCompareRegionCount\tAreas\tCompareAreas"
+        )?;
+
+        for (gene, [first, second]) in &self.genes {
+            let status = match (first.is_empty(), second.is_empty()) {
+                (false, true) => "GAINED",
+                (true, false) => "LOST",
+                _ => "SHARED",
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                gene,
+                status,
+                first.values().sum::<usize>(),
+                second.values().sum::<usize>(),
+                self.format_areas(first),
+                self.format_areas(second)
+            )?;
+        }
+        Ok(self.genes.len())
+    }
+
+    /// Per-area counts as AREA:count in rule priority order, NA if none.
+    fn format_areas(&self, counts: &IndexMap<Area, usize>) -> String {
+        if counts.is_empty() {
+            return "NA".to_string();
+        }
+        let rank = |area: &Area| {
+            self.rules
+                .iter()
+                .position(|a| a == area)
+                .unwrap_or(self.rules.len())
+        };
+        let mut areas: Vec<(&Area, &usize)> = counts.iter().collect();
+        areas.sort_by_key(|(area, _)| rank(area));
+        areas
+            .iter()
+            .map(|(area, count)| format!("{}:{}", area, count))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Genes associated with each area, exported as gene lists for enrichment
+/// tools (GO, GSEA).
+///
+/// Genes and areas keep their order of first association; INTERGENIC
+/// placeholders are ignored.
+pub struct GeneSets {
+    dir: PathBuf,
+    areas: IndexMap<Area, IndexSet<String>>,
+}
+
+impl GeneSets {
+    /// Create empty gene sets to be written to `dir`.
+    pub fn new(dir: &Path) -> Self {
+        GeneSets {
+            dir: dir.to_path_buf(),
+            areas: IndexMap::new(),
+        }
+    }
+
+    /// Record the associations reported for a region.
+    pub fn add(&mut self, candidates: &[Candidate]) {
+        for candidate in candidates {
+            if candidate.area == Area::Intergenic {
+                continue;
+            }
+            let genes = self.areas.entry(candidate.area.clone()).or_default();
+            // Avoid cloning genes already in the set
+            if !genes.contains(&candidate.gene) {
+                genes.insert(candidate.gene.clone());
+            }
+        }
+    }
+
+    /// Write one gene list per area (`<AREA>.txt`, one gene per line) and
+    /// the gene-area table (`gene_areas.tsv`), creating the directory.
+    pub fn write(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| {
+            format!("Failed to create gene set directory {}", self.dir.display())
+        })?;
+
+        let mut table = BufWriter::new(File::create(self.dir.join("gene_areas.tsv"))?);
+        writeln!(table, "Gene\tArea")?;
+        for (area, genes) in &self.areas {
+            let path = self.dir.join(format!("{}.txt", area));
+            let mut list = BufWriter::new(File::create(&path)?);
+            for gene in genes {
+                writeln!(list, "{}", gene)?;
+                writeln!(table, "{}\t{}", gene, area)?;
+            }
+            list.flush()?;
+        }
+        table.flush()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::{Area, Strand};
+    use crate::types::Strand;
 
     #[test]
     fn test_format_output_line() {
@@ -96,6 +1599,266 @@ mod tests {
         assert!(line.contains("name1"));
     }
 
+    #[test]
+    fn test_column_selection() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()]);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            50.0,
+            0,
+        );
+        let config = Config {
+            signed_distance: true,
+            select_columns: ["Gene:gene_id", "SignedDistance", "strand", "Region"]
+                .iter()
+                .map(|c| c.parse().unwrap())
+                .collect(),
+            ..Default::default()
+        };
+        let mut options = OutputOptions::from_config(&config);
+
+        let mut header = Vec::new();
+        write_header_with(&mut header, 1, &options).unwrap();
+        assert_eq!(header, b"gene_id\tSignedDistance\tstrand\tRegion\n");
+        // The region has no strand column
+        assert_eq!(
+            format_output_line_with(&region, &candidate, &options),
+            "G1\t0\tNA\tchr1_100_200"
+        );
+
+        // Indexed coordinates stay first
+        options.coordinates = true;
+        assert_eq!(
+            format_output_line_with(&region, &candidate, &options),
+            "chr1\t100\t200\tG1\t0\tNA\tchr1_100_200"
+        );
+    }
+
+    #[test]
+    fn test_annotated_bed_line() {
+        let region = Region::new(
+            "chr1".to_string(),
+            100,
+            200,
+            vec!["peak1".to_string(), "35".to_string(), "+\n".to_string()],
+        );
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            50,
+            80.0,
+            90.0,
+            500,
+        );
+        let options = OutputOptions {
+            format: OutputFormat::Bed,
+            ..Default::default()
+        };
+
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert_eq!(line, "chr1\t100\t200\tpeak1\t35\t+\tG1\tTSS\t50");
+
+        let unmatched = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &unmatched, &options);
+        assert_eq!(line, "chr1\t100\t200\tpeak1\t35\t+\t.\tINTERGENIC\t.");
+
+        let mut header = Vec::new();
+        write_header_with(&mut header, 3, &options).unwrap();
+        assert!(header.is_empty());
+    }
+
+    #[test]
+    fn test_wide_line() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()]);
+        let candidate = |gene: &str, area: Area, distance: i64| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                gene.to_string(),
+                distance,
+                100.0,
+                50.0,
+                distance,
+            )
+        };
+        let candidates = vec![
+            candidate("G1", Area::Intron, 0),
+            candidate("G2", Area::Upstream, 3000),
+            candidate("G3", Area::Tss, 0),
+        ];
+        let options = OutputOptions {
+            wide: Some(4),
+            rules: vec![Area::Tss, Area::Intron, Area::Upstream],
+            ..Default::default()
+        };
+
+        let line = format_wide_line(&region, &candidates, &options);
+        assert_eq!(
+            line,
+            "chr1_100_200\t150\tG3\tTSS\t0\tG1\tINTRON\t0\tG2\tUPSTREAM\t3000\tNA\tNA\tNA\tpeak1"
+        );
+        let unmatched = [crate::matcher::intergenic_candidate(&region)];
+        let line = format_wide_line(&region, &unmatched, &options);
+        assert!(line.starts_with("chr1_100_200\t150\tNA\tINTERGENIC\tNA\tNA\tNA\tNA\t"));
+
+        let mut header = Vec::new();
+        write_header_with(&mut header, 1, &options).unwrap();
+        let header = String::from_utf8(header).unwrap();
+        assert!(header.starts_with("Region\tMidpoint\tGene1\tArea1\tDistance1\tGene2\t"));
+        assert!(header.ends_with("\tDistance4\tname\n"));
+    }
+
+    #[test]
+    fn test_closest_line() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()]);
+        let mut extents = GeneExtents::default();
+        extents.genes.insert("G1".to_string(), (1001, 2000));
+        extents.genes.insert("G2".to_string(), (151, 300));
+        let options = OutputOptions {
+            compat: Some(Compat::BedtoolsClosest),
+            gene_extents: Some(Arc::new(extents)),
+            ..Default::default()
+        };
+        let candidate = |gene: &str| {
+            Candidate::new(
+                1001,
+                1100,
+                Strand::Negative,
+                "1".to_string(),
+                Area::Upstream,
+                "T1".to_string(),
+                gene.to_string(),
+                900,
+                100.0,
+                0.0,
+                -1800,
+            )
+        };
+
+        let line = format_output_line_with(&region, &candidate("G1"), &options);
+        assert_eq!(
+            line,
+            "chr1\t100\t200\tpeak1\tchr1\t1000\t2000\tG1\t0\t-\t801"
+        );
+        let line = format_output_line_with(&region, &candidate("G2"), &options);
+        assert!(line.ends_with("\tchr1\t150\t300\tG2\t0\t-\t0"));
+
+        let unmatched = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &unmatched, &options);
+        assert_eq!(line, "chr1\t100\t200\tpeak1\t.\t-1\t-1\t.\t-1\t.\t-1");
+
+        let mut header = Vec::new();
+        write_header_with(&mut header, 1, &options).unwrap();
+        assert!(header.is_empty());
+    }
+
+    #[test]
+    fn test_track_line() {
+        let region = Region::new(
+            "chr1".to_string(),
+            100,
+            200,
+            vec!["peak1".to_string(), "35".to_string(), "-".to_string()],
+        );
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            500,
+            80.0,
+            90.0,
+            -500,
+        );
+        let mut options = OutputOptions {
+            format: OutputFormat::Ucsc,
+            ..Default::default()
+        };
+
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert_eq!(line, "chr1\t100\t200\tG1:TSS\t0\t-\t100\t200\t215,48,39");
+
+        let unmatched = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &unmatched, &options);
+        assert_eq!(
+            line,
+            "chr1\t100\t200\tINTERGENIC\t0\t-\t100\t200\t153,153,153"
+        );
+
+        // Labels with spaces stay a single field
+        options.compat = Some(Compat::ChipSeeker);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.contains("\tG1:Promoter_(<=1kb)\t"));
+
+        let mut header = Vec::new();
+        write_header_with(&mut header, 3, &options).unwrap();
+        assert!(String::from_utf8(header)
+            .unwrap()
+            .starts_with("track name=\"rgmatch\""));
+    }
+
+    #[test]
+    fn test_gff_line() {
+        let region = Region::new(
+            "chr1".to_string(),
+            100,
+            200,
+            vec!["peak1".to_string(), "35".to_string(), "-".to_string()],
+        );
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            Area::Promoter,
+            "T1".to_string(),
+            "G1;a=b".to_string(),
+            500,
+            80.0,
+            90.0,
+            -500,
+        );
+        let options = OutputOptions {
+            format: OutputFormat::Gff3,
+            ..Default::default()
+        };
+
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert_eq!(
+            line,
+            "chr1\trgmatch\tregion\t100\t200\t.\t-\t.\tName=G1%3Ba%3Db;gene=G1%3Ba%3Db;\
+             transcript=T1;area=PROMOTER;distance=500;tss_distance=-500;region=chr1_100_200"
+        );
+
+        let unmatched = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &unmatched, &options);
+        assert!(line.ends_with("\t.\tarea=INTERGENIC;region=chr1_100_200"));
+
+        let mut header = Vec::new();
+        write_header_with(&mut header, 3, &options).unwrap();
+        assert_eq!(header, b"##gff-version 3\n");
+    }
+
     #[test]
     fn test_midpoint_is_integer() {
         // Test that midpoint uses integer division
@@ -144,6 +1907,699 @@ mod tests {
         assert!(line.contains("-1.00"));
     }
 
+    #[test]
+    fn test_intergenic_line() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["name1".to_string()]);
+        let candidate = crate::matcher::intergenic_candidate(&region);
+
+        let line = format_output_line(&region, &candidate);
+
+        assert_eq!(
+            line,
+            "chr1_100_200\t150\tNA\tNA\tNA\tINTERGENIC\tNA\tNA\tNA\tNA\tname1"
+        );
+    }
+
+    #[test]
+    fn test_tss_distance_list() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let mut candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1,2".to_string(),
+            Area::Intron,
+            "T1,T2".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            50.0,
+            300,
+        );
+        candidate.tss_distances = Some("300,1200".to_string());
+
+        let line = format_output_line(&region, &candidate);
+        assert_eq!(line.split('\t').nth(7), Some("300"));
+
+        let options = OutputOptions {
+            tss_distance_list: true,
+            ..OutputOptions::default()
+        };
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert_eq!(line.split('\t').nth(7), Some("300,1200"));
+    }
+
+    #[test]
+    fn test_antisense_column() {
+        let options = OutputOptions {
+            columns: vec![Column::Antisense],
+            ..OutputOptions::default()
+        };
+
+        let mut output = Vec::new();
+        write_header_with(&mut output, 1, &options).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("PercArea\tAntisense\tname\n"));
+
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["name1".to_string()]);
+        let mut candidate = crate::matcher::intergenic_candidate(&region);
+        candidate.antisense = Some(true);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA\t1\tname1"));
+
+        candidate.antisense = None;
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA\tNA\tname1"));
+    }
+
+    #[test]
+    fn test_loop_line() {
+        let lp = Loop {
+            anchor1: Region::new("chr1".to_string(), 100, 200, vec![]),
+            anchor2: Region::new("chr1".to_string(), 5000, 5100, vec![]),
+            metadata: vec!["loop1".to_string()],
+        };
+        let pair = LoopPair {
+            gene1: Some(AnchorGene {
+                gene: "G1".to_string(),
+                area: Area::Tss,
+                distance: 0,
+            }),
+            gene2: None,
+        };
+
+        let mut output = Vec::new();
+        write_loop_header(&mut output, 1).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("\tClass\tname\n"));
+
+        assert_eq!(
+            format_loop_line(&lp, &pair),
+            "chr1_100_200\tchr1_5000_5100\tG1\tTSS\t0\tNA\tINTERGENIC\tNA\tpromoter-enhancer\tloop1"
+        );
+    }
+
+    #[test]
+    fn test_association_id() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let mut candidate = crate::matcher::intergenic_candidate(&region);
+        let id = association_id(&region, &candidate);
+        assert_eq!(id.len(), 16);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        // Only the association itself counts, not its measures
+        candidate.pctg_region = 50.0;
+        assert_eq!(association_id(&region, &candidate), id);
+        candidate.area = Area::Tss;
+        assert_ne!(association_id(&region, &candidate), id);
+
+        let options = OutputOptions {
+            columns: vec![Column::AssociationId],
+            ..OutputOptions::default()
+        };
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with(&association_id(&region, &candidate)));
+    }
+
+    #[test]
+    fn test_gene_coverage_columns() {
+        let options = OutputOptions {
+            columns: vec![Column::PercGene, Column::PercTranscript],
+            ..OutputOptions::default()
+        };
+
+        let mut output = Vec::new();
+        write_header_with(&mut output, 0, &options).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("PercArea\tPercGene\tPercTranscript\n"));
+
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let mut candidate = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA\tNA"));
+
+        candidate.pctg_gene = Some(12.345);
+        candidate.pctg_transcript = Some(100.0);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\t12.35\t100.00"));
+    }
+
+    #[test]
+    fn test_overlap_bp_columns() {
+        let options = OutputOptions {
+            columns: vec![Column::OverlapArea, Column::OverlapGene],
+            ..OutputOptions::default()
+        };
+
+        let mut output = Vec::new();
+        write_header_with(&mut output, 0, &options).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("PercArea\tOverlapArea\tOverlapGene\n"));
+
+        let region = Region::new("chr1".to_string(), 101, 200, vec![]);
+        let mut candidate = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA\tNA"));
+
+        candidate.area = Area::Intron;
+        candidate.pctg_region = 37.0;
+        candidate.overlap_gene = Some(80);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\t37\t80"));
+    }
+
+    #[test]
+    fn test_region_strand_column() {
+        let options = OutputOptions {
+            columns: vec![Column::RegionStrand],
+            ..OutputOptions::default()
+        };
+        let region = |strand: &str| {
+            Region::new(
+                "chr1".to_string(),
+                101,
+                200,
+                vec!["peak".to_string(), "0".to_string(), strand.to_string()],
+            )
+        };
+        let candidate = crate::matcher::intergenic_candidate(&region("-"));
+
+        let line = format_output_line_with(&region("-"), &candidate, &options);
+        assert!(line.ends_with("\t-\tpeak\t0\t-"));
+        let line = format_output_line_with(&region("."), &candidate, &options);
+        assert!(line.ends_with("\tNA\tpeak\t0\t."));
+    }
+
+    #[test]
+    fn test_region_columns() {
+        let region = Region::new("chr1_KI270706v1_random".to_string(), 100, 200, vec![]);
+        let candidate = crate::matcher::intergenic_candidate(&region);
+        let line = |region_columns| {
+            let options = OutputOptions {
+                region_columns,
+                ..OutputOptions::default()
+            };
+            format_output_line_with(&region, &candidate, &options)
+        };
+        assert!(line(RegionColumns::Id).starts_with("chr1_KI270706v1_random_100_200\t150\t"));
+        assert!(line(RegionColumns::Split).starts_with("chr1_KI270706v1_random\t100\t200\t150\t"));
+        assert!(line(RegionColumns::Both).starts_with(
+            "chr1_KI270706v1_random_100_200\tchr1_KI270706v1_random\t100\t200\t150\t"
+        ));
+
+        let options = OutputOptions {
+            region_columns: RegionColumns::Split,
+            ..OutputOptions::default()
+        };
+        let mut header = Vec::new();
+        write_header_with(&mut header, 0, &options).unwrap();
+        assert!(header.starts_with(b"Chrom\tStart\tEnd\tMidpoint\t"));
+        assert_eq!(
+            options.table_columns()[..4],
+            ["Chrom", "Start", "End", "Midpoint"]
+        );
+    }
+
+    #[test]
+    fn test_header_options() {
+        let header = |options: &OutputOptions| {
+            let mut header = Vec::new();
+            write_header_with(&mut header, 4, options).unwrap();
+            String::from_utf8(header).unwrap()
+        };
+        let mut options = OutputOptions {
+            bed_names: vec!["peak".to_string(), "signal".to_string()],
+            comment_header: true,
+            ..OutputOptions::default()
+        };
+        let text = header(&options);
+        assert!(text.starts_with("#Region\tMidpoint\t"));
+        assert!(text.ends_with("\tPercArea\tpeak\tsignal\tstrand\tthickStart\n"));
+        assert!(options.table_columns().contains(&"signal"));
+        assert!(!options.table_columns().contains(&"score"));
+
+        // Indexed coordinates already start with #
+        options.coordinates = true;
+        assert!(header(&options).starts_with("#Chrom\tStart\tEnd\tRegion\t"));
+
+        options.no_header = true;
+        assert_eq!(header(&options), "");
+    }
+
+    #[test]
+    fn test_biotype_columns() {
+        let mut biotypes = Biotypes::default();
+        biotypes
+            .genes
+            .insert("G1".to_string(), "protein_coding".to_string());
+        biotypes
+            .transcripts
+            .insert("T1".to_string(), "retained_intron".to_string());
+        let options = OutputOptions {
+            columns: vec![Column::GeneType, Column::TranscriptType],
+            biotypes: Some(Arc::new(biotypes)),
+            ..OutputOptions::default()
+        };
+
+        let mut output = Vec::new();
+        write_header_with(&mut output, 0, &options).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("PercArea\tGeneType\tTranscriptType\n"));
+
+        let region = Region::new("chr1".to_string(), 101, 200, vec![]);
+        let mut candidate = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA\tNA"));
+
+        // Merged transcripts get one type each, NA when unknown
+        candidate.area = Area::Intron;
+        candidate.gene = "G1".to_string();
+        candidate.transcript = "T1,T2".to_string();
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tprotein_coding\tretained_intron,NA"));
+    }
+
+    #[test]
+    fn test_exon_count_column() {
+        let mut counts = ExonCounts::default();
+        counts.transcripts.insert("T1".to_string(), 4);
+        let options = OutputOptions {
+            columns: vec![Column::ExonCount],
+            exon_counts: Some(Arc::new(counts)),
+            ..OutputOptions::default()
+        };
+
+        let mut output = Vec::new();
+        write_header_with(&mut output, 0, &options).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("PercArea\tExonCount\n"));
+
+        let region = Region::new("chr1".to_string(), 101, 200, vec![]);
+        let mut candidate = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA"));
+
+        candidate.area = Area::Intron;
+        candidate.transcript = "T1,T2".to_string();
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\t4,NA"));
+    }
+
+    #[test]
+    fn test_area_coordinate_columns() {
+        let options = OutputOptions {
+            columns: vec![Column::AreaStart, Column::AreaEnd],
+            ..OutputOptions::default()
+        };
+
+        let mut output = Vec::new();
+        write_header_with(&mut output, 0, &options).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("PercArea\tAreaStart\tAreaEnd\n"));
+
+        let region = Region::new("chr1".to_string(), 101, 200, vec![]);
+        let mut candidate = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA\tNA"));
+
+        candidate.area = Area::Intron;
+        candidate.start = 51;
+        candidate.end = 149;
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\t51\t149"));
+    }
+
+    #[test]
+    fn test_signed_distance_column() {
+        let options = OutputOptions {
+            columns: vec![Column::SignedDistance],
+            ..OutputOptions::default()
+        };
+
+        let mut output = Vec::new();
+        write_header_with(&mut output, 0, &options).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.ends_with("PercArea\tSignedDistance\n"));
+
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let mut candidate = Candidate::new(
+            300,
+            400,
+            Strand::Negative,
+            "1".to_string(),
+            Area::Upstream,
+            "T1".to_string(),
+            "G1".to_string(),
+            250,
+            100.0,
+            -1.0,
+            -250,
+        );
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\t250\t-250\t100.00\t-1.00\t-250"));
+
+        candidate.area = Area::Downstream;
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\t250"));
+    }
+
+    #[test]
+    fn test_count_column() {
+        let config = Config {
+            duplicates: DuplicateMode::Collapse,
+            ..Config::default()
+        };
+        let options = OutputOptions::from_config(&config);
+        assert_eq!(options.columns, vec![Column::Count]);
+
+        let mut region = Region::new("chr1".to_string(), 100, 200, vec!["name1".to_string()]);
+        region.copies = 3;
+        let candidate = crate::matcher::intergenic_candidate(&region);
+        let line = format_output_line_with(&region, &candidate, &options);
+        assert!(line.ends_with("\tNA\t3\tname1"));
+    }
+
+    #[test]
+    fn test_chipseeker_category() {
+        let candidate = |area: Area, distance: i64, tss_distance: i64| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                "G1".to_string(),
+                distance,
+                100.0,
+                -1.0,
+                tss_distance,
+            )
+        };
+
+        assert_eq!(
+            chipseeker_category(&candidate(Area::Tss, 0, -150)),
+            "Promoter (<=1kb)"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::Upstream, 1500, -1500)),
+            "Promoter (1-2kb)"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::Intron, 0, 2500)),
+            "Promoter (2-3kb)"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::Intron, 0, 8000)),
+            "Intron"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::GeneBody, 0, 8000)),
+            "Exon"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::FivePrimeUtr, 0, 4000)),
+            "5' UTR"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::Downstream, 200, 9000)),
+            "Downstream (<=300)"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::Downstream, 2000, 9000)),
+            "Distal Intergenic"
+        );
+        assert_eq!(
+            chipseeker_category(&candidate(Area::Upstream, 6000, -6000)),
+            "Distal Intergenic"
+        );
+
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let options = OutputOptions {
+            compat: Some(Compat::ChipSeeker),
+            ..OutputOptions::default()
+        };
+        let line = format_output_line_with(
+            &region,
+            &crate::matcher::intergenic_candidate(&region),
+            &options,
+        );
+        assert!(line.contains("\tDistal Intergenic\t"));
+    }
+
+    #[test]
+    fn test_gene_summary() {
+        let candidate = |gene: &str, area: Area, distance: i64| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                gene.to_string(),
+                distance,
+                100.0,
+                -1.0,
+                0,
+            )
+        };
+        let region1 = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let region2 = Region::new("chr1".to_string(), 500, 600, vec![]);
+        let region3 = Region::new("chr1".to_string(), 900, 1000, vec![]);
+
+        let mut summary = GeneSummary::new(&crate::config::DEFAULT_RULES);
+        summary.add(
+            &region1,
+            &[
+                candidate("G1", Area::Upstream, 800),
+                candidate("G2", Area::Intron, 0),
+            ],
+        );
+        summary.add(&region2, &[candidate("G1", Area::Tss, 150)]);
+        summary.add(&region3, &[crate::matcher::intergenic_candidate(&region3)]);
+
+        let mut output = Vec::new();
+        assert_eq!(summary.write(&mut output).unwrap(), 2);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Gene\tRegionCount\tRegions\tBestArea\tMinDistance",
+                "G1\t2\tchr1_100_200,chr1_500_600\tTSS\t150",
+                "G2\t1\tchr1_100_200\tINTRON\t0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gene_summary_weights() {
+        let candidate = |gene: &str, area: Area| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                gene.to_string(),
+                0,
+                100.0,
+                -1.0,
+                0,
+            )
+        };
+        let region = |start: i64, score: &str| {
+            Region::new(
+                "chr1".to_string(),
+                start,
+                start + 100,
+                vec!["peak".to_string(), score.to_string()],
+            )
+        };
+
+        let mut summary = GeneSummary::new(&crate::config::DEFAULT_RULES);
+        summary.weight_column = Some(5);
+        summary.add(
+            &region(100, "10"),
+            &[candidate("G1", Area::Intron), candidate("G2", Area::Tss)],
+        );
+        summary.add(
+            &region(500, "20,5"),
+            &[candidate("G2", Area::Intron), candidate("G2", Area::Tss)],
+        );
+        summary.add(&region(900, "."), &[candidate("G3", Area::Tss)]);
+        summary.add(&region(1300, "10"), &[candidate("G4", Area::Tss)]);
+
+        let mut output = Vec::new();
+        summary.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Gene\tRegionCount\tRegions\tBestArea\tMinDistance\tWeight",
+                // The heavier region first
+                "G2\t2\tchr1_500_600,chr1_100_200\tTSS\t0\t35",
+                // Equal weights rank by best area
+                "G4\t1\tchr1_1300_1400\tTSS\t0\t10",
+                "G1\t1\tchr1_100_200\tINTRON\t0\t10",
+                "G3\t1\tchr1_900_1000\tTSS\t0\t0",
+            ]
+        );
+
+        // The gene table ranks genes the same way
+        let mut output = Vec::new();
+        summary.write_table(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let genes: Vec<&str> = output
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(genes, vec!["Gene", "G2", "G4", "G1", "G3"]);
+        assert!(output.starts_with(
+            "Gene\tRegionCount\tBestArea\tMinDistance\tTotalLength\tTotalScore\tWeight\n"
+        ));
+    }
+
+    #[test]
+    fn test_gene_table() {
+        let candidate = |gene: &str, area: Area, distance: i64| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                gene.to_string(),
+                distance,
+                100.0,
+                -1.0,
+                0,
+            )
+        };
+        let region = |start: i64, metadata: &[&str]| {
+            Region::new(
+                "chr1".to_string(),
+                start,
+                start + 99,
+                metadata.iter().map(|m| m.to_string()).collect(),
+            )
+        };
+
+        let mut summary = GeneSummary::new(&crate::config::DEFAULT_RULES);
+        summary.add(
+            &region(100, &["p1", "10"]),
+            &[
+                candidate("G1", Area::Upstream, 800),
+                candidate("G1", Area::Intron, 0),
+            ],
+        );
+        summary.add(
+            &region(500, &["p2", "2.5"]),
+            &[candidate("G1", Area::Tss, 150)],
+        );
+        summary.add(&region(900, &["p3"]), &[candidate("G2", Area::Tss, 50)]);
+
+        let mut output = Vec::new();
+        assert_eq!(summary.write_table(&mut output).unwrap(), 2);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        // Each region counts once per gene
+        assert_eq!(
+            lines,
+            vec![
+                "Gene\tRegionCount\tBestArea\tMinDistance\tTotalLength\tTotalScore",
+                "G1\t2\tTSS\t0\t200\t12.5",
+                "G2\t1\tTSS\t50\t100\tNA",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_comparison() {
+        let candidate = |gene: &str, area: Area| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                gene.to_string(),
+                0,
+                100.0,
+                -1.0,
+                0,
+            )
+        };
+
+        let mut comparison = SetComparison::new(&crate::config::DEFAULT_RULES);
+        comparison.add(
+            0,
+            &[
+                candidate("G1", Area::Intron),
+                candidate("G1", Area::Tss),
+                candidate("G2", Area::Upstream),
+            ],
+        );
+        comparison.add(0, &[candidate("G1", Area::Intron)]);
+        comparison.add(1, &[candidate("G1", Area::Tss)]);
+        comparison.add(1, &[candidate("G3", Area::Intron)]);
+
+        let mut output = Vec::new();
+        assert_eq!(comparison.write(&mut output).unwrap(), 3);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Gene\tStatus\tRegionCount\tCompareRegionCount\tAreas\tCompareAreas",
+                "G1\tSHARED\t2\t1\tTSS:1,INTRON:1\tTSS:1",
+                "G2\tGAINED\t1\t0\tUPSTREAM:1\tNA",
+                "G3\tLOST\t0\t1\tNA\tINTRON:1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gene_sets() {
+        let candidate = |gene: &str, area: Area| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                "1".to_string(),
+                area,
+                "T1".to_string(),
+                gene.to_string(),
+                0,
+                100.0,
+                -1.0,
+                0,
+            )
+        };
+        let region = Region::new("chr1".to_string(), 900, 1000, vec![]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut gene_sets = GeneSets::new(&dir.path().join("sets"));
+        gene_sets.add(&[candidate("G1", Area::Intron), candidate("G2", Area::Tss)]);
+        gene_sets.add(&[candidate("G3", Area::Intron), candidate("G1", Area::Intron)]);
+        gene_sets.add(&[crate::matcher::intergenic_candidate(&region)]);
+        gene_sets.write().unwrap();
+
+        let read =
+            |name: &str| std::fs::read_to_string(dir.path().join("sets").join(name)).unwrap();
+        assert_eq!(read("INTRON.txt"), "G1\nG3\n");
+        assert_eq!(read("TSS.txt"), "G2\n");
+        assert_eq!(
+            read("gene_areas.tsv"),
+            "Gene\tArea\nG1\tINTRON\nG3\tINTRON\nG2\tTSS\n"
+        );
+        assert!(!dir.path().join("sets").join("INTERGENIC.txt").exists());
+    }
+
     #[test]
     fn test_write_header() {
         let mut output = Vec::new();
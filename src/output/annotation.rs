@@ -0,0 +1,204 @@
+//! Summary of an annotation.
+//!
+//! Counts of the genes, transcripts and exons on each chromosome of a GTF
+//! or index, and of the genes and transcripts of each biotype, for checking
+//! an annotation before matching regions against it. The TSS counts and gene
+//! lengths help choosing the distances of a run.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use ahash::AHashSet;
+use anyhow::Result;
+
+use crate::output::sort::compare_chroms;
+use crate::parser::GtfData;
+use crate::types::Strand;
+
+/// Features counted on a chromosome.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeatureCounts {
+    pub genes: usize,
+    pub transcripts: usize,
+    pub exons: usize,
+    /// Distinct transcription start sites (transcripts may share one).
+    pub tss: usize,
+    /// Length in bp of the longest gene.
+    pub max_gene_length: i64,
+}
+
+impl FeatureCounts {
+    fn add(&mut self, other: &FeatureCounts) {
+        self.genes += other.genes;
+        self.transcripts += other.transcripts;
+        self.exons += other.exons;
+        self.tss += other.tss;
+        self.max_gene_length = self.max_gene_length.max(other.max_gene_length);
+    }
+}
+
+/// Genes and transcripts of a biotype.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BiotypeCounts {
+    pub genes: usize,
+    pub transcripts: usize,
+}
+
+/// Feature counts of an annotation per chromosome and per biotype.
+#[derive(Debug, Clone)]
+pub struct AnnotationStats {
+    /// Counts per chromosome, in karyotype order.
+    pub chromosomes: Vec<(String, FeatureCounts)>,
+    /// Counts per biotype (NA when unknown), most genes first.
+    pub biotypes: Vec<(String, BiotypeCounts)>,
+}
+
+impl AnnotationStats {
+    /// Count the features of a parsed annotation.
+    pub fn from_gtf(gtf_data: &GtfData) -> Self {
+        let mut chromosomes: Vec<(String, FeatureCounts)> = gtf_data
+            .genes_by_chrom
+            .iter()
+            .map(|(chrom, genes)| {
+                let tss: AHashSet<(bool, i64)> = genes
+                    .iter()
+                    .flat_map(|g| g.transcripts.iter().map(move |t| (g.strand, t)))
+                    .map(|(strand, t)| match strand {
+                        Strand::Negative => (true, t.end),
+                        _ => (false, t.start),
+                    })
+                    .collect();
+                let counts = FeatureCounts {
+                    genes: genes.len(),
+                    transcripts: genes.iter().map(|g| g.transcripts.len()).sum(),
+                    exons: genes
+                        .iter()
+                        .flat_map(|g| &g.transcripts)
+                        .map(|t| t.exons.len())
+                        .sum(),
+                    tss: tss.len(),
+                    max_gene_length: genes.iter().map(|g| g.end - g.start + 1).max().unwrap_or(0),
+                };
+                (chrom.clone(), counts)
+            })
+            .collect();
+        chromosomes.sort_by(|(a, _), (b, _)| compare_chroms(a, b));
+
+        let mut biotypes: BTreeMap<String, BiotypeCounts> = BTreeMap::new();
+        let name = |biotype: &Option<String>| biotype.as_deref().unwrap_or("NA").to_string();
+        for gene in gtf_data.genes_by_chrom.values().flatten() {
+            biotypes.entry(name(&gene.biotype)).or_default().genes += 1;
+            for transcript in &gene.transcripts {
+                biotypes
+                    .entry(name(&transcript.biotype))
+                    .or_default()
+                    .transcripts += 1;
+            }
+        }
+        let mut biotypes: Vec<(String, BiotypeCounts)> = biotypes.into_iter().collect();
+        biotypes.sort_by_key(|(_, counts)| std::cmp::Reverse((counts.genes, counts.transcripts)));
+        AnnotationStats {
+            chromosomes,
+            biotypes,
+        }
+    }
+
+    /// Counts over all chromosomes.
+    pub fn total(&self) -> FeatureCounts {
+        let mut total = FeatureCounts::default();
+        for (_, counts) in &self.chromosomes {
+            total.add(counts);
+        }
+        total
+    }
+
+    /// Write one row per chromosome and a `Total` row, then after an empty
+    /// line one row per biotype.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(
+            writer,
+            "Chrom\tGenes\tTranscripts\tExons\tTSS\tMaxGeneLength"
+        )?;
+        let total = self.total();
+        let rows = self.chromosomes.iter().map(|(c, n)| (c.as_str(), n));
+        for (chrom, counts) in rows.chain([("Total", &total)]) {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                chrom,
+                counts.genes,
+                counts.transcripts,
+                counts.exons,
+                counts.tss,
+                counts.max_gene_length
+            )?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "Biotype\tGenes\tTranscripts")?;
+        for (biotype, counts) in &self.biotypes {
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                biotype, counts.genes, counts.transcripts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Exon, Gene, Strand, Transcript};
+    use ahash::AHashMap;
+
+    fn gene(id: &str, strand: Strand, exons: &[usize]) -> Gene {
+        let mut gene = Gene::new(id.to_string(), strand);
+        for (i, &count) in exons.iter().enumerate() {
+            let mut transcript = Transcript::new(format!("{}.{}", id, i));
+            for exon in 0..count as i64 {
+                transcript.add_exon(Exon::new(100 * exon + 1, 100 * exon + 50));
+            }
+            transcript.calculate_size();
+            gene.add_transcript(transcript);
+        }
+        gene.calculate_size();
+        gene
+    }
+
+    #[test]
+    fn test_annotation_stats() {
+        let mut genes_by_chrom = AHashMap::new();
+        let mut g1 = gene("G1", Strand::Positive, &[2, 3]);
+        g1.biotype = Some("protein_coding".to_string());
+        g1.transcripts[0].biotype = Some("protein_coding".to_string());
+        g1.transcripts[1].biotype = Some("retained_intron".to_string());
+        let mut g2 = gene("G2", Strand::Negative, &[1, 3]);
+        g2.biotype = Some("lncRNA".to_string());
+        let mut g3 = gene("G3", Strand::Positive, &[1]);
+        g3.biotype = Some("protein_coding".to_string());
+        genes_by_chrom.insert("chr10".to_string(), vec![g3]);
+        genes_by_chrom.insert("chr2".to_string(), vec![g1, g2]);
+        let gtf_data = GtfData {
+            genes_by_chrom,
+            max_lengths: AHashMap::new(),
+        };
+
+        let stats = AnnotationStats::from_gtf(&gtf_data);
+        let mut text = Vec::new();
+        stats.write(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "Chrom\tGenes\tTranscripts\tExons\tTSS\tMaxGeneLength\n\
+             chr2\t2\t4\t9\t3\t250\n\
+             chr10\t1\t1\t1\t1\t50\n\
+             Total\t3\t5\t10\t4\t250\n\
+             \n\
+             Biotype\tGenes\tTranscripts\n\
+             protein_coding\t2\t1\n\
+             lncRNA\t1\t0\n\
+             NA\t0\t3\n\
+             retained_intron\t0\t1\n"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Checkpoints of a streaming run.
+//!
+//! A checkpoint records how far a run got: the byte offset in the BED file
+//! after the last region written, and the length of the output up to it.
+//! An interrupted run continues from there instead of starting over, as long
+//! as it has the same options and output header.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// Progress of a run, written as JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// BED file of the run.
+    pub bed: String,
+    /// Size of the BED file on disk, to notice a changed input.
+    pub bed_bytes: u64,
+    pub output: String,
+    /// Bytes of the (uncompressed) BED file whose regions are written.
+    pub bed_offset: u64,
+    /// Regions read up to `bed_offset`.
+    pub regions: u64,
+    /// Work items (chunks of regions) written.
+    pub work_items: u64,
+    /// Length of the output up to the last region written.
+    pub output_bytes: u64,
+    /// Lines written to the output, header excluded.
+    pub lines_written: u64,
+    /// Regions with at least one association up to `bed_offset`.
+    #[serde(default)]
+    pub matched_regions: u64,
+    /// Hex SHA-256 of the resolved options of the run.
+    #[serde(default)]
+    pub options: String,
+    /// Header written to the output, None until it is written.
+    #[serde(default)]
+    pub header: Option<String>,
+    /// BED metadata columns the header was written for.
+    #[serde(default)]
+    pub num_meta_columns: usize,
+}
+
+/// Hex SHA-256 of the options that shape the output of a run, leaving out
+/// those that only tune its performance.
+fn options_hash(config: &Config) -> Result<String> {
+    let config = Config {
+        max_memory: None,
+        io_threads: None,
+        adaptive_threads: false,
+        unordered: false,
+        ..config.clone()
+    };
+    let json = serde_json::to_vec(&config)?;
+    Ok(Sha256::digest(&json)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+impl Checkpoint {
+    /// Start the checkpoints of a run from the beginning of its BED file.
+    pub fn new(bed: &Path, output: &Path, config: &Config) -> Result<Self> {
+        let bed_bytes = fs::metadata(bed)
+            .with_context(|| format!("Failed to read BED file {}", bed.display()))?
+            .len();
+        Ok(Checkpoint {
+            bed: bed.display().to_string(),
+            bed_bytes,
+            output: output.display().to_string(),
+            options: options_hash(config)?,
+            ..Checkpoint::default()
+        })
+    }
+
+    /// Read a checkpoint file, None if there is none.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read checkpoint file {}", path.display()))?;
+        let checkpoint = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse checkpoint file {}", path.display()))?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Check that the checkpoint was written by a run of `bed` into `output`
+    /// with the same options, with the BED file unchanged since.
+    pub fn check(&self, bed: &Path, output: &Path, config: &Config) -> Result<()> {
+        let resumed = Checkpoint::new(bed, output, config)?;
+        if self.bed != resumed.bed || self.output != resumed.output {
+            bail!(
+                "The checkpoint is of a run of {} into {}, not of {} into {}",
+                self.bed,
+                self.output,
+                resumed.bed,
+                resumed.output
+            );
+        }
+        if self.bed_bytes != resumed.bed_bytes {
+            bail!("The BED file {} changed since the checkpoint", self.bed);
+        }
+        if self.options != resumed.options {
+            bail!(
+                "The checkpoint is of a run with other options than this one, \
+                 resume it with the same options or start over without --resume"
+            );
+        }
+        Ok(())
+    }
+
+    /// Check that the output header, as the resumed run would write it,
+    /// is the one written before the checkpoint.
+    pub fn check_header(&self, header: &str) -> Result<()> {
+        if self
+            .header
+            .as_deref()
+            .is_some_and(|written| written != header)
+        {
+            bail!(
+                "The output of the checkpoint has another header than this run would write, \
+                 start over without --resume"
+            );
+        }
+        Ok(())
+    }
+
+    /// Write the checkpoint to `path`, replacing the previous one at once so
+    /// that an interruption leaves either of them.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let file = File::create(&temp).context("Failed to create checkpoint file")?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(&temp, path).context("Failed to write checkpoint file")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let bed = dir.path().join("regions.bed");
+        fs::write(&bed, "chr1\t100\t200\n").unwrap();
+        let output = dir.path().join("out.tsv");
+        let path = dir.path().join("run.checkpoint");
+        assert_eq!(Checkpoint::read(&path).unwrap(), None);
+
+        let checkpoint = Checkpoint {
+            bed_offset: 13,
+            regions: 1,
+            work_items: 1,
+            output_bytes: 120,
+            lines_written: 2,
+            matched_regions: 1,
+            header: Some("Region\tGene\n".to_string()),
+            ..Checkpoint::new(&bed, &output, &Config::default()).unwrap()
+        };
+        assert_eq!(checkpoint.bed_bytes, 13);
+        checkpoint.write(&path).unwrap();
+        let read = Checkpoint::read(&path).unwrap().unwrap();
+        assert_eq!(read, checkpoint);
+        let config = Config::default();
+        read.check(&bed, &output, &config).unwrap();
+        read.check_header("Region\tGene\n").unwrap();
+
+        // Performance options can change
+        let tuned = Config {
+            io_threads: Some(2),
+            ..Config::default()
+        };
+        read.check(&bed, &output, &tuned).unwrap();
+
+        // Other options or another header cannot be resumed
+        let other = Config {
+            signed_distance: true,
+            ..Config::default()
+        };
+        assert!(read.check(&bed, &output, &other).is_err());
+        assert!(read.check_header("Region\tGene\tSignedDistance\n").is_err());
+
+        // Another output or a changed BED file cannot be resumed
+        let other_output = dir.path().join("other.tsv");
+        assert!(read.check(&bed, &other_output, &config).is_err());
+        fs::write(&bed, "chr1\t100\t200\nchr1\t300\t400\n").unwrap();
+        assert!(read.check(&bed, &output, &config).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Apache Parquet output.
+//!
+//! The tab-separated rows produced by the output formatting are converted
+//! into a typed columnar file: the header line gives the schema, column
+//! types follow [`column_type`], and NA values become nulls.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use arrow_array::builder::{Float64Builder, Int64Builder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use super::{column_type, open_output, ColumnType};
+
+/// Rows buffered before they are written as a record batch.
+const BATCH_ROWS: usize = 65_536;
+
+/// Builder of a single typed column.
+enum ColumnBuilder {
+    Integer(Int64Builder),
+    Decimal(Float64Builder),
+    Text(StringBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Int64 => ColumnBuilder::Integer(Int64Builder::new()),
+            DataType::Float64 => ColumnBuilder::Decimal(Float64Builder::new()),
+            _ => ColumnBuilder::Text(StringBuilder::new()),
+        }
+    }
+
+    /// Append a value; missing fields and NA are null.
+    fn append(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+        let value = value.filter(|v| *v != "NA");
+        match self {
+            ColumnBuilder::Integer(builder) => match value {
+                Some(v) => builder.append_value(
+                    v.parse()
+                        .with_context(|| format!("Invalid integer '{}' in column {}", v, name))?,
+                ),
+                None => builder.append_null(),
+            },
+            ColumnBuilder::Decimal(builder) => match value {
+                Some(v) => builder.append_value(
+                    v.parse()
+                        .with_context(|| format!("Invalid number '{}' in column {}", v, name))?,
+                ),
+                None => builder.append_null(),
+            },
+            ColumnBuilder::Text(builder) => builder.append_option(value),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Integer(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Decimal(builder) => Arc::new(builder.finish()),
+            ColumnBuilder::Text(builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// Open Parquet file, created once the header line is known.
+struct Table {
+    writer: ArrowWriter<Box<dyn Write + Send>>,
+    schema: SchemaRef,
+    columns: Vec<ColumnBuilder>,
+    rows: usize,
+}
+
+/// Writer converting tab-separated output lines into a Parquet file.
+///
+/// The first line written must be the header. [`ParquetWriter::finish`]
+/// must be called to write the file footer.
+pub struct ParquetWriter {
+    file: Option<Box<dyn Write + Send>>,
+    column_types: Vec<(String, ColumnType)>,
+    table: Option<Table>,
+    pending: Vec<u8>,
+}
+
+impl ParquetWriter {
+    /// Create the output file (standard output for `-`); `column_types`
+    /// override the type that [`column_type`] gives to these columns.
+    pub fn create(path: &Path, column_types: &[(String, ColumnType)]) -> Result<Self> {
+        Ok(ParquetWriter {
+            file: Some(open_output(path)?),
+            column_types: column_types.to_vec(),
+            table: None,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Type of a column from its header name.
+    fn column_type(&self, name: &str) -> DataType {
+        let kind = self
+            .column_types
+            .iter()
+            .find(|(c, _)| c == name)
+            .map_or_else(|| column_type(name), |&(_, kind)| kind);
+        match kind {
+            ColumnType::Integer => DataType::Int64,
+            ColumnType::Decimal => DataType::Float64,
+            ColumnType::Text => DataType::Utf8,
+        }
+    }
+
+    /// Add a line: the header creates the table, later lines are rows.
+    fn add_line(&mut self, line: &str) -> Result<()> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let Some(table) = self.table.as_mut() else {
+            let schema: SchemaRef = Arc::new(Schema::new(
+                fields
+                    .iter()
+                    .map(|name| Field::new(*name, self.column_type(name), true))
+                    .collect::<Vec<_>>(),
+            ));
+            let columns = schema
+                .fields()
+                .iter()
+                .map(|f| ColumnBuilder::new(f.data_type()))
+                .collect();
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let file = self.file.take().expect("output file is open");
+            let writer = ArrowWriter::try_new(file, Arc::clone(&schema), Some(properties))
+                .context("Failed to create Parquet writer")?;
+            self.table = Some(Table {
+                writer,
+                schema,
+                columns,
+                rows: 0,
+            });
+            return Ok(());
+        };
+
+        if fields.len() > table.columns.len() {
+            bail!(
+                "Output row has {} columns but the header has {}",
+                fields.len(),
+                table.columns.len()
+            );
+        }
+        for (i, column) in table.columns.iter_mut().enumerate() {
+            column.append(table.schema.field(i).name(), fields.get(i).copied())?;
+        }
+        table.rows += 1;
+        if table.rows == BATCH_ROWS {
+            table.write_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining rows and the file footer.
+    pub fn finish(mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            let line = String::from_utf8(std::mem::take(&mut self.pending))?;
+            self.add_line(&line)?;
+        }
+        let Some(mut table) = self.table else {
+            bail!("No header was written to the Parquet output");
+        };
+        table.write_batch()?;
+        table
+            .writer
+            .close()
+            .context("Failed to write Parquet footer")?;
+        Ok(())
+    }
+}
+
+impl Table {
+    fn write_batch(&mut self) -> Result<()> {
+        let arrays: Vec<ArrayRef> = self.columns.iter_mut().map(|c| c.finish()).collect();
+        let batch = RecordBatch::try_new(Arc::clone(&self.schema), arrays)?;
+        self.writer.write(&batch)?;
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+impl Write for ParquetWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        // Only complete lines are converted
+        if let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') {
+            let complete: Vec<u8> = self.pending.drain(..=end).collect();
+            let text = std::str::from_utf8(&complete)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            for line in text.lines().filter(|l| !l.is_empty()) {
+                self.add_line(line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int64Type};
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::File;
+
+    #[test]
+    fn test_parquet_writer() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = ParquetWriter::create(file.path(), &[]).unwrap();
+        writeln!(writer, "Region\tDistance\tPercArea\tName").unwrap();
+        write!(writer, "chr1_100_200\t150\t50.00\tpeak1\nchr1_900").unwrap();
+        writeln!(writer, "_1000\tNA\tNA").unwrap();
+        writer.finish().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(file.path()).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<Result<_, _>>().unwrap();
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let schema = batch.schema();
+        let types: Vec<&DataType> = schema.fields().iter().map(|f| f.data_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &DataType::Utf8,
+                &DataType::Int64,
+                &DataType::Float64,
+                &DataType::Utf8
+            ]
+        );
+
+        let region = batch.column(0).as_string::<i32>();
+        assert_eq!(region.value(1), "chr1_900_1000");
+        let distance = batch.column(1).as_primitive::<Int64Type>();
+        assert_eq!(distance.value(0), 150);
+        assert!(distance.is_null(1));
+        let perc_area = batch.column(2).as_primitive::<Float64Type>();
+        assert_eq!(perc_area.value(0), 50.0);
+        // Missing metadata is null
+        assert!(batch.column(3).is_null(1));
+    }
+
+    #[test]
+    fn test_parquet_writer_text_columns() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = ParquetWriter::create(
+            file.path(),
+            &[("TSSDistance".to_string(), ColumnType::Text)],
+        )
+        .unwrap();
+        writeln!(writer, "Gene\tTSSDistance\nG1\t300,1200").unwrap();
+        writer.finish().unwrap();
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = ParquetWriter::create(file.path(), &[]).unwrap();
+        assert!(writeln!(writer, "Gene\tTSSDistance\nG1\t300,1200").is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Comparison of two output files.
+//!
+//! Rows are matched by their region and gene, in order when a region has
+//! several rows for a gene (as at the exon and transcript levels), and the
+//! other columns they share are compared. Numbers are compared as numbers,
+//! so that `0.5` and `0.50` or a last-digit rounding difference are equal.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use ahash::AHashMap;
+use anyhow::{bail, Context, Result};
+
+use crate::parser::util::create_buffered_reader;
+
+/// Rows of a tab-separated output file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl OutputTable {
+    /// Read an output file (gzip-compressed if it ends with .gz), skipping
+    /// `#` comment lines.
+    pub fn read(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut lines = create_buffered_reader(file, path)
+            .lines()
+            .filter(|line| line.as_ref().map_or(true, |line| !line.starts_with('#')));
+        let Some(header) = lines.next().transpose()? else {
+            bail!("{} is empty", path.display());
+        };
+        let columns: Vec<String> = header.split('\t').map(str::to_string).collect();
+        let mut rows = Vec::new();
+        for line in lines {
+            let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+            if line.is_empty() {
+                continue;
+            }
+            rows.push(line.split('\t').map(str::to_string).collect());
+        }
+        Ok(OutputTable { columns, rows })
+    }
+
+    /// Index of a column.
+    fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == name)
+    }
+
+    /// Value of a column of a row, empty if the row is short.
+    fn value<'a>(&self, row: &'a [String], column: usize) -> &'a str {
+        row.get(column).map_or("", String::as_str)
+    }
+}
+
+/// How a row differs between the old and new outputs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowChange {
+    /// Only in the new output.
+    Added {
+        region: String,
+        gene: String,
+        area: Option<String>,
+    },
+    /// Only in the old output.
+    Removed {
+        region: String,
+        gene: String,
+        area: Option<String>,
+    },
+    /// In both, with (column, old, new) values that differ.
+    Changed {
+        region: String,
+        gene: String,
+        columns: Vec<(String, String, String)>,
+    },
+}
+
+/// Differences between two outputs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputDiff {
+    /// Removed and changed rows in their old order, then the added rows in
+    /// their new order.
+    pub changes: Vec<RowChange>,
+    /// Rows equal in both outputs.
+    pub unchanged: usize,
+}
+
+impl OutputDiff {
+    /// Number of (added, removed, changed) rows.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for change in &self.changes {
+            match change {
+                RowChange::Added { .. } => counts.0 += 1,
+                RowChange::Removed { .. } => counts.1 += 1,
+                RowChange::Changed { .. } => counts.2 += 1,
+            }
+        }
+        counts
+    }
+
+    /// Write one line per added or removed row, with its area, and per
+    /// changed value of a row.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "Change\tRegion\tGene\tColumn\tOld\tNew")?;
+        for change in &self.changes {
+            match change {
+                RowChange::Added { region, gene, area } => {
+                    let (column, area) = area_fields(area);
+                    writeln!(
+                        writer,
+                        "ADDED\t{}\t{}\t{}\tNA\t{}",
+                        region, gene, column, area
+                    )?;
+                }
+                RowChange::Removed { region, gene, area } => {
+                    let (column, area) = area_fields(area);
+                    writeln!(
+                        writer,
+                        "REMOVED\t{}\t{}\t{}\t{}\tNA",
+                        region, gene, column, area
+                    )?;
+                }
+                RowChange::Changed {
+                    region,
+                    gene,
+                    columns,
+                } => {
+                    for (column, old, new) in columns {
+                        writeln!(
+                            writer,
+                            "CHANGED\t{}\t{}\t{}\t{}\t{}",
+                            region, gene, column, old, new
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Column and value written for the area of an added or removed row.
+fn area_fields(area: &Option<String>) -> (&str, &str) {
+    match area {
+        Some(area) => ("Area", area),
+        None => (".", "."),
+    }
+}
+
+/// Whether two values are equal, as numbers within the relative
+/// `tolerance` (absolute below 1) if both are numbers.
+pub fn values_equal(old: &str, new: &str, tolerance: f64) -> bool {
+    if old == new {
+        return true;
+    }
+    match (old.parse::<f64>(), new.parse::<f64>()) {
+        (Ok(old), Ok(new)) => (old - new).abs() <= tolerance * old.abs().max(new.abs()).max(1.0),
+        _ => false,
+    }
+}
+
+/// Compare the rows of two outputs by region and gene.
+///
+/// Both outputs need Region and Gene columns; the other columns are
+/// compared when both outputs have them.
+pub fn diff_outputs(old: &OutputTable, new: &OutputTable, tolerance: f64) -> Result<OutputDiff> {
+    let key_columns = |table: &OutputTable, name: &str| -> Result<(usize, usize)> {
+        match (table.column("Region"), table.column("Gene")) {
+            (Some(region), Some(gene)) => Ok((region, gene)),
+            _ => bail!("The {} output has no Region and Gene columns", name),
+        }
+    };
+    let (old_region, old_gene) = key_columns(old, "old")?;
+    let (new_region, new_gene) = key_columns(new, "new")?;
+    let shared: Vec<(usize, usize)> = old
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != old_region && i != old_gene)
+        .filter_map(|(i, name)| new.column(name).map(|j| (i, j)))
+        .collect();
+    let old_area = old.column("Area");
+    let new_area = new.column("Area");
+
+    // Rows of the new output by key, in order
+    let mut new_rows: AHashMap<(&str, &str), VecDeque<usize>> = AHashMap::new();
+    for (i, row) in new.rows.iter().enumerate() {
+        let key = (new.value(row, new_region), new.value(row, new_gene));
+        new_rows.entry(key).or_default().push_back(i);
+    }
+    let mut paired = vec![false; new.rows.len()];
+
+    let mut diff = OutputDiff::default();
+    for row in &old.rows {
+        let key = (old.value(row, old_region), old.value(row, old_gene));
+        let (region, gene) = (key.0.to_string(), key.1.to_string());
+        let Some(j) = new_rows.get_mut(&key).and_then(VecDeque::pop_front) else {
+            let area = old_area.map(|c| old.value(row, c).to_string());
+            diff.changes.push(RowChange::Removed { region, gene, area });
+            continue;
+        };
+        paired[j] = true;
+        let new_row = &new.rows[j];
+        let columns: Vec<(String, String, String)> = shared
+            .iter()
+            .filter_map(|&(i, k)| {
+                let (old_value, new_value) = (old.value(row, i), new.value(new_row, k));
+                (!values_equal(old_value, new_value, tolerance)).then(|| {
+                    let name = old.columns[i].clone();
+                    (name, old_value.to_string(), new_value.to_string())
+                })
+            })
+            .collect();
+        if columns.is_empty() {
+            diff.unchanged += 1;
+        } else {
+            diff.changes.push(RowChange::Changed {
+                region,
+                gene,
+                columns,
+            });
+        }
+    }
+    for (j, row) in new.rows.iter().enumerate() {
+        if !paired[j] {
+            diff.changes.push(RowChange::Added {
+                region: new.value(row, new_region).to_string(),
+                gene: new.value(row, new_gene).to_string(),
+                area: new_area.map(|c| new.value(row, c).to_string()),
+            });
+        }
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str) -> OutputTable {
+        let mut lines = text.lines().map(|line| {
+            line.split('\t')
+                .map(str::to_string)
+                .collect::<Vec<String>>()
+        });
+        OutputTable {
+            columns: lines.next().unwrap(),
+            rows: lines.collect(),
+        }
+    }
+
+    #[test]
+    fn test_values_equal() {
+        assert!(values_equal("INTRON", "INTRON", 1e-6));
+        assert!(!values_equal("INTRON", "TSS", 1e-6));
+        assert!(values_equal("0.5", "0.50", 1e-6));
+        assert!(values_equal("1e-3", "0.001", 1e-6));
+        assert!(values_equal("33.333333", "33.3333334", 1e-6));
+        assert!(!values_equal("33.33", "33.34", 1e-6));
+        assert!(values_equal("33.33", "33.34", 1e-3));
+        assert!(!values_equal("NA", "0", 1e-6));
+    }
+
+    #[test]
+    fn test_diff_outputs() {
+        let old = table(
+            "Region\tGene\tArea\tDistance\tPercArea\n\
+             r1\tG1\tINTRON\t0\t50.00\n\
+             r1\tG1\tTSS\t0\t12.50\n\
+             r2\tG2\tUPSTREAM\t1500\t100.00\n\
+             r3\tG3\tTSS\t0\t100.00",
+        );
+        let new = table(
+            "Region\tGene\tDistance\tArea\tPercArea\tAntisense\n\
+             r1\tG1\t0\tINTRON\t50.0\t0\n\
+             r1\tG1\t0\tTSS\t12.5\t0\n\
+             r2\tG2\t1501\tPROMOTER\t100.00\t0\n\
+             r4\tG4\t0\tTSS\t100.00\t1",
+        );
+        let diff = diff_outputs(&old, &new, 1e-6).unwrap();
+        assert_eq!(diff.unchanged, 2);
+        assert_eq!(diff.counts(), (1, 1, 1));
+
+        let mut text = Vec::new();
+        diff.write(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "Change\tRegion\tGene\tColumn\tOld\tNew\n\
+             CHANGED\tr2\tG2\tArea\tUPSTREAM\tPROMOTER\n\
+             CHANGED\tr2\tG2\tDistance\t1500\t1501\n\
+             REMOVED\tr3\tG3\tArea\tTSS\tNA\n\
+             ADDED\tr4\tG4\tArea\tNA\tTSS\n"
+        );
+
+        let summary = table("Gene\tRegionCount\nG1\t2");
+        assert!(diff_outputs(&summary, &new, 1e-6).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Region by gene incidence matrix.
+//!
+//! One row per annotated region and one column per associated gene, for
+//! multi-omics tools that take a feature matrix rather than a table of
+//! associations. The matrix is written either sparse, as a MatrixMarket
+//! coordinate file with the row and column names in separate files, or dense
+//! as a wide tab-separated table.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use indexmap::{IndexMap, IndexSet};
+
+use crate::types::{Area, Candidate, MatrixFormat, MatrixValue, Precision, Region};
+
+/// Region by gene matrix, filled as regions are annotated.
+///
+/// Rows and columns keep their order of first appearance. Regions without
+/// associations still get a row, so that rows match the annotated regions.
+pub struct RegionGeneMatrix {
+    dir: PathBuf,
+    format: MatrixFormat,
+    value: MatrixValue,
+    precision: Precision,
+    regions: IndexSet<String>,
+    genes: IndexSet<String>,
+    entries: IndexMap<(usize, usize), f64>,
+}
+
+impl RegionGeneMatrix {
+    /// Create an empty matrix to be written to `dir`, with overlap values
+    /// at the given precision.
+    pub fn new(dir: &Path, format: MatrixFormat, value: MatrixValue, precision: Precision) -> Self {
+        RegionGeneMatrix {
+            dir: dir.to_path_buf(),
+            format,
+            value,
+            precision,
+            regions: IndexSet::new(),
+            genes: IndexSet::new(),
+            entries: IndexMap::new(),
+        }
+    }
+
+    /// Record the associations reported for a region.
+    pub fn add(&mut self, region: &Region, candidates: &[Candidate]) {
+        let (row, _) = self.regions.insert_full(region.id());
+        for candidate in candidates {
+            if candidate.area == Area::Intergenic {
+                continue;
+            }
+            let column = match self.genes.get_index_of(&candidate.gene) {
+                Some(column) => column,
+                None => self.genes.insert_full(candidate.gene.clone()).0,
+            };
+            let value = match self.value {
+                MatrixValue::Overlap => candidate.pctg_region,
+                MatrixValue::Binary => 1.0,
+            };
+            let entry = self.entries.entry((row, column)).or_insert(value);
+            *entry = entry.max(value);
+        }
+    }
+
+    /// Format a matrix value.
+    fn format_value(&self, value: f64) -> String {
+        match self.value {
+            MatrixValue::Overlap => self.precision.format(value),
+            MatrixValue::Binary => format!("{}", value),
+        }
+    }
+
+    /// Write the matrix to its directory, creating it: `matrix.mtx`,
+    /// `regions.tsv` and `genes.tsv`, or `matrix.tsv`.
+    pub fn write(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create matrix directory {}", self.dir.display()))?;
+        match self.format {
+            MatrixFormat::Mtx => self.write_mtx(),
+            MatrixFormat::Tsv => self.write_tsv(),
+        }
+    }
+
+    fn write_mtx(&self) -> Result<()> {
+        let field = match self.value {
+            MatrixValue::Overlap => "real",
+            MatrixValue::Binary => "integer",
+        };
+        let mut matrix = BufWriter::new(File::create(self.dir.join("matrix.mtx"))?);
+        writeln!(matrix, "%%MatrixMarket matrix coordinate {} general", field)?;
+        writeln!(
+            matrix,
+            "{} {} {}",
+            self.regions.len(),
+            self.genes.len(),
+            self.entries.len()
+        )?;
+        // MatrixMarket indices start at 1
+        for (&(row, column), &value) in &self.entries {
+            writeln!(
+                matrix,
+                "{} {} {}",
+                row + 1,
+                column + 1,
+                self.format_value(value)
+            )?;
+        }
+        matrix.flush()?;
+
+        for (name, names) in [("regions.tsv", &self.regions), ("genes.tsv", &self.genes)] {
+            let mut writer = BufWriter::new(File::create(self.dir.join(name))?);
+            for name in names {
+                writeln!(writer, "{}", name)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_tsv(&self) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(self.dir.join("matrix.tsv"))?);
+        let mut header = String::from("Region");
+        for gene in &self.genes {
+            header.push('\t');
+            header.push_str(gene);
+        }
+        writeln!(writer, "{}", header)?;
+
+        let zero = self.format_value(0.0);
+        for (row, region) in self.regions.iter().enumerate() {
+            let mut line = region.clone();
+            for column in 0..self.genes.len() {
+                line.push('\t');
+                match self.entries.get(&(row, column)) {
+                    Some(&value) => line.push_str(&self.format_value(value)),
+                    None => line.push_str(&zero),
+                }
+            }
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn candidate(gene: &str, area: Area, pctg_region: f64) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            "T1".to_string(),
+            gene.to_string(),
+            0,
+            pctg_region,
+            -1.0,
+            0,
+        )
+    }
+
+    fn fill(matrix: &mut RegionGeneMatrix) {
+        let region = |start: i64| Region::new("chr1".to_string(), start, start + 100, vec![]);
+        matrix.add(
+            &region(100),
+            &[
+                candidate("G1", Area::Tss, 30.0),
+                candidate("G1", Area::Intron, 70.0),
+                candidate("G2", Area::Upstream, 100.0),
+            ],
+        );
+        matrix.add(&region(500), &[]);
+        matrix.add(&region(900), &[candidate("G2", Area::Tss, 12.5)]);
+    }
+
+    #[test]
+    fn test_matrix_market() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut matrix = RegionGeneMatrix::new(
+            dir.path(),
+            MatrixFormat::Mtx,
+            MatrixValue::Overlap,
+            Precision::default(),
+        );
+        fill(&mut matrix);
+        matrix.write().unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(
+            read("matrix.mtx"),
+            "%%MatrixMarket matrix coordinate real general\n\
+             3 2 3\n1 1 70.00\n1 2 100.00\n3 2 12.50\n"
+        );
+        assert_eq!(
+            read("regions.tsv"),
+            "chr1_100_200\nchr1_500_600\nchr1_900_1000\n"
+        );
+        assert_eq!(read("genes.tsv"), "G1\nG2\n");
+    }
+
+    #[test]
+    fn test_matrix_tsv() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut matrix = RegionGeneMatrix::new(
+            dir.path(),
+            MatrixFormat::Tsv,
+            MatrixValue::Binary,
+            Precision::default(),
+        );
+        fill(&mut matrix);
+        matrix.write().unwrap();
+
+        let table = fs::read_to_string(dir.path().join("matrix.tsv")).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Region\tG1\tG2",
+                "chr1_100_200\t1\t1",
+                "chr1_500_600\t0\t0",
+                "chr1_900_1000\t0\t1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! JSON metadata sidecar of a run.
+//!
+//! Records what produced an output: the crate version, the command line, the
+//! input files with their size and SHA-256 checksum, the parameters and the
+//! resolved options as a JSON object, and the timing of the run, so results
+//! can be audited and reproduced.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// An input file of the run.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputFile {
+    /// Role of the file (e.g. `gtf`, `bed`).
+    pub role: String,
+    pub path: String,
+    /// Size in bytes.
+    pub bytes: u64,
+    /// Hex SHA-256 of the file contents (compressed files are hashed as is).
+    pub sha256: String,
+}
+
+impl InputFile {
+    /// Describe an input file, hashing its contents.
+    pub fn from_path(role: &str, path: &Path) -> Result<Self> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open {} for hashing", path.display()))?;
+        let (bytes, sha256) = sha256(&mut file)?;
+        Ok(InputFile {
+            role: role.to_string(),
+            path: path.display().to_string(),
+            bytes,
+            sha256,
+        })
+    }
+}
+
+/// Hex SHA-256 of a reader and the number of bytes read.
+fn sha256<R: Read>(reader: &mut R) -> Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let bytes = io::copy(reader, &mut hasher)?;
+    let digest = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok((bytes, digest))
+}
+
+/// Metadata of a run, completed when the run ends.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunMetadata {
+    pub version: String,
+    /// Command line arguments, including the program.
+    pub command: Vec<String>,
+    /// Start of the run in seconds since the Unix epoch.
+    pub started: u64,
+    /// Wall time of the run in seconds.
+    pub elapsed_seconds: f64,
+    pub inputs: Vec<InputFile>,
+    pub parameters: IndexMap<String, String>,
+    /// Resolved options of the run, including the seed.
+    pub config: Option<Config>,
+    #[serde(skip)]
+    start: Instant,
+}
+
+impl RunMetadata {
+    /// Start recording a run with the current time and command line.
+    pub fn start() -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        RunMetadata {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command: std::env::args().collect(),
+            started,
+            elapsed_seconds: 0.0,
+            inputs: Vec::new(),
+            parameters: IndexMap::new(),
+            config: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// Complete the metadata with the inputs, parameters and configuration
+    /// of the run and the time elapsed since its start.
+    pub fn finish(
+        &mut self,
+        inputs: Vec<InputFile>,
+        parameters: &[(&str, String)],
+        config: &Config,
+    ) {
+        self.inputs = inputs;
+        self.parameters = parameters
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        self.config = Some(config.clone());
+        self.elapsed_seconds = self.start.elapsed().as_secs_f64();
+    }
+
+    /// Write the metadata as pretty-printed JSON.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_metadata() {
+        let (bytes, digest) = sha256(&mut "abc".as_bytes()).unwrap();
+        assert_eq!(bytes, 3);
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let gtf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/subset_genome.gtf");
+        let input = InputFile::from_path("gtf", &gtf).unwrap();
+        assert_eq!(input.bytes, std::fs::metadata(&gtf).unwrap().len());
+        assert!(InputFile::from_path("bed", Path::new("missing.bed")).is_err());
+
+        let mut metadata = RunMetadata::start();
+        metadata.finish(
+            vec![input],
+            &[("Model", "rgmatch".to_string())],
+            &Config::default(),
+        );
+        let mut json = Vec::new();
+        metadata.write(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["parameters"]["Model"], "rgmatch");
+        assert_eq!(json["inputs"][0]["sha256"].as_str().unwrap().len(), 64);
+        assert_eq!(json["config"]["distance"], 10_000);
+        assert_eq!(json["config"]["seed"], 0);
+        assert_eq!(json["config"]["sort_output"], "none");
+        assert_eq!(json["config"]["rules"][0], "TSS");
+        assert!(json.get("start").is_none());
+    }
+}
@@ -0,0 +1,346 @@
+//! Performance metrics export.
+//!
+//! Timings, throughput, channel congestion and memory high-water marks of a
+//! run, written as JSON or as a Prometheus textfile (for the node_exporter
+//! textfile collector), so pipeline monitors can track performance
+//! regressions across runs. They are collected only when enabled, as
+//! timing every stage of every work item has a cost of its own.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::types::MetricsFormat;
+
+/// Performance metrics of a run.
+///
+/// Worker times are summed over the worker threads. In the sequential mode,
+/// the single thread counts as the worker for matching and as the writer for
+/// output, formatting included in `writer_io_seconds`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunMetrics {
+    pub threads: usize,
+    /// Wall time of the run in seconds.
+    pub elapsed_seconds: f64,
+    pub regions_processed: u64,
+    pub lines_written: u64,
+    pub regions_per_second: f64,
+    pub lines_per_second: f64,
+    /// Time spent matching regions to genes.
+    pub worker_matching_seconds: f64,
+    /// Time workers waited for room in the result channel.
+    pub worker_channel_wait_seconds: f64,
+    /// Time spent formatting output lines, by the writer or by the threads
+    /// formatting them ahead of it.
+    pub writer_format_seconds: f64,
+    /// Time the writer spent writing output lines.
+    pub writer_io_seconds: f64,
+    /// Largest number of results waiting to be written in order.
+    pub max_pending_results: u64,
+    /// Capacity of the result channel (0 in the sequential mode).
+    pub result_channel_bound: u64,
+    /// Peak resident set size of the process, where the platform reports it.
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl RunMetrics {
+    /// Set the throughput from the counts and the elapsed time.
+    pub fn set_throughput(&mut self) {
+        let per_second = |count: u64| {
+            if self.elapsed_seconds > 0.0 {
+                count as f64 / self.elapsed_seconds
+            } else {
+                0.0
+            }
+        };
+        self.regions_per_second = per_second(self.regions_processed);
+        self.lines_per_second = per_second(self.lines_written);
+    }
+
+    /// Write the metrics in the given format.
+    pub fn write<W: Write>(&self, writer: &mut W, format: MetricsFormat) -> Result<()> {
+        match format {
+            MetricsFormat::Json => {
+                serde_json::to_writer_pretty(&mut *writer, self)?;
+                writeln!(writer)?;
+            }
+            MetricsFormat::Prometheus => self.write_prometheus(writer)?,
+        }
+        Ok(())
+    }
+
+    /// Write the metrics as Prometheus gauges prefixed with `rgmatch_`.
+    fn write_prometheus<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut gauges = vec![
+            ("threads", "Worker threads of the run.", self.threads as f64),
+            (
+                "elapsed_seconds",
+                "Wall time of the run in seconds.",
+                self.elapsed_seconds,
+            ),
+            (
+                "regions_processed",
+                "Regions annotated.",
+                self.regions_processed as f64,
+            ),
+            (
+                "lines_written",
+                "Output lines written.",
+                self.lines_written as f64,
+            ),
+            (
+                "regions_per_second",
+                "Regions annotated per second.",
+                self.regions_per_second,
+            ),
+            (
+                "lines_per_second",
+                "Output lines written per second.",
+                self.lines_per_second,
+            ),
+            (
+                "worker_matching_seconds",
+                "Time spent matching regions to genes, summed over workers.",
+                self.worker_matching_seconds,
+            ),
+            (
+                "worker_channel_wait_seconds",
+                "Time workers waited for room in the result channel.",
+                self.worker_channel_wait_seconds,
+            ),
+            (
+                "writer_format_seconds",
+                "Time spent formatting output lines.",
+                self.writer_format_seconds,
+            ),
+            (
+                "writer_io_seconds",
+                "Time the writer spent writing output lines.",
+                self.writer_io_seconds,
+            ),
+            (
+                "max_pending_results",
+                "Largest number of results waiting to be written in order.",
+                self.max_pending_results as f64,
+            ),
+            (
+                "result_channel_bound",
+                "Capacity of the result channel.",
+                self.result_channel_bound as f64,
+            ),
+        ];
+        if let Some(bytes) = self.peak_rss_bytes {
+            gauges.push((
+                "peak_rss_bytes",
+                "Peak resident set size of the process in bytes.",
+                bytes as f64,
+            ));
+        }
+        for (name, help, value) in gauges {
+            writeln!(writer, "# HELP rgmatch_{} {}", name, help)?;
+            writeln!(writer, "# TYPE rgmatch_{} gauge", name)?;
+            writeln!(writer, "rgmatch_{} {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Performance counters of a run, shared by its threads.
+///
+/// All times are in nanoseconds. When disabled, nothing is timed or counted
+/// and the snapshot only has the settings and the elapsed time of the run.
+#[derive(Debug, Default)]
+pub struct PerfMetrics {
+    enabled: bool,
+    /// Total time workers spend on matching (match_region_to_genes + process_candidates)
+    worker_matching_ns: AtomicU64,
+    /// Total time workers spend waiting to send results on the channel
+    worker_channel_wait_ns: AtomicU64,
+    /// Total time spent formatting output lines
+    writer_format_ns: AtomicU64,
+    /// Total time the writer spends on I/O (writeln!)
+    writer_io_ns: AtomicU64,
+    /// Number of regions processed by workers
+    regions_processed: AtomicU64,
+    /// Number of output lines written
+    lines_written: AtomicU64,
+    /// Maximum size of the pending buffer in the writer
+    max_pending_size: AtomicU64,
+}
+
+impl PerfMetrics {
+    /// Create the counters of a run, collecting nothing unless `enabled`.
+    pub fn new(enabled: bool) -> Self {
+        PerfMetrics {
+            enabled,
+            ..PerfMetrics::default()
+        }
+    }
+
+    /// Whether the counters are collected.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Start timing a stage, None when disabled.
+    pub fn start(&self) -> Option<Instant> {
+        self.enabled.then(Instant::now)
+    }
+
+    fn add_time(counter: &AtomicU64, start: Option<Instant>) {
+        if let Some(start) = start {
+            counter.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn add_count(&self, counter: &AtomicU64, count: u64) {
+        if self.enabled {
+            counter.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// Add the time since `start` to the matching time.
+    pub fn add_worker_matching(&self, start: Option<Instant>) {
+        Self::add_time(&self.worker_matching_ns, start);
+    }
+
+    /// Add the time since `start` to the time waiting on the result channel.
+    pub fn add_worker_channel_wait(&self, start: Option<Instant>) {
+        Self::add_time(&self.worker_channel_wait_ns, start);
+    }
+
+    /// Add the time since `start` to the formatting time.
+    pub fn add_writer_format(&self, start: Option<Instant>) {
+        Self::add_time(&self.writer_format_ns, start);
+    }
+
+    /// Add the time since `start` to the writing time.
+    pub fn add_writer_io(&self, start: Option<Instant>) {
+        Self::add_time(&self.writer_io_ns, start);
+    }
+
+    pub fn add_regions_processed(&self, count: u64) {
+        self.add_count(&self.regions_processed, count);
+    }
+
+    pub fn add_lines_written(&self, count: u64) {
+        self.add_count(&self.lines_written, count);
+    }
+
+    /// Record the number of results waiting to be written in order.
+    pub fn update_max_pending(&self, size: usize) {
+        if self.enabled {
+            self.max_pending_size
+                .fetch_max(size as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Snapshot of the metrics of a run of `elapsed` seconds.
+    pub fn snapshot(&self, threads: usize, elapsed: f64, channel_bound: usize) -> RunMetrics {
+        let seconds = |ns: &AtomicU64| ns.load(Ordering::Relaxed) as f64 / 1e9;
+        let mut metrics = RunMetrics {
+            threads,
+            elapsed_seconds: elapsed,
+            regions_processed: self.regions_processed.load(Ordering::Relaxed),
+            lines_written: self.lines_written.load(Ordering::Relaxed),
+            worker_matching_seconds: seconds(&self.worker_matching_ns),
+            worker_channel_wait_seconds: seconds(&self.worker_channel_wait_ns),
+            writer_format_seconds: seconds(&self.writer_format_ns),
+            writer_io_seconds: seconds(&self.writer_io_ns),
+            max_pending_results: self.max_pending_size.load(Ordering::Relaxed),
+            result_channel_bound: channel_bound as u64,
+            peak_rss_bytes: peak_rss_bytes(),
+            ..RunMetrics::default()
+        };
+        metrics.set_throughput();
+        metrics
+    }
+}
+
+/// Peak resident set size of the process in bytes (`VmHWM` on Linux), None
+/// where it is not available.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_peak_rss(&status)
+}
+
+fn parse_peak_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line["VmHWM:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_metrics() {
+        assert_eq!(
+            parse_peak_rss("Name:\trgmatch\nVmPeak:\t  2048 kB\nVmHWM:\t    1500 kB\n"),
+            Some(1_536_000)
+        );
+        assert_eq!(parse_peak_rss("Name:\trgmatch\n"), None);
+
+        let mut metrics = RunMetrics {
+            threads: 4,
+            elapsed_seconds: 2.0,
+            regions_processed: 100,
+            lines_written: 250,
+            max_pending_results: 3,
+            result_channel_bound: 2000,
+            ..RunMetrics::default()
+        };
+        metrics.set_throughput();
+        assert_eq!(metrics.regions_per_second, 50.0);
+
+        let mut json = Vec::new();
+        metrics.write(&mut json, MetricsFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["lines_per_second"], 125.0);
+        assert!(json["peak_rss_bytes"].is_null());
+
+        let mut text = Vec::new();
+        metrics.write(&mut text, MetricsFormat::Prometheus).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("# TYPE rgmatch_lines_written gauge\nrgmatch_lines_written 250\n"));
+        assert!(text.contains("rgmatch_regions_per_second 50\n"));
+        assert!(!text.contains("peak_rss_bytes"));
+    }
+
+    #[test]
+    fn test_perf_metrics() {
+        let metrics = PerfMetrics::new(true);
+        let start = metrics.start();
+        assert!(start.is_some());
+        metrics.add_worker_matching(start);
+        metrics.add_regions_processed(10);
+        metrics.add_lines_written(20);
+        metrics.update_max_pending(5);
+        metrics.update_max_pending(3);
+        let snapshot = metrics.snapshot(2, 1.0, 4);
+        assert_eq!(snapshot.regions_processed, 10);
+        assert_eq!(snapshot.lines_per_second, 20.0);
+        assert_eq!(snapshot.max_pending_results, 5);
+        assert_eq!(snapshot.result_channel_bound, 4);
+
+        // Nothing is timed or counted when disabled
+        let metrics = PerfMetrics::new(false);
+        assert!(metrics.start().is_none());
+        metrics.add_worker_matching(metrics.start());
+        metrics.add_regions_processed(10);
+        metrics.update_max_pending(5);
+        let snapshot = metrics.snapshot(2, 1.0, 4);
+        assert_eq!(snapshot.regions_processed, 0);
+        assert_eq!(snapshot.worker_matching_seconds, 0.0);
+        assert_eq!(snapshot.max_pending_results, 0);
+    }
+}
@@ -0,0 +1,151 @@
+//! Binned TSS distance profile.
+//!
+//! Counts of the regions and associations in equal bins of TSS distance
+//! around the TSS, split by area, for plotting promoter metaprofiles without
+//! a second pass over the output. Distances are those of the TSSDistance
+//! column, negative upstream of the TSS.
+
+use std::io::Write;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+
+use crate::types::{Area, Candidate};
+
+/// Region and association counts per TSS distance bin.
+pub struct TssProfile {
+    bin: i64,
+    window: i64,
+    rules: Vec<Area>,
+    /// Regions with at least one association in each bin.
+    regions: Vec<usize>,
+    /// Associations in each bin, per area.
+    areas: IndexMap<Area, Vec<usize>>,
+}
+
+impl TssProfile {
+    /// Create an empty profile of `bin` bp bins spanning `[-window, window]`;
+    /// areas are written in the order of the rules.
+    pub fn new(bin: i64, window: i64, rules: &[Area]) -> Self {
+        let bins = ((2 * window + bin - 1) / bin) as usize;
+        TssProfile {
+            bin,
+            window,
+            rules: rules.to_vec(),
+            regions: vec![0; bins],
+            areas: IndexMap::new(),
+        }
+    }
+
+    /// Bin of a TSS distance, None outside the window.
+    fn bin_of(&self, distance: i64) -> Option<usize> {
+        if distance.abs() > self.window {
+            return None;
+        }
+        let bin = ((distance + self.window) / self.bin) as usize;
+        // The window end falls in the last bin
+        Some(bin.min(self.regions.len() - 1))
+    }
+
+    /// Count the associations of a region.
+    pub fn add(&mut self, candidates: &[Candidate]) {
+        let bins = self.regions.len();
+        let mut region_bins = Vec::new();
+        for candidate in candidates {
+            if candidate.area == Area::Intergenic {
+                continue;
+            }
+            let Some(bin) = self.bin_of(candidate.tss_distance) else {
+                continue;
+            };
+            self.areas
+                .entry(candidate.area.clone())
+                .or_insert_with(|| vec![0; bins])[bin] += 1;
+            if !region_bins.contains(&bin) {
+                region_bins.push(bin);
+            }
+        }
+        for bin in region_bins {
+            self.regions[bin] += 1;
+        }
+    }
+
+    /// Write the profile: one row per bin with its start and end, the
+    /// regions and the associations of each area.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let rank = |area: &Area| {
+            self.rules
+                .iter()
+                .position(|a| a == area)
+                .unwrap_or(self.rules.len())
+        };
+        let mut areas: Vec<(&Area, &Vec<usize>)> = self.areas.iter().collect();
+        areas.sort_by_key(|(area, _)| rank(area));
+
+        let mut header = String::from("BinStart\tBinEnd\tRegions");
+        for (area, _) in &areas {
+            header.push('\t');
+            header.push_str(area.as_str());
+        }
+        writeln!(writer, "{}", header)?;
+
+        for (i, regions) in self.regions.iter().enumerate() {
+            let start = -self.window + i as i64 * self.bin;
+            let end = (start + self.bin).min(self.window);
+            let mut line = format!("{}\t{}\t{}", start, end, regions);
+            for (_, counts) in &areas {
+                line.push_str(&format!("\t{}", counts[i]));
+            }
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn candidate(area: Area, tss_distance: i64) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            50.0,
+            tss_distance,
+        )
+    }
+
+    #[test]
+    fn test_tss_profile() {
+        let mut profile = TssProfile::new(100, 250, &[Area::Tss, Area::Intron]);
+        profile.add(&[
+            candidate(Area::Intron, 120),
+            candidate(Area::Tss, -250),
+            candidate(Area::Tss, 250),
+            candidate(Area::Tss, 180),
+            candidate(Area::Upstream, 251),
+        ]);
+        profile.add(&[candidate(Area::Intergenic, 0)]);
+
+        let mut text = Vec::new();
+        profile.write(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(
+            text,
+            "BinStart\tBinEnd\tRegions\tTSS\tINTRON\n\
+             -250\t-150\t1\t1\t0\n\
+             -150\t-50\t0\t0\t0\n\
+             -50\t50\t0\t0\t0\n\
+             50\t150\t1\t0\t1\n\
+             150\t250\t1\t2\t0\n"
+        );
+    }
+}
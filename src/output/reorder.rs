@@ -0,0 +1,263 @@
+//! Reordering of the results of parallel workers.
+//!
+//! Workers complete their work items out of order while the output is
+//! written in input order, so results that arrive before their turn wait in
+//! a buffer. With a memory limit, the results that do not fit are spilled
+//! to a temporary file as JSON and read back when their turn comes, so that
+//! a slow work item or output cannot make the buffer grow without bound.
+//! When the order does not matter, the buffer hands the results out as they
+//! arrive instead.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Number of spill files created by the process, naming their files.
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary file of spilled results, removed when dropped.
+struct SpillFile {
+    path: PathBuf,
+    file: File,
+    /// End of the spilled data.
+    end: u64,
+}
+
+impl SpillFile {
+    fn create() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "rgmatch-spill-{}-{}.tmp",
+            std::process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create spill file {}", path.display()))?;
+        Ok(SpillFile { path, file, end: 0 })
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A result waiting for its turn.
+enum Pending<T> {
+    /// Kept in memory, with its size in bytes.
+    Memory(T, u64),
+    /// Spilled to the spill file at `offset`.
+    Spilled { offset: u64, len: usize },
+}
+
+/// Results numbered from 0, handed out in order of their numbers.
+pub struct ReorderBuffer<T> {
+    /// Results from the next one on, indexed by their distance to it.
+    pending: VecDeque<Option<Pending<T>>>,
+    next: u64,
+    max_bytes: Option<u64>,
+    /// Bytes of the results kept in memory.
+    bytes: u64,
+    spill: Option<SpillFile>,
+    /// Results in the spill file.
+    spilled: usize,
+    spilled_total: u64,
+    /// Hand the results out in order of their numbers, not of their arrival.
+    ordered: bool,
+}
+
+impl<T: Serialize + DeserializeOwned> ReorderBuffer<T> {
+    /// Create an empty buffer, keeping up to `max_bytes` of results in
+    /// memory if limited.
+    pub fn new(max_bytes: Option<u64>) -> Self {
+        ReorderBuffer {
+            pending: VecDeque::new(),
+            next: 0,
+            max_bytes,
+            bytes: 0,
+            spill: None,
+            spilled: 0,
+            spilled_total: 0,
+            ordered: true,
+        }
+    }
+
+    /// Create an empty buffer handing the results out as they arrive.
+    pub fn unordered() -> Self {
+        ReorderBuffer {
+            ordered: false,
+            ..ReorderBuffer::new(None)
+        }
+    }
+
+    /// Add result number `seq`, of about `bytes` bytes in memory.
+    ///
+    /// The next result in order is always kept, since it is handed out at
+    /// once; the others are spilled if they would exceed the limit.
+    pub fn insert(&mut self, seq: u64, result: T, bytes: u64) -> Result<()> {
+        if !self.ordered {
+            self.bytes += bytes;
+            self.pending.push_back(Some(Pending::Memory(result, bytes)));
+            return Ok(());
+        }
+        let index = (seq - self.next) as usize;
+        while self.pending.len() <= index {
+            self.pending.push_back(None);
+        }
+        let fits = index == 0
+            || self
+                .max_bytes
+                .map_or(true, |max_bytes| self.bytes + bytes <= max_bytes);
+        let pending = if fits {
+            self.bytes += bytes;
+            Pending::Memory(result, bytes)
+        } else {
+            self.spill(&result)?
+        };
+        self.pending[index] = Some(pending);
+        Ok(())
+    }
+
+    /// Take the next result in order, if it was added.
+    pub fn pop(&mut self) -> Result<Option<T>> {
+        let Some(pending) = self.pending.front_mut().and_then(Option::take) else {
+            return Ok(None);
+        };
+        self.pending.pop_front();
+        self.next += 1;
+        let result = match pending {
+            Pending::Memory(result, bytes) => {
+                self.bytes -= bytes;
+                result
+            }
+            Pending::Spilled { offset, len } => self.read_back(offset, len)?,
+        };
+        Ok(Some(result))
+    }
+
+    /// Number of results from the next one to the last added, waiting or not.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Number of results spilled to disk so far.
+    pub fn spilled_total(&self) -> u64 {
+        self.spilled_total
+    }
+
+    /// Write a result to the end of the spill file.
+    fn spill(&mut self, result: &T) -> Result<Pending<T>> {
+        let spill = match self.spill.as_mut() {
+            Some(spill) => spill,
+            None => self.spill.insert(SpillFile::create()?),
+        };
+        let data = serde_json::to_vec(result)?;
+        let offset = spill.end;
+        spill.file.seek(SeekFrom::Start(offset))?;
+        spill
+            .file
+            .write_all(&data)
+            .context("Failed to write spill file")?;
+        spill.end += data.len() as u64;
+        self.spilled += 1;
+        self.spilled_total += 1;
+        Ok(Pending::Spilled {
+            offset,
+            len: data.len(),
+        })
+    }
+
+    /// Read a spilled result back, emptying the spill file once none is left.
+    fn read_back(&mut self, offset: u64, len: usize) -> Result<T> {
+        let spill = self
+            .spill
+            .as_mut()
+            .context("Spilled result without a spill file")?;
+        let mut data = vec![0; len];
+        spill.file.seek(SeekFrom::Start(offset))?;
+        spill
+            .file
+            .read_exact(&mut data)
+            .context("Failed to read spill file")?;
+        self.spilled -= 1;
+        if self.spilled == 0 {
+            spill.file.set_len(0)?;
+            spill.end = 0;
+        }
+        let result = serde_json::from_slice(&data).context("Corrupt spill file")?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(buffer: &mut ReorderBuffer<Vec<String>>) -> Vec<Vec<String>> {
+        let mut results = Vec::new();
+        while let Some(result) = buffer.pop().unwrap() {
+            results.push(result);
+        }
+        results
+    }
+
+    #[test]
+    fn test_reorder_buffer() {
+        for max_bytes in [None, Some(100), Some(0)] {
+            let mut buffer = ReorderBuffer::new(max_bytes);
+            let result = |seq: u64| vec![format!("result {}", seq); 3];
+            buffer.insert(2, result(2), 60).unwrap();
+            buffer.insert(1, result(1), 60).unwrap();
+            assert!(drain(&mut buffer).is_empty());
+            assert_eq!(buffer.pending_len(), 3);
+
+            buffer.insert(0, result(0), 60).unwrap();
+            assert_eq!(drain(&mut buffer), vec![result(0), result(1), result(2)]);
+            buffer.insert(4, result(4), 60).unwrap();
+            buffer.insert(3, result(3), 60).unwrap();
+            assert_eq!(drain(&mut buffer), vec![result(3), result(4)]);
+            assert_eq!(buffer.pending_len(), 0);
+
+            let spilled = match max_bytes {
+                None => 0,
+                Some(100) => 1,
+                _ => 3,
+            };
+            assert_eq!(buffer.spilled_total(), spilled);
+        }
+    }
+
+    #[test]
+    fn test_unordered_buffer() {
+        let mut buffer = ReorderBuffer::unordered();
+        buffer.insert(2, vec!["result 2".to_string()], 60).unwrap();
+        buffer.insert(0, vec!["result 0".to_string()], 60).unwrap();
+        assert_eq!(buffer.pending_len(), 2);
+        assert_eq!(
+            drain(&mut buffer),
+            vec![vec!["result 2".to_string()], vec!["result 0".to_string()]]
+        );
+        assert_eq!(buffer.spilled_total(), 0);
+    }
+
+    #[test]
+    fn test_reorder_buffer_removes_spill_file() {
+        let mut buffer = ReorderBuffer::new(Some(0));
+        buffer.insert(1, vec![1.5_f64], 8).unwrap();
+        let path = buffer.spill.as_ref().unwrap().path.clone();
+        assert!(path.exists());
+        drop(buffer);
+        assert!(!path.exists());
+    }
+}
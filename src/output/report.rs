@@ -0,0 +1,282 @@
+//! Self-contained HTML report of a run.
+//!
+//! A single static page, with inline SVG charts and no scripts or external
+//! resources: the distribution of the regions over the areas as a pie chart,
+//! a histogram of the TSS distances of the associations, the run statistics
+//! and the run parameters.
+
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+use std::io::Write;
+
+use anyhow::Result;
+
+use super::stats::RunSummary;
+
+/// Colors of the pie slices, reused in order.
+const COLORS: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+
+/// Number of bars of the TSS distance histogram.
+const HISTOGRAM_BINS: usize = 40;
+
+/// Escape text for HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Smallest 1, 2 or 5 times a power of ten at least `value`.
+fn nice_ceiling(value: i64) -> i64 {
+    let mut scale = 1;
+    loop {
+        for step in [1, 2, 5] {
+            if step * scale >= value {
+                return step * scale;
+            }
+        }
+        scale *= 10;
+    }
+}
+
+/// Counts of `values` in equal bins spanning `[-limit, limit]`.
+///
+/// The limit is the largest absolute value rounded up to a round number.
+pub fn histogram(values: &[i64], bins: usize) -> (i64, Vec<usize>) {
+    let limit = nice_ceiling(values.iter().map(|v| v.abs()).max().unwrap_or(0).max(1));
+    let mut counts = vec![0; bins];
+    for &value in values {
+        let bin = ((value + limit) as f64 / (2 * limit) as f64 * bins as f64) as usize;
+        counts[bin.min(bins - 1)] += 1;
+    }
+    (limit, counts)
+}
+
+/// SVG pie chart of the regions per area, with a legend.
+fn pie_chart(summary: &RunSummary) -> String {
+    let mut slices: Vec<(&str, usize)> = summary
+        .sorted_areas()
+        .into_iter()
+        .filter(|(_, tally)| tally.regions > 0)
+        .map(|(area, tally)| (area.as_str(), tally.regions))
+        .collect();
+    if summary.unmatched > 0 {
+        slices.push(("Unmatched", summary.unmatched));
+    }
+    let total: usize = slices.iter().map(|(_, n)| n).sum();
+
+    let height = (slices.len() * 22 + 20).max(220);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"480\" height=\"{}\" role=\"img\">\n",
+        height
+    );
+    let (cx, cy, r) = (110.0, 110.0, 100.0);
+    let mut angle = -PI / 2.0;
+    for (i, (label, count)) in slices.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let fraction = *count as f64 / total as f64;
+        if slices.len() == 1 {
+            let _ = writeln!(
+                svg,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                cx, cy, r, color
+            );
+        } else {
+            let end = angle + fraction * 2.0 * PI;
+            let large_arc = u8::from(fraction > 0.5);
+            let _ = writeln!(
+                svg,
+                "<path d=\"M{cx},{cy} L{:.2},{:.2} A{r},{r} 0 {large_arc} 1 {:.2},{:.2} Z\" fill=\"{color}\"/>",
+                cx + r * angle.cos(),
+                cy + r * angle.sin(),
+                cx + r * end.cos(),
+                cy + r * end.sin(),
+            );
+            angle = end;
+        }
+        let y = 20 + i * 22;
+        let _ = writeln!(
+            svg,
+            "<rect x=\"240\" y=\"{}\" width=\"14\" height=\"14\" fill=\"{}\"/>\
+             <text x=\"262\" y=\"{}\">{} ({}, {:.1}%)</text>",
+            y,
+            color,
+            y + 12,
+            escape(label),
+            count,
+            fraction * 100.0
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// SVG histogram of the TSS distances.
+fn distance_histogram(tss_distances: &[i64]) -> String {
+    let (limit, counts) = histogram(tss_distances, HISTOGRAM_BINS);
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let (width, height, left, bottom) = (640.0, 240.0, 50.0, 30.0);
+    let bar = (width - left) / HISTOGRAM_BINS as f64;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" role=\"img\">\n",
+        width,
+        height + bottom
+    );
+    for (i, &count) in counts.iter().enumerate() {
+        let h = count as f64 / max as f64 * (height - 10.0);
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"#4e79a7\"><title>{}</title></rect>",
+            left + i as f64 * bar,
+            height - h,
+            bar - 1.0,
+            h,
+            count
+        );
+    }
+    let _ = writeln!(
+        svg,
+        "<line x1=\"{left}\" y1=\"{height}\" x2=\"{width}\" y2=\"{height}\" stroke=\"#333\"/>\
+         <text x=\"{left}\" y=\"{}\">-{limit}</text>\
+         <text x=\"{:.2}\" y=\"{}\" text-anchor=\"middle\">0</text>\
+         <text x=\"{width}\" y=\"{}\" text-anchor=\"end\">{limit}</text>\
+         <text x=\"0\" y=\"14\">{max}</text>",
+        height + 20.0,
+        left + (width - left) / 2.0,
+        height + 20.0,
+        height + 20.0,
+    );
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Write the HTML report of a run; `parameters` are shown as given.
+pub fn write_html_report<W: Write>(
+    writer: &mut W,
+    summary: &RunSummary,
+    parameters: &[(&str, String)],
+) -> Result<()> {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>rgmatch report</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; color: #222; }\n\
+         table { border-collapse: collapse; margin-bottom: 1em; }\n\
+         td, th { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }\n\
+         td.n { text-align: right; }\n\
+         svg text { font-size: 12px; }\n\
+         </style>\n</head>\n<body>\n<h1>rgmatch report</h1>\n",
+    );
+
+    html.push_str("<h2>Regions</h2>\n<table>\n");
+    let matched = summary.regions - summary.unmatched;
+    for (label, value) in [
+        ("Regions", summary.regions),
+        ("Matched regions", matched),
+        ("Unmatched regions", summary.unmatched),
+        ("Associations", summary.associations()),
+    ] {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td class=\"n\">{}</td></tr>",
+            label, value
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Areas</h2>\n<p>Regions by their highest priority area.</p>\n");
+    html.push_str(&pie_chart(summary));
+    html.push_str("\n<table>\n<tr><th>Area</th><th>Regions</th><th>Associations</th></tr>\n");
+    for (area, tally) in summary.sorted_areas() {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+            area.as_str(),
+            tally.regions,
+            tally.associations
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>TSS distance</h2>\n<p>Distance from the region to the TSS of the associated transcripts (bp).</p>\n");
+    html.push_str(&distance_histogram(&summary.tss_distances));
+    if let Some(q) = summary.distance_quartiles() {
+        let _ = writeln!(
+            html,
+            "\n<p>Distance: minimum {}, quartiles {} / {} / {}, maximum {}.</p>",
+            q[0], q[1], q[2], q[3], q[4]
+        );
+    }
+
+    html.push_str("\n<h2>Parameters</h2>\n<table>\n");
+    for (name, value) in parameters {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(name),
+            escape(value)
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+
+    writer.write_all(html.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::types::{Area, Candidate, Region, Strand};
+
+    #[test]
+    fn test_histogram() {
+        assert_eq!(nice_ceiling(1), 1);
+        assert_eq!(nice_ceiling(3), 5);
+        assert_eq!(nice_ceiling(1500), 2000);
+
+        let (limit, counts) = histogram(&[-1000, -1, 0, 999, 1000], 4);
+        assert_eq!(limit, 1000);
+        assert_eq!(counts, vec![1, 1, 1, 2]);
+        assert_eq!(histogram(&[], 4), (1, vec![0; 4]));
+    }
+
+    #[test]
+    fn test_html_report() {
+        let mut summary = RunSummary::from_config(&Config::default());
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let mut candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            50.0,
+            -150,
+        );
+        summary.add(&region, &[candidate.clone()]);
+        candidate.area = Area::Intron;
+        summary.add(&region, &[candidate]);
+        summary.add(&region, &[]);
+
+        let mut html = Vec::new();
+        let parameters = [("BED", "peaks<1>.bed".to_string())];
+        write_html_report(&mut html, &summary, &parameters).unwrap();
+        let html = String::from_utf8(html).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        // One slice per area and one for the unmatched regions
+        assert_eq!(html.matches("<path ").count(), 3);
+        assert!(html.contains("Unmatched (1, 33.3%)"));
+        assert!(html.contains("<td>peaks&lt;1&gt;.bed</td>"));
+        assert!(!html.contains("<script") && !html.contains("src="));
+    }
+}
@@ -0,0 +1,510 @@
+//! Sorting of the per-association output.
+//!
+//! Rows are buffered with a key taken from their region and candidate and
+//! sorted in memory. Once the buffer is full they are spilled to a temporary
+//! file as a sorted run, and the runs are merged when the output is
+//! completed, so outputs larger than memory can be sorted. Rows with equal
+//! keys keep their order. Rows grouped by gene can also be ranked within
+//! their gene as they are written, heavier regions first at equal distance.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use anyhow::{bail, Context, Result};
+
+use crate::types::{Area, Candidate, Region, SortOrder};
+
+/// Bytes of rows buffered before a sorted run is spilled to disk.
+const SORT_BUFFER_BYTES: usize = 256 << 20;
+
+/// Sort key of an output row.
+#[derive(Debug, Clone, PartialEq)]
+struct RowKey {
+    /// Chromosome of the region, or gene (empty for unmatched regions).
+    name: String,
+    /// Start of the region, or distance to the gene.
+    start: i64,
+    end: i64,
+    /// Weight of the region, heavier first, when grouped by gene and distance.
+    weight: f64,
+    /// Position of the row in the unsorted output.
+    seq: u64,
+}
+
+/// Rank of a chromosome in karyotype order: numbered chromosomes by number,
+/// then X, Y and the mitochondrial genome, then the other contigs by name.
+fn chrom_rank(chrom: &str) -> (u8, u64, &str) {
+    let name = chrom
+        .strip_prefix("chr")
+        .or_else(|| chrom.strip_prefix("Chr"))
+        .unwrap_or(chrom);
+    if let Ok(number) = name.parse::<u64>() {
+        return (0, number, "");
+    }
+    match name {
+        "X" => (1, 0, ""),
+        "Y" => (2, 0, ""),
+        "M" | "MT" => (3, 0, ""),
+        _ => (4, 0, chrom),
+    }
+}
+
+/// Compare chromosomes in karyotype order.
+pub fn compare_chroms(a: &str, b: &str) -> Ordering {
+    chrom_rank(a).cmp(&chrom_rank(b)).then_with(|| a.cmp(b))
+}
+
+impl RowKey {
+    fn new(
+        order: SortOrder,
+        region: &Region,
+        candidate: &Candidate,
+        weight_column: Option<usize>,
+        seq: u64,
+    ) -> Self {
+        match order {
+            SortOrder::Gene | SortOrder::GeneDistance if candidate.area == Area::Intergenic => {
+                RowKey {
+                    name: String::new(),
+                    start: 0,
+                    end: 0,
+                    weight: 0.0,
+                    seq,
+                }
+            }
+            SortOrder::Gene => RowKey {
+                name: candidate.gene.clone(),
+                start: 0,
+                end: 0,
+                weight: 0.0,
+                seq,
+            },
+            SortOrder::GeneDistance => RowKey {
+                name: candidate.gene.clone(),
+                start: candidate.distance,
+                end: 0,
+                weight: weight_column.map_or(0.0, |column| region.weight(column)),
+                seq,
+            },
+            _ => RowKey {
+                name: region.chrom.clone(),
+                start: region.start,
+                end: region.end,
+                weight: 0.0,
+                seq,
+            },
+        }
+    }
+
+    fn compare(&self, other: &Self, order: SortOrder) -> Ordering {
+        let by_name = match order {
+            // Unmatched regions go last
+            SortOrder::Gene | SortOrder::GeneDistance => {
+                (self.name.is_empty(), &self.name).cmp(&(other.name.is_empty(), &other.name))
+            }
+            _ => compare_chroms(&self.name, &other.name),
+        };
+        by_name
+            .then(self.start.cmp(&other.start))
+            .then(self.end.cmp(&other.end))
+            .then(other.weight.total_cmp(&self.weight))
+            .then(self.seq.cmp(&other.seq))
+    }
+
+    /// Spilled form of the key, followed by the row.
+    fn write_with<W: Write>(&self, writer: &mut W, line: &str) -> Result<()> {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.name, self.start, self.end, self.weight, self.seq, line
+        )?;
+        Ok(())
+    }
+
+    /// Read back a spilled key and its row.
+    fn parse(spilled: &str) -> Result<(Self, String)> {
+        let fields: Vec<&str> = spilled.splitn(6, '\t').collect();
+        let [name, start, end, weight, seq, line] = fields[..] else {
+            bail!("Corrupt sort run line: {}", spilled);
+        };
+        let key = RowKey {
+            name: name.to_string(),
+            start: start.parse()?,
+            end: end.parse()?,
+            weight: weight.parse()?,
+            seq: seq.parse()?,
+        };
+        Ok((key, line.to_string()))
+    }
+}
+
+/// Number of sort runs created by the process, naming their files.
+static RUN_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorted run spilled to a temporary file, removed when dropped.
+struct SortRun {
+    path: PathBuf,
+}
+
+impl Drop for SortRun {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Next row of a run during the merge.
+struct MergeEntry {
+    key: RowKey,
+    line: String,
+    run: usize,
+    order: SortOrder,
+}
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeEntry {}
+
+impl PartialOrd for MergeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MergeEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.compare(&other.key, self.order)
+    }
+}
+
+/// Rank of the rows within their gene, written to one of their fields.
+struct GeneRank {
+    field: usize,
+    gene: String,
+    rank: usize,
+}
+
+impl GeneRank {
+    /// Write the rank of the next row, in output order, to its field.
+    fn fill(&mut self, key: &RowKey, line: &str) -> String {
+        if key.name.is_empty() {
+            return line.to_string();
+        }
+        if key.name == self.gene {
+            self.rank += 1;
+        } else {
+            self.gene.clone_from(&key.name);
+            self.rank = 1;
+        }
+        let rank = self.rank.to_string();
+        let fields: Vec<&str> = line
+            .split('\t')
+            .enumerate()
+            .map(|(i, field)| {
+                if i == self.field {
+                    rank.as_str()
+                } else {
+                    field
+                }
+            })
+            .collect();
+        fields.join("\t")
+    }
+}
+
+/// Rows of the output, sorted once all are added.
+pub struct LineSorter {
+    order: SortOrder,
+    rank: Option<GeneRank>,
+    weight_column: Option<usize>,
+    buffer_bytes: usize,
+    rows: Vec<(RowKey, String)>,
+    bytes: usize,
+    runs: Vec<SortRun>,
+    seq: u64,
+}
+
+impl LineSorter {
+    /// Create an empty sorter in the given order.
+    pub fn new(order: SortOrder) -> Self {
+        LineSorter::with_buffer(order, SORT_BUFFER_BYTES)
+    }
+
+    /// Create an empty sorter spilling runs beyond `buffer_bytes` of rows.
+    pub fn with_buffer(order: SortOrder, buffer_bytes: usize) -> Self {
+        LineSorter {
+            order,
+            rank: None,
+            weight_column: None,
+            buffer_bytes,
+            rows: Vec::new(),
+            bytes: 0,
+            runs: Vec::new(),
+            seq: 0,
+        }
+    }
+
+    /// Write the rank of every row within its gene to the given field, for
+    /// rows grouped by gene.
+    pub fn with_gene_rank(mut self, field: usize) -> Self {
+        self.rank = Some(GeneRank {
+            field,
+            gene: String::new(),
+            rank: 0,
+        });
+        self
+    }
+
+    /// Rank the rows of a gene at equal distance by the region weight in
+    /// the given BED column, heaviest first.
+    pub fn with_weight_column(mut self, column: Option<usize>) -> Self {
+        self.weight_column = column;
+        self
+    }
+
+    /// Write a row, with its rank if ranked.
+    fn emit<W: Write>(&mut self, writer: &mut W, key: &RowKey, line: &str) -> Result<()> {
+        match self.rank.as_mut() {
+            Some(rank) => writeln!(writer, "{}", rank.fill(key, line))?,
+            None => writeln!(writer, "{}", line)?,
+        }
+        Ok(())
+    }
+
+    /// Add the output line of a region-candidate pair.
+    pub fn add(&mut self, region: &Region, candidate: &Candidate, line: String) -> Result<()> {
+        let key = RowKey::new(self.order, region, candidate, self.weight_column, self.seq);
+        self.seq += 1;
+        self.bytes += line.len() + key.name.len() + std::mem::size_of::<(RowKey, String)>();
+        self.rows.push((key, line));
+        if self.bytes >= self.buffer_bytes {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sort the buffered rows.
+    fn sort_rows(&mut self) {
+        let order = self.order;
+        // Keys are unique through their sequence number
+        self.rows.sort_unstable_by(|a, b| a.0.compare(&b.0, order));
+    }
+
+    /// Write the buffered rows to a new sorted run.
+    fn spill(&mut self) -> Result<()> {
+        self.sort_rows();
+        let path = std::env::temp_dir().join(format!(
+            "rgmatch-sort-{}-{}.tmp",
+            std::process::id(),
+            RUN_COUNT.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+        let run = SortRun { path };
+        let file = File::create(&run.path)
+            .with_context(|| format!("Failed to create sort run file {}", run.path.display()))?;
+        let mut writer = BufWriter::new(file);
+        for (key, line) in self.rows.drain(..) {
+            key.write_with(&mut writer, &line)?;
+        }
+        writer.flush()?;
+        self.runs.push(run);
+        self.bytes = 0;
+        Ok(())
+    }
+
+    /// Write all rows in order.
+    pub fn finish<W: Write>(mut self, writer: &mut W) -> Result<()> {
+        self.sort_rows();
+        if self.runs.is_empty() {
+            for (key, line) in std::mem::take(&mut self.rows) {
+                self.emit(writer, &key, &line)?;
+            }
+            return Ok(());
+        }
+        if !self.rows.is_empty() {
+            self.spill()?;
+        }
+
+        let mut readers: Vec<Lines<BufReader<File>>> = self
+            .runs
+            .iter()
+            .map(|run| Ok(BufReader::new(File::open(&run.path)?).lines()))
+            .collect::<Result<_>>()?;
+        let mut heap = BinaryHeap::new();
+        let order = self.order;
+        let next = |run: usize, readers: &mut Vec<Lines<BufReader<File>>>| -> Result<_> {
+            match readers[run].next() {
+                Some(spilled) => {
+                    let (key, line) = RowKey::parse(&spilled?)?;
+                    Ok(Some(Reverse(MergeEntry {
+                        key,
+                        line,
+                        run,
+                        order,
+                    })))
+                }
+                None => Ok(None),
+            }
+        };
+        for run in 0..readers.len() {
+            if let Some(entry) = next(run, &mut readers)? {
+                heap.push(entry);
+            }
+        }
+        while let Some(Reverse(entry)) = heap.pop() {
+            self.emit(writer, &entry.key, &entry.line)?;
+            if let Some(next_entry) = next(entry.run, &mut readers)? {
+                heap.push(next_entry);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn candidate(gene: &str, area: Area) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            "T1".to_string(),
+            gene.to_string(),
+            0,
+            100.0,
+            50.0,
+            0,
+        )
+    }
+
+    fn rows() -> Vec<(Region, Candidate)> {
+        let region =
+            |chrom: &str, start: i64| Region::new(chrom.to_string(), start, start + 100, vec![]);
+        vec![
+            (region("chr10", 500), candidate("G3", Area::Intron)),
+            (region("chrX", 100), candidate("G1", Area::Tss)),
+            (region("chr2", 900), candidate("NA", Area::Intergenic)),
+            (region("chr2", 100), candidate("G1", Area::Intron)),
+            (
+                region("chr1_KI270706v1_random", 100),
+                candidate("G2", Area::Tss),
+            ),
+            (region("chr2", 100), candidate("G2", Area::Upstream)),
+            (region("chrM", 100), candidate("G3", Area::Tss)),
+        ]
+    }
+
+    fn sorted(order: SortOrder, buffer_bytes: usize) -> Vec<String> {
+        let mut sorter = LineSorter::with_buffer(order, buffer_bytes);
+        for (i, (region, candidate)) in rows().iter().enumerate() {
+            let line = format!("{}\t{}\t{}", i, region.id(), candidate.gene);
+            sorter.add(region, candidate, line).unwrap();
+        }
+        let mut output = Vec::new();
+        sorter.finish(&mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_chroms() {
+        let mut chroms = vec!["chrY", "chr10", "chrUn_x", "chrM", "chr2", "chrX", "chr1"];
+        chroms.sort_by(|a, b| compare_chroms(a, b));
+        assert_eq!(
+            chroms,
+            vec!["chr1", "chr2", "chr10", "chrX", "chrY", "chrM", "chrUn_x"]
+        );
+        assert_eq!(compare_chroms("2", "10"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_line_sorter() {
+        let coord = vec!["3", "5", "2", "0", "1", "6", "4"];
+        let gene = vec!["1", "3", "4", "5", "0", "6", "2"];
+        assert_eq!(sorted(SortOrder::Coord, SORT_BUFFER_BYTES), coord);
+        assert_eq!(sorted(SortOrder::Gene, SORT_BUFFER_BYTES), gene);
+        // Spilling every row gives the same order
+        assert_eq!(sorted(SortOrder::Coord, 1), coord);
+        assert_eq!(sorted(SortOrder::Gene, 1), gene);
+    }
+
+    #[test]
+    fn test_gene_rank() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let rows = [
+            ("G2", 500),
+            ("G1", 300),
+            ("NA", 0),
+            ("G2", 100),
+            ("G1", 300),
+        ];
+        for buffer_bytes in [SORT_BUFFER_BYTES, 1] {
+            let mut sorter =
+                LineSorter::with_buffer(SortOrder::GeneDistance, buffer_bytes).with_gene_rank(1);
+            for (i, &(gene, distance)) in rows.iter().enumerate() {
+                let area = if gene == "NA" {
+                    Area::Intergenic
+                } else {
+                    Area::Tss
+                };
+                let mut candidate = candidate(gene, area);
+                candidate.distance = distance;
+                sorter
+                    .add(&region, &candidate, format!("{}\tNA\t{}", gene, i))
+                    .unwrap();
+            }
+            let mut output = Vec::new();
+            sorter.finish(&mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "G1\t1\t1\nG1\t2\t4\nG2\t1\t3\nG2\t2\t0\nNA\tNA\t2\n"
+            );
+        }
+    }
+
+    #[test]
+    fn test_gene_rank_weights() {
+        let region = |score: &str| {
+            Region::new(
+                "chr1".to_string(),
+                100,
+                200,
+                vec!["peak".to_string(), score.to_string()],
+            )
+        };
+        let rows = [("G1", 300, "5"), ("G1", 300, "20"), ("G1", 100, "1")];
+        for buffer_bytes in [SORT_BUFFER_BYTES, 1] {
+            let mut sorter = LineSorter::with_buffer(SortOrder::GeneDistance, buffer_bytes)
+                .with_gene_rank(1)
+                .with_weight_column(Some(5));
+            for (i, &(gene, distance, score)) in rows.iter().enumerate() {
+                let mut candidate = candidate(gene, Area::Tss);
+                candidate.distance = distance;
+                sorter
+                    .add(&region(score), &candidate, format!("{}\tNA\t{}", gene, i))
+                    .unwrap();
+            }
+            let mut output = Vec::new();
+            sorter.finish(&mut output).unwrap();
+            // Equal distances rank the heavier region first
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "G1\t1\t2\nG1\t2\t1\nG1\t3\t0\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! SQLite output.
+//!
+//! The tab-separated rows produced by the output formatting are inserted
+//! into a new database, with column types following [`column_type`] and NA
+//! values stored as NULL. Associations go to an `associations` table indexed
+//! on gene and region, summarized by a `regions` and a `genes` table; the
+//! other outputs (gene summary, loops, ...) go to a `results` table.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+
+use super::{column_type, ColumnType};
+use crate::parser::bed::get_bed_headers;
+
+/// Table being filled, created once the header line is known.
+struct Table {
+    name: &'static str,
+    columns: Vec<(String, ColumnType)>,
+    insert: String,
+}
+
+/// Writer inserting tab-separated output lines into a SQLite database.
+///
+/// The first line written must be the header. [`SqliteWriter::finish`]
+/// must be called to commit the rows and build the summary tables.
+pub struct SqliteWriter {
+    conn: Connection,
+    column_types: Vec<(String, ColumnType)>,
+    table: Option<Table>,
+    pending: Vec<u8>,
+}
+
+/// Quote a column or table name as an SQL identifier.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+impl SqliteWriter {
+    /// Create the database, replacing any existing file; `column_types`
+    /// override the type that [`column_type`] gives to these columns.
+    pub fn create(path: &Path, column_types: &[(String, ColumnType)]) -> Result<Self> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).context("Failed to replace output file")
+            }
+            _ => {}
+        }
+        let conn = Connection::open(path).context("Failed to create output file")?;
+        // All rows are inserted in a single transaction
+        conn.execute_batch("BEGIN")?;
+        Ok(SqliteWriter {
+            conn,
+            column_types: column_types.to_vec(),
+            table: None,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Add a line: the header creates the table, later lines are rows.
+    fn add_line(&mut self, line: &str) -> Result<()> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let Some(table) = &self.table else {
+            let name = if ["Region", "Midpoint", "Gene"]
+                .iter()
+                .all(|column| fields.contains(column))
+            {
+                "associations"
+            } else {
+                "results"
+            };
+            let columns: Vec<(String, ColumnType)> = fields
+                .iter()
+                .map(|&field| {
+                    let kind = self
+                        .column_types
+                        .iter()
+                        .find(|(c, _)| c == field)
+                        .map_or_else(|| column_type(field), |&(_, kind)| kind);
+                    (field.to_string(), kind)
+                })
+                .collect();
+            let definitions: Vec<String> = columns
+                .iter()
+                .map(|(field, kind)| {
+                    let sql_type = match kind {
+                        ColumnType::Integer => "INTEGER",
+                        ColumnType::Decimal => "REAL",
+                        ColumnType::Text => "TEXT",
+                    };
+                    format!("{} {}", quote(field), sql_type)
+                })
+                .collect();
+            self.conn.execute_batch(&format!(
+                "CREATE TABLE {} ({})",
+                name,
+                definitions.join(", ")
+            ))?;
+            let placeholders = vec!["?"; columns.len()].join(", ");
+            let insert = format!("INSERT INTO {} VALUES ({})", name, placeholders);
+            self.table = Some(Table {
+                name,
+                columns,
+                insert,
+            });
+            return Ok(());
+        };
+
+        if fields.len() > table.columns.len() {
+            bail!(
+                "Output row has {} columns but the header has {}",
+                fields.len(),
+                table.columns.len()
+            );
+        }
+        let mut values = Vec::with_capacity(table.columns.len());
+        for (i, (field, kind)) in table.columns.iter().enumerate() {
+            let value =
+                match fields.get(i).copied().filter(|v| *v != "NA") {
+                    None => Value::Null,
+                    Some(v) => match kind {
+                        ColumnType::Integer => Value::Integer(v.parse().with_context(|| {
+                            format!("Invalid integer '{}' in column {}", v, field)
+                        })?),
+                        ColumnType::Decimal => Value::Real(v.parse().with_context(|| {
+                            format!("Invalid number '{}' in column {}", v, field)
+                        })?),
+                        ColumnType::Text => Value::Text(v.to_string()),
+                    },
+                };
+            values.push(value);
+        }
+        self.conn
+            .prepare_cached(&table.insert)?
+            .execute(params_from_iter(values))?;
+        Ok(())
+    }
+
+    /// Summarize the associations per region and per gene, and index them.
+    fn summarize(&self, table: &Table) -> Result<()> {
+        // Region metadata is the same for every association of a region
+        let mut region_columns = vec![quote("Region"), "MIN(\"Midpoint\") AS \"Midpoint\"".into()];
+        for (field, _) in &table.columns {
+            if get_bed_headers(9).contains(&field.as_str()) {
+                region_columns.push(format!("MIN({0}) AS {0}", quote(field)));
+            }
+        }
+        region_columns.push("COUNT(\"Gene\") AS \"Associations\"".into());
+
+        self.conn.execute_batch(&format!(
+            "CREATE INDEX associations_gene ON associations (\"Gene\");
+             CREATE INDEX associations_region ON associations (\"Region\");
+             CREATE TABLE regions AS SELECT {} FROM associations GROUP BY \"Region\";
+             CREATE UNIQUE INDEX regions_region ON regions (\"Region\");
+             CREATE TABLE genes AS SELECT \"Gene\", COUNT(DISTINCT \"Region\") AS \"RegionCount\",
+                 COUNT(*) AS \"Associations\" FROM associations
+                 WHERE \"Gene\" IS NOT NULL GROUP BY \"Gene\";
+             CREATE UNIQUE INDEX genes_gene ON genes (\"Gene\");",
+            region_columns.join(", ")
+        ))?;
+        Ok(())
+    }
+
+    /// Insert the remaining rows, build the summary tables and commit.
+    pub fn finish(mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            let line = String::from_utf8(std::mem::take(&mut self.pending))?;
+            self.add_line(&line)?;
+        }
+        let Some(table) = &self.table else {
+            bail!("No header was written to the SQLite output");
+        };
+        if table.name == "associations" {
+            self.summarize(table)?;
+        }
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+impl Write for SqliteWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        // Only complete lines are inserted
+        if let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') {
+            let complete: Vec<u8> = self.pending.drain(..=end).collect();
+            let text = std::str::from_utf8(&complete)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            for line in text.lines().filter(|l| !l.is_empty()) {
+                self.add_line(line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_writer() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = SqliteWriter::create(file.path(), &[]).unwrap();
+        writeln!(
+            writer,
+            "Region\tMidpoint\tGene\tArea\tDistance\tPercArea\tname"
+        )
+        .unwrap();
+        writeln!(writer, "chr1_100_200\t150\tG1\tTSS\t0\t50.00\tpeak1").unwrap();
+        write!(writer, "chr1_100_200\t150\tG2\tINTRON\t300\t").unwrap();
+        writeln!(
+            writer,
+            "NA\tpeak1\nchr1_900_1000\t950\tNA\tINTERGENIC\tNA\tNA\tpeak2"
+        )
+        .unwrap();
+        writeln!(
+            writer,
+            "chr2_100_200\t150\tG1\tUPSTREAM\t800\t100.00\tpeak3"
+        )
+        .unwrap();
+        writer.finish().unwrap();
+
+        let conn = Connection::open(file.path()).unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM associations"), 4);
+        assert_eq!(count("SELECT SUM(Distance) FROM associations"), 1100);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM associations WHERE PercArea IS NULL"),
+            2
+        );
+
+        let genes: Vec<(String, i64)> = conn
+            .prepare("SELECT Gene, RegionCount FROM genes ORDER BY Gene")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(genes, vec![("G1".to_string(), 2), ("G2".to_string(), 1)]);
+
+        let (name, associations): (String, i64) = conn
+            .query_row(
+                "SELECT name, Associations FROM regions WHERE Region = 'chr1_900_1000'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((name.as_str(), associations), ("peak2", 0));
+    }
+
+    #[test]
+    fn test_sqlite_writer_results_table() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = SqliteWriter::create(file.path(), &[]).unwrap();
+        writeln!(writer, "Gene\tRegionCount\nG1\t2").unwrap();
+        writer.finish().unwrap();
+
+        let conn = Connection::open(file.path()).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT RegionCount FROM results", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+}
@@ -0,0 +1,306 @@
+//! Summary statistics of a run.
+//!
+//! Counts gathered while the regions are written, for a quick check of the
+//! annotation without loading the whole output: regions and associations
+//! per area, report level and chromosome, and the quartiles of the
+//! distances to the genes. A manifest run sums them up per sample.
+
+use std::io::Write;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+
+use crate::config::Config;
+use crate::matcher::enrichment::region_area;
+use crate::types::{Area, Candidate, Region, ReportLevel};
+
+/// Regions and associations counted under one key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub regions: usize,
+    pub associations: usize,
+}
+
+/// Summary statistics of the regions written.
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    rules: Vec<Area>,
+    level: ReportLevel,
+    pub regions: usize,
+    /// Regions without any association.
+    pub unmatched: usize,
+    /// Regions by their highest priority area, associations by their area.
+    pub areas: IndexMap<Area, Tally>,
+    /// Associations per report level.
+    pub levels: IndexMap<ReportLevel, usize>,
+    /// Regions and associations per chromosome, in input order.
+    pub chromosomes: IndexMap<String, Tally>,
+    /// Distances of the associations.
+    pub distances: Vec<i64>,
+    /// TSS distances of the associations.
+    pub tss_distances: Vec<i64>,
+}
+
+impl RunSummary {
+    pub fn from_config(config: &Config) -> Self {
+        RunSummary {
+            rules: config.rules.clone(),
+            level: config.level,
+            regions: 0,
+            unmatched: 0,
+            areas: IndexMap::new(),
+            levels: IndexMap::new(),
+            chromosomes: IndexMap::new(),
+            distances: Vec::new(),
+            tss_distances: Vec::new(),
+        }
+    }
+
+    /// Count a region and its associations.
+    pub fn add(&mut self, region: &Region, candidates: &[Candidate]) {
+        // INTERGENIC rows of unmatched regions are not associations
+        let matched: Vec<&Candidate> = candidates
+            .iter()
+            .filter(|c| c.area != Area::Intergenic)
+            .collect();
+        self.regions += 1;
+        let chromosome = self.chromosomes.entry(region.chrom.clone()).or_default();
+        chromosome.regions += 1;
+        chromosome.associations += matched.len();
+        if matched.is_empty() {
+            self.unmatched += 1;
+            return;
+        }
+
+        self.areas
+            .entry(region_area(candidates, &self.rules))
+            .or_default()
+            .regions += 1;
+        for candidate in matched {
+            self.areas
+                .entry(candidate.area.clone())
+                .or_default()
+                .associations += 1;
+            *self
+                .levels
+                .entry(candidate.level.unwrap_or(self.level))
+                .or_insert(0) += 1;
+            self.distances.push(candidate.distance);
+            self.tss_distances.push(candidate.tss_distance);
+        }
+    }
+
+    /// Number of associations.
+    pub fn associations(&self) -> usize {
+        self.distances.len()
+    }
+
+    /// Minimum, quartiles and maximum of the distances (nearest rank), None
+    /// without associations.
+    pub fn distance_quartiles(&self) -> Option<[i64; 5]> {
+        if self.distances.is_empty() {
+            return None;
+        }
+        let mut sorted = self.distances.clone();
+        sorted.sort_unstable();
+        let rank = |p: f64| {
+            let rank = (p * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Some([sorted[0], rank(0.25), rank(0.5), rank(0.75), rank(1.0)])
+    }
+
+    /// Areas in rule order, followed by the areas missing from the rules.
+    pub fn sorted_areas(&self) -> Vec<(&Area, Tally)> {
+        let rank = |area: &Area| {
+            self.rules
+                .iter()
+                .position(|a| a == area)
+                .unwrap_or(self.rules.len())
+        };
+        let mut areas: Vec<(&Area, Tally)> = self.areas.iter().map(|(a, &t)| (a, t)).collect();
+        areas.sort_by_key(|(area, _)| rank(area));
+        areas
+    }
+
+    /// Write the statistics as tab-separated sections.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "Regions\t{}", self.regions)?;
+        writeln!(writer, "MatchedRegions\t{}", self.regions - self.unmatched)?;
+        writeln!(writer, "UnmatchedRegions\t{}", self.unmatched)?;
+        writeln!(writer, "Associations\t{}", self.associations())?;
+
+        writeln!(writer, "\nArea\tRegions\tAssociations")?;
+        for (area, tally) in self.sorted_areas() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                area.as_str(),
+                tally.regions,
+                tally.associations
+            )?;
+        }
+
+        writeln!(writer, "\nLevel\tAssociations")?;
+        for (level, count) in &self.levels {
+            writeln!(writer, "{}\t{}", level.as_str(), count)?;
+        }
+
+        writeln!(writer, "\nDistance\tMin\tQ1\tMedian\tQ3\tMax")?;
+        match self.distance_quartiles() {
+            Some(q) => writeln!(
+                writer,
+                "Distance\t{}\t{}\t{}\t{}\t{}",
+                q[0], q[1], q[2], q[3], q[4]
+            )?,
+            None => writeln!(writer, "Distance\tNA\tNA\tNA\tNA\tNA")?,
+        }
+
+        writeln!(writer, "\nChromosome\tRegions\tAssociations")?;
+        for (chrom, tally) in &self.chromosomes {
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                chrom, tally.regions, tally.associations
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Summary statistics of the samples of a manifest run, one row per sample.
+#[derive(Debug, Clone, Default)]
+pub struct SampleSummaries {
+    pub samples: Vec<(String, RunSummary)>,
+}
+
+impl SampleSummaries {
+    pub fn add(&mut self, sample: &str, summary: RunSummary) {
+        self.samples.push((sample.to_string(), summary));
+    }
+
+    /// Write one row per sample with its region and association counts and
+    /// the median distance of its associations.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(
+            writer,
+            "Sample\tRegions\tMatchedRegions\tUnmatchedRegions\tAssociations\tMedianDistance"
+        )?;
+        for (sample, summary) in &self.samples {
+            let median = summary
+                .distance_quartiles()
+                .map_or("NA".to_string(), |q| q[2].to_string());
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                sample,
+                summary.regions,
+                summary.regions - summary.unmatched,
+                summary.unmatched,
+                summary.associations(),
+                median
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn candidate(area: Area, distance: i64) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            "T1".to_string(),
+            "G1".to_string(),
+            distance,
+            100.0,
+            50.0,
+            0,
+        )
+    }
+
+    fn region(chrom: &str) -> Region {
+        Region::new(chrom.to_string(), 100, 200, vec![])
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let config = Config {
+            level: ReportLevel::Gene,
+            ..Default::default()
+        };
+        let mut summary = RunSummary::from_config(&config);
+        summary.add(
+            &region("chr1"),
+            &[candidate(Area::Intron, 0), candidate(Area::Tss, 0)],
+        );
+        summary.add(&region("chr1"), &[candidate(Area::Upstream, 3000)]);
+        summary.add(&region("chr2"), &[]);
+        summary.add(&region("chr2"), &[candidate(Area::Intergenic, 0)]);
+
+        assert_eq!((summary.regions, summary.unmatched), (4, 2));
+        assert_eq!(summary.associations(), 3);
+        assert_eq!(summary.distance_quartiles(), Some([0, 0, 0, 3000, 3000]));
+        assert_eq!(summary.levels[&ReportLevel::Gene], 3);
+        assert_eq!(
+            summary.chromosomes["chr2"],
+            Tally {
+                regions: 2,
+                associations: 0
+            }
+        );
+
+        // The first region counts once, through TSS
+        let areas: Vec<(Area, usize, usize)> = summary
+            .sorted_areas()
+            .into_iter()
+            .map(|(area, t)| (area.clone(), t.regions, t.associations))
+            .collect();
+        assert_eq!(
+            areas,
+            vec![
+                (Area::Tss, 1, 1),
+                (Area::Intron, 0, 1),
+                (Area::Upstream, 1, 1)
+            ]
+        );
+
+        let mut text = Vec::new();
+        summary.write(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("Regions\t4\nMatchedRegions\t2\nUnmatchedRegions\t2\n"));
+        assert!(text.contains("\nDistance\t0\t0\t0\t3000\t3000\n"));
+        assert!(text.ends_with("chr1\t2\t3\nchr2\t2\t0\n"));
+    }
+
+    #[test]
+    fn test_sample_summaries() {
+        let config = Config::default();
+        let mut treated = RunSummary::from_config(&config);
+        treated.add(
+            &region("chr1"),
+            &[candidate(Area::Tss, 0), candidate(Area::Upstream, 3000)],
+        );
+        treated.add(&region("chr2"), &[]);
+        let mut summaries = SampleSummaries::default();
+        summaries.add("treated", treated);
+        summaries.add("empty", RunSummary::from_config(&config));
+
+        let mut text = Vec::new();
+        summaries.write(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(
+            text,
+            "Sample\tRegions\tMatchedRegions\tUnmatchedRegions\tAssociations\tMedianDistance\n\
+             treated\t2\t1\t1\t2\t0\n\
+             empty\t0\t0\t0\t0\tNA\n"
+        );
+    }
+}
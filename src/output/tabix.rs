@@ -0,0 +1,412 @@
+//! bgzip-compressed output with a tabix index.
+//!
+//! Lines are compressed into BGZF blocks, the blocked gzip format read by
+//! htslib, and every line with coordinates is added to a tabix binning
+//! index written next to the output as `<output>.tbi`. Lines starting with
+//! `#` are headers and are not indexed.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use indexmap::IndexMap;
+
+/// Uncompressed bytes per BGZF block, as in bgzip.
+const BLOCK_SIZE: usize = 0xff00;
+
+/// Empty block marking the end of a BGZF file.
+const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Size of the linear index windows, as a shift.
+const LINEAR_SHIFT: u32 = 14;
+
+/// Writer of BGZF blocks, tracking the virtual offset of the next byte.
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    block: Vec<u8>,
+    /// Compressed bytes written so far.
+    address: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        BgzfWriter {
+            inner,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            address: 0,
+        }
+    }
+
+    /// Virtual offset of the next byte: the address of its compressed
+    /// block and its position in the uncompressed block.
+    pub fn virtual_offset(&self) -> u64 {
+        (self.address << 16) | self.block.len() as u64
+    }
+
+    /// Compress the pending bytes as one block.
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.block)?;
+        let data = encoder.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.block);
+
+        // Header, compressed data and footer; BSIZE is the block size - 1
+        let block_size = 18 + data.len() + 8;
+        let mut header = [
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43,
+            0x02, 0x00, 0x00, 0x00,
+        ];
+        header[16..].copy_from_slice(&((block_size - 1) as u16).to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&data)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner
+            .write_all(&(self.block.len() as u32).to_le_bytes())?;
+
+        self.address += block_size as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Write the last block and the end-of-file marker.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        self.inner.write_all(&EOF_BLOCK)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..n]);
+        // Full blocks are written at once, so offsets never point past a block
+        if self.block.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Columns holding the coordinates, and their convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabixPreset {
+    /// Chromosome, start and end in columns 1-3, 0-based start.
+    Bed,
+    /// Chromosome, start and end in columns 1, 4 and 5, 1-based.
+    Gff,
+}
+
+impl TabixPreset {
+    /// Format flags, then 1-based sequence, start and end columns.
+    fn columns(self) -> [i32; 4] {
+        match self {
+            // 0x10000: 0-based, half-open coordinates
+            TabixPreset::Bed => [0x10000, 1, 2, 3],
+            TabixPreset::Gff => [0, 1, 4, 5],
+        }
+    }
+}
+
+/// Bin of the smallest binning index level containing `[beg, end)`.
+fn reg2bin(beg: i64, end: i64) -> u32 {
+    let end = end - 1;
+    for (shift, first) in [(14, 4681), (17, 585), (20, 73), (23, 9), (26, 1)] {
+        if beg >> shift == end >> shift {
+            return (first + (beg >> shift)) as u32;
+        }
+    }
+    0
+}
+
+/// Index of the lines of one chromosome.
+#[derive(Default)]
+struct ReferenceIndex {
+    bins: BTreeMap<u32, Vec<(u64, u64)>>,
+    /// Offset of the first line overlapping each linear window.
+    linear: Vec<Option<u64>>,
+    last_start: i64,
+}
+
+impl ReferenceIndex {
+    fn add(&mut self, beg: i64, end: i64, offsets: (u64, u64)) {
+        let chunks = self.bins.entry(reg2bin(beg, end)).or_default();
+        match chunks.last_mut() {
+            // Consecutive lines of a bin share one chunk
+            Some(last) if last.1 == offsets.0 => last.1 = offsets.1,
+            _ => chunks.push(offsets),
+        }
+
+        let last_window = ((end - 1) >> LINEAR_SHIFT) as usize;
+        if self.linear.len() <= last_window {
+            self.linear.resize(last_window + 1, None);
+        }
+        for window in &mut self.linear[(beg >> LINEAR_SHIFT) as usize..=last_window] {
+            window.get_or_insert(offsets.0);
+        }
+        self.last_start = beg;
+    }
+}
+
+/// Writer of bgzip-compressed lines indexed with tabix.
+///
+/// Lines must be sorted by start within chromosomes, and each chromosome
+/// must come in a single run. [`TabixWriter::finish`] must be called to
+/// complete the output and write the index.
+pub struct TabixWriter {
+    output: BgzfWriter<BufWriter<File>>,
+    index_path: PathBuf,
+    preset: TabixPreset,
+    references: IndexMap<String, ReferenceIndex>,
+    pending: Vec<u8>,
+}
+
+impl TabixWriter {
+    /// Create the output file; the index is written to `<path>.tbi`.
+    pub fn create(path: &Path, preset: TabixPreset) -> Result<Self> {
+        let file = File::create(path).context("Failed to create output file")?;
+        let mut index_path = path.as_os_str().to_owned();
+        index_path.push(".tbi");
+        Ok(TabixWriter {
+            output: BgzfWriter::new(BufWriter::new(file)),
+            index_path: PathBuf::from(index_path),
+            preset,
+            references: IndexMap::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Write a complete line, including its newline, and index it.
+    fn add_line(&mut self, line: &[u8]) -> Result<()> {
+        let begin = self.output.virtual_offset();
+        self.output.write_all(line)?;
+        let offsets = (begin, self.output.virtual_offset());
+        if line.starts_with(b"#") {
+            return Ok(());
+        }
+
+        let text = std::str::from_utf8(line)?.trim_end_matches('\n');
+        let fields: Vec<&str> = text.split('\t').collect();
+        let [_, seq_col, beg_col, end_col] = self.preset.columns();
+        let coordinate = |col: i32| -> Result<i64> {
+            let value = fields.get(col as usize - 1).copied().unwrap_or_default();
+            value
+                .parse()
+                .with_context(|| format!("Invalid coordinate '{}' in output line", value))
+        };
+        let chrom = fields[seq_col as usize - 1];
+        let mut beg = coordinate(beg_col)?;
+        let mut end = coordinate(end_col)?;
+        if self.preset == TabixPreset::Gff {
+            beg -= 1;
+        }
+        beg = beg.max(0);
+        end = end.max(beg + 1);
+
+        let last = self.references.len().checked_sub(1);
+        let i = match self.references.get_index_of(chrom) {
+            Some(i) => i,
+            None => {
+                self.references
+                    .insert_full(chrom.to_string(), ReferenceIndex::default())
+                    .0
+            }
+        };
+        let reference = &mut self.references[i];
+        if last.is_some_and(|last| i < last) || beg < reference.last_start {
+            bail!(
+                "--bgzip needs the BED file sorted by chromosome and start (e.g. sort -k1,1 -k2,2n), \
+                 found {}:{} out of order",
+                chrom,
+                beg
+            );
+        }
+        reference.add(beg, end, offsets);
+        Ok(())
+    }
+
+    /// Serialize the tabix index.
+    fn index(&self) -> Vec<u8> {
+        fn push_i32(out: &mut Vec<u8>, value: i32) {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut out = Vec::new();
+
+        out.extend_from_slice(b"TBI\x01");
+        push_i32(&mut out, self.references.len() as i32);
+        for value in self.preset.columns() {
+            push_i32(&mut out, value);
+        }
+        push_i32(&mut out, b'#' as i32);
+        push_i32(&mut out, 0);
+
+        let names_len: usize = self.references.keys().map(|name| name.len() + 1).sum();
+        push_i32(&mut out, names_len as i32);
+        for name in self.references.keys() {
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+        }
+
+        for reference in self.references.values() {
+            push_i32(&mut out, reference.bins.len() as i32);
+            for (bin, chunks) in &reference.bins {
+                out.extend_from_slice(&bin.to_le_bytes());
+                push_i32(&mut out, chunks.len() as i32);
+                for (begin, end) in chunks {
+                    out.extend_from_slice(&begin.to_le_bytes());
+                    out.extend_from_slice(&end.to_le_bytes());
+                }
+            }
+
+            // Windows without lines point to the previous line
+            let first = reference.linear.iter().flatten().next().copied();
+            let mut previous = first.unwrap_or(0);
+            push_i32(&mut out, reference.linear.len() as i32);
+            for window in &reference.linear {
+                previous = window.unwrap_or(previous);
+                out.extend_from_slice(&previous.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    /// Write the remaining output and the index.
+    pub fn finish(mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            let mut line = std::mem::take(&mut self.pending);
+            line.push(b'\n');
+            self.add_line(&line)?;
+        }
+        let index = self.index();
+        self.output
+            .finish()
+            .context("Failed to write output file")?;
+
+        let file = File::create(&self.index_path).context("Failed to create tabix index")?;
+        let mut writer = BgzfWriter::new(BufWriter::new(file));
+        writer.write_all(&index)?;
+        writer.finish().context("Failed to write tabix index")?;
+        Ok(())
+    }
+}
+
+impl Write for TabixWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        // Only complete lines are indexed
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.add_line(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn read_bgzf(path: &Path) -> Vec<u8> {
+        let mut data = Vec::new();
+        MultiGzDecoder::new(File::open(path).unwrap())
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_reg2bin() {
+        assert_eq!(reg2bin(0, 1), 4681);
+        assert_eq!(reg2bin(16384, 16385), 4682);
+        assert_eq!(reg2bin(16000, 17000), 585);
+        assert_eq!(reg2bin(0, 1 << 29), 0);
+    }
+
+    #[test]
+    fn test_bgzf_blocks() {
+        let mut writer = BgzfWriter::new(Vec::new());
+        let data: Vec<u8> = (0..150_000u32).map(|i| (i % 251) as u8).collect();
+        writer.write_all(&data).unwrap();
+        // Two full blocks written, the third pending
+        assert_eq!(
+            writer.virtual_offset() & 0xffff,
+            (150_000 - 2 * BLOCK_SIZE) as u64
+        );
+        let output = writer.finish().unwrap();
+        assert!(output.ends_with(&EOF_BLOCK));
+
+        let mut decoded = Vec::new();
+        MultiGzDecoder::new(output.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_tabix_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.bed.gz");
+        let mut writer = TabixWriter::create(&path, TabixPreset::Bed).unwrap();
+        writeln!(writer, "#Chrom\tStart\tEnd").unwrap();
+        write!(writer, "chr1\t100\t200\tG1\nchr1\t100\t200\tG2\nchr1\t50").unwrap();
+        writeln!(writer, "000\t50100\tG3\nchr2\t10\t20\tG4").unwrap();
+        writer.finish().unwrap();
+
+        let text = String::from_utf8(read_bgzf(&path)).unwrap();
+        assert_eq!(text.lines().count(), 5);
+
+        let index = read_bgzf(&path.with_extension("gz.tbi"));
+        assert_eq!(&index[..4], b"TBI\x01");
+        // Two references, BED preset, '#' meta character
+        let i32_at = |at: usize| i32::from_le_bytes(index[at..at + 4].try_into().unwrap());
+        assert_eq!(i32_at(4), 2);
+        assert_eq!(
+            [i32_at(8), i32_at(12), i32_at(16), i32_at(20)],
+            [0x10000, 1, 2, 3]
+        );
+        assert_eq!(i32_at(24), b'#' as i32);
+        assert_eq!(i32_at(32), 10);
+        assert_eq!(&index[36..46], b"chr1\0chr2\0");
+        // chr1: the first two lines share a chunk in bin 4681, G3 is in bin 4684
+        assert_eq!(i32_at(46), 2);
+        assert_eq!(u32::from_le_bytes(index[50..54].try_into().unwrap()), 4681);
+        assert_eq!(i32_at(54), 1);
+    }
+
+    #[test]
+    fn test_tabix_writer_unsorted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.gff.gz");
+        let mut writer = TabixWriter::create(&path, TabixPreset::Gff).unwrap();
+        writeln!(writer, "chr1\trgmatch\tregion\t500\t600\t.\t.\t.\tarea=TSS").unwrap();
+        assert!(writeln!(writer, "chr1\trgmatch\tregion\t100\t200\t.\t.\t.\tarea=TSS").is_err());
+
+        let mut writer = TabixWriter::create(&path, TabixPreset::Bed).unwrap();
+        writeln!(writer, "chr1\t100\t200\nchr2\t100\t200").unwrap();
+        assert!(writeln!(writer, "chr1\t300\t400").is_err());
+    }
+}
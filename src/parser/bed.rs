@@ -3,13 +3,22 @@
 //! Parses BED (Browser Extensible Data) files containing genomic regions.
 
 use ahash::AHashMap;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use indexmap::map::Entry;
+use indexmap::IndexMap;
+use std::collections::VecDeque;
 use std::fs::File;
+use std::hash::Hash;
 use std::io::BufRead;
 use std::path::Path;
 
-use crate::parser::util::create_buffered_reader;
-use crate::types::Region;
+use crate::parser::gtf::GtfData;
+use crate::parser::util::{create_buffered_reader, is_header_line};
+use crate::types::{Exon, Gene, Region, Strand, Transcript};
+use crate::warnings;
+
+/// Number of regions read at a time when loading a whole file.
+const LOAD_CHUNK_SIZE: usize = 100_000;
 
 /// Streaming BED file reader for chunked processing.
 ///
@@ -17,7 +26,18 @@ use crate::types::Region;
 /// in chunks, enabling memory-efficient processing of large files.
 pub struct BedReader {
     reader: Box<dyn BufRead + Send>,
+    /// File name, for the warnings about its lines.
+    source: String,
     num_meta_columns: usize,
+    /// Bytes of the (uncompressed) file read so far.
+    offset: u64,
+    /// Lines read so far.
+    line_number: usize,
+    /// Whether a region was read, after which lines cannot be headers.
+    regions_seen: bool,
+    /// Pre-loaded regions served instead of the reader (see [`BedReader::merged`]
+    /// and [`BedReader::deduplicated`]).
+    preloaded: Option<VecDeque<Region>>,
 }
 
 impl BedReader {
@@ -28,20 +48,101 @@ impl BedReader {
 
         Ok(BedReader {
             reader,
+            source: path.display().to_string(),
             num_meta_columns: 0,
+            offset: 0,
+            line_number: 0,
+            regions_seen: false,
+            preloaded: None,
         })
     }
 
+    /// Create a BedReader continuing a file after the first `offset` bytes
+    /// of its (uncompressed) content, as given by [`BedReader::offset`].
+    ///
+    /// The skipped bytes are read and discarded, so compressed files can be
+    /// continued too.
+    pub fn resume(path: &Path, offset: u64) -> Result<Self> {
+        let mut bed_reader = Self::new(path)?;
+        while bed_reader.offset < offset {
+            let buffer = bed_reader
+                .reader
+                .fill_buf()
+                .context("Failed to read BED file")?;
+            if buffer.is_empty() {
+                bail!(
+                    "BED file {} is shorter than the offset to resume from ({} bytes)",
+                    path.display(),
+                    offset
+                );
+            }
+            let skipped = buffer.len().min((offset - bed_reader.offset) as usize);
+            bed_reader.line_number += buffer[..skipped].iter().filter(|&&b| b == b'\n').count();
+            bed_reader.reader.consume(skipped);
+            bed_reader.offset += skipped as u64;
+        }
+        bed_reader.regions_seen = offset > 0;
+        Ok(bed_reader)
+    }
+
+    /// Create a BedReader that merges overlapping regions before serving them.
+    ///
+    /// The whole file is read up front and merged with [`merge_regions`], so
+    /// the regions come out sorted by position instead of in file order.
+    pub fn merged(path: &Path, gap: i64) -> Result<Self> {
+        Self::preload(path, |regions| merge_regions(regions, gap))
+    }
+
+    /// Create a BedReader that collapses exact duplicate regions, i.e.
+    /// regions with the same `key`.
+    ///
+    /// The whole file is read up front and deduplicated with
+    /// [`collapse_duplicates`], keeping the file order of first occurrences.
+    pub fn deduplicated<K, F>(path: &Path, key: F) -> Result<Self>
+    where
+        K: Hash + Eq,
+        F: Fn(&Region) -> K,
+    {
+        Self::preload(path, |regions| collapse_duplicates(regions, key))
+    }
+
+    /// Read the whole file and serve the regions produced by `transform`.
+    fn preload<F>(path: &Path, transform: F) -> Result<Self>
+    where
+        F: FnOnce(Vec<Region>) -> Vec<Region>,
+    {
+        let mut bed_reader = Self::new(path)?;
+
+        let mut regions = Vec::new();
+        while let Some(chunk) = bed_reader.read_chunk(LOAD_CHUNK_SIZE)? {
+            regions.extend(chunk);
+        }
+        bed_reader.preloaded = Some(transform(regions).into());
+
+        Ok(bed_reader)
+    }
+
     /// Get the number of metadata columns found so far.
     pub fn num_meta_columns(&self) -> usize {
         self.num_meta_columns
     }
 
+    /// Bytes of the (uncompressed) file read so far, up to the end of the
+    /// last chunk. Always 0 for the merged and deduplicated readers.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
     /// Read the next chunk of regions from the BED file.
     ///
     /// Returns `None` when EOF is reached. The regions are returned in file order,
     /// preserving the original ordering for deterministic output.
     pub fn read_chunk(&mut self, size: usize) -> Result<Option<Vec<Region>>> {
+        if let Some(preloaded) = self.preloaded.as_mut() {
+            let chunk: Vec<Region> = preloaded.drain(..size.min(preloaded.len())).collect();
+            return Ok((!chunk.is_empty()).then_some(chunk));
+        }
+
         let mut regions = Vec::with_capacity(size);
         let mut line = String::new();
 
@@ -56,6 +157,8 @@ impl BedReader {
                 // EOF reached
                 break;
             }
+            self.offset += bytes_read as u64;
+            self.line_number += 1;
 
             // Skip empty lines
             let trimmed = line.trim_end();
@@ -63,8 +166,16 @@ impl BedReader {
                 continue;
             }
 
-            if let Some(region) = self.parse_line(trimmed) {
-                regions.push(region);
+            match parse_region(trimmed) {
+                Ok(region) => {
+                    warn_inverted(&region, &self.source, self.line_number);
+                    // Track the maximum number of metadata columns
+                    self.num_meta_columns = self.num_meta_columns.max(region.metadata.len());
+                    self.regions_seen = true;
+                    regions.push(region);
+                }
+                Err(_) if is_header_line(trimmed, self.regions_seen) => {}
+                Err(reason) => warnings::skip_line("BED", &self.source, self.line_number, reason)?,
             }
         }
 
@@ -74,37 +185,58 @@ impl BedReader {
             Ok(Some(regions))
         }
     }
+}
 
-    /// Parse a single BED line into a Region.
-    fn parse_line(&mut self, line: &str) -> Option<Region> {
-        let fields: Vec<&str> = line.split('\t').collect();
-
-        // Need at least 3 columns: chrom, start, end
-        if fields.len() < 3 {
-            return None;
-        }
-
-        let chrom = fields[0].to_string();
+/// Parse a BED line into a region, or tell why it is not one.
+fn parse_region(line: &str) -> Result<Region, &'static str> {
+    let fields: Vec<&str> = line.split('\t').collect();
 
-        // Try to parse start and end as integers
-        // If they fail (e.g., header line), skip this line
-        let start: i64 = fields[1].parse().ok()?;
-        let end: i64 = fields[2].parse().ok()?;
+    // Need at least 3 columns: chrom, start, end
+    if fields.len() < 3 {
+        return Err("expected at least 3 tab-separated columns");
+    }
 
-        // Extract up to 9 additional BED columns as metadata
-        let metadata: Vec<String> = fields
-            .iter()
-            .skip(3)
-            .take(9)
-            .map(|s| s.to_string())
-            .collect();
+    // Headers do not parse either; the callers tell them apart
+    let (Ok(start), Ok(end)) = (fields[1].parse::<i64>(), fields[2].parse::<i64>()) else {
+        return Err("invalid start or end coordinate");
+    };
+    let (start, end) = point_coordinates(start, end);
+
+    // Extract up to 9 additional BED columns as metadata
+    let metadata: Vec<String> = fields
+        .iter()
+        .skip(3)
+        .take(9)
+        .map(|s| s.to_string())
+        .collect();
+
+    Ok(Region::new(fields[0].to_string(), start, end, metadata))
+}
 
-        // Track the maximum number of metadata columns
-        if metadata.len() > self.num_meta_columns {
-            self.num_meta_columns = metadata.len();
-        }
+/// Coordinates of a BED region, matching zero-length insertion points as points.
+///
+/// Coordinates are inclusive, so `start == end` is already the single base
+/// at `start`. The empty interval of an insertion or cut site written as
+/// `end == start - 1` is read as the same single base.
+fn point_coordinates(start: i64, end: i64) -> (i64, i64) {
+    if end == start - 1 {
+        (start, start)
+    } else {
+        (start, end)
+    }
+}
 
-        Some(Region::new(chrom, start, end, metadata))
+/// Warn about a region ending before its start, on line `line_number` of
+/// `source`. It is still read as given, as before, but its overlaps and
+/// distances are negative, which is rarely what the file meant.
+fn warn_inverted(region: &Region, source: &str, line_number: usize) {
+    if region.end < region.start {
+        warnings::warn("BED lines: end before start", || {
+            format!(
+                "{} line {}: end before start; read as given",
+                source, line_number
+            )
+        });
     }
 }
 
@@ -123,15 +255,15 @@ pub fn parse_bed(path: &Path) -> Result<BedData> {
     let file = File::open(path).context("Failed to open BED file")?;
     let reader = create_buffered_reader(file, path);
 
-    parse_bed_reader(reader)
+    parse_bed_reader(reader, &path.display().to_string())
 }
 
-/// Parse BED data from a reader.
-fn parse_bed_reader<R: BufRead>(reader: R) -> Result<BedData> {
+/// Parse BED data from a reader of the file `source`.
+fn parse_bed_reader<R: BufRead>(reader: R, source: &str) -> Result<BedData> {
     let mut regions_by_chrom: AHashMap<String, Vec<Region>> = AHashMap::new();
     let mut num_meta_columns = 0;
 
-    for line_result in reader.lines() {
+    for (i, line_result) in reader.lines().enumerate() {
         let line = line_result.context("Failed to read BED line")?;
 
         // Skip empty lines
@@ -139,41 +271,23 @@ fn parse_bed_reader<R: BufRead>(reader: R) -> Result<BedData> {
             continue;
         }
 
-        let fields: Vec<&str> = line.split('\t').collect();
-
-        // Need at least 3 columns: chrom, start, end
-        if fields.len() < 3 {
-            continue;
-        }
-
-        let chrom = fields[0].to_string();
-
-        // Try to parse start and end as integers
-        // If they fail (e.g., header line), skip this line
-        let start: i64 = match fields[1].parse() {
-            Ok(v) => v,
-            Err(_) => continue, // Skip header lines
-        };
-        let end: i64 = match fields[2].parse() {
-            Ok(v) => v,
-            Err(_) => continue,
+        let region = match parse_region(&line) {
+            Ok(region) => region,
+            Err(_) if is_header_line(&line, !regions_by_chrom.is_empty()) => continue,
+            Err(reason) => {
+                warnings::skip_line("BED", source, i + 1, reason)?;
+                continue;
+            }
         };
 
-        // Extract up to 9 additional BED columns as metadata
-        let metadata: Vec<String> = fields
-            .iter()
-            .skip(3)
-            .take(9)
-            .map(|s| s.to_string())
-            .collect();
-
+        warn_inverted(&region, source, i + 1);
         // Track the maximum number of metadata columns
-        if metadata.len() > num_meta_columns {
-            num_meta_columns = metadata.len();
-        }
+        num_meta_columns = num_meta_columns.max(region.metadata.len());
 
-        let region = Region::new(chrom.clone(), start, end, metadata);
-        regions_by_chrom.entry(chrom).or_default().push(region);
+        regions_by_chrom
+            .entry(region.chrom.clone())
+            .or_default()
+            .push(region);
     }
 
     Ok(BedData {
@@ -182,6 +296,144 @@ fn parse_bed_reader<R: BufRead>(reader: R) -> Result<BedData> {
     })
 }
 
+/// Parse a BED file of named features as an annotation to match against.
+///
+/// Each feature becomes a single-exon gene named after the name column (or
+/// `chrom_start_end` without one), with a unique `chrom_start_end` transcript.
+/// Features without a strand are treated as being on the positive strand.
+pub fn parse_feature_bed(path: &Path) -> Result<GtfData> {
+    let bed_data = parse_bed(path)?;
+
+    let mut genes_by_chrom: AHashMap<String, Vec<Gene>> = AHashMap::new();
+    let mut max_lengths: AHashMap<String, i64> = AHashMap::new();
+
+    for (chrom, regions) in bed_data.regions_by_chrom {
+        let genes: Vec<Gene> = regions.iter().map(feature_gene).collect();
+
+        let max_len = genes.iter().map(|g| g.end - g.start).max().unwrap_or(0);
+        max_lengths.insert(chrom.clone(), max_len);
+        genes_by_chrom.insert(chrom, genes);
+    }
+
+    Ok(GtfData {
+        genes_by_chrom,
+        max_lengths,
+    })
+}
+
+/// Build the single-exon gene standing for a BED feature.
+fn feature_gene(region: &Region) -> Gene {
+    let strand = region.strand().unwrap_or(Strand::Positive);
+    let name = match region.metadata.first() {
+        Some(name) if !name.is_empty() => name.clone(),
+        _ => region.id(),
+    };
+
+    let mut transcript = Transcript::new(region.id());
+    transcript.add_exon(Exon::new(region.start, region.end));
+    transcript.calculate_size();
+    transcript.renumber_exons(strand);
+
+    let mut gene = Gene::new(name, strand);
+    gene.set_length(region.start, region.end);
+    gene.add_transcript(transcript);
+    gene
+}
+
+/// Merge overlapping or bookended regions, or regions closer than `gap` bp.
+///
+/// Chromosomes keep their order of first appearance and regions are sorted by
+/// start within each chromosome. Metadata columns of merged regions are
+/// aggregated into comma-separated lists of their distinct values, except the
+/// strand, which is kept when shared and set to "." otherwise.
+pub fn merge_regions(regions: Vec<Region>, gap: i64) -> Vec<Region> {
+    let mut by_chrom: IndexMap<String, Vec<Region>> = IndexMap::new();
+    for region in regions {
+        by_chrom
+            .entry(region.chrom.clone())
+            .or_default()
+            .push(region);
+    }
+
+    let mut merged = Vec::new();
+    for (_, mut regions) in by_chrom {
+        regions.sort_by_key(|r| (r.start, r.end));
+
+        let mut group: Vec<Region> = Vec::new();
+        let mut group_end = i64::MIN;
+        for region in regions {
+            if !group.is_empty() && region.start > group_end.saturating_add(gap) {
+                merged.push(merge_group(std::mem::take(&mut group)));
+            }
+            group_end = if group.is_empty() {
+                region.end
+            } else {
+                group_end.max(region.end)
+            };
+            group.push(region);
+        }
+        if !group.is_empty() {
+            merged.push(merge_group(group));
+        }
+    }
+
+    merged
+}
+
+/// Combine a group of overlapping regions into a single region.
+fn merge_group(group: Vec<Region>) -> Region {
+    if group.len() == 1 {
+        return group.into_iter().next().unwrap();
+    }
+
+    let start = group.iter().map(|r| r.start).min().unwrap();
+    let end = group.iter().map(|r| r.end).max().unwrap();
+    let num_columns = group.iter().map(|r| r.metadata.len()).max().unwrap();
+
+    let metadata = (0..num_columns)
+        .map(|i| {
+            let mut values: Vec<&str> = Vec::new();
+            for value in group.iter().filter_map(|r| r.metadata.get(i)) {
+                if !values.contains(&value.as_str()) {
+                    values.push(value);
+                }
+            }
+            // Column 6 of the BED file
+            if i == 2 && values.len() > 1 {
+                ".".to_string()
+            } else {
+                values.join(",")
+            }
+        })
+        .collect();
+
+    Region::new(group[0].chrom.clone(), start, end, metadata)
+}
+
+/// Collapse regions with the same key (e.g. [`Config::duplicate_key`]) into
+/// one.
+///
+/// The first occurrence is kept, with its metadata, in file order and its
+/// `copies` counts the number of duplicates.
+///
+/// [`Config::duplicate_key`]: crate::config::Config::duplicate_key
+pub fn collapse_duplicates<K, F>(regions: Vec<Region>, key: F) -> Vec<Region>
+where
+    K: Hash + Eq,
+    F: Fn(&Region) -> K,
+{
+    let mut unique: IndexMap<K, Region> = IndexMap::new();
+    for region in regions {
+        match unique.entry(key(&region)) {
+            Entry::Occupied(mut first) => first.get_mut().copies += region.copies,
+            Entry::Vacant(entry) => {
+                entry.insert(region);
+            }
+        }
+    }
+    unique.into_values().collect()
+}
+
 /// Get standard BED column headers for metadata columns.
 pub fn get_bed_headers(num_columns: usize) -> Vec<&'static str> {
     let all_headers = [
@@ -209,7 +461,7 @@ mod tests {
         let bed_content = "chr1\t100\t200\nchrom2\t300\t400\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_reader(reader, "test.bed").unwrap();
 
         assert!(result.regions_by_chrom.contains_key("chr1"));
         assert!(result.regions_by_chrom.contains_key("chrom2"));
@@ -221,12 +473,25 @@ mod tests {
         assert!(chr1_regions[0].metadata.is_empty());
     }
 
+    #[test]
+    fn test_parse_bed_insertion_points() {
+        let bed_content = "chr1\t100\t100\tins1\nchr1\t300\t299\tins2\n";
+
+        let reader = BufReader::new(bed_content.as_bytes());
+        let result = parse_bed_reader(reader, "test.bed").unwrap();
+
+        let regions = &result.regions_by_chrom["chr1"];
+        assert_eq!((regions[0].start, regions[0].end), (100, 100));
+        assert_eq!((regions[1].start, regions[1].end), (300, 300));
+        assert!(regions.iter().all(|r| r.length() == 1));
+    }
+
     #[test]
     fn test_parse_bed_with_metadata() {
         let bed_content = "chr1\t100\t200\tregion1\t500\t+\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_reader(reader, "test.bed").unwrap();
 
         let regions = &result.regions_by_chrom["chr1"];
         assert_eq!(regions[0].metadata.len(), 3);
@@ -241,24 +506,132 @@ mod tests {
         let bed_content = "chrom\tstart\tend\tname\nchr1\t100\t200\tregion1\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_reader(reader, "test.bed").unwrap();
 
         // Should skip header line (can't parse 'start' as int)
         assert!(result.regions_by_chrom.contains_key("chr1"));
         assert!(!result.regions_by_chrom.contains_key("chrom"));
     }
 
+    #[test]
+    fn test_parse_bed_skips_malformed_lines() {
+        let bed_content =
+            "track name=peaks\nchr1\t100\t200\nchr1\tx\t300\nchr2\t5\n# note\nchr1\t300\t400\n";
+
+        let reader = BufReader::new(bed_content.as_bytes());
+        let result = parse_bed_reader(reader, "test.bed").unwrap();
+
+        let regions = &result.regions_by_chrom["chr1"];
+        assert_eq!(regions.len(), 2);
+        assert!(!result.regions_by_chrom.contains_key("chr2"));
+    }
+
     #[test]
     fn test_parse_bed_empty_lines() {
         let bed_content = "\nchr1\t100\t200\n\nchr1\t300\t400\n\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_reader(reader, "test.bed").unwrap();
 
         let regions = &result.regions_by_chrom["chr1"];
         assert_eq!(regions.len(), 2);
     }
 
+    #[test]
+    fn test_merge_regions() {
+        let region = |chrom: &str, start: i64, end: i64, name: &str, strand: &str| {
+            Region::new(
+                chrom.to_string(),
+                start,
+                end,
+                vec![name.to_string(), "0".to_string(), strand.to_string()],
+            )
+        };
+        let regions = vec![
+            region("chr2", 100, 200, "e", "+"),
+            region("chr1", 300, 400, "b", "+"),
+            region("chr1", 100, 200, "a", "+"),
+            region("chr1", 350, 500, "c", "-"),
+            region("chr1", 500, 600, "d", "-"),
+            region("chr1", 700, 800, "f", "+"),
+        ];
+
+        let merged = merge_regions(regions.clone(), 0);
+        let ids: Vec<String> = merged.iter().map(|r| r.id()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "chr2_100_200",
+                "chr1_100_200",
+                "chr1_300_600",
+                "chr1_700_800"
+            ]
+        );
+        assert_eq!(merged[2].metadata, vec!["b,c,d", "0", "."]);
+        assert_eq!(merged[1].metadata, vec!["a", "0", "+"]);
+
+        // A gap merges nearby regions too
+        let merged = merge_regions(regions.clone(), 99);
+        let ids: Vec<String> = merged.iter().map(|r| r.id()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "chr2_100_200",
+                "chr1_100_200",
+                "chr1_300_600",
+                "chr1_700_800"
+            ]
+        );
+        let merged = merge_regions(regions, 100);
+        let ids: Vec<String> = merged.iter().map(|r| r.id()).collect();
+        assert_eq!(ids, vec!["chr2_100_200", "chr1_100_800"]);
+    }
+
+    #[test]
+    fn test_collapse_duplicates() {
+        let region = |start: i64, name: &str| {
+            Region::new(
+                "chr1".to_string(),
+                start,
+                start + 100,
+                vec![name.to_string()],
+            )
+        };
+        let regions = vec![region(500, "a"), region(100, "b"), region(500, "c")];
+
+        let key = |region: &Region| (region.chrom.clone(), region.start, region.end);
+        let collapsed = collapse_duplicates(regions, key);
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(collapsed[0].id(), "chr1_500_600");
+        assert_eq!(collapsed[0].metadata, vec!["a"]);
+        assert_eq!(collapsed[0].copies, 2);
+        assert_eq!(collapsed[1].copies, 1);
+    }
+
+    #[test]
+    fn test_feature_gene() {
+        let region = Region::new(
+            "chr1".to_string(),
+            100,
+            200,
+            vec!["CpG".to_string(), "0".to_string(), "-".to_string()],
+        );
+        let gene = feature_gene(&region);
+        assert_eq!(gene.gene_id, "CpG");
+        assert_eq!(gene.strand, Strand::Negative);
+        assert_eq!((gene.start, gene.end), (100, 200));
+        assert_eq!(gene.transcripts[0].transcript_id, "chr1_100_200");
+        assert_eq!(
+            gene.transcripts[0].exons[0].exon_number.as_deref(),
+            Some("1")
+        );
+
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let gene = feature_gene(&region);
+        assert_eq!(gene.gene_id, "chr1_100_200");
+        assert_eq!(gene.strand, Strand::Positive);
+    }
+
     #[test]
     fn test_get_bed_headers() {
         assert_eq!(get_bed_headers(0), Vec::<&str>::new());
@@ -360,4 +733,29 @@ mod tests {
         assert_eq!(chunk[0].start, 100);
         assert_eq!(chunk[1].start, 300);
     }
+
+    #[test]
+    fn test_bed_reader_resume() {
+        use std::io::Write;
+        use tempfile::NamedTempFile;
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        writeln!(temp_file, "chr1\t100\t200").unwrap();
+        writeln!(temp_file, "chr1\t300\t400").unwrap();
+        writeln!(temp_file, "chr2\t500\t600").unwrap();
+        temp_file.flush().unwrap();
+
+        let mut reader = BedReader::new(temp_file.path()).unwrap();
+        reader.read_chunk(2).unwrap().unwrap();
+        assert_eq!(reader.offset(), 26);
+
+        // The resumed reader continues after the first chunk
+        let mut resumed = BedReader::resume(temp_file.path(), reader.offset()).unwrap();
+        let chunk = resumed.read_chunk(10).unwrap().unwrap();
+        assert_eq!(chunk.len(), 1);
+        assert_eq!(chunk[0].chrom, "chr2");
+        assert_eq!(resumed.offset(), 39);
+
+        assert!(BedReader::resume(temp_file.path(), 40).is_err());
+    }
 }
@@ -0,0 +1,144 @@
+//! BEDPE parser for chromatin loops.
+//!
+//! Each line holds the two anchors of a loop (`chrom1 start1 end1 chrom2
+//! start2 end2`), optionally followed by `name score strand1 strand2` and
+//! further columns, kept as metadata.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::parser::util::{create_buffered_reader, is_header_line};
+use crate::types::Region;
+use crate::warnings;
+
+/// A chromatin loop joining two anchors.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub anchor1: Region,
+    pub anchor2: Region,
+    /// Columns after the anchor coordinates.
+    pub metadata: Vec<String>,
+}
+
+/// Result of parsing a BEDPE file.
+pub struct BedpeData {
+    /// Loops in file order.
+    pub loops: Vec<Loop>,
+    /// Number of metadata columns found.
+    pub num_meta_columns: usize,
+}
+
+/// Parse a BEDPE file of loops.
+///
+/// Supports both plain text and gzip-compressed files. Header, comment and
+/// malformed lines are skipped.
+pub fn parse_bedpe(path: &Path) -> Result<BedpeData> {
+    let file = File::open(path).context("Failed to open BEDPE file")?;
+    let reader = create_buffered_reader(file, path);
+
+    parse_bedpe_reader(reader, &path.display().to_string())
+}
+
+/// Parse BEDPE data from a reader of the file `source`.
+fn parse_bedpe_reader<R: BufRead>(reader: R, source: &str) -> Result<BedpeData> {
+    let mut loops = Vec::new();
+    let mut num_meta_columns = 0;
+
+    for (i, line_result) in reader.lines().enumerate() {
+        let line = line_result.context("Failed to read BEDPE line")?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        // Need at least 6 columns: both anchor coordinates
+        let anchors = if fields.len() < 6 {
+            Err("expected at least 6 tab-separated columns")
+        } else {
+            match (
+                parse_anchor(fields[0], fields[1], fields[2]),
+                parse_anchor(fields[3], fields[4], fields[5]),
+            ) {
+                (Some(anchor1), Some(anchor2)) => Ok((anchor1, anchor2)),
+                _ => Err("invalid anchor coordinates"),
+            }
+        };
+        let (anchor1, anchor2) = match anchors {
+            Ok(anchors) => anchors,
+            Err(_) if is_header_line(line, !loops.is_empty()) => continue,
+            Err(reason) => {
+                warnings::skip_line("BEDPE", source, i + 1, reason)?;
+                continue;
+            }
+        };
+
+        let metadata: Vec<String> = fields.iter().skip(6).map(|s| s.to_string()).collect();
+        num_meta_columns = num_meta_columns.max(metadata.len());
+
+        loops.push(Loop {
+            anchor1,
+            anchor2,
+            metadata,
+        });
+    }
+
+    Ok(BedpeData {
+        loops,
+        num_meta_columns,
+    })
+}
+
+/// Parse the coordinates of an anchor.
+fn parse_anchor(chrom: &str, start: &str, end: &str) -> Option<Region> {
+    let start: i64 = start.parse().ok()?;
+    let end: i64 = end.parse().ok()?;
+    Some(Region::new(chrom.to_string(), start, end, Vec::new()))
+}
+
+/// Get the header names of the BEDPE metadata columns.
+pub fn get_bedpe_headers(num_columns: usize) -> Vec<String> {
+    let known = ["name", "score", "strand1", "strand2"];
+
+    (0..num_columns)
+        .map(|i| match known.get(i) {
+            Some(name) => name.to_string(),
+            None => format!("column{}", i + 7),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_parse_bedpe() {
+        let content = "chrom1\tstart1\tend1\tchrom2\tstart2\tend2\n\
+                       chr1\t100\t200\tchr1\t5000\t5100\tloop1\t10\t.\t.\n\
+                       chr1\t100\t200\n\
+                       chr2\t300\t400\tchr3\t700\t800\n";
+
+        let data = parse_bedpe_reader(BufReader::new(content.as_bytes()), "test.bedpe").unwrap();
+
+        assert_eq!(data.loops.len(), 2);
+        assert_eq!(data.num_meta_columns, 4);
+        assert_eq!(data.loops[0].anchor1.id(), "chr1_100_200");
+        assert_eq!(data.loops[0].anchor2.id(), "chr1_5000_5100");
+        assert_eq!(data.loops[0].metadata[0], "loop1");
+        assert_eq!(data.loops[1].anchor2.chrom, "chr3");
+        assert!(data.loops[1].metadata.is_empty());
+    }
+
+    #[test]
+    fn test_get_bedpe_headers() {
+        assert_eq!(get_bedpe_headers(2), vec!["name", "score"]);
+        assert_eq!(
+            get_bedpe_headers(5),
+            vec!["name", "score", "strand1", "strand2", "column11"]
+        );
+    }
+}
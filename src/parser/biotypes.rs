@@ -0,0 +1,126 @@
+//! Per-biotype rule set parser.
+//!
+//! Parses a TOML table with one section per gene biotype, e.g.:
+//!
+//! ```toml
+//! [lncRNA]
+//! distance = 50      # kb
+//! promoter = 5000    # bp
+//!
+//! [miRNA]
+//! rules = "TSS,PROMOTER"
+//! exclusive = true
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::BiotypeRules;
+
+/// Overrides of one biotype section, as written in the file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BiotypeSection {
+    rules: Option<String>,
+    #[serde(default)]
+    exclusive: bool,
+    /// Maximum distance in kb.
+    distance: Option<i64>,
+    tss: Option<f64>,
+    tts: Option<f64>,
+    promoter: Option<f64>,
+}
+
+/// Parse a TOML file of per-biotype rule sets.
+///
+/// Sections are returned in biotype name order.
+pub fn parse_biotype_rules(path: &Path) -> Result<Vec<BiotypeRules>> {
+    let content = fs::read_to_string(path).context("Failed to open biotype rules file")?;
+    parse_biotype_rules_str(&content)
+}
+
+/// Parse per-biotype rule sets from a TOML string.
+fn parse_biotype_rules_str(content: &str) -> Result<Vec<BiotypeRules>> {
+    let sections: BTreeMap<String, BiotypeSection> =
+        toml::from_str(content).context("Failed to parse biotype rules")?;
+
+    let mut sets = Vec::with_capacity(sections.len());
+    for (biotype, section) in sections {
+        if section.distance.is_some_and(|d| d < 0) {
+            bail!(
+                "The distance of biotype {} cannot be lower than 0 kb.",
+                biotype
+            );
+        }
+        for (name, value) in [
+            ("TSS", section.tss),
+            ("TTS", section.tts),
+            ("promoter", section.promoter),
+        ] {
+            if value.is_some_and(|v| v < 0.0) {
+                bail!(
+                    "The {} distance of biotype {} cannot be lower than 0 bps.",
+                    name,
+                    biotype
+                );
+            }
+        }
+        if section.exclusive && section.rules.is_none() {
+            bail!("Biotype {} is exclusive but has no rules.", biotype);
+        }
+
+        sets.push(BiotypeRules {
+            biotype,
+            rules: section.rules,
+            exclusive: section.exclusive,
+            distance: section.distance.map(|kb| kb * 1000),
+            tss: section.tss,
+            tts: section.tts,
+            promoter: section.promoter,
+        });
+    }
+
+    Ok(sets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_biotype_rules() {
+        let sets = parse_biotype_rules_str(
+            r#"
+[miRNA]
+rules = "TSS,PROMOTER"
+exclusive = true
+
+[lncRNA]
+distance = 50
+promoter = 5000
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].biotype, "lncRNA");
+        assert_eq!(sets[0].distance, Some(50_000));
+        assert_eq!(sets[0].promoter, Some(5000.0));
+        assert_eq!(sets[0].rules, None);
+        assert_eq!(sets[1].biotype, "miRNA");
+        assert_eq!(sets[1].rules.as_deref(), Some("TSS,PROMOTER"));
+        assert!(sets[1].exclusive);
+    }
+
+    #[test]
+    fn test_parse_biotype_rules_invalid() {
+        assert!(parse_biotype_rules_str("[lncRNA]\nwindow = 5\n").is_err());
+        assert!(parse_biotype_rules_str("[lncRNA]\ndistance = -1\n").is_err());
+        assert!(parse_biotype_rules_str("[lncRNA]\ntss = -200.0\n").is_err());
+        assert!(parse_biotype_rules_str("[lncRNA]\nexclusive = true\n").is_err());
+        assert!(parse_biotype_rules_str("lncRNA = 5\n").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Chromosome sizes parser.
+//!
+//! Sizes are read from a UCSC-style `chrom.sizes` file (or a FASTA index),
+//! with one contig per line: `chrom length`. Extra columns are ignored.
+
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::parser::util::create_buffered_reader;
+
+/// Parse a chromosome sizes file into the length of each contig.
+///
+/// Supports both plain text and gzip-compressed files. Header, comment and
+/// malformed lines are skipped.
+pub fn parse_chrom_sizes(path: &Path) -> Result<AHashMap<String, i64>> {
+    let file = File::open(path).context("Failed to open chromosome sizes file")?;
+    let reader = create_buffered_reader(file, path);
+
+    parse_chrom_sizes_reader(reader)
+}
+
+/// Parse chromosome sizes from a reader.
+fn parse_chrom_sizes_reader<R: BufRead>(reader: R) -> Result<AHashMap<String, i64>> {
+    let mut sizes = AHashMap::new();
+
+    for line_result in reader.lines() {
+        let line = line_result.context("Failed to read chromosome sizes line")?;
+        let mut fields = line.split_whitespace();
+
+        let (Some(chrom), Some(length)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Ok(length) = length.parse::<i64>() else {
+            continue;
+        };
+        if length > 0 {
+            sizes.insert(chrom.to_string(), length);
+        }
+    }
+
+    Ok(sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_parse_chrom_sizes() {
+        let content = "# UCSC sizes\n\
+                       chr1\t248956422\n\
+                       chrM\t16569\t0\t60\t61\n\
+                       chrBad\tNA\n";
+
+        let sizes = parse_chrom_sizes_reader(BufReader::new(content.as_bytes())).unwrap();
+
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes["chr1"], 248956422);
+        assert_eq!(sizes["chrM"], 16569);
+    }
+}
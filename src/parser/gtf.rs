@@ -3,14 +3,15 @@
 //! Parses GTF (Gene Transfer Format) annotation files to build a hierarchical
 //! structure of genes, transcripts, and exons organized by chromosome.
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use anyhow::{Context, Result};
 use std::fs::File;
-use std::io::BufRead;
+use std::io::{BufRead, Write};
 use std::path::Path;
 
 use crate::parser::util::create_buffered_reader;
-use crate::types::{Exon, Gene, Strand, Transcript};
+use crate::types::{Exon, Gene, Region, Strand, Transcript};
+use crate::warnings;
 
 /// Result of parsing a GTF file.
 #[derive(Clone)]
@@ -21,6 +22,54 @@ pub struct GtfData {
     pub max_lengths: AHashMap<String, i64>,
 }
 
+impl GtfData {
+    /// Treat a chromosome as circular, of the given length.
+    ///
+    /// Every gene is mirrored one contig length before and after the origin,
+    /// so regions near either end see the genes across it at their wrapped
+    /// distance. Genes must be sorted by start, which is preserved.
+    pub fn wrap_circular(&mut self, chrom: &str, length: i64) {
+        let Some(genes) = self.genes_by_chrom.get_mut(chrom) else {
+            return;
+        };
+
+        let before = genes.iter().map(|g| g.shifted(-length));
+        let after = genes.iter().map(|g| g.shifted(length));
+        let mut wrapped: Vec<Gene> = before.chain(genes.iter().cloned()).chain(after).collect();
+        wrapped.sort_by(|a, b| a.start.cmp(&b.start).then(a.gene_id.cmp(&b.gene_id)));
+        *genes = wrapped;
+    }
+
+    /// IDs of the genes within `distance` bp of any of the regions.
+    pub fn genes_near(
+        &self,
+        regions_by_chrom: &AHashMap<String, Vec<Region>>,
+        distance: i64,
+    ) -> AHashSet<String> {
+        let mut near = AHashSet::new();
+        for (chrom, regions) in regions_by_chrom {
+            let Some(genes) = self.genes_by_chrom.get(chrom) else {
+                continue;
+            };
+            // Regions by start, with the furthest end up to each of them
+            let mut spans: Vec<(i64, i64)> = regions.iter().map(|r| (r.start, r.end)).collect();
+            spans.sort_unstable();
+            let mut max_end = i64::MIN;
+            for span in &mut spans {
+                max_end = max_end.max(span.1);
+                span.1 = max_end;
+            }
+            for gene in genes {
+                let before = spans.partition_point(|&(start, _)| start <= gene.end + distance);
+                if before > 0 && spans[before - 1].1 >= gene.start - distance {
+                    near.insert(gene.gene_id.clone());
+                }
+            }
+        }
+        near
+    }
+}
+
 /// Parse a GTF file and return organized gene data.
 ///
 /// Supports both plain text and gzip-compressed GTF files.
@@ -28,12 +77,43 @@ pub fn parse_gtf(path: &Path, gene_id_tag: &str, transcript_id_tag: &str) -> Res
     let file = File::open(path).context("Failed to open GTF file")?;
     let reader = create_buffered_reader(file, path);
 
-    parse_gtf_reader(reader, gene_id_tag, transcript_id_tag)
+    parse_gtf_reader(
+        reader,
+        &path.display().to_string(),
+        gene_id_tag,
+        transcript_id_tag,
+    )
 }
 
-/// Parse GTF data from a reader.
+/// Copy the lines of a GTF whose gene is one of `genes`, and its comments,
+/// returning the number of feature lines copied.
+pub fn filter_gtf<R: BufRead, W: Write>(
+    reader: R,
+    writer: &mut W,
+    gene_id_tag: &str,
+    genes: &AHashSet<String>,
+) -> Result<usize> {
+    let mut copied = 0;
+    for line_result in reader.lines() {
+        let line = line_result.context("Failed to read GTF line")?;
+        let keep = line.starts_with('#')
+            || line
+                .split('\t')
+                .nth(8)
+                .and_then(|attributes| extract_attribute(attributes, gene_id_tag))
+                .is_some_and(|gene_id| genes.contains(&gene_id));
+        if keep {
+            writeln!(writer, "{}", line)?;
+            copied += usize::from(!line.starts_with('#'));
+        }
+    }
+    Ok(copied)
+}
+
+/// Parse GTF data from a reader of the file `source`.
 fn parse_gtf_reader<R: BufRead>(
     reader: R,
+    source: &str,
     gene_id_tag: &str,
     transcript_id_tag: &str,
 ) -> Result<GtfData> {
@@ -45,11 +125,14 @@ fn parse_gtf_reader<R: BufRead>(
     // Genes organized by chromosome
     let mut genes_by_chrom: AHashMap<String, Vec<String>> = AHashMap::new(); // chrom -> gene_ids (in order added)
 
+    // CDS features per transcript, applied once all transcripts are known
+    let mut cds_features: AHashMap<String, Vec<(i64, i64)>> = AHashMap::new();
+
     // Flags to track if transcript and gene entries exist in GTF
     let mut gene_flag = false;
     let mut trans_flag = false;
 
-    for line_result in reader.lines() {
+    for (i, line_result) in reader.lines().enumerate() {
         let line = line_result.context("Failed to read GTF line")?;
 
         // Skip empty lines and comments
@@ -59,6 +142,7 @@ fn parse_gtf_reader<R: BufRead>(
 
         let fields: Vec<&str> = line.split('\t').collect();
         if fields.len() < 9 {
+            warnings::skip_line("GTF", source, i + 1, "expected 9 tab-separated columns")?;
             continue;
         }
 
@@ -75,7 +159,14 @@ fn parse_gtf_reader<R: BufRead>(
 
         let strand = match strand_str.parse::<Strand>() {
             Ok(s) => s,
-            Err(_) => continue, // Skip entries without valid strand
+            // Skip entries without valid strand, only worth a warning for
+            // the features that make up the genes
+            Err(_) => {
+                if matches!(feature_type, "exon" | "transcript" | "gene" | "CDS") {
+                    warnings::skip_line("GTF", source, i + 1, "invalid strand")?;
+                }
+                continue;
+            }
         };
 
         match feature_type {
@@ -87,7 +178,9 @@ fn parse_gtf_reader<R: BufRead>(
 
                 // Create or get gene
                 if !all_genes.contains_key(&gene_id) {
-                    all_genes.insert(gene_id.clone(), Gene::new(gene_id.clone(), strand));
+                    let mut gene = Gene::new(gene_id.clone(), strand);
+                    gene.biotype = extract_biotype(attributes);
+                    all_genes.insert(gene_id.clone(), gene);
                     genes_by_chrom
                         .entry(chrom.to_string())
                         .or_default()
@@ -99,7 +192,10 @@ fn parse_gtf_reader<R: BufRead>(
                 if is_new_transcript {
                     let gene = all_genes.get_mut(&gene_id).unwrap();
                     let transcript_idx = gene.transcripts.len();
-                    gene.add_transcript(Transcript::new(transcript_id.clone()));
+                    let mut transcript = Transcript::new(transcript_id.clone());
+                    transcript.tsl = extract_tsl(attributes);
+                    transcript.biotype = extract_transcript_biotype(attributes);
+                    gene.add_transcript(transcript);
                     all_transcripts.insert(transcript_id.clone(), transcript_idx);
                     gene_to_transcripts
                         .entry(gene_id.clone())
@@ -123,7 +219,9 @@ fn parse_gtf_reader<R: BufRead>(
 
                 // Create or get gene
                 if !all_genes.contains_key(&gene_id) {
-                    all_genes.insert(gene_id.clone(), Gene::new(gene_id.clone(), strand));
+                    let mut gene = Gene::new(gene_id.clone(), strand);
+                    gene.biotype = extract_biotype(attributes);
+                    all_genes.insert(gene_id.clone(), gene);
                     genes_by_chrom
                         .entry(chrom.to_string())
                         .or_default()
@@ -143,10 +241,13 @@ fn parse_gtf_reader<R: BufRead>(
                         .push(transcript_id.clone());
                 }
 
-                // Set transcript boundaries
+                // Set transcript boundaries, support level and biotype
                 let transcript_idx = all_transcripts[&transcript_id];
                 let gene = all_genes.get_mut(&gene_id).unwrap();
-                gene.transcripts[transcript_idx].set_length(start, end);
+                let transcript = &mut gene.transcripts[transcript_idx];
+                transcript.set_length(start, end);
+                transcript.tsl = extract_tsl(attributes);
+                transcript.biotype = extract_transcript_biotype(attributes);
             }
             "gene" => {
                 gene_flag = true;
@@ -156,7 +257,9 @@ fn parse_gtf_reader<R: BufRead>(
 
                 // Create or get gene
                 if !all_genes.contains_key(&gene_id) {
-                    all_genes.insert(gene_id.clone(), Gene::new(gene_id.clone(), strand));
+                    let mut gene = Gene::new(gene_id.clone(), strand);
+                    gene.biotype = extract_biotype(attributes);
+                    all_genes.insert(gene_id.clone(), gene);
                     genes_by_chrom
                         .entry(chrom.to_string())
                         .or_default()
@@ -166,6 +269,14 @@ fn parse_gtf_reader<R: BufRead>(
                 // Set gene boundaries
                 all_genes.get_mut(&gene_id).unwrap().set_length(start, end);
             }
+            "CDS" => {
+                if let Some(transcript_id) = extract_attribute(attributes, transcript_id_tag) {
+                    cds_features
+                        .entry(transcript_id)
+                        .or_default()
+                        .push((start, end));
+                }
+            }
             _ => {
                 // Skip other feature types
             }
@@ -176,6 +287,13 @@ fn parse_gtf_reader<R: BufRead>(
     for gene in all_genes.values_mut() {
         let strand = gene.strand;
         for transcript in &mut gene.transcripts {
+            // Attach the coding span
+            if let Some(features) = cds_features.get(&transcript.transcript_id) {
+                for &(start, end) in features {
+                    transcript.add_cds(start, end);
+                }
+            }
+
             // Renumber exons based on strand
             transcript.renumber_exons(strand);
 
@@ -215,6 +333,30 @@ fn parse_gtf_reader<R: BufRead>(
     })
 }
 
+/// Extract the gene biotype (GENCODE `gene_type` or Ensembl `gene_biotype`).
+fn extract_biotype(attributes: &str) -> Option<String> {
+    extract_attribute(attributes, "gene_type")
+        .or_else(|| extract_attribute(attributes, "gene_biotype"))
+}
+
+/// Extract the transcript biotype (GENCODE `transcript_type` or Ensembl
+/// `transcript_biotype`).
+fn extract_transcript_biotype(attributes: &str) -> Option<String> {
+    extract_attribute(attributes, "transcript_type")
+        .or_else(|| extract_attribute(attributes, "transcript_biotype"))
+}
+
+/// Extract the GENCODE transcript support level (`NA` gives None).
+///
+/// Values may carry a note, e.g. `1 (assigned to previous version 5)`.
+fn extract_tsl(attributes: &str) -> Option<u8> {
+    extract_attribute(attributes, "transcript_support_level")?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
 /// Extract an attribute value from the GTF attributes string.
 ///
 /// GTF attributes are in the format: key "value"; key "value"; ...
@@ -260,6 +402,46 @@ mod tests {
         assert_eq!(extract_attribute(attrs, "nonexistent"), None);
     }
 
+    #[test]
+    fn test_extract_biotype() {
+        assert_eq!(
+            extract_biotype(r#"gene_id "G1"; gene_type "lncRNA";"#),
+            Some("lncRNA".to_string())
+        );
+        assert_eq!(
+            extract_biotype(r#"gene_id "G1"; gene_biotype "miRNA";"#),
+            Some("miRNA".to_string())
+        );
+        assert_eq!(extract_biotype(r#"gene_id "G1";"#), None);
+        assert_eq!(
+            extract_transcript_biotype(
+                r#"gene_type "protein_coding"; transcript_type "retained_intron";"#
+            ),
+            Some("retained_intron".to_string())
+        );
+        assert_eq!(
+            extract_transcript_biotype(r#"transcript_biotype "nonsense_mediated_decay";"#),
+            Some("nonsense_mediated_decay".to_string())
+        );
+        assert_eq!(extract_transcript_biotype(r#"gene_type "lncRNA";"#), None);
+    }
+
+    #[test]
+    fn test_extract_tsl() {
+        assert_eq!(
+            extract_tsl(r#"transcript_id "T1"; transcript_support_level "2";"#),
+            Some(2)
+        );
+        assert_eq!(
+            extract_tsl(
+                r#"transcript_support_level "1 (assigned to previous version 5)"; tag "basic";"#
+            ),
+            Some(1)
+        );
+        assert_eq!(extract_tsl(r#"transcript_support_level "NA";"#), None);
+        assert_eq!(extract_tsl(r#"transcript_id "T1";"#), None);
+    }
+
     #[test]
     fn test_parse_gtf_reader() {
         let gtf_content = r#"##description: test
@@ -270,7 +452,7 @@ chr1	TEST	exon	1500	2000	.	+	.	gene_id "G1"; transcript_id "T1"; exon_number 2;
 "#;
 
         let reader = BufReader::new(gtf_content.as_bytes());
-        let result = parse_gtf_reader(reader, "gene_id", "transcript_id").unwrap();
+        let result = parse_gtf_reader(reader, "test.gtf", "gene_id", "transcript_id").unwrap();
 
         assert!(result.genes_by_chrom.contains_key("chr1"));
         let genes = &result.genes_by_chrom["chr1"];
@@ -292,6 +474,23 @@ chr1	TEST	exon	1500	2000	.	+	.	gene_id "G1"; transcript_id "T1"; exon_number 2;
         assert_eq!(transcript.exons[1].exon_number, Some("2".to_string()));
     }
 
+    #[test]
+    fn test_parse_gtf_cds_span() {
+        let gtf_content = r#"chr1	TEST	exon	1000	1200	.	+	.	gene_id "G1"; transcript_id "T1";
+chr1	TEST	CDS	1100	1200	.	+	0	gene_id "G1"; transcript_id "T1";
+chr1	TEST	exon	1500	2000	.	+	.	gene_id "G1"; transcript_id "T1";
+chr1	TEST	CDS	1500	1800	.	+	2	gene_id "G1"; transcript_id "T1";
+chr1	TEST	exon	3000	3500	.	+	.	gene_id "G1"; transcript_id "T2";
+"#;
+
+        let reader = BufReader::new(gtf_content.as_bytes());
+        let result = parse_gtf_reader(reader, "test.gtf", "gene_id", "transcript_id").unwrap();
+
+        let gene = &result.genes_by_chrom["chr1"][0];
+        assert_eq!(gene.transcripts[0].cds, Some((1100, 1800)));
+        assert_eq!(gene.transcripts[1].cds, None);
+    }
+
     #[test]
     fn test_parse_gtf_negative_strand() {
         let gtf_content = r#"chr1	TEST	exon	1000	1200	.	-	.	gene_id "G1"; transcript_id "T1";
@@ -299,7 +498,7 @@ chr1	TEST	exon	1500	2000	.	-	.	gene_id "G1"; transcript_id "T1";
 "#;
 
         let reader = BufReader::new(gtf_content.as_bytes());
-        let result = parse_gtf_reader(reader, "gene_id", "transcript_id").unwrap();
+        let result = parse_gtf_reader(reader, "test.gtf", "gene_id", "transcript_id").unwrap();
 
         let gene = &result.genes_by_chrom["chr1"][0];
         let transcript = &gene.transcripts[0];
@@ -310,4 +509,89 @@ chr1	TEST	exon	1500	2000	.	-	.	gene_id "G1"; transcript_id "T1";
         assert_eq!(transcript.exons[1].start, 1500);
         assert_eq!(transcript.exons[1].exon_number, Some("1".to_string()));
     }
+
+    #[test]
+    fn test_wrap_circular() {
+        let gtf_content = r#"chrM	TEST	exon	100	300	.	+	.	gene_id "G1"; transcript_id "T1";
+chrM	TEST	exon	15000	16000	.	-	.	gene_id "G2"; transcript_id "T2";
+chr1	TEST	exon	100	300	.	+	.	gene_id "G3"; transcript_id "T3";
+"#;
+
+        let reader = BufReader::new(gtf_content.as_bytes());
+        let mut result = parse_gtf_reader(reader, "test.gtf", "gene_id", "transcript_id").unwrap();
+        result.wrap_circular("chrM", 16569);
+        result.wrap_circular("chrUn", 1000);
+
+        let genes: Vec<(&str, i64, i64)> = result.genes_by_chrom["chrM"]
+            .iter()
+            .map(|g| (g.gene_id.as_str(), g.start, g.end))
+            .collect();
+        assert_eq!(
+            genes,
+            vec![
+                ("G1", 100 - 16569, 300 - 16569),
+                ("G2", 15000 - 16569, 16000 - 16569),
+                ("G1", 100, 300),
+                ("G2", 15000, 16000),
+                ("G1", 100 + 16569, 300 + 16569),
+                ("G2", 15000 + 16569, 16000 + 16569),
+            ]
+        );
+        let exon = &result.genes_by_chrom["chrM"][1].transcripts[0].exons[0];
+        assert_eq!((exon.start, exon.end), (15000 - 16569, 16000 - 16569));
+        assert_eq!(result.genes_by_chrom["chr1"].len(), 1);
+    }
+
+    #[test]
+    fn test_subset_near_regions() {
+        let gtf_content = r#"#!genome-build test
+chr1	TEST	exon	1000	1200	.	+	.	gene_id "G1"; transcript_id "T1";
+chr1	TEST	exon	1500	2000	.	+	.	gene_id "G1"; transcript_id "T1";
+chr1	TEST	exon	10000	12000	.	-	.	gene_id "G2"; transcript_id "T2";
+chr1	TEST	exon	50000	51000	.	+	.	gene_id "G3"; transcript_id "T3";
+chr2	TEST	exon	1000	2000	.	+	.	gene_id "G4"; transcript_id "T4";
+"#;
+        let gtf_data = parse_gtf_reader(
+            BufReader::new(gtf_content.as_bytes()),
+            "test.gtf",
+            "gene_id",
+            "transcript_id",
+        )
+        .unwrap();
+
+        // One region inside G1 that reaches towards G2, one far from any gene
+        let mut regions_by_chrom = AHashMap::new();
+        regions_by_chrom.insert(
+            "chr1".to_string(),
+            vec![
+                Region::new("chr1".to_string(), 30000, 30100, vec![]),
+                Region::new("chr1".to_string(), 1500, 8000, vec![]),
+            ],
+        );
+        regions_by_chrom.insert(
+            "chr3".to_string(),
+            vec![Region::new("chr3".to_string(), 1000, 2000, vec![])],
+        );
+        let near = |distance| {
+            let mut genes: Vec<String> = gtf_data
+                .genes_near(&regions_by_chrom, distance)
+                .into_iter()
+                .collect();
+            genes.sort();
+            genes
+        };
+        assert_eq!(near(0), vec!["G1"]);
+        assert_eq!(near(2000), vec!["G1", "G2"]);
+        assert_eq!(near(20000), vec!["G1", "G2", "G3"]);
+
+        let genes = gtf_data.genes_near(&regions_by_chrom, 2000);
+        let mut subset = Vec::new();
+        let copied = filter_gtf(gtf_content.as_bytes(), &mut subset, "gene_id", &genes).unwrap();
+        assert_eq!(copied, 3);
+        let subset = String::from_utf8(subset).unwrap();
+        assert_eq!(
+            subset,
+            gtf_content.lines().take(4).collect::<Vec<_>>().join("\n") + "\n"
+        );
+    }
 }
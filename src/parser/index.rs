@@ -0,0 +1,155 @@
+//! Pre-parsed annotation index.
+//!
+//! `rgmatch index` parses a GTF once and stores its genes, transcripts and
+//! exons as JSON, so later runs load the annotation without parsing the GTF
+//! attributes again. Indexes are recognized by their content wherever a GTF
+//! is expected, and may be gzip-compressed like GTF files.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+
+use ahash::AHashMap;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::parser::gtf::{parse_gtf, GtfData};
+use crate::parser::util::create_buffered_reader;
+use crate::types::Gene;
+
+/// Version of the index layout, bumped on incompatible changes.
+const INDEX_VERSION: u32 = 1;
+
+/// Start of every index file.
+const INDEX_MAGIC: &[u8] = b"{\"rgmatch_index\":";
+
+/// On-disk layout of an index.
+#[derive(Serialize, Deserialize)]
+struct AnnotationIndex {
+    /// Layout version; first so that indexes can be told from GTF files.
+    rgmatch_index: u32,
+    gene_id_tag: String,
+    transcript_id_tag: String,
+    genes_by_chrom: BTreeMap<String, Vec<Gene>>,
+}
+
+/// Write the parsed annotation as an index, recording the tags its gene and
+/// transcript IDs were read from.
+pub fn write_index<W: Write>(
+    gtf_data: &GtfData,
+    gene_id_tag: &str,
+    transcript_id_tag: &str,
+    writer: &mut W,
+) -> Result<()> {
+    let index = AnnotationIndex {
+        rgmatch_index: INDEX_VERSION,
+        gene_id_tag: gene_id_tag.to_string(),
+        transcript_id_tag: transcript_id_tag.to_string(),
+        genes_by_chrom: gtf_data
+            .genes_by_chrom
+            .iter()
+            .map(|(chrom, genes)| (chrom.clone(), genes.clone()))
+            .collect(),
+    };
+    serde_json::to_writer(&mut *writer, &index)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Whether a file is an annotation index rather than a GTF.
+pub fn is_index(path: &Path) -> Result<bool> {
+    let file = File::open(path).context("Failed to open annotation file")?;
+    let mut reader = create_buffered_reader(file, path);
+    let start = reader
+        .fill_buf()
+        .context("Failed to read annotation file")?;
+    Ok(start.starts_with(INDEX_MAGIC))
+}
+
+/// Load an annotation index, checking that it was built with the given
+/// gene and transcript ID tags.
+pub fn read_index(path: &Path, gene_id_tag: &str, transcript_id_tag: &str) -> Result<GtfData> {
+    let file = File::open(path).context("Failed to open annotation index")?;
+    let mut reader = create_buffered_reader(file, path);
+    let mut contents = Vec::new();
+    reader
+        .read_to_end(&mut contents)
+        .context("Failed to read annotation index")?;
+    let index: AnnotationIndex =
+        serde_json::from_slice(&contents).context("Failed to parse annotation index")?;
+
+    if index.rgmatch_index != INDEX_VERSION {
+        bail!(
+            "Annotation index {} has version {}, expected {}; rebuild it with rgmatch index",
+            path.display(),
+            index.rgmatch_index,
+            INDEX_VERSION
+        );
+    }
+    if index.gene_id_tag != gene_id_tag || index.transcript_id_tag != transcript_id_tag {
+        bail!(
+            "Annotation index {} was built with the gene tag {} and transcript tag {}",
+            path.display(),
+            index.gene_id_tag,
+            index.transcript_id_tag
+        );
+    }
+
+    let mut genes_by_chrom = AHashMap::new();
+    let mut max_lengths = AHashMap::new();
+    for (chrom, genes) in index.genes_by_chrom {
+        let max_len = genes.iter().map(|g| g.end - g.start).max().unwrap_or(0);
+        max_lengths.insert(chrom.clone(), max_len);
+        genes_by_chrom.insert(chrom, genes);
+    }
+    Ok(GtfData {
+        genes_by_chrom,
+        max_lengths,
+    })
+}
+
+/// Load an annotation from a GTF file or an index.
+pub fn parse_annotation(
+    path: &Path,
+    gene_id_tag: &str,
+    transcript_id_tag: &str,
+) -> Result<GtfData> {
+    if is_index(path)? {
+        read_index(path, gene_id_tag, transcript_id_tag)
+    } else {
+        parse_gtf(path, gene_id_tag, transcript_id_tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotation_index() {
+        let gtf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/subset_genome.gtf");
+        let gtf_data = parse_gtf(&gtf, "gene_id", "transcript_id").unwrap();
+        assert!(!is_index(&gtf).unwrap());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("subset.rgidx");
+        let mut file = File::create(&path).unwrap();
+        write_index(&gtf_data, "gene_id", "transcript_id", &mut file).unwrap();
+        drop(file);
+        assert!(is_index(&path).unwrap());
+
+        let loaded = parse_annotation(&path, "gene_id", "transcript_id").unwrap();
+        assert_eq!(loaded.max_lengths, gtf_data.max_lengths);
+        for (chrom, genes) in &gtf_data.genes_by_chrom {
+            let loaded = &loaded.genes_by_chrom[chrom];
+            assert_eq!(loaded.len(), genes.len());
+            for (a, b) in loaded.iter().zip(genes) {
+                assert_eq!(format!("{:?}", a), format!("{:?}", b));
+            }
+        }
+
+        // Indexes keep the ID tags they were built with
+        assert!(read_index(&path, "gene_name", "transcript_id").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Enhancer-gene links parser.
+//!
+//! Links are read from a tab-separated file with one enhancer per line:
+//! `chrom start end target_gene`, e.g. exported from the ABC model or EpiMap.
+//! Extra columns (scores, ...) are ignored.
+
+use ahash::AHashMap;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::parser::util::{create_buffered_reader, is_header_line};
+use crate::warnings;
+
+/// An enhancer linked to a target gene.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnhancerLink {
+    pub start: i64,
+    pub end: i64,
+    pub gene: String,
+}
+
+/// Parse a links file into the links of each chromosome, in file order.
+///
+/// Supports both plain text and gzip-compressed files. Header and malformed
+/// lines are skipped.
+pub fn parse_links(path: &Path) -> Result<AHashMap<String, Vec<EnhancerLink>>> {
+    let file = File::open(path).context("Failed to open links file")?;
+    let reader = create_buffered_reader(file, path);
+
+    parse_links_reader(reader, &path.display().to_string())
+}
+
+/// Parse links from a reader of the file `source`.
+fn parse_links_reader<R: BufRead>(
+    reader: R,
+    source: &str,
+) -> Result<AHashMap<String, Vec<EnhancerLink>>> {
+    let mut links: AHashMap<String, Vec<EnhancerLink>> = AHashMap::new();
+
+    for (i, line_result) in reader.lines().enumerate() {
+        let line = line_result.context("Failed to read links line")?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        // Need at least 4 columns: chrom, start, end, target gene
+        let coordinates = if fields.len() < 4 || fields[3].is_empty() {
+            Err("expected 4 tab-separated columns: chrom, start, end and target gene")
+        } else {
+            match (fields[1].parse(), fields[2].parse()) {
+                (Ok(start), Ok(end)) => Ok((start, end)),
+                _ => Err("invalid start or end coordinate"),
+            }
+        };
+        let (start, end) = match coordinates {
+            Ok(coordinates) => coordinates,
+            // Skip header lines
+            Err(_) if is_header_line(line, !links.is_empty()) => continue,
+            Err(reason) => {
+                warnings::skip_line("links", source, i + 1, reason)?;
+                continue;
+            }
+        };
+
+        links
+            .entry(fields[0].to_string())
+            .or_default()
+            .push(EnhancerLink {
+                start,
+                end,
+                gene: fields[3].to_string(),
+            });
+    }
+
+    Ok(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_parse_links() {
+        let content = "chr\tstart\tend\tTargetGene\tABC.Score\n\
+                       chr1\t1000\t1500\tGENE1\t0.05\n\
+                       chr1\t1000\t1500\tGENE2\n\
+                       chr2\t300\t400\n\
+                       chr2\t500\t600\tGENE3\n";
+
+        let links = parse_links_reader(BufReader::new(content.as_bytes()), "test.tsv").unwrap();
+
+        assert_eq!(links["chr1"].len(), 2);
+        assert_eq!(
+            links["chr1"][0],
+            EnhancerLink {
+                start: 1000,
+                end: 1500,
+                gene: "GENE1".to_string()
+            }
+        );
+        assert_eq!(links["chr1"][1].gene, "GENE2");
+        assert_eq!(links["chr2"].len(), 1);
+    }
+}
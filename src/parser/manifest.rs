@@ -0,0 +1,207 @@
+//! Sample manifest parser.
+//!
+//! A manifest lists the samples of an experiment, one per line:
+//! `sample<TAB>bed[<TAB>option=value...]`. The optional columns override
+//! options of the run for that sample, by their long name. Blank lines,
+//! `#` comments and a `sample<TAB>bed` header are skipped.
+//!
+//! The samples can also be the BED files matching a glob pattern, each named
+//! after its file.
+
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use crate::parser::util::create_buffered_reader;
+
+/// A sample of a manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestSample {
+    /// Sample name, used to name its output files.
+    pub name: String,
+    pub bed: PathBuf,
+    /// Options set for this sample only, as (long name, value).
+    pub overrides: Vec<(String, String)>,
+}
+
+/// Parse a manifest of samples.
+///
+/// Supports both plain text and gzip-compressed files. Malformed lines,
+/// duplicate sample names and names that cannot name a file are errors.
+pub fn parse_manifest(path: &Path) -> Result<Vec<ManifestSample>> {
+    let file = File::open(path).context("Failed to open manifest file")?;
+    let reader = create_buffered_reader(file, path);
+
+    parse_manifest_reader(reader)
+}
+
+/// Parse a manifest from a reader.
+fn parse_manifest_reader<R: BufRead>(reader: R) -> Result<Vec<ManifestSample>> {
+    let mut samples: Vec<ManifestSample> = Vec::new();
+
+    for (i, line_result) in reader.lines().enumerate() {
+        let line = line_result.context("Failed to read manifest line")?;
+        let line_number = i + 1;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split('\t').map(str::trim);
+        let (Some(name), Some(bed)) = (fields.next(), fields.next()) else {
+            bail!(
+                "Manifest line {}: expected a sample name and a BED file",
+                line_number
+            );
+        };
+        if samples.is_empty()
+            && name.eq_ignore_ascii_case("sample")
+            && bed.eq_ignore_ascii_case("bed")
+        {
+            continue;
+        }
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.contains(['/', '\\'])
+            || bed.is_empty()
+        {
+            bail!(
+                "Manifest line {}: invalid sample name or BED file",
+                line_number
+            );
+        }
+        if samples.iter().any(|sample| sample.name == name) {
+            bail!("Manifest line {}: duplicate sample {}", line_number, name);
+        }
+        let overrides = fields
+            .filter(|field| !field.is_empty())
+            .map(|field| match field.split_once('=') {
+                Some((option, value)) if !option.trim().is_empty() => {
+                    Ok((option.trim().to_string(), value.trim().to_string()))
+                }
+                _ => bail!(
+                    "Manifest line {}: overrides should be given as option=value: {}",
+                    line_number,
+                    field
+                ),
+            })
+            .collect::<Result<_>>()?;
+        samples.push(ManifestSample {
+            name: name.to_string(),
+            bed: PathBuf::from(bed),
+            overrides,
+        });
+    }
+
+    if samples.is_empty() {
+        bail!("The manifest lists no samples");
+    }
+    Ok(samples)
+}
+
+/// Whether a path is a glob pattern (with `*`, `?` or `[`) rather than a file.
+pub fn is_pattern(path: &Path) -> bool {
+    !path.exists() && path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// The samples of the files matching a glob pattern, in alphabetical order
+/// and named after their file without its extension (and `.gz`), e.g.
+/// `ctrl` for `peaks/ctrl.narrowPeak.gz`.
+pub fn glob_samples(pattern: &Path) -> Result<Vec<ManifestSample>> {
+    let text = pattern.to_string_lossy();
+    let paths = glob::glob(&text).with_context(|| format!("Invalid BED file pattern {}", text))?;
+    let mut samples: Vec<ManifestSample> = Vec::new();
+    for path in paths {
+        let bed = path.with_context(|| format!("Failed to list the files matching {}", text))?;
+        if !bed.is_file() {
+            continue;
+        }
+        let name = match bed.extension() {
+            Some(ext) if ext == "gz" => bed.with_extension(""),
+            _ => bed.clone(),
+        };
+        let name = name
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(other) = samples.iter().find(|sample| sample.name == name) {
+            bail!(
+                "{} and {} would both be sample {}",
+                other.bed.display(),
+                bed.display(),
+                name
+            );
+        }
+        samples.push(ManifestSample {
+            name,
+            bed,
+            overrides: Vec::new(),
+        });
+    }
+    if samples.is_empty() {
+        bail!("No BED file matches {}", text);
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn parse(content: &str) -> Result<Vec<ManifestSample>> {
+        parse_manifest_reader(BufReader::new(content.as_bytes()))
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let content = "sample\tbed\n\
+                       # controls\n\
+                       ctrl\tctrl.bed\n\
+                       \n\
+                       treated\tdata/treated.bed.gz\tdistance=20\treport = gene\n";
+
+        let samples = parse(content).unwrap();
+
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].name, "ctrl");
+        assert_eq!(samples[0].bed, PathBuf::from("ctrl.bed"));
+        assert!(samples[0].overrides.is_empty());
+        assert_eq!(samples[1].bed, PathBuf::from("data/treated.bed.gz"));
+        assert_eq!(
+            samples[1].overrides,
+            vec![
+                ("distance".to_string(), "20".to_string()),
+                ("report".to_string(), "gene".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_manifest_errors() {
+        assert!(parse("ctrl\n").is_err());
+        assert!(parse("a\ta.bed\na\tb.bed\n").is_err());
+        assert!(parse("../a\ta.bed\n").is_err());
+        assert!(parse("a\ta.bed\tdistance\n").is_err());
+        assert!(parse("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn test_glob_samples() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["b.narrowPeak", "a.narrowPeak.gz", "c.bed"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        let pattern = dir.path().join("*.narrowPeak*");
+        assert!(is_pattern(&pattern));
+        let samples = glob_samples(&pattern).unwrap();
+        let names: Vec<&str> = samples.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(samples[0].bed, dir.path().join("a.narrowPeak.gz"));
+
+        assert!(!is_pattern(&dir.path().join("c.bed")));
+        assert!(glob_samples(&dir.path().join("*.bam")).is_err());
+        std::fs::write(dir.path().join("a.bed"), "").unwrap();
+        assert!(glob_samples(&dir.path().join("a.*")).is_err());
+    }
+}
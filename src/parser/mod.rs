@@ -1,8 +1,20 @@
 //! Parsers for genomic file formats.
 
 pub mod bed;
+pub mod bedpe;
+pub mod biotypes;
+pub mod chrom_sizes;
 pub mod gtf;
+pub mod index;
+pub mod links;
+pub mod manifest;
 pub mod util;
 
-pub use bed::{parse_bed, BedReader};
+pub use bed::{collapse_duplicates, merge_regions, parse_bed, parse_feature_bed, BedReader};
+pub use bedpe::{parse_bedpe, BedpeData, Loop};
+pub use biotypes::parse_biotype_rules;
+pub use chrom_sizes::parse_chrom_sizes;
 pub use gtf::{parse_gtf, GtfData};
+pub use index::{parse_annotation, write_index};
+pub use links::parse_links;
+pub use manifest::{glob_samples, is_pattern, parse_manifest, ManifestSample};
@@ -16,3 +16,13 @@ pub fn create_buffered_reader(file: File, path: &Path) -> Box<dyn BufRead + Send
         Box::new(BufReader::new(file))
     }
 }
+
+/// Whether a line that is not a record is a header rather than a malformed
+/// record: a comment, a UCSC track or browser line, or any line before the
+/// first record (such as column names).
+pub fn is_header_line(line: &str, records_seen: bool) -> bool {
+    !records_seen
+        || line.starts_with('#')
+        || line.starts_with("track")
+        || line.starts_with("browser")
+}
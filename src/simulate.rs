@@ -0,0 +1,326 @@
+//! Synthetic annotations and regions.
+//!
+//! Generates a random but structurally valid GTF and BED file, for
+//! benchmarking and fuzzing the matcher without shipping real annotations.
+//! Genes are placed uniformly at random on each chromosome and may overlap,
+//! as in real annotations; the same seed gives the same files.
+
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use rand::Rng;
+
+use crate::types::{Exon, Gene, Region, Strand, Transcript};
+
+/// Length range of the exons, in bp.
+const EXON_LENGTH: (i64, i64) = (50, 500);
+/// Length range of the introns, in bp.
+const INTRON_LENGTH: (i64, i64) = (100, 5000);
+/// Furthest distance of a transcript start from the start of its gene.
+const MAX_TRANSCRIPT_OFFSET: i64 = 1000;
+/// Biotypes of the genes, with their weights.
+const BIOTYPES: [(&str, u32); 3] = [("protein_coding", 6), ("lncRNA", 3), ("pseudogene", 1)];
+
+/// Shape of a synthetic annotation and its regions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    /// Number of chromosomes, named chr1, chr2, ...
+    pub chromosomes: usize,
+    /// Length of every chromosome, in bp.
+    pub chrom_length: i64,
+    /// Genes per Mb.
+    pub gene_density: f64,
+    /// Most transcripts of a gene (at least one).
+    pub max_transcripts: usize,
+    /// Fewest exons of a transcript.
+    pub min_exons: usize,
+    /// Most exons of a transcript.
+    pub max_exons: usize,
+    /// Number of regions.
+    pub regions: usize,
+    /// Narrowest region, in bp.
+    pub min_width: i64,
+    /// Widest region, in bp.
+    pub max_width: i64,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation {
+            chromosomes: 3,
+            chrom_length: 1_000_000,
+            gene_density: 10.0,
+            max_transcripts: 3,
+            min_exons: 1,
+            max_exons: 10,
+            regions: 1000,
+            min_width: 100,
+            max_width: 1000,
+        }
+    }
+}
+
+impl Simulation {
+    /// Check that the shape can be generated.
+    pub fn check(&self) -> Result<()> {
+        if self.chromosomes == 0 {
+            bail!("The number of chromosomes must be greater than 0.");
+        }
+        if !(self.gene_density >= 0.0 && self.gene_density.is_finite()) {
+            bail!("The gene density must be a non-negative number.");
+        }
+        if self.max_transcripts == 0 {
+            bail!("The number of transcripts per gene must be greater than 0.");
+        }
+        if self.min_exons == 0 || self.min_exons > self.max_exons {
+            bail!("The exons per transcript should be a range MIN <= MAX with MIN greater than 0.");
+        }
+        if self.min_width <= 0 || self.min_width > self.max_width {
+            bail!("The region widths should be a range MIN <= MAX with MIN greater than 0.");
+        }
+        if self.max_width > self.chrom_length {
+            bail!("The widest region is longer than the chromosomes.");
+        }
+        if self.max_gene_length() > self.chrom_length {
+            bail!(
+                "The chromosomes are too short for genes of {} exons ({} bp needed).",
+                self.max_exons,
+                self.max_gene_length()
+            );
+        }
+        Ok(())
+    }
+
+    /// Length of the longest gene that can be generated.
+    fn max_gene_length(&self) -> i64 {
+        let exons = self.max_exons as i64;
+        MAX_TRANSCRIPT_OFFSET + exons * EXON_LENGTH.1 + (exons - 1) * INTRON_LENGTH.1
+    }
+
+    /// Names of the chromosomes.
+    pub fn chrom_names(&self) -> Vec<String> {
+        (1..=self.chromosomes)
+            .map(|i| format!("chr{}", i))
+            .collect()
+    }
+
+    /// Generate the genes of each chromosome, sorted by start.
+    pub fn genes<R: Rng>(&self, rng: &mut R) -> Vec<(String, Vec<Gene>)> {
+        let per_chrom = (self.gene_density * self.chrom_length as f64 / 1e6).round() as usize;
+        let mut gene_count = 0;
+        self.chrom_names()
+            .into_iter()
+            .map(|chrom| {
+                let mut genes: Vec<Gene> = (0..per_chrom).map(|_| self.gene(rng)).collect();
+                genes.sort_by_key(|gene| gene.start);
+                for gene in &mut genes {
+                    gene_count += 1;
+                    gene.gene_id = format!("G{:06}", gene_count);
+                    for (i, transcript) in gene.transcripts.iter_mut().enumerate() {
+                        transcript.transcript_id = format!("{}.{}", gene.gene_id, i + 1);
+                    }
+                }
+                (chrom, genes)
+            })
+            .collect()
+    }
+
+    /// Generate a gene at a random position of a chromosome, without IDs.
+    fn gene<R: Rng>(&self, rng: &mut R) -> Gene {
+        let strand = if rng.gen_bool(0.5) {
+            Strand::Positive
+        } else {
+            Strand::Negative
+        };
+        let weight = rng.gen_range(0..BIOTYPES.iter().map(|(_, w)| w).sum::<u32>());
+        let biotype = BIOTYPES
+            .iter()
+            .scan(0, |total, &(biotype, w)| {
+                *total += w;
+                Some((biotype, *total))
+            })
+            .find(|&(_, total)| weight < total)
+            .map(|(biotype, _)| biotype.to_string());
+
+        let mut gene = Gene::new(String::new(), strand);
+        gene.biotype = biotype.clone();
+        let start = rng.gen_range(1..=self.chrom_length - self.max_gene_length() + 1);
+        for _ in 0..rng.gen_range(1..=self.max_transcripts) {
+            let mut transcript = Transcript::new(String::new());
+            transcript.biotype = biotype.clone();
+            let mut exon_start = start + rng.gen_range(0..=MAX_TRANSCRIPT_OFFSET);
+            for exon in 0..rng.gen_range(self.min_exons..=self.max_exons) {
+                if exon > 0 {
+                    exon_start += rng.gen_range(INTRON_LENGTH.0..=INTRON_LENGTH.1);
+                }
+                let exon_end = exon_start + rng.gen_range(EXON_LENGTH.0..=EXON_LENGTH.1) - 1;
+                transcript.add_exon(Exon::new(exon_start, exon_end));
+                exon_start = exon_end + 1;
+            }
+            transcript.calculate_size();
+            gene.add_transcript(transcript);
+        }
+        gene.calculate_size();
+        gene
+    }
+
+    /// Generate the regions at random positions, sorted by chromosome and
+    /// start and named region_1, region_2, ...
+    pub fn regions<R: Rng>(&self, rng: &mut R) -> Vec<Region> {
+        let chroms = self.chrom_names();
+        let mut regions: Vec<(usize, Region)> = (0..self.regions)
+            .map(|_| {
+                let chrom = rng.gen_range(0..chroms.len());
+                let width = rng.gen_range(self.min_width..=self.max_width);
+                let start = rng.gen_range(1..=self.chrom_length - width + 1);
+                let region = Region::new(chroms[chrom].clone(), start, start + width - 1, vec![]);
+                (chrom, region)
+            })
+            .collect();
+        regions.sort_by_key(|(chrom, region)| (*chrom, region.start, region.end));
+        regions
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, mut region))| {
+                region.metadata.push(format!("region_{}", i + 1));
+                region
+            })
+            .collect()
+    }
+}
+
+/// Write genes as GTF gene, transcript and exon lines.
+pub fn write_gtf<W: Write + ?Sized>(writer: &mut W, genes: &[(String, Vec<Gene>)]) -> Result<()> {
+    for (chrom, genes) in genes {
+        for gene in genes {
+            let biotype = gene.biotype.as_deref().unwrap_or("NA");
+            let gene_attributes =
+                format!("gene_id \"{}\"; gene_type \"{}\";", gene.gene_id, biotype);
+            let line = |feature: &str, start: i64, end: i64, attributes: &str| {
+                format!(
+                    "{}\trgmatch\t{}\t{}\t{}\t.\t{}\t.\t{}",
+                    chrom, feature, start, end, gene.strand, attributes
+                )
+            };
+            writeln!(
+                writer,
+                "{}",
+                line("gene", gene.start, gene.end, &gene_attributes)
+            )?;
+            for transcript in &gene.transcripts {
+                let attributes = format!(
+                    "{} transcript_id \"{}\"; transcript_type \"{}\";",
+                    gene_attributes,
+                    transcript.transcript_id,
+                    transcript.biotype.as_deref().unwrap_or("NA")
+                );
+                let (start, end) = (transcript.start, transcript.end);
+                writeln!(writer, "{}", line("transcript", start, end, &attributes))?;
+                let count = transcript.exons.len();
+                for (i, exon) in transcript.exons.iter().enumerate() {
+                    // Exons are numbered 5' to 3'
+                    let number = match gene.strand {
+                        Strand::Positive => i + 1,
+                        Strand::Negative => count - i,
+                    };
+                    let attributes = format!("{} exon_number \"{}\";", attributes, number);
+                    writeln!(
+                        writer,
+                        "{}",
+                        line("exon", exon.start, exon.end, &attributes)
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write regions as BED lines, with their metadata columns.
+pub fn write_bed<W: Write + ?Sized>(writer: &mut W, regions: &[Region]) -> Result<()> {
+    for region in regions {
+        write!(writer, "{}\t{}\t{}", region.chrom, region.start, region.end)?;
+        for column in &region.metadata {
+            write!(writer, "\t{}", column)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_bed, parse_gtf};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_simulation_round_trip() {
+        let simulation = Simulation {
+            chromosomes: 2,
+            chrom_length: 200_000,
+            gene_density: 50.0,
+            regions: 100,
+            ..Simulation::default()
+        };
+        simulation.check().unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let genes = simulation.genes(&mut rng);
+        let regions = simulation.regions(&mut rng);
+        assert_eq!(genes.iter().map(|(_, g)| g.len()).sum::<usize>(), 20);
+        assert_eq!(regions.len(), 100);
+        for region in &regions {
+            let width = region.end - region.start + 1;
+            assert!((100..=1000).contains(&width));
+            assert!(region.start >= 1 && region.end <= 200_000);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let (gtf, bed) = (dir.path().join("sim.gtf"), dir.path().join("sim.bed"));
+        let mut text = Vec::new();
+        write_gtf(&mut text, &genes).unwrap();
+        std::fs::write(&gtf, &text).unwrap();
+        let mut text = Vec::new();
+        write_bed(&mut text, &regions).unwrap();
+        std::fs::write(&bed, &text).unwrap();
+
+        // The files read back as the generated genes and regions
+        let gtf_data = parse_gtf(&gtf, "gene_id", "transcript_id").unwrap();
+        for (chrom, genes) in &genes {
+            let parsed = &gtf_data.genes_by_chrom[chrom];
+            assert_eq!(parsed.len(), genes.len());
+            for (parsed, gene) in parsed.iter().zip(genes) {
+                assert_eq!(parsed.gene_id, gene.gene_id);
+                assert_eq!((parsed.start, parsed.end), (gene.start, gene.end));
+                assert_eq!(parsed.transcripts.len(), gene.transcripts.len());
+                assert_eq!(parsed.biotype, gene.biotype);
+            }
+        }
+        let bed_data = parse_bed(&bed).unwrap();
+        let parsed: usize = bed_data.regions_by_chrom.values().map(Vec::len).sum();
+        assert_eq!(parsed, 100);
+
+        // The same seed gives the same annotation
+        let again = simulation.genes(&mut StdRng::seed_from_u64(7));
+        let mut text_again = Vec::new();
+        write_gtf(&mut text_again, &again).unwrap();
+        assert_eq!(text_again, std::fs::read(&gtf).unwrap());
+    }
+
+    #[test]
+    fn test_simulation_check() {
+        assert!(Simulation::default().check().is_ok());
+        let short = Simulation {
+            chrom_length: 10_000,
+            ..Simulation::default()
+        };
+        assert!(short.check().is_err());
+        let exons = Simulation {
+            min_exons: 5,
+            max_exons: 2,
+            ..Simulation::default()
+        };
+        assert!(exons.check().is_err());
+    }
+}
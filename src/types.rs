@@ -3,13 +3,24 @@
 //! This module contains the fundamental types used throughout the genomic
 //! region-to-gene matching process.
 
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
 use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
+
+thread_local! {
+    /// Custom zones that deserialized areas may name (see [`Area::with_zones`]).
+    static ZONES: RefCell<Vec<Area>> = const { RefCell::new(Vec::new()) };
+}
 
 /// Strand orientation for genomic features.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Strand {
+    #[serde(rename = "+")]
     Positive,
+    #[serde(rename = "-")]
     Negative,
 }
 
@@ -54,7 +65,7 @@ impl fmt::Display for Strand {
 }
 
 /// Genomic area types for region annotation.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Area {
     Tss,
     FirstExon,
@@ -64,6 +75,25 @@ pub enum Area {
     GeneBody,
     Upstream,
     Downstream,
+    FivePrimeUtr,
+    ThreePrimeUtr,
+    Cds,
+    /// Placeholder area for regions without any gene association.
+    Intergenic,
+    /// User-defined upstream zone (see [`Zone`]).
+    Zone(Arc<str>),
+    /// GREAT basal regulatory domain.
+    Basal,
+    /// GREAT extended regulatory domain.
+    Extension,
+    /// Gene TSS within the cis window of the region (cis model).
+    Cis,
+    /// Target gene of an overlapped enhancer from an external links file.
+    Linked,
+    /// Overlap with a feature of a BED annotation.
+    Overlap,
+    /// Overlap with the extent of a gene (gene-extent mode).
+    OverlapGene,
 }
 
 /// Error type for parsing area from string.
@@ -91,6 +121,16 @@ impl FromStr for Area {
             "GENE_BODY" => Ok(Area::GeneBody),
             "UPSTREAM" => Ok(Area::Upstream),
             "DOWNSTREAM" => Ok(Area::Downstream),
+            "5_UTR" => Ok(Area::FivePrimeUtr),
+            "3_UTR" => Ok(Area::ThreePrimeUtr),
+            "CDS" => Ok(Area::Cds),
+            "INTERGENIC" => Ok(Area::Intergenic),
+            "BASAL" => Ok(Area::Basal),
+            "EXTENSION" => Ok(Area::Extension),
+            "CIS" => Ok(Area::Cis),
+            "LINKED" => Ok(Area::Linked),
+            "OVERLAP" => Ok(Area::Overlap),
+            "OVERLAP_GENE" => Ok(Area::OverlapGene),
             _ => Err(ParseAreaError),
         }
     }
@@ -98,7 +138,7 @@ impl FromStr for Area {
 
 impl Area {
     /// Convert area to string representation.
-    pub fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Area::Tss => "TSS",
             Area::FirstExon => "1st_EXON",
@@ -108,8 +148,81 @@ impl Area {
             Area::GeneBody => "GENE_BODY",
             Area::Upstream => "UPSTREAM",
             Area::Downstream => "DOWNSTREAM",
+            Area::FivePrimeUtr => "5_UTR",
+            Area::ThreePrimeUtr => "3_UTR",
+            Area::Cds => "CDS",
+            Area::Intergenic => "INTERGENIC",
+            Area::Zone(name) => name,
+            Area::Basal => "BASAL",
+            Area::Extension => "EXTENSION",
+            Area::Cis => "CIS",
+            Area::Linked => "LINKED",
+            Area::Overlap => "OVERLAP",
+            Area::OverlapGene => "OVERLAP_GENE",
         }
     }
+
+    /// Whether the area lies upstream of the TSS.
+    pub fn is_upstream(&self) -> bool {
+        matches!(
+            self,
+            Area::Tss | Area::Promoter | Area::Upstream | Area::Zone(_)
+        )
+    }
+
+    /// Area of a zone name, reusing the built-in area of the same name.
+    pub fn from_zone_name(name: &str) -> Self {
+        name.parse().unwrap_or_else(|_| Area::Zone(name.into()))
+    }
+
+    /// Run `f` with `zones` as the custom zones that areas deserialized on
+    /// this thread may name; other names are an error.
+    pub fn with_zones<R>(zones: &[Zone], f: impl FnOnce() -> R) -> R {
+        let areas = zones.iter().map(|zone| zone.area.clone()).collect();
+        let previous = ZONES.with(|current| current.replace(areas));
+        let result = f();
+        ZONES.with(|current| current.replace(previous));
+        result
+    }
+}
+
+impl Serialize for Area {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Area {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if let Ok(area) = name.parse() {
+            return Ok(area);
+        }
+        ZONES
+            .with(|zones| {
+                let zones = zones.borrow();
+                zones.iter().find(|zone| zone.as_str() == name).cloned()
+            })
+            .ok_or_else(|| D::Error::custom(format!("unknown area {}", name)))
+    }
+}
+
+/// A user-defined zone upstream of the TSS.
+///
+/// Covers the positions between `start` (inclusive) and `end` (exclusive)
+/// bp upstream of the TSS.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Zone {
+    pub area: Area,
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Zone {
+    /// Get zone length.
+    pub fn length(&self) -> i64 {
+        self.end - self.start
+    }
 }
 
 impl fmt::Display for Area {
@@ -119,7 +232,7 @@ impl fmt::Display for Area {
 }
 
 /// An exon within a transcript.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Exon {
     pub start: i64,
     pub end: i64,
@@ -144,7 +257,7 @@ impl Exon {
 }
 
 /// A transcript containing exons.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transcript {
     pub transcript_id: String,
     pub exons: Vec<Exon>,
@@ -152,6 +265,12 @@ pub struct Transcript {
     pub start: i64,
     /// Maximum end coordinate (initialized to 0).
     pub end: i64,
+    /// Coding span (first CDS start, last CDS end), if the transcript has CDS features.
+    pub cds: Option<(i64, i64)>,
+    /// GENCODE transcript support level (1 = best supported), if known.
+    pub tsl: Option<u8>,
+    /// Transcript biotype from the GTF `transcript_type`/`transcript_biotype` attribute.
+    pub biotype: Option<String>,
 }
 
 impl Transcript {
@@ -162,6 +281,9 @@ impl Transcript {
             exons: Vec::new(),
             start: i64::MAX,
             end: 0,
+            cds: None,
+            tsl: None,
+            biotype: None,
         }
     }
 
@@ -176,6 +298,14 @@ impl Transcript {
         self.end = end;
     }
 
+    /// Extend the coding span with a CDS feature.
+    pub fn add_cds(&mut self, start: i64, end: i64) {
+        self.cds = Some(match self.cds {
+            Some((s, e)) => (s.min(start), e.max(end)),
+            None => (start, end),
+        });
+    }
+
     /// Calculate transcript boundaries from exon coordinates.
     pub fn calculate_size(&mut self) {
         for exon in &self.exons {
@@ -217,7 +347,7 @@ impl Transcript {
 }
 
 /// A gene containing transcripts.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Gene {
     pub gene_id: String,
     pub strand: Strand,
@@ -226,6 +356,8 @@ pub struct Gene {
     pub start: i64,
     /// Maximum end coordinate (initialized to 0).
     pub end: i64,
+    /// Gene biotype from the GTF `gene_type`/`gene_biotype` attribute.
+    pub biotype: Option<String>,
 }
 
 impl Gene {
@@ -237,6 +369,7 @@ impl Gene {
             transcripts: Vec::new(),
             start: i64::MAX,
             end: 0,
+            biotype: None,
         }
     }
 
@@ -251,6 +384,145 @@ impl Gene {
         self.end = end;
     }
 
+    /// Keep only the transcripts chosen by the selection mode.
+    ///
+    /// Ties keep the first transcript in annotation order.
+    pub fn select_transcripts(&mut self, selection: TranscriptSelection) {
+        if selection == TranscriptSelection::Collapse {
+            self.collapse_transcripts();
+            return;
+        }
+        if selection == TranscriptSelection::All || self.transcripts.len() <= 1 {
+            return;
+        }
+
+        let key = |t: &Transcript| -> (usize, i64) {
+            let exonic_length = t.exons.iter().map(Exon::length).sum();
+            match selection {
+                TranscriptSelection::All | TranscriptSelection::Collapse => (0, 0),
+                TranscriptSelection::Longest => (0, exonic_length),
+                TranscriptSelection::MostExons => (t.exons.len(), exonic_length),
+            }
+        };
+
+        let mut best = 0;
+        for (i, transcript) in self.transcripts.iter().enumerate().skip(1) {
+            if key(transcript) > key(&self.transcripts[best]) {
+                best = i;
+            }
+        }
+        let transcript = self.transcripts.swap_remove(best);
+        self.transcripts = vec![transcript];
+    }
+
+    /// Replace all transcripts by a single meta-transcript named after the gene.
+    ///
+    /// The meta-transcript exons are the union of all transcript exons, with
+    /// overlapping or bookended exons merged, so its TSS is the 5'-most one.
+    pub fn collapse_transcripts(&mut self) {
+        let mut exons: Vec<(i64, i64)> = self
+            .transcripts
+            .iter()
+            .flat_map(|t| t.exons.iter().map(|e| (e.start, e.end)))
+            .collect();
+        exons.sort_unstable();
+
+        let mut meta = Transcript::new(self.gene_id.clone());
+        for (start, end) in exons {
+            match meta.exons.last_mut() {
+                Some(last) if start <= last.end + 1 => last.end = last.end.max(end),
+                _ => meta.add_exon(Exon::new(start, end)),
+            }
+        }
+        for (start, end) in self.transcripts.iter().filter_map(|t| t.cds) {
+            meta.add_cds(start, end);
+        }
+
+        meta.calculate_size();
+        meta.renumber_exons(self.strand);
+        self.transcripts = vec![meta];
+    }
+
+    /// Keep a single transcript per cluster of nearby TSSs.
+    ///
+    /// Walking TSSs 5' to 3', a transcript joins the current cluster while its
+    /// TSS lies within `window` bp of the cluster's first TSS. Each cluster is
+    /// represented by its longest transcript (exonic length, ties keep the
+    /// first in annotation order); representatives keep the annotation order.
+    pub fn cluster_tss(&mut self, window: i64) {
+        if self.transcripts.len() <= 1 {
+            return;
+        }
+
+        let strand = self.strand;
+        let tss = |t: &Transcript| -> i64 {
+            match strand {
+                Strand::Negative => -t.end,
+                _ => t.start,
+            }
+        };
+        // Longest first, then first in annotation order
+        let rank = |i: usize| -> (i64, std::cmp::Reverse<usize>) {
+            let exonic_length = self.transcripts[i].exons.iter().map(Exon::length).sum();
+            (exonic_length, std::cmp::Reverse(i))
+        };
+
+        let mut order: Vec<usize> = (0..self.transcripts.len()).collect();
+        order.sort_by_key(|&i| (tss(&self.transcripts[i]), i));
+
+        let mut keep = vec![false; self.transcripts.len()];
+        let mut cluster_start = i64::MIN;
+        let mut best: Option<usize> = None;
+        for i in order {
+            let transcript = &self.transcripts[i];
+            if best.is_none() || tss(transcript) - cluster_start > window {
+                if let Some(b) = best {
+                    keep[b] = true;
+                }
+                cluster_start = tss(transcript);
+                best = Some(i);
+            } else if best.is_some_and(|b| rank(i) > rank(b)) {
+                best = Some(i);
+            }
+        }
+        if let Some(b) = best {
+            keep[b] = true;
+        }
+
+        let mut keep = keep.into_iter();
+        self.transcripts.retain(|_| keep.next().unwrap_or(false));
+    }
+
+    /// Replace the transcripts by a single one spanning the gene extent.
+    ///
+    /// The transcript is named after the gene and has a single exon, so the
+    /// exon/intron structure is ignored.
+    pub fn collapse_to_extent(&mut self) {
+        let mut extent = Transcript::new(self.gene_id.clone());
+        extent.add_exon(Exon::new(self.start, self.end));
+        extent.calculate_size();
+        extent.renumber_exons(self.strand);
+        self.transcripts = vec![extent];
+    }
+
+    /// Copy of the gene moved by `offset` bp, e.g. one contig length to
+    /// mirror a gene across the origin of a circular contig.
+    pub fn shifted(&self, offset: i64) -> Gene {
+        let mut gene = self.clone();
+        gene.start += offset;
+        gene.end += offset;
+        for transcript in &mut gene.transcripts {
+            transcript.start += offset;
+            transcript.end += offset;
+            transcript.cds = transcript.cds.map(|(s, e)| (s + offset, e + offset));
+            for exon in &mut transcript.exons {
+                exon.start += offset;
+                exon.end += offset;
+            }
+        }
+        gene
+    }
+
     /// Calculate gene boundaries from transcript coordinates.
     pub fn calculate_size(&mut self) {
         for transcript in &self.transcripts {
@@ -265,7 +537,7 @@ impl Gene {
 }
 
 /// A candidate match between a genomic region and a gene annotation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Candidate {
     pub start: i64,
     pub end: i64,
@@ -278,6 +550,24 @@ pub struct Candidate {
     pub pctg_region: f64,
     pub pctg_area: f64,
     pub tss_distance: i64,
+    /// Whether the region overlaps a gene on the opposite strand to its own
+    /// (None when not computed or the region is unstranded).
+    pub antisense: Option<bool>,
+    /// Confidence score of the association between 0 and 1 (None when not computed).
+    pub confidence: Option<f64>,
+    /// Percentage of the gene extent covered by the region (None when not computed).
+    pub pctg_gene: Option<f64>,
+    /// Percentage of the transcript extent covered by the region (None when not computed).
+    pub pctg_transcript: Option<f64>,
+    /// Number of bases of the region overlapping the gene extent (None when not computed).
+    pub overlap_gene: Option<i64>,
+    /// TSS distances of the transcripts merged into a gene-level candidate,
+    /// comma-separated in the order of `transcript` (None when not merged).
+    pub tss_distances: Option<String>,
+    /// Transcript support level of the transcript (None when unknown).
+    pub tsl: Option<u8>,
+    /// Report level the candidate was selected at (None unless several are reported).
+    pub level: Option<ReportLevel>,
 }
 
 impl Candidate {
@@ -308,17 +598,38 @@ impl Candidate {
             pctg_region,
             pctg_area,
             tss_distance,
+            antisense: None,
+            confidence: None,
+            pctg_gene: None,
+            pctg_transcript: None,
+            overlap_gene: None,
+            tss_distances: None,
+            tsl: None,
+            level: None,
+        }
+    }
+
+    /// Distance signed relative to the transcript orientation: negative when the
+    /// region lies upstream of the TSS, positive otherwise.
+    pub fn signed_distance(&self) -> i64 {
+        match &self.area {
+            // GREAT distances are measured from the TSS itself
+            Area::Basal | Area::Extension | Area::Cis => self.tss_distance,
+            area if area.is_upstream() => -self.distance,
+            _ => self.distance,
         }
     }
 }
 
 /// A genomic region from a BED file.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Region {
     pub chrom: String,
     pub start: i64,
     pub end: i64,
     pub metadata: Vec<String>,
+    /// Number of identical input regions this region stands for.
+    pub copies: usize,
 }
 
 impl Region {
@@ -329,6 +640,7 @@ impl Region {
             start,
             end,
             metadata,
+            copies: 1,
         }
     }
 
@@ -346,14 +658,201 @@ impl Region {
     pub fn id(&self) -> String {
         format!("{}_{}_{}", self.chrom, self.start, self.end)
     }
+
+    /// Get the summit position from the narrowPeak peak column, if present.
+    ///
+    /// The peak column holds the summit offset from the region start; -1
+    /// (or a missing column) means no summit was called.
+    pub fn summit(&self) -> Option<i64> {
+        let offset: i64 = self.metadata.get(6)?.trim_end().parse().ok()?;
+        (offset >= 0).then_some(self.start + offset)
+    }
+
+    /// Get the region weight from a numeric BED column (1-based, 4-12).
+    ///
+    /// Comma-separated values, as left by merging regions, are summed;
+    /// missing or non-numeric values weigh 0.
+    pub fn weight(&self, column: usize) -> f64 {
+        let Some(value) = column.checked_sub(4).and_then(|i| self.metadata.get(i)) else {
+            return 0.0;
+        };
+        value
+            .trim_end()
+            .split(',')
+            .filter_map(|v| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+            // Unlike sum(), an empty list gives 0 rather than -0
+            .fold(0.0, |sum, v| sum + v)
+    }
+
+    /// Signed offset from `pos` to the region anchor, positive when the
+    /// anchor lies after `pos`.
+    ///
+    /// The edge anchor uses the region edge nearest to `pos` (0 inside the
+    /// region), and the summit anchor falls back to the midpoint when the
+    /// region has no summit.
+    pub fn anchor_offset(&self, anchor: DistanceAnchor, pos: i64) -> i64 {
+        match anchor {
+            DistanceAnchor::Midpoint => self.midpoint() - pos,
+            DistanceAnchor::Summit => self.summit().unwrap_or_else(|| self.midpoint()) - pos,
+            DistanceAnchor::Edge => {
+                if pos < self.start {
+                    self.start - pos
+                } else if pos > self.end {
+                    self.end - pos
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// Get the region strand from the BED strand column, if present.
+    ///
+    /// Returns None for unstranded regions (missing column or ".").
+    pub fn strand(&self) -> Option<Strand> {
+        self.metadata.get(2)?.trim_end().parse().ok()
+    }
+}
+
+/// Which genes are considered relative to the region strand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrandMode {
+    /// Consider genes on both strands.
+    #[default]
+    Any,
+    /// Only consider genes on the same strand as the region.
+    Same,
+    /// Only consider genes on the opposite strand to the region.
+    Opposite,
+}
+
+impl StrandMode {
+    /// Check whether a gene on `gene_strand` is considered for a region.
+    ///
+    /// Unstranded regions are matched against genes on both strands.
+    pub fn accepts(&self, region_strand: Option<Strand>, gene_strand: Strand) -> bool {
+        match (self, region_strand) {
+            (StrandMode::Any, _) | (_, None) => true,
+            (StrandMode::Same, Some(strand)) => strand == gene_strand,
+            (StrandMode::Opposite, Some(strand)) => strand != gene_strand,
+        }
+    }
+}
+
+/// Which genes are considered by their biotype.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BiotypeFilter {
+    /// Consider genes of every biotype.
+    #[default]
+    Any,
+    /// Only consider genes of this biotype.
+    Include(String),
+    /// Consider genes of every biotype but these (including genes without one).
+    Exclude(Vec<String>),
+}
+
+impl BiotypeFilter {
+    /// Check whether a gene of the given biotype is considered.
+    pub fn accepts(&self, biotype: Option<&str>) -> bool {
+        match self {
+            BiotypeFilter::Any => true,
+            BiotypeFilter::Include(included) => biotype == Some(included.as_str()),
+            BiotypeFilter::Exclude(excluded) => {
+                biotype.map_or(true, |b| !excluded.iter().any(|e| e == b))
+            }
+        }
+    }
+}
+
+/// Weights of the terms combined into the association confidence score.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ConfidenceWeights {
+    /// Weight of the percentage of the region overlapped.
+    pub region: f64,
+    /// Weight of the percentage of the area overlapped.
+    pub area: f64,
+    /// Weight of the area priority in the rules.
+    pub rank: f64,
+    /// Weight of the closeness to the gene.
+    pub distance: f64,
+}
+
+impl Default for ConfidenceWeights {
+    fn default() -> Self {
+        ConfidenceWeights {
+            region: 0.3,
+            area: 0.2,
+            rank: 0.3,
+            distance: 0.2,
+        }
+    }
+}
+
+/// Error type for parsing confidence weights from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseConfidenceWeightsError;
+
+impl fmt::Display for ParseConfidenceWeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid confidence weights: expected 4 non-negative numbers 'REGION,AREA,RANK,DISTANCE' with a positive sum"
+        )
+    }
+}
+
+impl std::error::Error for ParseConfidenceWeightsError {}
+
+impl FromStr for ConfidenceWeights {
+    type Err = ParseConfidenceWeightsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let weights: Vec<f64> = s
+            .split(',')
+            .map(|w| w.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| ParseConfidenceWeightsError)?;
+        if weights.len() != 4
+            || weights.iter().any(|w| !w.is_finite() || *w < 0.0)
+            || weights.iter().sum::<f64>() <= 0.0
+        {
+            return Err(ParseConfidenceWeightsError);
+        }
+        Ok(ConfidenceWeights {
+            region: weights[0],
+            area: weights[1],
+            rank: weights[2],
+            distance: weights[3],
+        })
+    }
 }
 
 /// Report level for output.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ReportLevel {
     Exon,
     Transcript,
     Gene,
+    /// Every raw candidate, without rule-based selection or filtering.
+    All,
+    /// Exon, transcript and gene levels at once, tagged in a Level column.
+    Levels,
+}
+
+impl ReportLevel {
+    /// Convert report level to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportLevel::Exon => "exon",
+            ReportLevel::Transcript => "transcript",
+            ReportLevel::Gene => "gene",
+            ReportLevel::All => "all",
+            ReportLevel::Levels => "levels",
+        }
+    }
 }
 
 /// Error type for parsing report level from string.
@@ -364,7 +863,7 @@ impl fmt::Display for ParseReportLevelError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "invalid report level: expected 'exon', 'transcript', or 'gene'"
+            "invalid report level: expected 'exon', 'transcript', 'gene', 'all' or 'levels'"
         )
     }
 }
@@ -379,15 +878,824 @@ impl FromStr for ReportLevel {
             "exon" => Ok(ReportLevel::Exon),
             "transcript" => Ok(ReportLevel::Transcript),
             "gene" => Ok(ReportLevel::Gene),
+            "all" => Ok(ReportLevel::All),
+            "levels" => Ok(ReportLevel::Levels),
             _ => Err(ParseReportLevelError),
         }
     }
 }
 
+/// Output compatibility mode mimicking another annotation tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compat {
+    /// ChIPseeker annotation categories in the Area column.
+    #[serde(rename = "chipseeker")]
+    ChipSeeker,
+    /// `bedtools closest -d` layout: the region columns, the gene as a BED6
+    /// feature and the distance.
+    BedtoolsClosest,
+    /// Output of the original Python rgmatch, with its single downstream and
+    /// upstream candidates: an overlap beyond the first or last exon replaces
+    /// the closest candidate of its direction and is reported again with it.
+    Python,
+}
+
+/// Error type for parsing compatibility mode from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCompatError;
+
+impl fmt::Display for ParseCompatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid compatibility mode: expected 'chipseeker', 'bedtools-closest' or 'python'"
+        )
+    }
+}
+
+impl std::error::Error for ParseCompatError {}
+
+impl FromStr for Compat {
+    type Err = ParseCompatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chipseeker" => Ok(Compat::ChipSeeker),
+            "bedtools-closest" => Ok(Compat::BedtoolsClosest),
+            "python" => Ok(Compat::Python),
+            _ => Err(ParseCompatError),
+        }
+    }
+}
+
+/// How exact duplicate input regions (same chrom, start and end) are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateMode {
+    /// Annotate every region independently.
+    #[default]
+    Keep,
+    /// Annotate duplicates once and replicate the results for every copy.
+    Replicate,
+    /// Report duplicates once, with the number of copies in a Count column.
+    Collapse,
+}
+
+/// Error type for parsing duplicate mode from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDuplicateModeError;
+
+impl fmt::Display for ParseDuplicateModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid duplicate mode: expected 'keep', 'replicate', or 'collapse'"
+        )
+    }
+}
+
+impl std::error::Error for ParseDuplicateModeError {}
+
+impl FromStr for DuplicateMode {
+    type Err = ParseDuplicateModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(DuplicateMode::Keep),
+            "replicate" => Ok(DuplicateMode::Replicate),
+            "collapse" => Ok(DuplicateMode::Collapse),
+            _ => Err(ParseDuplicateModeError),
+        }
+    }
+}
+
+/// Point of the region that distances to genes are measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DistanceAnchor {
+    /// Region midpoint.
+    #[default]
+    Midpoint,
+    /// Region edge nearest to the gene feature.
+    Edge,
+    /// Peak summit from the narrowPeak peak column.
+    Summit,
+}
+
+/// Error type for parsing distance anchor from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDistanceAnchorError;
+
+impl fmt::Display for ParseDistanceAnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid distance anchor: expected 'midpoint', 'edge', or 'summit'"
+        )
+    }
+}
+
+impl std::error::Error for ParseDistanceAnchorError {}
+
+impl FromStr for DistanceAnchor {
+    type Err = ParseDistanceAnchorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "midpoint" => Ok(DistanceAnchor::Midpoint),
+            "edge" => Ok(DistanceAnchor::Edge),
+            "summit" => Ok(DistanceAnchor::Summit),
+            _ => Err(ParseDistanceAnchorError),
+        }
+    }
+}
+
+/// Secondary tie-break among candidates tied after the priority rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TieBreak {
+    /// Report every tied candidate.
+    #[default]
+    None,
+    /// Keep the closest candidate (|distance|, then |TSS distance|).
+    Distance,
+    /// Keep the candidate closest to the TSS (|TSS distance|, then |distance|).
+    Tss,
+}
+
+/// Error type for parsing tie-break from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTieBreakError;
+
+impl fmt::Display for ParseTieBreakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid tie-break: expected 'none', 'distance', or 'tss'"
+        )
+    }
+}
+
+impl std::error::Error for ParseTieBreakError {}
+
+impl FromStr for TieBreak {
+    type Err = ParseTieBreakError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(TieBreak::None),
+            "distance" => Ok(TieBreak::Distance),
+            "tss" => Ok(TieBreak::Tss),
+            _ => Err(ParseTieBreakError),
+        }
+    }
+}
+
+/// Which transcripts of each gene are used for matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TranscriptSelection {
+    /// Use every transcript.
+    #[default]
+    All,
+    /// Keep only the transcript with the longest exonic length.
+    Longest,
+    /// Keep only the transcript with the most exons (longest on ties).
+    MostExons,
+    /// Collapse all transcripts into a single meta-transcript per gene.
+    Collapse,
+}
+
+/// Error type for parsing transcript selection from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTranscriptSelectionError;
+
+impl fmt::Display for ParseTranscriptSelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid transcript selection: expected 'all', 'longest', 'most-exons', or 'collapse'"
+        )
+    }
+}
+
+impl std::error::Error for ParseTranscriptSelectionError {}
+
+impl FromStr for TranscriptSelection {
+    type Err = ParseTranscriptSelectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "all" => Ok(TranscriptSelection::All),
+            "longest" => Ok(TranscriptSelection::Longest),
+            "most-exons" => Ok(TranscriptSelection::MostExons),
+            "collapse" => Ok(TranscriptSelection::Collapse),
+            _ => Err(ParseTranscriptSelectionError),
+        }
+    }
+}
+
+/// Association model used to link regions to genes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Model {
+    /// Exon/intron/proximity matching (the default rgmatch model).
+    #[default]
+    Rgmatch,
+    /// GREAT basal-plus-extension regulatory domains.
+    Great,
+    /// Every gene whose TSS lies within a window around the region (eQTL cis window).
+    Cis,
+}
+
+/// Error type for parsing model from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseModelError;
+
+impl fmt::Display for ParseModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid model: expected 'rgmatch', 'great', or 'cis'")
+    }
+}
+
+impl std::error::Error for ParseModelError {}
+
+impl FromStr for Model {
+    type Err = ParseModelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rgmatch" => Ok(Model::Rgmatch),
+            "great" => Ok(Model::Great),
+            "cis" => Ok(Model::Cis),
+            _ => Err(ParseModelError),
+        }
+    }
+}
+
+/// File format of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Tab-separated text with a header line.
+    #[default]
+    Tsv,
+    /// Typed columnar Apache Parquet file (needs the `parquet` feature).
+    Parquet,
+    /// Indexed SQLite database (needs the `sqlite` feature).
+    Sqlite,
+    /// The input BED columns followed by the gene, area and distance.
+    Bed,
+    /// GFF3 features with the annotation as attributes.
+    Gff3,
+    /// UCSC BED9 custom track with the gene and area as name and a color
+    /// per area.
+    Ucsc,
+}
+
+/// Error type for parsing output format from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOutputFormatError;
+
+impl fmt::Display for ParseOutputFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid output format: expected 'tsv', 'bed', 'gff3', 'ucsc', 'parquet' or 'sqlite'"
+        )
+    }
+}
+
+impl std::error::Error for ParseOutputFormatError {}
+
+impl FromStr for OutputFormat {
+    type Err = ParseOutputFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tsv" => Ok(OutputFormat::Tsv),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "sqlite" => Ok(OutputFormat::Sqlite),
+            "bed" => Ok(OutputFormat::Bed),
+            "gff3" | "gff" => Ok(OutputFormat::Gff3),
+            "ucsc" | "track" => Ok(OutputFormat::Ucsc),
+            _ => Err(ParseOutputFormatError),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// File extension of the format, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Bed | OutputFormat::Ucsc => "bed",
+            OutputFormat::Gff3 => "gff3",
+        }
+    }
+}
+
+/// Output column selected for writing, with the header to write it under.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColumnSpec {
+    /// Name of the column in the default output.
+    pub name: String,
+    /// Header written for the column.
+    pub header: String,
+}
+
+/// Error type for parsing a column selection from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseColumnSpecError;
+
+impl fmt::Display for ParseColumnSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid column: expected 'NAME' or 'NAME:HEADER' with non-empty names"
+        )
+    }
+}
+
+impl std::error::Error for ParseColumnSpecError {}
+
+impl FromStr for ColumnSpec {
+    type Err = ParseColumnSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, header) = match s.split_once(':') {
+            Some((name, header)) => (name.trim(), header.trim()),
+            None => (s.trim(), s.trim()),
+        };
+        if name.is_empty() || header.is_empty() || header.contains('\t') {
+            return Err(ParseColumnSpecError);
+        }
+        Ok(ColumnSpec {
+            name: name.to_string(),
+            header: header.to_string(),
+        })
+    }
+}
+
+/// File layout of the region-gene matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatrixFormat {
+    /// Sparse MatrixMarket coordinate file with region and gene name files.
+    #[default]
+    Mtx,
+    /// Dense table with one row per region and one column per gene.
+    Tsv,
+}
+
+/// Error type for parsing matrix format from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMatrixFormatError;
+
+impl fmt::Display for ParseMatrixFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid matrix format: expected 'mtx' or 'tsv'")
+    }
+}
+
+impl std::error::Error for ParseMatrixFormatError {}
+
+impl FromStr for MatrixFormat {
+    type Err = ParseMatrixFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mtx" | "mm" => Ok(MatrixFormat::Mtx),
+            "tsv" => Ok(MatrixFormat::Tsv),
+            _ => Err(ParseMatrixFormatError),
+        }
+    }
+}
+
+/// Value of a region-gene pair in the matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatrixValue {
+    /// Largest percentage of the region in one area of the gene (PercRegion).
+    #[default]
+    Overlap,
+    /// 1 for every associated pair.
+    Binary,
+}
+
+/// Error type for parsing matrix value from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMatrixValueError;
+
+impl fmt::Display for ParseMatrixValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid matrix value: expected 'overlap' or 'binary'")
+    }
+}
+
+impl std::error::Error for ParseMatrixValueError {}
+
+impl FromStr for MatrixValue {
+    type Err = ParseMatrixValueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "overlap" => Ok(MatrixValue::Overlap),
+            "binary" => Ok(MatrixValue::Binary),
+            _ => Err(ParseMatrixValueError),
+        }
+    }
+}
+
+/// File format of the performance metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetricsFormat {
+    /// Pretty-printed JSON object.
+    #[default]
+    Json,
+    /// Prometheus text exposition format, for the node_exporter textfile
+    /// collector.
+    Prometheus,
+}
+
+/// Error type for parsing metrics format from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMetricsFormatError;
+
+impl fmt::Display for ParseMetricsFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid metrics format: expected 'json' or 'prometheus'")
+    }
+}
+
+impl std::error::Error for ParseMetricsFormatError {}
+
+impl FromStr for MetricsFormat {
+    type Err = ParseMetricsFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(MetricsFormat::Json),
+            "prometheus" | "prom" => Ok(MetricsFormat::Prometheus),
+            _ => Err(ParseMetricsFormatError),
+        }
+    }
+}
+
+/// Format of the log messages written to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// One human-readable line per message.
+    #[default]
+    Text,
+    /// One JSON object per line, for pipeline log collectors.
+    Json,
+}
+
+/// Error type for parsing log format from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLogFormatError;
+
+impl fmt::Display for ParseLogFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid log format: expected 'text' or 'json'")
+    }
+}
+
+impl std::error::Error for ParseLogFormatError {}
+
+impl FromStr for LogFormat {
+    type Err = ParseLogFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(ParseLogFormatError),
+        }
+    }
+}
+
+/// An amount of memory, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemorySize(pub u64);
+
+/// Error type for parsing memory size from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMemorySizeError;
+
+impl fmt::Display for ParseMemorySizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid memory size: expected a number of bytes with an optional K, M, G or T suffix"
+        )
+    }
+}
+
+impl std::error::Error for ParseMemorySizeError {}
+
+impl FromStr for MemorySize {
+    type Err = ParseMemorySizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(digits);
+        let unit = unit.trim().to_uppercase();
+        let unit = unit
+            .strip_suffix("IB")
+            .or_else(|| unit.strip_suffix('B'))
+            .unwrap_or(&unit);
+        let shift = match unit {
+            "" => 0,
+            "K" => 10,
+            "M" => 20,
+            "G" => 30,
+            "T" => 40,
+            _ => return Err(ParseMemorySizeError),
+        };
+        match number.parse::<u64>() {
+            Ok(number) if number > 0 && number.leading_zeros() >= shift => {
+                Ok(MemorySize(number << shift))
+            }
+            _ => Err(ParseMemorySizeError),
+        }
+    }
+}
+
+/// Number of decimal places of the percentage columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Precision {
+    /// Round to this many decimal places.
+    Digits(usize),
+    /// Shortest representation that reads back as the same number.
+    Full,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision::Digits(2)
+    }
+}
+
+impl Precision {
+    /// Format a value at this precision.
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            Precision::Digits(digits) => format!("{:.*}", digits, value),
+            Precision::Full => value.to_string(),
+        }
+    }
+}
+
+/// Error type for parsing precision from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePrecisionError;
+
+impl fmt::Display for ParsePrecisionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid precision: expected a number of decimal places (0-17) or 'full'"
+        )
+    }
+}
+
+impl std::error::Error for ParsePrecisionError {}
+
+impl FromStr for Precision {
+    type Err = ParsePrecisionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("full") {
+            return Ok(Precision::Full);
+        }
+        match s.parse::<usize>() {
+            Ok(digits) if digits <= 17 => Ok(Precision::Digits(digits)),
+            _ => Err(ParsePrecisionError),
+        }
+    }
+}
+
+/// Columns identifying the region in the per-association output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegionColumns {
+    /// The Region ID (`chr1_100_200`).
+    #[default]
+    Id,
+    /// Chrom, Start and End columns in place of the Region ID.
+    Split,
+    /// The Region ID followed by Chrom, Start and End columns.
+    Both,
+}
+
+impl RegionColumns {
+    /// Headers of the region columns.
+    pub fn headers(&self) -> &'static [&'static str] {
+        match self {
+            RegionColumns::Id => &["Region"],
+            RegionColumns::Split => &["Chrom", "Start", "End"],
+            RegionColumns::Both => &["Region", "Chrom", "Start", "End"],
+        }
+    }
+}
+
+/// Error type for parsing region columns from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRegionColumnsError;
+
+impl fmt::Display for ParseRegionColumnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid region columns: expected 'id', 'split', or 'both'"
+        )
+    }
+}
+
+impl std::error::Error for ParseRegionColumnsError {}
+
+impl FromStr for RegionColumns {
+    type Err = ParseRegionColumnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "id" => Ok(RegionColumns::Id),
+            "split" => Ok(RegionColumns::Split),
+            "both" => Ok(RegionColumns::Both),
+            _ => Err(ParseRegionColumnsError),
+        }
+    }
+}
+
+/// Order of the rows of the per-association output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// Order of the input regions.
+    #[default]
+    None,
+    /// Genome coordinates of the regions, chromosomes in karyotype order.
+    Coord,
+    /// Grouped by gene, unmatched regions last.
+    Gene,
+    /// Grouped by gene and by distance within a gene, unmatched regions
+    /// last.
+    GeneDistance,
+}
+
+/// Error type for parsing sort order from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSortOrderError;
+
+impl fmt::Display for ParseSortOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid sort order: expected 'coord', 'gene', 'gene-distance', or 'none'"
+        )
+    }
+}
+
+impl std::error::Error for ParseSortOrderError {}
+
+impl FromStr for SortOrder {
+    type Err = ParseSortOrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(SortOrder::None),
+            "coord" => Ok(SortOrder::Coord),
+            "gene" => Ok(SortOrder::Gene),
+            "gene-distance" => Ok(SortOrder::GeneDistance),
+            _ => Err(ParseSortOrderError),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_precision() {
+        assert_eq!("4".parse::<Precision>(), Ok(Precision::Digits(4)));
+        assert_eq!("FULL".parse::<Precision>(), Ok(Precision::Full));
+        assert!("-1".parse::<Precision>().is_err());
+        assert!("18".parse::<Precision>().is_err());
+
+        assert_eq!(Precision::default().format(100.0 / 3.0), "33.33");
+        assert_eq!(Precision::Digits(0).format(12.6), "13");
+        assert_eq!(Precision::Digits(4).format(12.5), "12.5000");
+        let value = 100.0 / 3.0;
+        assert_eq!(Precision::Full.format(value).parse::<f64>(), Ok(value));
+        assert_eq!(Precision::Full.format(50.0), "50");
+    }
+
+    #[test]
+    fn test_sort_order_parsing() {
+        assert_eq!("coord".parse::<SortOrder>(), Ok(SortOrder::Coord));
+        assert_eq!("Gene".parse::<SortOrder>(), Ok(SortOrder::Gene));
+        assert_eq!(
+            "gene-distance".parse::<SortOrder>(),
+            Ok(SortOrder::GeneDistance)
+        );
+        assert_eq!("none".parse::<SortOrder>(), Ok(SortOrder::None));
+        assert!("start".parse::<SortOrder>().is_err());
+    }
+
+    #[test]
+    fn test_region_columns_parsing() {
+        assert_eq!("split".parse::<RegionColumns>(), Ok(RegionColumns::Split));
+        assert_eq!("Both".parse::<RegionColumns>(), Ok(RegionColumns::Both));
+        assert_eq!("id".parse::<RegionColumns>(), Ok(RegionColumns::Id));
+        assert!("bed".parse::<RegionColumns>().is_err());
+        assert_eq!(RegionColumns::Split.headers(), ["Chrom", "Start", "End"]);
+    }
+
+    #[test]
+    fn test_matrix_option_parsing() {
+        assert_eq!("MTX".parse::<MatrixFormat>(), Ok(MatrixFormat::Mtx));
+        assert_eq!("tsv".parse::<MatrixFormat>(), Ok(MatrixFormat::Tsv));
+        assert!("csv".parse::<MatrixFormat>().is_err());
+        assert_eq!("binary".parse::<MatrixValue>(), Ok(MatrixValue::Binary));
+        assert!("count".parse::<MatrixValue>().is_err());
+    }
+
+    #[test]
+    fn test_log_format_parsing() {
+        assert_eq!("text".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("yaml".parse::<LogFormat>(), Err(ParseLogFormatError));
+    }
+
+    #[test]
+    fn test_memory_size_parsing() {
+        assert_eq!("4096".parse::<MemorySize>(), Ok(MemorySize(4096)));
+        assert_eq!("512M".parse::<MemorySize>(), Ok(MemorySize(512 << 20)));
+        assert_eq!("2 GiB".parse::<MemorySize>(), Ok(MemorySize(2 << 30)));
+        assert_eq!("64kb".parse::<MemorySize>(), Ok(MemorySize(64 << 10)));
+        assert!("0".parse::<MemorySize>().is_err());
+        assert!("1.5G".parse::<MemorySize>().is_err());
+        assert!("10P".parse::<MemorySize>().is_err());
+        assert!("G".parse::<MemorySize>().is_err());
+    }
+
+    #[test]
+    fn test_area_serde_round_trip() {
+        let zones = vec![Zone {
+            area: Area::from_zone_name("DISTAL"),
+            start: 0,
+            end: 1000,
+        }];
+        let areas = vec![Area::Tss, zones[0].area.clone(), Area::FirstExon];
+        let json = serde_json::to_string(&areas).unwrap();
+        assert_eq!(json, r#"["TSS","DISTAL","1st_EXON"]"#);
+        let read: Vec<Area> = Area::with_zones(&zones, || serde_json::from_str(&json)).unwrap();
+        assert_eq!(read, areas);
+        // Zones share the configured name
+        let (Area::Zone(a), Area::Zone(b)) = (&read[1], &zones[0].area) else {
+            panic!("expected zones");
+        };
+        assert!(Arc::ptr_eq(a, b));
+
+        // Names other than the configured zones are an error
+        let err =
+            Area::with_zones(&zones, || serde_json::from_str::<Area>(r#""DISTL""#)).unwrap_err();
+        assert!(err.to_string().contains("unknown area DISTL"));
+        assert!(serde_json::from_str::<Area>(r#""DISTAL""#).is_err());
+    }
+
+    #[test]
+    fn test_metrics_format_parsing() {
+        assert_eq!("JSON".parse::<MetricsFormat>(), Ok(MetricsFormat::Json));
+        assert_eq!(
+            "prom".parse::<MetricsFormat>(),
+            Ok(MetricsFormat::Prometheus)
+        );
+        assert!("csv".parse::<MetricsFormat>().is_err());
+    }
+
+    #[test]
+    fn test_column_spec_parsing() {
+        let spec: ColumnSpec = "Gene".parse().unwrap();
+        assert_eq!((spec.name.as_str(), spec.header.as_str()), ("Gene", "Gene"));
+        let spec: ColumnSpec = "Gene:gene_id".parse().unwrap();
+        assert_eq!(
+            (spec.name.as_str(), spec.header.as_str()),
+            ("Gene", "gene_id")
+        );
+        assert!("".parse::<ColumnSpec>().is_err());
+        assert!("Gene:".parse::<ColumnSpec>().is_err());
+    }
+
     #[test]
     fn test_strand_parsing() {
         assert_eq!("+".parse::<Strand>(), Ok(Strand::Positive));
@@ -409,6 +1717,49 @@ mod tests {
         assert_eq!(exon.length(), 101);
     }
 
+    #[test]
+    fn test_region_strand() {
+        let meta = |strand: &str| vec!["name".to_string(), "0".to_string(), strand.to_string()];
+        let region = Region::new("chr1".to_string(), 100, 200, meta("-"));
+        assert_eq!(region.strand(), Some(Strand::Negative));
+        let region = Region::new("chr1".to_string(), 100, 200, meta("."));
+        assert_eq!(region.strand(), None);
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        assert_eq!(region.strand(), None);
+    }
+
+    #[test]
+    fn test_strand_mode_accepts() {
+        let pos = Some(Strand::Positive);
+        assert!(StrandMode::Any.accepts(pos, Strand::Negative));
+        assert!(StrandMode::Same.accepts(pos, Strand::Positive));
+        assert!(!StrandMode::Same.accepts(pos, Strand::Negative));
+        assert!(StrandMode::Opposite.accepts(pos, Strand::Negative));
+        assert!(!StrandMode::Opposite.accepts(pos, Strand::Positive));
+        assert!(StrandMode::Same.accepts(None, Strand::Negative));
+    }
+
+    #[test]
+    fn test_model_parsing() {
+        assert_eq!("great".parse::<Model>(), Ok(Model::Great));
+        assert_eq!("cis".parse::<Model>(), Ok(Model::Cis));
+        assert_eq!("GREAT".parse::<Model>(), Ok(Model::Great));
+        assert_eq!("rgmatch".parse::<Model>(), Ok(Model::Rgmatch));
+        assert!("nearest".parse::<Model>().is_err());
+    }
+
+    #[test]
+    fn test_output_format_parsing() {
+        assert_eq!("tsv".parse::<OutputFormat>(), Ok(OutputFormat::Tsv));
+        assert_eq!("Parquet".parse::<OutputFormat>(), Ok(OutputFormat::Parquet));
+        assert_eq!("sqlite".parse::<OutputFormat>(), Ok(OutputFormat::Sqlite));
+        assert_eq!("bed".parse::<OutputFormat>(), Ok(OutputFormat::Bed));
+        assert_eq!("gff".parse::<OutputFormat>(), Ok(OutputFormat::Gff3));
+        assert_eq!("track".parse::<OutputFormat>(), Ok(OutputFormat::Ucsc));
+        assert!("csv".parse::<OutputFormat>().is_err());
+        assert_eq!(OutputFormat::Ucsc.extension(), "bed");
+    }
+
     #[test]
     fn test_region_midpoint() {
         let region = Region::new("chr1".to_string(), 100, 200, vec![]);
@@ -419,6 +1770,31 @@ mod tests {
         assert_eq!(region2.midpoint(), 150); // (100 + 201) / 2 = 150 (integer division)
     }
 
+    #[test]
+    fn test_region_anchor_offset() {
+        let narrow_peak = |peak: &str| {
+            ["peak", "0", ".", "1.0", "-1", "-1", peak]
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        };
+        let region = Region::new("chr1".to_string(), 100, 200, narrow_peak("30"));
+        assert_eq!(region.summit(), Some(130));
+
+        assert_eq!(region.anchor_offset(DistanceAnchor::Midpoint, 50), 100);
+        assert_eq!(region.anchor_offset(DistanceAnchor::Summit, 50), 80);
+        assert_eq!(region.anchor_offset(DistanceAnchor::Edge, 50), 50);
+        assert_eq!(region.anchor_offset(DistanceAnchor::Edge, 260), -60);
+        assert_eq!(region.anchor_offset(DistanceAnchor::Edge, 120), 0);
+
+        // Without a summit the midpoint is used
+        let region = Region::new("chr1".to_string(), 100, 200, narrow_peak("-1"));
+        assert_eq!(region.summit(), None);
+        assert_eq!(region.anchor_offset(DistanceAnchor::Summit, 50), 100);
+        assert_eq!("EDGE".parse::<DistanceAnchor>(), Ok(DistanceAnchor::Edge));
+        assert!("start".parse::<DistanceAnchor>().is_err());
+    }
+
     #[test]
     fn test_transcript_renumber_positive() {
         let mut transcript = Transcript::new("T1".to_string());
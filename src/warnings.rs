@@ -0,0 +1,262 @@
+//! Warnings about the inputs of a run.
+//!
+//! Inputs are read leniently by default: a line that cannot be read is
+//! skipped with a warning, counted per input for the summary at the end of
+//! the run and its exit code. In strict mode such a line is an error
+//! instead. The warnings of the process are gathered in one place, like its
+//! log, so that the parsers report them without passing them around.
+//!
+//! Warnings are counted by kind (e.g. the BED lines skipped for the same
+//! reason) and only the first of each kind is logged, up to a budget for the
+//! run, so that an input with millions of bad lines does not flood stderr.
+//! The repeats are summarized with their counts at the end of the run.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use ahash::AHashMap;
+use anyhow::{bail, Result};
+use tracing::warn;
+
+/// Default number of warnings logged in a run.
+pub const DEFAULT_MAX_WARNINGS: u64 = 100;
+
+/// Warnings of a run, counted per input and per kind.
+pub struct Warnings {
+    strict: AtomicBool,
+    /// Most warnings logged; the others are only counted.
+    max_logged: AtomicU64,
+    state: Mutex<State>,
+}
+
+/// Counts of the warnings of a run.
+struct State {
+    /// Skipped lines per input, in order of their first warning.
+    counts: Vec<(&'static str, u64)>,
+    /// (kind, count, logged) in order of their first warning.
+    kinds: Vec<(String, u64, u64)>,
+    /// Index of each kind in `kinds`, created on the first warning.
+    index: Option<AHashMap<String, usize>>,
+    /// Warnings logged.
+    logged: u64,
+}
+
+impl Warnings {
+    /// Create an empty, lenient set of warnings.
+    pub const fn new() -> Self {
+        Warnings {
+            strict: AtomicBool::new(false),
+            max_logged: AtomicU64::new(DEFAULT_MAX_WARNINGS),
+            state: Mutex::new(State {
+                counts: Vec::new(),
+                kinds: Vec::new(),
+                index: None,
+                logged: 0,
+            }),
+        }
+    }
+
+    /// Make lines that cannot be read errors instead of warnings.
+    pub fn set_strict(&self, strict: bool) {
+        self.strict.store(strict, Ordering::Relaxed);
+    }
+
+    /// Log at most `max` warnings, counting the others.
+    pub fn set_max_warnings(&self, max: u64) {
+        self.max_logged.store(max, Ordering::Relaxed);
+    }
+
+    /// Report a warning of the given kind: counted, and logged with
+    /// `message` if it is the first of its kind and the budget of logged
+    /// warnings is not spent.
+    pub fn warn(&self, kind: &str, message: impl FnOnce() -> String) {
+        let max = self.max_logged.load(Ordering::Relaxed);
+        let (log, spent) = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let state = &mut *state;
+            let index = state.index.get_or_insert_with(AHashMap::new);
+            let i = match index.get(kind) {
+                Some(&i) => i,
+                None => {
+                    index.insert(kind.to_string(), state.kinds.len());
+                    state.kinds.push((kind.to_string(), 0, 0));
+                    state.kinds.len() - 1
+                }
+            };
+            let entry = &mut state.kinds[i];
+            entry.1 += 1;
+            let first = entry.1 == 1;
+            let log = first && state.logged < max;
+            if log {
+                entry.2 += 1;
+                state.logged += 1;
+            }
+            // The budget is announced when it stops the first warning
+            let spent = first && max > 0 && state.logged == max && !log;
+            if spent {
+                state.logged += 1;
+            }
+            (log, spent)
+        };
+        if log {
+            warn!("{}", message());
+        } else if spent {
+            warn!(
+                "Reached --max-warnings {}: further warnings are only counted",
+                max
+            );
+        }
+    }
+
+    /// Report line `line_number` of `source`, a file of the given kind of
+    /// `input`, that cannot be read: skipped with a warning, or an error in
+    /// strict mode.
+    pub fn skip_line(
+        &self,
+        input: &'static str,
+        source: &str,
+        line_number: usize,
+        reason: &str,
+    ) -> Result<()> {
+        if self.strict.load(Ordering::Relaxed) {
+            bail!("{} line {}: {}", source, line_number, reason);
+        }
+        {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            match state.counts.iter_mut().find(|(kind, _)| *kind == input) {
+                Some((_, count)) => *count += 1,
+                None => state.counts.push((input, 1)),
+            }
+        }
+        self.warn(&format!("{} lines: {}", input, reason), || {
+            format!("{} line {}: {}; skipped", source, line_number, reason)
+        });
+        Ok(())
+    }
+
+    /// Skipped lines per input, in order of their first warning.
+    pub fn summary(&self) -> Vec<(&'static str, u64)> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .counts
+            .clone()
+    }
+
+    /// Number of skipped lines.
+    pub fn total(&self) -> u64 {
+        self.summary().iter().map(|(_, count)| count).sum()
+    }
+
+    /// Warnings not logged, as (kind, count, unlogged) in order of their
+    /// first warning.
+    pub fn unlogged(&self) -> Vec<(String, u64, u64)> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .kinds
+            .iter()
+            .filter(|(_, count, logged)| count > logged)
+            .map(|(kind, count, logged)| (kind.clone(), *count, count - logged))
+            .collect()
+    }
+}
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Warnings::new()
+    }
+}
+
+/// Warnings of the process.
+static WARNINGS: Warnings = Warnings::new();
+
+/// Make lines that cannot be read errors instead of warnings.
+pub fn set_strict(strict: bool) {
+    WARNINGS.set_strict(strict);
+}
+
+/// Log at most `max` warnings of the process, counting the others.
+pub fn set_max_warnings(max: u64) {
+    WARNINGS.set_max_warnings(max);
+}
+
+/// Report a warning of the process (see [`Warnings::warn`]).
+pub fn warn(kind: &str, message: impl FnOnce() -> String) {
+    WARNINGS.warn(kind, message);
+}
+
+/// Report a line of an input that cannot be read (see [`Warnings::skip_line`]).
+pub fn skip_line(
+    input: &'static str,
+    source: &str,
+    line_number: usize,
+    reason: &str,
+) -> Result<()> {
+    WARNINGS.skip_line(input, source, line_number, reason)
+}
+
+/// Skipped lines per input of the process.
+pub fn summary() -> Vec<(&'static str, u64)> {
+    WARNINGS.summary()
+}
+
+/// Number of lines skipped by the process.
+pub fn total() -> u64 {
+    WARNINGS.total()
+}
+
+/// Warnings of the process that were not logged (see [`Warnings::unlogged`]).
+pub fn unlogged() -> Vec<(String, u64, u64)> {
+    WARNINGS.unlogged()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warnings() {
+        let warnings = Warnings::new();
+        warnings.skip_line("BED", "a.bed", 3, "bad").unwrap();
+        warnings.skip_line("GTF", "a.gtf", 1, "bad").unwrap();
+        warnings.skip_line("BED", "b.bed", 7, "bad").unwrap();
+        assert_eq!(warnings.summary(), vec![("BED", 2), ("GTF", 1)]);
+        assert_eq!(warnings.total(), 3);
+
+        warnings.set_strict(true);
+        let error = warnings.skip_line("BED", "a.bed", 9, "bad").unwrap_err();
+        assert_eq!(error.to_string(), "a.bed line 9: bad");
+        assert_eq!(warnings.total(), 3);
+    }
+
+    #[test]
+    fn test_repeated_warnings() {
+        let warnings = Warnings::new();
+        for line in 1..=5 {
+            warnings.skip_line("BED", "a.bed", line, "bad").unwrap();
+        }
+        warnings.skip_line("BED", "a.bed", 6, "worse").unwrap();
+        warnings.warn("regions on chrUn", || "chrUn".to_string());
+        assert_eq!(warnings.summary(), vec![("BED", 6)]);
+        // Only the first of each kind is logged
+        assert_eq!(
+            warnings.unlogged(),
+            vec![("BED lines: bad".to_string(), 5, 4)]
+        );
+
+        let warnings = Warnings::new();
+        warnings.set_max_warnings(1);
+        warnings.skip_line("BED", "a.bed", 1, "bad").unwrap();
+        warnings.skip_line("BED", "a.bed", 2, "worse").unwrap();
+        warnings.skip_line("GTF", "a.gtf", 1, "bad").unwrap();
+        assert_eq!(warnings.total(), 3);
+        assert_eq!(
+            warnings.unlogged(),
+            vec![
+                ("BED lines: worse".to_string(), 1, 1),
+                ("GTF lines: bad".to_string(), 1, 1)
+            ]
+        );
+    }
+}
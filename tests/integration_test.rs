@@ -1,6 +1,7 @@
 use assert_cmd::Command;
+use predicates::prelude::PredicateBooleanExt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use tempfile::NamedTempFile;
 
@@ -84,3 +85,3340 @@ fn test_golden_output_transcript() -> Result<(), Box<dyn std::error::Error>> {
 fn test_golden_output_gene() -> Result<(), Box<dyn std::error::Error>> {
     run_golden_test("gene", "subset_golden_output_gene.txt")
 }
+
+/// Every BED region must appear in the output when unmatched regions are reported.
+fn run_report_unmatched_test(threads: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let bed_path = data_dir.join("subset_peaks.bed");
+    let output_file = NamedTempFile::new()?;
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+    cmd.arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg(output_file.path())
+        .arg("-j")
+        .arg(threads)
+        .arg("--report-unmatched")
+        .assert()
+        .success();
+
+    let mut expected = Vec::new();
+    for line in BufReader::new(File::open(&bed_path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        expected.push(format!("{}_{}_{}", fields[0], fields[1], fields[2]));
+    }
+
+    let mut reported: Vec<String> = Vec::new();
+    let mut intergenic = 0;
+    for line in BufReader::new(File::open(output_file.path())?)
+        .lines()
+        .skip(1)
+    {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields[5] == "INTERGENIC" {
+            intergenic += 1;
+            assert_eq!(fields[2], "NA");
+        }
+        if reported.last().map(String::as_str) != Some(fields[0]) {
+            reported.push(fields[0].to_string());
+        }
+    }
+
+    assert_eq!(reported, expected);
+    assert!(intergenic > 0, "expected at least one unmatched region");
+    Ok(())
+}
+
+#[test]
+fn test_report_unmatched_sequential() -> Result<(), Box<dyn std::error::Error>> {
+    run_report_unmatched_test("1")
+}
+
+#[test]
+fn test_report_unmatched_parallel() -> Result<(), Box<dyn std::error::Error>> {
+    run_report_unmatched_test("4")
+}
+
+/// Run rgmatch on the subset data with extra arguments and return the output lines.
+fn run_subset(extra_args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    run_bed(&data_dir.join("subset_peaks.bed"), extra_args)
+}
+
+/// Run rgmatch on the subset GTF with a custom BED file and return the output lines.
+fn run_bed(bed: &Path, extra_args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let output_file = NamedTempFile::new()?;
+
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+    cmd.arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(bed)
+        .arg("-o")
+        .arg(output_file.path())
+        .args(extra_args)
+        .assert()
+        .success();
+
+    let lines = BufReader::new(File::open(output_file.path())?)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines)
+}
+
+#[test]
+fn test_great_model() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--model", "great", "-j", "1"])?;
+    let parallel = run_subset(&["--model", "great", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    assert!(sequential.len() > 1);
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert!(
+            fields[5] == "BASAL" || fields[5] == "EXTENSION",
+            "unexpected area in GREAT mode: {}",
+            line
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_cis_model() -> Result<(), Box<dyn std::error::Error>> {
+    let args = ["--model", "cis", "--cis-window", "100"];
+    let sequential = run_subset(&[&args[..], &["-j", "1"]].concat())?;
+    let parallel = run_subset(&[&args[..], &["-j", "4"]].concat())?;
+    assert_eq!(sequential, parallel);
+
+    assert!(sequential.len() > 1);
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields[5], "CIS", "unexpected area in cis mode: {}", line);
+        let tss_distance: i64 = fields[7].parse()?;
+        assert!(
+            tss_distance.abs() <= 100_000,
+            "outside the window: {}",
+            line
+        );
+        assert_eq!(fields[6].parse::<i64>()?, tss_distance.abs());
+    }
+
+    // A wider window only adds genes
+    let wide = run_subset(&["--model", "cis", "--cis-window", "500"])?;
+    assert!(wide.len() > sequential.len());
+    Ok(())
+}
+
+#[test]
+fn test_merge_input() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--merge-input", "1000", "-j", "1"])?;
+    let parallel = run_subset(&["--merge-input", "1000", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    // Merged regions are sorted and at least the gap apart within a chromosome
+    let mut regions: Vec<(String, i64, i64)> = Vec::new();
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let region: Vec<&str> = fields[0].rsplitn(3, '_').collect();
+        let key = (
+            region[2].to_string(),
+            region[1].parse()?,
+            region[0].parse()?,
+        );
+        if regions.last() != Some(&key) {
+            regions.push(key);
+        }
+    }
+    for pair in regions.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            assert!(pair[1].1 > pair[0].2 + 1000, "{:?}", pair);
+        }
+    }
+
+    let unmerged = run_subset(&[])?;
+    assert!(sequential.len() < unmerged.len());
+    Ok(())
+}
+
+#[test]
+fn test_duplicate_regions() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let peaks: Vec<String> = BufReader::new(File::open(data_dir.join("subset_peaks.bed"))?)
+        .lines()
+        .take(200)
+        .collect::<Result<_, _>>()?;
+
+    // Every region appears twice, once in each half of the file
+    let mut bed = NamedTempFile::new()?;
+    for line in peaks.iter().chain(peaks.iter()) {
+        writeln!(bed, "{}", line)?;
+    }
+    bed.flush()?;
+
+    let keep = run_bed(bed.path(), &["-j", "1"])?;
+    for threads in ["1", "4"] {
+        let replicate = run_bed(bed.path(), &["--duplicates", "replicate", "-j", threads])?;
+        assert_eq!(keep, replicate);
+    }
+
+    let collapse = run_bed(bed.path(), &["--duplicates", "collapse", "-j", "1"])?;
+    assert_eq!(
+        collapse,
+        run_bed(bed.path(), &["--duplicates", "collapse", "-j", "4"])?
+    );
+    assert!(collapse[0].contains("\tCount\t"));
+    assert_eq!((collapse.len() - 1) * 2, keep.len() - 1);
+    for line in collapse.iter().skip(1) {
+        assert_eq!(line.split('\t').nth(10), Some("2"));
+    }
+
+    // Regions on opposite strands are not duplicates when the strand matters
+    let mut stranded = NamedTempFile::new()?;
+    writeln!(stranded, "chr1\t230000\t230100\tplus\t0\t+")?;
+    writeln!(stranded, "chr1\t230000\t230100\tminus\t0\t-")?;
+    stranded.flush()?;
+    let args = ["--same-strand", "--distance", "100"];
+    let keep = run_bed(stranded.path(), &args)?;
+    let collapse = run_bed(
+        stranded.path(),
+        &[&args[..], &["--duplicates", "collapse"]].concat(),
+    )?;
+    assert_eq!(collapse.len(), keep.len());
+    assert!(collapse[1..].iter().any(|line| line.contains("\tminus\t")));
+
+    // Merged regions are never duplicates
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(bed.path())
+        .args(["--merge-input", "--duplicates", "collapse"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn test_gene_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--gene-summary", "-r", "gene", "-j", "1"])?;
+    let parallel = run_subset(&["--gene-summary", "-r", "gene", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    assert_eq!(
+        sequential[0],
+        "Gene\tRegionCount\tRegions\tBestArea\tMinDistance"
+    );
+    let associations = run_subset(&["-r", "gene", "-j", "1"])?;
+    let mut total_regions = 0;
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let count: usize = fields[1].parse()?;
+        assert_eq!(fields[2].split(',').count(), count);
+        total_regions += count;
+    }
+    // At gene level every association is a distinct (region, gene) pair
+    assert_eq!(total_regions, associations.len() - 1);
+    Ok(())
+}
+
+#[test]
+fn test_feature_annotation() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let peaks = data_dir.join("subset_peaks.bed");
+
+    // Every other peak becomes a named feature
+    let mut features = NamedTempFile::new()?;
+    for line in BufReader::new(File::open(&peaks)?).lines().step_by(2) {
+        writeln!(features, "{}", line?)?;
+    }
+    features.flush()?;
+
+    let run = |threads: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output_file = NamedTempFile::new()?;
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("--features")
+            .arg(features.path())
+            .arg("-b")
+            .arg(&peaks)
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["-j", threads])
+            .assert()
+            .success();
+        let lines = BufReader::new(File::open(output_file.path())?)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(lines)
+    };
+
+    let sequential = run("1")?;
+    assert_eq!(sequential, run("4")?);
+
+    let mut overlaps = 0;
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert!(
+            ["OVERLAP", "UPSTREAM", "DOWNSTREAM"].contains(&fields[5]),
+            "unexpected area in feature mode: {}",
+            line
+        );
+        // A region that is itself a feature overlaps it completely
+        if fields[0] == fields[3] {
+            assert_eq!(fields[5], "OVERLAP");
+            overlaps += 1;
+        }
+    }
+    assert_eq!(overlaps, 1200);
+    Ok(())
+}
+
+#[test]
+fn test_promoters_only() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--promoters-only", "-j", "1"])?;
+    let parallel = run_subset(&["--promoters-only", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+    assert!(sequential.len() > 1);
+
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert!(
+            fields[5] == "TSS" || fields[5] == "PROMOTER",
+            "unexpected area in promoters-only mode: {}",
+            line
+        );
+    }
+
+    // Every region-transcript pair the full matcher reports as TSS/PROMOTER is
+    // found (areas may differ, as windows use the exact overlap rather than
+    // the midpoint distance), while windows of other transcripts add more
+    let key = |line: &String| -> String {
+        let fields: Vec<&str> = line.split('\t').collect();
+        [fields[0], fields[3]].join("\t")
+    };
+    let windows: std::collections::HashSet<String> = sequential.iter().map(key).collect();
+    for line in run_subset(&["-j", "1"])?.iter().skip(1) {
+        let area = line.split('\t').nth(5);
+        if area == Some("TSS") || area == Some("PROMOTER") {
+            assert!(
+                windows.contains(&key(line)),
+                "missing in promoters-only mode: {}",
+                line
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_partial_rules() -> Result<(), Box<dyn std::error::Error>> {
+    // Unlisted areas are appended in default order
+    let full = run_subset(&["-r", "transcript"])?;
+    let partial = run_subset(&["-r", "transcript", "-R", "TSS,1st_EXON"])?;
+    assert_eq!(full, partial);
+
+    let exclusive = run_subset(&["-R", "TSS,PROMOTER", "--exclusive"])?;
+    assert!(exclusive.len() > 1);
+    for line in exclusive.iter().skip(1) {
+        let area = line.split('\t').nth(5).unwrap();
+        assert!(
+            area == "TSS" || area == "PROMOTER",
+            "unexpected area with exclusive rules: {}",
+            line
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_biotype_rules() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+
+    // Gene biotypes of the subset GTF
+    let mut lncrna = std::collections::HashSet::new();
+    for line in BufReader::new(File::open(data_dir.join("subset_genome.gtf"))?).lines() {
+        let line = line?;
+        if line.contains("\tgene\t") && line.contains("gene_type \"lncRNA\"") {
+            let gene_id = line.split('"').nth(1).unwrap().to_string();
+            lncrna.insert(gene_id);
+        }
+    }
+    assert!(!lncrna.is_empty());
+
+    // A biotype without genes leaves the output unchanged
+    let mut unused = NamedTempFile::new()?;
+    writeln!(unused, "[snoRNA_missing]\ndistance = 50")?;
+    let unused_path = unused.path().to_str().unwrap();
+    assert_eq!(
+        run_subset(&["--biotype-rules", unused_path])?,
+        run_subset(&[])?
+    );
+
+    let mut rules = NamedTempFile::new()?;
+    writeln!(
+        rules,
+        "[lncRNA]\nrules = \"TSS,PROMOTER\"\nexclusive = true"
+    )?;
+    let rules_path = rules.path().to_str().unwrap();
+    let sequential = run_subset(&["--biotype-rules", rules_path, "-j", "1"])?;
+    let parallel = run_subset(&["--biotype-rules", rules_path, "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    let mut other_areas = 0;
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if lncrna.contains(fields[2]) {
+            assert!(
+                fields[5] == "TSS" || fields[5] == "PROMOTER",
+                "unexpected area for a lncRNA: {}",
+                line
+            );
+        } else if fields[5] != "TSS" && fields[5] != "PROMOTER" {
+            other_areas += 1;
+        }
+    }
+    // Other biotypes keep the default rules
+    assert!(other_areas > 0);
+    Ok(())
+}
+
+#[test]
+fn test_report_all() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--report-all", "-j", "1"])?;
+    let parallel = run_subset(&["--report-all", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    // Without filters every candidate is already reported at exon level
+    assert_eq!(sequential, run_subset(&["-r", "all"])?);
+    assert_eq!(sequential, run_subset(&[])?);
+    assert!(sequential.len() > run_subset(&["-r", "transcript"])?.len());
+    Ok(())
+}
+
+#[test]
+fn test_confidence_column() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--confidence", "-j", "1"])?;
+    let parallel = run_subset(&["--confidence", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    let header: Vec<&str> = sequential[0].split('\t').collect();
+    assert_eq!(header[10], "Confidence");
+    for line in sequential.iter().skip(1) {
+        let score: f64 = line.split('\t').nth(10).unwrap().parse()?;
+        assert!((0.0..=1.0).contains(&score), "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_gene_extent() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--gene-extent", "-j", "1"])?;
+    let parallel = run_subset(&["--gene-extent", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+    assert!(sequential.len() > 1);
+
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert!(
+            matches!(fields[5], "OVERLAP_GENE" | "UPSTREAM" | "DOWNSTREAM"),
+            "unexpected area in gene-extent mode: {}",
+            line
+        );
+        // The single transcript of each gene is named after it
+        assert_eq!(fields[2], fields[3]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_gene_coverage_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let plain = run_subset(&[])?;
+    let covered = run_subset(&["--gene-coverage"])?;
+    assert_eq!(plain.len(), covered.len());
+    assert!(covered[0].contains("PercArea\tPercGene\tPercTranscript"));
+
+    for (line, covered_line) in plain.iter().zip(&covered).skip(1) {
+        let fields: Vec<&str> = covered_line.split('\t').collect();
+        assert_eq!(
+            fields[..10].join("\t"),
+            line.split('\t').take(10).collect::<Vec<_>>().join("\t")
+        );
+        for value in &fields[10..12] {
+            if fields[5] == "INTERGENIC" {
+                assert_eq!(*value, "NA");
+            } else {
+                let pctg: f64 = value.parse()?;
+                assert!(
+                    (0.0..=100.0).contains(&pctg),
+                    "bad coverage: {}",
+                    covered_line
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_biotype_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let plain = run_subset(&["-r", "gene"])?;
+    let typed = run_subset(&["-r", "gene", "--biotypes"])?;
+    assert_eq!(plain.len(), typed.len());
+    assert!(typed[0].contains("PercArea\tGeneType\tTranscriptType\tname"));
+
+    for line in &typed[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        // Every gene and transcript of the subset GTF has a type
+        assert_ne!(fields[10], "NA", "missing gene type: {}", line);
+        let types: Vec<&str> = fields[11].split(',').collect();
+        assert_eq!(types.len(), fields[3].split(',').count());
+        assert!(!types.contains(&"NA"), "missing transcript type: {}", line);
+        if fields[2] == "ENSG00000290825.2" {
+            assert_eq!(fields[10], "lncRNA");
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_exon_count_column() -> Result<(), Box<dyn std::error::Error>> {
+    let lines = run_subset(&["--area-coordinates", "--exon-count"])?;
+    assert!(lines[0].contains("PercArea\tAreaStart\tAreaEnd\tExonCount\tname"));
+
+    for line in &lines[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let count: usize = fields[12].parse()?;
+        // Regions spanning several exons or introns list them all
+        for number in fields[4].split(',') {
+            let number: usize = number.parse()?;
+            // Introns lie between two exons of the transcript
+            match fields[5] {
+                "INTRON" => assert!(number < count, "{}", line),
+                _ => assert!((1..=count).contains(&number), "{}", line),
+            }
+        }
+        let (start, end): (i64, i64) = (fields[10].parse()?, fields[11].parse()?);
+        assert!(start <= end, "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_overlap_bp_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let lines = run_subset(&["--gene-coverage", "--overlap-bp"])?;
+    assert!(lines[0].contains("PercTranscript\tOverlapArea\tOverlapGene"));
+
+    for line in lines.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields[5] == "INTERGENIC" {
+            continue;
+        }
+        let region: Vec<i64> = fields[0]
+            .rsplitn(3, '_')
+            .take(2)
+            .map(|v| v.parse())
+            .collect::<Result<_, _>>()?;
+        let length = region[0] - region[1] + 1;
+
+        let overlap_area: i64 = fields[12].parse()?;
+        let overlap_gene: i64 = fields[13].parse()?;
+        assert!((0..=length).contains(&overlap_area), "{}", line);
+        assert!((0..=length).contains(&overlap_gene), "{}", line);
+        // Both describe the same overlap with the gene
+        let pctg_gene: f64 = fields[10].parse()?;
+        assert_eq!(overlap_gene > 0, pctg_gene > 0.0, "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_gene_summary_weights() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let peaks: Vec<String> = BufReader::new(File::open(data_dir.join("subset_peaks.bed"))?)
+        .lines()
+        .collect::<Result<_, _>>()?;
+
+    // Column 7 holds a score, higher for later peaks
+    let mut bed = NamedTempFile::new()?;
+    for (i, peak) in peaks.iter().enumerate() {
+        writeln!(bed, "{}\t{}", peak, i)?;
+    }
+    bed.flush()?;
+
+    let args = ["--gene-summary", "--weight-column", "7"];
+    let sequential = run_bed(bed.path(), &[&args[..], &["-j", "1"]].concat())?;
+    let parallel = run_bed(bed.path(), &[&args[..], &["-j", "4"]].concat())?;
+    assert_eq!(sequential, parallel);
+    assert!(sequential[0].ends_with("\tMinDistance\tWeight"));
+
+    // Genes are ranked by decreasing total weight
+    let weights: Vec<f64> = sequential
+        .iter()
+        .skip(1)
+        .map(|l| l.rsplit('\t').next().unwrap().parse())
+        .collect::<Result<_, _>>()?;
+    assert!(weights.len() > 1);
+    assert!(weights.windows(2).all(|w| w[0] >= w[1]));
+
+    // Same genes as the unweighted summary
+    let mut genes: Vec<String> = sequential
+        .iter()
+        .skip(1)
+        .map(|l| l.split('\t').next().unwrap().to_string())
+        .collect();
+    let mut plain: Vec<String> = run_bed(bed.path(), &["--gene-summary"])?
+        .iter()
+        .skip(1)
+        .map(|l| l.split('\t').next().unwrap().to_string())
+        .collect();
+    let ranked = genes.clone();
+    genes.sort();
+    plain.sort();
+    assert_eq!(genes, plain);
+
+    // The gene table ranks genes the same way, without --gene-summary
+    let dir = tempfile::tempdir()?;
+    let table = dir.path().join("genes.tsv");
+    run_bed(
+        bed.path(),
+        &[
+            "--weight-column",
+            "7",
+            "--gene-table",
+            table.to_str().unwrap(),
+        ],
+    )?;
+    let table = std::fs::read_to_string(&table)?;
+    assert!(table
+        .lines()
+        .next()
+        .unwrap()
+        .ends_with("\tTotalScore\tWeight"));
+    let table_genes: Vec<&str> = table
+        .lines()
+        .skip(1)
+        .map(|l| l.split('\t').next().unwrap())
+        .collect();
+    assert_eq!(table_genes, ranked);
+
+    // Rows of a gene at equal distance are ranked heaviest first
+    let args = [
+        "--weight-column",
+        "7",
+        "--sort-output",
+        "gene-distance",
+        "--gene-rank",
+    ];
+    let sorted = run_bed(bed.path(), &args)?;
+    assert!(sorted.len() > 1);
+    for pair in sorted[1..].windows(2) {
+        let [a, b] = [&pair[0], &pair[1]].map(|l| l.split('\t').collect::<Vec<_>>());
+        if a[2] == b[2] && a[6] == b[6] && a[2] != "NA" {
+            assert!(a[14].parse::<f64>()? >= b[14].parse::<f64>()?);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bedpe_loops() -> Result<(), Box<dyn std::error::Error>> {
+    use std::collections::{HashMap, HashSet};
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let peaks: Vec<String> = BufReader::new(File::open(data_dir.join("subset_peaks.bed"))?)
+        .lines()
+        .take(60)
+        .collect::<Result<_, _>>()?;
+
+    // Join consecutive peaks into loops
+    let mut bedpe = NamedTempFile::new()?;
+    for (i, pair) in peaks.windows(2).enumerate() {
+        let a: Vec<&str> = pair[0].split('\t').collect();
+        let b: Vec<&str> = pair[1].split('\t').collect();
+        writeln!(
+            bedpe,
+            "{}\t{}\tloop{}\t0",
+            a[..3].join("\t"),
+            b[..3].join("\t"),
+            i
+        )?;
+    }
+    bedpe.flush()?;
+
+    let lines = run_bed(bedpe.path(), &["--bedpe"])?;
+    assert_eq!(
+        lines[0],
+        "Anchor1\tAnchor2\tGene1\tArea1\tDistance1\tGene2\tArea2\tDistance2\tClass\tname\tscore"
+    );
+
+    // Anchor genes are those of the regular annotation of each peak
+    let mut genes: HashMap<String, HashSet<String>> = HashMap::new();
+    for line in run_subset(&[])?.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        genes
+            .entry(fields[0].to_string())
+            .or_default()
+            .insert(fields[2].to_string());
+    }
+
+    let mut loops = HashSet::new();
+    for line in lines.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields.len(), 11);
+        loops.insert(fields[9].to_string());
+        for (anchor, gene) in [(fields[0], fields[2]), (fields[1], fields[5])] {
+            assert!(
+                gene == "NA" || genes.get(anchor).is_some_and(|g| g.contains(gene)),
+                "unexpected gene for anchor: {}",
+                line
+            );
+        }
+        let promoters = [fields[3], fields[6]]
+            .iter()
+            .filter(|area| matches!(**area, "TSS" | "PROMOTER"))
+            .count();
+        let expected = [
+            "enhancer-enhancer",
+            "promoter-enhancer",
+            "promoter-promoter",
+        ];
+        assert_eq!(fields[8], expected[promoters], "bad class: {}", line);
+    }
+    // Every loop is reported
+    assert_eq!(loops.len(), peaks.len() - 1);
+    Ok(())
+}
+
+#[test]
+fn test_asymmetric_distances() -> Result<(), Box<dyn std::error::Error>> {
+    let symmetric = run_subset(&["-q", "10"])?;
+    let split = run_subset(&["--upstream-distance", "10", "--downstream-distance", "10"])?;
+    assert_eq!(symmetric, split);
+
+    let args = ["--upstream-distance", "50", "--downstream-distance", "1"];
+    let sequential = run_subset(&[&args[..], &["-j", "1"]].concat())?;
+    let parallel = run_subset(&[&args[..], &["-j", "4"]].concat())?;
+    assert_eq!(sequential, parallel);
+
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let distance: i64 = fields[6].parse()?;
+        match fields[5] {
+            "DOWNSTREAM" | "TTS" => assert!(distance <= 1000, "too far: {}", line),
+            _ => assert!(distance <= 50_000, "too far: {}", line),
+        }
+    }
+    assert!(sequential.iter().any(|line| line
+        .split('\t')
+        .nth(6)
+        .and_then(|d| d.parse::<i64>().ok())
+        > Some(10_000)));
+    Ok(())
+}
+
+#[test]
+fn test_distance_column() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let peaks: Vec<String> = BufReader::new(File::open(data_dir.join("subset_peaks.bed"))?)
+        .lines()
+        .collect::<Result<_, _>>()?;
+
+    // Column 7 holds the distance of each region in kb
+    let with_distances =
+        |distance: &dyn Fn(usize) -> &'static str| -> std::io::Result<NamedTempFile> {
+            let mut bed = NamedTempFile::new()?;
+            for (i, peak) in peaks.iter().enumerate() {
+                writeln!(bed, "{}\t{}", peak, distance(i))?;
+            }
+            bed.flush()?;
+            Ok(bed)
+        };
+
+    // The same distance everywhere matches --distance
+    let bed = with_distances(&|_| "10")?;
+    let trim = |lines: Vec<String>| -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| l.split('\t').take(10).collect::<Vec<_>>().join("\t"))
+            .collect()
+    };
+    assert_eq!(
+        trim(run_bed(bed.path(), &["--distance-column", "7"])?),
+        trim(run_subset(&[])?)
+    );
+
+    // Mixed distances are honored per region, in both execution modes
+    let bed = with_distances(&|i| if i % 2 == 0 { "100" } else { "1" })?;
+    let sequential = run_bed(bed.path(), &["--distance-column", "7", "-j", "1"])?;
+    let parallel = run_bed(bed.path(), &["--distance-column", "7", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    let mut far = false;
+    for line in sequential.iter().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let limit: i64 = fields[13].parse::<i64>()? * 1000;
+        let distance: i64 = fields[6].parse()?;
+        assert!(distance <= limit, "beyond the region distance: {}", line);
+        far |= distance > 10_000;
+    }
+    assert!(far);
+    Ok(())
+}
+
+#[test]
+fn test_distance_column_duplicates() -> Result<(), Box<dyn std::error::Error>> {
+    // Same coordinates, but only the second copy reaches the nearest gene
+    let mut bed = NamedTempFile::new()?;
+    writeln!(bed, "chr1\t230000\t230100\tnear\t0\t.\t0.001")?;
+    writeln!(bed, "chr1\t230000\t230100\tfar\t0\t.\t500")?;
+    bed.flush()?;
+
+    let keep = run_bed(bed.path(), &["--distance-column", "7", "-j", "1"])?;
+    assert!(keep.len() > 1);
+    assert!(keep.iter().skip(1).all(|line| line.contains("\tfar\t")));
+    for threads in ["1", "4"] {
+        let replicate = run_bed(
+            bed.path(),
+            &[
+                "--distance-column",
+                "7",
+                "--duplicates",
+                "replicate",
+                "-j",
+                threads,
+            ],
+        )?;
+        assert_eq!(keep, replicate);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_links() -> Result<(), Box<dyn std::error::Error>> {
+    // One enhancer over the first peak, one far from every peak
+    let mut links = NamedTempFile::new()?;
+    writeln!(links, "chrom\tstart\tend\tgene")?;
+    writeln!(links, "chr1\t10000\t10300\tLINKED_GENE")?;
+    writeln!(links, "chr1\t1\t50\tNOWHERE")?;
+    links.flush()?;
+    let path = links.path().to_str().unwrap();
+
+    let sequential = run_subset(&["--links", path, "-j", "1"])?;
+    let parallel = run_subset(&["--links", path, "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    let linked: Vec<&String> = sequential
+        .iter()
+        .filter(|l| l.split('\t').nth(5) == Some("LINKED"))
+        .collect();
+    assert_eq!(linked.len(), 1);
+    let fields: Vec<&str> = linked[0].split('\t').collect();
+    assert_eq!(fields[0], "chr1_10033_10250");
+    assert_eq!(fields[2], "LINKED_GENE");
+    assert_eq!(fields[8], "100.00");
+
+    // Positional associations are unchanged
+    let positional: Vec<&String> = sequential
+        .iter()
+        .filter(|l| l.split('\t').nth(5) != Some("LINKED"))
+        .collect();
+    let plain = run_subset(&[])?;
+    assert_eq!(positional, plain.iter().collect::<Vec<_>>());
+    Ok(())
+}
+
+#[test]
+fn test_tss_cluster() -> Result<(), Box<dyn std::error::Error>> {
+    let sequential = run_subset(&["--tss-cluster", "500", "-j", "1"])?;
+    let parallel = run_subset(&["--tss-cluster", "500", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+
+    // Near-duplicate transcripts no longer yield their own rows
+    let plain = run_subset(&[])?;
+    assert!(sequential.len() < plain.len());
+
+    // A zero window only merges transcripts sharing the same TSS
+    let exact = run_subset(&["--tss-cluster", "0"])?;
+    assert!(exact.len() >= sequential.len());
+    Ok(())
+}
+
+#[test]
+fn test_tss_distance_list() -> Result<(), Box<dyn std::error::Error>> {
+    let lists = run_subset(&["-r", "gene", "--tss-distance-list"])?;
+    let plain = run_subset(&["-r", "gene"])?;
+    assert_eq!(lists.len(), plain.len());
+
+    let mut merged = 0;
+    for (line, plain_line) in lists.iter().zip(&plain).skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let plain_fields: Vec<&str> = plain_line.split('\t').collect();
+        // One distance per merged transcript, the first being the reported one
+        let distances: Vec<&str> = fields[7].split(',').collect();
+        assert_eq!(distances.len(), fields[3].split(',').count(), "{}", line);
+        assert_eq!(distances[0], plain_fields[7]);
+        if distances.len() > 1 {
+            merged += 1;
+        }
+    }
+    assert!(merged > 0);
+    Ok(())
+}
+
+#[test]
+fn test_circular() -> Result<(), Box<dyn std::error::Error>> {
+    // Regions near both ends of a 900 kb chr1
+    let mut bed = NamedTempFile::new()?;
+    writeln!(bed, "chr1\t500\t600")?;
+    writeln!(bed, "chr1\t899000\t899100")?;
+    bed.flush()?;
+    let mut sizes = NamedTempFile::new()?;
+    writeln!(sizes, "chr1\t900000")?;
+    sizes.flush()?;
+    let sizes = sizes.path().to_str().unwrap();
+
+    let rows = |lines: &[String]| -> Vec<(String, String, String, String)> {
+        lines
+            .iter()
+            .skip(1)
+            .map(|l| {
+                let f: Vec<&str> = l.split('\t').collect();
+                (f[0].into(), f[2].into(), f[5].into(), f[6].into())
+            })
+            .collect()
+    };
+    let linear = rows(&run_bed(bed.path(), &["-q", "20", "--chrom-sizes", sizes])?);
+    let circular = rows(&run_bed(
+        bed.path(),
+        &["-q", "20", "--chrom-sizes", sizes, "--circular", "chr1"],
+    )?);
+
+    // Past the end of the contig, the first gene is closer than the last one
+    assert_eq!(
+        linear[1],
+        (
+            "chr1_899000_899100".into(),
+            "ENSG00000296088.1".into(),
+            "DOWNSTREAM".into(),
+            "15594".into()
+        )
+    );
+    assert_eq!(
+        circular[1],
+        (
+            "chr1_899000_899100".into(),
+            "ENSG00000290825.2".into(),
+            "UPSTREAM".into(),
+            "12071".into()
+        )
+    );
+    // Near the origin, the wrapped last gene is farther than the first one
+    assert_eq!(linear[0], circular[0]);
+    Ok(())
+}
+
+#[test]
+fn test_report_levels() -> Result<(), Box<dyn std::error::Error>> {
+    let combined = run_subset(&["-r", "levels"])?;
+    assert!(combined[0].contains("\tPercArea\tLevel"));
+
+    for level in ["exon", "transcript", "gene"] {
+        // The rows of each level match a separate run at that level
+        let rows: Vec<String> = combined
+            .iter()
+            .skip(1)
+            .filter_map(|line| {
+                let mut fields: Vec<&str> = line.split('\t').collect();
+                (fields.remove(10) == level).then(|| fields.join("\t"))
+            })
+            .collect();
+        let separate = run_subset(&["-r", level])?;
+        assert_eq!(rows, separate[1..], "level {}", level);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_best_per_region() -> Result<(), Box<dyn std::error::Error>> {
+    let best = run_subset(&["-r", "gene", "--best-per-region"])?;
+    let genes = run_subset(&["-r", "gene"])?;
+
+    let regions = |lines: &[String]| -> Vec<String> {
+        let mut regions: Vec<String> = lines
+            .iter()
+            .skip(1)
+            .map(|l| l.split('\t').next().unwrap().to_string())
+            .collect();
+        regions.dedup();
+        regions
+    };
+    // One row per region, picked among its gene-level rows
+    assert_eq!(best.len() - 1, regions(&best).len());
+    assert_eq!(regions(&best), regions(&genes));
+    assert!(best.len() < genes.len());
+    for line in best.iter().skip(1) {
+        assert!(genes.contains(line), "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_diff_gtf() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let gtf = data_dir.join("subset_genome.gtf");
+    let diff_gtf = |new_gtf: &Path| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = NamedTempFile::new()?;
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("diff-gtf")
+            .arg("-g")
+            .arg(&gtf)
+            .arg("--new-gtf")
+            .arg(new_gtf)
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .arg("-o")
+            .arg(output.path())
+            .args(["-r", "gene"])
+            .assert()
+            .success();
+        Ok(std::fs::read_to_string(output.path())?
+            .lines()
+            .map(str::to_string)
+            .collect())
+    };
+
+    // Against the same annotation nothing changes
+    let same = diff_gtf(&gtf)?;
+    assert_eq!(same.len(), 1);
+    assert!(same[0].starts_with("Region\tGene\tOldArea\tNewArea\tChange"));
+
+    // Retire the first reported gene in the new annotation
+    let genes = run_subset(&["-r", "gene"])?;
+    let retired = genes[1].split('\t').nth(2).unwrap().to_string();
+    let mut new_gtf = NamedTempFile::new()?;
+    for line in BufReader::new(File::open(&gtf)?).lines() {
+        let line = line?;
+        if !line.contains(&format!("gene_id \"{}\";", retired)) {
+            writeln!(new_gtf, "{}", line)?;
+        }
+    }
+    new_gtf.flush()?;
+
+    let diff = diff_gtf(new_gtf.path())?;
+    let lost: Vec<&String> = diff
+        .iter()
+        .filter(|l| l.split('\t').nth(1) == Some(retired.as_str()))
+        .collect();
+    assert!(!lost.is_empty());
+    for line in lost {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!((fields[3], fields[4]), ("NA", "LOST"), "{}", line);
+    }
+
+    // The annotate options that do not apply to the comparison are rejected
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("diff-gtf")
+        .arg("-g")
+        .arg(&gtf)
+        .arg("--new-gtf")
+        .arg(&gtf)
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .arg("--gene-summary")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--gene-summary cannot be used with diff-gtf",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_compare_bed() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let peaks = data_dir.join("subset_peaks.bed");
+
+    // Against itself every gene is shared with identical counts
+    let same = run_subset(&["--compare-bed", peaks.to_str().unwrap()])?;
+    assert_eq!(
+        same[0],
+        "Gene\tStatus\tRegionCount\tCompareRegionCount\tAreas\tCompareAreas"
+    );
+    assert!(same.len() > 1);
+    for line in &same[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields[1], "SHARED", "{}", line);
+        assert_eq!(fields[2], fields[3], "{}", line);
+        assert_eq!(fields[4], fields[5], "{}", line);
+    }
+
+    // Against no regions every gene is gained
+    let empty = NamedTempFile::new()?;
+    let gained = run_subset(&["--compare-bed", empty.path().to_str().unwrap()])?;
+    assert_eq!(gained.len(), same.len());
+    for line in &gained[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!((fields[1], fields[3], fields[5]), ("GAINED", "0", "NA"));
+    }
+
+    // Swapping the sets turns them into lost genes
+    let lost = run_bed(empty.path(), &["--compare-bed", peaks.to_str().unwrap()])?;
+    assert_eq!(lost.len(), same.len());
+    assert!(lost[1..]
+        .iter()
+        .all(|line| line.split('\t').nth(1) == Some("LOST")));
+    Ok(())
+}
+
+#[test]
+fn test_background_enrichment() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+
+    // Chromosomes extend 1 Mb past the last peak
+    let mut ends: Vec<(String, i64)> = Vec::new();
+    for line in BufReader::new(File::open(data_dir.join("subset_peaks.bed"))?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        let end: i64 = fields[2].parse()?;
+        match ends.iter_mut().find(|(chrom, _)| chrom == fields[0]) {
+            Some(entry) => entry.1 = entry.1.max(end),
+            None => ends.push((fields[0].to_string(), end)),
+        }
+    }
+    let mut sizes = NamedTempFile::new()?;
+    for (chrom, end) in &ends {
+        writeln!(sizes, "{}\t{}", chrom, end + 1_000_000)?;
+    }
+    sizes.flush()?;
+    let sizes = sizes.path().to_str().unwrap();
+
+    let args = ["--chrom-sizes", sizes, "--background", "5"];
+    let lines = run_subset(&args)?;
+    assert_eq!(lines[0], "Area\tObserved\tExpected\tFoldEnrichment\tPValue");
+    assert_eq!(lines[1].split('\t').next(), Some("TSS"));
+
+    let mut observed = 0;
+    for line in &lines[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        observed += fields[1].parse::<usize>()?;
+        let p_value: f64 = fields[4].parse()?;
+        assert!(p_value > 0.0 && p_value <= 1.0, "{}", line);
+    }
+    // Every region counts once
+    assert_eq!(observed, 2400);
+
+    // The background is reproducible, and changes with the seed
+    assert_eq!(run_subset(&args)?, lines);
+    let seeded = run_subset(&[&args[..], &["--seed", "7"]].concat())?;
+    assert_ne!(seeded, lines);
+    assert_eq!(run_subset(&[&args[..], &["--seed=7"]].concat())?, seeded);
+    Ok(())
+}
+
+#[test]
+fn test_gene_sets() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let sets = dir.path().join("sets");
+    let lines = run_subset(&["-r", "gene", "--gene-sets", sets.to_str().unwrap()])?;
+
+    // The table holds each gene-area pair of the main output once
+    let mut expected: Vec<String> = Vec::new();
+    for line in &lines[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let pair = format!("{}\t{}", fields[2], fields[5]);
+        if !expected.contains(&pair) {
+            expected.push(pair);
+        }
+    }
+    let table = std::fs::read_to_string(sets.join("gene_areas.tsv"))?;
+    let table: Vec<&str> = table.lines().collect();
+    assert_eq!(table[0], "Gene\tArea");
+    let mut pairs: Vec<&str> = table[1..].to_vec();
+    let mut expected: Vec<&str> = expected.iter().map(String::as_str).collect();
+    pairs.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(pairs, expected);
+
+    let tss = std::fs::read_to_string(sets.join("TSS.txt"))?;
+    assert!(tss
+        .lines()
+        .all(|gene| expected.contains(&format!("{}\tTSS", gene).as_str())));
+
+    // The sequential path writes the same sets
+    let sequential = dir.path().join("sequential");
+    run_subset(&[
+        "-r",
+        "gene",
+        "-j",
+        "1",
+        "--gene-sets",
+        sequential.to_str().unwrap(),
+    ])?;
+    assert_eq!(
+        std::fs::read_to_string(sequential.join("gene_areas.tsv"))?,
+        std::fs::read_to_string(sets.join("gene_areas.tsv"))?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_by_area() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let split = dir.path().join("areas");
+    let lines = run_subset(&["--split-by-area", split.to_str().unwrap()])?;
+
+    // Each file holds the rows of its area, in the order of the main output
+    let mut areas: Vec<&str> = lines[1..]
+        .iter()
+        .map(|line| line.split('\t').nth(5).unwrap())
+        .collect();
+    areas.sort_unstable();
+    areas.dedup();
+    for area in &areas {
+        let file = std::fs::read_to_string(split.join(format!("{}.tsv", area)))?;
+        let rows: Vec<&str> = file.lines().collect();
+        assert_eq!(rows[0], lines[0]);
+        let expected: Vec<&str> = lines[1..]
+            .iter()
+            .map(String::as_str)
+            .filter(|line| line.split('\t').nth(5) == Some(area))
+            .collect();
+        assert_eq!(rows[1..], expected[..]);
+    }
+    assert_eq!(std::fs::read_dir(&split)?.count(), areas.len());
+
+    // The split needs the per-association output
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .arg("--gene-summary")
+        .arg("--split-by-area")
+        .arg(&split)
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_output() -> Result<(), Box<dyn std::error::Error>> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let output_file = NamedTempFile::new()?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .arg("-o")
+        .arg(output_file.path())
+        .args(["-r", "gene", "--format", "parquet"])
+        .assert()
+        .success();
+
+    // Same rows and columns as the text output
+    let lines = run_subset(&["-r", "gene"])?;
+    let reader = SerializedFileReader::new(File::open(output_file.path())?)?;
+    let metadata = reader.metadata().file_metadata();
+    assert_eq!(metadata.num_rows() as usize, lines.len() - 1);
+    let names: Vec<&str> = metadata
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|c| c.name())
+        .collect();
+    assert_eq!(names.join("\t"), lines[0]);
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_output() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let output_file = NamedTempFile::new()?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .arg("-o")
+        .arg(output_file.path())
+        .args(["-r", "gene", "--format", "sqlite"])
+        .assert()
+        .success();
+
+    let lines = run_subset(&["-r", "gene"])?;
+    let mut genes: Vec<&str> = lines[1..]
+        .iter()
+        .map(|l| l.split('\t').nth(2).unwrap())
+        .collect();
+    genes.sort_unstable();
+    genes.dedup();
+
+    let conn = rusqlite::Connection::open(output_file.path())?;
+    let count = |sql: &str| -> rusqlite::Result<i64> { conn.query_row(sql, [], |row| row.get(0)) };
+    assert_eq!(
+        count("SELECT COUNT(*) FROM associations")? as usize,
+        lines.len() - 1
+    );
+    assert_eq!(count("SELECT COUNT(*) FROM genes")? as usize, genes.len());
+    assert_eq!(
+        count("SELECT SUM(Associations) FROM regions")? as usize,
+        lines.len() - 1
+    );
+    Ok(())
+}
+
+#[test]
+fn test_annotated_bed_output() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let bed_lines = BufReader::new(File::open(data_dir.join("subset_peaks.bed"))?)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let table = run_subset(&["-r", "gene"])?;
+    let lines = run_subset(&["-r", "gene", "--format", "bed"])?;
+    // One line per association, without a header
+    assert_eq!(lines.len(), table.len() - 1);
+
+    for (line, row) in lines.iter().zip(&table[1..]) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let row: Vec<&str> = row.split('\t').collect();
+        let n = fields.len();
+        // The input BED line is kept verbatim
+        let bed_line = fields[..n - 3].join("\t");
+        assert!(bed_lines.contains(&bed_line), "{}", line);
+        assert_eq!(fields[n - 3..], [row[2], row[5], row[6]]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_gff3_output() -> Result<(), Box<dyn std::error::Error>> {
+    let table = run_subset(&["-r", "gene"])?;
+    let lines = run_subset(&["-r", "gene", "--format", "gff3"])?;
+    assert_eq!(lines[0], "##gff-version 3");
+    assert_eq!(lines.len(), table.len());
+
+    for (line, row) in lines[1..].iter().zip(&table[1..]) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let row: Vec<&str> = row.split('\t').collect();
+        assert_eq!(fields.len(), 9);
+        assert_eq!(format!("{}_{}_{}", fields[0], fields[3], fields[4]), row[0]);
+        assert!(fields[8].contains(&format!("gene={};", row[2])), "{}", line);
+        assert!(
+            fields[8].contains(&format!(";area={};", row[5])),
+            "{}",
+            line
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bgzip_output() -> Result<(), Box<dyn std::error::Error>> {
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("out.tsv.gz");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .arg("-o")
+        .arg(&output)
+        .args(["-r", "gene", "--bgzip"])
+        .assert()
+        .success();
+
+    let mut text = String::new();
+    MultiGzDecoder::new(File::open(&output)?).read_to_string(&mut text)?;
+    let lines: Vec<&str> = text.lines().collect();
+    let table = run_subset(&["-r", "gene"])?;
+    assert_eq!(lines.len(), table.len());
+
+    // The region coordinates come first, then the usual columns
+    assert_eq!(lines[0], format!("#Chrom\tStart\tEnd\t{}", table[0]));
+    for (line, row) in lines[1..].iter().zip(&table[1..]) {
+        let fields: Vec<&str> = line.splitn(4, '\t').collect();
+        assert_eq!(
+            format!("{}_{}_{}", fields[0], fields[1], fields[2]),
+            row.split('\t').next().unwrap()
+        );
+        assert_eq!(fields[3], row);
+    }
+
+    let mut index = Vec::new();
+    MultiGzDecoder::new(File::open(dir.path().join("out.tsv.gz.tbi"))?).read_to_end(&mut index)?;
+    assert_eq!(&index[..4], b"TBI\x01");
+
+    // Unsorted regions cannot be indexed
+    let unsorted = dir.path().join("unsorted.bed");
+    std::fs::write(&unsorted, "chr1\t5000\t5100\nchr1\t1000\t1100\n")?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(&unsorted)
+        .arg("-o")
+        .arg(dir.path().join("unsorted.gz"))
+        .args(["--bgzip", "--report-unmatched"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("sorted by chromosome and start"));
+    Ok(())
+}
+
+#[test]
+fn test_stdout_output() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let expected = run_subset(&["-r", "gene"])?;
+
+    for (threads, output) in [("1", None), ("4", Some("-"))] {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .args(["-r", "gene", "-j", threads]);
+        if let Some(output) = output {
+            cmd.args(["-o", output]);
+        }
+        let stdout = cmd.assert().success().get_output().stdout.clone();
+        let lines: Vec<&str> = std::str::from_utf8(&stdout)?.lines().collect();
+        assert_eq!(lines, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_column_selection() -> Result<(), Box<dyn std::error::Error>> {
+    let table = run_subset(&["-r", "gene"])?;
+    let lines = run_subset(&["-r", "gene", "--columns", "Area,Region,Gene:gene_id,name"])?;
+    assert_eq!(lines[0], "Area\tRegion\tgene_id\tname");
+    assert_eq!(lines.len(), table.len());
+    for (line, row) in lines[1..].iter().zip(&table[1..]) {
+        let row: Vec<&str> = row.split('\t').collect();
+        assert_eq!(
+            *line,
+            format!("{}\t{}\t{}\t{}", row[5], row[0], row[2], row[10])
+        );
+    }
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .args(["--columns", "Region,Level"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unknown output column: Level"));
+    Ok(())
+}
+
+#[test]
+fn test_run_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let summary = dir.path().join("summary.tsv");
+    let lines = run_subset(&["-r", "gene", "--summary", summary.to_str().unwrap()])?;
+    let text = std::fs::read_to_string(&summary)?;
+    let sections: Vec<&str> = text.split("\n\n").collect();
+    assert_eq!(sections.len(), 5);
+
+    let totals: Vec<&str> = sections[0].lines().collect();
+    assert_eq!(totals[0], "Regions\t2400");
+    assert_eq!(totals[3], format!("Associations\t{}", lines.len() - 1));
+
+    // Each region counts once in the areas and chromosomes
+    let column_sum = |section: &str, column: usize| -> usize {
+        section
+            .lines()
+            .skip(1)
+            .map(|line| {
+                line.split('\t')
+                    .nth(column)
+                    .unwrap()
+                    .parse::<usize>()
+                    .unwrap()
+            })
+            .sum()
+    };
+    let matched: usize = totals[1].split('\t').nth(1).unwrap().parse()?;
+    assert_eq!(column_sum(sections[1], 1), matched);
+    assert_eq!(column_sum(sections[1], 2), lines.len() - 1);
+    assert_eq!(
+        sections[2],
+        format!("Level\tAssociations\ngene\t{}", lines.len() - 1)
+    );
+    assert_eq!(column_sum(sections[4], 1), 2400);
+
+    // The sequential path gives the same statistics
+    let sequential = dir.path().join("sequential.tsv");
+    run_subset(&[
+        "-r",
+        "gene",
+        "-j",
+        "1",
+        "--summary",
+        sequential.to_str().unwrap(),
+    ])?;
+    assert_eq!(std::fs::read_to_string(&sequential)?, text);
+    Ok(())
+}
+
+#[test]
+fn test_html_report() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let report = dir.path().join("report.html");
+    run_subset(&["-r", "gene", "--html-report", report.to_str().unwrap()])?;
+    let html = std::fs::read_to_string(&report)?;
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.trim_end().ends_with("</html>"));
+    assert_eq!(html.matches("<svg").count(), 2);
+    assert!(html.contains("<tr><th>Regions</th><td class=\"n\">2400</td></tr>"));
+    assert!(html.contains("<tr><th>Report level</th><td>gene</td></tr>"));
+    // Nothing is loaded from elsewhere
+    assert!(!html.contains("<script") && !html.contains("<link") && !html.contains("src="));
+    Ok(())
+}
+
+#[test]
+fn test_gene_table() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let table = dir.path().join("genes.tsv");
+    let lines = run_subset(&["-r", "gene", "--gene-table", table.to_str().unwrap()])?;
+    // The main output is unchanged
+    assert_eq!(lines, run_subset(&["-r", "gene"])?);
+
+    // Counts, areas and distances match the gene summary
+    let summary = run_subset(&["-r", "gene", "--gene-summary"])?;
+    let table = std::fs::read_to_string(&table)?;
+    let table: Vec<&str> = table.lines().collect();
+    assert_eq!(
+        table[0],
+        "Gene\tRegionCount\tBestArea\tMinDistance\tTotalLength\tTotalScore"
+    );
+    assert_eq!(table.len(), summary.len());
+    for (row, summary) in table[1..].iter().zip(&summary[1..]) {
+        let row: Vec<&str> = row.split('\t').collect();
+        let summary: Vec<&str> = summary.split('\t').collect();
+        assert_eq!(row[..2], summary[..2]);
+        assert_eq!(row[2..4], summary[3..5]);
+        assert!(row[4].parse::<i64>()? > 0);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_tss_profile() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("profile.tsv");
+    let lines = run_subset(&[
+        "--tss-profile",
+        path.to_str().unwrap(),
+        "--tss-profile-bin",
+        "500",
+        "--tss-profile-window",
+        "2000",
+    ])?;
+    let profile = std::fs::read_to_string(&path)?;
+    let profile: Vec<&str> = profile.lines().collect();
+    assert!(profile[0].starts_with("BinStart\tBinEnd\tRegions\t"));
+    assert_eq!(profile.len(), 9);
+    assert!(profile[1].starts_with("-2000\t-1500\t"));
+    assert!(profile[8].starts_with("1500\t2000\t"));
+
+    // Every association within the window is counted once
+    let within = lines[1..]
+        .iter()
+        .filter(|line| {
+            let tss_distance: i64 = line.split('\t').nth(7).unwrap().parse().unwrap();
+            tss_distance.abs() <= 2000
+        })
+        .count();
+    let counted: usize = profile[1..]
+        .iter()
+        .flat_map(|row| row.split('\t').skip(3))
+        .map(|count| count.parse::<usize>().unwrap())
+        .sum();
+    assert!(within > 0);
+    assert_eq!(counted, within);
+    Ok(())
+}
+
+#[test]
+fn test_region_gene_matrix() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mtx = dir.path().join("mtx");
+    let lines = run_subset(&["-r", "gene", "--matrix", mtx.to_str().unwrap()])?;
+
+    // One entry per region-gene pair of the main output
+    let mut pairs: Vec<(&str, &str)> = lines[1..]
+        .iter()
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            (fields[0], fields[2])
+        })
+        .collect();
+    pairs.sort_unstable();
+    pairs.dedup();
+
+    let regions = std::fs::read_to_string(mtx.join("regions.tsv"))?;
+    let genes = std::fs::read_to_string(mtx.join("genes.tsv"))?;
+    let regions: Vec<&str> = regions.lines().collect();
+    let genes: Vec<&str> = genes.lines().collect();
+    let matrix = std::fs::read_to_string(mtx.join("matrix.mtx"))?;
+    let mut matrix = matrix.lines();
+    assert_eq!(
+        matrix.next(),
+        Some("%%MatrixMarket matrix coordinate real general")
+    );
+    assert_eq!(
+        matrix.next(),
+        Some(format!("{} {} {}", regions.len(), genes.len(), pairs.len()).as_str())
+    );
+    let mut entries: Vec<(&str, &str)> = matrix
+        .map(|entry| {
+            let fields: Vec<usize> = entry
+                .split(' ')
+                .take(2)
+                .map(|i| i.parse().unwrap())
+                .collect();
+            (regions[fields[0] - 1], genes[fields[1] - 1])
+        })
+        .collect();
+    entries.sort_unstable();
+    assert_eq!(entries, pairs);
+
+    // The dense table holds the same pairs
+    let tsv = dir.path().join("tsv");
+    run_subset(&[
+        "-r",
+        "gene",
+        "--matrix",
+        tsv.to_str().unwrap(),
+        "--matrix-format",
+        "tsv",
+        "--matrix-value",
+        "binary",
+    ])?;
+    let table = std::fs::read_to_string(tsv.join("matrix.tsv"))?;
+    let table: Vec<&str> = table.lines().collect();
+    assert_eq!(table.len(), regions.len() + 1);
+    let ones: usize = table[1..]
+        .iter()
+        .map(|row| row.split('\t').skip(1).filter(|v| *v == "1").count())
+        .sum();
+    assert_eq!(ones, pairs.len());
+    Ok(())
+}
+
+#[test]
+fn test_sort_output() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let dir = tempfile::tempdir()?;
+    let forward = run_subset(&["-r", "gene"])?;
+
+    // Reversed regions are put back in karyotype order
+    let peaks = std::fs::read_to_string(data_dir.join("subset_peaks.bed"))?;
+    let mut reversed: Vec<&str> = peaks.lines().collect();
+    reversed.reverse();
+    let reversed_bed = dir.path().join("reversed.bed");
+    std::fs::write(&reversed_bed, reversed.join("\n") + "\n")?;
+    for threads in ["1", "4"] {
+        let sorted = run_bed(
+            &reversed_bed,
+            &["-r", "gene", "-j", threads, "--sort-output", "coord"],
+        )?;
+        assert_eq!(sorted, forward);
+    }
+
+    // Grouped by gene, rows keep their order within a gene
+    let grouped = run_subset(&["-r", "gene", "--sort-output", "gene"])?;
+    assert_eq!(grouped[0], forward[0]);
+    let mut genes: Vec<&str> = grouped[1..]
+        .iter()
+        .map(|line| line.split('\t').nth(2).unwrap())
+        .collect();
+    genes.dedup();
+    let mut sorted_genes = genes.clone();
+    sorted_genes.sort_unstable();
+    assert_eq!(genes, sorted_genes);
+    for gene in &genes {
+        let rows = |lines: &[String]| -> Vec<String> {
+            lines[1..]
+                .iter()
+                .filter(|line| line.split('\t').nth(2) == Some(gene))
+                .cloned()
+                .collect()
+        };
+        assert_eq!(rows(&grouped), rows(&forward));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_gene_rank() -> Result<(), Box<dyn std::error::Error>> {
+    let args = [
+        "-r",
+        "gene",
+        "--sort-output",
+        "gene-distance",
+        "--gene-rank",
+    ];
+    let ranked = run_subset(&[&args[..], &["-j", "1"]].concat())?;
+    assert_eq!(ranked, run_subset(&[&args[..], &["-j", "4"]].concat())?);
+    assert_eq!(ranked[0].split('\t').nth(10), Some("GeneRank"));
+    assert_eq!(ranked.len(), run_subset(&["-r", "gene"])?.len());
+
+    // Within a gene, rows are ranked from the closest
+    let mut previous: Option<(String, i64, usize)> = None;
+    for line in &ranked[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (gene, distance, rank) = (fields[2], fields[6].parse::<i64>()?, fields[10].parse()?);
+        match &previous {
+            Some((last, last_distance, last_rank)) if last == gene => {
+                assert!(distance >= *last_distance);
+                assert_eq!(rank, last_rank + 1);
+            }
+            _ => assert_eq!(rank, 1),
+        }
+        previous = Some((gene.to_string(), distance, rank));
+    }
+
+    // Selected columns are ranked too
+    let selected = run_subset(&[&args[..], &["--columns", "GeneRank,Gene"]].concat())?;
+    assert!(selected[1].starts_with("1\t"));
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    for extra in [
+        vec!["--gene-rank"],
+        vec!["--gene-rank", "--sort-output", "gene"],
+    ] {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .args(&extra)
+            .assert()
+            .failure();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_precision() -> Result<(), Box<dyn std::error::Error>> {
+    let default = run_subset(&["--gene-coverage"])?;
+    let full = run_subset(&["--gene-coverage", "--precision", "full"])?;
+    let rounded = run_subset(&["--gene-coverage", "--precision", "0"])?;
+    assert_eq!(full.len(), default.len());
+    assert_eq!(full[0], default[0]);
+
+    // Full values round to the default two decimal places
+    for ((line, full_line), rounded_line) in default.iter().zip(&full).zip(&rounded).skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let full_fields: Vec<&str> = full_line.split('\t').collect();
+        let rounded_fields: Vec<&str> = rounded_line.split('\t').collect();
+        for i in [8, 9, 10, 11] {
+            if fields[i] == "NA" {
+                assert_eq!(full_fields[i], "NA");
+                continue;
+            }
+            let value: f64 = full_fields[i].parse()?;
+            assert_eq!(format!("{:.2}", value), fields[i]);
+            assert_eq!(format!("{:.0}", value), rounded_fields[i]);
+        }
+        assert_eq!(fields[..8], full_fields[..8]);
+    }
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let err = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .args(["--precision", "exact"])
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(err.get_output().stderr.clone())?;
+    assert!(stderr.contains("Precision should be"), "{}", stderr);
+    Ok(())
+}
+
+#[test]
+fn test_region_strand_column() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let bed = dir.path().join("stranded.bed");
+    std::fs::write(
+        &bed,
+        "chr1\t10033\t10250\tp1\t0\t+\nchr1\t10385\t10713\tp2\t0\t-\nchr1\t16097\t16381\tp3\t0\t.\n",
+    )?;
+    let lines = run_bed(
+        &bed,
+        &["-r", "gene", "--region-strand", "--report-unmatched"],
+    )?;
+    let header: Vec<&str> = lines[0].split('\t').collect();
+    let column = header.iter().position(|&h| h == "RegionStrand").unwrap();
+    assert_eq!(header[column - 1], "PercArea");
+    assert!(lines.len() > 3);
+    for line in &lines[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let expected = match fields[fields.len() - 1] {
+            "." => "NA",
+            strand => strand,
+        };
+        assert_eq!(fields[column], expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_wide_layout() -> Result<(), Box<dyn std::error::Error>> {
+    let long = run_subset(&["-r", "gene"])?;
+    let sequential = run_subset(&["-r", "gene", "--wide", "2", "-j", "1"])?;
+    let parallel = run_subset(&["-r", "gene", "--wide", "2", "-j", "4"])?;
+    assert_eq!(sequential, parallel);
+    assert!(sequential[0]
+        .starts_with("Region\tMidpoint\tGene1\tArea1\tDistance1\tGene2\tArea2\tDistance2\tname\t"));
+
+    // One row per region, with genes of its long rows
+    let mut regions: Vec<&str> = long[1..]
+        .iter()
+        .map(|l| l.split('\t').next().unwrap())
+        .collect();
+    regions.dedup();
+    assert_eq!(sequential.len() - 1, regions.len());
+    for line in &sequential[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let prefix = format!("{}\t{}\t{}\t", fields[0], fields[1], fields[2]);
+        assert!(long.iter().any(|l| l.starts_with(&prefix)), "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bedtools_closest_compat() -> Result<(), Box<dyn std::error::Error>> {
+    let args = ["--compat", "bedtools-closest", "-r", "gene"];
+    let sequential = run_subset(&[&args[..], &["-j", "1"]].concat())?;
+    let parallel = run_subset(&[&args[..], &["-j", "4"]].concat())?;
+    assert_eq!(sequential, parallel);
+    assert!(!sequential.is_empty());
+    for line in &sequential {
+        // Six BED columns of the region, six of the gene and the distance
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields.len(), 13, "{}", line);
+        let distance: i64 = fields[12].parse()?;
+        if fields[6] == "." {
+            assert_eq!(&fields[6..], [".", "-1", "-1", ".", "-1", ".", "-1"]);
+        } else {
+            assert_eq!(fields[6], fields[0]);
+            assert!(distance >= 0);
+            let (start, end): (i64, i64) = (fields[1].parse()?, fields[2].parse()?);
+            let (gene_start, gene_end): (i64, i64) = (fields[7].parse()?, fields[8].parse()?);
+            assert_eq!(distance == 0, gene_start < end && start < gene_end);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_ucsc_track() -> Result<(), Box<dyn std::error::Error>> {
+    let lines = run_subset(&["--format", "ucsc", "--report-unmatched"])?;
+    assert!(lines[0].starts_with("track ") && lines[0].contains("itemRgb=\"On\""));
+    assert!(lines.len() > 1);
+    for line in &lines[1..] {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields.len(), 9);
+        assert_eq!((fields[6], fields[7]), (fields[1], fields[2]));
+        let area = fields[3].rsplit(':').next().unwrap();
+        let color = rgmatch::output::area_color(&area.parse()?);
+        assert_eq!(fields[8], color);
+    }
+
+    // Tracks cannot be indexed
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let output = NamedTempFile::new()?;
+    let err = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .args(["--format", "ucsc", "--bgzip", "-o"])
+        .arg(output.path())
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(err.get_output().stderr.clone())?;
+    assert!(stderr.contains("--bgzip is only available"), "{}", stderr);
+    Ok(())
+}
+
+#[test]
+fn test_header_options() -> Result<(), Box<dyn std::error::Error>> {
+    let lines = run_subset(&["-r", "gene"])?;
+    let no_header = run_subset(&["-r", "gene", "--no-header"])?;
+    assert_eq!(no_header, lines[1..]);
+
+    let commented = run_subset(&["-r", "gene", "--comment-header", "--bed-names", "peak"])?;
+    assert_eq!(commented[1..], lines[1..]);
+    let header = lines[0].replacen("Region", "#Region", 1);
+    assert_eq!(commented[0], header.replace("\tname", "\tpeak"));
+
+    let selected = run_subset(&[
+        "-r",
+        "gene",
+        "--bed-names",
+        "peak",
+        "--columns",
+        "peak,Gene",
+    ])?;
+    assert_eq!(selected[0], "peak\tGene");
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .args([
+            "-g",
+            "x.gtf",
+            "-b",
+            "x.bed",
+            "--no-header",
+            "--comment-header",
+        ])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn test_region_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let id = run_subset(&["-r", "gene"])?;
+    let split = run_subset(&["-r", "gene", "--region-columns", "split"])?;
+    let both = run_subset(&["-r", "gene", "--region-columns", "both"])?;
+    assert_eq!(id.len(), split.len());
+    assert_eq!(id.len(), both.len());
+    assert!(split[0].starts_with("Chrom\tStart\tEnd\tMidpoint\t"));
+    assert!(both[0].starts_with("Region\tChrom\tStart\tEnd\tMidpoint\t"));
+    for ((id, split), both) in id.iter().zip(&split).zip(&both).skip(1) {
+        let (region, rest) = id.split_once('\t').unwrap();
+        let coordinates: Vec<&str> = split.splitn(4, '\t').collect();
+        assert_eq!(coordinates.join("_"), format!("{}_{}", region, rest));
+        assert_eq!(both, &format!("{}\t{}", region, split));
+    }
+
+    // The split columns can be selected
+    let selected = run_subset(&[
+        "-r",
+        "gene",
+        "--region-columns",
+        "split",
+        "--columns",
+        "Chrom,Start,Gene",
+    ])?;
+    assert_eq!(selected[0], "Chrom\tStart\tGene");
+    Ok(())
+}
+
+#[test]
+fn test_metadata_sidecar() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("run.json");
+    run_subset(&[
+        "-r",
+        "gene",
+        "--seed",
+        "7",
+        "--metadata",
+        path.to_str().unwrap(),
+    ])?;
+    let metadata: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+
+    assert_eq!(metadata["version"], env!("CARGO_PKG_VERSION"));
+    let command = metadata["command"].as_array().unwrap();
+    assert!(command.iter().any(|arg| arg == "--metadata"));
+    assert_eq!(metadata["parameters"]["Report level"], "gene");
+    assert!(metadata["elapsed_seconds"].as_f64().unwrap() >= 0.0);
+    // The resolved options, as a JSON object
+    assert_eq!(metadata["config"]["level"], "Gene");
+    assert_eq!(metadata["config"]["distance"], 10_000);
+    assert_eq!(metadata["config"]["seed"], 7);
+    assert_eq!(metadata["config"]["duplicates"], "keep");
+
+    let inputs = metadata["inputs"].as_array().unwrap();
+    let roles: Vec<&str> = inputs.iter().map(|i| i["role"].as_str().unwrap()).collect();
+    assert_eq!(roles, vec!["gtf", "bed"]);
+    let bed = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/subset_peaks.bed");
+    assert_eq!(inputs[1]["bytes"], std::fs::metadata(&bed)?.len());
+    assert_eq!(inputs[1]["sha256"].as_str().unwrap().len(), 64);
+    Ok(())
+}
+
+#[test]
+fn test_metrics_export() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("metrics.json");
+    let bed = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/subset_peaks.bed");
+    let regions = std::fs::read_to_string(&bed)?.lines().count() as u64;
+
+    // Both the sequential and the parallel pipelines are measured
+    for threads in ["1", "4"] {
+        let lines = run_subset(&["-j", threads, "--metrics", path.to_str().unwrap()])?;
+        let metrics: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        assert_eq!(metrics["threads"].as_u64(), threads.parse().ok());
+        assert_eq!(metrics["regions_processed"], regions);
+        assert_eq!(metrics["lines_written"], lines.len() as u64 - 1);
+        assert!(metrics["worker_matching_seconds"].as_f64().unwrap() > 0.0);
+        assert!(metrics["regions_per_second"].as_f64().unwrap() > 0.0);
+    }
+
+    let path = dir.path().join("rgmatch.prom");
+    let lines = run_subset(&[
+        "--metrics",
+        path.to_str().unwrap(),
+        "--metrics-format",
+        "prometheus",
+    ])?;
+    let text = std::fs::read_to_string(&path)?;
+    assert!(text.contains(&format!("\nrgmatch_lines_written {}\n", lines.len() - 1)));
+    assert!(text.contains("# TYPE rgmatch_max_pending_results gauge\n"));
+    Ok(())
+}
+
+#[test]
+fn test_perf() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let output_file = NamedTempFile::new()?;
+    let regions = std::fs::read_to_string(data_dir.join("subset_peaks.bed"))?
+        .lines()
+        .count();
+
+    for threads in ["1", "4"] {
+        let run = |extra: &[&str]| {
+            Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+                .arg("-g")
+                .arg(data_dir.join("subset_genome.gtf"))
+                .arg("-b")
+                .arg(data_dir.join("subset_peaks.bed"))
+                .arg("-o")
+                .arg(output_file.path())
+                .args(["-j", threads])
+                .args(extra)
+                .assert()
+                .success()
+        };
+        run(&["--perf"])
+            .stderr(predicates::str::contains(format!(
+                "Processed {} regions",
+                regions
+            )))
+            .stderr(predicates::str::contains("Matching "));
+        // Without --perf, the run is not timed
+        run(&[]).stderr(predicates::str::contains("Processed ").not());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_several_outputs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let tsv = dir.path().join("copy.tsv");
+    let bed = dir.path().join("out.bed");
+    let tsv_target = format!("tsv:{}", tsv.display());
+    let bed_target = format!("bed:{}", bed.display());
+
+    for threads in ["1", "4"] {
+        let lines = run_subset(&["-j", threads, "-o", &tsv_target, "-o", &bed_target])?;
+        let copy = std::fs::read_to_string(&tsv)?;
+        assert_eq!(copy.lines().collect::<Vec<_>>(), lines);
+        // The annotated BED has no header line
+        let bed_lines = std::fs::read_to_string(&bed)?;
+        assert_eq!(bed_lines.lines().count(), lines.len() - 1);
+        assert!(bed_lines.starts_with("chr"));
+    }
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    for extra in [
+        vec!["-o", "-", "-o", "out.tsv"],
+        vec!["-o", "-", "-o", "tsv:-"],
+        vec!["-o", "-", "-o", &tsv_target, "--gene-summary"],
+        vec!["-o", "-", "-o", &bed_target, "--wide", "2"],
+    ] {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .args(&extra)
+            .assert()
+            .failure();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_python_compat() -> Result<(), Box<dyn std::error::Error>> {
+    let lines = run_subset(&[])?;
+    let python = run_subset(&["--compat", "python"])?;
+    assert_eq!(python, run_subset(&["--compat", "python", "-j", "1"])?);
+
+    // Python rgmatch loses some proximity associations and repeats overlaps
+    let unique: std::collections::HashSet<&String> = python.iter().collect();
+    assert!(unique.len() < python.len());
+    let rust: std::collections::HashSet<&String> = lines.iter().collect();
+    assert_eq!(rust.len(), lines.len());
+    assert!(unique.is_subset(&rust));
+    assert!(unique.len() < rust.len());
+    Ok(())
+}
+
+#[test]
+fn test_association_id() -> Result<(), Box<dyn std::error::Error>> {
+    let ids = |lines: &[String]| -> Vec<String> {
+        lines[1..]
+            .iter()
+            .map(|line| line.split('\t').nth(10).unwrap().to_string())
+            .collect()
+    };
+    let sequential = run_subset(&["--association-id", "-j", "1"])?;
+    let parallel = run_subset(&["--association-id", "-j", "4"])?;
+    assert_eq!(sequential[0].split('\t').nth(10), Some("AssociationID"));
+    assert_eq!(ids(&sequential), ids(&parallel));
+
+    // One identifier per association
+    let unique: std::collections::HashSet<String> = ids(&sequential).into_iter().collect();
+    assert_eq!(unique.len(), sequential.len() - 1);
+
+    // Gene-level rows are identified too
+    let gene = run_subset(&["--association-id", "-r", "gene"])?;
+    assert!(ids(&gene).iter().all(|id| id.len() == 16));
+    Ok(())
+}
+
+#[test]
+fn test_subcommands() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let gtf = data_dir.join("subset_genome.gtf");
+    let bed = data_dir.join("subset_peaks.bed");
+    let dir = tempfile::tempdir()?;
+    let flat = run_subset(&["-r", "gene", "--biotypes"])?;
+
+    // An index stands in for the GTF, and annotate for the flat invocation
+    let index = dir.path().join("subset.rgidx.gz");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("index")
+        .arg("-g")
+        .arg(&gtf)
+        .arg("-o")
+        .arg(&index)
+        .assert()
+        .success();
+    let output = dir.path().join("out.tsv");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("annotate")
+        .arg("-g")
+        .arg(&index)
+        .arg("-b")
+        .arg(&bed)
+        .arg("-o")
+        .arg(&output)
+        .args(["-r", "gene", "--biotypes"])
+        .assert()
+        .success();
+    let annotated = std::fs::read_to_string(&output)?;
+    assert_eq!(annotated.lines().collect::<Vec<_>>(), flat);
+
+    // The index keeps the ID tags it was built with
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&index)
+        .arg("-b")
+        .arg(&bed)
+        .args(["-G", "gene_name"])
+        .assert()
+        .failure();
+
+    let stats = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("stats")
+        .arg("-g")
+        .arg(&index)
+        .assert()
+        .success();
+    let stats = String::from_utf8(stats.get_output().stdout.clone())?;
+    assert!(stats.starts_with("Chrom\tGenes\tTranscripts\tExons\tTSS\tMaxGeneLength\nchr1\t"));
+    let (chroms, biotypes) = stats.split_once("\n\n").unwrap();
+    let total: Vec<usize> = chroms
+        .lines()
+        .last()
+        .unwrap()
+        .split('\t')
+        .skip(1)
+        .map(|n| n.parse())
+        .collect::<Result<_, _>>()?;
+    let (genes, transcripts, tss) = (total[0], total[1], total[3]);
+    assert!(genes > 0);
+    assert!(tss >= genes && tss <= transcripts);
+    assert!(biotypes.starts_with("Biotype\tGenes\tTranscripts\n"));
+    let biotype_genes: usize = biotypes
+        .lines()
+        .skip(1)
+        .map(|line| line.split('\t').nth(1).unwrap().parse::<usize>().unwrap())
+        .sum();
+    assert_eq!(biotype_genes, genes);
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("validate")
+        .arg("-g")
+        .arg(&gtf)
+        .arg("-b")
+        .arg(&bed)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Regions: 2400 on 24 chromosomes"));
+
+    // Regions on chromosomes missing from the annotation fail validation
+    let mut unknown = NamedTempFile::new()?;
+    writeln!(unknown, "1\t100\t200")?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("validate")
+        .arg("-g")
+        .arg(&gtf)
+        .arg("-b")
+        .arg(unknown.path())
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn test_config_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("rgmatch.toml");
+    std::fs::write(
+        &path,
+        "report = \"gene\"\n\
+         distance = 20\n\
+         rules = [\"TSS\", \"PROMOTER\", \"1st_EXON\", \"TTS\", \"INTRON\", \"GENE_BODY\", \"UPSTREAM\", \"DOWNSTREAM\"]\n\
+         biotypes = true\n\
+         signed_distance = false\n",
+    )?;
+    let config = path.to_str().unwrap();
+
+    let from_file = run_subset(&["--config", config])?;
+    let rules = "TSS,PROMOTER,1st_EXON,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM";
+    let from_flags = run_subset(&["-r", "gene", "-q", "20", "--rules", rules, "--biotypes"])?;
+    assert_eq!(from_file, from_flags);
+
+    // Command-line options override the file, also through conflicts
+    let overridden = run_subset(&["--config", config, "-r", "exon"])?;
+    let expected = run_subset(&["-r", "exon", "-q", "20", "--rules", rules, "--biotypes"])?;
+    assert_eq!(overridden, expected);
+    let all = run_subset(&["--config", config, "--report-all"])?;
+    assert!(all.len() > from_file.len());
+
+    // Unknown options are reported
+    std::fs::write(&path, "distnce = 20\n")?;
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .arg("--config")
+        .arg(&path)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("distnce"));
+
+    // The scaffold is valid TOML with every option commented out
+    let scaffold = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .args(["config", "--print-default"])
+        .assert()
+        .success();
+    let scaffold = String::from_utf8(scaffold.get_output().stdout.clone())?;
+    assert!(scaffold.contains("\n# distance = 10\n"));
+    assert!(scaffold.contains("\n# rules = \"TSS,1st_EXON,"));
+    std::fs::write(&path, &scaffold)?;
+    assert_eq!(run_subset(&["--config", config])?, run_subset(&[])?);
+
+    // ... and every key with a default can be uncommented as is
+    let uncommented: String = scaffold
+        .lines()
+        .map(|line| match line.strip_prefix("# ") {
+            Some(option)
+                if option
+                    .split_once(" = ")
+                    .is_some_and(|(key, _)| !key.contains(' ')) =>
+            {
+                option
+            }
+            _ => line,
+        })
+        .map(|line| format!("{}\n", line))
+        .collect();
+    assert!(uncommented.contains("\nperc_area = 90\n"));
+    std::fs::write(&path, &uncommented)?;
+    assert_eq!(run_subset(&["--config", config])?, run_subset(&[])?);
+
+    // Keys with underscores are read with either separator
+    std::fs::write(&path, "perc_area = 80\nperc-region = 40\n")?;
+    assert_eq!(
+        run_subset(&["--config", config])?,
+        run_subset(&["--perc_area", "80", "--perc_region", "40"])?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_env_options() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("output.tsv");
+    let run = |env: &[(&str, &str)], args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .arg("-o")
+            .arg(&output)
+            .args(args)
+            .envs(env.iter().copied());
+        cmd
+    };
+    let lines = |env: &[(&str, &str)], args: &[&str]| -> std::io::Result<String> {
+        run(env, args).assert().success();
+        std::fs::read_to_string(&output)
+    };
+
+    let expected = lines(&[], &["-r", "gene", "-q", "20", "--biotypes"])?;
+    let env = [
+        ("RGMATCH_REPORT", "gene"),
+        ("RGMATCH_DISTANCE", "20"),
+        ("RGMATCH_BIOTYPES", "true"),
+        ("RGMATCH_SIGNED_DISTANCE", "false"),
+        ("RGMATCH_THREADS", ""),
+    ];
+    assert_eq!(lines(&env, &[])?, expected);
+
+    // The command line takes precedence over the environment, which takes
+    // precedence over the config file
+    let exon = lines(&[], &["-r", "exon", "-q", "20", "--biotypes"])?;
+    assert_eq!(lines(&env, &["-r", "exon"])?, exon);
+    let path = dir.path().join("rgmatch.toml");
+    std::fs::write(&path, "distance = 5\nreport = \"exon\"\n")?;
+    let config = path.to_str().unwrap();
+    assert_eq!(lines(&env, &["--config", config])?, expected);
+    let config_env = [("RGMATCH_CONFIG", config), ("RGMATCH_DISTANCE", "20")];
+    assert_eq!(lines(&config_env, &["--biotypes"])?, exon);
+
+    // The global options can be set too, with the same precedence
+    run(&[("RGMATCH_QUIET", "1")], &[])
+        .assert()
+        .success()
+        .stderr(predicates::str::is_empty());
+    run(&[("RGMATCH_QUIET", "1")], &["--verbose"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("DEBUG"));
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .args(["index", "-g"])
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-o")
+        .arg(dir.path().join("index.rgidx"))
+        .env("RGMATCH_LOG_FORMAT", "json")
+        .assert()
+        .success()
+        .stderr(predicates::str::starts_with("{"));
+
+    // ... as the seed, for annotate and simulate
+    let seeded = lines(
+        &[("RGMATCH_SEED", "1")],
+        &["-r", "gene", "-q", "20", "--biotypes"],
+    )?;
+    assert_eq!(seeded, expected);
+    let simulate = |env: &[(&str, &str)], args: &[&str]| -> std::io::Result<String> {
+        let gtf = dir.path().join("simulated.gtf");
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("simulate")
+            .arg("-g")
+            .arg(&gtf)
+            .arg("-b")
+            .arg(dir.path().join("simulated.bed"))
+            .args(["--chromosomes", "1", "--regions", "10"])
+            .args(args)
+            .envs(env.iter().copied())
+            .assert()
+            .success();
+        std::fs::read_to_string(&gtf)
+    };
+    let seed_2 = simulate(&[], &["--seed", "2"])?;
+    assert_eq!(simulate(&[("RGMATCH_SEED", "2")], &[])?, seed_2);
+    assert_ne!(simulate(&[], &[])?, seed_2);
+    assert_eq!(
+        simulate(&[("RGMATCH_SEED", "3")], &["--seed", "2"])?,
+        seed_2
+    );
+
+    // ... and the warning budget
+    let malformed = dir.path().join("malformed.bed");
+    let peaks = std::fs::read_to_string(data_dir.join("subset_peaks.bed"))?;
+    let first = peaks.lines().next().unwrap();
+    std::fs::write(&malformed, format!("{}\nchr1\tx\t5\nchr1\t100\n", first))?;
+    let warn = |env: &[(&str, &str)], args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(&malformed)
+            .arg("-o")
+            .arg(&output)
+            .args(args)
+            .envs(env.iter().copied());
+        cmd.assert().code(3)
+    };
+    warn(&[("RGMATCH_MAX_WARNINGS", "0")], &[])
+        .stderr(predicates::str::contains("malformed.bed line").not())
+        .stderr(predicates::str::contains("Completed with 2 warnings"));
+    warn(&[("RGMATCH_MAX_WARNINGS", "0")], &["--max-warnings", "5"])
+        .stderr(predicates::str::contains("malformed.bed line 3:"));
+
+    // Unknown variables are ignored with a warning
+    run(&[("RGMATCH_DISTNCE", "20")], &[])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains(
+            "Ignored environment variable RGMATCH_DISTNCE",
+        ));
+    run(&[("RGMATCH_DISTANCE", "far")], &[])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("far"));
+    Ok(())
+}
+
+#[test]
+fn test_log_verbosity() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let output_file = NamedTempFile::new()?;
+    let annotate = |args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.args(args)
+            .arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .arg("-o")
+            .arg(output_file.path());
+        let assert = cmd.assert().success();
+        String::from_utf8(assert.get_output().stderr.clone())
+    };
+
+    let default = annotate(&[])?;
+    assert!(default.contains("INFO Done!"), "{}", default);
+    assert!(!default.contains("DEBUG"), "{}", default);
+    assert!(annotate(&["--quiet"])?.is_empty());
+
+    // One JSON object per line, with the debug messages
+    let json = annotate(&["--verbose", "--log-format", "json"])?;
+    let events: Vec<serde_json::Value> = json
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert!(events.iter().any(|event| event["level"] == "DEBUG"
+        && event["message"]
+            .as_str()
+            .is_some_and(|message| message.starts_with("Finished in"))));
+    assert_eq!(events.last().unwrap()["message"], "Done!");
+
+    // Logging options before a subcommand
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .args(["--quiet", "index", "-g"])
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-o")
+        .arg(output_file.path())
+        .assert()
+        .success()
+        .stderr(predicates::str::is_empty());
+
+    // -Q and -D are the short forms anywhere, next to -q (--distance) and
+    // -v (--perc_area)
+    let with_short = annotate(&["-Q", "-q", "10", "-v", "50"])?;
+    assert!(with_short.is_empty(), "{}", with_short);
+    for verbose in [["-D", "annotate"], ["annotate", "-DD"]] {
+        let log = annotate(&verbose)?;
+        assert!(log.contains("DEBUG"), "{}", log);
+    }
+
+    // -q and -v without a value are rejected rather than read as log options
+    for args in [&["-vv"][..], &["-q"], &["-v", "index"]] {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.args(args)
+            .arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("is ambiguous"))
+            .stderr(predicates::str::contains("-Q (--quiet)"));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("out.tsv");
+    let dry_run = |bed: &Path, args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(bed)
+            .arg("-o")
+            .arg(&output)
+            .arg("--dry-run")
+            .args(args);
+        cmd.assert()
+    };
+
+    let assert = dry_run(&data_dir.join("subset_peaks.bed"), &["-r", "gene"]).success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone())?;
+    assert!(
+        stdout.contains("Regions: 2400 on 24 chromosomes"),
+        "{}",
+        stdout
+    );
+    let (_, options) = stdout
+        .split_once("Configuration (for --config):\n")
+        .unwrap();
+    assert!(options.contains("report = \"gene\""), "{}", stdout);
+    assert!(!options.contains("dry-run"), "{}", stdout);
+    assert!(!output.exists());
+
+    // The printed configuration reproduces the run
+    let config = dir.path().join("run.toml");
+    std::fs::write(&config, options)?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("--config")
+        .arg(&config)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(stdout.clone());
+
+    // Regions on chromosomes missing from the annotation are a warning on stderr
+    let bed = dir.path().join("mixed.bed");
+    std::fs::write(&bed, "chr1\t100\t200\nscaffold_1\t100\t200\n")?;
+    let assert = dry_run(&bed, &[]).success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone())?;
+    assert!(!stdout.contains("scaffold_1"), "{}", stdout);
+    assert!(String::from_utf8(assert.get_output().stderr.clone())?
+        .contains("1 regions on chromosomes missing from the annotation: scaffold_1 (1)"));
+
+    // Misconfigurations fail before annotating
+    dry_run(&data_dir.join("subset_peaks.bed"), &["--rules", "TSS,EXON"])
+        .failure()
+        .stderr(predicates::str::contains("Rules not properly passed"));
+    let bed = dir.path().join("scaffolds.bed");
+    std::fs::write(&bed, "scaffold_1\t100\t200\n")?;
+    dry_run(&bed, &[])
+        .failure()
+        .stderr(predicates::str::contains("No chromosome of the regions"));
+    assert!(!output.exists());
+    Ok(())
+}
+
+#[test]
+fn test_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let dir = tempfile::tempdir()?;
+    let peaks = std::fs::read_to_string(data_dir.join("subset_peaks.bed"))?;
+    let lines: Vec<&str> = peaks.lines().collect();
+    let (first, second) = lines.split_at(lines.len() / 2);
+    let first_bed = dir.path().join("first.bed");
+    let second_bed = dir.path().join("second.bed");
+    std::fs::write(&first_bed, first.join("\n") + "\n")?;
+    std::fs::write(&second_bed, second.join("\n") + "\n")?;
+    let manifest = dir.path().join("samples.tsv");
+    std::fs::write(
+        &manifest,
+        format!(
+            "sample\tbed\nfirst\t{}\nsecond\t{}\treport=gene\tdistance=20\n",
+            first_bed.display(),
+            second_bed.display()
+        ),
+    )?;
+    let out = dir.path().join("out");
+    let annotate = |manifest: &Path, args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("--manifest")
+            .arg(manifest)
+            .arg("-o")
+            .arg(&out)
+            .args(args);
+        cmd.assert()
+    };
+
+    // Each sample matches its own run, with its overrides
+    annotate(&manifest, &["-r", "transcript"]).success();
+    let read = |path: std::path::PathBuf| -> Result<Vec<String>, std::io::Error> {
+        BufReader::new(File::open(path)?).lines().collect()
+    };
+    assert_eq!(
+        read(out.join("first.tsv"))?,
+        run_bed(&first_bed, &["-r", "transcript"])?
+    );
+    assert_eq!(
+        read(out.join("second.tsv"))?,
+        run_bed(&second_bed, &["-r", "gene", "-q", "20"])?
+    );
+    assert!(out.join("first.summary.tsv").exists());
+
+    let summary = read(out.join("summary.tsv"))?;
+    assert_eq!(
+        summary[0],
+        "Sample\tRegions\tMatchedRegions\tUnmatchedRegions\tAssociations\tMedianDistance"
+    );
+    assert_eq!(summary.len(), 3);
+    let fields: Vec<&str> = summary[1].split('\t').collect();
+    assert_eq!(fields[0], "first");
+    assert_eq!(fields[1], first.len().to_string());
+
+    // Options shared by the samples cannot be overridden
+    std::fs::write(
+        &manifest,
+        format!("first\t{}\ttranscript=gene_name\n", first_bed.display()),
+    )?;
+    annotate(&manifest, &[])
+        .failure()
+        .stderr(predicates::str::contains("shared by all the samples"));
+    std::fs::write(
+        &manifest,
+        format!("first\t{}\tdistnce=20\n", first_bed.display()),
+    )?;
+    annotate(&manifest, &[])
+        .failure()
+        .stderr(predicates::str::contains("Unknown option for sample first"));
+    Ok(())
+}
+
+#[test]
+fn test_bed_pattern() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let dir = tempfile::tempdir()?;
+    let peaks = std::fs::read_to_string(data_dir.join("subset_peaks.bed"))?;
+    let lines: Vec<&str> = peaks.lines().collect();
+    let (first, second) = lines.split_at(lines.len() / 2);
+    let ctrl = dir.path().join("ctrl.narrowPeak");
+    let treated = dir.path().join("treated.narrowPeak");
+    std::fs::write(&ctrl, first.join("\n") + "\n")?;
+    std::fs::write(&treated, second.join("\n") + "\n")?;
+    std::fs::write(dir.path().join("notes.txt"), "")?;
+    let out = dir.path().join("out");
+    let annotate = |pattern: &str, output: &Path| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(dir.path().join(pattern))
+            .arg("-o")
+            .arg(output);
+        cmd.assert()
+    };
+
+    // Each matching file is a sample named after it
+    annotate("*.narrowPeak", &out).success();
+    let read = |path: std::path::PathBuf| -> Result<Vec<String>, std::io::Error> {
+        BufReader::new(File::open(path)?).lines().collect()
+    };
+    assert_eq!(read(out.join("ctrl.tsv"))?, run_bed(&ctrl, &[])?);
+    assert_eq!(read(out.join("treated.tsv"))?, run_bed(&treated, &[])?);
+    let summary = read(out.join("summary.tsv"))?;
+    assert_eq!(summary.len(), 3);
+    assert!(summary[1].starts_with("ctrl\t"));
+    assert!(summary[2].starts_with("treated\t"));
+
+    // A single match is annotated as usual
+    let single = dir.path().join("single.tsv");
+    annotate("c*.narrowPeak", &single).success();
+    assert_eq!(read(single)?, run_bed(&ctrl, &[])?);
+
+    annotate("*.bed", &out)
+        .failure()
+        .stderr(predicates::str::contains("No BED file matches"));
+    annotate("*.narrowPeak", Path::new("-"))
+        .failure()
+        .stderr(predicates::str::contains("single output directory"));
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_resume() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let dir = tempfile::tempdir()?;
+    let bed = data_dir.join("subset_peaks.bed");
+    let peaks = std::fs::read_to_string(&bed)?;
+    let expected = run_bed(&bed, &["-j", "1"])?;
+
+    // An interrupted run: the first regions written, then part of a line
+    let first: String = peaks
+        .lines()
+        .take(1000)
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let first_bed = dir.path().join("first.bed");
+    std::fs::write(&first_bed, &first)?;
+    let partial = run_bed(&first_bed, &["-j", "1"])?;
+    let matched = partial[1..]
+        .iter()
+        .map(|line| line.split('\t').next())
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    let output = dir.path().join("out.tsv");
+    let checkpoint = dir.path().join("run.checkpoint");
+
+    // A run failing on a truncated gzipped BED file leaves a checkpoint
+    // recording its options and header
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(peaks.as_bytes())?;
+    let gzipped = encoder.finish()?;
+    let truncated = dir.path().join("truncated.bed.gz");
+    std::fs::write(&truncated, &gzipped[..gzipped.len() / 2])?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(&truncated)
+        .arg("-o")
+        .arg(dir.path().join("truncated.tsv"))
+        .arg("--checkpoint")
+        .arg(&checkpoint)
+        .args([
+            "--checkpoint-interval",
+            "0",
+            "--batch-size",
+            "100",
+            "-j",
+            "1",
+        ])
+        .assert()
+        .failure();
+    let recorded: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&checkpoint)?)?;
+    assert_eq!(
+        recorded["header"].as_str(),
+        Some(format!("{}\n", expected[0]).as_str())
+    );
+
+    let write_state = |header: &serde_json::Value| -> Result<usize, Box<dyn std::error::Error>> {
+        let written = partial.join("\n") + "\n";
+        std::fs::write(&output, written.clone() + "chr1\t10033")?;
+        let state = serde_json::json!({
+            "bed": bed.display().to_string(),
+            "bed_bytes": peaks.len(),
+            "output": output.display().to_string(),
+            "bed_offset": first.len(),
+            "regions": 1000,
+            "work_items": 1,
+            "output_bytes": written.len(),
+            "lines_written": partial.len() - 1,
+            "matched_regions": matched,
+            "options": recorded["options"],
+            "header": header,
+            "num_meta_columns": recorded["num_meta_columns"],
+        });
+        std::fs::write(&checkpoint, state.to_string())?;
+        Ok(written.len())
+    };
+    let interrupt = || write_state(&recorded["header"]).map(|_| ());
+    let annotate = |args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(&bed)
+            .arg("-o")
+            .arg(&output)
+            .arg("--checkpoint")
+            .arg(&checkpoint)
+            .args(args);
+        cmd.assert()
+    };
+
+    // A resumed run completes the output, and removes its checkpoint
+    for threads in ["1", "4"] {
+        interrupt()?;
+        annotate(&["--resume", "-j", threads]).success();
+        let lines = BufReader::new(File::open(&output)?)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(lines, expected);
+        assert!(!checkpoint.exists());
+    }
+
+    // Without --resume the run starts over
+    interrupt()?;
+    annotate(&["-j", "1"]).success();
+    let lines = BufReader::new(File::open(&output)?)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(lines, expected);
+
+    // Other options or another header are not resumed, and the output is
+    // left as it was
+    for args in [&["-r", "gene"][..], &["--signed-distance"]] {
+        let written = write_state(&recorded["header"])?;
+        annotate(&[&["--resume"][..], args].concat())
+            .failure()
+            .stderr(predicates::str::contains("other options"));
+        assert_eq!(std::fs::metadata(&output)?.len() as usize, written + 10);
+    }
+    write_state(&serde_json::json!("Region\tGene\n"))?;
+    annotate(&["--resume"])
+        .failure()
+        .stderr(predicates::str::contains("another header"));
+
+    annotate(&["--bgzip"])
+        .failure()
+        .stderr(predicates::str::contains("--checkpoint"));
+    Ok(())
+}
+
+#[test]
+fn test_max_memory() -> Result<(), Box<dyn std::error::Error>> {
+    // Results spilled to disk are written as if kept in memory
+    for args in [
+        &["-r", "transcript"][..],
+        &["-r", "gene", "--zones", "TSS:0-200,PROXIMAL:200-2000"][..],
+        &["--report-unmatched", "--gene-summary"][..],
+    ] {
+        let expected = run_subset(&[args, &["-j", "1"]].concat())?;
+        let spilled = run_subset(
+            &[
+                args,
+                &["-j", "4", "--batch-size", "50", "--max-memory", "1"],
+            ]
+            .concat(),
+        )?;
+        assert_eq!(spilled, expected);
+    }
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+    cmd.arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(data_dir.join("subset_peaks.bed"))
+        .args(["--max-memory", "1.5G"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Max memory should be"));
+    Ok(())
+}
+
+#[test]
+fn test_strict_and_exit_codes() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let dir = tempfile::tempdir()?;
+    let peaks = std::fs::read_to_string(data_dir.join("subset_peaks.bed"))?;
+    let lines: Vec<&str> = peaks.lines().take(20).collect();
+    let bed = dir.path().join("regions.bed");
+    let malformed = dir.path().join("malformed.bed");
+    let unmatched = dir.path().join("unmatched.bed");
+    std::fs::write(&bed, lines.join("\n") + "\n")?;
+    std::fs::write(
+        &malformed,
+        format!(
+            "chrom\tstart\tend\n{}\nchr1\t100\n{}\n",
+            lines[..10].join("\n"),
+            lines[10..].join("\n")
+        ),
+    )?;
+    std::fs::write(&unmatched, "chrUn\t100\t200\n")?;
+    let annotate = |bed: &Path, args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(bed)
+            .arg("-o")
+            .arg(dir.path().join("out.tsv"))
+            .args(args);
+        cmd.assert()
+    };
+
+    annotate(&bed, &[]).code(0);
+
+    // Malformed lines are skipped with a warning, the header silently
+    annotate(&malformed, &[])
+        .code(3)
+        .stderr(predicates::str::contains("malformed.bed line 12"))
+        .stderr(predicates::str::contains(
+            "Completed with 1 warning: skipped 1 BED lines",
+        ))
+        .stderr(predicates::str::contains("line 1:").not());
+    assert_eq!(
+        BufReader::new(File::open(dir.path().join("out.tsv"))?)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?,
+        run_bed(&bed, &[])?
+    );
+
+    // ... or fail the run in strict mode, before or after the subcommand
+    annotate(&malformed, &["--strict"])
+        .code(1)
+        .stderr(predicates::str::contains("malformed.bed line 12"));
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("--strict")
+        .arg("validate")
+        .arg("-g")
+        .arg(data_dir.join("subset_genome.gtf"))
+        .arg("-b")
+        .arg(&malformed)
+        .assert()
+        .code(1);
+
+    annotate(&unmatched, &["-j", "4"])
+        .code(4)
+        .stderr(predicates::str::contains("No region matched a gene"));
+
+    // Regions ending before their start are read as given, with a warning
+    let inverted = dir.path().join("inverted.bed");
+    std::fs::write(
+        &inverted,
+        format!("chr1\t14500\t14400\n{}\n", lines.join("\n")),
+    )?;
+    annotate(&inverted, &[])
+        .code(0)
+        .stderr(predicates::str::contains(
+            "inverted.bed line 1: end before start; read as given",
+        ));
+    Ok(())
+}
+
+#[test]
+fn test_repeated_warnings() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let dir = tempfile::tempdir()?;
+    let peaks = std::fs::read_to_string(data_dir.join("subset_peaks.bed"))?;
+    let mut text: String = peaks
+        .lines()
+        .take(10)
+        .map(|line| line.to_string() + "\n")
+        .collect();
+    for i in 1..=50 {
+        text += &format!("chr1\tx\t{}\nchrUn\t{}\t{}\n", i, i * 100, i * 100 + 50);
+    }
+    let bed = dir.path().join("repeated.bed");
+    std::fs::write(&bed, text)?;
+    let annotate = |args: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(&bed)
+            .arg("-o")
+            .arg(dir.path().join("out.tsv"))
+            .args(args);
+        cmd.assert()
+    };
+
+    // The first warning of each kind is logged, the repeats counted
+    for args in [&[][..], &["-j", "4"]] {
+        annotate(args)
+            .code(3)
+            .stderr(predicates::str::contains("repeated.bed line 11:"))
+            .stderr(predicates::str::contains("repeated.bed line 13:").not())
+            .stderr(predicates::str::contains("chrUn is not in the annotation"))
+            .stderr(predicates::str::contains(
+                "Completed with 50 warnings: skipped 50 BED lines",
+            ))
+            .stderr(predicates::str::contains(
+                "50 BED lines: invalid start or end coordinate (49 not logged)",
+            ))
+            .stderr(predicates::str::contains(
+                "50 regions on chrUn, a chromosome not in the annotation (49 not logged)",
+            ));
+    }
+
+    // ... up to the budget of logged warnings
+    annotate(&["--max-warnings", "0"])
+        .code(3)
+        .stderr(predicates::str::contains("repeated.bed line").not())
+        .stderr(predicates::str::contains(
+            "50 BED lines: invalid start or end coordinate (50 not logged)",
+        ));
+    annotate(&["--max-warnings", "1"])
+        .code(3)
+        .stderr(predicates::str::contains("repeated.bed line 11:"))
+        .stderr(predicates::str::contains(
+            "Reached --max-warnings 1: further warnings are only counted",
+        ))
+        .stderr(predicates::str::contains("chrUn is not in the annotation").not());
+    Ok(())
+}
+
+#[test]
+fn test_subset_gtf() -> Result<(), Box<dyn std::error::Error>> {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let gtf = data_dir.join("subset_genome.gtf");
+    let bed = data_dir.join("subset_peaks.bed");
+    let dir = tempfile::tempdir()?;
+
+    let subset = dir.path().join("near.gtf");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("subset-gtf")
+        .arg("-g")
+        .arg(&gtf)
+        .arg("-b")
+        .arg(&bed)
+        .args(["-q", "10", "-o"])
+        .arg(&subset)
+        .assert()
+        .success();
+    let full_lines = std::fs::read_to_string(&gtf)?.lines().count();
+    let subset_lines = std::fs::read_to_string(&subset)?.lines().count();
+    assert!(subset_lines > 0 && subset_lines < full_lines);
+
+    // The genes within the distance are all a run at that distance can report
+    let output = dir.path().join("out.tsv");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&subset)
+        .arg("-b")
+        .arg(&bed)
+        .args(["-q", "10", "-o"])
+        .arg(&output)
+        .assert()
+        .success();
+    let annotated = std::fs::read_to_string(&output)?;
+    assert_eq!(
+        annotated.lines().collect::<Vec<_>>(),
+        run_subset(&["-q", "10"])?
+    );
+
+    // An index has no GTF lines to copy
+    let index = dir.path().join("subset.rgidx");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("index")
+        .arg("-g")
+        .arg(&gtf)
+        .arg("-o")
+        .arg(&index)
+        .assert()
+        .success();
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("subset-gtf")
+        .arg("-g")
+        .arg(&index)
+        .arg("-b")
+        .arg(&bed)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("needs the GTF it was built from"));
+    Ok(())
+}
+
+#[test]
+fn test_simulate() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let simulate =
+        |name: &str, seed: &str| -> Result<(String, String), Box<dyn std::error::Error>> {
+            let gtf = dir.path().join(format!("{}.gtf", name));
+            let bed = dir.path().join(format!("{}.bed", name));
+            Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+                .arg("simulate")
+                .arg("-g")
+                .arg(&gtf)
+                .arg("-b")
+                .arg(&bed)
+                .args([
+                    "--chromosomes",
+                    "2",
+                    "--gene-density",
+                    "20",
+                    "--regions",
+                    "200",
+                ])
+                .args(["--seed", seed])
+                .assert()
+                .success();
+            Ok((
+                std::fs::read_to_string(&gtf)?,
+                std::fs::read_to_string(&bed)?,
+            ))
+        };
+    let (gtf, bed) = simulate("a", "1")?;
+    assert_eq!(gtf.lines().filter(|l| l.contains("\tgene\t")).count(), 40);
+    assert_eq!(bed.lines().count(), 200);
+    assert_eq!(simulate("b", "1")?, (gtf, bed));
+    assert_ne!(simulate("c", "2")?.0, simulate("a", "1")?.0);
+
+    // The files are valid inputs of a run
+    let output = dir.path().join("out.tsv");
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(dir.path().join("a.gtf"))
+        .arg("-b")
+        .arg(dir.path().join("a.bed"))
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    assert!(std::fs::read_to_string(&output)?.lines().count() > 1);
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("simulate")
+        .arg("-g")
+        .arg(dir.path().join("d.gtf"))
+        .arg("-b")
+        .arg(dir.path().join("d.bed"))
+        .args(["--chrom-length", "10000"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("too short"));
+    Ok(())
+}
+
+#[test]
+fn test_diff_outputs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let write = |name: &str, lines: &[String]| -> std::io::Result<std::path::PathBuf> {
+        let path = dir.path().join(name);
+        std::fs::write(&path, lines.join("\n") + "\n")?;
+        Ok(path)
+    };
+    let lines = run_subset(&["-r", "gene"])?;
+    let old = write("old.tsv", &lines)?;
+    let diff = |new: &Path| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("diff").arg(&old).arg(new);
+        cmd
+    };
+
+    // Reformatted numbers are not differences
+    let reformatted: Vec<String> = lines.iter().map(|l| l.replace("100.00", "100")).collect();
+    assert_ne!(reformatted, lines);
+    let new = write("reformatted.tsv", &reformatted)?;
+    diff(&new)
+        .assert()
+        .success()
+        .stdout("Change\tRegion\tGene\tColumn\tOld\tNew\n");
+
+    let new = write("wider.tsv", &run_subset(&["-r", "gene", "-q", "12"])?)?;
+    diff(&new)
+        .assert()
+        .code(5)
+        .stdout(predicates::str::contains("\nADDED\t"))
+        .stderr(predicates::str::contains("rows added, 0 removed"));
+
+    // The first row of the old output missing from the new one
+    let mut removed = lines.clone();
+    let first = removed.remove(1);
+    let new = write("removed.tsv", &removed)?;
+    let region_gene: Vec<&str> = first.split('\t').collect();
+    diff(&new)
+        .assert()
+        .code(5)
+        .stdout(predicates::str::contains(format!(
+            "REMOVED\t{}\t{}\tArea\t",
+            region_gene[0], region_gene[2]
+        )));
+    Ok(())
+}
+
+#[test]
+fn test_unordered() -> Result<(), Box<dyn std::error::Error>> {
+    let mut expected = run_subset(&["-j", "1"])?;
+    for threads in ["1", "4"] {
+        let mut unordered = run_subset(&["-j", threads, "--batch-size", "50", "--unordered"])?;
+        // The same rows, under the header
+        assert_eq!(unordered[0], expected[0]);
+        unordered.sort();
+        expected.sort();
+        assert_eq!(unordered, expected);
+    }
+
+    // Options relying on the input order are rejected before any output
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let dir = tempfile::tempdir()?;
+    let output = dir.path().join("output.tsv");
+    for args in [&["--max-memory", "1G"][..], &["--bgzip"]] {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .arg("-o")
+            .arg(&output)
+            .arg("--unordered")
+            .args(args)
+            .assert()
+            .code(2)
+            .stderr(predicates::str::contains("cannot be used with"));
+    }
+    assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_adaptive_batches() -> Result<(), Box<dyn std::error::Error>> {
+    // Adapted batches give the same output as fixed ones
+    for distance in ["10", "1000"] {
+        let expected = run_subset(&["-j", "1", "--distance", distance])?;
+        let adapted = run_subset(&["-j", "4", "--distance", distance])?;
+        assert_eq!(adapted, expected);
+    }
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    let output_file = NamedTempFile::new()?;
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("--verbose")
+            .arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["-j", "4"])
+            .args(extra)
+            .assert()
+            .success()
+    };
+    run(&[]).stderr(predicates::str::contains("batches of 100 to "));
+    run(&["--batch-size", "50"]).stderr(predicates::str::contains("batches of").not());
+    Ok(())
+}
+
+#[test]
+fn test_io_threads() -> Result<(), Box<dyn std::error::Error>> {
+    // Lines formatted ahead of the writer are written as if it formatted them
+    for args in [
+        &["-r", "transcript"][..],
+        &["-r", "gene", "--wide", "2"][..],
+    ] {
+        let expected = run_subset(&[args, &["-j", "1"]].concat())?;
+        for threads in [
+            &["-j", "4", "--io-threads", "2"][..],
+            &["-j", "0"][..],
+            &["-j", "0", "--io-threads", "1"][..],
+        ] {
+            let split = run_subset(&[args, threads, &["--batch-size", "50"]].concat())?;
+            assert_eq!(split, expected);
+        }
+    }
+
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    let run = |threads: &[&str]| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_rgmatch"));
+        cmd.arg("-g")
+            .arg(data_dir.join("subset_genome.gtf"))
+            .arg("-b")
+            .arg(data_dir.join("subset_peaks.bed"))
+            .args(threads);
+        cmd
+    };
+    run(&["-j", "4", "--io-threads", "4"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--io-threads must be lower than --threads",
+        ));
+    run(&["-j", "16", "--io-threads", "2", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Threads: 14 matching, 2 formatting output",
+        ));
+    Ok(())
+}
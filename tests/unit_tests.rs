@@ -12,7 +12,7 @@ use rgmatch::matcher::rules::{apply_rules, select_transcript};
 use rgmatch::matcher::tss::{check_tss, TssExonInfo};
 use rgmatch::matcher::tts::{check_tts, TtsExonInfo};
 use rgmatch::output::{format_output_line, write_header};
-use rgmatch::types::{Area, Candidate, ReportLevel, Strand, Transcript};
+use rgmatch::types::{Area, Candidate, Exon, Gene, ReportLevel, Strand, Transcript};
 
 // -------------------------------------------------------------------------
 // Helper functions
@@ -41,6 +41,18 @@ fn make_candidate(
     )
 }
 
+/// Gene spanning `start..end` with a single transcript `T_<gene_id>` of one exon.
+fn make_simple_gene(gene_id: &str, strand: Strand, start: i64, end: i64) -> Gene {
+    let mut gene = Gene::new(gene_id.to_string(), strand);
+    gene.set_length(start, end);
+    let mut transcript = Transcript::new(format!("T_{}", gene_id));
+    transcript.add_exon(Exon::new(start, end));
+    transcript.calculate_size();
+    transcript.renumber_exons(strand);
+    gene.add_transcript(transcript);
+    gene
+}
+
 fn default_rules() -> Vec<Area> {
     vec![
         Area::Tss,
@@ -1097,6 +1109,11 @@ mod test_types_area {
         assert_eq!(Area::GeneBody.as_str(), "GENE_BODY");
         assert_eq!(Area::Upstream.as_str(), "UPSTREAM");
         assert_eq!(Area::Downstream.as_str(), "DOWNSTREAM");
+        assert_eq!(Area::FivePrimeUtr.as_str(), "5_UTR");
+        assert_eq!(Area::ThreePrimeUtr.as_str(), "3_UTR");
+        assert_eq!(Area::Cds.as_str(), "CDS");
+        assert_eq!(Area::Intergenic.as_str(), "INTERGENIC");
+        assert_eq!(Area::Overlap.as_str(), "OVERLAP");
     }
 
     #[test]
@@ -1121,6 +1138,11 @@ mod test_types_area {
         assert_eq!("GENE_BODY".parse::<Area>().unwrap(), Area::GeneBody);
         assert_eq!("UPSTREAM".parse::<Area>().unwrap(), Area::Upstream);
         assert_eq!("DOWNSTREAM".parse::<Area>().unwrap(), Area::Downstream);
+        assert_eq!("5_UTR".parse::<Area>().unwrap(), Area::FivePrimeUtr);
+        assert_eq!("3_UTR".parse::<Area>().unwrap(), Area::ThreePrimeUtr);
+        assert_eq!("CDS".parse::<Area>().unwrap(), Area::Cds);
+        assert_eq!("INTERGENIC".parse::<Area>().unwrap(), Area::Intergenic);
+        assert_eq!("OVERLAP".parse::<Area>().unwrap(), Area::Overlap);
     }
 
     #[test]
@@ -1271,7 +1293,7 @@ mod test_types_transcript {
 
 mod test_types_gene {
     use super::*;
-    use rgmatch::types::Exon;
+    use rgmatch::types::{Exon, TranscriptSelection};
     use rgmatch::Gene;
 
     #[test]
@@ -1337,6 +1359,161 @@ mod test_types_gene {
         assert_eq!(cloned.end, 500);
         assert_eq!(cloned.transcripts.len(), 1);
     }
+
+    fn make_transcript(id: &str, exons: &[(i64, i64)]) -> Transcript {
+        let mut t = Transcript::new(id.to_string());
+        for &(start, end) in exons {
+            t.add_exon(Exon::new(start, end));
+        }
+        t
+    }
+
+    fn make_gene_with_transcripts() -> Gene {
+        let mut g = Gene::new("G1".to_string(), Strand::Positive);
+        // 2 exons, 400bp exonic
+        g.add_transcript(make_transcript("T1", &[(100, 299), (500, 699)]));
+        // 3 exons, 300bp exonic
+        g.add_transcript(make_transcript("T2", &[(100, 199), (500, 599), (800, 899)]));
+        // 1 exon, 500bp exonic
+        g.add_transcript(make_transcript("T3", &[(100, 599)]));
+        g
+    }
+
+    #[test]
+    fn test_gene_select_longest_transcript() {
+        let mut g = make_gene_with_transcripts();
+        g.select_transcripts(TranscriptSelection::Longest);
+        assert_eq!(g.transcripts.len(), 1);
+        assert_eq!(g.transcripts[0].transcript_id, "T3");
+    }
+
+    #[test]
+    fn test_gene_select_most_exons_transcript() {
+        let mut g = make_gene_with_transcripts();
+        g.select_transcripts(TranscriptSelection::MostExons);
+        assert_eq!(g.transcripts.len(), 1);
+        assert_eq!(g.transcripts[0].transcript_id, "T2");
+    }
+
+    #[test]
+    fn test_gene_select_all_transcripts() {
+        let mut g = make_gene_with_transcripts();
+        g.select_transcripts(TranscriptSelection::All);
+        assert_eq!(g.transcripts.len(), 3);
+    }
+
+    #[test]
+    fn test_gene_select_transcript_tie_keeps_first() {
+        let mut g = Gene::new("G1".to_string(), Strand::Positive);
+        g.add_transcript(make_transcript("T1", &[(100, 199)]));
+        g.add_transcript(make_transcript("T2", &[(300, 399)]));
+        g.select_transcripts(TranscriptSelection::Longest);
+        assert_eq!(g.transcripts[0].transcript_id, "T1");
+    }
+
+    #[test]
+    fn test_gene_collapse_transcripts() {
+        let mut g = make_gene_with_transcripts();
+        g.strand = Strand::Negative;
+        g.transcripts[1].add_cds(150, 850);
+        g.select_transcripts(TranscriptSelection::Collapse);
+
+        assert_eq!(g.transcripts.len(), 1);
+        let meta = &g.transcripts[0];
+        assert_eq!(meta.transcript_id, "G1");
+        let exons: Vec<(i64, i64, Option<&str>)> = meta
+            .exons
+            .iter()
+            .map(|e| (e.start, e.end, e.exon_number.as_deref()))
+            .collect();
+        assert_eq!(exons, vec![(100, 699, Some("2")), (800, 899, Some("1"))]);
+        assert_eq!((meta.start, meta.end), (100, 899));
+        assert_eq!(meta.cds, Some((150, 850)));
+    }
+
+    #[test]
+    fn test_gene_collapse_merges_bookended_exons() {
+        let mut g = Gene::new("G1".to_string(), Strand::Positive);
+        g.add_transcript(make_transcript("T1", &[(100, 199)]));
+        g.add_transcript(make_transcript("T2", &[(200, 299), (400, 499)]));
+        g.collapse_transcripts();
+
+        let exons: Vec<(i64, i64)> = g.transcripts[0]
+            .exons
+            .iter()
+            .map(|e| (e.start, e.end))
+            .collect();
+        assert_eq!(exons, vec![(100, 299), (400, 499)]);
+    }
+
+    #[test]
+    fn test_gene_cluster_tss() {
+        let mut g = Gene::new("G1".to_string(), Strand::Positive);
+        g.add_transcript(make_transcript("T1", &[(105, 199)]));
+        g.add_transcript(make_transcript("T2", &[(100, 299)]));
+        g.add_transcript(make_transcript("T3", &[(500, 899)]));
+        g.add_transcript(make_transcript("T4", &[(108, 299)]));
+        g.transcripts
+            .iter_mut()
+            .for_each(Transcript::calculate_size);
+        g.cluster_tss(10);
+
+        // T1, T2 and T4 share a cluster represented by the longest, T2
+        let ids: Vec<&str> = g
+            .transcripts
+            .iter()
+            .map(|t| t.transcript_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["T2", "T3"]);
+
+        // On the minus strand TSSs are transcript ends
+        let mut g = Gene::new("G1".to_string(), Strand::Negative);
+        g.add_transcript(make_transcript("T1", &[(100, 299)]));
+        g.add_transcript(make_transcript("T2", &[(200, 295)]));
+        g.add_transcript(make_transcript("T3", &[(150, 280)]));
+        g.transcripts
+            .iter_mut()
+            .for_each(Transcript::calculate_size);
+        g.cluster_tss(5);
+        let ids: Vec<&str> = g
+            .transcripts
+            .iter()
+            .map(|t| t.transcript_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["T1", "T3"]);
+    }
+
+    #[test]
+    fn test_gene_collapse_to_extent() {
+        let mut g = make_gene_with_transcripts();
+        g.set_length(100, 899);
+        g.collapse_to_extent();
+
+        assert_eq!(g.transcripts.len(), 1);
+        let extent = &g.transcripts[0];
+        assert_eq!(extent.transcript_id, "G1");
+        assert_eq!(extent.exons.len(), 1);
+        assert_eq!((extent.exons[0].start, extent.exons[0].end), (100, 899));
+        assert_eq!(extent.exons[0].exon_number.as_deref(), Some("1"));
+        assert_eq!((extent.start, extent.end), (100, 899));
+    }
+
+    #[test]
+    fn test_transcript_selection_from_str() {
+        assert_eq!(
+            "longest".parse::<TranscriptSelection>(),
+            Ok(TranscriptSelection::Longest)
+        );
+        assert_eq!(
+            "most-exons".parse::<TranscriptSelection>(),
+            Ok(TranscriptSelection::MostExons)
+        );
+        assert_eq!(
+            "collapse".parse::<TranscriptSelection>(),
+            Ok(TranscriptSelection::Collapse)
+        );
+        assert!("canonical".parse::<TranscriptSelection>().is_err());
+    }
 }
 
 mod test_types_candidate {
@@ -1474,6 +1651,7 @@ mod test_types_report_level {
             ReportLevel::Transcript
         );
         assert_eq!("gene".parse::<ReportLevel>().unwrap(), ReportLevel::Gene);
+        assert_eq!("all".parse::<ReportLevel>().unwrap(), ReportLevel::All);
     }
 
     #[test]
@@ -1889,7 +2067,10 @@ mod test_overlap_functions {
         assert!(
             has_gene_body_or_intron,
             "Should find GENE_BODY or INTRON: {:?}",
-            candidates.iter().map(|c| c.area).collect::<Vec<_>>()
+            candidates
+                .iter()
+                .map(|c| c.area.clone())
+                .collect::<Vec<_>>()
         );
     }
 }
@@ -2455,7 +2636,7 @@ mod test_error_types {
         let err = ReportLevel::from_str("invalid").unwrap_err();
         assert_eq!(
             format!("{}", err),
-            "invalid report level: expected 'exon', 'transcript', or 'gene'"
+            "invalid report level: expected 'exon', 'transcript', 'gene', 'all' or 'levels'"
         );
     }
 
@@ -2743,7 +2924,7 @@ mod test_output {
                 200,
                 Strand::Positive,
                 "1".to_string(),
-                area,
+                area.clone(),
                 "T1".to_string(),
                 "G1".to_string(),
                 0,
@@ -3448,7 +3629,7 @@ mod test_overlap_extended {
         let candidates = match_region_to_genes(&region, &genes, &config, 0);
         assert!(!candidates.is_empty());
         // Should have both gene body and intron candidates
-        let areas: Vec<Area> = candidates.iter().map(|c| c.area).collect();
+        let areas: Vec<Area> = candidates.iter().map(|c| c.area.clone()).collect();
         assert!(
             areas.contains(&Area::Intron)
                 || areas.contains(&Area::GeneBody)
@@ -3471,7 +3652,7 @@ mod test_overlap_extended {
         let candidates = match_region_to_genes(&region, &genes, &config, 0);
         // Region is upstream of gene
         assert!(!candidates.is_empty());
-        let areas: Vec<Area> = candidates.iter().map(|c| c.area).collect();
+        let areas: Vec<Area> = candidates.iter().map(|c| c.area.clone()).collect();
         assert!(
             areas.contains(&Area::Upstream)
                 || areas.contains(&Area::Tss)
@@ -4168,6 +4349,21 @@ mod test_gene_extended {
 mod test_region_extended {
     use rgmatch::Region;
 
+    #[test]
+    fn test_region_weight() {
+        let meta = ["peak", "250", "+", "1.5,2.5", "."]
+            .map(String::from)
+            .to_vec();
+        let r = Region::new("chr1".to_string(), 100, 200, meta);
+        assert_eq!(r.weight(5), 250.0);
+        // Merged lists are summed
+        assert_eq!(r.weight(7), 4.0);
+        // Missing or non-numeric values weigh nothing
+        assert_eq!(r.weight(4), 0.0);
+        assert_eq!(r.weight(8), 0.0);
+        assert_eq!(r.weight(12), 0.0);
+    }
+
     #[test]
     fn test_region_special_chrom_names() {
         let r1 = Region::new("chr1_random".to_string(), 100, 200, vec![]);
@@ -4350,6 +4546,35 @@ mod test_overlap_complex {
         assert!(has_first_exon);
     }
 
+    #[test]
+    fn test_intron_coordinates() {
+        let config = Config::default();
+        let genes = vec![make_multi_exon_gene(
+            "G1",
+            Strand::Positive,
+            vec![(1000, 1200), (1800, 2000), (2500, 2700)],
+        )];
+
+        // Inside the first intron
+        let region = Region::new("chr1".into(), 1400, 1500, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].area, Area::Intron);
+        assert_eq!((candidates[0].start, candidates[0].end), (1201, 1799));
+
+        // Across both introns, the merged candidate spans them
+        let region = Region::new("chr1".into(), 1700, 2100, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        let intron = candidates.iter().find(|c| c.area == Area::Intron).unwrap();
+        assert_eq!(intron.exon_number, "1,2");
+        assert_eq!((intron.start, intron.end), (1201, 2499));
+        let exon = candidates
+            .iter()
+            .find(|c| c.area == Area::GeneBody)
+            .unwrap();
+        assert_eq!((exon.start, exon.end), (1800, 2000));
+    }
+
     #[test]
     fn test_region_between_two_genes() {
         let config = Config::default();
@@ -4848,7 +5073,7 @@ mod test_candidate_extended {
                 200,
                 Strand::Positive,
                 "1".to_string(),
-                area,
+                area.clone(),
                 "T1".to_string(),
                 "G1".to_string(),
                 0,
@@ -6170,3 +6395,1217 @@ mod test_result_ordering {
         assert_eq!(output, vec![0, 1, 2, 3, 4, 5, 6, 7]);
     }
 }
+
+// -------------------------------------------------------------------------
+// Nearest-k Gene Reporting Tests
+// -------------------------------------------------------------------------
+
+mod test_nearest_k {
+    use super::*;
+    use rgmatch::matcher::overlap::match_region_to_genes;
+    use rgmatch::matcher::process_candidates_for_output;
+    use rgmatch::{Gene, Region};
+
+    fn reported_genes(config: &Config) -> Vec<String> {
+        // Region sits between G1 (downstream side) and G2/G3 (upstream side)
+        let genes = vec![
+            make_simple_gene("G1", Strand::Positive, 1000, 2000),
+            make_simple_gene("G2", Strand::Positive, 5000, 6000),
+            make_simple_gene("G3", Strand::Positive, 9000, 10000),
+        ];
+        let region = Region::new("chr1".to_string(), 3000, 3100, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, config, 0);
+        let processed = process_candidates_for_output(candidates, config);
+        let mut genes: Vec<String> = processed.into_iter().map(|c| c.gene).collect();
+        genes.dedup();
+        genes
+    }
+
+    #[test]
+    fn test_default_reports_single_closest() {
+        let config = Config {
+            level: ReportLevel::Gene,
+            ..Config::default()
+        };
+        assert_eq!(reported_genes(&config), vec!["G1"]);
+    }
+
+    #[test]
+    fn test_nearest_k_reports_all_genes_within_distance() {
+        let config = Config {
+            level: ReportLevel::Gene,
+            nearest_k: Some(3),
+            ..Config::default()
+        };
+        let genes = reported_genes(&config);
+        assert_eq!(genes.len(), 3);
+        assert!(genes.contains(&"G3".to_string()));
+    }
+
+    #[test]
+    fn test_nearest_k_truncates_to_closest() {
+        let config = Config {
+            level: ReportLevel::Gene,
+            nearest_k: Some(1),
+            ..Config::default()
+        };
+        assert_eq!(reported_genes(&config), vec!["G1"]);
+    }
+
+    #[test]
+    fn test_nearest_k_respects_max_distance() {
+        let mut config = Config {
+            level: ReportLevel::Gene,
+            nearest_k: Some(5),
+            ..Config::default()
+        };
+        config.set_distance_kb(3);
+        let genes = reported_genes(&config);
+        assert!(genes.contains(&"G1".to_string()));
+        assert!(genes.contains(&"G2".to_string()));
+        assert!(!genes.contains(&"G3".to_string()));
+    }
+
+    #[test]
+    fn test_nearest_per_strand() {
+        let config = Config {
+            level: ReportLevel::Gene,
+            nearest_per_strand: true,
+            ..Config::default()
+        };
+        // Divergent pair around the region, with a closer plus-strand gene
+        let genes = vec![
+            make_simple_gene("M1", Strand::Negative, 500, 1500),
+            make_simple_gene("P1", Strand::Positive, 3500, 4000),
+            make_simple_gene("P2", Strand::Positive, 5000, 6000),
+        ];
+        let region = Region::new("chr1".to_string(), 3000, 3100, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        let processed = process_candidates_for_output(candidates, &config);
+
+        let reported: Vec<(&str, Area)> = processed
+            .iter()
+            .map(|c| (c.gene.as_str(), c.area.clone()))
+            .collect();
+        assert_eq!(
+            reported.len(),
+            2,
+            "one gene per strand expected: {:?}",
+            reported
+        );
+        assert!(reported.contains(&("M1", Area::Upstream)));
+        assert!(reported.contains(&("P1", Area::Promoter)));
+    }
+
+    #[test]
+    fn test_flanking() {
+        let config = Config {
+            level: ReportLevel::Gene,
+            flanking: true,
+            ..Config::default()
+        };
+        let mut genes = vec![
+            make_simple_gene("L2", Strand::Negative, 200, 600),
+            make_simple_gene("L1", Strand::Positive, 1000, 2000),
+            make_simple_gene("R1", Strand::Negative, 5000, 6000),
+            make_simple_gene("R2", Strand::Positive, 7000, 8000),
+        ];
+        let region = Region::new("chr1".to_string(), 3000, 3100, vec![]);
+        let flanking = |genes: &[Gene]| -> Vec<String> {
+            let candidates = match_region_to_genes(&region, genes, &config, 0);
+            let mut reported: Vec<String> = process_candidates_for_output(candidates, &config)
+                .into_iter()
+                .map(|c| c.gene)
+                .collect();
+            reported.sort();
+            reported
+        };
+        assert_eq!(flanking(&genes), vec!["L1", "R1"]);
+
+        // Overlapping genes are reported along with both flanks
+        genes.insert(2, make_simple_gene("O", Strand::Positive, 2900, 3500));
+        assert_eq!(flanking(&genes), vec!["L1", "O", "R1"]);
+    }
+}
+
+// -------------------------------------------------------------------------
+// UTR Classification Tests
+// -------------------------------------------------------------------------
+
+mod test_utr_classification {
+    use super::*;
+    use rgmatch::matcher::overlap::match_region_to_genes;
+    use rgmatch::types::Exon;
+    use rgmatch::{Gene, Region};
+
+    /// Two-exon coding gene: exons [1000,1200] and [1500,2000], CDS [1100,1800].
+    fn make_coding_gene(strand: Strand) -> Gene {
+        let mut gene = Gene::new("G1".to_string(), strand);
+        gene.set_length(1000, 2000);
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(1000, 1200));
+        transcript.add_exon(Exon::new(1500, 2000));
+        transcript.add_cds(1100, 1200);
+        transcript.add_cds(1500, 1800);
+        transcript.calculate_size();
+        transcript.renumber_exons(strand);
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    fn utr_config() -> Config {
+        let mut config = Config::new();
+        config.enable_utr();
+        config
+    }
+
+    fn areas(candidates: &[Candidate]) -> Vec<Area> {
+        candidates.iter().map(|c| c.area.clone()).collect()
+    }
+
+    #[test]
+    fn test_five_prime_utr_positive() {
+        let genes = vec![make_coding_gene(Strand::Positive)];
+        let region = Region::new("chr1".to_string(), 1010, 1050, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &utr_config(), 0);
+
+        let utr: Vec<&Candidate> = candidates
+            .iter()
+            .filter(|c| c.area == Area::FivePrimeUtr)
+            .collect();
+        assert_eq!(utr.len(), 1);
+        assert_eq!(utr[0].pctg_region, 100.0);
+        assert!(!areas(&candidates).contains(&Area::FirstExon));
+    }
+
+    #[test]
+    fn test_overlap_split_between_utr_and_coding() {
+        let genes = vec![make_coding_gene(Strand::Positive)];
+        let region = Region::new("chr1".to_string(), 1050, 1149, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &utr_config(), 0);
+
+        let utr = candidates
+            .iter()
+            .find(|c| c.area == Area::FivePrimeUtr)
+            .unwrap();
+        let exon = candidates
+            .iter()
+            .find(|c| c.area == Area::FirstExon)
+            .unwrap();
+        assert_eq!(utr.pctg_region, 50.0);
+        assert_eq!(utr.pctg_area, 50.0);
+        assert_eq!(exon.pctg_region, 50.0);
+    }
+
+    #[test]
+    fn test_three_prime_utr_positive() {
+        let genes = vec![make_coding_gene(Strand::Positive)];
+        let region = Region::new("chr1".to_string(), 1900, 1950, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &utr_config(), 0);
+        assert_eq!(areas(&candidates), vec![Area::ThreePrimeUtr]);
+    }
+
+    #[test]
+    fn test_utr_sides_swap_on_negative_strand() {
+        let genes = vec![make_coding_gene(Strand::Negative)];
+        let region = Region::new("chr1".to_string(), 1010, 1050, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &utr_config(), 0);
+        assert_eq!(areas(&candidates), vec![Area::ThreePrimeUtr]);
+
+        let region = Region::new("chr1".to_string(), 1900, 1950, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &utr_config(), 0);
+        assert_eq!(areas(&candidates), vec![Area::FivePrimeUtr]);
+    }
+
+    #[test]
+    fn test_utr_disabled_keeps_exon_areas() {
+        let genes = vec![make_coding_gene(Strand::Positive)];
+        let region = Region::new("chr1".to_string(), 1010, 1050, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &Config::new(), 0);
+        assert_eq!(areas(&candidates), vec![Area::FirstExon]);
+    }
+
+    #[test]
+    fn test_non_coding_transcript_unaffected() {
+        let mut gene = Gene::new("G1".to_string(), Strand::Positive);
+        gene.set_length(1000, 1200);
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(1000, 1200));
+        transcript.calculate_size();
+        transcript.renumber_exons(Strand::Positive);
+        gene.add_transcript(transcript);
+
+        let region = Region::new("chr1".to_string(), 1010, 1050, vec![]);
+        let candidates = match_region_to_genes(&region, &[gene], &utr_config(), 0);
+        assert_eq!(areas(&candidates), vec![Area::FirstExon]);
+    }
+}
+
+// -------------------------------------------------------------------------
+// CDS Classification Tests
+// -------------------------------------------------------------------------
+
+mod test_cds_classification {
+    use super::*;
+    use rgmatch::matcher::overlap::match_region_to_genes;
+    use rgmatch::types::Exon;
+    use rgmatch::{Gene, Region};
+
+    /// Two-exon coding gene: exons [1000,1200] and [1500,2000], CDS [1100,1800].
+    fn make_coding_gene() -> Gene {
+        let mut gene = Gene::new("G1".to_string(), Strand::Positive);
+        gene.set_length(1000, 2000);
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(1000, 1200));
+        transcript.add_exon(Exon::new(1500, 2000));
+        transcript.add_cds(1100, 1200);
+        transcript.add_cds(1500, 1800);
+        transcript.calculate_size();
+        transcript.renumber_exons(Strand::Positive);
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    fn find(candidates: &[Candidate], area: Area) -> Option<&Candidate> {
+        candidates.iter().find(|c| c.area == area)
+    }
+
+    #[test]
+    fn test_cds_replaces_exon_area() {
+        let mut config = Config::new();
+        config.enable_cds();
+        let region = Region::new("chr1".to_string(), 1600, 1649, vec![]);
+        let candidates = match_region_to_genes(&region, &[make_coding_gene()], &config, 0);
+
+        let cds = find(&candidates, Area::Cds).unwrap();
+        assert_eq!(cds.pctg_region, 100.0);
+        // 50bp of the 301bp coding portion of exon 2
+        assert!((cds.pctg_area - 50.0 / 301.0 * 100.0).abs() < 1e-9);
+        assert!(find(&candidates, Area::GeneBody).is_none());
+    }
+
+    #[test]
+    fn test_cds_without_utr_keeps_exon_area_for_non_coding_part() {
+        let mut config = Config::new();
+        config.enable_cds();
+        let region = Region::new("chr1".to_string(), 1050, 1149, vec![]);
+        let candidates = match_region_to_genes(&region, &[make_coding_gene()], &config, 0);
+
+        assert_eq!(find(&candidates, Area::Cds).unwrap().pctg_region, 50.0);
+        assert_eq!(
+            find(&candidates, Area::FirstExon).unwrap().pctg_region,
+            50.0
+        );
+    }
+
+    #[test]
+    fn test_cds_and_utr_split_exon() {
+        let mut config = Config::new();
+        config.enable_utr();
+        config.enable_cds();
+        let region = Region::new("chr1".to_string(), 1750, 1849, vec![]);
+        let candidates = match_region_to_genes(&region, &[make_coding_gene()], &config, 0);
+
+        let areas: Vec<Area> = candidates.iter().map(|c| c.area.clone()).collect();
+        assert_eq!(areas, vec![Area::Cds, Area::ThreePrimeUtr]);
+        assert_eq!(candidates[0].pctg_region, 51.0);
+        assert_eq!(candidates[1].pctg_region, 49.0);
+    }
+}
+
+// -------------------------------------------------------------------------
+// Custom Zone Tests
+// -------------------------------------------------------------------------
+
+mod test_custom_zones {
+    use super::*;
+    use rgmatch::matcher::overlap::match_region_to_genes;
+    use rgmatch::types::Exon;
+    use rgmatch::{Gene, Region};
+
+    fn make_gene(strand: Strand) -> Gene {
+        let mut gene = Gene::new("G1".to_string(), strand);
+        gene.set_length(10000, 11000);
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(10000, 11000));
+        transcript.calculate_size();
+        transcript.renumber_exons(strand);
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    fn zones_config() -> Config {
+        let mut config = Config::new();
+        assert!(config.parse_zones("TSS:0-200,PROXIMAL:200-2000,DISTAL:2000-5000"));
+        config
+    }
+
+    fn areas(region: Region, gene: Gene) -> Vec<String> {
+        match_region_to_genes(&region, &[gene], &zones_config(), 0)
+            .iter()
+            .map(|c| c.area.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_region_in_custom_zone() {
+        let region = Region::new("chr1".to_string(), 9000, 9099, vec![]);
+        assert_eq!(areas(region, make_gene(Strand::Positive)), vec!["PROXIMAL"]);
+    }
+
+    #[test]
+    fn test_region_spanning_zones() {
+        let region = Region::new("chr1".to_string(), 7950, 8049, vec![]);
+        assert_eq!(
+            areas(region, make_gene(Strand::Positive)),
+            vec!["PROXIMAL", "DISTAL"]
+        );
+    }
+
+    #[test]
+    fn test_region_beyond_zones_is_upstream() {
+        let region = Region::new("chr1".to_string(), 4000, 4099, vec![]);
+        assert_eq!(areas(region, make_gene(Strand::Positive)), vec!["UPSTREAM"]);
+    }
+
+    #[test]
+    fn test_zones_on_negative_strand() {
+        let region = Region::new("chr1".to_string(), 11100, 11149, vec![]);
+        assert_eq!(areas(region, make_gene(Strand::Negative)), vec!["TSS"]);
+    }
+
+    #[test]
+    fn test_zone_areas_survive_rules() {
+        let mut config = zones_config();
+        config.level = ReportLevel::Transcript;
+        let genes = [make_gene(Strand::Positive)];
+        let region = Region::new("chr1".to_string(), 7950, 8049, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        let processed = rgmatch::matcher::process_candidates_for_output(candidates, &config);
+        assert_eq!(processed.len(), 1);
+        assert_eq!(processed[0].area.as_str(), "PROXIMAL");
+    }
+}
+
+// -------------------------------------------------------------------------
+// Promoter Tier Tests
+// -------------------------------------------------------------------------
+
+mod test_promoter_tiers {
+    use super::*;
+    use rgmatch::matcher::overlap::match_region_to_genes;
+    use rgmatch::types::Exon;
+    use rgmatch::{Gene, Region};
+
+    fn make_gene() -> Gene {
+        let mut gene = Gene::new("G1".to_string(), Strand::Positive);
+        gene.set_length(10000, 11000);
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(10000, 11000));
+        transcript.calculate_size();
+        transcript.renumber_exons(Strand::Positive);
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    fn tiers(start: i64, end: i64) -> Vec<(String, f64)> {
+        let mut config = Config::new();
+        assert!(config.split_promoter(300));
+        let region = Region::new("chr1".to_string(), start, end, vec![]);
+        match_region_to_genes(&region, &[make_gene()], &config, 0)
+            .iter()
+            .map(|c| (c.area.to_string(), c.pctg_region))
+            .collect()
+    }
+
+    #[test]
+    fn test_proximal_tier() {
+        // 200-500 bp upstream of the TSS at 10000
+        assert_eq!(
+            tiers(9600, 9699),
+            vec![("PROMOTER_PROXIMAL".to_string(), 100.0)]
+        );
+    }
+
+    #[test]
+    fn test_distal_tier() {
+        assert_eq!(
+            tiers(9000, 9099),
+            vec![("PROMOTER_DISTAL".to_string(), 100.0)]
+        );
+    }
+
+    #[test]
+    fn test_region_spanning_tiers() {
+        // 9450..9499 is distal, 9500..9549 is proximal
+        assert_eq!(
+            tiers(9450, 9549),
+            vec![
+                ("PROMOTER_PROXIMAL".to_string(), 50.0),
+                ("PROMOTER_DISTAL".to_string(), 50.0)
+            ]
+        );
+    }
+}
+
+// -------------------------------------------------------------------------
+// Strand-Matched Annotation Tests
+// -------------------------------------------------------------------------
+
+mod test_strand_mode {
+    use super::*;
+    use rgmatch::matcher::overlap::match_region_to_genes;
+    use rgmatch::types::StrandMode;
+    use rgmatch::Region;
+
+    fn matched_genes(strand_mode: StrandMode, region_strand: &str) -> Vec<String> {
+        let genes = vec![
+            make_simple_gene("PLUS", Strand::Positive, 1000, 2000),
+            make_simple_gene("MINUS", Strand::Negative, 1000, 2000),
+        ];
+        let meta = vec![
+            "peak".to_string(),
+            "0".to_string(),
+            region_strand.to_string(),
+        ];
+        let region = Region::new("chr1".to_string(), 1400, 1500, meta);
+        let config = Config {
+            strand_mode,
+            ..Config::default()
+        };
+        let mut genes: Vec<String> = match_region_to_genes(&region, &genes, &config, 0)
+            .into_iter()
+            .map(|c| c.gene)
+            .collect();
+        genes.dedup();
+        genes
+    }
+
+    #[test]
+    fn test_any_strand_matches_both() {
+        assert_eq!(matched_genes(StrandMode::Any, "+"), vec!["PLUS", "MINUS"]);
+    }
+
+    #[test]
+    fn test_same_strand() {
+        assert_eq!(matched_genes(StrandMode::Same, "+"), vec!["PLUS"]);
+        assert_eq!(matched_genes(StrandMode::Same, "-"), vec!["MINUS"]);
+    }
+
+    #[test]
+    fn test_opposite_strand() {
+        assert_eq!(matched_genes(StrandMode::Opposite, "+"), vec!["MINUS"]);
+        assert_eq!(matched_genes(StrandMode::Opposite, "-"), vec!["PLUS"]);
+    }
+
+    #[test]
+    fn test_unstranded_region_matches_both() {
+        assert_eq!(matched_genes(StrandMode::Same, "."), vec!["PLUS", "MINUS"]);
+    }
+}
+
+// -------------------------------------------------------------------------
+// Antisense Flag Tests
+// -------------------------------------------------------------------------
+
+mod test_antisense_flag {
+    use super::*;
+    use rgmatch::matcher::{annotate_region, overlaps_antisense_gene};
+    use rgmatch::types::StrandMode;
+    use rgmatch::Region;
+
+    fn stranded_region(start: i64, end: i64, strand: &str) -> Region {
+        let meta = vec!["peak".to_string(), "0".to_string(), strand.to_string()];
+        Region::new("chr1".to_string(), start, end, meta)
+    }
+
+    #[test]
+    fn test_overlaps_antisense_gene() {
+        let genes = vec![
+            make_simple_gene("PLUS", Strand::Positive, 1000, 2000),
+            make_simple_gene("MINUS", Strand::Negative, 1800, 3000),
+        ];
+
+        assert_eq!(
+            overlaps_antisense_gene(&stranded_region(1900, 1950, "+"), &genes, 0),
+            Some(true)
+        );
+        assert_eq!(
+            overlaps_antisense_gene(&stranded_region(1100, 1200, "+"), &genes, 0),
+            Some(false)
+        );
+        assert_eq!(
+            overlaps_antisense_gene(&stranded_region(1100, 1200, "-"), &genes, 0),
+            Some(true)
+        );
+        assert_eq!(
+            overlaps_antisense_gene(&stranded_region(1100, 1200, "."), &genes, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_antisense_flag_does_not_change_selection() {
+        let genes = vec![
+            make_simple_gene("PLUS", Strand::Positive, 1000, 2000),
+            make_simple_gene("MINUS", Strand::Negative, 1800, 3000),
+        ];
+        let region = stranded_region(1900, 1950, "+");
+
+        let plain = Config {
+            strand_mode: StrandMode::Same,
+            ..Config::default()
+        };
+        let flagged = Config {
+            report_antisense: true,
+            ..plain.clone()
+        };
+
+        let without = annotate_region(&region, &genes, &plain, 0);
+        let with = annotate_region(&region, &genes, &flagged, 0);
+        assert_eq!(without.len(), with.len());
+        assert!(with.iter().all(|c| c.gene == "PLUS"));
+        assert!(with.iter().all(|c| c.antisense == Some(true)));
+        assert!(without.iter().all(|c| c.antisense.is_none()));
+    }
+}
+
+// -------------------------------------------------------------------------
+// Signed Distance Tests
+// -------------------------------------------------------------------------
+
+mod test_signed_distance {
+    use super::*;
+    use rgmatch::matcher::annotate_region;
+    use rgmatch::{Gene, Region};
+
+    fn single_candidate(region: &Region, gene: Gene) -> Candidate {
+        let config = Config {
+            level: ReportLevel::Gene,
+            ..Config::default()
+        };
+        let mut candidates = annotate_region(region, &[gene], &config, 0);
+        assert_eq!(candidates.len(), 1);
+        candidates.pop().unwrap()
+    }
+
+    #[test]
+    fn test_upstream_is_negative_on_both_strands() {
+        let region = Region::new("chr1".to_string(), 5900, 6000, vec![]);
+
+        let plus = single_candidate(
+            &region,
+            make_simple_gene("G", Strand::Positive, 10000, 12000),
+        );
+        assert_eq!(plus.area, Area::Upstream);
+        assert!(plus.distance > 0);
+        assert_eq!(plus.signed_distance(), -plus.distance);
+
+        let region = Region::new("chr1".to_string(), 16000, 16100, vec![]);
+        let minus = single_candidate(
+            &region,
+            make_simple_gene("G", Strand::Negative, 10000, 12000),
+        );
+        assert_eq!(minus.area, Area::Upstream);
+        assert_eq!(minus.signed_distance(), -minus.distance);
+    }
+
+    #[test]
+    fn test_downstream_is_positive_on_both_strands() {
+        let region = Region::new("chr1".to_string(), 16000, 16100, vec![]);
+        let plus = single_candidate(
+            &region,
+            make_simple_gene("G", Strand::Positive, 10000, 12000),
+        );
+        assert_eq!(plus.area, Area::Downstream);
+        assert!(plus.signed_distance() > 0);
+
+        let region = Region::new("chr1".to_string(), 5900, 6000, vec![]);
+        let minus = single_candidate(
+            &region,
+            make_simple_gene("G", Strand::Negative, 10000, 12000),
+        );
+        assert_eq!(minus.area, Area::Downstream);
+        assert_eq!(minus.signed_distance(), minus.distance);
+    }
+}
+
+// -------------------------------------------------------------------------
+// Distance Anchor Tests
+// -------------------------------------------------------------------------
+
+mod test_distance_anchor {
+    use super::*;
+    use rgmatch::matcher::annotate_region;
+    use rgmatch::types::DistanceAnchor;
+    use rgmatch::{Gene, Region};
+
+    fn annotate(region: &Region, genes: &[Gene], anchor: DistanceAnchor) -> Vec<Candidate> {
+        let config = Config {
+            level: ReportLevel::Gene,
+            distance_anchor: anchor,
+            ..Config::default()
+        };
+        annotate_region(region, genes, &config, 0)
+    }
+
+    #[test]
+    fn test_edge_anchor_shortens_distance() {
+        let genes = vec![make_simple_gene("G", Strand::Positive, 10000, 12000)];
+        let region = Region::new("chr1".to_string(), 7000, 9000, vec![]);
+
+        let midpoint = annotate(&region, &genes, DistanceAnchor::Midpoint);
+        assert_eq!(midpoint[0].distance, 2000);
+        assert_eq!(midpoint[0].tss_distance, -2000);
+
+        let edge = annotate(&region, &genes, DistanceAnchor::Edge);
+        assert_eq!(edge[0].distance, 1000);
+        assert_eq!(edge[0].tss_distance, -1000);
+    }
+
+    #[test]
+    fn test_edge_anchor_changes_cutoff() {
+        let genes = vec![make_simple_gene("G", Strand::Positive, 20000, 22000)];
+        // Midpoint is 10500 bp from the TSS, the region end only 9000 bp
+        let region = Region::new("chr1".to_string(), 8000, 11000, vec![]);
+
+        assert!(annotate(&region, &genes, DistanceAnchor::Midpoint).is_empty());
+        let edge = annotate(&region, &genes, DistanceAnchor::Edge);
+        assert_eq!(edge.len(), 1);
+        assert_eq!(edge[0].area, Area::Upstream);
+    }
+
+    #[test]
+    fn test_summit_anchor() {
+        let genes = vec![make_simple_gene("G", Strand::Negative, 10000, 12000)];
+        let meta: Vec<String> = ["peak", "0", ".", "1.0", "-1", "-1", "900"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let region = Region::new("chr1".to_string(), 14000, 15000, meta);
+
+        let summit = annotate(&region, &genes, DistanceAnchor::Summit);
+        assert_eq!(summit[0].area, Area::Upstream);
+        assert_eq!(summit[0].distance, 2900);
+
+        let midpoint = annotate(&region, &genes, DistanceAnchor::Midpoint);
+        assert_eq!(midpoint[0].distance, 2500);
+    }
+
+    #[test]
+    fn test_gene_tss_distance() {
+        let genes = vec![
+            make_simple_gene("P", Strand::Positive, 10000, 12000),
+            make_simple_gene("N", Strand::Negative, 30000, 32000),
+        ];
+        let config = Config {
+            level: ReportLevel::Gene,
+            gene_tss_distance: true,
+            ..Config::default()
+        };
+
+        // Downstream of P: measured from its TSS rather than its TTS
+        let region = Region::new("chr1".to_string(), 13000, 14000, vec![]);
+        let downstream = annotate(&region, &genes, DistanceAnchor::Midpoint);
+        assert_eq!(downstream[0].distance, 1500);
+        let candidates = annotate_region(&region, &genes, &config, 0);
+        assert_eq!(candidates[0].area, Area::Downstream);
+        assert_eq!(candidates[0].distance, 3500);
+        assert_eq!(candidates[0].tss_distance, 3500);
+
+        // Overlaps are no longer reported at distance 0
+        let region = Region::new("chr1".to_string(), 11000, 11200, vec![]);
+        let candidates = annotate_region(&region, &genes, &config, 0);
+        assert_eq!(candidates[0].area, Area::FirstExon);
+        assert_eq!(candidates[0].distance, 1100);
+
+        // The TSS of a negative-strand gene is its end
+        let region = Region::new("chr1".to_string(), 27000, 29000, vec![]);
+        let candidates = annotate_region(&region, &genes, &config, 0);
+        assert_eq!(candidates[0].gene, "N");
+        assert_eq!(candidates[0].area, Area::Downstream);
+        assert_eq!(candidates[0].distance, 4000);
+        assert_eq!(candidates[0].tss_distance, 4000);
+    }
+}
+
+// -------------------------------------------------------------------------
+// Reciprocal Overlap Tests
+// -------------------------------------------------------------------------
+
+mod test_reciprocal_overlap {
+    use super::*;
+    use rgmatch::matcher::annotate_region;
+    use rgmatch::types::Exon;
+    use rgmatch::{Gene, Region};
+
+    fn make_gene() -> Gene {
+        let mut gene = Gene::new("G".to_string(), Strand::Positive);
+        gene.set_length(1000, 9000);
+        let mut transcript = Transcript::new("T".to_string());
+        transcript.add_exon(Exon::new(1000, 2000));
+        transcript.add_exon(Exon::new(8000, 9000));
+        transcript.calculate_size();
+        transcript.renumber_exons(Strand::Positive);
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    fn annotate(start: i64, end: i64, fraction: Option<f64>) -> Vec<Candidate> {
+        let config = Config {
+            level: ReportLevel::Transcript,
+            reciprocal_overlap: fraction,
+            ..Config::default()
+        };
+        let region = Region::new("chr1".to_string(), start, end, vec![]);
+        annotate_region(&region, &[make_gene()], &config, 0)
+    }
+
+    #[test]
+    fn test_region_matching_feature_passes() {
+        let result = annotate(1100, 2000, Some(0.8));
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].area, Area::FirstExon);
+    }
+
+    #[test]
+    fn test_small_region_in_large_intron_is_dropped() {
+        assert_eq!(annotate(4000, 4100, None)[0].area, Area::Intron);
+        assert!(annotate(4000, 4100, Some(0.5)).is_empty());
+    }
+
+    #[test]
+    fn test_broad_region_is_dropped() {
+        // Covers the whole first exon and half of the intron, both too small a
+        // part of the region or of the feature
+        assert!(!annotate(500, 5000, None).is_empty());
+        assert!(annotate(500, 5000, Some(0.6)).is_empty());
+    }
+
+    #[test]
+    fn test_proximity_only_is_dropped() {
+        assert_eq!(annotate(12000, 12100, None)[0].area, Area::Downstream);
+        assert!(annotate(12000, 12100, Some(0.1)).is_empty());
+    }
+}
+
+// -------------------------------------------------------------------------
+// Tie-Break Tests
+// -------------------------------------------------------------------------
+
+mod test_tie_break {
+    use super::*;
+    use rgmatch::matcher::process_candidates_for_output;
+    use rgmatch::types::TieBreak;
+
+    fn make_candidate(exon: &str, distance: i64, tss_distance: i64) -> Candidate {
+        Candidate::new(
+            1000,
+            2000,
+            Strand::Positive,
+            exon.to_string(),
+            Area::Upstream,
+            "T1".to_string(),
+            "G1".to_string(),
+            distance,
+            100.0,
+            -1.0,
+            tss_distance,
+        )
+    }
+
+    fn process(level: ReportLevel, tie_break: TieBreak) -> Vec<Candidate> {
+        let config = Config {
+            level,
+            tie_break,
+            ..Config::default()
+        };
+        let candidates = vec![
+            make_candidate("1", 800, -800),
+            make_candidate("2", 300, -3000),
+            make_candidate("3", 300, -2000),
+        ];
+        process_candidates_for_output(candidates, &config)
+    }
+
+    #[test]
+    fn test_ties_reported_by_default() {
+        assert_eq!(process(ReportLevel::Transcript, TieBreak::None).len(), 3);
+    }
+
+    #[test]
+    fn test_distance_then_tss_distance() {
+        let result = process(ReportLevel::Transcript, TieBreak::Distance);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].exon_number, "3");
+
+        let result = process(ReportLevel::Gene, TieBreak::Distance);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].exon_number, "3");
+    }
+
+    #[test]
+    fn test_tss_distance_first() {
+        let result = process(ReportLevel::Transcript, TieBreak::Tss);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].exon_number, "1");
+    }
+
+    #[test]
+    fn test_exon_level_unaffected() {
+        assert_eq!(process(ReportLevel::Exon, TieBreak::Distance).len(), 3);
+    }
+
+    #[test]
+    fn test_tie_break_from_str() {
+        assert_eq!("none".parse::<TieBreak>(), Ok(TieBreak::None));
+        assert_eq!("Distance".parse::<TieBreak>(), Ok(TieBreak::Distance));
+        assert_eq!("tss".parse::<TieBreak>(), Ok(TieBreak::Tss));
+        assert!("closest".parse::<TieBreak>().is_err());
+    }
+}
+
+// -------------------------------------------------------------------------
+// Report-All Tests
+// -------------------------------------------------------------------------
+
+mod test_report_all {
+    use super::*;
+    use rgmatch::matcher::process_candidates_for_output;
+
+    fn make_candidate(area: Area, transcript: &str, pctg_region: f64) -> Candidate {
+        Candidate::new(
+            1000,
+            2000,
+            Strand::Positive,
+            "1".to_string(),
+            area,
+            transcript.to_string(),
+            "G1".to_string(),
+            0,
+            pctg_region,
+            50.0,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_report_all_skips_selection_and_filters() {
+        let candidates = vec![
+            make_candidate(Area::Intron, "T1", 10.0),
+            make_candidate(Area::FirstExon, "T1", 90.0),
+            make_candidate(Area::GeneBody, "T2", 100.0),
+        ];
+        let mut config = Config {
+            level: ReportLevel::All,
+            max_hits: Some(1),
+            reciprocal_overlap: Some(0.9),
+            ..Config::default()
+        };
+        assert!(config.parse_partial_rules("TSS", true));
+
+        let result = process_candidates_for_output(candidates.clone(), &config);
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].area, Area::Intron);
+
+        config.level = ReportLevel::Transcript;
+        assert!(process_candidates_for_output(candidates, &config).is_empty());
+    }
+}
+
+// -------------------------------------------------------------------------
+// Confidence Score Tests
+// -------------------------------------------------------------------------
+
+mod test_confidence {
+    use super::*;
+    use rgmatch::matcher::process_candidates_for_output;
+    use rgmatch::types::ConfidenceWeights;
+
+    #[test]
+    fn test_confidence_weights_from_str() {
+        let weights: ConfidenceWeights = "1,0,0,1".parse().unwrap();
+        assert_eq!(weights.region, 1.0);
+        assert_eq!(weights.distance, 1.0);
+        assert!("1,0,0".parse::<ConfidenceWeights>().is_err());
+        assert!("1,0,0,-1".parse::<ConfidenceWeights>().is_err());
+        assert!("0,0,0,0".parse::<ConfidenceWeights>().is_err());
+        assert!("a,b,c,d".parse::<ConfidenceWeights>().is_err());
+    }
+
+    #[test]
+    fn test_candidates_scored_when_enabled() {
+        let candidate = Candidate::new(
+            1000,
+            2000,
+            Strand::Positive,
+            "1".to_string(),
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            100.0,
+            0,
+        );
+
+        let processed = process_candidates_for_output(vec![candidate.clone()], &Config::default());
+        assert_eq!(processed[0].confidence, None);
+
+        let config = Config {
+            confidence: Some(ConfidenceWeights::default()),
+            ..Config::default()
+        };
+        let processed = process_candidates_for_output(vec![candidate], &config);
+        assert_eq!(processed[0].confidence, Some(1.0));
+    }
+}
+
+// -------------------------------------------------------------------------
+// Gene Coverage Tests
+// -------------------------------------------------------------------------
+
+mod test_gene_coverage {
+    use super::*;
+    use rgmatch::matcher::overlap::{extent_coverage, extent_overlap};
+    use rgmatch::types::Exon;
+    use rgmatch::{Gene, Region};
+
+    fn make_transcript(id: &str, exons: &[(i64, i64)]) -> Transcript {
+        let mut transcript = Transcript::new(id.to_string());
+        for &(start, end) in exons {
+            transcript.add_exon(Exon::new(start, end));
+        }
+        transcript.calculate_size();
+        transcript.renumber_exons(Strand::Positive);
+        transcript
+    }
+
+    fn make_gene() -> Gene {
+        let mut gene = Gene::new("G1".to_string(), Strand::Positive);
+        gene.set_length(1000, 2000);
+        gene.add_transcript(make_transcript("T1", &[(1000, 1199), (1800, 2000)]));
+        gene.add_transcript(make_transcript("T2", &[(1800, 2000)]));
+        gene
+    }
+
+    #[test]
+    fn test_extent_coverage() {
+        assert_eq!(extent_coverage(1000, 2000, 1000, 2000), 100.0);
+        assert_eq!(extent_coverage(500, 3000, 1000, 2000), 100.0);
+        assert_eq!(extent_coverage(1000, 1009, 1000, 1099), 10.0);
+        assert_eq!(extent_coverage(100, 200, 1000, 2000), 0.0);
+
+        assert_eq!(extent_overlap(1000, 1009, 1000, 1099), 10);
+        assert_eq!(extent_overlap(500, 3000, 1000, 2000), 1001);
+        assert_eq!(extent_overlap(100, 200, 1000, 2000), 0);
+    }
+
+    #[test]
+    fn test_candidates_carry_gene_and_transcript_coverage() {
+        let genes = vec![make_gene()];
+        let region = Region::new("chr1".to_string(), 1500, 2500, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &Config::default(), 0);
+
+        assert!(!candidates.is_empty());
+        for candidate in &candidates {
+            assert!((candidate.pctg_gene.unwrap() - 501.0 / 1001.0 * 100.0).abs() < 1e-9);
+            let expected = if candidate.transcript == "T1" {
+                501.0 / 1001.0 * 100.0
+            } else {
+                100.0
+            };
+            assert!((candidate.pctg_transcript.unwrap() - expected).abs() < 1e-9);
+        }
+
+        // A domain swallowing the whole gene covers all of it
+        let region = Region::new("chr1".to_string(), 500, 3000, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &Config::default(), 0);
+        assert!(candidates
+            .iter()
+            .all(|c| c.pctg_gene == Some(100.0) && c.pctg_transcript == Some(100.0)));
+    }
+}
+
+// -------------------------------------------------------------------------
+// Asymmetric Distance Tests
+// -------------------------------------------------------------------------
+
+mod test_asymmetric_distance {
+    use super::*;
+    use rgmatch::{Gene, Region};
+
+    fn areas(region: &Region, genes: &[Gene], config: &Config) -> Vec<(String, Area)> {
+        match_region_to_genes(region, genes, config, 0)
+            .into_iter()
+            .map(|c| (c.gene, c.area))
+            .collect()
+    }
+
+    #[test]
+    fn test_closest_gene_beyond_its_limit_is_skipped() {
+        // UP ends 3 kb before the region, DOWN starts 20 kb after it
+        let genes = vec![
+            make_simple_gene("UP", Strand::Positive, 1000, 2000),
+            make_simple_gene("DOWN", Strand::Positive, 25000, 26000),
+        ];
+        let region = Region::new("chr1".to_string(), 5000, 5000, vec![]);
+        let config = Config {
+            upstream_distance: Some(50_000),
+            downstream_distance: Some(1_000),
+            ..Config::default()
+        };
+
+        // The region lies downstream of UP (too far) and upstream of DOWN
+        assert_eq!(
+            areas(&region, &genes, &config),
+            vec![("DOWN".to_string(), Area::Upstream)]
+        );
+
+        // With the symmetric default the closest one is reported
+        assert_eq!(
+            areas(&region, &genes, &Config::default()),
+            vec![("UP".to_string(), Area::Downstream)]
+        );
+    }
+
+    #[test]
+    fn test_region_distance_column_overrides_limits() {
+        let genes = vec![make_simple_gene("G1", Strand::Positive, 1000, 2000)];
+        let region = |distance: &str| {
+            let meta = ["peak", "0", "+", distance].map(String::from).to_vec();
+            Region::new("chr1".to_string(), 30000, 30000, meta)
+        };
+        let config = Config {
+            distance_column: Some(7),
+            ..Config::default()
+        };
+
+        // 28 kb downstream of G1: beyond --distance but within the region's own limit
+        assert!(areas(&region("10"), &genes, &config).is_empty());
+        assert_eq!(
+            areas(&region("30"), &genes, &config),
+            vec![("G1".to_string(), Area::Downstream)]
+        );
+        // Missing values fall back to --distance
+        assert!(areas(&region("NA"), &genes, &config).is_empty());
+    }
+
+    #[test]
+    fn test_downstream_limit() {
+        let genes = vec![make_simple_gene("G1", Strand::Positive, 1000, 2000)];
+        let region = Region::new("chr1".to_string(), 7000, 7000, vec![]);
+
+        let config = Config {
+            downstream_distance: Some(4_000),
+            ..Config::default()
+        };
+        assert!(areas(&region, &genes, &config).is_empty());
+
+        let config = Config {
+            downstream_distance: Some(6_000),
+            ..Config::default()
+        };
+        assert_eq!(
+            areas(&region, &genes, &config),
+            vec![("G1".to_string(), Area::Downstream)]
+        );
+    }
+}
+
+// -------------------------------------------------------------------------
+// Point Region Tests
+// -------------------------------------------------------------------------
+
+mod test_point_regions {
+    use super::*;
+    use rgmatch::types::Exon;
+    use rgmatch::{Gene, Region};
+
+    fn make_gene() -> Gene {
+        let mut gene = Gene::new("G1".to_string(), Strand::Positive);
+        gene.set_length(1000, 3000);
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(1000, 1999));
+        transcript.add_exon(Exon::new(2500, 3000));
+        transcript.calculate_size();
+        transcript.renumber_exons(Strand::Positive);
+        gene.add_transcript(transcript);
+        gene
+    }
+
+    fn areas(position: i64) -> Vec<(Area, f64, f64)> {
+        let region = Region::new("chr1".to_string(), position, position, vec![]);
+        match_region_to_genes(&region, &[make_gene()], &Config::default(), 0)
+            .into_iter()
+            .map(|c| (c.area, c.pctg_region, c.pctg_area))
+            .collect()
+    }
+
+    #[test]
+    fn test_point_regions_match_as_single_bases() {
+        // On the first base of the gene
+        assert_eq!(areas(1000), vec![(Area::FirstExon, 100.0, 0.1)]);
+
+        // Within an intron
+        let intron = areas(2200);
+        assert_eq!(intron.len(), 1);
+        assert_eq!(intron[0].0, Area::Intron);
+        assert_eq!(intron[0].1, 100.0);
+
+        // Upstream of the gene
+        let upstream = areas(500);
+        assert!(upstream
+            .iter()
+            .all(|&(_, pctg_region, pctg_area)| pctg_region.is_finite() && pctg_area.is_finite()));
+        assert!(!upstream.is_empty());
+    }
+}
+
+// -------------------------------------------------------------------------
+// Python Compatibility Tests
+// -------------------------------------------------------------------------
+
+mod test_python_compat {
+    use super::*;
+    use rgmatch::types::Compat;
+    use rgmatch::Region;
+
+    fn areas(config: &Config) -> Vec<(String, Area, i64)> {
+        // A ends 250 bp upstream of the region midpoint; B starts within the region
+        let genes = vec![
+            make_simple_gene("A", Strand::Negative, 1000, 2000),
+            make_simple_gene("B", Strand::Positive, 2300, 3000),
+        ];
+        let region = Region::new("chr1".to_string(), 2100, 2400, vec![]);
+        match_region_to_genes(&region, &genes, config, 0)
+            .into_iter()
+            .map(|c| (c.gene, c.area, c.distance))
+            .collect()
+    }
+
+    #[test]
+    fn test_overlap_replaces_closest_candidate() {
+        let default = areas(&Config::default());
+        assert!(default.iter().any(|(gene, _, _)| gene == "A"));
+
+        let mut config = Config::default();
+        config.set_compat(Compat::Python);
+        let python = areas(&config);
+
+        // A is lost and the upstream part of B is reported again in its place
+        let mut expected: Vec<_> = default
+            .iter()
+            .filter(|(gene, _, _)| gene == "B")
+            .cloned()
+            .collect();
+        let upstream: Vec<_> = expected
+            .iter()
+            .filter(|(_, area, _)| *area != Area::FirstExon)
+            .cloned()
+            .collect();
+        assert!(!upstream.is_empty());
+        expected.extend(upstream);
+        assert_eq!(python, expected);
+    }
+}